@@ -0,0 +1,71 @@
+//! Planning and writing a prompt over a generated tree of 5,000 files.
+//! Run with `cargo bench --bench prompt`.
+
+use std::fs;
+use std::hint::black_box;
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tempfile::TempDir;
+use techdocs::plan::{LargeFiles, Normalize};
+use techdocs::prelude::*;
+
+const DIRS: usize = 50;
+const FILES_PER_DIR: usize = 100;
+
+/// A source file of about 1.5 KB. Every third one has CRLF line endings and trailing
+/// spaces, so normalizing has something to do.
+fn source(index: usize) -> String {
+    let ending = if index.is_multiple_of(3) { "  \r\n" } else { "\n" };
+    (0..40).map(|line| format!("    let value_{} = compute({}, {});{}", line, index, line, ending)).collect()
+}
+
+fn tree() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    for d in 0..DIRS {
+        let sub = dir.path().join(format!("module_{:02}", d));
+        fs::create_dir(&sub).unwrap();
+        for f in 0..FILES_PER_DIR {
+            let index = d * FILES_PER_DIR + f;
+            fs::write(sub.join(format!("file_{:03}.rs", f)), source(index)).unwrap();
+        }
+    }
+    dir
+}
+
+fn bench_prompt(c: &mut Criterion) {
+    let tree = tree();
+    // Room for every file, so each run reads and writes all 5,000
+    let everything = PromptOptions::new().max_total_mb(100);
+    let formats = [
+        ("default", everything.clone()),
+        ("normalize_off", everything.clone().normalize(Normalize::Off)),
+        ("line_numbers", everything.clone().line_numbers(true)),
+        (
+            "head_tail",
+            everything.clone().max_file_kb(1).large_files(LargeFiles::HeadTail { head_percent: 60 }),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("5000_files");
+    group.sample_size(10);
+    group.bench_function("collect", |b| {
+        b.iter(|| PromptPlan::collect(black_box(tree.path()), &everything).unwrap())
+    });
+    let mut prompt = Vec::new();
+    for (name, options) in &formats {
+        group.bench_function(format!("write_files_prompt/{}", name), |b| {
+            b.iter(|| write_prompt(tree.path(), options, &mut prompt))
+        });
+    }
+    group.finish();
+}
+
+fn write_prompt(dir: &Path, options: &PromptOptions, prompt: &mut Vec<u8>) {
+    prompt.clear();
+    write_files_prompt(black_box(dir), options, &mut *prompt).unwrap();
+    black_box(prompt.len());
+}
+
+criterion_group!(benches, bench_prompt);
+criterion_main!(benches);
@@ -0,0 +1,687 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use syn::{Item, UseTree};
+
+use crate::docs::{partition_files, workspace_members, Partition};
+use crate::provider::{CompletionRequest, GenerationProfile, LlmClient, ThinkingConfig};
+use crate::{format_file_content, read_file_lossy, CollectedFile, Result};
+
+/// Default system prompt for architecture overviews, embedded at compile time
+pub const DEFAULT_ARCHITECTURE_PROMPT: &str = include_str!("../prompts/architecture.txt");
+/// The overview has a single level-1 heading
+pub const ARCHITECTURE_PROFILE: GenerationProfile = GenerationProfile {
+    stop_sequences: &["\n# "],
+    target_length: None,
+    max_tokens: None,
+};
+/// Source files larger than this are not scanned for dependencies
+const MAX_SOURCE_BYTES: u64 = 1024 * 1024;
+/// Extensions of the files whose `import` and `require` specifiers are followed
+const NODE_EXTENSIONS: &[&str] = &["cjs", "js", "jsx", "mjs", "svelte", "ts", "tsx", "vue"];
+
+/// Modules of a project and the dependencies between them
+#[derive(Debug, Clone)]
+pub struct ModuleGraph {
+    pub modules: Vec<Partition>,
+    /// Mermaid node id of each module, in the order of `modules`
+    pub ids: Vec<String>,
+    /// `(from, to)` indices into `modules`, for a module that uses another
+    pub edges: BTreeSet<(usize, usize)>,
+}
+
+/// What a source file refers to outside itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Dependency {
+    /// A path in the project, relative to its root and possibly without an extension
+    Path(PathBuf),
+    /// A crate or package by name, which may be a workspace member
+    Package(String),
+}
+
+/// The modules an architecture overview describes: workspace members, or the top-level
+/// directories, with the `src` directory of a single Rust crate split into its
+/// top-level Rust modules so the crate does not collapse into one node
+pub fn architecture_modules(dir: &Path, files: Vec<CollectedFile>) -> Vec<Partition> {
+    let partitions = partition_files(dir, files);
+    if workspace_members(dir).is_some() || !dir.join("Cargo.toml").is_file() {
+        return partitions;
+    }
+
+    let mut modules = Vec::new();
+    for partition in partitions {
+        if partition.name != "src" {
+            modules.push(partition);
+            continue;
+        }
+        let mut split: BTreeMap<String, Vec<CollectedFile>> = BTreeMap::new();
+        for file in partition.files {
+            let relative = file.path.strip_prefix(dir.join("src")).unwrap_or(&file.path);
+            let mut components = relative.components();
+            let first = components.next().map(|c| c.as_os_str().to_string_lossy().into_owned());
+            let name = match (first, components.next()) {
+                // A file inside a directory belongs to the module of that directory
+                (Some(first), Some(_)) => format!("src/{}", first),
+                (Some(first), None) => match first.strip_suffix(".rs") {
+                    Some("lib" | "main") | None => "src".to_string(),
+                    Some(stem) => format!("src/{}", stem),
+                },
+                (None, _) => "src".to_string(),
+            };
+            split.entry(name).or_default().push(file);
+        }
+        modules.extend(split.into_iter().map(|(name, files)| Partition { name, files }));
+    }
+    modules
+}
+
+/// The dependencies between `modules` of the project in `dir`. Rust files contribute
+/// their `use` and `mod` items, JavaScript and TypeScript files their relative and
+/// workspace package imports; other files place their module in the graph without
+/// edges. Files that cannot be read or parsed are logged and skipped.
+pub fn module_graph(dir: &Path, modules: Vec<Partition>) -> ModuleGraph {
+    let packages = package_names(dir, &modules);
+    let mut edges = BTreeSet::new();
+    for (from, module) in modules.iter().enumerate() {
+        for file in &module.files {
+            if file.size > MAX_SOURCE_BYTES {
+                continue;
+            }
+            let extension = file.path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+            let dependencies = if extension == "rs" {
+                rust_dependencies(dir, &file.path)
+            } else if NODE_EXTENSIONS.contains(&extension) {
+                node_dependencies(dir, &file.path)
+            } else {
+                continue;
+            };
+            for dependency in dependencies {
+                let to = match &dependency {
+                    Dependency::Path(path) => module_of(&modules, path),
+                    Dependency::Package(name) => packages.get(name).copied(),
+                };
+                if let Some(to) = to.filter(|&to| to != from) {
+                    edges.insert((from, to));
+                }
+            }
+        }
+    }
+
+    let mut ids: Vec<String> = Vec::with_capacity(modules.len());
+    for (index, module) in modules.iter().enumerate() {
+        let slug: String = module
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        // The prefix keeps ids clear of Mermaid keywords such as `end`
+        let mut id = format!("m_{}", slug);
+        if ids.contains(&id) {
+            id = format!("{}_{}", id, index);
+        }
+        ids.push(id);
+    }
+    ModuleGraph { modules, ids, edges }
+}
+
+impl ModuleGraph {
+    /// The graph as a Mermaid `graph TD` diagram, without the code fence
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("graph TD\n");
+        for (id, module) in self.ids.iter().zip(&self.modules) {
+            let _ = writeln!(out, "    {}[\"{}\"]", id, module.name.replace('"', "#quot;"));
+        }
+        for &(from, to) in &self.edges {
+            let _ = writeln!(out, "    {} --> {}", self.ids[from], self.ids[to]);
+        }
+        out
+    }
+
+    /// Edges by node id, to compare with a [`MermaidDiagram`]
+    fn id_edges(&self) -> BTreeSet<(String, String)> {
+        self.edges
+            .iter()
+            .map(|&(from, to)| (self.ids[from].clone(), self.ids[to].clone()))
+            .collect()
+    }
+}
+
+/// Module of a workspace member or top-level package, by the name other code imports
+/// it under: the crate name with `-` as `_`, or the `package.json` name
+fn package_names(dir: &Path, modules: &[Partition]) -> BTreeMap<String, usize> {
+    let mut names = BTreeMap::new();
+    for (index, module) in modules.iter().enumerate() {
+        let root = dir.join(&module.name);
+        if let Some(name) = crate_name(&root) {
+            names.insert(name, index);
+        }
+        let package = fs::read_to_string(root.join("package.json"))
+            .ok()
+            .and_then(|manifest| serde_json::from_str::<serde_json::Value>(&manifest).ok());
+        if let Some(name) = package.as_ref().and_then(|p| p.get("name")).and_then(|n| n.as_str()) {
+            names.insert(name.to_string(), index);
+        }
+    }
+    names
+}
+
+/// Name the crate whose manifest is in `root` is referred to by in Rust paths
+fn crate_name(root: &Path) -> Option<String> {
+    let manifest = fs::read_to_string(root.join("Cargo.toml")).ok()?.parse::<toml::Table>().ok()?;
+    let name = manifest.get("package")?.get("name")?.as_str()?;
+    Some(name.replace('-', "_"))
+}
+
+/// The module holding `path`: the one whose directory contains it or, for a Rust
+/// module split out of `src`, whose name is the path without its extension. The most
+/// specific match wins.
+fn module_of(modules: &[Partition], path: &Path) -> Option<usize> {
+    let stem = path.with_extension("");
+    modules
+        .iter()
+        .enumerate()
+        .filter(|(_, module)| path.starts_with(&module.name) || stem == Path::new(&module.name))
+        .max_by_key(|(_, module)| Path::new(&module.name).components().count())
+        .map(|(index, _)| index)
+}
+
+/// `path` with `.` and `..` components resolved without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// The paths and crates `file` names in its `use` and `mod` items, as paths relative
+/// to `dir`
+fn rust_dependencies(dir: &Path, file: &Path) -> Vec<Dependency> {
+    let parsed = match fs::read_to_string(file).map(|source| syn::parse_file(&source)) {
+        Ok(Ok(parsed)) => parsed,
+        Ok(Err(e)) => {
+            tracing::warn!("Skipping {} for the module graph: {}", file.display(), e);
+            return Vec::new();
+        }
+        Err(e) => {
+            tracing::warn!("Skipping {} for the module graph: {}", file.display(), e);
+            return Vec::new();
+        }
+    };
+    // The crate is the nearest directory above the file with a manifest
+    let Some(crate_root) = file.ancestors().skip(1).find(|dir| dir.join("Cargo.toml").is_file()) else {
+        return Vec::new();
+    };
+    let src = crate_root.join("src");
+    let Ok(relative) = file.strip_prefix(&src) else {
+        return Vec::new();
+    };
+    // `src/a/b.rs` and `src/a/b/mod.rs` are module `a::b`; lib.rs, main.rs, and
+    // binaries are the crate root
+    let mut module: Vec<String> = relative
+        .with_extension("")
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if module.last().is_some_and(|last| last == "mod")
+        || (module.len() == 1 && (module[0] == "lib" || module[0] == "main"))
+    {
+        module.pop();
+    }
+    if module.first().is_some_and(|first| first == "bin") {
+        module.clear();
+    }
+
+    let context = RustContext {
+        dir,
+        src: &src,
+        own_crate: crate_name(crate_root),
+    };
+    let mut dependencies = Vec::new();
+    context.collect(&parsed.items, &module, &mut dependencies);
+    dependencies
+}
+
+struct RustContext<'a> {
+    dir: &'a Path,
+    src: &'a Path,
+    own_crate: Option<String>,
+}
+
+impl RustContext<'_> {
+    fn collect(&self, items: &[Item], module: &[String], out: &mut Vec<Dependency>) {
+        for item in items {
+            match item {
+                Item::Use(item) => {
+                    let mut paths = Vec::new();
+                    use_paths(&item.tree, Vec::new(), &mut paths);
+                    for path in paths {
+                        if let Some(dependency) = self.resolve(module, &path) {
+                            out.push(dependency);
+                        }
+                    }
+                }
+                Item::Mod(item) => {
+                    let mut child = module.to_vec();
+                    child.push(item.ident.to_string());
+                    match &item.content {
+                        Some((_, items)) => self.collect(items, &child, out),
+                        None => out.push(self.path(&child)),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// A `use` path seen in `module`, as the file or crate it reaches
+    fn resolve(&self, module: &[String], path: &[String]) -> Option<Dependency> {
+        let (first, rest) = path.split_first()?;
+        let mut absolute = match first.as_str() {
+            "crate" => Vec::new(),
+            "self" => module.to_vec(),
+            "super" => module[..module.len().saturating_sub(1)].to_vec(),
+            name if self.own_crate.as_deref() == Some(name) => Vec::new(),
+            name => return Some(Dependency::Package(name.to_string())),
+        };
+        for segment in rest {
+            match segment.as_str() {
+                "super" => {
+                    absolute.pop();
+                }
+                "self" => {}
+                _ => absolute.push(segment.clone()),
+            }
+        }
+        Some(self.path(&absolute))
+    }
+
+    /// The path, relative to the project, of the file a module would be in
+    fn path(&self, module: &[String]) -> Dependency {
+        let mut path = self.src.to_path_buf();
+        path.extend(module);
+        Dependency::Path(path.strip_prefix(self.dir).unwrap_or(&path).to_path_buf())
+    }
+}
+
+/// Every path a `use` tree brings in, as its segments; a glob ends at its parent
+fn use_paths(tree: &UseTree, prefix: Vec<String>, out: &mut Vec<Vec<String>>) {
+    let extend = |name: String| {
+        let mut path = prefix.clone();
+        path.push(name);
+        path
+    };
+    match tree {
+        UseTree::Path(path) => use_paths(&path.tree, extend(path.ident.to_string()), out),
+        UseTree::Name(name) => out.push(extend(name.ident.to_string())),
+        UseTree::Rename(rename) => out.push(extend(rename.ident.to_string())),
+        UseTree::Glob(_) => out.push(prefix),
+        UseTree::Group(group) => {
+            for tree in &group.items {
+                use_paths(tree, prefix.clone(), out);
+            }
+        }
+    }
+}
+
+/// The modules `file` imports: relative specifiers as project paths, bare ones as
+/// package names (`@scope/name` for scoped packages)
+fn node_dependencies(dir: &Path, file: &Path) -> Vec<Dependency> {
+    let source = match read_file_lossy(file) {
+        Ok(source) => source,
+        Err(e) => {
+            tracing::warn!("Skipping {} for the module graph: {}", file.display(), e);
+            return Vec::new();
+        }
+    };
+    let base = file.parent().unwrap_or(dir);
+    let mut dependencies = Vec::new();
+    for specifier in import_specifiers(&source) {
+        if specifier.starts_with('.') {
+            let path = normalize(&base.join(specifier));
+            if let Ok(relative) = path.strip_prefix(dir) {
+                dependencies.push(Dependency::Path(relative.to_path_buf()));
+            }
+        } else {
+            let segments = if specifier.starts_with('@') { 2 } else { 1 };
+            let name: Vec<&str> = specifier.split('/').take(segments).collect();
+            dependencies.push(Dependency::Package(name.join("/")));
+        }
+    }
+    dependencies
+}
+
+/// String literals following `from`, `import`, `import(`, and `require(`
+fn import_specifiers(source: &str) -> Vec<&str> {
+    let mut specifiers = Vec::new();
+    for keyword in ["from", "import", "import(", "require("] {
+        let mut rest = source;
+        while let Some(position) = rest.find(keyword) {
+            let before = rest[..position].chars().next_back();
+            let after = rest[position + keyword.len()..].trim_start();
+            rest = &rest[position + keyword.len()..];
+            // Whole words only, so `imported` or `fromage` do not count
+            if before.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '$' || c == '.') {
+                continue;
+            }
+            let Some(quote) = after.chars().next().filter(|c| matches!(c, '"' | '\'' | '`')) else {
+                continue;
+            };
+            if let Some(end) = after[1..].find(quote) {
+                let specifier = &after[1..1 + end];
+                if !specifier.is_empty() && !specifier.contains('\n') {
+                    specifiers.push(specifier);
+                }
+            }
+        }
+    }
+    specifiers
+}
+
+/// The nodes and edges of a Mermaid flowchart
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MermaidDiagram {
+    pub nodes: BTreeSet<String>,
+    pub edges: BTreeSet<(String, String)>,
+}
+
+/// Check a Mermaid flowchart, given without its code fence: it must start with `graph`
+/// or `flowchart`, its brackets and quotes must balance, and every node an edge
+/// refers to must be declared with a label. Returns the diagram, or what is wrong.
+pub fn parse_mermaid(source: &str) -> std::result::Result<MermaidDiagram, String> {
+    let mut lines = source.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with("%%"));
+    match lines.next() {
+        Some(header) if header.starts_with("graph") || header.starts_with("flowchart") => {}
+        Some(header) => return Err(format!("expected `graph` or `flowchart`, found `{}`", header)),
+        None => return Err("the diagram is empty".into()),
+    }
+
+    let mut diagram = MermaidDiagram::default();
+    let mut referenced = BTreeSet::new();
+    for line in lines {
+        check_brackets(line)?;
+        let keyword = line.split_whitespace().next().unwrap_or_default();
+        if matches!(keyword, "subgraph" | "end" | "direction" | "classDef" | "class" | "style" | "linkStyle" | "click") {
+            continue;
+        }
+        let mut previous: Option<String> = None;
+        for part in split_arrows(line) {
+            let id: String = part.chars().take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '-').collect();
+            if id.is_empty() {
+                return Err(format!("expected a node id in `{}`", line));
+            }
+            if part[id.len()..].trim_start().starts_with(['[', '(', '{', '>']) {
+                diagram.nodes.insert(id.clone());
+            } else {
+                referenced.insert(id.clone());
+            }
+            if let Some(from) = previous.replace(id.clone()) {
+                diagram.edges.insert((from, id));
+            }
+        }
+    }
+    if let Some(undeclared) = referenced.iter().find(|id| !diagram.nodes.contains(*id)) {
+        return Err(format!("node `{}` is used but never declared", undeclared));
+    }
+    Ok(diagram)
+}
+
+/// Brackets on one line must close in order, ignoring those inside quoted labels
+fn check_brackets(line: &str) -> std::result::Result<(), String> {
+    let mut stack = Vec::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            '[' | '(' | '{' => stack.push(c),
+            ']' | ')' | '}' => {
+                let open = match c {
+                    ']' => '[',
+                    ')' => '(',
+                    _ => '{',
+                };
+                if stack.pop() != Some(open) {
+                    return Err(format!("unbalanced `{}` in `{}`", c, line));
+                }
+            }
+            _ => {}
+        }
+    }
+    if quoted {
+        return Err(format!("unclosed quote in `{}`", line));
+    }
+    if let Some(open) = stack.last() {
+        return Err(format!("unclosed `{}` in `{}`", open, line));
+    }
+    Ok(())
+}
+
+/// The node parts of a flowchart line, with arrows and their `|labels|` removed
+fn split_arrows(line: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = line;
+    while let Some(start) = ["-->", "---", "-.->", "==>", "-.-"].iter().filter_map(|arrow| rest.find(arrow)).min() {
+        parts.push(rest[..start].trim());
+        rest = rest[start..].trim_start_matches(['-', '.', '=', '>']).trim_start();
+        if let Some(label) = rest.strip_prefix('|') {
+            rest = label.split_once('|').map(|(_, after)| after.trim_start()).unwrap_or_default();
+        }
+    }
+    parts.push(rest.trim());
+    parts
+}
+
+/// Byte range of the first ```mermaid block in `markdown`, covering the lines between
+/// its fences
+fn mermaid_block(markdown: &str) -> Option<std::ops::Range<usize>> {
+    let mut offset = 0;
+    let mut start = None;
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim();
+        match start {
+            None if trimmed == "```mermaid" => start = Some(offset + line.len()),
+            Some(start) if trimmed == "```" => return Some(start..offset),
+            _ => {}
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// `markdown` with its Mermaid diagram checked against `graph`. A diagram that does
+/// not parse or whose edges differ from the graph is replaced with the graph's own,
+/// and a missing one is added in a `Dependencies` section at the end.
+pub fn ensure_diagram(markdown: &str, graph: &ModuleGraph) -> String {
+    let expected = graph.to_mermaid();
+    match mermaid_block(markdown) {
+        Some(range) => {
+            match parse_mermaid(&markdown[range.clone()]) {
+                Ok(diagram) if diagram.edges == graph.id_edges() => return markdown.to_string(),
+                Ok(_) => tracing::warn!("The model changed the dependency diagram; using the extracted one"),
+                Err(e) => tracing::warn!("The model's dependency diagram is invalid ({}); using the extracted one", e),
+            }
+            format!("{}{}{}", &markdown[..range.start], expected, &markdown[range.end..])
+        }
+        None => {
+            tracing::warn!("The model left out the dependency diagram; appending the extracted one");
+            format!("{}\n\n## Dependencies\n\n```mermaid\n{}```\n", markdown.trim_end(), expected)
+        }
+    }
+}
+
+/// The module inventory, dependency list, diagram, and as many files as fit in
+/// `budget` bytes, as the prompt body for [`generate_architecture`]
+fn architecture_prompt(dir: &Path, graph: &ModuleGraph, budget: u64) -> Result<String> {
+    let name = dir.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut out = format!("Project: {}\n\nModules:\n", name);
+    for module in &graph.modules {
+        let _ = writeln!(out, "- {} ({} files)", module.name, module.files.len());
+    }
+    out.push_str("\nDependencies, extracted from `use`, `mod`, and `import` statements:\n");
+    if graph.edges.is_empty() {
+        out.push_str("(none found)\n");
+    }
+    for &(from, to) in &graph.edges {
+        let _ = writeln!(out, "- {} uses {}", graph.modules[from].name, graph.modules[to].name);
+    }
+    let _ = write!(out, "\nDiagram:\n```mermaid\n{}```\n", graph.to_mermaid());
+
+    let mut size = 0;
+    let mut left_out = Vec::new();
+    for file in graph.modules.iter().flat_map(|module| &module.files) {
+        let relative = file.path.strip_prefix(dir).unwrap_or(&file.path);
+        if size + file.size > budget {
+            left_out.push(relative.display().to_string());
+            continue;
+        }
+        size += file.size;
+        let content = read_file_lossy(&file.path)?;
+        let _ = writeln!(out, "\nFile: {}", relative.display());
+        let _ = writeln!(out, "{}", format_file_content(&file.path, &content));
+    }
+    if !left_out.is_empty() {
+        let _ = writeln!(out, "\nFiles not shown: {}", left_out.join(", "));
+    }
+    Ok(out)
+}
+
+/// Generate the architecture overview of the project in `dir` from `graph`, sending at
+/// most `budget` bytes of file content. `max_tokens` and `thinking` are passed on as
+/// [`CompletionRequest::max_tokens`] and [`CompletionRequest::thinking`]. The diagram in
+/// the result always matches the extracted dependencies; see [`ensure_diagram`].
+pub async fn generate_architecture(
+    client: &dyn LlmClient,
+    dir: &Path,
+    graph: &ModuleGraph,
+    model: Option<&str>,
+    budget: u64,
+    max_tokens: Option<u32>,
+    thinking: Option<ThinkingConfig>,
+) -> Result<String> {
+    let request = CompletionRequest {
+        model: model.map(String::from),
+        max_tokens,
+        thinking,
+        ..CompletionRequest::new(DEFAULT_ARCHITECTURE_PROMPT, architecture_prompt(dir, graph, budget)?)
+    }
+    .with_profile(&ARCHITECTURE_PROFILE);
+    let text = client.complete(&request).await?.text;
+    Ok(ensure_diagram(&text, graph))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_files;
+    use crate::testing::{FixtureTree, MockClient};
+
+    fn graph(tree: &FixtureTree) -> ModuleGraph {
+        let files = collect_files(tree.path(), &[], 1024).unwrap();
+        module_graph(tree.path(), architecture_modules(tree.path(), files))
+    }
+
+    fn edges(graph: &ModuleGraph) -> Vec<(&str, &str)> {
+        graph
+            .edges
+            .iter()
+            .map(|&(from, to)| (graph.modules[from].name.as_str(), graph.modules[to].name.as_str()))
+            .collect()
+    }
+
+    fn rust_crate() -> FixtureTree {
+        FixtureTree::new()
+            .file("Cargo.toml", "[package]\nname = \"demo-app\"\n")
+            .file("src/main.rs", "mod config;\nmod server;\nmod util;\n\nfn main() {}\n")
+            .file("src/config.rs", "use std::path::Path;\npub struct Settings;\n")
+            .file("src/server/mod.rs", "use crate::config::Settings;\nuse super::util::*;\nuse serde::Serialize;\nmod routes;\n")
+            .file("src/server/routes.rs", "use demo_app::util::{self, helper as h};\n")
+            .file("src/util.rs", "pub fn helper() {}\n")
+            .file("docs/guide.md", "# Guide\n")
+    }
+
+    #[test]
+    fn rust_modules_are_linked_by_their_use_and_mod_items() {
+        let graph = graph(&rust_crate());
+        let names: Vec<_> = graph.modules.iter().map(|module| module.name.as_str()).collect();
+        assert_eq!(names, ["docs", "src", "src/config", "src/server", "src/util"]);
+        assert_eq!(
+            edges(&graph),
+            [("src", "src/config"), ("src", "src/server"), ("src", "src/util"), ("src/server", "src/config"), ("src/server", "src/util")]
+        );
+        assert_eq!(graph.ids, ["m_docs", "m_src", "m_src_config", "m_src_server", "m_src_util"]);
+    }
+
+    #[test]
+    fn node_workspaces_are_linked_by_their_imports() {
+        let tree = FixtureTree::new()
+            .file("package.json", r#"{"name": "root", "workspaces": ["packages/*"]}"#)
+            .file("packages/ui/package.json", r#"{"name": "@demo/ui"}"#)
+            .file("packages/ui/index.ts", "export const imported = 1;\n")
+            .file("packages/core/package.json", r#"{"name": "core"}"#)
+            .file("packages/core/index.js", "module.exports = {};\n")
+            .file("packages/app/package.json", r#"{"name": "app"}"#)
+            .file(
+                "packages/app/main.tsx",
+                "import { imported } from \"@demo/ui/button\";\nimport local from './local';\nconst core = require('core');\nconst _ = require('lodash');\n",
+            )
+            .file("packages/app/local.ts", "export default 1;\n");
+        let graph = graph(&tree);
+        assert_eq!(edges(&graph), [("packages/app", "packages/core"), ("packages/app", "packages/ui")]);
+    }
+
+    #[test]
+    fn other_projects_are_shown_by_directory_without_edges() {
+        let tree = FixtureTree::new().file("scripts/build.sh", "./lib/common.sh\n").file("lib/common.sh", "echo\n");
+        let graph = graph(&tree);
+        assert!(graph.edges.is_empty());
+        assert_eq!(graph.to_mermaid(), "graph TD\n    m_lib[\"lib\"]\n    m_scripts[\"scripts\"]\n");
+    }
+
+    #[test]
+    fn mermaid_diagrams_are_checked() {
+        let diagram = parse_mermaid("graph TD\n  a[\"A (core)\"] -->|uses| b(B)\n  %% comment\n  b --> a\n").unwrap();
+        assert_eq!(diagram.nodes, BTreeSet::from(["a".to_string(), "b".to_string()]));
+        assert_eq!(diagram.edges.len(), 2);
+
+        assert_eq!(parse_mermaid("sequenceDiagram\n").unwrap_err(), "expected `graph` or `flowchart`, found `sequenceDiagram`");
+        assert_eq!(parse_mermaid("graph TD\n  a[A] --> c\n").unwrap_err(), "node `c` is used but never declared");
+        assert_eq!(parse_mermaid("graph TD\n  a[A --> b[B]\n").unwrap_err(), "unclosed `[` in `a[A --> b[B]`");
+        assert_eq!(parse_mermaid("graph TD\n  a[\"A] --> b\n").unwrap_err(), "unclosed quote in `a[\"A] --> b`");
+    }
+
+    #[test]
+    fn a_wrong_or_missing_diagram_is_replaced_by_the_extracted_one() {
+        let graph = graph(&rust_crate());
+        let expected = graph.to_mermaid();
+        let kept = format!("# Architecture\n\n```mermaid\n{}```\n", expected);
+        assert_eq!(ensure_diagram(&kept, &graph), kept);
+
+        let invented = "# Architecture\n\n```mermaid\ngraph TD\n    m_src[\"src\"] --> m_db[\"db\"]\n```\n\nMore\n";
+        assert_eq!(ensure_diagram(invented, &graph), format!("# Architecture\n\n```mermaid\n{}```\n\nMore\n", expected));
+
+        let missing = ensure_diagram("# Architecture\n\nText\n", &graph);
+        assert_eq!(missing, format!("# Architecture\n\nText\n\n## Dependencies\n\n```mermaid\n{}```\n", expected));
+    }
+
+    #[tokio::test]
+    async fn the_extracted_edges_are_handed_to_the_model() {
+        let tree = rust_crate();
+        let graph = graph(&tree);
+        let client = MockClient::text("# Architecture\n\nA small server.\n");
+        let document = generate_architecture(&client, tree.path(), &graph, None, 64, None, None).await.unwrap();
+        assert!(document.ends_with(&format!("## Dependencies\n\n```mermaid\n{}```\n", graph.to_mermaid())));
+
+        let request = &client.requests()[0];
+        assert_eq!(request.system, DEFAULT_ARCHITECTURE_PROMPT);
+        assert!(request.content.contains("\n- src/server uses src/config\n"), "{}", request.content);
+        assert!(request.content.contains("\nFiles not shown: "), "{}", request.content);
+    }
+}
@@ -0,0 +1,258 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use flate2::read::GzDecoder;
+
+use crate::{FileContext, Result, TechDocsError};
+
+/// Kinds of source archive that can be extracted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// A gzip-compressed tarball (`.tar.gz`, `.tgz`)
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// The format a `Content-Type` names, ignoring parameters
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        match essence.as_str() {
+            "application/gzip" | "application/x-gzip" | "application/x-compressed-tar" => Some(Self::TarGz),
+            "application/zip" | "application/x-zip-compressed" => Some(Self::Zip),
+            _ => None,
+        }
+    }
+
+    /// The format the extension of `file_name` names
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let name = file_name.to_ascii_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Extract `archive` into the empty directory `dest`, returning the project root: the
+/// archive's single top-level directory when it has one, as GitHub source archives do,
+/// or `dest` itself.
+///
+/// An entry with an absolute path or a `..` component rejects the whole archive, and
+/// extraction stops once more than `max_bytes` have been written. Links and special
+/// files are skipped, so nothing extracted points outside `dest`.
+pub fn extract(archive: &Path, format: ArchiveFormat, dest: &Path, max_bytes: u64) -> Result<PathBuf> {
+    let mut budget = Budget { remaining: max_bytes, max_bytes };
+    match format {
+        ArchiveFormat::TarGz => extract_tar_gz(archive, dest, &mut budget)?,
+        ArchiveFormat::Zip => extract_zip(archive, dest, &mut budget)?,
+    }
+    Ok(project_root(dest))
+}
+
+fn extract_tar_gz(archive: &Path, dest: &Path, budget: &mut Budget) -> Result<()> {
+    let mut tar = tar::Archive::new(GzDecoder::new(fs::File::open(archive).file_context("open", archive)?));
+    for entry in tar.entries().map_err(invalid)? {
+        let mut entry = entry.map_err(invalid)?;
+        let name = entry.path().map_err(invalid)?.into_owned();
+        let Some(target) = entry_path(dest, &name)? else {
+            continue;
+        };
+        let kind = entry.header().entry_type();
+        if kind.is_dir() {
+            fs::create_dir_all(&target).file_context("create", &target)?;
+        } else if kind.is_file() {
+            write_file(&mut entry, &target, budget)?;
+        } else {
+            tracing::debug!("Skipping {} in archive: not a regular file", name.display());
+        }
+    }
+    Ok(())
+}
+
+fn extract_zip(archive: &Path, dest: &Path, budget: &mut Budget) -> Result<()> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(archive).file_context("open", archive)?).map_err(invalid)?;
+    for index in 0..zip.len() {
+        let mut file = zip.by_index(index).map_err(invalid)?;
+        let name = PathBuf::from(file.name());
+        let Some(target) = entry_path(dest, &name)? else {
+            continue;
+        };
+        if file.is_dir() {
+            fs::create_dir_all(&target).file_context("create", &target)?;
+        } else if file.is_file() {
+            write_file(&mut file, &target, budget)?;
+        } else {
+            tracing::debug!("Skipping {} in archive: not a regular file", name.display());
+        }
+    }
+    Ok(())
+}
+
+/// Where entry `name` goes under `dest`, or `None` for an entry naming `dest` itself.
+/// Absolute names and names climbing out with `..` are refused.
+fn entry_path(dest: &Path, name: &Path) -> Result<Option<PathBuf>> {
+    let mut relative = PathBuf::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(TechDocsError::Archive(format!(
+                    "entry {} would be extracted outside the archive's directory",
+                    name.display()
+                )));
+            }
+        }
+    }
+    Ok((!relative.as_os_str().is_empty()).then(|| dest.join(relative)))
+}
+
+/// Bytes that may still be written before extraction is stopped
+struct Budget {
+    remaining: u64,
+    max_bytes: u64,
+}
+
+/// Copy an entry's content to `target`, charging it to `budget`. Read failures mean a
+/// corrupt archive; write failures are the server's own.
+fn write_file(reader: &mut impl Read, target: &Path, budget: &mut Budget) -> Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).file_context("create", parent)?;
+    }
+    let mut file = fs::File::create(target).file_context("create", target)?;
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer).map_err(invalid)?;
+        if read == 0 {
+            return Ok(());
+        }
+        budget.remaining = budget.remaining.checked_sub(read as u64).ok_or_else(|| {
+            TechDocsError::Budget(format!(
+                "the archive expands to more than {} MB",
+                budget.max_bytes / (1024 * 1024)
+            ))
+        })?;
+        file.write_all(&buffer[..read])?;
+    }
+}
+
+/// The single directory `dest` holds, or `dest` when it holds anything else
+fn project_root(dest: &Path) -> PathBuf {
+    let entries: Vec<_> = match fs::read_dir(dest) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).take(2).collect(),
+        Err(_) => return dest.to_path_buf(),
+    };
+    match entries.as_slice() {
+        [only] if only.file_type().is_ok_and(|file_type| file_type.is_dir()) => only.path(),
+        _ => dest.to_path_buf(),
+    }
+}
+
+fn invalid(error: impl std::fmt::Display) -> TechDocsError {
+    TechDocsError::Archive(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A zip archive in `dir` with `entries` of name and content
+    fn zip_archive(dir: &Path, entries: &[(&str, &str)]) -> PathBuf {
+        let path = dir.join("upload.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        for (name, content) in entries {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    /// A gzip-compressed tarball in `dir` with `entries` of name and content. Names are
+    /// written into the header as given, since the tar builder refuses `..`.
+    fn tar_gz_archive(dir: &Path, entries: &[(&str, &str)]) -> PathBuf {
+        let path = dir.join("upload.tar.gz");
+        let gzip = flate2::write::GzEncoder::new(fs::File::create(&path).unwrap(), flate2::Compression::fast());
+        let mut tar = tar::Builder::new(gzip);
+        for (name, content) in entries {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append(&header, content.as_bytes()).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+        path
+    }
+
+    fn extract_into(dir: &TempDir, archive: &Path, format: ArchiveFormat, max_bytes: u64) -> Result<PathBuf> {
+        let dest = dir.path().join("source");
+        fs::create_dir(&dest).unwrap();
+        extract(archive, format, &dest, max_bytes)
+    }
+
+    #[test]
+    fn formats_are_recognized_by_content_type_and_name() {
+        assert_eq!(ArchiveFormat::from_content_type("application/zip"), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::from_content_type("Application/GZIP; charset=binary"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_content_type("application/json"), None);
+        assert_eq!(ArchiveFormat::from_file_name("repo-main.TGZ"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_file_name("repo.tar"), None);
+    }
+
+    #[test]
+    fn a_single_top_level_directory_is_the_project_root() {
+        let dir = TempDir::new().unwrap();
+        let archive = zip_archive(dir.path(), &[("repo-main/README.md", "# Repo\n"), ("repo-main/src/lib.rs", "")]);
+        let root = extract_into(&dir, &archive, ArchiveFormat::Zip, u64::MAX).unwrap();
+        assert_eq!(root, dir.path().join("source/repo-main"));
+        assert_eq!(fs::read_to_string(root.join("README.md")).unwrap(), "# Repo\n");
+
+        let dir = TempDir::new().unwrap();
+        let archive = tar_gz_archive(dir.path(), &[("./README.md", "# Repo\n"), ("src/lib.rs", "")]);
+        let root = extract_into(&dir, &archive, ArchiveFormat::TarGz, u64::MAX).unwrap();
+        assert_eq!(root, dir.path().join("source"));
+        assert!(root.join("src/lib.rs").is_file());
+    }
+
+    #[test]
+    fn entries_climbing_out_reject_the_archive() {
+        for name in ["../evil", "repo/../../evil", "/tmp/evil"] {
+            let dir = TempDir::new().unwrap();
+            let archive = zip_archive(dir.path(), &[("repo/README.md", "# Repo\n"), (name, "pwned\n")]);
+            let error = extract_into(&dir, &archive, ArchiveFormat::Zip, u64::MAX).unwrap_err();
+            assert!(matches!(&error, TechDocsError::Archive(message) if message.contains("outside")), "{}: {}", name, error);
+            assert!(!dir.path().join("evil").exists());
+
+            let dir = TempDir::new().unwrap();
+            let archive = tar_gz_archive(dir.path(), &[(name, "pwned\n")]);
+            let error = extract_into(&dir, &archive, ArchiveFormat::TarGz, u64::MAX).unwrap_err();
+            assert!(matches!(error, TechDocsError::Archive(_)), "{}: {}", name, error);
+            assert!(!dir.path().join("evil").exists());
+        }
+    }
+
+    #[test]
+    fn extraction_stops_past_the_size_limit() {
+        let dir = TempDir::new().unwrap();
+        let archive = zip_archive(dir.path(), &[("a.txt", &"a".repeat(600)), ("b.txt", &"b".repeat(600))]);
+        let error = extract_into(&dir, &archive, ArchiveFormat::Zip, 1000).unwrap_err();
+        assert!(matches!(error, TechDocsError::Budget(_)), "{}", error);
+    }
+
+    #[test]
+    fn unreadable_archives_are_invalid() {
+        for format in [ArchiveFormat::Zip, ArchiveFormat::TarGz] {
+            let dir = TempDir::new().unwrap();
+            let archive = dir.path().join("upload");
+            fs::write(&archive, "not an archive").unwrap();
+            assert!(matches!(extract_into(&dir, &archive, format, u64::MAX), Err(TechDocsError::Archive(_))), "{:?}", format);
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::fs;
+use std::path::Path;
+
+use crate::git::open_repository;
+#[cfg(feature = "claude")]
+use crate::github::RemoteMetadata;
+use crate::infra::detect_infra;
+use crate::license::{detect_license, license_files};
+use crate::sections::headings;
+
+/// What badge generation knows about a project
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepoMetadata {
+    /// Package name from Cargo.toml, when the crate can be published
+    pub crate_name: Option<String>,
+    /// Workflow file names under `.github/workflows`, sorted
+    pub workflows: Vec<String>,
+    /// Those of `workflows` that run on pushes or pull requests, rather than only on
+    /// releases or schedules
+    pub push_workflows: Vec<String>,
+    /// SPDX expression from [`detect_license`]
+    pub license: Option<String>,
+    /// License file name relative to the project root
+    pub license_file: Option<String>,
+    /// `owner/name` of the GitHub repository the `origin` remote points at
+    pub github_slug: Option<String>,
+}
+
+impl RepoMetadata {
+    /// Fill in the repository and license that could not be read locally from what
+    /// GitHub says
+    #[cfg(feature = "claude")]
+    pub fn with_remote(mut self, remote: Option<&RemoteMetadata>) -> Self {
+        if let Some(remote) = remote {
+            self.github_slug = self.github_slug.or_else(|| Some(remote.repository.clone()));
+            self.license = self.license.or_else(|| remote.license.clone());
+        }
+        self
+    }
+}
+
+/// A shields-style badge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Badge {
+    pub label: String,
+    pub image_url: String,
+    pub link: String,
+}
+
+impl Badge {
+    fn new(label: &str, image_url: String, link: String) -> Self {
+        Self {
+            label: label.to_string(),
+            image_url,
+            link,
+        }
+    }
+
+    /// Markdown for the badge: an image wrapped in a link
+    pub fn to_markdown(&self) -> String {
+        format!("[![{}]({})]({})", self.label, self.image_url, self.link)
+    }
+}
+
+/// Inspect `dir` for the metadata badges are built from. Anything that cannot be read
+/// is left unset.
+pub fn detect_metadata(dir: &Path) -> RepoMetadata {
+    let manifest = fs::read_to_string(dir.join("Cargo.toml"))
+        .ok()
+        .and_then(|content| content.parse::<toml::Table>().ok());
+    let package = manifest.as_ref().and_then(|m| m.get("package")).and_then(|p| p.as_table());
+    let publishable = package
+        .and_then(|p| p.get("publish"))
+        .is_none_or(|publish| publish.as_bool() != Some(false));
+    let crate_name = package
+        .and_then(|p| p.get("name"))
+        .and_then(|name| name.as_str())
+        .filter(|_| publishable)
+        .map(String::from);
+
+    let mut workflows: Vec<String> = fs::read_dir(dir.join(".github").join("workflows"))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".yml") || name.ends_with(".yaml"))
+        .collect();
+    workflows.sort();
+    let push_workflows = detect_infra(dir)
+        .push_workflows()
+        .filter_map(|pipeline| pipeline.file.strip_prefix(".github/workflows/").map(String::from))
+        .collect();
+
+    let license_file = license_files(dir).into_iter().next();
+    let license = detect_license(dir).map(|license| license.spdx);
+
+    let github_slug = open_repository(dir).ok().and_then(|repo| {
+        let remote = repo.find_remote("origin").ok()?;
+        github_slug(remote.url()?)
+    });
+
+    RepoMetadata {
+        crate_name,
+        workflows,
+        push_workflows,
+        license,
+        license_file,
+        github_slug,
+    }
+}
+
+/// `owner/name` from a GitHub remote URL in HTTPS or SSH form
+pub fn github_slug(remote_url: &str) -> Option<String> {
+    let path = remote_url
+        .strip_prefix("https://github.com/")
+        .or_else(|| remote_url.strip_prefix("http://github.com/"))
+        .or_else(|| remote_url.strip_prefix("git@github.com:"))
+        .or_else(|| remote_url.strip_prefix("ssh://git@github.com/"))?;
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let mut parts = path.split('/');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(owner), Some(name), None) if !owner.is_empty() && !name.is_empty() => {
+            Some(format!("{}/{}", owner, name))
+        }
+        _ => None,
+    }
+}
+
+/// Badges supported by `metadata`, in display order: crates.io, docs.rs, CI, license.
+/// Badges whose prerequisites are missing are left out.
+pub fn badges(metadata: &RepoMetadata) -> Vec<Badge> {
+    let mut badges = Vec::new();
+
+    if let Some(name) = &metadata.crate_name {
+        badges.push(Badge::new(
+            "Crates.io",
+            format!("https://img.shields.io/crates/v/{}.svg", name),
+            format!("https://crates.io/crates/{}", name),
+        ));
+        badges.push(Badge::new(
+            "Docs.rs",
+            format!("https://docs.rs/{}/badge.svg", name),
+            format!("https://docs.rs/{}", name),
+        ));
+    }
+
+    if let Some(slug) = &metadata.github_slug {
+        // Prefer a workflow that is obviously CI over, say, a release workflow
+        let workflow = metadata
+            .workflows
+            .iter()
+            .find(|w| w.starts_with("ci.") || w.starts_with("rust."))
+            .or_else(|| metadata.push_workflows.first())
+            .or_else(|| metadata.workflows.first());
+        if let Some(workflow) = workflow {
+            badges.push(Badge::new(
+                "CI",
+                format!("https://github.com/{}/actions/workflows/{}/badge.svg", slug, workflow),
+                format!("https://github.com/{}/actions/workflows/{}", slug, workflow),
+            ));
+        }
+    }
+
+    if let Some(license) = &metadata.license {
+        let link = match (&metadata.license_file, &metadata.github_slug) {
+            (Some(file), _) => file.clone(),
+            (None, Some(slug)) => format!("https://github.com/{}", slug),
+            (None, None) => format!("https://spdx.org/licenses/{}.html", license),
+        };
+        badges.push(Badge::new(
+            "License",
+            format!("https://img.shields.io/badge/license-{}-blue.svg", shields_escape(license)),
+            link,
+        ));
+    }
+
+    badges
+}
+
+/// Escape text for a shields.io static badge path segment
+fn shields_escape(text: &str) -> String {
+    text.replace('-', "--").replace('_', "__").replace(' ', "%20")
+}
+
+/// Insert a line of `badges` after the first H1 of `readme`, or at the top when it has none.
+/// Badges whose image is already in the document are skipped.
+pub fn insert_badges(readme: &str, badges: &[Badge]) -> String {
+    let line = badges
+        .iter()
+        .filter(|badge| !readme.contains(&badge.image_url))
+        .map(Badge::to_markdown)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if line.is_empty() {
+        return readme.to_string();
+    }
+
+    let mut lines: Vec<&str> = readme.lines().collect();
+    let at = match headings(readme).into_iter().find(|h| h.level == 1) {
+        Some(title) => {
+            lines.insert(title.line + 1, "");
+            title.line + 2
+        }
+        None => 0,
+    };
+    lines.insert(at, &line);
+    // Keep the badges a paragraph of their own
+    if lines.get(at + 1).is_some_and(|next| !next.trim().is_empty()) {
+        lines.insert(at + 1, "");
+    }
+    let mut output = lines.join("\n");
+    if readme.ends_with('\n') {
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    const MIT: &str = "MIT License\n\nPermission is hereby granted, free of charge, to any person obtaining a copy\n";
+    const ON_PUSH: &str = "name: Build\non: [push, pull_request]\njobs:\n  test:\n    runs-on: ubuntu-latest\n";
+    const ON_RELEASE: &str = "name: Release\non:\n  release:\n    types: [published]\njobs:\n  publish:\n    runs-on: ubuntu-latest\n";
+
+    fn with_origin(tree: &FixtureTree, url: &str) {
+        let repo = git2::Repository::init(tree.path()).unwrap();
+        repo.remote("origin", url).unwrap();
+    }
+
+    #[test]
+    fn a_published_crate_on_github_gets_every_badge() {
+        let tree = FixtureTree::new()
+            .file("Cargo.toml", "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n")
+            .file("LICENSE", MIT)
+            .file(".github/workflows/build.yml", ON_PUSH);
+        with_origin(&tree, "git@github.com:acme/demo.git");
+
+        let metadata = detect_metadata(tree.path());
+        assert_eq!(metadata.crate_name.as_deref(), Some("demo"));
+        assert_eq!(metadata.github_slug.as_deref(), Some("acme/demo"));
+        assert_eq!((metadata.license.as_deref(), metadata.license_file.as_deref()), (Some("MIT"), Some("LICENSE")));
+        let labels: Vec<_> = badges(&metadata).into_iter().map(|badge| badge.label).collect();
+        assert_eq!(labels, ["Crates.io", "Docs.rs", "CI", "License"]);
+        assert_eq!(
+            badges(&metadata)[2].to_markdown(),
+            "[![CI](https://github.com/acme/demo/actions/workflows/build.yml/badge.svg)](https://github.com/acme/demo/actions/workflows/build.yml)"
+        );
+    }
+
+    #[test]
+    fn a_project_without_metadata_gets_no_badges() {
+        let tree = FixtureTree::new().file("main.py", "print('hi')\n");
+        let metadata = detect_metadata(tree.path());
+        assert_eq!(metadata, RepoMetadata::default());
+        assert!(badges(&metadata).is_empty());
+        assert_eq!(insert_badges("# Demo\n\nText\n", &badges(&metadata)), "# Demo\n\nText\n");
+    }
+
+    #[test]
+    fn partial_metadata_gets_only_the_badges_it_supports() {
+        // Not published, and the only workflow runs on releases; without a remote there is no CI badge
+        let tree = FixtureTree::new()
+            .file("Cargo.toml", "[package]\nname = \"internal\"\npublish = false\nlicense = \"MIT OR Apache-2.0\"\n")
+            .file(".github/workflows/release.yml", ON_RELEASE);
+        let metadata = detect_metadata(tree.path());
+        assert_eq!(metadata.crate_name, None);
+        assert_eq!(metadata.workflows, ["release.yml"]);
+        assert!(metadata.push_workflows.is_empty());
+        let found = badges(&metadata);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].image_url, "https://img.shields.io/badge/license-MIT%20OR%20Apache--2.0-blue.svg");
+        assert_eq!(found[0].link, "https://spdx.org/licenses/MIT OR Apache-2.0.html");
+
+        // With a remote, a push workflow is preferred over a release one listed first
+        let metadata = RepoMetadata {
+            workflows: vec!["a-release.yml".into(), "build.yml".into()],
+            push_workflows: vec!["build.yml".into()],
+            github_slug: Some("acme/demo".into()),
+            ..metadata
+        };
+        assert!(badges(&metadata)[0].image_url.ends_with("/build.yml/badge.svg"));
+    }
+
+    #[test]
+    fn badges_go_after_the_title_once() {
+        let badge = Badge::new("License", "https://img.shields.io/badge/license-MIT-blue.svg".into(), "LICENSE".into());
+        let badges = std::slice::from_ref(&badge);
+        let readme = insert_badges("# Demo\nIntro\n", badges);
+        assert_eq!(readme, format!("# Demo\n\n{}\n\nIntro\n", badge.to_markdown()));
+        assert_eq!(insert_badges(&readme, badges), readme);
+        assert_eq!(insert_badges("Intro", badges), format!("{}\n\nIntro", badge.to_markdown()));
+    }
+
+    #[test]
+    fn slugs_are_read_from_github_urls_only() {
+        assert_eq!(github_slug("https://github.com/acme/demo.git").as_deref(), Some("acme/demo"));
+        assert_eq!(github_slug("ssh://git@github.com/acme/demo/").as_deref(), Some("acme/demo"));
+        assert_eq!(github_slug("https://gitlab.com/acme/demo"), None);
+        assert_eq!(github_slug("https://github.com/acme/demo/tree/main"), None);
+    }
+}
@@ -0,0 +1,867 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::claude::{BatchRequest, ClaudeClient};
+use crate::config::{Config, PartialConfig};
+use crate::generate::{tidy_markdown, GenerateOptions, ReadmePrompt};
+use crate::license::detect_license;
+use crate::plan::Normalize;
+use crate::progress::no_progress;
+use crate::provider::{CompletionRequest, CompletionResult, CostTracker, LlmClient, Spend, Usage};
+use crate::summary::{parse_summary, summarize, summary_files, summary_request};
+use crate::{resolve_path, validate_directory, write_atomic, Result, TechDocsError};
+
+/// Version of the [`BatchState`] file format
+pub const BATCH_STATE_VERSION: u32 = 1;
+/// Name of the state file a batch run keeps in its output directory
+pub const BATCH_STATE_FILE: &str = "batch-state.json";
+
+/// What a batch run generates for each repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchKind {
+    /// `README.md`
+    Readme,
+    /// `summary.json` with a description and topics
+    Summary,
+}
+
+/// One repository in a batch manifest, with optional per-repository settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchEntry {
+    /// Local path or GitHub URL
+    pub source: String,
+    /// Name of the output directory (default: derived from `source`)
+    pub name: Option<String>,
+    pub exclude: Option<Vec<String>>,
+    /// Name of the filter preset
+    pub preset: Option<String>,
+    pub max_file_size_kb: Option<u64>,
+    pub max_total_size_mb: Option<u64>,
+    pub model: Option<String>,
+    pub prompt_file: Option<PathBuf>,
+}
+
+impl BatchEntry {
+    fn new(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            name: None,
+            exclude: None,
+            preset: None,
+            max_file_size_kb: None,
+            max_total_size_mb: None,
+            model: None,
+            prompt_file: None,
+        }
+    }
+
+    /// Settings for this repository, falling back to `defaults` for anything unset
+    fn overrides(&self, defaults: &PartialConfig) -> PartialConfig {
+        PartialConfig {
+            exclude: self.exclude.clone().or_else(|| defaults.exclude.clone()),
+            include: defaults.include.clone(),
+            preset: self.preset.clone().or_else(|| defaults.preset.clone()),
+            max_file_size_kb: self.max_file_size_kb.or(defaults.max_file_size_kb),
+            max_total_size_mb: self.max_total_size_mb.or(defaults.max_total_size_mb),
+            max_depth: defaults.max_depth,
+            max_walk_entries: defaults.max_walk_entries,
+            max_walk_secs: defaults.max_walk_secs,
+            model: self.model.clone().or_else(|| defaults.model.clone()),
+            prompt_file: self.prompt_file.clone().or_else(|| defaults.prompt_file.clone()),
+            footer: defaults.footer.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    repo: Vec<BatchEntry>,
+}
+
+/// Read a batch manifest. A `.toml` file holds `[[repo]]` tables; any other file
+/// lists one path or URL per line, with blank lines and `#` comments ignored.
+pub fn read_manifest(path: &Path) -> Result<Vec<BatchEntry>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| TechDocsError::Config(format!("Failed to read {}: {}", path.display(), e)))?;
+
+    let entries = if path.extension().is_some_and(|e| e == "toml") {
+        toml::from_str::<Manifest>(&content)
+            .map_err(|e| TechDocsError::Config(format!("{}: {}", path.display(), e.message())))?
+            .repo
+    } else {
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(BatchEntry::new)
+            .collect()
+    };
+
+    if entries.is_empty() {
+        return Err(TechDocsError::Config(format!("{}: no repositories listed", path.display())));
+    }
+    Ok(entries)
+}
+
+/// Output directory names for `entries`, unique and safe to use as a path component
+pub fn output_names(entries: &[BatchEntry]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    entries
+        .iter()
+        .map(|entry| {
+            let base = entry.name.clone().unwrap_or_else(|| {
+                let source = entry.source.trim_end_matches('/');
+                let last = source.rsplit('/').next().unwrap_or(source);
+                last.trim_end_matches(".git").to_string()
+            });
+            let mut base: String = base
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '-' })
+                .collect();
+            if base.trim_matches('.').is_empty() {
+                base = "repo".to_string();
+            }
+
+            let mut name = base.clone();
+            let mut suffix = 2;
+            while !seen.insert(name.clone()) {
+                name = format!("{}-{}", base, suffix);
+                suffix += 1;
+            }
+            name
+        })
+        .collect()
+}
+
+/// Result of processing one repository
+#[derive(Debug)]
+pub struct BatchOutcome {
+    pub name: String,
+    pub source: String,
+    /// The written file, or the error message
+    pub result: std::result::Result<PathBuf, String>,
+    pub duration: Duration,
+    pub usage: Usage,
+    /// Finished by the run being resumed, and not processed again
+    pub resumed: bool,
+}
+
+/// How far a repository of a batch run got
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepoStatus {
+    Done,
+    Failed,
+}
+
+/// One repository's entry in a [`BatchState`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoState {
+    pub source: String,
+    pub status: RepoStatus,
+    /// The written file, once done
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    /// Why the repository failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub usage: Usage,
+}
+
+/// Progress of a batch run, saved after every repository so an interrupted run can be
+/// resumed: repositories by output name, and what the run has spent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchState {
+    /// [`BATCH_STATE_VERSION`] of the format the state was written in
+    pub version: u32,
+    pub kind: BatchKind,
+    #[serde(default)]
+    pub spend: Spend,
+    #[serde(default)]
+    pub repos: BTreeMap<String, RepoState>,
+}
+
+impl BatchState {
+    pub fn new(kind: BatchKind) -> Self {
+        Self { version: BATCH_STATE_VERSION, kind, spend: Spend::default(), repos: BTreeMap::new() }
+    }
+
+    /// Read a state file, failing on one written in another format version
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| TechDocsError::Config(format!("Failed to read {}: {}", path.display(), e)))?;
+        let version = serde_json::from_str::<serde_json::Value>(&content)
+            .map_err(|e| TechDocsError::Config(format!("{}: {}", path.display(), e)))?
+            .get("version")
+            .and_then(|version| version.as_u64());
+        if version != Some(BATCH_STATE_VERSION.into()) {
+            return Err(TechDocsError::Config(format!(
+                "{}: unsupported batch state version {}; expected {}",
+                path.display(),
+                version.map_or("(none)".to_string(), |version| version.to_string()),
+                BATCH_STATE_VERSION
+            )));
+        }
+        serde_json::from_str(&content).map_err(|e| TechDocsError::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Write the state by replacing `path`, so a crash leaves the previous state whole
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| TechDocsError::Output(e.to_string()))?;
+        write_atomic(path, &json)
+    }
+
+    /// Record how a repository ended; a resumed repository keeps its earlier entry
+    pub fn record(&mut self, outcome: &BatchOutcome) {
+        if outcome.resumed {
+            return;
+        }
+        let (status, output, error) = match &outcome.result {
+            Ok(path) => (RepoStatus::Done, Some(path.clone()), None),
+            Err(e) => (RepoStatus::Failed, None, Some(e.clone())),
+        };
+        self.repos.insert(
+            outcome.name.clone(),
+            RepoState { source: outcome.source.clone(), status, output, error, usage: outcome.usage },
+        );
+    }
+
+    /// The earlier entry for the repository `name` when it was for the same source
+    fn entry(&self, name: &str, source: &str) -> Option<&RepoState> {
+        self.repos.get(name).filter(|repo| repo.source == source)
+    }
+}
+
+/// A [`BatchState`] kept on disk, updated as repositories finish
+pub struct StateFile {
+    path: PathBuf,
+    state: Mutex<BatchState>,
+    /// The run's spending, copied into the state on every save
+    cost: CostTracker,
+}
+
+impl StateFile {
+    pub fn new(path: PathBuf, state: BatchState, cost: CostTracker) -> Self {
+        Self { path, state: Mutex::new(state), cost }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// A copy of the state as last recorded
+    pub fn state(&self) -> BatchState {
+        self.state.lock().expect("state lock").clone()
+    }
+
+    /// Record `outcome` and save. A state that cannot be saved is logged rather than
+    /// failing the repository, whose output is already written.
+    fn record(&self, outcome: &BatchOutcome) {
+        let mut state = self.state.lock().expect("state lock");
+        state.record(outcome);
+        state.spend = self.cost.spend();
+        if let Err(e) = state.save(&self.path) {
+            tracing::warn!("Failed to save the batch state: {}", e);
+        }
+    }
+}
+
+/// Settings shared by every repository in a batch
+pub struct BatchOptions {
+    pub kind: BatchKind,
+    pub out_dir: PathBuf,
+    /// Maximum number of repositories processed at once
+    pub jobs: usize,
+    /// Settings applied where a manifest entry sets none
+    pub defaults: PartialConfig,
+    /// How file content is cleaned up in README prompts
+    pub normalize: Normalize,
+    /// Where progress is saved after every repository. Repositories its state already
+    /// marks done are skipped and reported as resumed.
+    pub state: Option<Arc<StateFile>>,
+    /// Process only the repositories the state marks failed, leaving out those no
+    /// earlier run reached
+    pub retry_failed_only: bool,
+}
+
+/// The entries still to process, with their output names, and the outcomes of those a
+/// resumed run already finished. Names come from the whole manifest, so they match the
+/// earlier run's.
+fn select_entries(entries: Vec<BatchEntry>, options: &BatchOptions) -> (Vec<(BatchEntry, String)>, Vec<BatchOutcome>) {
+    let names = output_names(&entries);
+    let state = options.state.as_ref().map(|state| state.state());
+    let mut selected = Vec::new();
+    let mut resumed = Vec::new();
+    for (entry, name) in entries.into_iter().zip(names) {
+        let earlier = state.as_ref().and_then(|state| state.entry(&name, &entry.source));
+        match earlier {
+            Some(RepoState { status: RepoStatus::Done, output, usage, .. }) => resumed.push(BatchOutcome {
+                result: Ok(output.clone().unwrap_or_else(|| options.out_dir.join(&name))),
+                name,
+                source: entry.source,
+                duration: Duration::ZERO,
+                usage: *usage,
+                resumed: true,
+            }),
+            None if options.retry_failed_only => {}
+            _ => selected.push((entry, name)),
+        }
+    }
+    (selected, resumed)
+}
+
+/// Process every entry, at most `options.jobs` at a time. A failing repository is
+/// recorded in its outcome and does not stop the others. Outcomes are in manifest order.
+pub async fn run_batch(
+    client: Arc<dyn LlmClient>,
+    entries: Vec<BatchEntry>,
+    options: BatchOptions,
+) -> Vec<BatchOutcome> {
+    let names = output_names(&entries);
+    let (entries, mut outcomes) = select_entries(entries, &options);
+    let options = Arc::new(options);
+    let processed = for_each_entry(entries, options.jobs, |entry, name| {
+        let client = Arc::clone(&client);
+        let options = Arc::clone(&options);
+        async move {
+            let started = Instant::now();
+            let out_dir = options.out_dir.join(&name);
+            let result = process_entry(client.as_ref(), &entry, &options, &out_dir).await;
+            let (result, usage) = match result {
+                Ok((path, usage)) => (Ok(path), usage),
+                Err(e) => (Err(e.to_string()), Usage::default()),
+            };
+            let outcome = BatchOutcome {
+                name,
+                source: entry.source,
+                result,
+                duration: started.elapsed(),
+                usage,
+                resumed: false,
+            };
+            if let Some(state) = &options.state {
+                state.record(&outcome);
+            }
+            outcome
+        }
+    })
+    .await;
+    outcomes.extend(processed);
+    in_manifest_order(&names, outcomes)
+}
+
+/// `outcomes` sorted into manifest order, given the output names of the whole manifest
+fn in_manifest_order(names: &[String], mut outcomes: Vec<BatchOutcome>) -> Vec<BatchOutcome> {
+    let order: HashMap<&str, usize> = names.iter().enumerate().map(|(index, name)| (name.as_str(), index)).collect();
+    outcomes.sort_by_key(|outcome| order.get(outcome.name.as_str()).copied());
+    outcomes
+}
+
+/// Like [`run_batch`], sending every request in one message batch of the Batches API
+/// instead, at half the price and without rate limits. All prompts are assembled first,
+/// `options.jobs` repositories at a time; the batch is then polled until it ends or
+/// `timeout` passes, and each result is written as it is read back. Fails only when the
+/// batch itself cannot be submitted, completed, or read, or when `cost` has no room
+/// for every request; the usage of the results is recorded in `cost` at list price.
+pub async fn run_batch_api(
+    client: &ClaudeClient,
+    entries: Vec<BatchEntry>,
+    options: BatchOptions,
+    timeout: Duration,
+    cost: &CostTracker,
+) -> Result<Vec<BatchOutcome>> {
+    let started = Instant::now();
+    let names = output_names(&entries);
+    let (entries, resumed) = select_entries(entries, &options);
+    let options = Arc::new(options);
+    let prepared = for_each_entry(entries, options.jobs, |entry, name| {
+        let options = Arc::clone(&options);
+        async move {
+            let prepared = prepare_entry(&entry, &options).await.map_err(|e| e.to_string());
+            (entry, name, prepared)
+        }
+    })
+    .await;
+
+    let mut outcomes = Vec::new();
+    let mut pending = HashMap::new();
+    let mut requests = Vec::new();
+    for (index, (entry, name, prepared)) in prepared.into_iter().enumerate() {
+        let submitted = prepared.is_ok();
+        let result = match prepared {
+            Ok(prepared) => {
+                let custom_id = format!("repo-{}", index);
+                requests.push(BatchRequest { custom_id: custom_id.clone(), request: prepared.request });
+                pending.insert(custom_id, (index, prepared.license));
+                Err("no result in the batch".to_string())
+            }
+            Err(e) => Err(e),
+        };
+        let outcome = BatchOutcome {
+            name,
+            source: entry.source,
+            result,
+            duration: started.elapsed(),
+            usage: Usage::default(),
+            resumed: false,
+        };
+        // A repository that could not be prepared has already ended
+        if let (Some(state), false) = (&options.state, submitted) {
+            state.record(&outcome);
+        }
+        outcomes.push(outcome);
+    }
+    let finish = |mut outcomes: Vec<BatchOutcome>| {
+        outcomes.extend(resumed);
+        in_manifest_order(&names, outcomes)
+    };
+    if requests.is_empty() {
+        return Ok(finish(outcomes));
+    }
+
+    cost.check_all(requests.iter().map(|request| &request.request))?;
+    let batch = client.create_batch(&requests).await?;
+    tracing::info!("Submitted batch {} with {} requests", batch.id, requests.len());
+    client.wait_for_batch(&batch.id, timeout).await?;
+    client
+        .fetch_batch_results(&batch.id, |result| {
+            let Some((index, license)) = pending.remove(&result.custom_id) else {
+                tracing::warn!("Ignoring batch result for unknown request {}", result.custom_id);
+                return Ok(());
+            };
+            let outcome = &mut outcomes[index];
+            let out_dir = options.out_dir.join(&outcome.name);
+            outcome.duration = started.elapsed();
+            outcome.result = match result.result {
+                Ok(completion) => {
+                    outcome.usage = completion.usage;
+                    cost.record(&completion.model, completion.usage);
+                    write_result(options.kind, &out_dir, &completion, license).map_err(|e| e.to_string())
+                }
+                Err(e) => Err(e),
+            };
+            if let Some(state) = &options.state {
+                state.record(outcome);
+            }
+            Ok(())
+        })
+        .await?;
+    // Requests the batch returned nothing for failed too
+    if let Some(state) = &options.state {
+        for (index, _) in pending.values() {
+            state.record(&outcomes[*index]);
+        }
+    }
+    Ok(finish(outcomes))
+}
+
+/// Run `task` for each entry and its output name, at most `jobs` at a time. Results are
+/// in manifest order; a task that panics loses its result and the rest still complete.
+async fn for_each_entry<T, F, Fut>(entries: Vec<(BatchEntry, String)>, jobs: usize, task: F) -> Vec<T>
+where
+    T: Send + 'static,
+    F: Fn(BatchEntry, String) -> Fut,
+    Fut: Future<Output = T> + Send + 'static,
+{
+    let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut tasks = JoinSet::new();
+
+    for (index, (entry, name)) in entries.into_iter().enumerate() {
+        let semaphore = Arc::clone(&semaphore);
+        let work = task(entry, name);
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await.expect("semaphore is never closed");
+            (index, work.await)
+        });
+    }
+
+    let mut results: Vec<(usize, T)> = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(result) => results.push(result),
+            Err(e) => tracing::error!("batch task failed: {}", e),
+        }
+    }
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// A repository's request, ready to send, and what is needed to use its answer
+struct PreparedEntry {
+    request: CompletionRequest,
+    /// License of the project, for a summary; the checkout is gone by the time the
+    /// answer comes
+    license: Option<String>,
+}
+
+/// Resolve an entry and assemble its request without sending it
+async fn prepare_entry(entry: &BatchEntry, options: &BatchOptions) -> Result<PreparedEntry> {
+    let (path, _temp_dir) = resolve_path(&entry.source).await?;
+    validate_directory(&path)?;
+    let config = Config::load(Some(&path), entry.overrides(&options.defaults))?;
+    match options.kind {
+        BatchKind::Readme => {
+            let mut generate = GenerateOptions::from_config(&config)?;
+            generate.files.normalize = options.normalize;
+            let prompt = ReadmePrompt::build(&entry.source, &path, &generate, &no_progress)?;
+            Ok(PreparedEntry { request: prompt.request(), license: None })
+        }
+        BatchKind::Summary => Ok(PreparedEntry {
+            request: summary_request(&path, &summary_files(&path)?, config.model.as_deref())?,
+            license: detect_license(&path).map(|license| license.spdx),
+        }),
+    }
+}
+
+/// Write the document in `completion` to `out_dir`, returning its path
+fn write_result(kind: BatchKind, out_dir: &Path, completion: &CompletionResult, license: Option<String>) -> Result<PathBuf> {
+    let (file, content) = match kind {
+        BatchKind::Readme => ("README.md", tidy_markdown(&completion.text)),
+        BatchKind::Summary => {
+            let summary = parse_summary(completion, license)?;
+            let json = serde_json::to_string_pretty(&summary).map_err(|e| TechDocsError::Output(e.to_string()))?;
+            ("summary.json", json)
+        }
+    };
+    fs::create_dir_all(out_dir)
+        .map_err(|e| TechDocsError::Output(format!("Failed to create {}: {}", out_dir.display(), e)))?;
+    let output = out_dir.join(file);
+    write_atomic(&output, &content)?;
+    Ok(output)
+}
+
+async fn process_entry(
+    client: &dyn LlmClient,
+    entry: &BatchEntry,
+    options: &BatchOptions,
+    out_dir: &Path,
+) -> Result<(PathBuf, Usage)> {
+    let (path, _temp_dir) = resolve_path(&entry.source).await?;
+    validate_directory(&path)?;
+    let config = Config::load(Some(&path), entry.overrides(&options.defaults))?;
+
+    fs::create_dir_all(out_dir)
+        .map_err(|e| TechDocsError::Output(format!("Failed to create {}: {}", out_dir.display(), e)))?;
+
+    match options.kind {
+        BatchKind::Readme => {
+            let mut generate = GenerateOptions::from_config(&config)?;
+            generate.files.normalize = options.normalize;
+            let prompt = ReadmePrompt::build(&entry.source, &path, &generate, &no_progress)?;
+            let mut readme = prompt.generate(client, None).await?;
+            generate.post_processors.run(&mut readme)?;
+            let output = out_dir.join("README.md");
+            write_atomic(&output, &readme.content)?;
+            Ok((output, readme.usage))
+        }
+        BatchKind::Summary => {
+            let files = summary_files(&path)?;
+            let (summary, usage) = summarize(client, &path, &files, config.model.as_deref()).await?;
+            let json = serde_json::to_string_pretty(&summary)
+                .map_err(|e| TechDocsError::Output(e.to_string()))?;
+            let output = out_dir.join("summary.json");
+            write_atomic(&output, &json)?;
+            Ok((output, usage))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use crate::provider::CostTrackedClient;
+    use crate::testing::{FixtureTree, MockClient};
+
+    fn project(name: &str) -> FixtureTree {
+        FixtureTree::new().file("README.md", format!("# {}\n", name)).file("src/main.rs", "fn main() {}\n")
+    }
+
+    fn options(out: &FixtureTree, jobs: usize) -> BatchOptions {
+        BatchOptions {
+            kind: BatchKind::Summary,
+            out_dir: out.path().to_path_buf(),
+            jobs,
+            defaults: PartialConfig::default(),
+            normalize: Normalize::Off,
+            state: None,
+            retry_failed_only: false,
+        }
+    }
+
+    fn summarizer() -> MockClient {
+        MockClient::tool(serde_json::json!({"description": "A project.", "topics": ["demo"]}))
+    }
+
+    #[test]
+    fn manifests_are_read_as_lines_or_toml() {
+        let dir = FixtureTree::new()
+            .file("repos.txt", "# catalog\nhttps://github.com/acme/api\n\n  ../local  \n")
+            .file("repos.toml", "[[repo]]\nsource = \"https://github.com/acme/api\"\nname = \"api-docs\"\nmodel = \"claude-a\"\n")
+            .file("empty.txt", "# nothing yet\n")
+            .file("bad.toml", "[[repo]]\nsource = \"x\"\nbranch = \"main\"\n");
+
+        let lines = read_manifest(&dir.join("repos.txt")).unwrap();
+        assert_eq!(lines.iter().map(|entry| entry.source.as_str()).collect::<Vec<_>>(), ["https://github.com/acme/api", "../local"]);
+        let tables = read_manifest(&dir.join("repos.toml")).unwrap();
+        assert_eq!((tables[0].name.as_deref(), tables[0].model.as_deref()), (Some("api-docs"), Some("claude-a")));
+
+        let error = read_manifest(&dir.join("empty.txt")).unwrap_err();
+        assert!(error.to_string().contains("no repositories listed"), "{}", error);
+        let error = read_manifest(&dir.join("bad.toml")).unwrap_err();
+        assert!(error.to_string().contains("unknown field `branch`"), "{}", error);
+    }
+
+    #[test]
+    fn output_names_are_unique_path_components() {
+        let entries: Vec<BatchEntry> =
+            ["https://github.com/acme/api.git", "https://github.com/other/api/", "../..", "a b/c:d"].map(BatchEntry::new).into();
+        assert_eq!(output_names(&entries), ["api", "api-2", "repo", "c-d"]);
+    }
+
+    #[tokio::test]
+    async fn a_failing_repository_does_not_stop_the_others() {
+        let (first, second, out) = (project("first"), project("second"), FixtureTree::new());
+        let missing = first.join("missing");
+        let entries = vec![
+            BatchEntry { name: Some("first".into()), ..BatchEntry::new(first.path().to_str().unwrap()) },
+            BatchEntry { name: Some("missing".into()), ..BatchEntry::new(missing.to_str().unwrap()) },
+            BatchEntry { name: Some("second".into()), ..BatchEntry::new(second.path().to_str().unwrap()) },
+        ];
+        let client = Arc::new(summarizer());
+
+        let outcomes = run_batch(client.clone(), entries, options(&out, 2)).await;
+        assert_eq!(outcomes.iter().map(|outcome| outcome.name.as_str()).collect::<Vec<_>>(), ["first", "missing", "second"]);
+        assert_eq!(outcomes[0].result.as_ref().unwrap(), &out.join("first/summary.json"));
+        assert!(outcomes[1].result.is_err());
+        assert_eq!(outcomes[1].source, missing.to_str().unwrap());
+        assert_eq!(outcomes[2].result.as_ref().unwrap(), &out.join("second/summary.json"));
+        assert!(outcomes.iter().all(|outcome| !outcome.resumed));
+        assert_eq!(client.requests().len(), 2);
+
+        let summary: serde_json::Value = serde_json::from_str(&fs::read_to_string(out.join("second/summary.json")).unwrap()).unwrap();
+        assert_eq!(summary["description"], "A project.");
+        assert!(!out.join("missing/summary.json").exists());
+    }
+
+    #[tokio::test]
+    async fn at_most_jobs_repositories_run_at_once() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let entries: Vec<(BatchEntry, String)> =
+            (0..6).map(|index| (BatchEntry::new(&format!("repo-{}", index)), format!("repo-{}", index))).collect();
+
+        let finished = for_each_entry(entries, 2, |_, name| {
+            let (running, most) = (Arc::clone(&running), Arc::clone(&most));
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                name
+            }
+        })
+        .await;
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+        assert_eq!(finished, (0..6).map(|index| format!("repo-{}", index)).collect::<Vec<_>>());
+    }
+
+    /// A local Batches API answering one connection per body in turn, its base URL, and
+    /// the request line and body of each request it received
+    async fn batch_api(bodies: Vec<String>) -> (String, Arc<Mutex<Vec<(String, String)>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&received);
+        tokio::spawn(async move {
+            for body in bodies {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut head = String::new();
+                while reader.read_line(&mut head).await.unwrap() > 0 && !head.ends_with("\r\n\r\n") {}
+                let length = head
+                    .lines()
+                    .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|n| n.trim().parse().unwrap()))
+                    .unwrap_or(0);
+                let mut request = vec![0; length];
+                reader.read_exact(&mut request).await.unwrap();
+                let line = head.lines().next().unwrap_or_default().to_string();
+                log.lock().unwrap().push((line, String::from_utf8(request).unwrap()));
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                reader.into_inner().write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (base_url, received)
+    }
+
+    fn batch_handle(status: &str) -> String {
+        serde_json::json!({
+            "id": "msgbatch_01",
+            "processing_status": status,
+            "request_counts": {"processing": 0, "succeeded": 1, "errored": 1, "canceled": 0, "expired": 0},
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn a_partly_errored_message_batch_writes_what_succeeded() {
+        let (first, second, out) = (project("first"), project("second"), FixtureTree::new());
+        let missing = first.join("missing");
+        let entries = vec![
+            BatchEntry { name: Some("first".into()), ..BatchEntry::new(first.path().to_str().unwrap()) },
+            BatchEntry { name: Some("missing".into()), ..BatchEntry::new(missing.to_str().unwrap()) },
+            BatchEntry { name: Some("second".into()), ..BatchEntry::new(second.path().to_str().unwrap()) },
+        ];
+        let succeeded = serde_json::json!({
+            "custom_id": "repo-0",
+            "result": {"type": "succeeded", "message": {
+                "model": "claude-3-5-haiku-20241022",
+                "content": [{"type": "tool_use", "id": "t", "name": "record_summary", "input": {"description": "The first.", "topics": []}}],
+                "stop_reason": "tool_use",
+                "stop_sequence": null,
+                "usage": {"input_tokens": 1000, "output_tokens": 100},
+            }},
+        });
+        let errored = serde_json::json!({
+            "custom_id": "repo-2",
+            "result": {"type": "errored", "error": {"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}},
+        });
+        let results = format!("{}\n{}\n", succeeded, errored);
+        let (base_url, received) = batch_api(vec![batch_handle("in_progress"), batch_handle("ended"), results]).await;
+        let client = ClaudeClient::with_base_url("test-key", base_url);
+        let cost = CostTracker::new(None);
+
+        let outcomes = run_batch_api(&client, entries, options(&out, 2), Duration::from_secs(60), &cost).await.unwrap();
+        assert_eq!(outcomes[0].result.as_ref().unwrap(), &out.join("first/summary.json"));
+        assert_eq!(outcomes[0].usage.input_tokens, 1000);
+        assert!(outcomes[1].result.is_err());
+        assert_eq!(outcomes[2].result.as_ref().unwrap_err(), "overloaded_error: Overloaded");
+        let summary: serde_json::Value = serde_json::from_str(&fs::read_to_string(out.join("first/summary.json")).unwrap()).unwrap();
+        assert_eq!(summary["description"], "The first.");
+        assert_eq!(cost.spend().calls, 1);
+
+        let received = received.lock().unwrap().clone();
+        let lines: Vec<&str> = received.iter().map(|(line, _)| line.as_str()).collect();
+        assert_eq!(
+            lines,
+            [
+                "POST /v1/messages/batches HTTP/1.1",
+                "GET /v1/messages/batches/msgbatch_01 HTTP/1.1",
+                "GET /v1/messages/batches/msgbatch_01/results HTTP/1.1",
+            ]
+        );
+        let submitted: serde_json::Value = serde_json::from_str(&received[0].1).unwrap();
+        let ids: Vec<&str> = submitted["requests"].as_array().unwrap().iter().map(|request| request["custom_id"].as_str().unwrap()).collect();
+        assert_eq!(ids, ["repo-0", "repo-2"]);
+        assert_eq!(submitted["requests"][0]["params"]["tool_choice"]["name"], "record_summary");
+    }
+
+    /// A state in which `done` finished and `failed` failed, with $0.50 spent over 5 calls
+    fn interrupted(done: &FixtureTree, failed: &FixtureTree) -> BatchState {
+        let mut state = BatchState::new(BatchKind::Summary);
+        state.spend = Spend { calls: 5, usage: Usage::default(), cost_usd: 0.5 };
+        let repo = |tree: &FixtureTree, status, error: Option<&str>| RepoState {
+            source: tree.path().to_str().unwrap().to_string(),
+            status,
+            output: (status == RepoStatus::Done).then(|| tree.join("summary.json")),
+            error: error.map(String::from),
+            usage: Usage::default(),
+        };
+        state.repos.insert("done".into(), repo(done, RepoStatus::Done, None));
+        state.repos.insert("failed".into(), repo(failed, RepoStatus::Failed, Some("network blip")));
+        state
+    }
+
+    fn entries(trees: [(&str, &FixtureTree); 3]) -> Vec<BatchEntry> {
+        trees
+            .into_iter()
+            .map(|(name, tree)| BatchEntry { name: Some(name.into()), ..BatchEntry::new(tree.path().to_str().unwrap()) })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn a_resumed_run_skips_what_is_done_and_keeps_the_spending() {
+        let (done, failed, unreached, out) = (project("done"), project("failed"), project("unreached"), FixtureTree::new());
+        let state_path = out.join(BATCH_STATE_FILE);
+        interrupted(&done, &failed).save(&state_path).unwrap();
+
+        let state = BatchState::load(&state_path).unwrap();
+        let cost = CostTracker::resume(None, state.spend);
+        let state = Arc::new(StateFile::new(state_path.clone(), state, cost.clone()));
+        let client = Arc::new(CostTrackedClient::new(summarizer(), cost.clone()));
+        let options = BatchOptions { state: Some(state), ..options(&out, 1) };
+
+        let outcomes = run_batch(client, entries([("done", &done), ("failed", &failed), ("unreached", &unreached)]), options).await;
+        assert_eq!(outcomes.iter().map(|outcome| outcome.resumed).collect::<Vec<_>>(), [true, false, false]);
+        assert_eq!(outcomes[0].result.as_ref().unwrap(), &done.join("summary.json"));
+        assert!(outcomes.iter().all(|outcome| outcome.result.is_ok()));
+        assert_eq!(cost.spend().calls, 7);
+
+        let saved = BatchState::load(&state_path).unwrap();
+        assert!(saved.repos.values().all(|repo| repo.status == RepoStatus::Done), "{:?}", saved.repos);
+        assert_eq!(saved.repos.len(), 3);
+        assert_eq!((saved.spend.calls, saved.spend.cost_usd), (7, 0.5));
+    }
+
+    #[tokio::test]
+    async fn retrying_failed_repositories_leaves_the_rest_alone() {
+        let (done, failed, unreached, out) = (project("done"), project("failed"), project("unreached"), FixtureTree::new());
+        let state = Arc::new(StateFile::new(out.join(BATCH_STATE_FILE), interrupted(&done, &failed), CostTracker::default()));
+        let client = Arc::new(summarizer());
+        let options = BatchOptions { state: Some(state), retry_failed_only: true, ..options(&out, 1) };
+
+        let outcomes = run_batch(client.clone(), entries([("done", &done), ("failed", &failed), ("unreached", &unreached)]), options).await;
+        assert_eq!(outcomes.iter().map(|outcome| outcome.name.as_str()).collect::<Vec<_>>(), ["done", "failed"]);
+        assert!(outcomes[0].resumed && !outcomes[1].resumed);
+        assert_eq!(client.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_repository_whose_source_changed_is_processed_again() {
+        let (done, failed, moved, out) = (project("done"), project("failed"), project("moved"), FixtureTree::new());
+        let state = Arc::new(StateFile::new(out.join(BATCH_STATE_FILE), interrupted(&done, &failed), CostTracker::default()));
+        let client = Arc::new(summarizer());
+        let options = BatchOptions { state: Some(state), ..options(&out, 1) };
+
+        let outcomes = run_batch(client.clone(), entries([("done", &moved), ("failed", &failed), ("other", &done)]), options).await;
+        assert!(outcomes.iter().all(|outcome| !outcome.resumed && outcome.result.is_ok()));
+        assert_eq!(client.requests().len(), 3);
+        assert_eq!(outcomes[0].result.as_ref().unwrap(), &out.join("done/summary.json"));
+    }
+
+    #[test]
+    fn state_files_of_another_version_are_refused() {
+        let dir = FixtureTree::new()
+            .file("future.json", r#"{"version": 2, "kind": "readme", "repos": {}}"#)
+            .file("unversioned.json", r#"{"kind": "readme"}"#)
+            .file("truncated.json", r#"{"version": 1, "kind": "rea"#);
+        let error = BatchState::load(&dir.join("future.json")).unwrap_err();
+        assert!(error.to_string().contains("unsupported batch state version 2; expected 1"), "{}", error);
+        let error = BatchState::load(&dir.join("unversioned.json")).unwrap_err();
+        assert!(error.to_string().contains("version (none)"), "{}", error);
+        assert!(BatchState::load(&dir.join("truncated.json")).is_err());
+
+        let path = dir.join("state.json");
+        BatchState::new(BatchKind::Readme).save(&path).unwrap();
+        let loaded = BatchState::load(&path).unwrap();
+        assert_eq!((loaded.version, loaded.kind), (BATCH_STATE_VERSION, BatchKind::Readme));
+    }
+}
+
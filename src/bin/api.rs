@@ -1,132 +1,4605 @@
-use std::io::Read;
-use std::net::SocketAddr;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::future::{Future, IntoFuture};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use axum::{self,
-    routing::{get, post},
-    http::StatusCode,
+    routing::{delete, get, post},
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    response::{Html, IntoResponse, Response},
+    response::sse::{Event, KeepAlive, Sse},
+    middleware::{self, Next},
+    body::{Body, Bytes},
     Json, Router,
-    extract::State,
+    extract::{
+        rejection::{BytesRejection, JsonRejection},
+        multipart::{Multipart, MultipartError},
+        ConnectInfo, DefaultBodyLimit, FromRequest, Path as AxumPath, Query, State,
+    },
 };
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::builder::BoolishValueParser;
+use clap::Parser;
 use serde::{Deserialize, Serialize};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
 
+use futures_util::{Stream, StreamExt};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tokio::sync::mpsc::unbounded_channel;
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+use tokio_util::io::ReaderStream;
+use tower::ServiceBuilder;
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing::field::Empty;
+use url::Url;
 use techdocs::{
-    list_files_prompt, resolve_path, generate_readme,
-    Result as TechDocsResult,
+    resolve_path_at, load_prompt_templates, load_readme_prompt, code_language, normalize_source, CancellationToken, Checkout,
+    Result as TechDocsResult, TechDocsError,
 };
+use techdocs::archive::{extract, ArchiveFormat};
+use techdocs::badges::{badges, detect_metadata};
+use techdocs::cache::{CacheKey, ResultCache};
+use techdocs::callback::{CallbackPolicy, CallbackSender};
+use techdocs::doctor::{check_api_key_online, check_disk_space, CheckResult, CheckStatus};
+use techdocs::documents::{new_document_id, Document, DocumentInfo, DocumentStore, LocalDocumentStore, Retention};
+use techdocs::claude::ClaudeClient;
+use techdocs::generate::{GenerateOptions, Provenance, ReadmePrompt};
+use techdocs::git::head_commit;
+use techdocs::github::{fetch_remote_metadata, GitHubClient, PullRequestOutcome, PullRequestText, RemoteMetadata, Repository};
+use techdocs::manifest::FileManifest;
+use techdocs::metrics::{self, Registry};
+use techdocs::plan::{Normalize, PromptOptions, PromptPlan, SkipReason};
+use techdocs::postprocess::{Footer, PostProcessors};
+use techdocs::jobs::{JobHandle, JobQueue, JobSnapshot, JobStatus, QueueFull};
+use techdocs::progress::{no_progress, ProgressEvent, ProgressFn};
+use techdocs::project::BUILTIN_TEMPLATES;
+use techdocs::provider::{CostTrackedClient, CostTracker, TextFn, Usage};
+use techdocs::logging::{init_logging, LogFormat, LogOptions};
+use techdocs::config::{parse_exclude_patterns, Config, PartialConfig};
+use techdocs::summary::{pick_summary_files, summarize, ProjectSummary};
+use techdocs::validate::{check_links, validate_markdown, OutputIssue};
+use techdocs::workspace::Workspace;
 
 #[derive(Clone)]
 struct AppState {
-    readme_prompt: String,
+    settings: Arc<Settings>,
+    jobs: Arc<GenerateJobs>,
+    /// Set when the server has a callback secret
+    callbacks: Option<Arc<CallbackSender>>,
+    /// Set when the server has a GitHub webhook secret
+    webhook: Option<Arc<GitHubWebhook>>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Server configuration the generation pipeline runs with
+struct Settings {
+    templates: PromptTemplates,
+    limits: RequestLimits,
+    paths: PathPolicy,
+    /// Largest prompt or manifest returned to a client, in bytes
+    max_response_bytes: usize,
+    slots: GenerationSlots,
+    /// Generated READMEs by project revision and prompt; `None` when caching is off
+    cache: Option<ResultCache<GenerateReadmeResponse>>,
+    /// Largest request body accepted, in bytes
+    max_body_bytes: usize,
+    /// Largest archive accepted by /generate/upload, in bytes
+    max_upload_bytes: u64,
+    /// Longest a request may take, including a streamed generation
+    request_timeout: Duration,
+    /// Cancelled when the drain timeout runs out at shutdown, stopping the work that
+    /// requests and jobs still have in progress
+    shutdown: CancellationToken,
+    /// Where repositories are cloned, within a disk quota
+    workspace: Arc<Workspace>,
+    /// Per-client request rate limit; `None` when clients are not limited
+    limiter: Option<Arc<ClientLimiter>>,
+    /// Where generated READMEs are kept for /documents; `None` when they are not
+    documents: Option<Arc<dyn DocumentStore>>,
+    retention: Retention,
+    /// Latest provider check, for /ready
+    provider_probe: Arc<ProviderProbe>,
+    /// Free disk space below which the workspace makes the server not ready
+    min_free_bytes: u64,
+    started_at: DateTime<Utc>,
+    /// Pipeline counters and timings the library records, for /metrics
+    pipeline_metrics: Arc<Registry>,
+    /// Ask the GitHub API about repository URLs for the prompt and the response
+    remote_metadata: bool,
+}
+
+impl Settings {
+    fn check_response_size(&self, bytes: usize) -> Result<(), ApiError> {
+        if bytes > self.max_response_bytes {
+            return Err(ApiError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                ErrorCode::ResponseTooLarge,
+                format!(
+                    "the response would be {} bytes, over this server's limit of {}; \
+                     narrow it with include_patterns, max_files, or max_total_size_mb, or lower max_tokens",
+                    bytes, self.max_response_bytes
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Template used when a request names none
+const DEFAULT_TEMPLATE: &str = "readme";
+
+/// README system prompts by name: the built-in templates such as `generic` and `rust`,
+/// `readme`, from TECHDOCS_PROMPT_FILE or built in, plus every template in the server's
+/// --prompts-dir, which may replace any of them
+struct PromptTemplates {
+    default: String,
+    dir: Option<PathBuf>,
+    templates: RwLock<BTreeMap<String, Arc<str>>>,
+}
+
+impl PromptTemplates {
+    fn load(default: String, dir: Option<PathBuf>) -> TechDocsResult<Self> {
+        let templates = Self::read(&default, dir.as_deref())?;
+        Ok(Self {
+            default,
+            dir,
+            templates: RwLock::new(templates),
+        })
+    }
+
+    fn read(default: &str, dir: Option<&Path>) -> TechDocsResult<BTreeMap<String, Arc<str>>> {
+        let mut templates: BTreeMap<String, Arc<str>> =
+            BUILTIN_TEMPLATES.iter().map(|(name, text)| (name.to_string(), Arc::from(*text))).collect();
+        templates.insert(DEFAULT_TEMPLATE.to_string(), Arc::from(default));
+        if let Some(dir) = dir {
+            templates.extend(load_prompt_templates(dir)?.into_iter().map(|(name, text)| (name, Arc::from(text))));
+        }
+        Ok(templates)
+    }
+
+    /// Read the directory again, keeping the current templates if that fails
+    fn reload(&self) -> TechDocsResult<Vec<String>> {
+        let templates = Self::read(&self.default, self.dir.as_deref())?;
+        let names = templates.keys().cloned().collect();
+        *self.templates.write().unwrap() = templates;
+        Ok(names)
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.templates.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Whether the templates can be read again as they were at startup
+    fn check(&self) -> CheckResult {
+        let (status, detail) = match Self::read(&self.default, self.dir.as_deref()) {
+            Ok(_) if self.default.trim().is_empty() => (CheckStatus::Fail, "the README prompt is empty".to_string()),
+            Ok(templates) => (CheckStatus::Pass, format!("{} templates", templates.len())),
+            Err(e) => (CheckStatus::Fail, e.to_string()),
+        };
+        CheckResult { name: "templates", status, detail, required: true }
+    }
+
+    /// The template called `name`, or the default one, with its name
+    fn get(&self, name: Option<&str>) -> Result<(String, Arc<str>), String> {
+        let name = name.unwrap_or(DEFAULT_TEMPLATE);
+        let text = self.templates.read().unwrap().get(name).cloned();
+        match text {
+            Some(text) => Ok((name.to_string(), text)),
+            None => Err(format!("template must be one of: {}", self.names().join(", "))),
+        }
+    }
+}
+
+/// Bounds how many generations call the provider at once. A request waits at most
+/// `max_wait` for a slot and is then turned away, so a burst cannot pile up clones and
+/// provider calls until the server runs out of disk or memory.
+struct GenerationSlots {
+    semaphore: Arc<Semaphore>,
+    limit: usize,
+    max_wait: Duration,
+    waiting: AtomicUsize,
+    rejected: AtomicU64,
+}
+
+/// Counts a request among the waiting ones until it gets a slot or gives up
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn new(waiting: &'a AtomicUsize) -> Self {
+        waiting.fetch_add(1, Ordering::Relaxed);
+        Self(waiting)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl GenerationSlots {
+    fn new(limit: usize, max_wait: Duration) -> Self {
+        let limit = limit.max(1);
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit,
+            max_wait,
+            waiting: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Take a slot, or give up with 429 once none frees up within `max_wait`
+    async fn acquire(&self) -> Result<OwnedSemaphorePermit, ApiError> {
+        let _waiting = Waiting::new(&self.waiting);
+        match tokio::time::timeout(self.max_wait, Arc::clone(&self.semaphore).acquire_owned()).await {
+            Ok(permit) => Ok(permit.expect("generation slots are never closed")),
+            Err(_) => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                Err(ApiError::new(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    ErrorCode::Busy,
+                    "the server is busy with other generations; try again later",
+                )
+                .retry_after(self.max_wait))
+            }
+        }
+    }
+
+    /// Take a slot however long it takes, for jobs that already waited their turn in the queue
+    async fn acquire_queued(&self) -> OwnedSemaphorePermit {
+        let _waiting = Waiting::new(&self.waiting);
+        Arc::clone(&self.semaphore)
+            .acquire_owned()
+            .await
+            .expect("generation slots are never closed")
+    }
+
+    fn in_flight(&self) -> usize {
+        self.limit - self.semaphore.available_permits()
+    }
+}
+
+/// Queue of `POST /jobs` generations
+type GenerateJobs = JobQueue<GenerateReadmeRequest, GenerateOutcome>;
+
+/// Which local directories requests may read. Repository URLs are always allowed, as
+/// far as `resolve_path` accepts their host.
+#[derive(Debug, Clone, Default)]
+struct PathPolicy {
+    /// Canonical directories under which local paths are allowed; empty rejects every local path
+    allowed_roots: Vec<PathBuf>,
+}
+
+impl PathPolicy {
+    /// Build a policy from configured roots, dropping (with a warning) those that do not exist
+    fn new(roots: &[PathBuf]) -> Self {
+        let allowed_roots = roots
+            .iter()
+            .filter_map(|root| match root.canonicalize() {
+                Ok(root) => Some(root),
+                Err(e) => {
+                    tracing::warn!("ignoring allowed path {}: {}", root.display(), e);
+                    None
+                }
+            })
+            .collect();
+        Self { allowed_roots }
+    }
+
+    /// The input to resolve for `path_or_url`: URLs unchanged, local paths canonicalized.
+    /// Canonicalizing first resolves `..` and symlinks, so the prefix check sees where the
+    /// path really leads. Missing and forbidden paths get the same answer, so a client
+    /// cannot probe which directories exist.
+    fn check(&self, path_or_url: &str) -> Result<String, String> {
+        let path_or_url = normalize_source(path_or_url).map_err(|e| e.to_string())?;
+        if Url::parse(path_or_url).is_ok() {
+            return Ok(path_or_url.to_string());
+        }
+        let forbidden = || format!("{} is not under a directory this server allows", path_or_url);
+        let path = Path::new(path_or_url).canonicalize().map_err(|_| forbidden())?;
+        if !self.allowed_roots.iter().any(|root| path.starts_with(root)) {
+            return Err(forbidden());
+        }
+        Ok(path.to_string_lossy().into_owned())
+    }
+}
+
+/// A request's `path_or_url` without surrounding whitespace and quotes. An empty one is a
+/// malformed request rather than a path the policy forbids.
+fn request_source(path_or_url: &str) -> Result<&str, ApiError> {
+    normalize_source(path_or_url)
+        .map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::InvalidUrl, e.to_string()))
+}
+
+/// Resolve a request's `path_or_url` after checking it against the server's path policy,
+/// cloning into the server's workspace and checking out `revision` when one is given
+async fn resolve_request_path(
+    settings: &Settings,
+    path_or_url: &str,
+    revision: Option<&str>,
+    progress: ProgressFn<'_>,
+    cancel: &CancellationToken,
+) -> Result<(PathBuf, Option<Checkout>), ApiError> {
+    let path_or_url = settings.paths.check(request_source(path_or_url)?).map_err(ApiError::forbidden)?;
+    Ok(resolve_path_at(&path_or_url, revision, Some(&settings.workspace), progress, cancel).await?)
+}
+
+/// Ceilings for the per-request overrides of /generate, so a client cannot ask the
+/// server to read or spend without bound
+#[derive(Debug, Clone)]
+struct RequestLimits {
+    max_file_size_kb: u64,
+    max_total_size_mb: u64,
+    max_files: usize,
+    max_tokens: u32,
+    /// Files and directories a walk may find before the source is refused
+    walk_entries: usize,
+    /// How long file selection may take before the source is refused
+    walk_time: Duration,
+    /// Models a request may choose; empty allows any
+    models: Vec<String>,
+}
+
+impl RequestLimits {
+    /// Check the overrides in `request`, describing the first one out of range
+    fn check(&self, request: &GenerateReadmeRequest) -> Result<(), String> {
+        check_range("max_file_size_kb", request.max_file_size_kb, self.max_file_size_kb)?;
+        check_range("max_total_size_mb", request.max_total_size_mb, self.max_total_size_mb)?;
+        check_range("max_files", request.max_files, self.max_files)?;
+        check_range("max_files_per_dir", request.max_files_per_dir, self.max_files)?;
+        check_range("max_tokens", request.max_tokens, self.max_tokens)?;
+        if request.max_cost_usd.is_some_and(|cost| !(cost.is_finite() && cost > 0.0)) {
+            return Err("max_cost_usd must be a positive number".to_string());
+        }
+        if let Some(model) = &request.model {
+            if model.trim().is_empty() {
+                return Err("model must not be empty".to_string());
+            }
+            if !self.models.is_empty() && !self.models.contains(model) {
+                return Err(format!("model must be one of: {}", self.models.join(", ")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the limits and model that a project's `.techdocs.toml` changed, from `base`
+    /// loaded without it to `merged` loaded with it, so a repository cannot raise what a
+    /// request may not
+    fn check_project(&self, base: &Config, merged: &Config) -> Result<(), String> {
+        let in_project = |e: String| format!("the project's .techdocs.toml: {}", e);
+        if merged.max_file_size_kb != base.max_file_size_kb {
+            check_range("max_file_size_kb", Some(merged.max_file_size_kb), self.max_file_size_kb).map_err(in_project)?;
+        }
+        if merged.max_total_size_mb != base.max_total_size_mb {
+            check_range("max_total_size_mb", Some(merged.max_total_size_mb), self.max_total_size_mb)
+                .map_err(in_project)?;
+        }
+        if let Some(model) = merged.model.as_ref().filter(|_| merged.model != base.model) {
+            if !self.models.is_empty() && !self.models.contains(model) {
+                return Err(in_project(format!("model must be one of: {}", self.models.join(", "))));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Merge the server's configuration, the project's `.techdocs.toml` in `path`, and a
+/// request's `layer`, refusing limits or a model the project file sets past the server's
+fn load_config(settings: &Settings, path: &Path, layer: PartialConfig) -> Result<Config, ApiError> {
+    let base = Config::load(None, layer.clone())?;
+    let config = Config::load(Some(path), layer)?;
+    settings.limits.check_project(&base, &config).map_err(ApiError::invalid)?;
+    Ok(config)
+}
+
+fn check_range<T: PartialOrd + From<u8> + std::fmt::Display>(
+    field: &str,
+    value: Option<T>,
+    max: T,
+) -> Result<(), String> {
+    match value {
+        Some(value) if value < T::from(1) || value > max => {
+            Err(format!("{} must be between 1 and {}, got {}", field, max, value))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A project to document and how to choose its files. Used by /generate,
+/// /generate/stream, /prompt, /files, and /jobs.
+#[derive(Debug, Default, Deserialize, ToSchema)]
 struct GenerateReadmeRequest {
+    /// Local directory or GitHub repository URL. Local paths are only accepted under
+    /// the directories in the server's TECHDOCS_ALLOWED_PATHS.
+    #[schema(example = "https://github.com/username/repo")]
     path_or_url: String,
+    /// Patterns to exclude, in .gitignore format, on top of the defaults
+    #[schema(example = json!(["target", "node_modules", "*.log"]))]
     exclude_patterns: Option<Vec<String>>,
+    /// Exclude patterns in the `--exclude-from` file format (one per line, blank lines
+    /// and `#` comments ignored), applied before `exclude_patterns`
+    #[schema(example = "# build output\ntarget/\n*.log\n")]
+    exclude_file: Option<String>,
+    /// Return the assembled prompt instead of calling Claude
+    #[serde(default)]
+    dry_run: bool,
+    /// Skip files larger than this; at most the server's --limit-file-size-kb
+    #[schema(minimum = 1)]
+    max_file_size_kb: Option<u64>,
+    /// Stop adding files once the prompt reaches this size; at most the server's
+    /// --limit-total-size-mb
+    #[schema(minimum = 1)]
+    max_total_size_mb: Option<u64>,
+    /// Include at most this many files; at most the server's --limit-files
+    #[schema(minimum = 1)]
+    max_files: Option<usize>,
+    /// Include at most this many files from any one directory, manifests and entry
+    /// points first, naming the rest; at most the server's --limit-files
+    #[schema(minimum = 1)]
+    max_files_per_dir: Option<usize>,
+    /// Glob patterns limiting the prompt to matching files
+    #[serde(default)]
+    #[schema(example = json!(["src/**", "Cargo.toml"]))]
+    include_patterns: Vec<String>,
+    /// Filter preset deciding which files are left out by default: `default`, `strict`,
+    /// `docs-only`, `code-only`, or `none`
+    #[schema(example = "strict")]
+    preset: Option<String>,
+    /// How many directories deep to walk, 1 being the project's own files
+    #[schema(minimum = 1)]
+    max_depth: Option<u64>,
+    /// Claude model to use; the server may restrict the choice with --models
+    #[schema(example = "claude-3-5-haiku-latest")]
+    model: Option<String>,
+    /// Prompt template to generate with: a built-in one such as `generic`, `rust`, or
+    /// `node`, or one from the server's --prompts-dir; `readme` by default
+    #[schema(example = "readme-minimal")]
+    template: Option<String>,
+    /// Maximum output tokens; at most the server's --limit-max-tokens
+    #[schema(minimum = 1)]
+    max_tokens: Option<u32>,
+    /// Fail with 402 `cost_limit_exceeded` instead of sending Claude a prompt whose
+    /// estimated input alone costs more than this many USD
+    #[schema(example = 0.5)]
+    max_cost_usd: Option<f64>,
+    /// Generate again even when a cached README matches
+    #[serde(default)]
+    force: bool,
+    /// Open a pull request replacing the repository's README.md, with the server's
+    /// GitHub token; only for repository URLs
+    #[serde(default)]
+    create_pr: bool,
+    /// Also check that every http(s) link in the README answers
+    #[serde(default)]
+    check_links: bool,
+    /// Fail with `invalid_output` instead of returning a README with problems in
+    /// `validation`
+    #[serde(default)]
+    strict_output: bool,
+    /// Add a table of contents of the level 2 and 3 headings
+    #[serde(default)]
+    toc: bool,
+    /// Add crates.io, docs.rs, CI, and license badges under the title, where detected
+    #[serde(default)]
+    badges: bool,
+    /// Footer template appended to the README, with `{version}`, `{commit}`, `{date}`,
+    /// `{model}`, `{source}`, and `{license}` filled in; the project's `.techdocs.toml`
+    /// may also set one
+    #[schema(example = "Generated for version {version} on {date}")]
+    footer: Option<String>,
+    /// Commit to check out after cloning; only set for webhook jobs
+    #[serde(skip)]
+    revision: Option<Arc<PendingRevision>>,
+    /// Where an uploaded archive was extracted; set by /generate/upload in place of a
+    /// resolved `path_or_url`
+    #[serde(skip)]
+    uploaded: Option<PathBuf>,
+}
+
+/// A generation to queue with POST /jobs
+#[derive(Debug, Deserialize, ToSchema)]
+struct SubmitJobRequest {
+    #[serde(flatten)]
+    request: GenerateReadmeRequest,
+    /// URL the job's final state is POSTed to when it finishes, signed with the
+    /// server's TECHDOCS_API_CALLBACK_SECRET
+    #[schema(example = "https://example.com/techdocs-callback")]
+    callback_url: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+/// A generated README
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 struct GenerateReadmeResponse {
+    /// README in Markdown
     readme: String,
+    /// SPDX license detected in the project
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "MIT OR Apache-2.0")]
+    license: Option<String>,
+    /// Usage of the request that generated the README, also when it comes from the cache
+    usage: Usage,
+    /// What `usage` cost in USD at list price; 0 when the model's price is unknown
+    #[serde(default)]
+    #[schema(example = 0.0123)]
+    cost_usd: f64,
+    /// Prompt template the README was generated with
+    #[serde(default)]
+    #[schema(example = "readme")]
+    template: String,
+    /// Model that generated the README
+    #[serde(default)]
+    #[schema(example = "claude-3-5-sonnet-latest")]
+    model: String,
+    /// Whether the README was served from the cache
+    #[serde(default)]
+    cached: bool,
+    /// Why generation stopped; `max_tokens` means the README may be cut short
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "end_turn")]
+    stop_reason: Option<String>,
+    /// The pull request opened with the README, when the request asked for one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pull_request: Option<PullRequestOutcome>,
+    /// Id of the stored copy at /documents/{id}, when the server keeps documents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    document_id: Option<String>,
+    /// Broken markdown and links found in the README; empty when there are none
+    #[serde(default)]
+    validation: Vec<OutputIssue>,
+    /// What GitHub says about the repository, for a repository URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    repository: Option<RemoteMetadata>,
 }
 
-#[derive(Debug, Serialize)]
+/// What a dry run would send to Claude
+#[derive(Debug, Clone, Serialize, ToSchema)]
+struct DryRunResponse {
+    /// Prompt template the system prompt comes from
+    template: String,
+    system_prompt: String,
+    /// File listing sent as the user message
+    content: String,
+    files_included: usize,
+    files_skipped: usize,
+    /// Rough token estimate for the whole prompt
+    estimated_tokens: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<String>,
+}
+
+/// Response of `/files`
+#[derive(Debug, Serialize, ToSchema)]
+struct FileListing {
+    files: Vec<ListedFile>,
+    files_included: usize,
+    files_skipped: usize,
+    /// Tokens the included files add to the prompt
+    estimated_tokens: u64,
+    /// Whether max_total_size_mb cut the list short
+    budget_reached: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ListedFile {
+    /// Path relative to the project root
+    path: String,
+    /// Size in bytes
+    size: u64,
+    /// Code fence language the prompt tags the file with
+    #[schema(example = "rs")]
+    language: String,
+    /// Why the file was left out; omitted for included files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<SkipReason>,
+    estimated_tokens: u64,
+}
+
+impl FileListing {
+    fn new(plan: &PromptPlan) -> Self {
+        let files = plan
+            .files
+            .iter()
+            .map(|file| ListedFile {
+                // Server paths are nobody's business
+                path: file
+                    .path
+                    .strip_prefix(&plan.root)
+                    .unwrap_or(&file.path)
+                    .to_string_lossy()
+                    .into_owned(),
+                size: file.size,
+                language: code_language(&file.path).to_string(),
+                skipped: file.skipped,
+                estimated_tokens: file.estimated_tokens,
+            })
+            .collect();
+        Self {
+            files,
+            files_included: plan.included().count(),
+            files_skipped: plan.skipped().count(),
+            estimated_tokens: plan.included().map(|file| file.estimated_tokens).sum(),
+            budget_reached: plan.budget_reached,
+            license: plan.license.as_ref().map(|license| license.spdx.clone()),
+        }
+    }
+}
+
+/// Result of the generation pipeline: a README, or the prompt for a dry run
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(untagged)]
+enum GenerateOutcome {
+    Readme(Box<GenerateReadmeResponse>),
+    DryRun(DryRunResponse),
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct SummaryRequest {
+    /// Local directory (under TECHDOCS_ALLOWED_PATHS) or GitHub repository URL
+    path_or_url: String,
+    /// Fail with 402 `cost_limit_exceeded` instead of sending Claude a prompt whose
+    /// estimated input alone costs more than this many USD
+    #[schema(example = 0.05)]
+    max_cost_usd: Option<f64>,
+}
+
+/// Stable, machine-readable kinds of failure; messages may change, codes do not.
+/// `clone_failed` comes with 404 when the repository does not exist or is private,
+/// `provider_rate_limited` with 429, `provider_error` and `github_error` with 502, `timeout` with 408
+/// when the whole request took longer than the server allows, and
+/// `insufficient_storage` with 507 when the repository does not fit the server's
+/// clone space, `invalid_archive` with 422 for an upload that cannot be extracted,
+/// `invalid_output` with 502 for a README that failed `strict_output` validation, and
+/// `cancelled` with 503 for work stopped by server shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum ErrorCode {
+    InvalidRequest,
+    InvalidUrl,
+    Unauthorized,
+    PathForbidden,
+    NotFound,
+    CloneFailed,
+    BudgetExceeded,
+    SourceTooLarge,
+    CostLimitExceeded,
+    ResponseTooLarge,
+    PayloadTooLarge,
+    Timeout,
+    InsufficientStorage,
+    Busy,
+    RateLimited,
+    ProviderRateLimited,
+    ProviderError,
+    GithubError,
+    InvalidArchive,
+    InvalidOutput,
+    Cancelled,
+    Internal,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 struct ErrorResponse {
+    code: ErrorCode,
+    /// What went wrong, for people
     error: String,
+    /// Also sent as the `x-request-id` header and logged with the request
+    request_id: String,
+}
+
+tokio::task_local! {
+    /// Id of the request being handled, for its error body
+    static REQUEST_ID: String;
+}
+
+/// A failed request: the status, the code clients branch on, and a message for people
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    code: ErrorCode,
+    message: String,
+    retry_after: Option<Duration>,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    fn invalid(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::InvalidRequest, message)
+    }
+
+    fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, ErrorCode::PathForbidden, message)
+    }
+
+    fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Internal, message)
+    }
+
+    /// A request body the extractors refused, such as malformed or oversized JSON
+    fn rejected_body(status: StatusCode, message: impl Into<String>) -> Self {
+        let code = match status {
+            StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::PayloadTooLarge,
+            _ => ErrorCode::InvalidRequest,
+        };
+        Self::new(status, code, message)
+    }
+
+    fn timeout(limit: Duration) -> Self {
+        Self::new(
+            StatusCode::REQUEST_TIMEOUT,
+            ErrorCode::Timeout,
+            format!("the request took longer than this server's limit of {}s", limit.as_secs()),
+        )
+    }
+
+    /// Ask the client to wait `after` before retrying
+    fn retry_after(mut self, after: Duration) -> Self {
+        self.retry_after = Some(after);
+        self
+    }
+
+    /// The body sent to the client, tagged with the current request's id
+    fn body(&self) -> ErrorResponse {
+        ErrorResponse {
+            code: self.code,
+            error: self.message.clone(),
+            request_id: REQUEST_ID.try_with(Clone::clone).unwrap_or_default(),
+        }
+    }
+}
+
+/// The one place library errors get their status and code
+impl From<TechDocsError> for ApiError {
+    fn from(error: TechDocsError) -> Self {
+        let (status, code) = match &error {
+            TechDocsError::Url(_) => (StatusCode::BAD_REQUEST, ErrorCode::InvalidUrl),
+            TechDocsError::Config(_) | TechDocsError::Ignore(_) => (StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest),
+            // GitHub answers a missing repository, or one we may not see, with an auth challenge
+            TechDocsError::Git(e) if matches!(e.code(), git2::ErrorCode::NotFound | git2::ErrorCode::Auth) => {
+                (StatusCode::NOT_FOUND, ErrorCode::CloneFailed)
+            }
+            TechDocsError::Git(_) => (StatusCode::BAD_GATEWAY, ErrorCode::CloneFailed),
+            TechDocsError::Budget(_) => (StatusCode::PAYLOAD_TOO_LARGE, ErrorCode::BudgetExceeded),
+            TechDocsError::SourceTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, ErrorCode::SourceTooLarge),
+            TechDocsError::CostLimitExceeded { .. } => (StatusCode::PAYMENT_REQUIRED, ErrorCode::CostLimitExceeded),
+            TechDocsError::Quota(_) => (StatusCode::INSUFFICIENT_STORAGE, ErrorCode::InsufficientStorage),
+            TechDocsError::RateLimited(_) => (StatusCode::TOO_MANY_REQUESTS, ErrorCode::ProviderRateLimited),
+            TechDocsError::Claude(_) | TechDocsError::ClaudeClient(_) => {
+                (StatusCode::BAD_GATEWAY, ErrorCode::ProviderError)
+            }
+            TechDocsError::GitHub(_) => (StatusCode::BAD_GATEWAY, ErrorCode::GithubError),
+            TechDocsError::Archive(_) => (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::InvalidArchive),
+            TechDocsError::Validation(_) => (StatusCode::BAD_GATEWAY, ErrorCode::InvalidOutput),
+            TechDocsError::Cancelled => (StatusCode::SERVICE_UNAVAILABLE, ErrorCode::Cancelled),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Internal),
+        };
+        Self::new(status, code, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if self.status.is_server_error() {
+            tracing::warn!("{}", self.message);
+        }
+        let mut response = (self.status, Json(self.body())).into_response();
+        if let Some(after) = self.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, header::HeaderValue::from(after.as_secs().max(1)));
+        }
+        response
+    }
+}
+
+/// `Json` whose rejections, such as a malformed body, use the API's error body
+struct ApiJson<T>(T);
+
+impl<S, T> FromRequest<S> for ApiJson<T>
+where
+    Json<T>: FromRequest<S, Rejection = JsonRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request<Body>, state: &S) -> Result<Self, ApiError> {
+        match Json::<T>::from_request(request, state).await {
+            Ok(Json(value)) => Ok(ApiJson(value)),
+            Err(rejection) => Err(ApiError::rejected_body(rejection.status(), rejection.body_text())),
+        }
+    }
+}
+
+/// Header carrying the request id, both ways
+const REQUEST_ID_HEADER: header::HeaderName = header::HeaderName::from_static("x-request-id");
+
+/// The id of `request`: the client's `x-request-id`, or the one generated for it
+fn request_id_of<B>(request: &Request<B>) -> String {
+    request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Make the request's id available to error bodies, including those of spawned
+/// streaming generations
+async fn request_id(request: Request<Body>, next: Next) -> Response {
+    let id = request_id_of(&request);
+    REQUEST_ID.scope(id, next.run(request)).await
+}
+
+/// Fail requests that run longer than `limit`. Dropping the handler cancels the clone
+/// and provider call it was waiting on and removes its temporary checkout.
+async fn request_timeout(State(limit): State<Duration>, request: Request<Body>, next: Next) -> Response {
+    match tokio::time::timeout(limit, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!("Request timed out after {}s", limit.as_secs());
+            ApiError::timeout(limit).into_response()
+        }
+    }
+}
+
+async fn not_found() -> ApiError {
+    ApiError::new(StatusCode::NOT_FOUND, ErrorCode::NotFound, "no such endpoint")
+}
+
+/// What is running: the build and when the server started
+#[derive(Debug, Serialize, ToSchema)]
+struct VersionInfo {
+    #[schema(example = "0.1.0")]
+    version: &'static str,
+    /// Commit the server was built from, or `unknown` outside a git checkout
+    git_hash: &'static str,
+    #[schema(example = "rustc 1.84.0 (9fc6b4312 2025-01-07)")]
+    rustc: &'static str,
+    #[schema(example = "2025-01-31T12:00:00Z")]
+    started_at: String,
+    uptime_secs: i64,
 }
 
+/// Build and uptime
+///
+/// The crate version, commit, and compiler the server was built with, and when it
+/// started, for telling deployments apart.
+#[utoipa::path(
+    get,
+    path = "/version",
+    responses(
+        (status = 200, description = "Build information", body = VersionInfo),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+    ),
+)]
+async fn version_handler(State(state): State<AppState>) -> Json<VersionInfo> {
+    let started_at = state.settings.started_at;
+    Json(VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("GIT_HASH"),
+        rustc: env!("RUSTC_VERSION"),
+        started_at: started_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        uptime_secs: (Utc::now() - started_at).num_seconds(),
+    })
+}
+
+/// Health check
+#[utoipa::path(get, path = "/health", security(()), responses((status = 200, description = "Service is healthy")))]
 async fn health_check() -> StatusCode {
     StatusCode::OK
 }
 
+/// Longest a provider check may take before it counts as failed
+const PROVIDER_PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Checks the provider in the background every `interval`, so /ready answers from the
+/// latest result without calling the provider itself
+struct ProviderProbe {
+    latest: RwLock<Option<CheckResult>>,
+}
+
+impl ProviderProbe {
+    /// Start checking with a token count request for `model`, which generates nothing
+    fn spawn(interval: Duration, model: Option<String>) -> Arc<Self> {
+        let probe = Arc::new(Self { latest: RwLock::new(None) });
+        let background = Arc::clone(&probe);
+        tokio::spawn(async move {
+            loop {
+                let check = tokio::time::timeout(PROVIDER_PROBE_TIMEOUT, check_api_key_online(model.as_deref()))
+                    .await
+                    .unwrap_or_else(|_| CheckResult {
+                        name: "provider",
+                        status: CheckStatus::Fail,
+                        detail: format!("no answer within {}s", PROVIDER_PROBE_TIMEOUT.as_secs()),
+                        required: true,
+                    });
+                if check.status == CheckStatus::Fail {
+                    tracing::warn!("Provider check failed: {}", check.detail);
+                }
+                *background.latest.write().unwrap() = Some(check);
+                tokio::time::sleep(interval).await;
+            }
+        });
+        probe
+    }
+
+    /// The latest result, failed until the first check finishes
+    fn check(&self) -> CheckResult {
+        let latest = self.latest.read().unwrap().clone();
+        let (status, detail) = match latest {
+            Some(check) => (check.status, check.detail),
+            None => (CheckStatus::Fail, "not checked yet".to_string()),
+        };
+        CheckResult { name: "provider", status, detail, required: true }
+    }
+}
+
+/// Whether the workspace can take another clone: writable, with free disk space and
+/// room left in its quota
+fn check_workspace(workspace: &Workspace, min_free_bytes: u64) -> CheckResult {
+    let check = check_disk_space("workspace", workspace.root(), min_free_bytes);
+    if check.status == CheckStatus::Fail || workspace.used_bytes() < workspace.quota_bytes() {
+        return check;
+    }
+    CheckResult {
+        status: CheckStatus::Fail,
+        detail: format!("the {} MB clone quota is in use", workspace.quota_bytes() / (1024 * 1024)),
+        ..check
+    }
+}
+
+/// One component /ready checked
+#[derive(Debug, Serialize, ToSchema)]
+struct ComponentCheck {
+    #[schema(example = "provider")]
+    component: &'static str,
+    healthy: bool,
+    detail: String,
+}
+
+/// Whether the server can serve generations, and what is wrong when it cannot
+#[derive(Debug, Serialize, ToSchema)]
+struct Readiness {
+    ready: bool,
+    /// Components that failed their check
+    #[schema(example = json!(["provider"]))]
+    failing: Vec<&'static str>,
+    checks: Vec<ComponentCheck>,
+}
+
+/// Readiness check
+///
+/// Unlike /health, checks what generation needs: the provider accepts the server's key,
+/// as of the latest background check; the workspace is writable, with free disk space
+/// and room in its quota; and the prompt templates can be read.
+#[utoipa::path(
+    get,
+    path = "/ready",
+    responses(
+        (status = 200, description = "Ready to serve", body = Readiness),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 503, description = "A component is failing", body = Readiness),
+    ),
+)]
+async fn ready_handler(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let settings = &state.settings;
+    let checks = [
+        settings.provider_probe.check(),
+        check_workspace(&settings.workspace, settings.min_free_bytes),
+        settings.templates.check(),
+    ];
+    let failing: Vec<_> = checks.iter().filter(|check| check.is_blocking()).map(|check| check.name).collect();
+    let status = if failing.is_empty() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    let checks = checks
+        .into_iter()
+        .map(|check| ComponentCheck {
+            component: check.name,
+            healthy: !check.is_blocking(),
+            detail: check.detail,
+        })
+        .collect();
+    (status, Json(Readiness { ready: failing.is_empty(), failing, checks }))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct PromptsReloaded {
+    /// Names of the templates now available
+    #[schema(example = json!(["readme", "readme-minimal"]))]
+    templates: Vec<String>,
+}
+
+/// Reload prompt templates
+///
+/// Reads the server's --prompts-dir again, so templates can be added or edited without
+/// a restart. When the directory cannot be read, the current templates stay in use.
+#[utoipa::path(
+    post,
+    path = "/admin/reload-prompts",
+    responses(
+        (status = 200, description = "Templates reloaded", body = PromptsReloaded),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 500, description = "The prompt directory could not be read", body = ErrorResponse),
+    ),
+)]
+async fn reload_prompts_handler(State(state): State<AppState>) -> Result<Json<PromptsReloaded>, ApiError> {
+    let templates = state.settings.templates.reload().map_err(|e| ApiError::internal(e.to_string()))?;
+    tracing::info!("Reloaded prompt templates: {}", templates.join(", "));
+    Ok(Json(PromptsReloaded { templates }))
+}
+
+/// Filters of /documents
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DocumentsQuery {
+    /// Only documents generated from this GitHub repository, as `owner/name`
+    #[param(example = "username/repo")]
+    repo: Option<String>,
+    /// Most documents to return, newest first
+    limit: Option<usize>,
+}
+
+/// Stored documents, newest first
+#[derive(Debug, Serialize, ToSchema)]
+struct DocumentList {
+    documents: Vec<DocumentInfo>,
+}
+
+/// List stored documents
+///
+/// The READMEs this server generated and kept, newest first, without their content.
+#[utoipa::path(
+    get,
+    path = "/documents",
+    params(DocumentsQuery),
+    responses(
+        (status = 200, description = "Stored documents", body = DocumentList),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 404, description = "The server does not keep documents", body = ErrorResponse),
+    ),
+)]
+async fn list_documents_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DocumentsQuery>,
+) -> Result<Json<DocumentList>, ApiError> {
+    let documents = document_store(&state.settings)?.list().await?;
+    let documents = documents
+        .into_iter()
+        .filter(|info| match &query.repo {
+            Some(repo) => info.repository.as_ref().is_some_and(|stored| stored.eq_ignore_ascii_case(repo)),
+            None => true,
+        })
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+    Ok(Json(DocumentList { documents }))
+}
+
+/// Fetch a stored document
+///
+/// The markdown as `text/markdown`, or with `Accept: application/json` the markdown
+/// in `content` next to where it came from.
+#[utoipa::path(
+    get,
+    path = "/documents/{id}",
+    params(("id" = String, Path, description = "Document id from /documents or a /generate response")),
+    responses(
+        (status = 200, description = "The document", content(
+            (String = "text/markdown"),
+            (Document = "application/json"),
+        )),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 404, description = "No such document, or the server does not keep documents", body = ErrorResponse),
+    ),
+)]
+async fn get_document_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let document = document_store(&state.settings)?
+        .get(&id)
+        .await?
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, ErrorCode::NotFound, format!("no document {}", id)))?;
+    let wants_json = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"));
+    if wants_json {
+        return Ok(Json(document).into_response());
+    }
+    Ok(([(header::CONTENT_TYPE, "text/markdown; charset=utf-8")], document.content).into_response())
+}
+
+fn document_store(settings: &Settings) -> Result<&dyn DocumentStore, ApiError> {
+    settings.documents.as_deref().ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            ErrorCode::NotFound,
+            "this server does not keep documents; start it with --documents-dir",
+        )
+    })
+}
+
+/// Generation load in the Prometheus text format
+///
+/// Reports the configured generation slots, how many generations are running, how many
+/// requests and jobs wait for a slot, and how many were turned away with 429, followed
+/// by the pipeline's file, prompt, clone, and provider counters.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics", body = String, content_type = "text/plain"),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+    ),
+)]
+async fn metrics_handler(State(state): State<AppState>) -> Response {
+    let slots = &state.settings.slots;
+    let workspace = &state.settings.workspace;
+    let metrics = [
+        ("techdocs_generation_slots", "gauge", "Generations allowed to run at once", slots.limit as u64),
+        ("techdocs_generations_in_flight", "gauge", "Generations running now", slots.in_flight() as u64),
+        (
+            "techdocs_generations_waiting",
+            "gauge",
+            "Requests and jobs waiting for a generation slot",
+            slots.waiting.load(Ordering::Relaxed) as u64,
+        ),
+        (
+            "techdocs_generations_rejected_total",
+            "counter",
+            "Requests turned away with 429 because no slot freed up in time",
+            slots.rejected.load(Ordering::Relaxed),
+        ),
+        (
+            "techdocs_workspace_used_bytes",
+            "gauge",
+            "Disk space taken by repositories cloned for requests and jobs",
+            workspace.used_bytes(),
+        ),
+        (
+            "techdocs_workspace_quota_bytes",
+            "gauge",
+            "Disk space clones may take in total",
+            workspace.quota_bytes(),
+        ),
+    ];
+    let mut body = String::new();
+    for (name, kind, help, value) in metrics {
+        body.push_str(&format!("# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"));
+    }
+    if let Some(limiter) = &state.settings.limiter {
+        let limits = [
+            ("techdocs_rate_limit_per_minute", "Requests a client may make a minute", limiter.per_minute),
+            ("techdocs_rate_limit_burst", "Requests a client may make at once", limiter.burst),
+        ];
+        for (name, help, value) in limits {
+            body.push_str(&format!("# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n"));
+        }
+        let name = "techdocs_rate_limited_total";
+        body.push_str(&format!(
+            "# HELP {name} Requests turned away with 429 by the per-client rate limit\n# TYPE {name} counter\n"
+        ));
+        let rejected = [("token", &limiter.rejected_tokens), ("ip", &limiter.rejected_ips)];
+        for (client, count) in rejected {
+            body.push_str(&format!("{name}{{client=\"{client}\"}} {}\n", count.load(Ordering::Relaxed)));
+        }
+    }
+    body.push_str(&state.settings.pipeline_metrics.render_prometheus());
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+/// Bearer tokens accepted by the API; empty when authentication is disabled
+#[derive(Clone, Default)]
+struct ApiTokens(Arc<Vec<String>>);
+
+impl ApiTokens {
+    /// Whether `candidate` matches one of the tokens. Every token is compared in constant
+    /// time, so the response time does not reveal how much of a token was right.
+    fn accepts(&self, candidate: &str) -> bool {
+        self.0
+            .iter()
+            .fold(0u8, |matched, token| matched | token.as_bytes().ct_eq(candidate.as_bytes()).unwrap_u8())
+            == 1
+    }
+}
+
+/// The token of a request's `Authorization: Bearer <token>` header, not yet checked
+fn bearer_token(request: &Request<Body>) -> Option<String> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+}
+
+/// Reject requests without a valid `Authorization: Bearer <token>` header. Only the
+/// outcome is recorded on the request span, never the token.
+async fn require_token(
+    State(tokens): State<ApiTokens>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let token = bearer_token(&request).filter(|token| tokens.accepts(token));
+    tracing::Span::current().record("authorized", token.is_some());
+
+    let Some(token) = token else {
+        let mut response =
+            ApiError::new(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, "missing or invalid API token").into_response();
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+        return response;
+    };
+    request.extensions_mut().insert(ValidToken(token));
+    next.run(request).await
+}
+
+/// The bearer token of a request, once [`require_token`] has checked it against the
+/// configured tokens
+#[derive(Clone)]
+struct ValidToken(String);
+
+/// Who a request is counted against: its checked bearer token, or without one its
+/// address
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ClientKey {
+    Token(String),
+    Ip(IpAddr),
+}
+
+/// Clients seen this long ago or more are forgotten once the table reaches this size
+const CLIENT_TABLE_PRUNE_AT: usize = 4096;
+
+/// Allows each client `per_minute` requests a minute on average and up to `burst` at
+/// once, with the generic cell rate algorithm: a client is only tracked by the time its
+/// next request is due, which moves `interval` ahead with every request it makes.
+struct ClientLimiter {
+    per_minute: u32,
+    burst: u32,
+    interval: Duration,
+    /// How far ahead of now a client's due time may run before it is turned away
+    tolerance: Duration,
+    /// Take the client address from the last `X-Forwarded-For` entry, which the proxy
+    /// in front of the server appended
+    trust_forwarded_for: bool,
+    due: std::sync::Mutex<HashMap<ClientKey, Instant>>,
+    rejected_tokens: AtomicU64,
+    rejected_ips: AtomicU64,
+}
+
+impl ClientLimiter {
+    fn new(per_minute: u32, burst: u32, trust_forwarded_for: bool) -> Self {
+        let interval = Duration::from_secs(60) / per_minute.max(1);
+        Self {
+            per_minute,
+            burst: burst.max(1),
+            interval,
+            tolerance: interval * (burst.max(1) - 1),
+            trust_forwarded_for,
+            due: std::sync::Mutex::new(HashMap::new()),
+            rejected_tokens: AtomicU64::new(0),
+            rejected_ips: AtomicU64::new(0),
+        }
+    }
+
+    /// The client `request` counts against, if it can be told apart at all. A bearer
+    /// token counts only once checked, since a client could otherwise send a new made-up
+    /// token with every request; when the server takes no tokens, every client is
+    /// counted by its address.
+    fn key(&self, request: &Request<Body>) -> Option<ClientKey> {
+        if let Some(ValidToken(token)) = request.extensions().get::<ValidToken>() {
+            return Some(ClientKey::Token(token.clone()));
+        }
+        let headers = request.headers();
+        let forwarded = self
+            .trust_forwarded_for
+            .then(|| headers.get("x-forwarded-for"))
+            .flatten()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|address| address.trim().parse().ok());
+        let connected = || request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip());
+        forwarded.or_else(connected).map(ClientKey::Ip)
+    }
+
+    /// Count a request by `key`, or say how long the client must wait
+    fn check(&self, key: ClientKey) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    /// [`ClientLimiter::check`] for a request made at `now`
+    fn check_at(&self, key: ClientKey, now: Instant) -> Result<(), Duration> {
+        let mut due = self.due.lock().unwrap();
+        if due.len() >= CLIENT_TABLE_PRUNE_AT {
+            // A due time in the past means the client has its full burst again
+            due.retain(|_, due| *due > now);
+        }
+        let next = due.get(&key).map_or(now, |due| (*due).max(now));
+        let ahead = next - now;
+        if ahead > self.tolerance {
+            match key {
+                ClientKey::Token(_) => self.rejected_tokens.fetch_add(1, Ordering::Relaxed),
+                ClientKey::Ip(_) => self.rejected_ips.fetch_add(1, Ordering::Relaxed),
+            };
+            return Err(ahead - self.tolerance);
+        }
+        due.insert(key, next + self.interval);
+        Ok(())
+    }
+}
+
+/// Turn away clients over their rate limit with 429. /metrics is never limited, so
+/// scraping keeps working while a client is throttled.
+async fn limit_clients(State(limiter): State<Arc<ClientLimiter>>, request: Request<Body>, next: Next) -> Response {
+    if request.uri().path() == "/metrics" {
+        return next.run(request).await;
+    }
+    match over_limit(&limiter, &request) {
+        Some(response) => response,
+        None => next.run(request).await,
+    }
+}
+
+/// Count requests without a valid token against their address before
+/// [`require_token`] turns them away, so guessing tokens is held to the same rate as
+/// any other client. Requests with a valid token pass on to [`limit_clients`], which
+/// counts them by their token.
+async fn limit_unauthenticated(
+    State((limiter, tokens)): State<(Arc<ClientLimiter>, ApiTokens)>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    if bearer_token(&request).is_some_and(|token| tokens.accepts(&token)) {
+        return next.run(request).await;
+    }
+    match over_limit(&limiter, &request) {
+        Some(response) => response,
+        None => next.run(request).await,
+    }
+}
+
+/// Count `request` against its client, and the 429 to send when the client is over
+/// its limit
+fn over_limit(limiter: &ClientLimiter, request: &Request<Body>) -> Option<Response> {
+    let wait = limiter.check(limiter.key(request)?).err()?;
+    let response = ApiError::new(
+        StatusCode::TOO_MANY_REQUESTS,
+        ErrorCode::RateLimited,
+        format!("rate limit of {} requests a minute exceeded; try again later", limiter.per_minute),
+    )
+    .retry_after(wait)
+    .into_response();
+    Some(response)
+}
+
+/// Generate a README for a local directory or GitHub repository
+///
+/// With `dry_run`, returns the assembled prompt instead of calling Claude.
+#[utoipa::path(
+    post,
+    path = "/generate",
+    request_body = GenerateReadmeRequest,
+    responses(
+        (status = 200, description = "The README, or the prompt for a dry run", body = GenerateOutcome),
+        (status = 400, description = "Malformed body, invalid patterns, or unsupported URL", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 403, description = "Local path outside the server's TECHDOCS_ALLOWED_PATHS", body = ErrorResponse),
+        (status = 408, description = "The request took longer than the server's --request-timeout-secs", body = ErrorResponse),
+        (status = 413, description = "The body exceeds the server's --max-body-kb", body = ErrorResponse),
+        (status = 422, description = "A limit or model override is outside what the server allows", body = ErrorResponse),
+        (
+            status = 429,
+            description = "The client is over its rate limit (`rate_limited`), every generation slot stayed busy (`busy`), or Claude rate limited the server (`provider_rate_limited`)",
+            body = ErrorResponse,
+            headers(("Retry-After" = u64, description = "Seconds to wait, for `rate_limited` and `busy`")),
+        ),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 502, description = "Cloning the repository, the request to Claude, or opening the pull request failed", body = ErrorResponse),
+        (status = 507, description = "The repository does not fit the server's clone space", body = ErrorResponse),
+    ),
+)]
 async fn generate_readme_handler(
     State(state): State<AppState>,
-    Json(request): Json<GenerateReadmeRequest>,
-) -> Result<Json<GenerateReadmeResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let exclude_patterns = request.exclude_patterns.unwrap_or_default();
+    ApiJson(request): ApiJson<GenerateReadmeRequest>,
+) -> Result<Json<GenerateOutcome>, ApiError> {
+    let _slot = acquire_slot(&state.settings, &request).await?;
+    generate(&state.settings, request, &Observer::SILENT, &state.settings.shutdown).await.map(Json)
+}
+
+/// Check `request` and wait for a generation slot. Dry runs only collect files, like
+/// /prompt, so they run without one.
+async fn acquire_slot(
+    settings: &Settings,
+    request: &GenerateReadmeRequest,
+) -> Result<Option<OwnedSemaphorePermit>, ApiError> {
+    // A request that would fail anyway should not wait for a slot
+    check_request(settings, request)?;
+    if request.dry_run {
+        return Ok(None);
+    }
+    settings.slots.acquire().await.map(Some)
+}
+
+/// Name of the uploaded archive inside its workspace directory
+const UPLOAD_FILE: &str = "upload.archive";
+/// Directory inside the workspace directory the archive is extracted into
+const UPLOAD_SOURCE: &str = "source";
+
+/// Query of /generate/upload
+#[derive(Debug, Deserialize)]
+struct UploadQuery {
+    /// The /generate options as JSON, without `path_or_url`, for raw uploads
+    request: Option<String>,
+}
+
+/// Generate a README for an uploaded source archive
+///
+/// Send a `.tar.gz` or `.zip` as the body, with `Content-Type: application/gzip` or
+/// `application/zip` and the /generate options as JSON in the `request` query
+/// parameter, or as `multipart/form-data` with an `archive` file part and an optional
+/// `request` part. The archive is extracted into the server's workspace and deleted
+/// with it once the README is returned. Entries with absolute paths or `..` reject
+/// the archive.
+#[utoipa::path(
+    post,
+    path = "/generate/upload",
+    params(("request" = Option<String>, Query, description = "The /generate options as JSON, without `path_or_url`")),
+    request_body(content = Vec<u8>, content_type = "application/gzip", description = "A gzip-compressed tarball or zip archive of the project"),
+    responses(
+        (status = 200, description = "The README, or the prompt for a dry run", body = GenerateOutcome),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 408, description = "The request took longer than the server's --request-timeout-secs", body = ErrorResponse),
+        (status = 413, description = "The archive exceeds the server's --max-upload-mb, or expands past its --max-clone-mb", body = ErrorResponse),
+        (status = 415, description = "The body is not a gzip-compressed tarball, zip archive, or multipart form", body = ErrorResponse),
+        (status = 422, description = "The archive cannot be extracted (`invalid_archive`), or an option is invalid", body = ErrorResponse),
+        (
+            status = 429,
+            description = "The client is over its rate limit (`rate_limited`), every generation slot stayed busy (`busy`), or Claude rate limited the server (`provider_rate_limited`)",
+            body = ErrorResponse,
+            headers(("Retry-After" = u64, description = "Seconds to wait, for `rate_limited` and `busy`")),
+        ),
+        (status = 502, description = "The request to Claude failed", body = ErrorResponse),
+        (status = 507, description = "The server's workspace has no room for the archive", body = ErrorResponse),
+    ),
+)]
+async fn upload_handler(
+    State(state): State<AppState>,
+    Query(query): Query<UploadQuery>,
+    request: Request<Body>,
+) -> Result<Json<GenerateOutcome>, ApiError> {
+    let settings = &state.settings;
+    // Deleted with everything in it when the request ends
+    let dir = settings.workspace.create_dir()?;
+    let archive = dir.path().join(UPLOAD_FILE);
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let (options, format, digest) = if content_type.starts_with("multipart/form-data") {
+        let multipart = Multipart::from_request(request, &())
+            .await
+            .map_err(|rejection| ApiError::rejected_body(rejection.status(), rejection.body_text()))?;
+        receive_multipart(settings, multipart, &archive).await?
+    } else {
+        let format = ArchiveFormat::from_content_type(&content_type).ok_or_else(unsupported_upload)?;
+        // Options arrive before the body, so bad ones are refused before it is read
+        check_request(settings, &upload_request(query.request.as_deref(), dir.path())?)?;
+        let chunks = request.into_body().into_data_stream();
+        let digest = receive_upload(chunks, &archive, settings.max_upload_bytes, |e| {
+            ApiError::rejected_body(StatusCode::BAD_REQUEST, format!("reading the upload failed: {}", e))
+        })
+        .await?;
+        (query.request, format, digest)
+    };
 
-    // Resolve path (local or GitHub URL)
-    let (path, _temp_dir) = resolve_path(&request.path_or_url)
+    let mut request = upload_request(options.as_deref(), dir.path())?;
+    request.path_or_url = format!("upload:{}", digest);
+    check_request(settings, &request)?;
+
+    let source = dir.path().join(UPLOAD_SOURCE);
+    let max_bytes = settings.workspace.max_clone_bytes();
+    let extracted = {
+        let archive = archive.clone();
+        // The library error is not Send, so it is converted on the blocking thread
+        tokio::task::spawn_blocking(move || {
+            std::fs::create_dir(&source)
+                .map_err(TechDocsError::from)
+                .and_then(|()| extract(&archive, format, &source, max_bytes))
+                .map_err(ApiError::from)
+        })
         .await
-        .map_err(|e| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            )
-        })?;
+        .map_err(|e| ApiError::internal(e.to_string()))?
+    };
+    let root = extracted?;
+    tokio::fs::remove_file(&archive).await.map_err(TechDocsError::from)?;
+    dir.measure()?;
+    tracing::info!("Extracted {:?} upload {} into {}", format, digest, root.display());
+
+    request.uploaded = Some(root);
+    let _slot = acquire_slot(settings, &request).await?;
+    generate(settings, request, &Observer::SILENT, &settings.shutdown).await.map(Json)
+}
+
+/// The /generate request an upload makes, from its `request` JSON. `uploaded` stands in
+/// for the extracted archive until it is known.
+fn upload_request(options: Option<&str>, uploaded: &Path) -> Result<GenerateReadmeRequest, ApiError> {
+    let mut value = match options {
+        Some(options) => serde_json::from_str(options)
+            .map_err(|e| ApiError::rejected_body(StatusCode::BAD_REQUEST, format!("request: {}", e)))?,
+        None => serde_json::json!({}),
+    };
+    let Some(fields) = value.as_object_mut() else {
+        return Err(ApiError::invalid("request must be a JSON object"));
+    };
+    if fields.contains_key("path_or_url") {
+        return Err(ApiError::invalid("path_or_url does not apply to uploads; the archive is the project"));
+    }
+    fields.insert("path_or_url".into(), "upload".into());
+    let mut request: GenerateReadmeRequest =
+        serde_json::from_value(value).map_err(|e| ApiError::invalid(format!("request: {}", e)))?;
+    request.uploaded = Some(uploaded.to_path_buf());
+    Ok(request)
+}
+
+/// Read a multipart upload: the `archive` file part, written to `archive`, and the
+/// optional `request` part. Returns the options, the archive's format, and its digest.
+async fn receive_multipart(
+    settings: &Settings,
+    mut multipart: Multipart,
+    archive: &Path,
+) -> Result<(Option<String>, ArchiveFormat, String), ApiError> {
+    let mut options = None;
+    let mut received = None;
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        match field.name() {
+            Some("request") => options = Some(field.text().await.map_err(multipart_error)?),
+            Some("archive") => {
+                let format = field
+                    .content_type()
+                    .and_then(ArchiveFormat::from_content_type)
+                    .or_else(|| field.file_name().and_then(ArchiveFormat::from_file_name))
+                    .ok_or_else(unsupported_upload)?;
+                let digest = receive_upload(field, archive, settings.max_upload_bytes, multipart_error).await?;
+                received = Some((format, digest));
+            }
+            name => {
+                return Err(ApiError::invalid(format!(
+                    "unexpected part {}; send `archive` and optionally `request`",
+                    name.unwrap_or("without a name")
+                )));
+            }
+        }
+    }
+    let (format, digest) = received.ok_or_else(|| ApiError::invalid("the form has no `archive` part"))?;
+    Ok((options, format, digest))
+}
+
+/// Stream `chunks` to the file `archive` without holding them in memory, refusing more
+/// than `max_bytes`, and return the SHA-256 of what was written
+async fn receive_upload<E>(
+    chunks: impl Stream<Item = Result<Bytes, E>>,
+    archive: &Path,
+    max_bytes: u64,
+    read_error: impl Fn(E) -> ApiError,
+) -> Result<String, ApiError> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut chunks = std::pin::pin!(chunks);
+    let mut file = tokio::fs::File::create(archive).await.map_err(TechDocsError::from)?;
+    let mut hasher = Sha256::new();
+    let mut received = 0u64;
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(&read_error)?;
+        received += chunk.len() as u64;
+        if received > max_bytes {
+            return Err(ApiError::rejected_body(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("the archive is larger than this server's limit of {} MB", max_bytes / (1024 * 1024)),
+            ));
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await.map_err(TechDocsError::from)?;
+    }
+    file.flush().await.map_err(TechDocsError::from)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
 
-    // Generate file list with prompt
-    let mut file_list = Vec::new();
-    list_files_prompt(
-        &path,
-        &exclude_patterns,
-        100,  // max file size in KB
-        10,   // max total size in MB
-        &mut file_list,
+fn multipart_error(error: MultipartError) -> ApiError {
+    match error.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => ApiError::rejected_body(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "the upload is larger than this server's --max-upload-mb",
+        ),
+        status => ApiError::rejected_body(status, error.body_text()),
+    }
+}
+
+fn unsupported_upload() -> ApiError {
+    ApiError::new(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        ErrorCode::InvalidRequest,
+        "send a .tar.gz as application/gzip, a .zip as application/zip, or either as multipart/form-data",
     )
-    .map_err(|e| {
+}
+
+/// Assemble the prompt without generating
+///
+/// Returns the file listing /generate would send to Claude as plain text, streamed as the
+/// files are read. Needs no API key. Responses are gzip-compressed when the client
+/// accepts it.
+#[utoipa::path(
+    post,
+    path = "/prompt",
+    request_body = GenerateReadmeRequest,
+    responses(
+        (status = 200, description = "The assembled prompt", body = String, content_type = "text/plain"),
+        (status = 400, description = "Malformed body, invalid patterns, or unsupported URL", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 403, description = "Local path outside the server's TECHDOCS_ALLOWED_PATHS", body = ErrorResponse),
+        (status = 413, description = "The response would exceed the server's --max-response-mb", body = ErrorResponse),
+        (status = 422, description = "A limit or model override is outside what the server allows", body = ErrorResponse),
+    ),
+)]
+async fn prompt_handler(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<GenerateReadmeRequest>,
+) -> Result<Response, ApiError> {
+    let collected = collect(&state.settings, request, &Observer::SILENT, &state.settings.shutdown).await?;
+    let body = stream_prompt(&state.settings, collected)?;
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response())
+}
+
+/// List the files a generation would consider
+///
+/// Every file considered, with its size, language, and why it was skipped, if it was.
+#[utoipa::path(
+    post,
+    path = "/files",
+    request_body = GenerateReadmeRequest,
+    responses(
+        (status = 200, description = "File manifest", body = FileListing),
+        (status = 400, description = "Malformed body, invalid patterns, or unsupported URL", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 403, description = "Local path outside the server's TECHDOCS_ALLOWED_PATHS", body = ErrorResponse),
+        (status = 413, description = "The response would exceed the server's --max-response-mb", body = ErrorResponse),
+        (status = 422, description = "A limit or model override is outside what the server allows", body = ErrorResponse),
+    ),
+)]
+async fn files_handler(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<GenerateReadmeRequest>,
+) -> Result<Response, ApiError> {
+    let collected = collect(&state.settings, request, &Observer::SILENT, &state.settings.shutdown).await?;
+    let body = serde_json::to_vec(&FileListing::new(&collected.plan)).map_err(|e| ApiError::internal(e.to_string()))?;
+    state.settings.check_response_size(body.len())?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], body).into_response())
+}
+
+/// Query of /manifest
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ManifestQuery {
+    /// Include the text of each included file (default: true)
+    content: Option<bool>,
+}
+
+/// Describe a project's files as a versioned manifest
+///
+/// Every file considered, with its size, language, why it was skipped, and, unless
+/// `content=false`, the text of each included file. `schema_version` changes only when
+/// a field is renamed, removed, or changes meaning.
+#[utoipa::path(
+    post,
+    path = "/manifest",
+    params(ManifestQuery),
+    request_body = GenerateReadmeRequest,
+    responses(
+        (status = 200, description = "File manifest", body = FileManifest),
+        (status = 400, description = "Malformed body, invalid patterns, or unsupported URL", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 403, description = "Local path outside the server's TECHDOCS_ALLOWED_PATHS", body = ErrorResponse),
+        (status = 413, description = "The response would exceed the server's --max-response-mb", body = ErrorResponse),
+        (status = 422, description = "A limit or model override is outside what the server allows", body = ErrorResponse),
+    ),
+)]
+async fn manifest_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ManifestQuery>,
+    ApiJson(request): ApiJson<GenerateReadmeRequest>,
+) -> Result<Response, ApiError> {
+    let source = request.path_or_url.clone();
+    let collected = collect(&state.settings, request, &Observer::SILENT, &state.settings.shutdown).await?;
+    let manifest = FileManifest::from_plan(&source, &collected.plan, query.content.unwrap_or(true))?;
+    let body = serde_json::to_vec(&manifest).map_err(|e| ApiError::internal(e.to_string()))?;
+    state.settings.check_response_size(body.len())?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], body).into_response())
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct CacheCleared {
+    entries_removed: usize,
+}
+
+/// Clear cached READMEs
+///
+/// Removes every cached README, in memory and in the server's --cache-dir.
+#[utoipa::path(
+    delete,
+    path = "/cache",
+    responses(
+        (status = 200, description = "Cache cleared", body = CacheCleared),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+    ),
+)]
+async fn clear_cache_handler(State(state): State<AppState>) -> Json<CacheCleared> {
+    let entries_removed = state.settings.cache.as_ref().map_or(0, |cache| cache.clear());
+    tracing::info!("Cleared {} cached READMEs", entries_removed);
+    Json(CacheCleared { entries_removed })
+}
+
+/// Generate a README, streaming progress and text as server-sent events
+///
+/// Events: `cloning`, `collecting` (with `files_included` and `files_skipped`), and
+/// `generating` as each stage starts; `delta` with the next piece of README `text`;
+/// then `done` with the object /generate returns, or `error` with an ErrorResponse.
+/// Comment lines keep the connection alive. Disconnecting cancels the generation.
+#[utoipa::path(
+    post,
+    path = "/generate/stream",
+    request_body = GenerateReadmeRequest,
+    responses(
+        (status = 200, description = "Event stream", body = String, content_type = "text/event-stream"),
+        (status = 400, description = "Malformed body, invalid patterns, or unsupported URL", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 403, description = "Local path outside the server's TECHDOCS_ALLOWED_PATHS", body = ErrorResponse),
+        (status = 422, description = "A limit or model override is outside what the server allows", body = ErrorResponse),
         (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })?;
+            status = 429,
+            description = "The client is over its rate limit (`rate_limited`), every generation slot stayed busy (`busy`), or Claude rate limited the server (`provider_rate_limited`)",
+            body = ErrorResponse,
+            headers(("Retry-After" = u64, description = "Seconds to wait, for `rate_limited` and `busy`")),
+        ),
+    ),
+)]
+async fn generate_stream_handler(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<GenerateReadmeRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    // Waiting happens before the stream starts, so a busy server still answers 429
+    let slot = acquire_slot(&state.settings, &request).await?;
+    let (sender, receiver) = unbounded_channel::<Event>();
+    let id = REQUEST_ID.try_with(Clone::clone).unwrap_or_default();
+    let cancel = state.settings.shutdown.child_token();
+    let on_drop = cancel.clone();
+    let task = tokio::spawn(REQUEST_ID.scope(id, async move {
+        let _slot = slot;
+        let send = |name: &str, data: serde_json::Value| {
+            let _ = sender.send(Event::default().event(name).data(data.to_string()));
+        };
+        let on_status = |status: JobStatus| {
+            // Collecting is announced once the file counts are known
+            if status != JobStatus::Collecting {
+                send(status.as_str(), serde_json::json!({}));
+            }
+        };
+        let on_progress = |event: &ProgressEvent| {
+            if let ProgressEvent::WalkFinished { files_included, files_skipped } = event {
+                send(
+                    "collecting",
+                    serde_json::json!({ "files_included": files_included, "files_skipped": files_skipped }),
+                );
+            }
+        };
+        let on_text = |text: &str| send("delta", serde_json::json!({ "text": text }));
+        let observer = Observer {
+            status: &on_status,
+            progress: &on_progress,
+            text: Some(&on_text),
+        };
+        let limit = state.settings.request_timeout;
+        let outcome = tokio::time::timeout(limit, generate(&state.settings, request, &observer, &cancel))
+            .await
+            .unwrap_or_else(|_| Err(ApiError::timeout(limit)));
+        match outcome {
+            Ok(outcome) => send("done", serde_json::to_value(outcome).unwrap_or_default()),
+            Err(error) => send("error", serde_json::to_value(error.body()).unwrap_or_default()),
+        }
+    }));
 
-    // Generate README using Claude
-    let readme = generate_readme(&state.readme_prompt, &String::from_utf8_lossy(&file_list))
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            )
-        })?;
+    let events = futures_util::stream::unfold((receiver, CancelOnDrop(task, on_drop)), |(mut receiver, task)| async move {
+        let event = receiver.recv().await?;
+        Some((Ok(event), (receiver, task)))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Aborts a streaming generation when its response is dropped, which is how a client
+/// disconnect shows up. Aborting drops the provider request, so no more tokens are spent;
+/// cancelling the token also stops a file walk, which runs without yielding to the abort.
+struct CancelOnDrop(JoinHandle<()>, CancellationToken);
 
-    Ok(Json(GenerateReadmeResponse { readme }))
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if !self.0.is_finished() {
+            tracing::info!("Client disconnected; cancelling generation");
+            self.1.cancel();
+            self.0.abort();
+        }
+    }
 }
 
-#[tokio::main]
-async fn main() -> TechDocsResult<()> {
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "techdocs=debug,tower_http=debug".into()),
+/// Queue a README generation
+///
+/// Returns a job id right away, for repositories that take longer than a client or
+/// proxy is willing to wait. Poll GET /jobs/{id} for the result, or pass a
+/// `callback_url` to have it POSTed when the job finishes.
+#[utoipa::path(
+    post,
+    path = "/jobs",
+    request_body = SubmitJobRequest,
+    responses(
+        (status = 202, description = "Job queued", body = JobSnapshot<GenerateOutcome>),
+        (status = 400, description = "Malformed body, invalid patterns, or unsupported or disallowed URL", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 403, description = "Local path outside the server's TECHDOCS_ALLOWED_PATHS", body = ErrorResponse),
+        (status = 422, description = "A limit or model override is outside what the server allows, or a callback without a server callback secret", body = ErrorResponse),
+        (status = 429, description = "The job queue is full (`busy`), or the client is over its rate limit (`rate_limited`)", body = ErrorResponse),
+    ),
+)]
+async fn submit_job_handler(
+    State(state): State<AppState>,
+    ApiJson(SubmitJobRequest { request, callback_url }): ApiJson<SubmitJobRequest>,
+) -> Result<(StatusCode, Json<JobSnapshot<GenerateOutcome>>), ApiError> {
+    // Reject what would fail anyway before taking a slot in the queue
+    check_request(&state.settings, &request)?;
+    if let Some(url) = &callback_url {
+        let callbacks = state.callbacks.as_ref().ok_or_else(|| {
+            ApiError::invalid("this server sends no callbacks; it needs TECHDOCS_API_CALLBACK_SECRET")
+        })?;
+        callbacks.policy().check(url).await.map_err(ApiError::from)?;
+    }
+    let job = state.jobs.submit(request, callback_url).map_err(|QueueFull| {
+        ApiError::new(StatusCode::TOO_MANY_REQUESTS, ErrorCode::Busy, "the job queue is full; try again later")
+    })?;
+    tracing::info!("Queued job {}", job.id);
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Get the status of a queued generation
+///
+/// Returns the job's status and, once it is done, its result. Finished jobs are kept
+/// for the server's --job-ttl-secs.
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    params(("id" = String, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "Current state of the job", body = JobSnapshot<GenerateOutcome>),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 404, description = "Unknown or expired job", body = ErrorResponse),
+    ),
+)]
+async fn job_status_handler(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<String>,
+) -> Result<Json<JobSnapshot<GenerateOutcome>>, ApiError> {
+    state.jobs.get(&id).map(Json).ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            ErrorCode::NotFound,
+            format!("no job {}; finished jobs are kept for a limited time", id),
         )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    })
+}
 
-    // No need to initialize Claude client anymore
+/// Commit a webhook job checks out. Until the job starts, a later push to the same
+/// repository moves it forward, which is how rapid pushes collapse into one job.
+#[derive(Debug)]
+struct PendingRevision(std::sync::Mutex<Option<String>>);
 
-    // Load README prompt
-    let mut readme_prompt = String::new();
-    std::fs::File::open("prompts/readme.txt")?
-        .read_to_string(&mut readme_prompt)?;
+impl PendingRevision {
+    fn new(commit: String) -> Self {
+        Self(std::sync::Mutex::new(Some(commit)))
+    }
 
-    // Create app state
-    let state = AppState {
-        readme_prompt,
-    };
+    /// Take the commit to build; later pushes then need a job of their own
+    fn claim(&self) -> Option<String> {
+        self.0.lock().unwrap().take()
+    }
 
-    // Build router
-    let app = Router::new()
-        .route("/health", get(health_check))
-        .route("/generate", post(generate_readme_handler))
-        .with_state(state)
-        .layer(TraceLayer::new_for_http());
+    /// Move a job that has not started yet to `commit`. False once it has started.
+    fn advance(&self, commit: &str) -> bool {
+        match self.0.lock().unwrap().as_mut() {
+            Some(pending) => {
+                *pending = commit.to_string();
+                true
+            }
+            None => false,
+        }
+    }
+}
 
-    // Start server
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    println!("Listening on {}", addr);
-    
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+/// Verifies GitHub webhook deliveries and collapses pushes that arrive close together
+struct GitHubWebhook {
+    secret: String,
+    dedupe_window: Duration,
+    /// The last job queued for each repository, by full name
+    recent: std::sync::Mutex<HashMap<String, RecentPush>>,
+}
 
-    Ok(())
+struct RecentPush {
+    job_id: String,
+    queued_at: Instant,
+    revision: Arc<PendingRevision>,
+}
+
+impl GitHubWebhook {
+    /// Whether `signature`, the `X-Hub-Signature-256` header, is the HMAC-SHA256 of
+    /// `body` under the shared secret. The comparison takes constant time.
+    fn verify(&self, signature: &str, body: &[u8]) -> bool {
+        let Some(signature) = signature.strip_prefix("sha256=") else {
+            return false;
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()).expect("HMAC takes keys of any length");
+        mac.update(body);
+        let expected: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+        expected.as_bytes().ct_eq(signature.to_ascii_lowercase().as_bytes()).into()
+    }
+}
+
+/// The parts of a GitHub push event the webhook needs
+#[derive(Debug, Deserialize)]
+struct PushEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    /// Commit the branch points at after the push
+    after: String,
+    #[serde(default)]
+    deleted: bool,
+    repository: PushRepository,
+}
+
+#[derive(Debug, Deserialize)]
+struct PushRepository {
+    full_name: String,
+    html_url: String,
+    default_branch: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct WebhookIgnored {
+    /// Why the delivery was ignored
+    ignored: String,
+}
+
+/// Largest webhook payload GitHub delivers
+const GITHUB_WEBHOOK_MAX_BODY: usize = 25 * 1024 * 1024;
+
+/// Regenerate a README when a repository's default branch moves
+///
+/// GitHub webhook for push events, enabled by TECHDOCS_GITHUB_WEBHOOK_SECRET. GitHub
+/// signs each delivery with the shared secret, which takes the place of a bearer token.
+/// Other events and branches are acknowledged with 200 and ignored, so GitHub does not
+/// report them as failed deliveries.
+#[utoipa::path(
+    post,
+    path = "/webhooks/github",
+    security(()),
+    params(
+        ("X-GitHub-Event" = String, Header, description = "Event type; only `push` is handled"),
+        ("X-Hub-Signature-256" = String, Header, description = "HMAC-SHA256 of the body under the shared secret"),
+    ),
+    request_body(content = Object, description = "GitHub push event"),
+    responses(
+        (status = 200, description = "Another event or branch, acknowledged and ignored", body = WebhookIgnored),
+        (status = 202, description = "Job queued, or a queued job moved to the pushed commit", body = JobSnapshot<GenerateOutcome>),
+        (status = 401, description = "Missing or invalid signature", body = ErrorResponse),
+        (status = 404, description = "The server has no webhook secret configured", body = ErrorResponse),
+        (status = 429, description = "The job queue is full", body = ErrorResponse),
+    ),
+)]
+async fn github_webhook_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Response, ApiError> {
+    let webhook = state
+        .webhook
+        .as_ref()
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, ErrorCode::NotFound, "no such endpoint"))?;
+    let body = body.map_err(|rejection| ApiError::rejected_body(rejection.status(), rejection.body_text()))?;
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    if !webhook.verify(header("x-hub-signature-256"), &body) {
+        return Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            ErrorCode::Unauthorized,
+            "missing or invalid webhook signature",
+        ));
+    }
+
+    let ignored = |reason: String| {
+        tracing::debug!("Ignoring webhook: {}", reason);
+        (StatusCode::OK, Json(WebhookIgnored { ignored: reason })).into_response()
+    };
+    let event = header("x-github-event");
+    if event != "push" {
+        return Ok(ignored(format!("{} events are not handled", event)));
+    }
+    let push: PushEvent = serde_json::from_slice(&body)
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest, format!("invalid push event: {}", e)))?;
+    let repository = &push.repository;
+    if push.deleted || push.git_ref != format!("refs/heads/{}", repository.default_branch) {
+        return Ok(ignored(format!("{} is not the default branch of {}", push.git_ref, repository.full_name)));
+    }
+
+    let mut recent = webhook.recent.lock().unwrap();
+    recent.retain(|_, previous| previous.queued_at.elapsed() < webhook.dedupe_window);
+    if let Some(previous) = recent.get(&repository.full_name) {
+        if previous.revision.advance(&push.after) {
+            if let Some(job) = state.jobs.get(&previous.job_id) {
+                tracing::info!("Moved queued job {} for {} to {}", job.id, repository.full_name, push.after);
+                return Ok((StatusCode::ACCEPTED, Json(job)).into_response());
+            }
+        }
+    }
+
+    let revision = Arc::new(PendingRevision::new(push.after.clone()));
+    let request = GenerateReadmeRequest {
+        path_or_url: repository.html_url.clone(),
+        revision: Some(Arc::clone(&revision)),
+        ..Default::default()
+    };
+    let job = state.jobs.submit(request, None).map_err(|QueueFull| {
+        ApiError::new(StatusCode::TOO_MANY_REQUESTS, ErrorCode::Busy, "the job queue is full; try again later")
+    })?;
+    tracing::info!("Queued job {} for a push to {} at {}", job.id, repository.full_name, push.after);
+    recent.insert(
+        repository.full_name.clone(),
+        RecentPush {
+            job_id: job.id.clone(),
+            queued_at: Instant::now(),
+            revision,
+        },
+    );
+    Ok((StatusCode::ACCEPTED, Json(job)).into_response())
+}
+
+/// Check a request's overrides and local path against the server's limits
+fn check_request(
+    settings: &Settings,
+    request: &GenerateReadmeRequest,
+) -> Result<(), ApiError> {
+    settings.limits.check(request).map_err(ApiError::invalid)?;
+    settings.templates.get(request.template.as_deref()).map_err(ApiError::invalid)?;
+    if let Some(footer) = &request.footer {
+        Footer::new(footer).map_err(|e| ApiError::invalid(e.to_string()))?;
+    }
+    let source = match request.uploaded {
+        Some(_) => request.path_or_url.as_str(),
+        None => request_source(&request.path_or_url)?,
+    };
+    if request.create_pr {
+        if request.dry_run {
+            return Err(ApiError::invalid("create_pr cannot be combined with dry_run"));
+        }
+        if request.uploaded.is_some() || Url::parse(source).is_err() {
+            return Err(ApiError::invalid("create_pr needs a GitHub repository URL, not a local path or upload"));
+        }
+    }
+    if request.uploaded.is_none() {
+        settings.paths.check(&request.path_or_url).map_err(ApiError::forbidden)?;
+    }
+    Ok(())
+}
+
+/// Where the pipeline reports what it is doing
+struct Observer<'a> {
+    /// Receives each stage as it starts
+    status: &'a (dyn Fn(JobStatus) + Send + Sync),
+    /// Receives progress within the cloning and collecting stages
+    progress: ProgressFn<'a>,
+    /// Receives README text as it is generated; without it the reply is not streamed
+    text: Option<TextFn<'a>>,
+}
+
+impl Observer<'static> {
+    /// Reports nothing and does not stream
+    const SILENT: Self = Self {
+        status: &|_| {},
+        progress: &no_progress,
+        text: None,
+    };
+}
+
+/// Run a generation request through the pipeline, reporting to `observer` on the way.
+/// Cancelling `cancel` stops the clone, file walk, or provider call that is running.
+async fn generate(
+    settings: &Settings,
+    request: GenerateReadmeRequest,
+    observer: &Observer<'_>,
+    cancel: &CancellationToken,
+) -> Result<GenerateOutcome, ApiError> {
+    let (dry_run, max_tokens, force, create_pr) = (request.dry_run, request.max_tokens, request.force, request.create_pr);
+    let max_cost_usd = request.max_cost_usd;
+    let (check_web_links, strict_output) = (request.check_links, request.strict_output);
+    let (toc, add_badges) = (request.toc, request.badges);
+    let footer = request.footer.as_deref().map(Footer::new).transpose()?;
+    let source = match request.uploaded {
+        Some(_) => request.path_or_url.clone(),
+        None => settings.paths.check(&request.path_or_url).unwrap_or_default(),
+    };
+    let (template, system_prompt) = settings.templates.get(request.template.as_deref()).map_err(ApiError::invalid)?;
+    let uploaded = request.uploaded.is_some();
+    let collected = collect(settings, request, observer, cancel).await?;
+    let remote_metadata = match settings.remote_metadata && !uploaded {
+        true => fetch_remote_metadata(&source).await,
+        false => None,
+    };
+    let footer = match footer {
+        Some(footer) => Some(footer),
+        None => collected.config.footer.as_deref().map(Footer::new).transpose()?,
+    };
+    let options = GenerateOptions {
+        model: collected.config.model.clone(),
+        max_tokens,
+        system_prompt: Some(system_prompt.to_string()),
+        cancel: cancel.clone(),
+        cost: CostTracker::new(max_cost_usd),
+        post_processors: PostProcessors::builtin(
+            toc,
+            add_badges.then(|| badges(&detect_metadata(&collected.plan.root).with_remote(remote_metadata.as_ref()))),
+            footer.clone(),
+        ),
+        remote_metadata,
+        ..Default::default()
+    };
+    let prompt = ReadmePrompt::from_plan(&source, &collected.plan, &options)?;
+
+    // Return the assembled prompt without calling Claude
+    if dry_run {
+        settings.check_response_size(prompt.content.len())?;
+        let estimated_tokens = prompt.estimated_tokens();
+        return Ok(GenerateOutcome::DryRun(DryRunResponse {
+            template,
+            files_included: prompt.stats.files_included,
+            files_skipped: prompt.stats.files_skipped,
+            estimated_tokens,
+            license: prompt.provenance.license,
+            system_prompt: prompt.system,
+            content: prompt.content,
+        }));
+    }
+
+    let cache_key = settings
+        .cache
+        .as_ref()
+        .map(|_| {
+            // The same README with another table of contents, badges, or footer is another result
+            let finish = format!("{:?} {:?}", options.post_processors, footer);
+            cache_key(&source, &collected, (&template, &system_prompt), &prompt.content, max_tokens, &finish)
+        });
+    let hit = match (&settings.cache, &cache_key, force) {
+        (Some(cache), Some(key), false) => cache.get(key),
+        _ => None,
+    };
+    let mut response = match hit {
+        Some(hit) => {
+            tracing::info!("Serving cached README for {}", source);
+            if let Some(on_text) = observer.text {
+                on_text(&hit.readme);
+            }
+            settings.check_response_size(hit.readme.len())?;
+            GenerateReadmeResponse { cached: true, ..hit }
+        }
+        None => {
+            // Generate README using Claude
+            (observer.status)(JobStatus::Generating);
+            let client = ClaudeClient::new()?;
+            let mut generated = prompt.generate_with_progress(&client, observer.text, observer.progress).await?;
+            options.post_processors.run(&mut generated)?;
+            // Checked before the README is stored or cached, where it could never be served
+            settings.check_response_size(generated.content.len())?;
+
+            let mut response = GenerateReadmeResponse {
+                readme: generated.content,
+                license: generated.provenance.license.clone(),
+                usage: generated.usage,
+                cost_usd: prompt.cost.spend().cost_usd,
+                template,
+                model: generated.model,
+                cached: false,
+                stop_reason: generated.stop_reason,
+                pull_request: None,
+                document_id: None,
+                validation: Vec::new(),
+                repository: generated.provenance.repository.clone(),
+            };
+            // Stored before caching, so cached copies point at the stored document
+            if let Some(documents) = &settings.documents {
+                response.document_id =
+                    store_document(documents.as_ref(), settings.retention, &generated.provenance, &response).await;
+            }
+            if let (Some(cache), Some(key)) = (&settings.cache, &cache_key) {
+                cache.insert(key, response.clone());
+            }
+            response
+        }
+    };
+
+    response.validation = validate_markdown(&response.readme, &collected.plan.root);
+    if check_web_links {
+        response.validation.extend(check_links(&response.readme).await);
+    }
+    if strict_output && !response.validation.is_empty() {
+        let issues: Vec<String> = response.validation.iter().map(ToString::to_string).collect();
+        return Err(TechDocsError::Validation(issues.join("; ")).into());
+    }
+
+    if create_pr {
+        let repo = Repository::from_url(&source)?;
+        let text = PullRequestText::for_readme(&response.model, &response.usage);
+        let github = GitHubClient::new()?;
+        let outcome = github.open_readme_pull_request(&repo, &response.readme, &text).await?;
+        response.pull_request = Some(outcome);
+    }
+    Ok(GenerateOutcome::Readme(Box::new(response)))
+}
+
+/// Key for a generation's cached README. The prompt covers the content of every
+/// included file, so edits outside git, and different filters or limits that change
+/// the chosen files, all miss; the commit and filters are added so that two
+/// revisions or selections never share an entry even when their prompts match.
+fn cache_key(
+    source: &str,
+    collected: &Collected,
+    (template, system_prompt): (&str, &str),
+    prompt: &str,
+    max_tokens: Option<u32>,
+    post_processing: &str,
+) -> String {
+    // URLs name the same repository with or without a trailing slash or `.git`
+    let source = match Url::parse(source) {
+        Ok(_) => source.trim_end_matches('/').trim_end_matches(".git"),
+        Err(_) => source,
+    };
+    let commit = head_commit(&collected.plan.root).unwrap_or_default();
+    let config = &collected.config;
+    CacheKey::new()
+        .part(source)
+        .part(commit)
+        .part(format!("{:?}", collected.files))
+        .part(config.model.as_deref().unwrap_or_default())
+        .part(max_tokens.map(|tokens| tokens.to_string()).unwrap_or_default())
+        .part(template)
+        .part(system_prompt)
+        .part(prompt)
+        .part(post_processing)
+        .finish()
+}
+
+/// A request's project, resolved and with its files chosen
+struct Collected {
+    plan: PromptPlan,
+    config: Config,
+    /// The files the request asked for, after merging its configuration
+    files: PromptOptions,
+    /// Keeps a cloned repository on disk until the plan's files have been read
+    _checkout: Option<Checkout>,
+}
+
+/// Keep a generated README in the document store, returning its id. Failing to store
+/// it is logged, not returned, because the README itself is fine.
+async fn store_document(
+    documents: &dyn DocumentStore,
+    retention: Retention,
+    provenance: &Provenance,
+    response: &GenerateReadmeResponse,
+) -> Option<String> {
+    let source = &provenance.source;
+    let document = Document {
+        info: DocumentInfo {
+            id: new_document_id(),
+            source: source.clone(),
+            repository: Repository::from_url(source).ok().map(|repo| repo.to_string()),
+            commit: provenance.commit.clone(),
+            model: response.model.clone(),
+            template: response.template.clone(),
+            usage: response.usage,
+            created_at: Utc::now(),
+        },
+        content: response.readme.clone(),
+    };
+    if let Err(e) = documents.put(&document).await {
+        tracing::warn!("cannot store the README for {}: {}", source, e);
+        return None;
+    }
+    if let Err(e) = retention.sweep(documents).await {
+        tracing::warn!("cannot sweep stored documents: {}", e);
+    }
+    Some(document.info.id)
+}
+
+/// Check a request, resolve its path or URL, and choose the files for its prompt.
+/// Shared by every endpoint that works on a project.
+async fn collect(
+    settings: &Settings,
+    request: GenerateReadmeRequest,
+    observer: &Observer<'_>,
+    cancel: &CancellationToken,
+) -> Result<Collected, ApiError> {
+    check_request(settings, &request)?;
+
+    // Resolve path (local or GitHub URL); the upload handler keeps an extracted
+    // archive on disk itself
+    let (path, checkout) = match request.uploaded.clone() {
+        Some(path) => (path, None),
+        None => {
+            if Url::parse(&request.path_or_url).is_ok() {
+                (observer.status)(JobStatus::Cloning);
+            }
+            let revision = request.revision.as_ref().and_then(|revision| revision.claim());
+            resolve_request_path(settings, &request.path_or_url, revision.as_deref(), observer.progress, cancel).await?
+        }
+    };
+    (observer.status)(JobStatus::Collecting);
+
+    // Patterns from exclude_file come first, then exclude_patterns
+    let exclude = match request.exclude_file.as_deref() {
+        Some(content) => {
+            let mut patterns = parse_exclude_patterns(content, "exclude_file")?;
+            patterns.extend(request.exclude_patterns.unwrap_or_default());
+            Some(patterns)
+        }
+        None => request.exclude_patterns,
+    };
+
+    // Merge server configuration with the project's .techdocs.toml and the request
+    let config = load_config(settings, &path, PartialConfig {
+        exclude,
+        preset: request.preset,
+        max_depth: request.max_depth,
+        max_file_size_kb: request.max_file_size_kb,
+        max_total_size_mb: request.max_total_size_mb,
+        model: request.model,
+        ..Default::default()
+    })?;
+
+    // Include patterns from the request are added to those of the project's configuration
+    let files = PromptOptions {
+        max_files: request.max_files,
+        max_files_per_dir: request.max_files_per_dir,
+        normalize: Normalize::Full,
+        ..PromptOptions::from_config(&config)
+    }
+    .include(request.include_patterns)
+    .walk_ceilings(settings.limits.walk_entries, settings.limits.walk_time);
+
+    // The walk reads the disk, so it runs on a blocking thread and sends its progress
+    // back here. The library error is not Send, so it is converted on that thread.
+    let (events, mut received) = unbounded_channel::<ProgressEvent>();
+    let walking = {
+        let (path, files, cancel) = (path.clone(), files.clone(), cancel.clone());
+        tokio::task::spawn_blocking(move || {
+            let progress = move |event: &ProgressEvent| {
+                let _ = events.send(event.clone());
+            };
+            PromptPlan::collect_cancellable(&path, &files, &progress, &|| cancel.is_cancelled()).map_err(ApiError::from)
+        })
+    };
+    while let Some(event) = received.recv().await {
+        (observer.progress)(&event);
+    }
+    let plan = walking.await.map_err(|e| ApiError::internal(e.to_string()))??;
+
+    Ok(Collected {
+        plan,
+        config,
+        files,
+        _checkout: checkout,
+    })
+}
+
+/// Bytes of prompt buffered ahead of a client reading /prompt
+const PROMPT_STREAM_BUFFER: usize = 64 * 1024;
+
+/// Stream the prompt for `collected` as a response body, refusing one too large to send
+/// back to a client. Files are read as the client takes them; a read failure ends the
+/// body with an error rather than a silently shorter prompt.
+fn stream_prompt(settings: &Settings, collected: Collected) -> Result<Body, ApiError> {
+    settings.check_response_size(collected.plan.prompt_bytes() as usize)?;
+    let (writer, reader) = tokio::io::duplex(PROMPT_STREAM_BUFFER);
+    // The task owns the checkout, so a clone stays on disk until its files are read
+    let writing = tokio::spawn(async move {
+        let written = collected.plan.write_prompt_async(writer).await;
+        written.map_err(|e| std::io::Error::other(e.to_string()))
+    });
+    let failure = futures_util::stream::once(async move {
+        match writing.await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => {
+                tracing::warn!("Streaming the prompt failed: {}", e);
+                Some(Err(e))
+            }
+            Err(e) => Some(Err(std::io::Error::other(e))),
+        }
+    })
+    .filter_map(std::future::ready);
+    Ok(Body::from_stream(ReaderStream::new(reader).chain(failure)))
+}
+
+/// Generate a short project description
+///
+/// A 2-3 sentence description and a list of topics, from the project's README,
+/// manifests, and entry points only.
+#[utoipa::path(
+    post,
+    path = "/summary",
+    request_body = SummaryRequest,
+    responses(
+        (status = 200, description = "Summary generated", body = ProjectSummary),
+        (status = 400, description = "Malformed body, invalid patterns, or unsupported URL", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorResponse),
+        (status = 402, description = "The prompt's estimated cost is over max_cost_usd (`cost_limit_exceeded`)", body = ErrorResponse),
+        (status = 403, description = "Local path outside the server's TECHDOCS_ALLOWED_PATHS", body = ErrorResponse),
+        (status = 408, description = "The request took longer than the server's --request-timeout-secs", body = ErrorResponse),
+        (status = 413, description = "The body exceeds the server's --max-body-kb", body = ErrorResponse),
+        (status = 422, description = "A limit or model override is outside what the server allows", body = ErrorResponse),
+        (
+            status = 429,
+            description = "The client is over its rate limit (`rate_limited`), every generation slot stayed busy (`busy`), or Claude rate limited the server (`provider_rate_limited`)",
+            body = ErrorResponse,
+            headers(("Retry-After" = u64, description = "Seconds to wait, for `rate_limited` and `busy`")),
+        ),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 502, description = "Cloning the repository or the request to Claude failed", body = ErrorResponse),
+        (status = 507, description = "The repository does not fit the server's clone space", body = ErrorResponse),
+    ),
+)]
+async fn summary_handler(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<SummaryRequest>,
+) -> Result<Json<ProjectSummary>, ApiError> {
+    let _slot = state.settings.slots.acquire().await?;
+    let settings = &state.settings;
+    let cancel = &settings.shutdown;
+
+    // The same walk, limits, and configuration as /generate; the summary is written from
+    // the README, manifests, and entry points among the files it found
+    let request = GenerateReadmeRequest {
+        path_or_url: request.path_or_url,
+        max_cost_usd: request.max_cost_usd,
+        ..Default::default()
+    };
+    let max_cost_usd = request.max_cost_usd;
+    let collected = collect(settings, request, &Observer::SILENT, cancel).await?;
+    let root = &collected.plan.root;
+    let files = pick_summary_files(root, collected.plan.files.iter().map(|file| file.path.clone()));
+
+    let client = CostTrackedClient::new(ClaudeClient::new()?, CostTracker::new(max_cost_usd));
+    let summary = tokio::select! {
+        summary = summarize(&client, root, &files, collected.config.model.as_deref()) => summary?.0,
+        _ = cancel.cancelled() => return Err(TechDocsError::Cancelled.into()),
+    };
+
+    Ok(Json(summary))
+}
+
+/// The API description served at /openapi.json, derived from the handlers and types
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "TechDocs API",
+        description = "Generate technical documentation for local directories and GitHub repositories"
+    ),
+    servers((url = "http://localhost:3000", description = "Local development server")),
+    paths(
+        health_check,
+        ready_handler,
+        version_handler,
+        generate_readme_handler,
+        generate_stream_handler,
+        upload_handler,
+        prompt_handler,
+        files_handler,
+        manifest_handler,
+        summary_handler,
+        submit_job_handler,
+        job_status_handler,
+        github_webhook_handler,
+        clear_cache_handler,
+        list_documents_handler,
+        get_document_handler,
+        reload_prompts_handler,
+        metrics_handler,
+    ),
+    modifiers(&BearerAuth),
+)]
+struct ApiDoc;
+
+/// Declares bearer token authentication
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi.components.get_or_insert_with(Default::default).add_security_scheme(
+            "bearerAuth",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("One of the tokens in the server's TECHDOCS_API_TOKENS"))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// The API description, requiring a bearer token everywhere but on routes that opt out
+/// when the server has tokens
+fn openapi(auth: bool) -> utoipa::openapi::OpenApi {
+    let mut openapi = ApiDoc::openapi();
+    if auth {
+        openapi.security = Some(vec![SecurityRequirement::new("bearerAuth", Vec::<String>::new())]);
+    }
+    openapi
+}
+
+/// Redoc page rendering /openapi.json
+const DOCS_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>TechDocs API</title>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+  </head>
+  <body>
+    <redoc spec-url="/openapi.json"></redoc>
+    <script src="https://cdn.redoc.ly/redoc/latest/bundles/redoc.standalone.js"></script>
+  </body>
+</html>
+"#;
+
+#[derive(Parser)]
+#[command(author, version, about = "HTTP API for README generation")]
+struct ServerArgs {
+    /// Print the OpenAPI description as JSON and exit
+    #[arg(long)]
+    print_openapi: bool,
+
+    /// Log more: -v for debug, -vv for trace (RUST_LOG takes precedence when set)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Deprecated: log filter such as `debug`; use -v instead
+    #[arg(long, hide = true)]
+    log_level: Option<String>,
+
+    /// Format of log lines on stderr: text or json
+    #[arg(long, default_value = "text", env = "TECHDOCS_LOG_FORMAT")]
+    log_format: String,
+
+    /// Never use ANSI colors (also honors NO_COLOR)
+    #[arg(long, env = "TECHDOCS_NO_COLOR", value_parser = BoolishValueParser::new())]
+    no_color: bool,
+
+    /// Largest max_file_size_kb a /generate request may ask for
+    #[arg(long, default_value_t = 1024, env = "TECHDOCS_API_LIMIT_FILE_SIZE_KB")]
+    limit_file_size_kb: u64,
+
+    /// Largest max_total_size_mb a /generate request may ask for
+    #[arg(long, default_value_t = 50, env = "TECHDOCS_API_LIMIT_TOTAL_SIZE_MB")]
+    limit_total_size_mb: u64,
+
+    /// Largest max_files a /generate request may ask for
+    #[arg(long, default_value_t = 10_000, env = "TECHDOCS_API_LIMIT_FILES")]
+    limit_files: usize,
+
+    /// Largest max_tokens a /generate request may ask for
+    #[arg(long, default_value_t = 16_384, env = "TECHDOCS_API_LIMIT_MAX_TOKENS")]
+    limit_max_tokens: u32,
+
+    /// Files and directories a walk may find before the source is refused with 413;
+    /// lower than the CLI's default so one request cannot tie up the server
+    #[arg(long, default_value_t = 50_000, env = "TECHDOCS_API_LIMIT_WALK_ENTRIES")]
+    limit_walk_entries: usize,
+
+    /// Seconds file selection may take before the source is refused with 413
+    #[arg(long, default_value_t = 30, env = "TECHDOCS_API_LIMIT_WALK_SECS")]
+    limit_walk_secs: u64,
+
+    /// Do not ask the GitHub API for the description, topics, homepage, license, and
+    /// stars of requested repository URLs
+    #[arg(long, env = "TECHDOCS_API_NO_REMOTE_METADATA", value_parser = BoolishValueParser::new())]
+    no_remote_metadata: bool,
+
+    /// Comma-separated models a /generate request may choose (default: any)
+    #[arg(long, env = "TECHDOCS_API_MODELS", value_delimiter = ',')]
+    models: Vec<String>,
+
+    /// Comma-separated directories local paths in requests must be under; without it,
+    /// only repository URLs are accepted
+    #[arg(long, env = "TECHDOCS_ALLOWED_PATHS", value_delimiter = ',')]
+    allowed_paths: Vec<PathBuf>,
+
+    /// Largest prompt, file list, or README returned by /prompt, /files, a dry run, or a
+    /// generation, in MB; larger responses get 413
+    #[arg(long, default_value_t = 50, env = "TECHDOCS_API_MAX_RESPONSE_MB")]
+    max_response_mb: u64,
+
+    /// Number of generations (/generate, /generate/stream, /summary, and jobs) that call
+    /// the provider at the same time
+    #[arg(long, default_value_t = 4, env = "TECHDOCS_API_MAX_CONCURRENT_GENERATIONS")]
+    max_concurrent_generations: usize,
+
+    /// Seconds a request waits for a generation slot before getting 429
+    #[arg(long, default_value_t = 10, env = "TECHDOCS_API_GENERATION_WAIT_SECS")]
+    generation_wait_secs: u64,
+
+    /// Number of generated READMEs kept in memory; 0 keeps none
+    #[arg(long, default_value_t = 0, env = "TECHDOCS_API_CACHE_ENTRIES")]
+    cache_entries: usize,
+
+    /// Directory to keep generated READMEs in across restarts
+    #[arg(long, env = "TECHDOCS_API_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Seconds a cached README is served before it is generated again
+    #[arg(long, default_value_t = 86_400, env = "TECHDOCS_API_CACHE_TTL_SECS")]
+    cache_ttl_secs: u64,
+
+    /// Directory to keep every generated README in, served at /documents
+    #[arg(long, env = "TECHDOCS_API_DOCUMENTS_DIR")]
+    documents_dir: Option<PathBuf>,
+
+    /// Most documents kept; the oldest are deleted first. 0 keeps any number
+    #[arg(long, default_value_t = 1000, env = "TECHDOCS_API_DOCUMENTS_MAX")]
+    documents_max: usize,
+
+    /// Seconds a document is kept; 0 keeps documents until --documents-max pushes them out
+    #[arg(long, default_value_t = 0, env = "TECHDOCS_API_DOCUMENTS_TTL_SECS")]
+    documents_ttl_secs: u64,
+
+    /// Directory of prompt templates, one `.txt` file each, that requests choose by file
+    /// name with `template`
+    #[arg(long, env = "TECHDOCS_API_PROMPTS_DIR")]
+    prompts_dir: Option<PathBuf>,
+
+    /// Directory repositories are cloned into [default: techdocs-api in the system
+    /// temporary directory]
+    #[arg(long, env = "TECHDOCS_API_WORKSPACE_DIR")]
+    workspace_dir: Option<PathBuf>,
+
+    /// Disk space all clones together may take, in MB; requests that need more get 507
+    #[arg(long, default_value_t = 10_240, env = "TECHDOCS_API_WORKSPACE_QUOTA_MB")]
+    workspace_quota_mb: u64,
+
+    /// Disk space a single clone may take, in MB; larger repositories get 507
+    #[arg(long, default_value_t = 2048, env = "TECHDOCS_API_MAX_CLONE_MB")]
+    max_clone_mb: u64,
+
+    /// Age in seconds after which a clone left in the workspace by an earlier run is
+    /// deleted at startup
+    #[arg(long, default_value_t = 3600, env = "TECHDOCS_API_WORKSPACE_TTL_SECS")]
+    workspace_ttl_secs: u64,
+
+    /// Free disk space in MB the workspace needs for /ready to report the server ready
+    #[arg(long, default_value_t = 512, env = "TECHDOCS_API_READY_MIN_FREE_MB")]
+    ready_min_free_mb: u64,
+
+    /// Seconds between the background provider checks /ready reports
+    #[arg(long, default_value_t = 60, env = "TECHDOCS_API_READY_PROBE_SECS")]
+    ready_probe_secs: u64,
+
+    /// Requests a minute each client may make, counted by bearer token or else by
+    /// address; 0 turns the limit off
+    #[arg(long, default_value_t = 0, env = "TECHDOCS_API_RATE_LIMIT_PER_MINUTE")]
+    rate_limit_per_minute: u32,
+
+    /// Requests a client may make at once before the per-minute rate applies
+    #[arg(long, default_value_t = 10, env = "TECHDOCS_API_RATE_LIMIT_BURST")]
+    rate_limit_burst: u32,
+
+    /// Count clients by the last X-Forwarded-For address, for servers behind a proxy
+    /// that sets it
+    #[arg(long, env = "TECHDOCS_API_TRUST_FORWARDED_FOR", value_parser = BoolishValueParser::new())]
+    trust_forwarded_for: bool,
+
+    /// Largest request body accepted, in KB, apart from uploads and GitHub webhook
+    /// deliveries; larger bodies get 413
+    #[arg(long, default_value_t = 64, env = "TECHDOCS_API_MAX_BODY_KB")]
+    max_body_kb: u64,
+
+    /// Largest archive accepted by /generate/upload, in MB; larger uploads get 413
+    #[arg(long, default_value_t = 100, env = "TECHDOCS_API_MAX_UPLOAD_MB")]
+    max_upload_mb: u64,
+
+    /// Seconds a request may take, from receiving it to the last byte of a streamed
+    /// README; slower requests get 408 and their clone and provider call are cancelled
+    #[arg(long, default_value_t = 300, env = "TECHDOCS_API_REQUEST_TIMEOUT_SECS")]
+    request_timeout_secs: u64,
+
+    /// Seconds to let in-flight requests and jobs finish after SIGINT or SIGTERM
+    #[arg(long, default_value_t = 30, env = "TECHDOCS_API_DRAIN_TIMEOUT_SECS")]
+    drain_timeout_secs: u64,
+
+    /// Number of POST /jobs generations that run at the same time
+    #[arg(long, default_value_t = 2, env = "TECHDOCS_API_JOB_WORKERS")]
+    job_workers: usize,
+
+    /// Number of jobs that may wait for a worker before POST /jobs returns 429
+    #[arg(long, default_value_t = 32, env = "TECHDOCS_API_JOB_QUEUE_SIZE")]
+    job_queue_size: usize,
+
+    /// Seconds a finished job's result is kept for GET /jobs/{id}
+    #[arg(long, default_value_t = 3600, env = "TECHDOCS_API_JOB_TTL_SECS")]
+    job_ttl_secs: u64,
+
+    /// Secret job completion callbacks are signed with; enables `callback_url` on POST /jobs
+    #[arg(long, env = "TECHDOCS_API_CALLBACK_SECRET", hide_env_values = true)]
+    callback_secret: Option<String>,
+
+    /// Comma-separated hosts callbacks may be sent to; when unset, any host that
+    /// resolves to public addresses only
+    #[arg(long, env = "TECHDOCS_API_CALLBACK_ALLOWED_HOSTS", value_delimiter = ',')]
+    callback_allowed_hosts: Vec<String>,
+
+    /// Secret shared with GitHub; enables POST /webhooks/github
+    #[arg(long, env = "TECHDOCS_GITHUB_WEBHOOK_SECRET", hide_env_values = true)]
+    github_webhook_secret: Option<String>,
+
+    /// Seconds during which further pushes to a repository join its queued webhook job
+    #[arg(long, default_value_t = 60, env = "TECHDOCS_GITHUB_WEBHOOK_DEDUPE_SECS")]
+    github_webhook_dedupe_secs: u64,
+
+    /// Comma-separated bearer tokens; when set, every endpoint requires one except
+    /// /health and POST /webhooks/github, which checks GitHub's signature instead
+    #[arg(long, env = "TECHDOCS_API_TOKENS", value_delimiter = ',', hide_env_values = true)]
+    api_tokens: Vec<String>,
+
+    /// Comma-separated origins browsers may call the API from, or `*` for any; when
+    /// unset, cross-origin browser requests are refused
+    #[arg(long, env = "TECHDOCS_CORS_ORIGINS", value_delimiter = ',')]
+    cors_origins: Vec<String>,
+
+    /// Address to listen on, for plain HTTP and HTTPS alike; 0.0.0.0:3000 accepts
+    /// connections from other machines
+    #[arg(long, default_value = "127.0.0.1:3000", env = "TECHDOCS_API_ADDR")]
+    bind: SocketAddr,
+
+    /// PEM certificate chain to serve HTTPS with; needs --tls-key. Send SIGHUP to read
+    /// it again after renewal.
+    #[arg(long, env = "TECHDOCS_TLS_CERT", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key of --tls-cert
+    #[arg(long, env = "TECHDOCS_TLS_KEY", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+}
+
+/// Read a PEM certificate chain and private key into a server config, failing when
+/// either cannot be read or the key is not the certificate's
+fn load_tls_config(cert: &Path, key: &Path) -> TechDocsResult<Arc<rustls::ServerConfig>> {
+    let invalid = |path: &Path, reason: &dyn std::fmt::Display| {
+        TechDocsError::Config(format!("cannot load TLS file {}: {}", path.display(), reason))
+    };
+    let open = |path: &Path| {
+        std::fs::File::open(path).map(std::io::BufReader::new).map_err(|e| invalid(path, &e))
+    };
+    let chain = rustls_pemfile::certs(&mut open(cert)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(cert, &e))?;
+    if chain.is_empty() {
+        return Err(invalid(cert, &"no PEM certificate in the file"));
+    }
+    let private_key = rustls_pemfile::private_key(&mut open(key)?)
+        .map_err(|e| invalid(key, &e))?
+        .ok_or_else(|| invalid(key, &"no PEM private key in the file"))?;
+
+    let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| TechDocsError::Config(format!("TLS: {}", e)))?
+        .with_no_client_auth()
+        .with_single_cert(chain, private_key)
+        .map_err(|e| {
+            TechDocsError::Config(format!(
+                "TLS key {} does not fit certificate {}: {}",
+                key.display(),
+                cert.display(),
+                e
+            ))
+        })?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// Read the certificate and key again on every SIGHUP. A pair that fails to load is
+/// logged and the server keeps the one it has.
+#[cfg(unix)]
+fn spawn_tls_reloader(tls: axum_server::tls_rustls::RustlsConfig, cert: PathBuf, key: PathBuf) {
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => signal,
+        Err(e) => {
+            tracing::error!("cannot listen for SIGHUP; the TLS certificate will not be reloaded: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match load_tls_config(&cert, &key) {
+                Ok(config) => {
+                    tls.reload_from_config(config);
+                    tracing::info!("Reloaded TLS certificate {}", cert.display());
+                }
+                Err(e) => tracing::error!("Keeping the current TLS certificate: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_tls_reloader(_tls: axum_server::tls_rustls::RustlsConfig, _cert: PathBuf, _key: PathBuf) {}
+
+/// Browsers may cache a preflight response this long
+const CORS_MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// CORS for browser clients on `origins`, exact origins or `*`; `None` without origins,
+/// which leaves cross-origin browser requests blocked
+fn cors_layer(origins: &[String]) -> TechDocsResult<Option<CorsLayer>> {
+    let origins: Vec<&str> = origins.iter().map(|origin| origin.trim()).filter(|origin| !origin.is_empty()).collect();
+    let allow_origin = if origins.is_empty() {
+        return Ok(None);
+    } else if origins.contains(&"*") {
+        AllowOrigin::any()
+    } else {
+        let origins = origins
+            .iter()
+            .map(|origin| {
+                header::HeaderValue::from_str(origin)
+                    .map_err(|_| TechDocsError::Config(format!("invalid CORS origin: {}", origin)))
+            })
+            .collect::<TechDocsResult<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::POST])
+            .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
+            .expose_headers([header::RETRY_AFTER, header::HeaderName::from_static("x-request-id")])
+            .max_age(CORS_MAX_AGE),
+    ))
+}
+
+/// Build the router. With `tokens`, every route except /health and the webhook, which
+/// checks its own signature, requires a bearer token. With
+/// `cors`, preflight requests are answered before authentication.
+fn router(state: AppState, tokens: ApiTokens, cors: Option<CorsLayer>) -> Router {
+    let spec = openapi(!tokens.0.is_empty());
+    let max_body_bytes = state.settings.max_body_bytes;
+    // Room for the archive plus the other multipart fields
+    let max_upload_body = usize::try_from(state.settings.max_upload_bytes)
+        .unwrap_or(usize::MAX)
+        .saturating_add(max_body_bytes);
+    let timeout = state.settings.request_timeout;
+    let limiter = state.settings.limiter.clone();
+    // GitHub signs webhook deliveries with the shared secret instead of sending a token,
+    // and sends pushes far larger than API requests
+    let webhook = Router::new()
+        .route(
+            "/webhooks/github",
+            post(github_webhook_handler).layer(DefaultBodyLimit::max(GITHUB_WEBHOOK_MAX_BODY)),
+        )
+        .with_state(state.clone());
+    let mut api = Router::new()
+        .route("/ready", get(ready_handler))
+        .route("/version", get(version_handler))
+        .route("/openapi.json", get(move || async move { Json(spec) }))
+        .route("/docs", get(|| async { Html(DOCS_PAGE) }))
+        .route("/generate", post(generate_readme_handler))
+        .route("/generate/stream", post(generate_stream_handler))
+        .route("/generate/upload", post(upload_handler).layer(DefaultBodyLimit::max(max_upload_body)))
+        .route("/prompt", post(prompt_handler))
+        .route("/files", post(files_handler))
+        .route("/manifest", post(manifest_handler))
+        .route("/summary", post(summary_handler))
+        .route("/jobs", post(submit_job_handler))
+        .route("/jobs/{id}", get(job_status_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/cache", delete(clear_cache_handler))
+        .route("/documents", get(list_documents_handler))
+        .route("/documents/{id}", get(get_document_handler))
+        .route("/admin/reload-prompts", post(reload_prompts_handler))
+        .with_state(state);
+    // Layers added later wrap those added earlier: requests with a valid token are
+    // counted by it inside authentication, and the rest by their address outside it
+    if let Some(limiter) = &limiter {
+        api = api.route_layer(middleware::from_fn_with_state(Arc::clone(limiter), limit_clients));
+    }
+    if !tokens.0.is_empty() {
+        api = api.route_layer(middleware::from_fn_with_state(tokens.clone(), require_token));
+        if let Some(limiter) = limiter {
+            api = api.route_layer(middleware::from_fn_with_state((limiter, tokens), limit_unauthenticated));
+        }
+    }
+
+    let app = Router::new()
+        .route("/health", get(health_check))
+        .merge(webhook)
+        .merge(api)
+        .fallback(not_found)
+        .layer(middleware::from_fn_with_state(timeout, request_timeout))
+        .layer(DefaultBodyLimit::max(max_body_bytes))
+        .layer(middleware::from_fn(request_id))
+        // Prompts and manifests compress well; event streams are left alone
+        .layer(CompressionLayer::new());
+    let app = match cors {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+    // The id is set before the span is made, so every log line of a request carries it,
+    // and returned on the response, including the one a client supplied
+    app.layer(
+        ServiceBuilder::new()
+            .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+            .layer(TraceLayer::new_for_http().make_span_with(|request: &Request<Body>| {
+                tracing::debug_span!(
+                    "request",
+                    method = %request.method(),
+                    uri = %request.uri(),
+                    version = ?request.version(),
+                    authorized = Empty,
+                    request_id = %request_id_of(request),
+                )
+            }))
+            .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER)),
+    )
+}
+
+/// How often stored documents past their retention are deleted, besides after each store
+const DOCUMENT_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Delete documents past `retention` now and then, so a TTL is kept without new
+/// documents coming in
+fn spawn_document_sweeper(documents: Arc<dyn DocumentStore>, retention: Retention) {
+    if retention.is_unbounded() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(DOCUMENT_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match retention.sweep(documents.as_ref()).await {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!("Removed {} documents past their retention", removed),
+                Err(e) => tracing::warn!("cannot sweep stored documents: {}", e),
+            }
+        }
+    });
+}
+
+#[tokio::main]
+async fn main() -> TechDocsResult<()> {
+    let args = ServerArgs::parse();
+
+    if args.print_openapi {
+        let auth = args.api_tokens.iter().any(|token| !token.trim().is_empty());
+        let spec = openapi(auth).to_pretty_json().map_err(|e| TechDocsError::Output(e.to_string()))?;
+        println!("{}", spec);
+        return Ok(());
+    }
+
+    // Initialize tracing on stderr
+    let format: LogFormat = args.log_format.parse()?;
+    init_logging(&LogOptions {
+        targets: vec!["techdocs".into(), "tower_http".into()],
+        verbose: args.verbose,
+        quiet: false,
+        log_level: args.log_level.clone(),
+        format,
+        no_color: args.no_color,
+    });
+
+    // Refuse to start with a certificate that cannot be served
+    let tls = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => {
+            let tls = axum_server::tls_rustls::RustlsConfig::from_config(load_tls_config(cert, key)?);
+            spawn_tls_reloader(tls.clone(), cert.clone(), key.clone());
+            Some(tls)
+        }
+        _ => None,
+    };
+
+    let state = app_state(&args)?;
+    let (jobs, shutdown) = (Arc::clone(&state.jobs), state.settings.shutdown.clone());
+
+    let tokens: Vec<String> = args
+        .api_tokens
+        .iter()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+        .collect();
+    if tokens.is_empty() {
+        tracing::warn!("TECHDOCS_API_TOKENS is not set; the API accepts unauthenticated requests");
+    } else {
+        tracing::info!("API token authentication enabled ({} tokens)", tokens.len());
+    }
+
+    let cors = cors_layer(&args.cors_origins)?;
+    if cors.is_some() {
+        tracing::info!("CORS enabled for {}", args.cors_origins.join(", "));
+    }
+
+    let app = router(state, ApiTokens(Arc::new(tokens)), cors);
+
+    // Start server
+    tracing::info!("Listening on {}://{}", if tls.is_some() { "https" } else { "http" }, args.bind);
+    let listener = tokio::net::TcpListener::bind(args.bind).await?;
+    let (stop, stopped) = watch::channel(false);
+    let stopped = async move {
+        let mut stopped = stopped;
+        let _ = stopped.wait_for(|stopped| *stopped).await;
+    };
+    let mut server = serve(listener, app, tls, stopped)?;
+
+    tokio::select! {
+        result = &mut server => {
+            // The server only stops by itself when it fails
+            result.map_err(|e| TechDocsError::Other(Box::new(e)))??;
+            return Ok(());
+        }
+        _ = shutdown_signal() => {}
+    }
+
+    // Stop accepting connections and let in-flight requests and jobs finish
+    let drain = Duration::from_secs(args.drain_timeout_secs);
+    tracing::info!("Shutting down; waiting up to {}s for in-flight requests", drain.as_secs());
+    stop.send_replace(true);
+    let (served, interrupted) = tokio::join!(tokio::time::timeout(drain, &mut server), jobs.shutdown(drain));
+    if interrupted > 0 {
+        tracing::warn!("{} jobs did not finish and were marked failed", interrupted);
+    }
+    // Aborting a task takes effect only when it yields, which a file walk does not;
+    // the token stops walks and everything else still running
+    shutdown.cancel();
+    if served.is_err() {
+        // Returning drops the remaining requests with the runtime, which still runs
+        // their destructors, so cloned repositories are removed
+        tracing::warn!("Drain timeout reached; cancelling the remaining requests");
+        server.abort();
+    }
+    tracing::info!("Shut down");
+    Ok(())
+}
+
+/// Serve `app` on `listener`, over HTTPS with `tls`, until `stopped` completes and the
+/// open connections finish
+fn serve(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    tls: Option<axum_server::tls_rustls::RustlsConfig>,
+    stopped: impl Future<Output = ()> + Send + 'static,
+) -> TechDocsResult<JoinHandle<std::io::Result<()>>> {
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    Ok(match tls {
+        None => tokio::spawn(axum::serve(listener, app).with_graceful_shutdown(stopped).into_future()),
+        Some(tls) => {
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                async move {
+                    stopped.await;
+                    handle.graceful_shutdown(None);
+                }
+            });
+            tokio::spawn(axum_server::from_tcp_rustls(listener.into_std()?, tls).handle(handle).serve(app))
+        }
+    })
+}
+
+/// The state the server described by `args` runs with, starting its job workers and
+/// background checks
+fn app_state(args: &ServerArgs) -> TechDocsResult<AppState> {
+    // Load server configuration (user config file and TECHDOCS_* environment)
+    let config = Config::load(None, PartialConfig::default())?;
+
+    // Load README prompt, overridable with TECHDOCS_PROMPT_FILE, and the named templates
+    let readme_prompt = load_readme_prompt(config.prompt_file.as_deref())?;
+    let templates = PromptTemplates::load(readme_prompt, args.prompts_dir.clone())?;
+    tracing::info!("Prompt templates: {}", templates.names().join(", "));
+
+    // Clones go to a workspace of their own, cleared of what an earlier run left behind
+    let workspace_dir = args.workspace_dir.clone().unwrap_or_else(|| std::env::temp_dir().join("techdocs-api"));
+    let workspace = Workspace::new(
+        workspace_dir,
+        args.workspace_quota_mb.saturating_mul(1024 * 1024),
+        args.max_clone_mb.saturating_mul(1024 * 1024),
+    )?;
+    let swept = workspace.sweep(Duration::from_secs(args.workspace_ttl_secs));
+    if swept > 0 {
+        tracing::info!("Removed {} stale clones from {}", swept, workspace.root().display());
+    }
+    let workspace = Arc::new(workspace);
+
+    // Caching is on when it has somewhere to keep entries
+    let cache = match (args.cache_entries, args.cache_dir.clone()) {
+        (0, None) => None,
+        (entries, dir) => Some(ResultCache::new(entries, Duration::from_secs(args.cache_ttl_secs), dir)?),
+    };
+
+    // Generated READMEs are kept when there is somewhere to keep them
+    let documents = match &args.documents_dir {
+        Some(dir) => Some(Arc::new(LocalDocumentStore::new(dir.clone())?) as Arc<dyn DocumentStore>),
+        None => None,
+    };
+    let retention = Retention {
+        max_documents: (args.documents_max > 0).then_some(args.documents_max),
+        max_age: (args.documents_ttl_secs > 0).then(|| Duration::from_secs(args.documents_ttl_secs)),
+    };
+    if let Some(documents) = &documents {
+        spawn_document_sweeper(Arc::clone(documents), retention);
+    }
+
+    let pipeline_metrics = Arc::new(Registry::default());
+    metrics::set_recorder(pipeline_metrics.clone());
+
+    // Create app state
+    let settings = Arc::new(Settings {
+        templates,
+        limits: RequestLimits {
+            max_file_size_kb: args.limit_file_size_kb,
+            max_total_size_mb: args.limit_total_size_mb,
+            max_files: args.limit_files,
+            max_tokens: args.limit_max_tokens,
+            walk_entries: args.limit_walk_entries,
+            walk_time: Duration::from_secs(args.limit_walk_secs),
+            models: args.models.iter().map(|m| m.trim().to_string()).filter(|m| !m.is_empty()).collect(),
+        },
+        paths: PathPolicy::new(&args.allowed_paths),
+        max_response_bytes: usize::try_from(args.max_response_mb.saturating_mul(1024 * 1024)).unwrap_or(usize::MAX),
+        slots: GenerationSlots::new(args.max_concurrent_generations, Duration::from_secs(args.generation_wait_secs)),
+        cache,
+        max_body_bytes: usize::try_from(args.max_body_kb.saturating_mul(1024)).unwrap_or(usize::MAX),
+        max_upload_bytes: args.max_upload_mb.saturating_mul(1024 * 1024),
+        request_timeout: Duration::from_secs(args.request_timeout_secs.max(1)),
+        shutdown: CancellationToken::new(),
+        pipeline_metrics,
+        remote_metadata: !args.no_remote_metadata,
+        workspace,
+        limiter: (args.rate_limit_per_minute > 0).then(|| {
+            Arc::new(ClientLimiter::new(args.rate_limit_per_minute, args.rate_limit_burst, args.trust_forwarded_for))
+        }),
+        documents,
+        retention,
+        provider_probe: ProviderProbe::spawn(Duration::from_secs(args.ready_probe_secs.max(1)), config.model.clone()),
+        min_free_bytes: args.ready_min_free_mb.saturating_mul(1024 * 1024),
+        started_at: Utc::now(),
+    });
+    let callbacks = args
+        .callback_secret
+        .clone()
+        .filter(|secret| !secret.is_empty())
+        .map(|secret| Arc::new(CallbackSender::new(secret, CallbackPolicy::new(&args.callback_allowed_hosts))));
+    let jobs = {
+        let settings = Arc::clone(&settings);
+        JobQueue::start(
+            args.job_workers,
+            args.job_queue_size,
+            Duration::from_secs(args.job_ttl_secs),
+            callbacks.clone(),
+            move |request: GenerateReadmeRequest, job: JobHandle<GenerateOutcome>| {
+                let settings = Arc::clone(&settings);
+                async move {
+                    // The job keeps its queued status until a slot frees up
+                    let _slot = match request.dry_run {
+                        true => None,
+                        false => Some(settings.slots.acquire_queued().await),
+                    };
+                    tracing::info!("Starting job {}", job.id());
+                    let on_status = |status| job.set_status(status);
+                    let on_progress = |event: &ProgressEvent| job.set_progress(event);
+                    let observer = Observer {
+                        status: &on_status,
+                        progress: &on_progress,
+                        text: None,
+                    };
+                    let outcome = generate(&settings, request, &observer, &settings.shutdown).await;
+                    match &outcome {
+                        Ok(_) => tracing::info!("Job {} done", job.id()),
+                        Err(e) => tracing::warn!("Job {} failed: {}", job.id(), e.message),
+                    }
+                    outcome.map_err(|e| e.message)
+                }
+            },
+        )
+    };
+    let webhook = args
+        .github_webhook_secret
+        .clone()
+        .filter(|secret| !secret.is_empty())
+        .map(|secret| {
+            Arc::new(GitHubWebhook {
+                secret,
+                dedupe_window: Duration::from_secs(args.github_webhook_dedupe_secs),
+                recent: Default::default(),
+            })
+        });
+    Ok(AppState {
+        settings,
+        jobs: Arc::new(jobs),
+        callbacks,
+        webhook,
+    })
+}
+
+/// Resolves on Ctrl-C or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("cannot listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("cannot listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    /// A server started with `flags`, allowed to read `project` and cloning into a
+    /// workspace of its own
+    struct TestServer {
+        app: Router,
+        state: AppState,
+        _workspace: TempDir,
+    }
+
+    impl TestServer {
+        fn start(project: &Path, flags: &[&str], tokens: &[&str]) -> Self {
+            let workspace = TempDir::new().unwrap();
+            let mut argv = vec!["techdocs-api".to_string(), "--allowed-paths".to_string()];
+            argv.push(project.display().to_string());
+            argv.push("--workspace-dir".to_string());
+            argv.push(workspace.path().display().to_string());
+            argv.extend(flags.iter().map(|flag| flag.to_string()));
+            let args = ServerArgs::try_parse_from(argv).unwrap();
+            let tokens = ApiTokens(Arc::new(tokens.iter().map(|token| token.to_string()).collect()));
+            let state = app_state(&args).unwrap();
+            let cors = cors_layer(&args.cors_origins).unwrap();
+            let app = router(state.clone(), tokens, cors);
+            Self { app, state, _workspace: workspace }
+        }
+
+        async fn send(&self, request: Request<Body>) -> (StatusCode, serde_json::Value) {
+            let response = self.app.clone().oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice(&body).unwrap_or_default())
+        }
+
+        async fn post(&self, uri: &str, body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+            let request = Request::post(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            self.send(request).await
+        }
+    }
+
+    /// A project with one small and one 3 KB file
+    fn project() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "Notes about the project\n").unwrap();
+        std::fs::write(dir.path().join("big.txt"), "a".repeat(3000)).unwrap();
+        dir
+    }
+
+    fn dry_run(project: &TempDir, overrides: serde_json::Value) -> serde_json::Value {
+        let mut body = serde_json::json!({"path_or_url": project.path(), "dry_run": true});
+        body.as_object_mut().unwrap().extend(overrides.as_object().unwrap().clone());
+        body
+    }
+
+    /// An allowed root holding a project, next to a directory the server must not read
+    fn roots() -> (TempDir, PathPolicy) {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("allowed/project")).unwrap();
+        std::fs::create_dir_all(dir.path().join("secret")).unwrap();
+        // Shares the allowed root's name as a string prefix, not as a path
+        std::fs::create_dir_all(dir.path().join("allowed-not")).unwrap();
+        std::fs::write(dir.path().join("secret/key.txt"), "hunter2\n").unwrap();
+        let policy = PathPolicy::new(&[dir.path().join("allowed")]);
+        (dir, policy)
+    }
+
+    #[test]
+    fn paths_under_an_allowed_root_are_canonicalized() {
+        let (dir, policy) = roots();
+        let project = dir.path().join("allowed/project");
+        let canonical = project.canonicalize().unwrap().display().to_string();
+        assert_eq!(policy.check(&project.display().to_string()), Ok(canonical.clone()));
+        let roundabout = dir.path().join("allowed/project/../project/.");
+        assert_eq!(policy.check(&roundabout.display().to_string()), Ok(canonical));
+        let url = "https://github.com/username/repo";
+        assert_eq!(policy.check(url), Ok(url.to_string()));
+    }
+
+    #[test]
+    fn traversal_out_of_an_allowed_root_is_refused() {
+        let (dir, policy) = roots();
+        for path in ["allowed/../secret", "allowed/project/../../secret", "allowed/../allowed-not"] {
+            let path = dir.path().join(path).display().to_string();
+            assert!(policy.check(&path).unwrap_err().contains("is not under a directory this server allows"), "{}", path);
+        }
+        assert!(policy.check("/etc").is_err());
+        assert!(PathPolicy::default().check(&dir.path().join("allowed/project").display().to_string()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_an_allowed_root_are_refused() {
+        let (dir, policy) = roots();
+        std::os::unix::fs::symlink(dir.path().join("secret"), dir.path().join("allowed/link")).unwrap();
+        let path = dir.path().join("allowed/link").display().to_string();
+        assert!(policy.check(&path).is_err());
+        // A root that is itself a link allows where it leads
+        std::os::unix::fs::symlink(dir.path().join("allowed"), dir.path().join("alias")).unwrap();
+        let policy = PathPolicy::new(&[dir.path().join("alias")]);
+        assert!(policy.check(&dir.path().join("allowed/project").display().to_string()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn forbidden_paths_get_403() {
+        let (dir, _) = roots();
+        let server = TestServer::start(&dir.path().join("allowed"), &[], &[]);
+        for path in ["secret", "allowed/../secret", "missing"] {
+            let body = serde_json::json!({"path_or_url": dir.path().join(path), "dry_run": true});
+            let (status, body) = server.post("/generate", body).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{}", path);
+            assert_eq!(body["code"], "path_forbidden");
+        }
+        let body = serde_json::json!({"path_or_url": dir.path().join("allowed/project"), "dry_run": true});
+        assert_eq!(server.post("/generate", body).await.0, StatusCode::OK);
+    }
+
+    /// A GET of a job that does not exist from `ip`, with `token` as its bearer token
+    fn from_client(ip: [u8; 4], token: &str) -> Request<Body> {
+        let mut request = Request::get("/jobs/missing")
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(ConnectInfo(SocketAddr::from((ip, 40000))));
+        request
+    }
+
+    #[tokio::test]
+    async fn made_up_tokens_do_not_escape_the_rate_limit() {
+        let project = project();
+        let flags = ["--rate-limit-per-minute", "1", "--rate-limit-burst", "2"];
+        let server = TestServer::start(project.path(), &flags, &[]);
+        let mut statuses = Vec::new();
+        for attempt in 0..4 {
+            let token = format!("made-up-{}", attempt);
+            statuses.push(server.send(from_client([203, 0, 113, 7], &token)).await.0);
+        }
+        assert_eq!(statuses[3], StatusCode::TOO_MANY_REQUESTS, "{:?}", statuses);
+        // Another address has its own allowance
+        let (status, _) = server.send(from_client([203, 0, 113, 8], "made-up")).await;
+        assert_ne!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn checked_tokens_are_limited_each_on_their_own() {
+        let project = project();
+        let flags = ["--rate-limit-per-minute", "1", "--rate-limit-burst", "1"];
+        let server = TestServer::start(project.path(), &flags, &["first", "second"]);
+        let ip = [203, 0, 113, 7];
+        assert_ne!(server.send(from_client(ip, "first")).await.0, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(server.send(from_client(ip, "first")).await.0, StatusCode::TOO_MANY_REQUESTS);
+        // The same address with the other token is another client
+        assert_ne!(server.send(from_client(ip, "second")).await.0, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(server.send(from_client(ip, "wrong")).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn wrong_tokens_are_limited_by_address() {
+        let project = project();
+        let flags = ["--rate-limit-per-minute", "1", "--rate-limit-burst", "2"];
+        let server = TestServer::start(project.path(), &flags, &["right"]);
+        let ip = [203, 0, 113, 7];
+        for attempt in 0..2 {
+            let token = format!("guess-{}", attempt);
+            assert_eq!(server.send(from_client(ip, &token)).await.0, StatusCode::UNAUTHORIZED);
+        }
+        let response = server.app.clone().oneshot(from_client(ip, "guess-2")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+        // The guesses do not count against the right token from the same address
+        assert_ne!(server.send(from_client(ip, "right")).await.0, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn allowance_comes_back_as_the_window_rolls_over() {
+        // One request a second, two at once
+        let limiter = ClientLimiter::new(60, 2, false);
+        let key = || ClientKey::Ip(IpAddr::from([203, 0, 113, 7]));
+        let start = Instant::now();
+        assert_eq!(limiter.check_at(key(), start), Ok(()));
+        assert_eq!(limiter.check_at(key(), start), Ok(()));
+        assert_eq!(limiter.check_at(key(), start), Err(Duration::from_secs(1)));
+        // Halfway to the next slot the client still waits, for the rest of it
+        let half = start + Duration::from_millis(500);
+        assert_eq!(limiter.check_at(key(), half), Err(Duration::from_millis(500)));
+        // One interval later there is room for one more, and no more
+        let next = start + Duration::from_secs(1);
+        assert_eq!(limiter.check_at(key(), next), Ok(()));
+        assert!(limiter.check_at(key(), next).is_err());
+        // Idle for a whole window, the full burst is back
+        let later = start + Duration::from_secs(60);
+        assert_eq!(limiter.check_at(key(), later), Ok(()));
+        assert_eq!(limiter.check_at(key(), later), Ok(()));
+        assert!(limiter.check_at(key(), later).is_err());
+        assert_eq!(limiter.rejected_ips.load(Ordering::Relaxed), 4);
+    }
+
+    #[tokio::test]
+    async fn file_urls_on_the_server_network_are_not_fetched() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        for url in ["https://127.0.0.1/notes.md", "https://169.254.169.254/latest/meta-data.txt"] {
+            let body = serde_json::json!({"path_or_url": url, "dry_run": true});
+            let (status, body) = server.post("/generate", body).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", url);
+            assert_eq!(body["code"], "invalid_url");
+            assert!(body["error"].as_str().unwrap().contains("non-public address"), "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_sources_get_422() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        for source in ["", "   ", "\n", "\"\""] {
+            let body = serde_json::json!({"path_or_url": source, "dry_run": true});
+            let (status, body) = server.post("/generate", body).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{:?}", source);
+            assert_eq!(body["code"], "invalid_url");
+        }
+        // Quotes and a trailing newline around an allowed path are dropped
+        let body = serde_json::json!({"path_or_url": format!("\"{}\"\n", project.path().display()), "dry_run": true});
+        assert_eq!(server.post("/generate", body).await.0, StatusCode::OK);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn files_linked_from_outside_the_project_are_not_sent() {
+        let project = project();
+        let outside = TempDir::new().unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "hunter2\n").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret.txt"), project.path().join("leak.txt")).unwrap();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({}))).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let content = body["content"].as_str().unwrap();
+        assert!(content.contains("Notes about the project"));
+        assert!(!content.contains("hunter2"));
+    }
+
+    fn files_request(project: &TempDir, token: Option<&str>) -> Request<Body> {
+        let mut request = Request::post("/files").header(header::CONTENT_TYPE, "application/json");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, token);
+        }
+        request
+            .body(Body::from(serde_json::json!({"path_or_url": project.path()}).to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn requests_without_a_valid_token_are_refused() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &["first-token", "second-token"]);
+        for token in [None, Some("Bearer wrong-token"), Some("Bearer first"), Some("first-token"), Some("Basic first-token")] {
+            let response = server.app.clone().oneshot(files_request(&project, token)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{:?}", token);
+            assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["code"], "unauthorized");
+            assert!(!body["error"].as_str().unwrap().contains("token-"), "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn any_configured_token_is_accepted() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &["first-token", "second-token"]);
+        for token in ["Bearer first-token", "Bearer second-token"] {
+            let (status, body) = server.send(files_request(&project, Some(token))).await;
+            assert_eq!(status, StatusCode::OK, "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn health_needs_no_token() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &["first-token"]);
+        let response = server.app.clone().oneshot(Request::get("/health").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn server_details_need_a_token() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &["first-token"]);
+        for uri in ["/ready", "/version", "/openapi.json", "/docs", "/metrics"] {
+            let response = server.app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", uri);
+            let request = Request::get(uri).header(header::AUTHORIZATION, "Bearer first-token").body(Body::empty()).unwrap();
+            let response = server.app.clone().oneshot(request).await.unwrap();
+            assert_ne!(response.status(), StatusCode::UNAUTHORIZED, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn without_tokens_every_request_is_served() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let (status, body) = server.send(files_request(&project, None)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    /// A zip archive of `entries` of name and content, in memory
+    fn zip_bytes(entries: &[(&str, &str)]) -> Vec<u8> {
+        use std::io::Write;
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    async fn upload(server: &TestServer, archive: Vec<u8>) -> (StatusCode, serde_json::Value) {
+        let request = Request::post("/generate/upload?request=%7B%22dry_run%22%3Atrue%7D")
+            .header(header::CONTENT_TYPE, "application/zip")
+            .body(Body::from(archive))
+            .unwrap();
+        server.send(request).await
+    }
+
+    #[tokio::test]
+    async fn uploaded_archives_are_extracted_and_collected() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let (status, body) = upload(&server, zip_bytes(&[("repo-main/notes.txt", "Uploaded notes\n")])).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["files_included"].as_u64(), Some(1));
+        assert!(body["content"].as_str().unwrap().contains("Uploaded notes"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn archives_with_entries_outside_their_directory_are_refused() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        for name in ["../evil", "/etc/evil"] {
+            let (status, body) = upload(&server, zip_bytes(&[("notes.txt", "notes\n"), (name, "pwned\n")])).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+            assert_eq!(body["code"], "invalid_archive");
+        }
+        let (status, body) = upload(&server, b"not an archive".to_vec()).await;
+        assert_eq!((status, &body["code"]), (StatusCode::UNPROCESSABLE_ENTITY, &serde_json::json!("invalid_archive")));
+    }
+
+    #[tokio::test]
+    async fn uploads_over_the_size_limit_get_413() {
+        let project = project();
+        let server = TestServer::start(project.path(), &["--max-upload-mb", "1"], &[]);
+        let (status, _) = upload(&server, vec![0; 2 * 1024 * 1024]).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    /// A self-signed certificate for `localhost` and its key, as PEM files in `dir`
+    fn certificate(dir: &Path, name: &str) -> (PathBuf, PathBuf, rcgen::CertifiedKey) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let (cert, key) = (dir.join(format!("{}.pem", name)), dir.join(format!("{}.key", name)));
+        std::fs::write(&cert, certified.cert.pem()).unwrap();
+        std::fs::write(&key, certified.key_pair.serialize_pem()).unwrap();
+        (cert, key, certified)
+    }
+
+    #[test]
+    fn tls_files_that_cannot_be_served_are_refused_at_startup() {
+        let dir = TempDir::new().unwrap();
+        let (cert, key, _) = certificate(dir.path(), "server");
+        let (_, other_key, _) = certificate(dir.path(), "other");
+        assert!(load_tls_config(&cert, &key).is_ok());
+
+        let error = load_tls_config(&cert, &other_key).unwrap_err().to_string();
+        assert!(error.contains("does not fit certificate"), "{}", error);
+        let error = load_tls_config(&dir.path().join("missing.pem"), &key).unwrap_err().to_string();
+        assert!(error.contains("cannot load TLS file") && error.contains("missing.pem"), "{}", error);
+        let error = load_tls_config(&key, &key).unwrap_err().to_string();
+        assert!(error.contains("no PEM certificate"), "{}", error);
+        let error = load_tls_config(&cert, &cert).unwrap_err().to_string();
+        assert!(error.contains("no PEM private key"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn requests_are_served_over_tls_on_the_configured_address() {
+        let dir = TempDir::new().unwrap();
+        let (cert, key, certified) = certificate(dir.path(), "server");
+        let server = TestServer::start(dir.path(), &[], &[]);
+        let args = ServerArgs::try_parse_from(["techdocs-api", "--bind", "127.0.0.1:0"]).unwrap();
+        let listener = tokio::net::TcpListener::bind(args.bind).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let tls = axum_server::tls_rustls::RustlsConfig::from_config(load_tls_config(&cert, &key).unwrap());
+        let _server = serve(listener, server.app, Some(tls), std::future::pending()).unwrap();
+
+        let response = tokio::task::spawn_blocking(move || {
+            use std::io::{Read, Write};
+            let mut roots = rustls::RootCertStore::empty();
+            roots.add(certified.cert.der().clone()).unwrap();
+            let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let connection = rustls::ClientConnection::new(Arc::new(config), "localhost".try_into().unwrap()).unwrap();
+            let mut stream = rustls::StreamOwned::new(connection, std::net::TcpStream::connect(addr).unwrap());
+            stream.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+            let mut response = String::new();
+            // The server may close without a TLS close_notify once the response is sent
+            let _ = stream.read_to_string(&mut response);
+            response
+        })
+        .await
+        .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    }
+
+    #[test]
+    fn the_listen_address_defaults_to_loopback() {
+        let args = ServerArgs::try_parse_from(["techdocs-api"]).unwrap();
+        assert_eq!(args.bind, SocketAddr::from(([127, 0, 0, 1], 3000)));
+        let args = ServerArgs::try_parse_from(["techdocs-api", "--bind", "0.0.0.0:8443"]).unwrap();
+        assert_eq!(args.bind, SocketAddr::from(([0, 0, 0, 0], 8443)));
+        assert!(ServerArgs::try_parse_from(["techdocs-api", "--bind", "localhost"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn only_what_is_returned_is_held_to_the_response_size() {
+        let project = TempDir::new().unwrap();
+        for index in 0..12 {
+            std::fs::write(project.path().join(format!("part{:02}.txt", index)), "a".repeat(100 * 1024)).unwrap();
+        }
+        let server = TestServer::start(project.path(), &["--max-response-mb", "1"], &[]);
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({}))).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["code"], "response_too_large");
+
+        // A real run never returns the prompt; the cost ceiling stops it before Claude is called
+        let body = serde_json::json!({"path_or_url": project.path(), "max_cost_usd": 0.000001});
+        let (status, body) = server.post("/generate", body).await;
+        assert_ne!(status, StatusCode::PAYLOAD_TOO_LARGE, "{}", body);
+        assert_ne!(body["code"], "response_too_large");
+    }
+
+    #[tokio::test]
+    async fn generate_uses_the_default_limits() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({}))).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!((body["files_included"].as_u64(), body["files_skipped"].as_u64()), (Some(2), Some(0)));
+    }
+
+    #[tokio::test]
+    async fn generate_honors_overrides_within_the_ceilings() {
+        let project = project();
+        let server = TestServer::start(project.path(), &["--limit-file-size-kb", "10"], &[]);
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({"max_file_size_kb": 1}))).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!((body["files_included"].as_u64(), body["files_skipped"].as_u64()), (Some(1), Some(1)));
+    }
+
+    #[tokio::test]
+    async fn generate_refuses_overrides_past_the_ceilings() {
+        let project = project();
+        let server = TestServer::start(project.path(), &["--limit-file-size-kb", "10", "--models", "claude-a"], &[]);
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({"max_file_size_kb": 11}))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "max_file_size_kb must be between 1 and 10, got 11");
+
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({"model": "claude-b"}))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "model must be one of: claude-a");
+    }
+
+    #[tokio::test]
+    async fn project_configuration_cannot_raise_the_ceilings() {
+        let project = project();
+        let server = TestServer::start(project.path(), &["--limit-file-size-kb", "10", "--models", "claude-a"], &[]);
+
+        std::fs::write(project.path().join(".techdocs.toml"), "max_file_size_kb = 1000000\n").unwrap();
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({}))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "the project's .techdocs.toml: max_file_size_kb must be between 1 and 10, got 1000000");
+
+        std::fs::write(project.path().join(".techdocs.toml"), "max_total_size_mb = 100000\n").unwrap();
+        let (status, body) = server.post("/prompt", dry_run(&project, serde_json::json!({}))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["error"].as_str().unwrap().contains("max_total_size_mb must be between 1 and 50"), "{}", body);
+
+        std::fs::write(project.path().join(".techdocs.toml"), "model = \"claude-b\"\n").unwrap();
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({}))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "the project's .techdocs.toml: model must be one of: claude-a");
+        let (status, body) = server.post("/summary", serde_json::json!({"path_or_url": project.path()})).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "the project's .techdocs.toml: model must be one of: claude-a");
+    }
+
+    #[tokio::test]
+    async fn summary_is_held_to_the_request_limits_and_cost() {
+        let project = project();
+        std::fs::write(project.path().join("README.md"), "# Notes\n").unwrap();
+        let server = TestServer::start(project.path(), &[], &[]);
+
+        let body = serde_json::json!({"path_or_url": project.path(), "max_cost_usd": 0.000001});
+        let (status, body) = server.post("/summary", body).await;
+        assert_eq!(status, StatusCode::PAYMENT_REQUIRED, "{}", body);
+        assert_eq!(body["code"], "cost_limit_exceeded");
+
+        let body = serde_json::json!({"path_or_url": project.path(), "max_cost_usd": -1.0});
+        let (status, body) = server.post("/summary", body).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "max_cost_usd must be a positive number");
+    }
+
+    #[tokio::test]
+    async fn project_configuration_may_tighten_the_limits() {
+        let project = project();
+        std::fs::write(project.path().join(".techdocs.toml"), "max_file_size_kb = 1\nmodel = \"claude-a\"\n").unwrap();
+        let server = TestServer::start(project.path(), &["--limit-file-size-kb", "10", "--models", "claude-a"], &[]);
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({}))).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["files_skipped"].as_u64(), Some(1));
+    }
+
+    /// The events of a /generate/stream response, by name and data
+    async fn stream_events(server: &TestServer, body: serde_json::Value) -> Vec<(String, serde_json::Value)> {
+        let request = Request::post("/generate/stream")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = server.app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/event-stream");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec())
+            .unwrap()
+            .split("\n\n")
+            .filter_map(|event| {
+                let name = event.lines().find_map(|line| line.strip_prefix("event: "))?;
+                let data = event.lines().find_map(|line| line.strip_prefix("data: ")).unwrap_or("null");
+                Some((name.to_string(), serde_json::from_str(data).unwrap()))
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn streams_report_collecting_then_the_result() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let events = stream_events(&server, dry_run(&project, serde_json::json!({"max_file_size_kb": 1}))).await;
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["collecting", "done"], "{:?}", events);
+        assert_eq!(events[0].1, serde_json::json!({"files_included": 1, "files_skipped": 1}));
+        assert_eq!(events[1].1["files_included"], 1);
+        assert!(events[1].1["content"].as_str().unwrap().contains("Notes about the project"));
+    }
+
+    #[tokio::test]
+    async fn stream_failures_end_with_an_error_event() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        // The cost ceiling stops the generation before Claude is called
+        let body = serde_json::json!({"path_or_url": project.path(), "max_cost_usd": 0.000001});
+        let events = stream_events(&server, body).await;
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["collecting", "generating", "error"], "{:?}", events);
+        let error = &events[2].1;
+        assert_eq!(error["code"], "cost_limit_exceeded");
+        assert!(!error["request_id"].as_str().unwrap().is_empty(), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn streams_refuse_bad_requests_before_streaming() {
+        let (dir, _) = roots();
+        let flags = ["--generation-wait-secs", "0", "--max-concurrent-generations", "1"];
+        let server = TestServer::start(&dir.path().join("allowed"), &flags, &[]);
+        let body = serde_json::json!({"path_or_url": dir.path().join("secret")});
+        let (status, body) = server.post("/generate/stream", body).await;
+        assert_eq!((status, &body["code"]), (StatusCode::FORBIDDEN, &serde_json::json!("path_forbidden")));
+
+        // A busy server answers 429 instead of an event stream
+        let _slot = server.state.settings.slots.acquire().await.unwrap();
+        let body = serde_json::json!({"path_or_url": dir.path().join("allowed/project")});
+        let (status, body) = server.post("/generate/stream", body).await;
+        assert_eq!((status, &body["code"]), (StatusCode::TOO_MANY_REQUESTS, &serde_json::json!("busy")));
+    }
+
+    #[tokio::test]
+    async fn work_cancelled_at_shutdown_gets_503() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        server.state.settings.shutdown.cancel();
+        let body = serde_json::json!({"path_or_url": project.path(), "max_cost_usd": 0.000001});
+        let (status, body) = server.post("/generate", body).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+        assert_eq!(body["code"], "cancelled");
+    }
+
+    #[tokio::test]
+    async fn jobs_left_at_shutdown_are_marked_failed() {
+        let project = project();
+        let flags = ["--max-concurrent-generations", "1"];
+        let server = TestServer::start(project.path(), &flags, &[]);
+        // The job waits for the slot this test holds
+        let _slot = server.state.settings.slots.acquire().await.unwrap();
+        let (status, job) = server.post("/jobs", serde_json::json!({"path_or_url": project.path()})).await;
+        assert_eq!(status, StatusCode::ACCEPTED, "{}", job);
+
+        assert_eq!(server.state.jobs.shutdown(Duration::from_millis(50)).await, 1);
+        let request = Request::get(format!("/jobs/{}", job["id"].as_str().unwrap())).body(Body::empty()).unwrap();
+        let (status, job) = server.send(request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(job["status"], "failed");
+        assert_eq!(job["error"], "interrupted by server shutdown");
+    }
+
+    async fn metrics(server: &TestServer) -> String {
+        let response = server.app.clone().oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        String::from_utf8(to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_busy_server_answers_429_with_retry_after() {
+        let project = project();
+        let flags = ["--max-concurrent-generations", "1", "--generation-wait-secs", "0"];
+        let server = TestServer::start(project.path(), &flags, &[]);
+        let _slot = server.state.settings.slots.acquire().await.unwrap();
+
+        let request = Request::post("/generate")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::json!({"path_or_url": project.path()}).to_string()))
+            .unwrap();
+        let response = server.app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["code"], "busy");
+
+        // Cheap requests do not need a slot
+        let health = server.app.clone().oneshot(Request::get("/health").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(health.status(), StatusCode::OK);
+        assert_eq!(server.post("/generate", dry_run(&project, serde_json::json!({}))).await.0, StatusCode::OK);
+        assert_eq!(server.post("/prompt", serde_json::json!({"path_or_url": project.path()})).await.0, StatusCode::OK);
+
+        let metrics = metrics(&server).await;
+        assert!(metrics.contains("\ntechdocs_generation_slots 1\n"), "{}", metrics);
+        assert!(metrics.contains("\ntechdocs_generations_in_flight 1\n"), "{}", metrics);
+        assert!(metrics.contains("\ntechdocs_generations_rejected_total 1\n"), "{}", metrics);
+    }
+
+    #[tokio::test]
+    async fn waiting_requests_get_the_next_free_slot() {
+        let project = project();
+        let flags = ["--max-concurrent-generations", "1", "--generation-wait-secs", "30"];
+        let server = TestServer::start(project.path(), &flags, &[]);
+        let slot = server.state.settings.slots.acquire().await.unwrap();
+
+        // The cost ceiling stops the generation once it has a slot, before Claude is called
+        let body = serde_json::json!({"path_or_url": project.path(), "max_cost_usd": 0.000001});
+        let waiting = tokio::spawn({
+            let request = Request::post("/generate")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            server.app.clone().oneshot(request)
+        });
+        while server.state.settings.slots.waiting.load(Ordering::Relaxed) == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(metrics(&server).await.contains("\ntechdocs_generations_waiting 1\n"));
+
+        drop(slot);
+        let response = waiting.await.unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+        assert!(metrics(&server).await.contains("\ntechdocs_generations_waiting 0\n"));
+    }
+
+    /// A README response as the pipeline would produce it
+    fn readme_response(readme: &str) -> GenerateReadmeResponse {
+        serde_json::from_value(serde_json::json!({"readme": readme, "usage": Usage::default()})).unwrap()
+    }
+
+    #[tokio::test]
+    async fn clearing_the_cache_reports_what_was_removed() {
+        let project = project();
+        let server = TestServer::start(project.path(), &["--cache-entries", "10"], &["token"]);
+        let cache = server.state.settings.cache.as_ref().unwrap();
+        cache.insert("first", readme_response("# First"));
+        cache.insert("second", readme_response("# Second"));
+
+        let clear = || Request::delete("/cache").header(header::AUTHORIZATION, "Bearer token").body(Body::empty()).unwrap();
+        let (status, body) = server.send(clear()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, serde_json::json!({"entries_removed": 2}));
+        assert_eq!(cache.get("first").map(|hit| hit.readme), None);
+        assert_eq!(server.send(clear()).await.1, serde_json::json!({"entries_removed": 0}));
+
+        let (status, _) = server.send(Request::delete("/cache").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn clearing_without_a_cache_removes_nothing() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let (status, body) = server.send(Request::delete("/cache").body(Body::empty()).unwrap()).await;
+        assert_eq!((status, body), (StatusCode::OK, serde_json::json!({"entries_removed": 0})));
+    }
+
+    fn json_request(uri: &str, body: impl Into<Body>) -> Request<Body> {
+        Request::post(uri).header(header::CONTENT_TYPE, "application/json").body(body.into()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn failures_get_a_code_status_and_request_id() {
+        let project = project();
+        let server = TestServer::start(project.path(), &["--limit-walk-entries", "1"], &[]);
+        let generate = |overrides: serde_json::Value| {
+            let mut body = serde_json::json!({"path_or_url": project.path()});
+            body.as_object_mut().unwrap().extend(overrides.as_object().unwrap().clone());
+            json_request("/generate", body.to_string())
+        };
+        let cases = [
+            (json_request("/generate", "{not json"), StatusCode::BAD_REQUEST, "invalid_request"),
+            (Request::post("/generate").body(Body::from("{}")).unwrap(), StatusCode::UNSUPPORTED_MEDIA_TYPE, "invalid_request"),
+            (generate(serde_json::json!({"max_files": 0})), StatusCode::UNPROCESSABLE_ENTITY, "invalid_request"),
+            (generate(serde_json::json!({"path_or_url": "/etc"})), StatusCode::FORBIDDEN, "path_forbidden"),
+            (generate(serde_json::json!({"path_or_url": "ftp://example.com/repo"})), StatusCode::BAD_REQUEST, "invalid_url"),
+            (generate(serde_json::json!({"dry_run": true})), StatusCode::PAYLOAD_TOO_LARGE, "source_too_large"),
+            (Request::get("/jobs/missing").body(Body::empty()).unwrap(), StatusCode::NOT_FOUND, "not_found"),
+            (Request::get("/no/such/route").body(Body::empty()).unwrap(), StatusCode::NOT_FOUND, "not_found"),
+        ];
+        for (request, status, code) in cases {
+            let uri = request.uri().clone();
+            let response = server.app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), status, "{}", uri);
+            let id = response.headers()["x-request-id"].to_str().unwrap().to_string();
+            let body: serde_json::Value = serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+            assert_eq!(body["code"], code, "{}: {}", uri, body);
+            assert_eq!(body["request_id"], id.as_str(), "{}", uri);
+            assert!(!body["error"].as_str().unwrap().is_empty(), "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn the_cost_ceiling_gets_402() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let body = serde_json::json!({"path_or_url": project.path(), "max_cost_usd": 0.000001});
+        let (status, body) = server.post("/generate", body).await;
+        assert_eq!(status, StatusCode::PAYMENT_REQUIRED, "{}", body);
+        assert_eq!(body["code"], "cost_limit_exceeded");
+    }
+
+    const WEBHOOK_SECRET: &str = "webhook-secret";
+
+    /// A GitHub delivery of `event` with `payload`, signed with `secret`
+    fn delivery(event: &str, payload: &serde_json::Value, secret: &str) -> Request<Body> {
+        let body = payload.to_string();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body.as_bytes());
+        let signature: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+        Request::post("/webhooks/github")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("x-github-event", event)
+            .header("x-hub-signature-256", format!("sha256={}", signature))
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    fn push(git_ref: &str, after: &str) -> serde_json::Value {
+        serde_json::json!({
+            "ref": git_ref,
+            "after": after,
+            "deleted": false,
+            "repository": {
+                "full_name": "username/repo",
+                "html_url": "https://github.com/username/repo",
+                "default_branch": "main",
+            },
+        })
+    }
+
+    /// A server with a webhook secret whose single generation slot is held by the
+    /// returned permit, so queued jobs stay queued
+    async fn webhook_server(project: &TempDir) -> (TestServer, OwnedSemaphorePermit) {
+        let flags = ["--github-webhook-secret", WEBHOOK_SECRET, "--max-concurrent-generations", "1"];
+        let server = TestServer::start(project.path(), &flags, &["token"]);
+        let slot = server.state.settings.slots.acquire().await.unwrap();
+        (server, slot)
+    }
+
+    #[tokio::test]
+    async fn webhook_deliveries_must_be_signed_with_the_secret() {
+        let project = project();
+        let (server, _slot) = webhook_server(&project).await;
+        let payload = push("refs/heads/main", "abc123");
+        let mut unsigned = delivery("push", &payload, WEBHOOK_SECRET);
+        unsigned.headers_mut().remove("x-hub-signature-256");
+        let mut unprefixed = delivery("push", &payload, WEBHOOK_SECRET);
+        let signature = unprefixed.headers()["x-hub-signature-256"].to_str().unwrap().trim_start_matches("sha256=").to_string();
+        unprefixed.headers_mut().insert("x-hub-signature-256", signature.parse().unwrap());
+        let mut tampered = delivery("push", &payload, WEBHOOK_SECRET);
+        *tampered.body_mut() = Body::from(push("refs/heads/main", "def456").to_string());
+
+        for request in [delivery("push", &payload, "wrong-secret"), unsigned, unprefixed, tampered] {
+            let (status, body) = server.send(request).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", body);
+            assert_eq!(body["code"], "unauthorized");
+        }
+    }
+
+    #[tokio::test]
+    async fn webhooks_are_off_without_a_secret() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let (status, body) = server.send(delivery("push", &push("refs/heads/main", "abc123"), WEBHOOK_SECRET)).await;
+        assert_eq!((status, &body["code"]), (StatusCode::NOT_FOUND, &serde_json::json!("not_found")));
+    }
+
+    #[tokio::test]
+    async fn other_events_and_branches_are_ignored() {
+        let project = project();
+        let (server, _slot) = webhook_server(&project).await;
+        let mut deleted = push("refs/heads/main", "0000000");
+        deleted["deleted"] = true.into();
+        let cases = [
+            (delivery("ping", &serde_json::json!({"zen": "Keep it simple."}), WEBHOOK_SECRET), "ping events are not handled"),
+            (
+                delivery("push", &push("refs/heads/feature", "abc123"), WEBHOOK_SECRET),
+                "refs/heads/feature is not the default branch of username/repo",
+            ),
+            (delivery("push", &deleted, WEBHOOK_SECRET), "refs/heads/main is not the default branch of username/repo"),
+        ];
+        for (request, reason) in cases {
+            let (status, body) = server.send(request).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body, serde_json::json!({"ignored": reason}));
+        }
+
+        let (status, body) = server.send(delivery("push", &serde_json::json!({"ref": "refs/heads/main"}), WEBHOOK_SECRET)).await;
+        assert_eq!((status, &body["code"]), (StatusCode::BAD_REQUEST, &serde_json::json!("invalid_request")));
+    }
+
+    #[tokio::test]
+    async fn pushes_to_the_default_branch_queue_one_job() {
+        let project = project();
+        let (server, _slot) = webhook_server(&project).await;
+        // The signature takes the place of the bearer token
+        let (status, first) = server.send(delivery("push", &push("refs/heads/main", "abc123"), WEBHOOK_SECRET)).await;
+        assert_eq!(status, StatusCode::ACCEPTED, "{}", first);
+        assert_eq!(first["status"], "queued");
+
+        // A push while the job still waits moves it instead of queuing another
+        let (status, second) = server.send(delivery("push", &push("refs/heads/main", "def456"), WEBHOOK_SECRET)).await;
+        assert_eq!(status, StatusCode::ACCEPTED, "{}", second);
+        assert_eq!(second["id"], first["id"]);
+    }
+
+    async fn get_json(server: &TestServer, uri: &str, token: Option<&str>) -> serde_json::Value {
+        let mut request = Request::get(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let (status, body) = server.send(request.body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK, "{}: {}", uri, body);
+        body
+    }
+
+    #[tokio::test]
+    async fn the_openapi_document_describes_generate() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let spec = get_json(&server, "/openapi.json", None).await;
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3."), "{}", spec["openapi"]);
+        let responses = spec["paths"]["/generate"]["post"]["responses"].as_object().unwrap();
+        for status in ["200", "400", "403", "413", "422", "429", "502"] {
+            assert!(responses.contains_key(status), "{}: {:?}", status, responses.keys().collect::<Vec<_>>());
+        }
+        for schema in ["GenerateReadmeRequest", "GenerateReadmeResponse", "ErrorResponse"] {
+            assert!(spec["components"]["schemas"][schema].is_object(), "{}", schema);
+        }
+        for path in ["/generate/stream", "/jobs", "/jobs/{id}", "/ready", "/version", "/webhooks/github"] {
+            assert!(spec["paths"][path].is_object(), "{}", path);
+        }
+        // Without tokens nothing requires authentication
+        assert!(spec.get("security").is_none(), "{}", spec["security"]);
+    }
+
+    #[tokio::test]
+    async fn the_openapi_document_declares_token_auth() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &["token"]);
+        let spec = get_json(&server, "/openapi.json", Some("token")).await;
+        assert_eq!(spec["security"], serde_json::json!([{"bearerAuth": []}]));
+        assert_eq!(spec["components"]["securitySchemes"]["bearerAuth"]["scheme"], "bearer");
+        // Routes that take no token say so
+        assert_eq!(spec["paths"]["/health"]["get"]["security"], serde_json::json!([{}]));
+        assert_eq!(spec["paths"]["/webhooks/github"]["post"]["security"], serde_json::json!([{}]));
+    }
+
+    #[tokio::test]
+    async fn the_docs_page_renders_the_openapi_document() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let response = server.app.clone().oneshot(Request::get("/docs").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/html"));
+        let page = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&page).contains(r#"spec-url="/openapi.json""#));
+    }
+
+    #[tokio::test]
+    async fn bodies_over_the_limit_get_413() {
+        let project = project();
+        let server = TestServer::start(project.path(), &["--max-body-kb", "1"], &[]);
+        let body = serde_json::json!({"path_or_url": project.path(), "exclude_patterns": ["x".repeat(2048)], "dry_run": true});
+        let (status, body) = server.post("/generate", body).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["code"], "payload_too_large");
+        assert_eq!(server.post("/generate", dry_run(&project, serde_json::json!({}))).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn slow_requests_get_408_and_leave_nothing_behind() {
+        let project = project();
+        let flags = ["--request-timeout-secs", "1", "--max-concurrent-generations", "1", "--generation-wait-secs", "30"];
+        let server = TestServer::start(project.path(), &flags, &[]);
+        // The upload is extracted, then waits for a slot past the timeout
+        let _slot = server.state.settings.slots.acquire().await.unwrap();
+        let request = Request::post("/generate/upload")
+            .header(header::CONTENT_TYPE, "application/zip")
+            .body(Body::from(zip_bytes(&[("notes.txt", "notes\n")])))
+            .unwrap();
+        let (status, body) = server.send(request).await;
+        assert_eq!(status, StatusCode::REQUEST_TIMEOUT, "{}", body);
+        assert_eq!(body["code"], "timeout");
+        assert_eq!(body["error"], "the request took longer than this server's limit of 1s");
+
+        let workspace = &server.state.settings.workspace;
+        assert_eq!(std::fs::read_dir(workspace.root()).unwrap().count(), 0);
+        assert_eq!(workspace.used_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_full_workspace_gets_507() {
+        let project = project();
+        let server = TestServer::start(project.path(), &["--workspace-quota-mb", "0"], &[]);
+        let (status, body) = upload(&server, zip_bytes(&[("notes.txt", "notes\n")])).await;
+        assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE, "{}", body);
+        assert_eq!(body["code"], "insufficient_storage");
+        assert_eq!(body["error"], "Workspace full: the 0 MB of clone space on this server is in use; try again later");
+
+        let metrics = metrics(&server).await;
+        assert!(metrics.contains("\ntechdocs_workspace_used_bytes 0\n"), "{}", metrics);
+        assert!(metrics.contains("\ntechdocs_workspace_quota_bytes 0\n"), "{}", metrics);
+    }
+
+    #[tokio::test]
+    async fn uploads_past_the_clone_cap_are_refused_and_removed() {
+        let project = project();
+        let server = TestServer::start(project.path(), &["--max-clone-mb", "0"], &[]);
+        let (status, body) = upload(&server, zip_bytes(&[("notes.txt", "notes\n")])).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE, "{}", body);
+        assert_eq!(body["code"], "budget_exceeded");
+        let workspace = &server.state.settings.workspace;
+        assert_eq!(std::fs::read_dir(workspace.root()).unwrap().count(), 0);
+        assert_eq!(workspace.used_bytes(), 0);
+    }
+
+    fn preflight(origin: &str) -> Request<Body> {
+        Request::options("/generate")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization,content-type")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn preflights_from_allowed_origins_skip_authentication() {
+        let project = project();
+        let server =
+            TestServer::start(project.path(), &["--cors-origins", "https://docs.example.com"], &["secret-token"]);
+        let response = server.app.clone().oneshot(preflight("https://docs.example.com")).await.unwrap();
+        assert!(response.status().is_success(), "{}", response.status());
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://docs.example.com");
+        let methods = headers[header::ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap();
+        assert!(methods.contains("POST"), "{}", methods);
+        let allowed = headers[header::ACCESS_CONTROL_ALLOW_HEADERS].to_str().unwrap();
+        assert!(allowed.contains("authorization"), "{}", allowed);
+
+        // The real request still needs its token, and exposes the headers clients read
+        let request = Request::get("/health").header(header::ORIGIN, "https://docs.example.com").body(Body::empty()).unwrap();
+        let response = server.app.clone().oneshot(request).await.unwrap();
+        let exposed = response.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS].to_str().unwrap();
+        assert!(exposed.contains("retry-after") && exposed.contains("x-request-id"), "{}", exposed);
+        let (status, _) = server.post("/generate", dry_run(&project, serde_json::json!({}))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn other_origins_get_no_cors_headers() {
+        let project = project();
+        let server = TestServer::start(project.path(), &["--cors-origins", "https://docs.example.com"], &[]);
+        let response = server.app.clone().oneshot(preflight("https://evil.example.com")).await.unwrap();
+        assert!(!response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let server = TestServer::start(project.path(), &[], &[]);
+        let response = server.app.clone().oneshot(preflight("https://docs.example.com")).await.unwrap();
+        assert!(!response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[tokio::test]
+    async fn any_origin_is_allowed_with_a_star() {
+        let project = project();
+        let server = TestServer::start(project.path(), &["--cors-origins", "*"], &[]);
+        let response = server.app.clone().oneshot(preflight("https://anywhere.example.com")).await.unwrap();
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    #[test]
+    fn invalid_cors_origins_are_a_config_error() {
+        let error = cors_layer(&["https://ok.example.com".to_string(), "bad\norigin".to_string()]).unwrap_err();
+        assert!(matches!(&error, TechDocsError::Config(message) if message.contains("invalid CORS origin")), "{}", error);
+        assert!(cors_layer(&[" ".to_string()]).unwrap().is_none());
+    }
+
+    /// A prompt directory with one template, `readme-minimal`
+    fn prompts() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("readme-minimal.txt"), "Write a three-line README.").unwrap();
+        std::fs::write(dir.path().join("notes.md"), "Not a template").unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn dry_runs_use_the_named_template() {
+        let (project, prompts) = (project(), prompts());
+        let dir = prompts.path().display().to_string();
+        let server = TestServer::start(project.path(), &["--prompts-dir", &dir], &[]);
+
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({"template": "readme-minimal"}))).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["template"], "readme-minimal");
+        assert!(body["system_prompt"].as_str().unwrap().contains("Write a three-line README."), "{}", body);
+
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({}))).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["template"], DEFAULT_TEMPLATE);
+
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({"template": "notes"}))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+        let error = body["error"].as_str().unwrap();
+        assert!(error.starts_with("template must be one of: ") && error.contains("readme-minimal"), "{}", error);
+        assert!(!error.contains("notes"), "{}", error);
+    }
+
+    fn reload(token: Option<&str>) -> Request<Body> {
+        let mut request = Request::post("/admin/reload-prompts");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reloading_picks_up_new_templates() {
+        let (project, prompts) = (project(), prompts());
+        let dir = prompts.path().display().to_string();
+        let server = TestServer::start(project.path(), &["--prompts-dir", &dir], &["admin-token"]);
+        std::fs::write(prompts.path().join("changelog.txt"), "Write a changelog.").unwrap();
+
+        let (status, _) = server.send(reload(None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, body) = server.send(reload(Some("admin-token"))).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let templates: Vec<&str> = body["templates"].as_array().unwrap().iter().map(|name| name.as_str().unwrap()).collect();
+        assert!(templates.contains(&"changelog") && templates.contains(&"readme-minimal"), "{:?}", templates);
+        assert!(templates.contains(&DEFAULT_TEMPLATE), "{:?}", templates);
+    }
+
+    #[tokio::test]
+    async fn a_failed_reload_keeps_the_current_templates() {
+        let (project, prompts) = (project(), prompts());
+        let dir = prompts.path().display().to_string();
+        let server = TestServer::start(project.path(), &["--prompts-dir", &dir], &[]);
+        drop(prompts);
+
+        let (status, body) = server.send(reload(None)).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{}", body);
+        assert!(body["error"].as_str().unwrap().contains("cannot read prompt directory"), "{}", body);
+        assert!(server.state.settings.templates.get(Some("readme-minimal")).is_ok());
+    }
+
+    #[tokio::test]
+    async fn requests_get_an_id_unless_they_bring_one() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let health = || Request::get("/health").body(Body::empty()).unwrap();
+        let first = server.app.clone().oneshot(health()).await.unwrap();
+        let second = server.app.clone().oneshot(health()).await.unwrap();
+        let first = first.headers()[REQUEST_ID_HEADER].to_str().unwrap().to_string();
+        let second = second.headers()[REQUEST_ID_HEADER].to_str().unwrap().to_string();
+        assert_eq!(first.len(), 36, "{}", first);
+        assert_ne!(first, second);
+
+        let request = Request::post("/generate")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(REQUEST_ID_HEADER, "client-trace-42")
+            .body(Body::from(r#"{"path_or_url": "/nowhere"}"#))
+            .unwrap();
+        let response = server.app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "client-trace-42");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["request_id"], "client-trace-42", "{}", body);
+    }
+
+    #[tokio::test]
+    async fn version_reports_the_build() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &["secret-token"]);
+        let (status, _) = server.send(Request::get("/version").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let version = get_json(&server, "/version", Some("secret-token")).await;
+        assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+        assert!(!version["git_hash"].as_str().unwrap().is_empty(), "{}", version);
+        assert!(version["rustc"].as_str().unwrap().starts_with("rustc "), "{}", version);
+        assert!(version["uptime_secs"].as_i64().unwrap() >= 0, "{}", version);
+        let started_at = version["started_at"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(started_at).is_ok(), "{}", started_at);
+    }
+
+    #[tokio::test]
+    async fn pull_requests_need_a_repository_url_and_a_real_run() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let (status, body) = server.post("/generate", dry_run(&project, serde_json::json!({"create_pr": true}))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+        assert_eq!(body["error"], "create_pr cannot be combined with dry_run");
+
+        let local = serde_json::json!({"path_or_url": project.path(), "create_pr": true});
+        for uri in ["/generate", "/jobs"] {
+            let (status, body) = server.post(uri, local.clone()).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}: {}", uri, body);
+            assert_eq!(body["error"], "create_pr needs a GitHub repository URL, not a local path or upload", "{}", uri);
+        }
+
+        let request = Request::post("/generate/upload?request=%7B%22create_pr%22%3Atrue%7D")
+            .header(header::CONTENT_TYPE, "application/zip")
+            .body(Body::from(zip_bytes(&[("notes.txt", "notes\n")])))
+            .unwrap();
+        let (status, body) = server.send(request).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+        assert_eq!(body["code"], "invalid_request");
+    }
+
+    /// A stored document from `repository`, made `hours_ago`
+    fn stored_document(repository: &str, hours_ago: i64, content: &str) -> Document {
+        Document {
+            info: DocumentInfo {
+                id: new_document_id(),
+                source: format!("https://github.com/{}", repository),
+                repository: Some(repository.to_string()),
+                commit: None,
+                model: "claude-test".to_string(),
+                template: DEFAULT_TEMPLATE.to_string(),
+                usage: Usage::default(),
+                created_at: Utc::now() - chrono::Duration::hours(hours_ago),
+            },
+            content: content.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn documents_are_listed_newest_first_and_filtered() {
+        let (project, documents) = (project(), TempDir::new().unwrap());
+        let dir = documents.path().display().to_string();
+        let server = TestServer::start(project.path(), &["--documents-dir", &dir], &[]);
+        let store = server.state.settings.documents.as_ref().unwrap();
+        let old = stored_document("acme/demo", 2, "# Old\n");
+        let new = stored_document("acme/demo", 0, "# New\n");
+        let other = stored_document("acme/other", 1, "# Other\n");
+        for document in [&old, &new, &other] {
+            store.put(document).await.unwrap();
+        }
+        let ids = |body: &serde_json::Value| -> Vec<String> {
+            body["documents"].as_array().unwrap().iter().map(|info| info["id"].as_str().unwrap().to_string()).collect()
+        };
+
+        let listed = get_json(&server, "/documents", None).await;
+        assert_eq!(ids(&listed), [new.info.id.as_str(), other.info.id.as_str(), old.info.id.as_str()]);
+        assert!(listed["documents"][0].get("content").is_none(), "{}", listed);
+        assert_eq!(ids(&get_json(&server, "/documents?repo=ACME/demo", None).await), [new.info.id.as_str(), old.info.id.as_str()]);
+        assert_eq!(ids(&get_json(&server, "/documents?limit=1", None).await), [new.info.id.as_str()]);
+        assert!(ids(&get_json(&server, "/documents?repo=acme/missing", None).await).is_empty());
+    }
+
+    #[tokio::test]
+    async fn documents_are_served_as_markdown_or_json() {
+        let (project, documents) = (project(), TempDir::new().unwrap());
+        let dir = documents.path().display().to_string();
+        let server = TestServer::start(project.path(), &["--documents-dir", &dir], &[]);
+        let document = stored_document("acme/demo", 0, "# Demo\n");
+        server.state.settings.documents.as_ref().unwrap().put(&document).await.unwrap();
+        let uri = format!("/documents/{}", document.info.id);
+
+        let response = server.app.clone().oneshot(Request::get(&uri).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/markdown; charset=utf-8");
+        assert_eq!(to_bytes(response.into_body(), usize::MAX).await.unwrap(), "# Demo\n");
+
+        let request = Request::get(&uri).header(header::ACCEPT, "application/json").body(Body::empty()).unwrap();
+        let (status, body) = server.send(request).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["id"], document.info.id.as_str());
+        assert_eq!(body["repository"], "acme/demo");
+        assert_eq!(body["content"], "# Demo\n");
+
+        let (status, body) = server.send(Request::get("/documents/missing").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+        assert_eq!(body["error"], "no document missing");
+    }
+
+    #[tokio::test]
+    async fn servers_without_a_documents_dir_keep_none() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        for uri in ["/documents", "/documents/anything"] {
+            let (status, body) = server.send(Request::get(uri).body(Body::empty()).unwrap()).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}: {}", uri, body);
+            assert_eq!(body["error"], "this server does not keep documents; start it with --documents-dir", "{}", uri);
+        }
+    }
+
+    /// /ready's status and the components it reports failing
+    async fn readiness(server: &TestServer) -> (StatusCode, serde_json::Value, Vec<String>) {
+        let (status, body) = server.send(Request::get("/ready").body(Body::empty()).unwrap()).await;
+        let failing = body["failing"].as_array().unwrap().iter().map(|name| name.as_str().unwrap().to_string()).collect();
+        (status, body, failing)
+    }
+
+    #[tokio::test]
+    async fn ready_reports_every_component() {
+        let project = project();
+        let server = TestServer::start(project.path(), &[], &[]);
+        let (status, body, failing) = readiness(&server).await;
+        let components: Vec<&str> = body["checks"].as_array().unwrap().iter().map(|check| check["component"].as_str().unwrap()).collect();
+        assert_eq!(components, ["provider", "workspace", "templates"]);
+        // The provider depends on the key this runs with; the rest must be healthy
+        assert!(failing.iter().all(|name| name == "provider"), "{}", body);
+        assert_eq!(status == StatusCode::OK, failing.is_empty(), "{}", body);
+        assert_eq!(body["ready"], failing.is_empty());
+        for check in body["checks"].as_array().unwrap() {
+            assert_eq!(check["healthy"], !failing.iter().any(|name| name == check["component"].as_str().unwrap()), "{}", check);
+        }
+    }
+
+    #[tokio::test]
+    async fn ready_fails_while_the_clone_quota_is_in_use() {
+        let project = project();
+        let server = TestServer::start(project.path(), &["--workspace-quota-mb", "0"], &[]);
+        let (status, body, failing) = readiness(&server).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+        assert_eq!(body["ready"], false);
+        assert!(failing.contains(&"workspace".to_string()), "{}", body);
+        let workspace = body["checks"].as_array().unwrap().iter().find(|check| check["component"] == "workspace").unwrap();
+        assert_eq!(workspace["detail"], "the 0 MB clone quota is in use");
+    }
+
+    #[tokio::test]
+    async fn ready_fails_when_templates_cannot_be_read() {
+        let (project, prompts) = (project(), prompts());
+        let dir = prompts.path().display().to_string();
+        let server = TestServer::start(project.path(), &["--prompts-dir", &dir], &[]);
+        drop(prompts);
+        let (status, body, failing) = readiness(&server).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+        assert!(failing.contains(&"templates".to_string()), "{}", body);
+    }
+
+    #[test]
+    fn the_provider_is_failing_until_first_checked() {
+        let probe = ProviderProbe { latest: RwLock::new(None) };
+        let check = probe.check();
+        assert!(check.is_blocking());
+        assert_eq!(check.detail, "not checked yet");
+        *probe.latest.write().unwrap() =
+            Some(CheckResult { name: "provider", status: CheckStatus::Pass, detail: "key accepted".to_string(), required: true });
+        assert!(!probe.check().is_blocking());
+    }
+
+    #[tokio::test]
+    async fn webhook_deliveries_may_be_larger_than_api_requests() {
+        let project = project();
+        let (server, _slot) = webhook_server(&project).await;
+        let mut payload = push("refs/heads/main", "abc123");
+        let commits: Vec<_> = (0..400).map(|index| serde_json::json!({"id": index, "message": "a".repeat(256)})).collect();
+        payload["commits"] = commits.into();
+        assert!(payload.to_string().len() > 64 * 1024);
+        let (status, body) = server.send(delivery("push", &payload, WEBHOOK_SECRET)).await;
+        assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+
+        // API requests keep the smaller limit
+        let body = dry_run(&project, serde_json::json!({"padding": "a".repeat(70 * 1024)}));
+        let request = Request::post("/generate")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::AUTHORIZATION, "Bearer token")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let (status, body) = server.send(request).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE, "{}", body);
+    }
 }
@@ -1,22 +1,313 @@
-use std::io::Read;
-use clap::{Parser, Subcommand};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use clap::builder::{BoolishValueParser, NonEmptyStringValueParser, PossibleValuesParser};
+use clap::{value_parser, Parser, Subcommand, ValueEnum};
+use indicatif::{ProgressBar, ProgressStyle};
 // use claude_client::claude::ClaudeClient; // Not needed anymore
 use techdocs::{
-    list_files, list_files_prompt, resolve_path, validate_directory, generate_readme,
-    Result as TechDocsResult,
+    is_remote, resolve_path, resolve_path_cancellable, validate_directory, inspect_directory, DirCheck,
+    ensure_writable,
+    render_dry_run, write_atomic, write_atomic_bytes, append_to_file,
+    write_with_backup, gzip_bytes,
+    CancellationToken, Result as TechDocsResult, TechDocsError, DEFAULT_README_PROMPT,
 };
+use techdocs::architecture::{architecture_modules, generate_architecture, module_graph};
+use techdocs::badges::{badges, detect_metadata};
+use techdocs::batch::{
+    read_manifest, run_batch, run_batch_api, BatchKind, BatchOptions, BatchState, RepoStatus, StateFile, BATCH_STATE_FILE,
+};
+use techdocs::changelog::{
+    format_date, group_commits, insert_section, render_markdown, render_prompt_input,
+    CHANGELOG_PROFILE, DEFAULT_CHANGELOG_PROMPT,
+};
+use techdocs::commit_message::{generate_commit_message, CommitType, DEFAULT_MAX_DIFF_TOKENS};
+use techdocs::contributing::{contributing_request, directory_tree, TREE_DEPTH, TREE_LINES};
+use techdocs::config::{read_exclude_file, Config, PartialConfig};
+use techdocs::claude::ClaudeClient;
+use techdocs::clipboard::copy_to_clipboard;
+use techdocs::diff::unified_diff;
+use techdocs::doctor::run_checks;
+use techdocs::filters::{filter_rules, PRESETS};
+use techdocs::docs::{generate_docs, partition_files, PageStatus};
+use techdocs::generate::{GenerateOptions, ReadmePrompt};
+use techdocs::{collect_config_files, generate_document_with_profile};
+use techdocs::git::{
+    commits_between, default_branch, diff_against_base, diff_commits, latest_tag,
+    open_repository, resolve_commit, staged_changes, DEFAULT_MAX_COMMITS,
+};
+use techdocs::github::{fetch_remote_metadata, GitHubClient, PullRequestOutcome, PullRequestText, Repository};
+use techdocs::release_notes::{self, classify_commits, ChangeStats, DEFAULT_RELEASE_NOTES_PROMPT, RELEASE_NOTES_PROFILE};
+use techdocs::review::{diff_prompt, generate_review, group_by_file};
+use techdocs::sections::{
+    headings, normalize_heading, section_instructions, splice_sections, KNOWN_SECTIONS,
+};
+use techdocs::summary::generate_summary;
+use techdocs::claude::{DEFAULT_MAX_TOKENS, DEFAULT_MODEL};
+use techdocs::logging::{init_logging, LogFormat, LogOptions};
+use techdocs::incremental::{file_hashes, settings_hash, state_path, update_prompt, ReadmeState};
+use techdocs::keep::{extract_kept_sections, merge_kept_sections, preserve_instructions, KeptSection};
+use techdocs::lang::{language_instructions, language_name, localized_path};
+use techdocs::manifest::FileManifest;
+use techdocs::metrics::{self, Registry};
+use techdocs::monorepo::{detect_workspace, find_context_root, workspace_member, Member, ParentContext};
+use techdocs::plan::{
+    estimate_tokens, LargeFiles, Normalize, PromptOptions, PromptPlan, PromptStats, SkipReason, DEFAULT_HEAD_PERCENT,
+};
+use techdocs::plan_file::PlanFile;
+use techdocs::postprocess::{Footer, PostProcessors};
+use techdocs::preview::{open_in_browser, serve_preview, Preview};
+use techdocs::progress::{ProgressEvent, ProgressFn};
+use techdocs::tooling::detect_tooling;
+use techdocs::project::{builtin_template, BUILTIN_TEMPLATES};
+use techdocs::provider::{
+    CostTrackedClient, CostTracker, LlmClient, RateLimitedClient, ThinkingConfig, Usage, MIN_THINKING_BUDGET,
+};
+use techdocs::tokens::TokenReport;
+use techdocs::validate::{check_links, validate_markdown};
+use techdocs::watch::{own_file_excludes, ChangeWatcher};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum BatchKindArg {
+    Readme,
+    Summary,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CommitTypeArg {
+    Feat,
+    Fix,
+    Docs,
+    Style,
+    Refactor,
+    Perf,
+    Test,
+    Build,
+    Ci,
+    Chore,
+    Revert,
+}
+
+impl From<CommitTypeArg> for CommitType {
+    fn from(arg: CommitTypeArg) -> Self {
+        match arg {
+            CommitTypeArg::Feat => CommitType::Feat,
+            CommitTypeArg::Fix => CommitType::Fix,
+            CommitTypeArg::Docs => CommitType::Docs,
+            CommitTypeArg::Style => CommitType::Style,
+            CommitTypeArg::Refactor => CommitType::Refactor,
+            CommitTypeArg::Perf => CommitType::Perf,
+            CommitTypeArg::Test => CommitType::Test,
+            CommitTypeArg::Build => CommitType::Build,
+            CommitTypeArg::Ci => CommitType::Ci,
+            CommitTypeArg::Chore => CommitType::Chore,
+            CommitTypeArg::Revert => CommitType::Revert,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormatArg {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LargeFilesArg {
+    Skip,
+    HeadTail,
+}
+
+/// How `--no-normalize` and `--keep-trailing-whitespace` clean up file content
+fn normalize_policy(no_normalize: bool, keep_trailing_whitespace: bool) -> Normalize {
+    match (no_normalize, keep_trailing_whitespace) {
+        (true, _) => Normalize::Off,
+        (false, true) => Normalize::LineEndings,
+        (false, false) => Normalize::Full,
+    }
+}
+
+impl LargeFilesArg {
+    fn policy(self, head_percent: u8) -> LargeFiles {
+        match self {
+            LargeFilesArg::Skip => LargeFiles::Skip,
+            LargeFilesArg::HeadTail => LargeFiles::HeadTail { head_percent },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Exit code for errors without a more specific class
+const EXIT_FAILURE: u8 = 1;
+/// Invalid flags or configuration
+const EXIT_USAGE: u8 = 2;
+/// The path, URL, or git revision could not be resolved or read, or a file changed
+/// since the `--plan-in` plan was written
+const EXIT_INPUT: u8 = 3;
+/// File filtering failed, the source was too large to walk, or a size or cost budget
+/// was exceeded
+const EXIT_BUDGET: u8 = 4;
+/// The Claude API request failed
+const EXIT_PROVIDER: u8 = 5;
+/// The result could not be written, failed --strict-output, or the GitHub pull
+/// request could not be opened
+const EXIT_OUTPUT: u8 = 6;
+/// `readme --diff` found that the generated README differs from the existing one
+const EXIT_README_CHANGED: u8 = 7;
+/// The run stopped before a Claude request that would have passed --max-cost-usd
+const EXIT_COST_LIMIT: u8 = 8;
+/// Interrupted with Ctrl-C, 128 + SIGINT as shells report it
+const EXIT_INTERRUPTED: u8 = 130;
+
+/// How long `prompt --watch` waits for changes to settle before rebuilding
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  Success
+  1  Other failure
+  2  Invalid flags or configuration
+  3  Path, URL, or git revision could not be resolved or read, or a file changed since
+     the --plan-in plan was written
+  4  File filtering failed, the source was too large to walk, or a size or cost budget
+     was exceeded
+  5  Claude API request failed
+  6  Output could not be written or failed --strict-output, or the GitHub pull request
+     could not be opened
+  7  readme --diff: the README is out of date
+  8  A Claude request would have taken the run past --max-cost-usd
+  130  Interrupted with Ctrl-C";
+
+/// How a command that did not fail ended
+enum Outcome {
+    Done,
+    /// `readme --diff` found the README out of date and wrote nothing
+    ReadmeChanged,
+}
+
+/// Map an error to the documented exit code
+fn exit_code(error: &TechDocsError) -> u8 {
+    match error {
+        TechDocsError::Config(_) => EXIT_USAGE,
+        TechDocsError::Io(_)
+        | TechDocsError::File { .. }
+        | TechDocsError::Git(_)
+        | TechDocsError::Url(_)
+        | TechDocsError::Archive(_)
+        | TechDocsError::PlanChanged(_) => EXIT_INPUT,
+        TechDocsError::Ignore(_)
+        | TechDocsError::Budget(_)
+        | TechDocsError::Quota(_)
+        | TechDocsError::SourceTooLarge(_) => EXIT_BUDGET,
+        TechDocsError::Claude(_) | TechDocsError::ClaudeClient(_) | TechDocsError::RateLimited(_) => EXIT_PROVIDER,
+        TechDocsError::Output(_) | TechDocsError::GitHub(_) | TechDocsError::Validation(_) => EXIT_OUTPUT,
+        TechDocsError::CostLimitExceeded { .. } => EXIT_COST_LIMIT,
+        TechDocsError::Cancelled => EXIT_INTERRUPTED,
+        _ => EXIT_FAILURE,
+    }
+}
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_long_help = EXIT_CODES_HELP)]
 struct Args {
-    /// Additional patterns to exclude (in .gitignore format)
+    /// Additional patterns to exclude (in .gitignore format) [env: TECHDOCS_EXCLUDE]
     #[arg(short, long, value_delimiter = ',', global = true)]
     exclude: Option<Vec<String>>,
 
+    /// Read exclude patterns from a file, one per line (blank lines and # comments are ignored)
+    #[arg(long, global = true, env = "TECHDOCS_EXCLUDE_FROM")]
+    exclude_from: Option<PathBuf>,
+
+    /// Named filter preset deciding which files are left out by default; see
+    /// `config show-filters` [env: TECHDOCS_PRESET]
+    #[arg(long, global = true, value_parser = preset_names())]
+    preset: Option<String>,
+
+    /// Only consider files matching these glob patterns, on top of the preset's
+    /// [env: TECHDOCS_INCLUDE]
+    #[arg(long, value_delimiter = ',', global = true)]
+    include: Option<Vec<String>>,
+
+    /// How many directories deep to walk, 1 being the project's own files [env: TECHDOCS_MAX_DEPTH]
+    #[arg(long, global = true, value_parser = value_parser!(u64).range(1..))]
+    max_depth: Option<u64>,
+
+    /// Give up once the walk finds more files and directories than this; 0 never gives up
+    /// (default: 200000) [env: TECHDOCS_MAX_WALK_ENTRIES]
+    #[arg(long, global = true)]
+    max_walk_entries: Option<u64>,
+
+    /// Give up once selecting files takes longer than this many seconds; 0 never gives up
+    /// (default: 300) [env: TECHDOCS_MAX_WALK_SECS]
+    #[arg(long, global = true)]
+    max_walk_secs: Option<u64>,
+
+    /// Walk into directories that symlinks point at
+    #[arg(long, global = true, env = "TECHDOCS_FOLLOW_SYMLINKS", value_parser = BoolishValueParser::new())]
+    follow_symlinks: bool,
+
+    /// Read files that symlinks lead to outside the project directory (skipped by default)
+    #[arg(long, global = true, env = "TECHDOCS_ALLOW_EXTERNAL_SYMLINKS", value_parser = BoolishValueParser::new())]
+    allow_external_symlinks: bool,
+
+    /// Hide progress indicators and log only warnings and errors
+    #[arg(short, long, global = true, env = "TECHDOCS_QUIET", value_parser = BoolishValueParser::new())]
+    quiet: bool,
+
+    /// Log more: -v for debug, -vv for trace (RUST_LOG takes precedence when set)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Deprecated: log filter such as `debug`; use -v instead
+    #[arg(long, global = true, hide = true)]
+    log_level: Option<String>,
+
+    /// Format of log lines on stderr
+    #[arg(long, value_enum, global = true, default_value = "text", env = "TECHDOCS_LOG_FORMAT")]
+    log_format: LogFormatArg,
+
+    /// Never use ANSI colors (also honors NO_COLOR)
+    #[arg(long, global = true, env = "TECHDOCS_NO_COLOR", value_parser = BoolishValueParser::new())]
+    no_color: bool,
+
+    /// Print file, prompt, clone, and Claude request metrics to stderr on exit, in the
+    /// Prometheus text format
+    #[arg(long, global = true)]
+    print_metrics: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+// Parsed once per run, so the size of the largest subcommand costs nothing
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// List all files in a directory
@@ -28,69 +319,2837 @@ enum Commands {
     Prompt {
         /// Path to directory or GitHub repository URL
         path_or_url: String,
-        /// Maximum file size in KB (default: 100)
-        #[arg(long, default_value = "100")]
-        max_file_size_kb: u64,
-        /// Maximum total size in MB (default: 10)
-        #[arg(long, default_value = "10")]
-        max_total_size_mb: u64,
+        /// Maximum file size in KB (default: 100) [env: TECHDOCS_MAX_FILE_SIZE_KB]
+        #[arg(long)]
+        max_file_size_kb: Option<u64>,
+        /// Maximum total size in MB (default: 10) [env: TECHDOCS_MAX_TOTAL_SIZE_MB]
+        #[arg(long)]
+        max_total_size_mb: Option<u64>,
+        /// Write the prompt to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Append to the --output file after a part separator instead of replacing it
+        #[arg(long, requires = "output", conflicts_with = "watch")]
+        append: bool,
+        /// Gzip the --output file (implied when its name ends in .gz)
+        #[arg(long, requires = "output")]
+        gzip: bool,
+        /// Number each line of the files, as in `  42 | fn main() {`
+        #[arg(long)]
+        line_numbers: bool,
+        /// Include at most this many files from any one directory, manifests and entry
+        /// points first; the rest are only named
+        #[arg(long)]
+        max_files_per_dir: Option<NonZeroUsize>,
+        /// List the files under a heading for each top-level directory, the one with the
+        /// manifest first
+        #[arg(long)]
+        group_by_dir: bool,
+        /// What to do with files over the size limit: leave them out, or send their first
+        /// and last lines with a marker where the middle was
+        #[arg(long, value_enum, default_value = "skip")]
+        large_files: LargeFilesArg,
+        /// Percent of the size limit that `--large-files head-tail` gives a file's first lines
+        #[arg(long, default_value_t = DEFAULT_HEAD_PERCENT, value_parser = value_parser!(u8).range(0..=100))]
+        head_percent: u8,
+        /// Send files byte for byte, without stripping byte order marks, turning CRLF and
+        /// CR line endings into LF, or trimming trailing whitespace
+        #[arg(long)]
+        no_normalize: bool,
+        /// Keep spaces and tabs at the ends of lines while normalizing
+        #[arg(long, conflicts_with = "no_normalize")]
+        keep_trailing_whitespace: bool,
+        /// Copy the prompt to the system clipboard instead of printing it
+        #[arg(long, conflicts_with = "watch")]
+        clipboard: bool,
+        /// Keep running and rebuild the prompt whenever files in the directory change
+        #[arg(long)]
+        watch: bool,
+        /// Also generate a README into this file with Claude after every change.
+        /// Each change costs a request, so the estimated cost is confirmed first
+        #[arg(long, value_name = "FILE", requires = "watch")]
+        watch_generate: Option<PathBuf>,
+        /// Skip the --watch-generate cost confirmation
+        #[arg(short, long, env = "TECHDOCS_YES", value_parser = BoolishValueParser::new())]
+        yes: bool,
+        /// Send only this Cargo, npm, or pnpm workspace member (by package name or path)
+        /// and the workspace manifests
+        #[arg(long, value_name = "NAME")]
+        member: Option<String>,
+        /// After building the prompt, serve it on a localhost URL until Ctrl-C: rendered
+        /// at `/`, as markdown at `/raw`, and its statistics at `/stats`
+        #[arg(long, conflicts_with_all = ["watch", "clipboard"])]
+        serve: bool,
+        /// Stop serving after this many seconds
+        #[arg(long, value_name = "SECONDS", requires = "serve")]
+        serve_timeout: Option<u64>,
+        /// Open the served page in the default browser
+        #[arg(long, requires = "serve")]
+        open: bool,
     },
     /// Generate a README for a directory
     Readme {
         /// Path to directory or GitHub repository URL
         path_or_url: String,
+        /// Write the generated README to this file instead of stdout
+        #[arg(short, long, conflicts_with = "write")]
+        output: Option<PathBuf>,
+        /// Write README.md into the target directory
+        #[arg(long)]
+        write: bool,
+        /// Overwrite an existing README.md when using --write (the old file is kept as README.md.bak)
+        #[arg(long, requires = "write")]
+        force: bool,
+        /// Maximum file size in KB (default: 100) [env: TECHDOCS_MAX_FILE_SIZE_KB]
+        #[arg(long)]
+        max_file_size_kb: Option<u64>,
+        /// Maximum total size in MB (default: 10) [env: TECHDOCS_MAX_TOTAL_SIZE_MB]
+        #[arg(long)]
+        max_total_size_mb: Option<u64>,
+        /// Custom system prompt file (defaults to the built-in template for the project
+        /// type) [env: TECHDOCS_PROMPT_FILE]
+        #[arg(long)]
+        prompt_file: Option<PathBuf>,
+        /// Built-in prompt template to use instead of the one for the detected project
+        /// type; `generic` suits any project
+        #[arg(long, conflicts_with = "prompt_file", value_parser = template_names())]
+        template: Option<String>,
+        /// Claude model to use [env: TECHDOCS_MODEL]
+        #[arg(long)]
+        model: Option<String>,
+        /// Maximum output tokens, thinking included (default: 4096, or the thinking budget
+        /// plus 4096 with --thinking-budget)
+        #[arg(long, value_name = "TOKENS", value_parser = value_parser!(u32).range(1..))]
+        max_tokens: Option<u32>,
+        /// Let Claude think for up to this many tokens before writing, for large or complex
+        /// projects; at least 1024 and below --max-tokens
+        #[arg(long, value_name = "TOKENS")]
+        thinking_budget: Option<u32>,
+        /// Language to write the README in, e.g. `ja`; repeat for one README per language.
+        /// The first is written to README.md, the rest to README.<lang>.md
+        #[arg(long, value_name = "CODE", value_parser = NonEmptyStringValueParser::new())]
+        lang: Vec<String>,
+        /// Regenerate only these top-level sections, e.g. `installation,usage`. With --write
+        /// or --diff they replace the same-named sections of the existing README
+        #[arg(long, value_delimiter = ',', value_parser = NonEmptyStringValueParser::new())]
+        sections: Vec<String>,
+        /// Add crates.io, docs.rs, CI, and license badges under the title, where detected
+        #[arg(long)]
+        badges: bool,
+        /// Add a table of contents of the level 2 and 3 headings after the introduction,
+        /// or between `<!-- toc -->` and `<!-- tocstop -->` markers where the README has them
+        #[arg(long)]
+        toc: bool,
+        /// Append a footer rendered from this template, filling in {version}, {commit},
+        /// {date}, {model}, {source}, and {license} [env: TECHDOCS_FOOTER]
+        #[arg(long, value_name = "TEMPLATE")]
+        footer: Option<String>,
+        /// Include at most this many files from any one directory, manifests and entry
+        /// points first; the rest are only named
+        #[arg(long)]
+        max_files_per_dir: Option<NonZeroUsize>,
+        /// List the files under a heading for each top-level directory, the one with the
+        /// manifest first
+        #[arg(long)]
+        group_by_dir: bool,
+        /// What to do with files over the size limit: leave them out, or send their first
+        /// and last lines with a marker where the middle was
+        #[arg(long, value_enum, default_value = "skip")]
+        large_files: LargeFilesArg,
+        /// Percent of the size limit that `--large-files head-tail` gives a file's first lines
+        #[arg(long, default_value_t = DEFAULT_HEAD_PERCENT, value_parser = value_parser!(u8).range(0..=100))]
+        head_percent: u8,
+        /// Send files byte for byte, without stripping byte order marks, turning CRLF and
+        /// CR line endings into LF, or trimming trailing whitespace
+        #[arg(long)]
+        no_normalize: bool,
+        /// Keep spaces and tabs at the ends of lines while normalizing
+        #[arg(long, conflicts_with = "no_normalize")]
+        keep_trailing_whitespace: bool,
+        /// Print the system prompt and file listing that would be sent, without calling Claude
+        #[arg(long, conflicts_with_all = ["output", "write"])]
+        dry_run: bool,
+        /// Print a diff against the existing README.md; exits with code 7 when they differ
+        #[arg(long, conflicts_with_all = ["output", "dry_run"])]
+        diff: bool,
+        /// Show the estimated tokens and cost and ask before calling Claude
+        #[arg(long, conflicts_with = "dry_run")]
+        confirm: bool,
+        /// Answer yes to confirmation questions (--confirm, --diff --write)
+        #[arg(short, long, env = "TECHDOCS_YES", value_parser = BoolishValueParser::new())]
+        yes: bool,
+        /// Fail before calling Claude if the estimated cost in USD exceeds this, and
+        /// stop before any request that would take the run's spending past it
+        #[arg(long, env = "TECHDOCS_MAX_COST_USD")]
+        max_cost_usd: Option<f64>,
+        /// Colorize the diff
+        #[arg(long, value_enum, default_value = "auto", env = "TECHDOCS_COLOR")]
+        color: ColorChoice,
+        /// Open a pull request with the README on a GitHub repository URL, using
+        /// TECHDOCS_GITHUB_TOKEN or GITHUB_TOKEN
+        #[arg(long, conflicts_with_all = ["output", "write", "diff", "dry_run"])]
+        create_pr: bool,
+        /// Skip Claude when no file changed since the last --incremental run, and update the
+        /// previous README when only a few did. The state is kept in .techdocs/state.json
+        /// beside the README, which is replaced as with --force
+        #[arg(long, conflicts_with_all = ["dry_run", "create_pr"])]
+        incremental: bool,
+        /// Write every file considered to this JSON file before calling Claude: its size,
+        /// its SHA-256 when it is sent, and why it is left out when it is not. Works with
+        /// --dry-run for review before anything is sent
+        #[arg(long, value_name = "FILE", conflicts_with = "all_members")]
+        plan_out: Option<PathBuf>,
+        /// Send exactly the files a --plan-out file lists, as it showed them, instead of
+        /// walking the project; fails if any of them changed since it was written
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["all_members", "max_files_per_dir", "group_by_dir", "no_normalize", "keep_trailing_whitespace"]
+        )]
+        plan_in: Option<PathBuf>,
+        /// Tell Claude the commit count and dates, recent commit subjects, and top
+        /// contributors when the project is a git repository (the default)
+        #[arg(long, overrides_with = "no_git_context")]
+        git_context: bool,
+        /// Leave the git history and contributors out of the prompt
+        #[arg(long)]
+        no_git_context: bool,
+        /// Leave the summary of CI workflows, Dockerfiles, Compose files, and Kubernetes
+        /// manifests out of the prompt
+        #[arg(long)]
+        no_infra_context: bool,
+        /// Do not ask the GitHub API for the description, topics, homepage, license, and
+        /// stars of a repository URL (set TECHDOCS_GITHUB_TOKEN or GITHUB_TOKEN for a
+        /// higher rate limit)
+        #[arg(long)]
+        no_remote_metadata: bool,
+        /// Send README.md with the other files instead of asking Claude to keep its tone
+        /// and structure
+        #[arg(long)]
+        ignore_existing_readme: bool,
+        /// Also check that every http(s) link in the README answers
+        #[arg(long)]
+        check_links: bool,
+        /// Fail instead of warning when the README has broken markdown or links
+        #[arg(long)]
+        strict_output: bool,
+        /// Document only this Cargo, npm, or pnpm workspace member (by package name or
+        /// path); its README replaces the one in the member's directory
+        #[arg(long, value_name = "NAME")]
+        member: Option<String>,
+        /// Directory above the project whose manifests and README are sent as labeled
+        /// context, capped apart from the size limits (default: the nearest enclosing
+        /// workspace or git repository root, when the project is below one)
+        #[arg(long, value_name = "PATH", conflicts_with = "all_members")]
+        context_root: Option<PathBuf>,
+        /// Send no files from above the project, even inside a workspace or repository
+        #[arg(long, conflicts_with = "context_root")]
+        no_context_root: bool,
+        /// Write one README into the directory of every workspace member
+        #[arg(
+            long,
+            requires = "write",
+            conflicts_with_all = ["member", "lang", "sections", "diff", "dry_run", "create_pr", "incremental"]
+        )]
+        all_members: bool,
+        /// After generating, serve the README on a localhost URL until Ctrl-C: rendered
+        /// at `/`, as markdown at `/raw`, and the run's statistics at `/stats`
+        #[arg(long, conflicts_with_all = ["dry_run", "create_pr", "all_members"])]
+        serve: bool,
+        /// Stop serving after this many seconds
+        #[arg(long, value_name = "SECONDS", requires = "serve")]
+        serve_timeout: Option<u64>,
+        /// Open the served page in the default browser
+        #[arg(long, requires = "serve")]
+        open: bool,
     },
+    /// Generate a short project description and topic list
+    Summary {
+        /// Path to directory or GitHub repository URL
+        path_or_url: String,
+        /// Output format
+        #[arg(long, value_enum, default_value = "text", env = "TECHDOCS_FORMAT")]
+        format: OutputFormat,
+        /// Claude model to use [env: TECHDOCS_MODEL]
+        #[arg(long)]
+        model: Option<String>,
+    },
+    /// Review the changes on the current branch with Claude
+    Review {
+        /// Path to a git repository or GitHub repository URL
+        path_or_url: String,
+        /// Revision to diff HEAD against (default: the repository's default branch)
+        #[arg(long)]
+        base: Option<String>,
+        /// Output format
+        #[arg(long, value_enum, default_value = "text", env = "TECHDOCS_FORMAT")]
+        format: OutputFormat,
+        /// Maximum diff size per file in KB (default: 100) [env: TECHDOCS_MAX_FILE_SIZE_KB]
+        #[arg(long)]
+        max_file_size_kb: Option<u64>,
+        /// Maximum total diff size in MB (default: 10) [env: TECHDOCS_MAX_TOTAL_SIZE_MB]
+        #[arg(long)]
+        max_total_size_mb: Option<u64>,
+        /// Claude model to use [env: TECHDOCS_MODEL]
+        #[arg(long)]
+        model: Option<String>,
+    },
+    /// Generate an architecture overview with a Mermaid diagram of module dependencies
+    Architecture {
+        /// Path to directory or GitHub repository URL
+        path_or_url: String,
+        /// Write the overview to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Maximum file size in KB (default: 100) [env: TECHDOCS_MAX_FILE_SIZE_KB]
+        #[arg(long)]
+        max_file_size_kb: Option<u64>,
+        /// Maximum total size of the files sent in MB (default: 10) [env: TECHDOCS_MAX_TOTAL_SIZE_MB]
+        #[arg(long)]
+        max_total_size_mb: Option<u64>,
+        /// Claude model to use [env: TECHDOCS_MODEL]
+        #[arg(long)]
+        model: Option<String>,
+        /// Maximum output tokens, thinking included (default: 4096, or the thinking budget
+        /// plus 4096 with --thinking-budget)
+        #[arg(long, value_name = "TOKENS", value_parser = value_parser!(u32).range(1..))]
+        max_tokens: Option<u32>,
+        /// Let Claude think for up to this many tokens before writing, for large or complex
+        /// projects; at least 1024 and below --max-tokens
+        #[arg(long, value_name = "TOKENS")]
+        thinking_budget: Option<u32>,
+    },
+    /// Generate a CONTRIBUTING.md from the repository's build, test, lint, and CI setup
+    Contributing {
+        /// Path to directory or GitHub repository URL
+        path_or_url: String,
+        /// Write the guide to this file instead of stdout
+        #[arg(short, long, conflicts_with = "write")]
+        output: Option<PathBuf>,
+        /// Write CONTRIBUTING.md into the target directory
+        #[arg(long)]
+        write: bool,
+        /// Overwrite an existing CONTRIBUTING.md when using --write (the old file is kept as CONTRIBUTING.md.bak)
+        #[arg(long, requires = "write")]
+        force: bool,
+        /// Claude model to use [env: TECHDOCS_MODEL]
+        #[arg(long)]
+        model: Option<String>,
+    },
+    /// Write a commit message for the staged changes, for `git commit -F -`
+    CommitMsg {
+        /// Path inside the git repository
+        #[arg(default_value = ".")]
+        path: PathBuf,
+        /// Commit type the subject must start with
+        #[arg(long = "type", value_enum)]
+        commit_type: Option<CommitTypeArg>,
+        /// Maximum tokens of diff to send; larger diffs are cut or listed by name
+        #[arg(long, default_value_t = DEFAULT_MAX_DIFF_TOKENS)]
+        max_diff_tokens: usize,
+        /// Claude model to use [env: TECHDOCS_MODEL]
+        #[arg(long)]
+        model: Option<String>,
+    },
+    /// Generate one documentation page per module plus an index
+    Docs {
+        /// Path to directory or GitHub repository URL
+        path_or_url: String,
+        /// Directory to write the pages into (default: docs/ inside the project)
+        #[arg(short, long)]
+        output_dir: Option<PathBuf>,
+        /// Maximum file size in KB (default: 100) [env: TECHDOCS_MAX_FILE_SIZE_KB]
+        #[arg(long)]
+        max_file_size_kb: Option<u64>,
+        /// Maximum size of one module's files in MB before it is summarized in chunks (default: 10) [env: TECHDOCS_MAX_TOTAL_SIZE_MB]
+        #[arg(long)]
+        max_total_size_mb: Option<u64>,
+        /// Claude model to use [env: TECHDOCS_MODEL]
+        #[arg(long)]
+        model: Option<String>,
+        /// Stop before any Claude request that would take the run's spending in USD past this
+        #[arg(long, env = "TECHDOCS_MAX_COST_USD")]
+        max_cost_usd: Option<f64>,
+    },
+    /// Generate a CHANGELOG section from git history
+    Changelog {
+        /// Path to a git repository or GitHub repository URL
+        path_or_url: String,
+        /// Start of the commit range, exclusive (default: the most recent tag)
+        #[arg(long)]
+        from: Option<String>,
+        /// End of the commit range, inclusive
+        #[arg(long, default_value = "HEAD")]
+        to: String,
+        /// Print the grouped commit list without calling Claude (no API key needed)
+        #[arg(long)]
+        no_llm: bool,
+        /// Include merge commits in the commit list
+        #[arg(long)]
+        include_merges: bool,
+        /// Newest commits to list and diff; older ones in the range are only counted
+        #[arg(long, default_value_t = DEFAULT_MAX_COMMITS)]
+        max_commits: usize,
+        /// Write the changelog section to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Insert the section into the existing --output file above previous releases
+        #[arg(long, requires = "output")]
+        append: bool,
+        /// Claude model to use [env: TECHDOCS_MODEL]
+        #[arg(long)]
+        model: Option<String>,
+    },
+    /// Generate user-facing release notes between two tags
+    ReleaseNotes {
+        /// Path to a git repository or GitHub repository URL
+        path_or_url: String,
+        /// Previous release tag, exclusive (default: the tag before --to)
+        #[arg(long)]
+        from: Option<String>,
+        /// Release tag, inclusive (default: the most recent tag, or HEAD when there are none)
+        #[arg(long)]
+        to: Option<String>,
+        /// Print the grouped commit list without calling Claude (no API key needed)
+        #[arg(long)]
+        no_llm: bool,
+        /// Newest commits to list and diff; older ones in the range are only counted
+        #[arg(long, default_value_t = DEFAULT_MAX_COMMITS)]
+        max_commits: usize,
+        /// Write the release notes to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Claude model to use [env: TECHDOCS_MODEL]
+        #[arg(long)]
+        model: Option<String>,
+    },
+    /// Generate READMEs or summaries for many repositories listed in a manifest
+    Batch {
+        /// File with one path or URL per line, or a .toml file with [[repo]] entries
+        manifest: PathBuf,
+        /// Directory to write one output directory per repository into
+        #[arg(short, long)]
+        output_dir: PathBuf,
+        /// What to generate for each repository
+        #[arg(long, value_enum, default_value = "readme")]
+        kind: BatchKindArg,
+        /// Number of repositories processed at once
+        #[arg(short, long, default_value_t = 4, env = "TECHDOCS_JOBS")]
+        jobs: usize,
+        /// Maximum Claude requests started per minute, shared by all jobs
+        #[arg(long, default_value_t = 50, env = "TECHDOCS_REQUESTS_PER_MINUTE")]
+        requests_per_minute: u32,
+        /// Claude model to use where the manifest sets none [env: TECHDOCS_MODEL]
+        #[arg(long)]
+        model: Option<String>,
+        /// Assemble every prompt first and send them as one message batch, at half the
+        /// price and with no per-minute limit; results can take up to a day
+        #[arg(long)]
+        use_batch_api: bool,
+        /// Cancel the message batch if it has not ended after this many minutes
+        #[arg(long, default_value_t = 24 * 60, requires = "use_batch_api")]
+        batch_timeout_minutes: u64,
+        /// Stop sending Claude requests once the next would take the run's spending in
+        /// USD past this; with --use-batch-api, the whole batch must fit
+        #[arg(long, env = "TECHDOCS_MAX_COST_USD")]
+        max_cost_usd: Option<f64>,
+        /// Continue the run whose progress is in this state file (batch-state.json in the
+        /// output directory): repositories it finished are skipped, failed ones retried, and
+        /// what it spent counts toward --max-cost-usd
+        #[arg(long, value_name = "STATE_FILE")]
+        resume: Option<PathBuf>,
+        /// With --resume, retry only the repositories that failed, not those the run never reached
+        #[arg(long, requires = "resume")]
+        retry_failed_only: bool,
+    },
+    /// Estimate prompt tokens and cost for README generation
+    Tokens {
+        /// Path to directory or GitHub repository URL
+        path_or_url: String,
+        /// Output format
+        #[arg(long, value_enum, default_value = "text", env = "TECHDOCS_FORMAT")]
+        format: OutputFormat,
+        /// Count tokens with the Anthropic API instead of estimating (needs ANTHROPIC_API_KEY)
+        #[arg(long)]
+        exact: bool,
+        /// Maximum file size in KB (default: 100) [env: TECHDOCS_MAX_FILE_SIZE_KB]
+        #[arg(long)]
+        max_file_size_kb: Option<u64>,
+        /// Maximum total size in MB (default: 10) [env: TECHDOCS_MAX_TOTAL_SIZE_MB]
+        #[arg(long)]
+        max_total_size_mb: Option<u64>,
+        /// Custom system prompt file (defaults to the built-in README prompt) [env: TECHDOCS_PROMPT_FILE]
+        #[arg(long)]
+        prompt_file: Option<PathBuf>,
+        /// Claude model to price against [env: TECHDOCS_MODEL]
+        #[arg(long)]
+        model: Option<String>,
+    },
+    /// Write the files a README would be generated from, and their content, as JSON
+    Manifest {
+        /// Path to directory or GitHub repository URL
+        path_or_url: String,
+        /// Write the manifest to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Leave file content out, listing only paths, sizes, and token estimates
+        #[arg(long)]
+        no_content: bool,
+        /// Mark the included entries as numbered, with token estimates to match; content
+        /// is left as the file's text
+        #[arg(long)]
+        line_numbers: bool,
+        /// Maximum file size in KB (default: 100) [env: TECHDOCS_MAX_FILE_SIZE_KB]
+        #[arg(long)]
+        max_file_size_kb: Option<u64>,
+        /// Maximum total size in MB (default: 10) [env: TECHDOCS_MAX_TOTAL_SIZE_MB]
+        #[arg(long)]
+        max_total_size_mb: Option<u64>,
+    },
+    /// Check the API key, provider, git, cache directory, and configuration
+    Doctor {
+        /// Project directory or GitHub repository URL whose .techdocs.toml should be included
+        path_or_url: Option<String>,
+        /// Also verify the API key with a token count request
+        #[arg(long)]
+        online: bool,
+    },
+    /// Inspect the effective configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print the merged configuration from defaults, config files, environment, and flags
+    Show {
+        /// Project directory or GitHub repository URL whose .techdocs.toml should be included
+        path_or_url: Option<String>,
+    },
+    /// Print the filter preset and every exclude and include rule in the order they apply
+    ShowFilters {
+        /// Project directory or GitHub repository URL whose .techdocs.toml should be included
+        path_or_url: Option<String>,
+    },
+}
+
+/// Progress indicators on stderr, drawn only when stderr is a terminal
+struct Progress {
+    enabled: bool,
+    bar: Mutex<Option<ProgressBar>>,
+    /// The latest event, shown even without a terminal when the run is interrupted
+    last: Mutex<Option<ProgressEvent>>,
+}
+
+impl Progress {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled: enabled && std::io::stderr().is_terminal(),
+            bar: Mutex::new(None),
+            last: Mutex::new(None),
+        }
+    }
+
+    /// The active indicator, started on first use
+    fn bar(&self) -> Option<ProgressBar> {
+        if !self.enabled {
+            return None;
+        }
+        let mut bar = self.bar.lock().expect("progress lock");
+        Some(
+            bar.get_or_insert_with(|| {
+                let bar = ProgressBar::new_spinner();
+                bar.enable_steady_tick(Duration::from_millis(100));
+                bar
+            })
+            .clone(),
+        )
+    }
+
+    /// Update the display for a library progress event
+    fn handle(&self, event: &ProgressEvent) {
+        // Per-file and per-delta events say nothing about where an interrupted run was
+        if !matches!(
+            event,
+            ProgressEvent::FileIncluded { .. } | ProgressEvent::FileSkipped { .. } | ProgressEvent::LlmDelta { .. }
+        ) {
+            *self.last.lock().expect("progress lock") = Some(event.clone());
+        }
+        let Some(bar) = self.bar() else { return };
+        match event {
+            ProgressEvent::Clone { received_objects, total_objects, .. } => {
+                if bar.length() != Some(*total_objects as u64) {
+                    bar.set_style(
+                        ProgressStyle::with_template("{spinner} Cloning [{bar:30}] {pos}/{len} objects")
+                            .expect("valid template")
+                            .progress_chars("=> "),
+                    );
+                    bar.set_length(*total_objects as u64);
+                }
+                bar.set_position(*received_objects as u64);
+            }
+            ProgressEvent::Scan { entries_seen } => {
+                if bar.length().is_some() {
+                    bar.set_style(ProgressStyle::default_spinner());
+                    bar.unset_length();
+                }
+                bar.set_message(format!("Scanning: {} files and directories found", entries_seen));
+            }
+            ProgressEvent::Walk { files_seen, files_included } => {
+                if bar.length().is_some() {
+                    bar.set_style(ProgressStyle::default_spinner());
+                    bar.unset_length();
+                }
+                bar.set_message(format!("Collecting files: {} seen, {} included", files_seen, files_included));
+            }
+            ProgressEvent::WalkFinished { .. } => bar.set_message(""),
+            // Skipped files are listed by report_skipped, and generation has its own spinner
+            _ => {}
+        }
+    }
+
+    /// Show a spinner with `message` until the next update or `finish`
+    fn spin(&self, message: &str) {
+        if let Some(bar) = self.bar() {
+            bar.set_style(ProgressStyle::default_spinner());
+            bar.unset_length();
+            bar.set_message(message.to_string());
+        }
+    }
+
+    /// Tell the user on stderr which files were left out of the prompt, or cut short,
+    /// for size reasons. Files over the per-directory limit are counted once for their
+    /// directory.
+    fn report_skipped(&self, plan: &PromptPlan) {
+        let mut reported_dirs = Vec::new();
+        for file in &plan.files {
+            let line = match (file.skipped, file.truncated) {
+                // Files outside the root were already logged as warnings
+                (Some(SkipReason::BuildArtifact | SkipReason::OutsideRoot), _) => continue,
+                (Some(SkipReason::DirLimit), _) => {
+                    let dir = file.path.parent().unwrap_or(&file.path);
+                    if reported_dirs.contains(&dir) {
+                        continue;
+                    }
+                    reported_dirs.push(dir);
+                    let count = plan
+                        .files
+                        .iter()
+                        .filter(|other| other.skipped == Some(SkipReason::DirLimit) && other.path.parent() == Some(dir))
+                        .count();
+                    format!("Skipping {} more files in {} ({})", count, dir.display(), SkipReason::DirLimit)
+                }
+                (Some(reason), _) => format!("Skipping {} ({}, {} bytes)", file.path.display(), reason, file.size),
+                (None, Some(truncation)) => format!(
+                    "Truncating {} ({} bytes): {}",
+                    file.path.display(),
+                    file.size,
+                    truncation.marker().trim_end()
+                ),
+                (None, None) => continue,
+            };
+            match self.bar() {
+                Some(bar) => bar.suspend(|| eprintln!("{}", line)),
+                None => eprintln!("{}", line),
+            }
+        }
+    }
+
+    /// Remove the indicator before printing results
+    fn finish(&self) {
+        if let Some(bar) = self.bar.lock().expect("progress lock").take() {
+            bar.finish_and_clear();
+        }
+    }
+
+    /// Say how far an interrupted run got
+    fn report_interrupted(&self) {
+        self.finish();
+        match self.last.lock().expect("progress lock").take() {
+            Some(ProgressEvent::Clone { received_objects, total_objects, .. }) => {
+                eprintln!("Interrupted while cloning: {} of {} objects received", received_objects, total_objects);
+            }
+            Some(ProgressEvent::Scan { entries_seen }) => {
+                eprintln!("Interrupted while scanning: {} files and directories found", entries_seen);
+            }
+            Some(ProgressEvent::Walk { files_seen, files_included }) => {
+                eprintln!("Interrupted while collecting files: {} seen, {} included", files_seen, files_included);
+            }
+            Some(ProgressEvent::WalkFinished { files_included, files_skipped }) => {
+                eprintln!("Interrupted after collecting files: {} included, {} skipped", files_included, files_skipped);
+            }
+            Some(ProgressEvent::LlmRequestStarted { .. }) => {
+                eprintln!("Interrupted while generating; the request was dropped");
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Tell the user on stderr when generation ended early: at a stop sequence of the
+/// command's profile, or at the output token limit
+fn report_stop(stop_reason: Option<&str>, stop_sequence: Option<&str>) {
+    match stop_reason {
+        Some("stop_sequence") => eprintln!("Generation ended at stop sequence {:?}", stop_sequence.unwrap_or_default()),
+        Some("max_tokens") => eprintln!("Generation reached the output token limit; the result may be cut short"),
+        _ => {}
+    }
+}
+
+/// The output limit and extended thinking for `--max-tokens` and `--thinking-budget`.
+/// With a budget and no limit, the limit leaves the usual room for the answer on top.
+fn thinking_limits(
+    max_tokens: Option<u32>,
+    thinking_budget: Option<u32>,
+) -> TechDocsResult<(Option<u32>, Option<ThinkingConfig>)> {
+    let Some(budget) = thinking_budget else {
+        return Ok((max_tokens, None));
+    };
+    let max_tokens = max_tokens.unwrap_or(budget.saturating_add(DEFAULT_MAX_TOKENS));
+    let thinking = ThinkingConfig::new(budget, max_tokens).map_err(|_| {
+        TechDocsError::Config(format!(
+            "--thinking-budget must be at least {} and below --max-tokens ({}), not {}",
+            MIN_THINKING_BUDGET, max_tokens, budget
+        ))
+    })?;
+    Ok((Some(max_tokens), Some(thinking)))
+}
+
+/// Tell the user on stderr how much of the output went to thinking, when Claude thought
+fn report_thinking(thinking_tokens: u64) {
+    if thinking_tokens > 0 {
+        eprintln!("Claude thought for about {} tokens, counted in the output tokens", thinking_tokens);
+    }
+}
+
+/// Serve `preview` until Ctrl-C or `timeout` seconds, telling the user where, and open
+/// it in the browser when asked
+async fn serve_and_report(
+    preview: Preview,
+    timeout: Option<u64>,
+    open: bool,
+    cancel: &CancellationToken,
+) -> TechDocsResult<()> {
+    serve_preview(preview, timeout.map(Duration::from_secs), cancel, |url| {
+        eprintln!("Previewing at {} (Ctrl-C to stop)", url);
+        if open {
+            if let Err(e) = open_in_browser(url) {
+                eprintln!("Warning: {}", e);
+            }
+        }
+    })
+    .await
+}
+
+/// Tell the user on stderr what the run's Claude requests cost, when it made any
+fn report_spend(cost: &CostTracker) {
+    let spend = cost.spend();
+    if spend.calls > 0 {
+        eprintln!(
+            "Spent ${:.4} on {} Claude requests ({} input tokens, {} output tokens)",
+            spend.cost_usd, spend.calls, spend.usage.input_tokens, spend.usage.output_tokens
+        );
+    }
+}
+
+/// Separator written before each prompt appended with `prompt --append`
+fn prompt_part_header(source: &str) -> String {
+    format!(
+        "===== techdocs prompt: {} ({}) =====\n",
+        source,
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+    )
+}
+
+/// Write `prompt` to `output`, gzipped if asked, and return the number of bytes written.
+/// A new file replaces the old one atomically; a `part` header means the part is appended
+/// instead, as its own gzip member when compressing so the file still decompresses whole.
+fn write_prompt_file(output: &Path, prompt: &str, part: Option<&str>, gzip: bool) -> TechDocsResult<usize> {
+    let content = match part {
+        Some(header) => format!("{}{}\n", header, prompt),
+        None => prompt.to_string(),
+    };
+    let bytes = if gzip {
+        gzip_bytes(content.as_bytes())?
+    } else {
+        content.into_bytes()
+    };
+    match part {
+        Some(_) => append_to_file(output, &bytes)?,
+        None => write_atomic_bytes(output, &bytes)?,
+    }
+    Ok(bytes.len())
+}
+
+/// One README to generate: the default one or a translation
+#[derive(Debug)]
+struct ReadmeTarget {
+    /// Language requested with --lang
+    lang: Option<String>,
+    /// README file in the project this one replaces
+    file: PathBuf,
+    /// Current contents of `file`, read only when it is diffed or overwritten
+    existing: Option<String>,
+    kept_sections: Vec<KeptSection>,
+}
+
+impl ReadmeTarget {
+    /// The README in `lang` replacing `file`, with the current contents and kept sections
+    /// of `file` when `read` is set and it exists
+    fn read(lang: Option<String>, file: PathBuf, read: bool) -> TechDocsResult<Self> {
+        let existing = match fs::read_to_string(&file) {
+            _ if !read => None,
+            Ok(existing) => Some(existing),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(TechDocsError::File { action: "read", path: file, source: e }),
+        };
+        let kept_sections = match &existing {
+            Some(existing) => extract_kept_sections(existing)?,
+            None => Vec::new(),
+        };
+        Ok(Self { lang, file, existing, kept_sections })
+    }
+
+    /// The prompt for this README: `base` in its language, keeping its kept sections
+    fn prompt(&self, base: &ReadmePrompt) -> ReadmePrompt {
+        let mut prompt = base.clone();
+        if let Some(code) = &self.lang {
+            prompt.system.push_str(&language_instructions(code));
+        }
+        if !self.kept_sections.is_empty() {
+            prompt.content.push_str(&preserve_instructions(&self.kept_sections));
+        }
+        prompt
+    }
+
+    fn file_name(&self) -> String {
+        self.file.file_name().unwrap_or_default().to_string_lossy().into_owned()
+    }
+
+    /// The README to write from the `generated` one: with `sections` asked for, only
+    /// those are taken and spliced into the current README, warning about any Claude
+    /// left out. The kept sections go back in either way.
+    fn finish(&self, generated: String, sections: &[String]) -> String {
+        let readme = if sections.is_empty() {
+            generated
+        } else {
+            let produced: Vec<String> = headings(&generated).iter().map(|h| normalize_heading(&h.text)).collect();
+            for section in sections {
+                if !produced.contains(&normalize_heading(section)) {
+                    eprintln!("Warning: Claude did not produce a `{}` section", section);
+                }
+            }
+            // Sections replace their counterparts; without a README they stand alone
+            match &self.existing {
+                Some(existing) => splice_sections(existing, &generated, sections),
+                None => generated,
+            }
+        };
+        merge_kept_sections(&readme, &self.kept_sections)
+    }
+}
+
+/// How `readme --all-members` treats each member's README
+struct MemberReadmes<'a> {
+    /// Directory or URL given on the command line, recorded as the README's source
+    source: &'a str,
+    /// Built-in template from --template, instead of the one for each member's type
+    template: Option<&'a str>,
+    force: bool,
+    ignore_existing_readme: bool,
+    check_links: bool,
+    strict_output: bool,
+}
+
+/// Generate a README for each workspace member from its files and the workspace
+/// manifests, and write it into the member's directory. Every README is checked for
+/// being writable before the first request.
+async fn write_member_readmes(
+    path: &Path,
+    members: &[Member],
+    options: GenerateOptions,
+    context: &MemberReadmes<'_>,
+    progress: &Progress,
+    on_progress: ProgressFn<'_>,
+    cancelled: &dyn Fn() -> bool,
+) -> TechDocsResult<()> {
+    let mut targets = Vec::new();
+    for member in members {
+        let file = path.join(&member.path).join("README.md");
+        ensure_writable(&file, context.force)?;
+        targets.push((member, ReadmeTarget::read(None, file, true)?));
+    }
+
+    let client = ClaudeClient::new()?;
+    for (member, target) in &targets {
+        let mut options = options.clone();
+        options.files.include.extend(member.include_patterns());
+        if let (false, Some(existing)) = (context.ignore_existing_readme, &target.existing) {
+            options.files.exclude.push(format!("/{}/README.md", member.slash_path()));
+            options.existing_readme = Some(existing.clone());
+        }
+        let plan = PromptPlan::collect_cancellable(path, &options.files, on_progress, cancelled)?;
+        progress.report_skipped(&plan);
+
+        // Members of one workspace can be different kinds of project
+        let template = match context.template {
+            Some(name) => Some(name),
+            None if options.system_prompt.is_none() => Some(plan.project.template()),
+            None => None,
+        };
+        if let Some(name) = template {
+            let text = builtin_template(name).expect("template names are checked by clap");
+            options.system_prompt = Some(text.to_string());
+        }
+        options
+            .system_prompt
+            .get_or_insert_with(|| DEFAULT_README_PROMPT.to_string())
+            .push_str(&member.instructions());
+        let prompt = target.prompt(&ReadmePrompt::from_plan(context.source, &plan, &options)?);
+
+        progress.spin(&format!("Generating README for {} with Claude", member.name));
+        let result = prompt.generate_with_progress(&client, None, on_progress).await;
+        progress.finish();
+        let mut result = result?;
+        report_stop(result.stop_reason.as_deref(), result.stop_sequence.as_deref());
+        report_thinking(result.thinking_tokens);
+
+        result.content = merge_kept_sections(&result.content, &target.kept_sections);
+        options.post_processors.run(&mut result)?;
+        let readme = result.content;
+        let mut issues = validate_markdown(&readme, &path.join(&member.path));
+        if context.check_links {
+            progress.spin("Checking links");
+            issues.extend(check_links(&readme).await);
+            progress.finish();
+        }
+        for issue in &issues {
+            eprintln!("Warning: {} {}", target.file.display(), issue);
+        }
+        if context.strict_output && !issues.is_empty() {
+            return Err(TechDocsError::Validation(format!(
+                "{} problem(s) in the generated README for {}; it was not written",
+                issues.len(),
+                member.name
+            )));
+        }
+
+        let outcome = write_with_backup(&target.file, &readme, context.force)?;
+        if let Some(backup) = &outcome.backup {
+            eprintln!("Backed up existing README to {}", backup.display());
+        }
+        eprintln!("Wrote {} bytes to {}", outcome.bytes, outcome.path.display());
+    }
+    report_spend(&options.cost);
+    Ok(())
+}
+
+/// How `readme` generates and delivers the READMEs of one project, or of one workspace
+/// member
+#[derive(Default)]
+struct ReadmeRun<'a> {
+    /// Directory or URL given on the command line, recorded as the README's source
+    source: &'a str,
+    /// Member from --member, whose README is generated instead of the project's
+    member: Option<Member>,
+    /// Languages from --lang, in the order given
+    languages: &'a [String],
+    /// Built-in template from --template, instead of the one for the project type
+    template: Option<&'a str>,
+    /// Sections from --sections, generated on their own and spliced into the current README
+    sections: Vec<String>,
+    ignore_existing_readme: bool,
+    plan_in: Option<&'a Path>,
+    plan_out: Option<&'a Path>,
+    dry_run: bool,
+    incremental: bool,
+    /// Ask before sending, showing what it costs
+    confirm: bool,
+    yes: bool,
+    max_cost_usd: Option<f64>,
+    check_links: bool,
+    strict_output: bool,
+    diff: bool,
+    color_diff: bool,
+    /// Repository from --create-pr, sent the README as a pull request instead of writing it
+    pull_request: Option<Repository>,
+    write: bool,
+    output: Option<&'a Path>,
+    force: bool,
+    serve: bool,
+    serve_timeout: Option<u64>,
+    open: bool,
+}
+
+impl ReadmeRun<'_> {
+    /// Whether an existing README may be replaced. With --diff the user reviews the
+    /// change, and with --incremental the existing README is the one the last run wrote.
+    fn overwrite(&self) -> bool {
+        self.force || self.diff || self.incremental
+    }
+}
+
+/// The READMEs generated for each target in order, and the model and usage of the first
+struct GeneratedReadmes {
+    readmes: Vec<String>,
+    model: String,
+    usage: Usage,
+}
+
+/// Generate the READMEs of one project, or of one workspace member, and deliver them as
+/// `run` asks. Every README is checked for being writable, and every request for its
+/// cost, before the first request.
+async fn generate_project_readmes(
+    path: &Path,
+    mut options: GenerateOptions,
+    run: &ReadmeRun<'_>,
+    progress: &Progress,
+    on_progress: ProgressFn<'_>,
+    cancel: &CancellationToken,
+) -> TechDocsResult<Outcome> {
+    // A member's README lives in its own directory
+    let readme_dir = match &run.member {
+        Some(member) => path.join(&member.path),
+        None => path.to_path_buf(),
+    };
+    let readme_path = readme_dir.join("README.md");
+    if let Some(member) = &run.member {
+        options.files.include.extend(member.include_patterns());
+    }
+    let targets = readme_targets(&readme_path, run)?;
+
+    if !run.ignore_existing_readme {
+        // Sent as its own section rather than as one of the files
+        match fs::read(&readme_path) {
+            Ok(existing) => {
+                options.files.exclude.push(match &run.member {
+                    Some(member) => format!("/{}/README.md", member.slash_path()),
+                    None => "/README.md".to_string(),
+                });
+                options.existing_readme = Some(String::from_utf8_lossy(&existing).into_owned());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(TechDocsError::File { action: "read", path: readme_path, source: e }),
+        }
+    }
+
+    // Generate file list with prompt, or send the files a reviewed plan lists
+    let plan = match run.plan_in {
+        Some(file) => PlanFile::load(file)?.replay(path)?,
+        None => PromptPlan::collect_cancellable(path, &options.files, on_progress, &|| cancel.is_cancelled())?,
+    };
+    progress.report_skipped(&plan);
+    if let Some(file) = run.plan_out {
+        PlanFile::from_plan(run.source, &plan)?.save(file)?;
+        tracing::info!("Wrote the file plan to {}", file.display());
+    }
+    options.system_prompt = readme_system_prompt(options.system_prompt.take(), &plan, run);
+    let mut base_prompt = ReadmePrompt::from_plan(run.source, &plan, &options)?;
+
+    if run.dry_run {
+        progress.finish();
+        print_dry_run(&targets, &base_prompt);
+        return Ok(Outcome::Done);
+    }
+
+    // Compare with the last incremental run: skip Claude, or send only what changed
+    let mut incremental_state = None;
+    if run.incremental {
+        let target = &targets[0];
+        let state_file = match run.output {
+            Some(output) => state_path(output.parent().unwrap_or(Path::new(""))),
+            None => state_path(path),
+        };
+        let mut outputs = vec![target.file.clone(), target.file.with_extension("md.bak")];
+        outputs.extend(run.output.map(Path::to_path_buf));
+        let files = file_hashes(&plan, &outputs)?;
+        let settings = settings_hash(&target.prompt(&base_prompt));
+        match incremental_run(ReadmeState::load(&state_file), &settings, &files, &base_prompt, &plan)? {
+            Incremental::UpToDate(readme) => {
+                progress.finish();
+                let written = run.output.map(Path::to_path_buf).or_else(|| run.write.then(|| target.file.clone()));
+                report_up_to_date(&readme, written.as_deref())?;
+                return Ok(Outcome::Done);
+            }
+            Incremental::Update(prompt) => base_prompt = *prompt,
+            Incremental::Regenerate => {}
+        }
+        incremental_state = Some((state_file, settings, files));
+    }
+
+    // Check the cost of every request before anything is sent
+    if run.confirm || run.max_cost_usd.is_some() {
+        let model = base_prompt.model.as_deref().unwrap_or(DEFAULT_MODEL);
+        let estimate = CostEstimate::new(&targets, &base_prompt, &plan, model);
+        if let Some(ceiling) = run.max_cost_usd {
+            estimate.check_ceiling(ceiling, model)?;
+        }
+        if run.confirm && !run.yes {
+            progress.finish();
+            eprintln!("{}", estimate.describe(model));
+            if !confirm("Proceed?")? {
+                eprintln!("Aborted; nothing was sent");
+                return Ok(Outcome::Done);
+            }
+        }
+    }
+
+    let GeneratedReadmes { mut readmes, model, usage } =
+        generate_readmes(&targets, &base_prompt, &run.sections, &options.post_processors, progress, on_progress).await?;
+    validate_readmes(&targets, &readmes, &readme_dir, run, progress).await?;
+
+    // Which READMEs differ from the files they replace; all of them without --diff
+    let mut changed = vec![true; targets.len()];
+    if run.diff {
+        let diffs = readme_diffs(&targets, &readmes, run.color_diff);
+        for (index, (target, diff)) in targets.iter().zip(diffs).enumerate() {
+            match diff {
+                Some(changes) => print!("{}", changes),
+                None => {
+                    eprintln!("{} is up to date", target.file_name());
+                    changed[index] = false;
+                }
+            }
+        }
+        if !changed.contains(&true) {
+            if let Some((state_file, settings, files)) = incremental_state {
+                ReadmeState::new(settings, files, readmes.swap_remove(0)).save(&state_file)?;
+            }
+            report_spend(&base_prompt.cost);
+            return Ok(Outcome::Done);
+        }
+
+        if !run.write {
+            report_spend(&base_prompt.cost);
+            return Ok(Outcome::ReadmeChanged);
+        }
+        if !run.yes && !confirm("Write these changes?")? {
+            eprintln!("Aborted; no README was changed");
+            report_spend(&base_prompt.cost);
+            return Ok(Outcome::ReadmeChanged);
+        }
+    }
+
+    if let Some(repo) = &run.pull_request {
+        let github = GitHubClient::new()?;
+        progress.spin(&format!("Opening a pull request on {}", repo));
+        let outcome = github
+            .open_readme_pull_request(repo, &readmes[0], &PullRequestText::for_readme(&model, &usage))
+            .await;
+        progress.finish();
+        match outcome? {
+            PullRequestOutcome::Opened { url, .. } => println!("{}", url),
+            PullRequestOutcome::Unchanged => eprintln!("{} already has this README; no changes", repo),
+        }
+        return Ok(Outcome::Done);
+    }
+
+    write_readmes(&targets, &readmes, &changed, run)?;
+    // The first language is served; the others were written or printed above
+    let preview = run.serve.then(|| Preview {
+        title: targets[0].file_name(),
+        markdown: readmes[0].clone(),
+        stats: serde_json::json!({
+            "prompt": base_prompt.stats,
+            "model": model,
+            "usage": usage,
+            "spend": base_prompt.cost.spend(),
+        }),
+    });
+    if let Some((state_file, settings, files)) = incremental_state {
+        ReadmeState::new(settings, files, readmes.swap_remove(0)).save(&state_file)?;
+    }
+    report_spend(&base_prompt.cost);
+    if let Some(preview) = preview {
+        serve_and_report(preview, run.serve_timeout, run.open, cancel).await?;
+    }
+    Ok(Outcome::Done)
+}
+
+/// The directory above `path` whose manifests and README are sent as context: `root`
+/// from --context-root, or else the nearest one found unless `skip`
+fn readme_context_root(root: Option<PathBuf>, path: &Path, skip: bool) -> TechDocsResult<Option<PathBuf>> {
+    match root {
+        Some(root) if ParentContext::read(&root, path).is_none() => Err(TechDocsError::Config(format!(
+            "--context-root {} is not a directory above {}",
+            root.display(),
+            path.display()
+        ))),
+        Some(root) => Ok(Some(root)),
+        None if skip => Ok(None),
+        None => Ok(find_context_root(path)),
+    }
+}
+
+/// One README per language, the first replacing `readme_path` and the rest beside it,
+/// each with the kept sections of the file it replaces. That file is read only when it
+/// is diffed or overwritten, and checked for being writable with --write.
+fn readme_targets(readme_path: &Path, run: &ReadmeRun<'_>) -> TechDocsResult<Vec<ReadmeTarget>> {
+    let mut targets = Vec::new();
+    for (index, lang) in readme_languages(run.languages).into_iter().enumerate() {
+        let file = language_file(readme_path, index, lang.as_deref());
+        if run.write {
+            ensure_writable(&file, run.overwrite())?;
+        }
+        targets.push(ReadmeTarget::read(lang, file, run.write || run.diff)?);
+    }
+    Ok(targets)
+}
+
+/// The system prompt for `plan`'s README, given the `configured` one. --template wins,
+/// then a configured prompt file, then the template for the project type; the sections
+/// and member asked for are appended to it.
+fn readme_system_prompt(configured: Option<String>, plan: &PromptPlan, run: &ReadmeRun<'_>) -> Option<String> {
+    let template = match run.template {
+        Some(name) => Some(name),
+        None if configured.is_none() => Some(plan.project.template()),
+        None => None,
+    };
+    let mut system_prompt = configured;
+    if let Some(name) = template {
+        tracing::info!("Using the {} prompt template", name);
+        let text = builtin_template(name).expect("template names are checked by clap");
+        system_prompt = Some(text.to_string());
+    }
+    for section in &run.sections {
+        if !KNOWN_SECTIONS.contains(&normalize_heading(section).as_str()) {
+            eprintln!("Warning: unknown section `{}`; asking Claude for it anyway", section);
+        }
+    }
+    if !run.sections.is_empty() {
+        system_prompt
+            .get_or_insert_with(|| DEFAULT_README_PROMPT.to_string())
+            .push_str(&section_instructions(&run.sections));
+    }
+    if let Some(member) = &run.member {
+        system_prompt
+            .get_or_insert_with(|| DEFAULT_README_PROMPT.to_string())
+            .push_str(&member.instructions());
+    }
+    system_prompt
+}
+
+/// Print each README's prompt to stdout, and what sending them all would take to stderr
+fn print_dry_run(targets: &[ReadmeTarget], base: &ReadmePrompt) {
+    let mut tokens = 0;
+    for target in targets {
+        let prompt = target.prompt(base);
+        if targets.len() > 1 {
+            println!("===== {} =====", target.file_name());
+        }
+        print!("{}", render_dry_run(&prompt.system, &prompt.content));
+        tokens += prompt.estimated_tokens();
+    }
+    eprintln!(
+        "Dry run: {} files included, {} skipped, ~{} tokens ({} system prompt) in {} request(s)",
+        base.stats.files_included,
+        base.stats.files_skipped,
+        tokens,
+        estimate_tokens(base.system.len() as u64),
+        targets.len(),
+    );
+}
+
+/// What `readme --incremental` does after comparing with the last run
+#[derive(Debug)]
+enum Incremental {
+    /// No file changed since the last run, which generated this README
+    UpToDate(String),
+    /// Few files changed: send this prompt, revising the last README for them
+    Update(Box<ReadmePrompt>),
+    /// Generate the README from scratch
+    Regenerate,
+}
+
+/// Compare the project's `files` and the prompt's `settings`, as hashed by
+/// [`file_hashes`] and [`settings_hash`], with the `state` the last run saved
+fn incremental_run(
+    state: Option<ReadmeState>,
+    settings: &str,
+    files: &BTreeMap<String, String>,
+    base: &ReadmePrompt,
+    plan: &PromptPlan,
+) -> TechDocsResult<Incremental> {
+    let Some(state) = state else {
+        return Ok(Incremental::Regenerate);
+    };
+    if state.settings != settings {
+        eprintln!("The prompt or model changed since the last run; regenerating the README");
+        return Ok(Incremental::Regenerate);
+    }
+    let changes = state.changes(files);
+    if changes.is_empty() {
+        return Ok(Incremental::UpToDate(state.readme));
+    }
+    if !state.can_update(&changes) {
+        eprintln!("{} files changed; regenerating the README", changes.len());
+        return Ok(Incremental::Regenerate);
+    }
+    eprintln!("{} of {} files changed; updating the previous README", changes.len(), state.files.len());
+    Ok(Incremental::Update(Box::new(update_prompt(base, plan, &state.readme, &changes)?)))
+}
+
+/// Tell the user the last run's README is up to date, restoring it to `written` when
+/// that file is gone, or printing it when it goes to stdout
+fn report_up_to_date(readme: &str, written: Option<&Path>) -> TechDocsResult<()> {
+    match written {
+        Some(file) if !file.exists() => {
+            write_atomic(file, readme)?;
+            eprintln!("No files changed since the last run; restored {}", file.display());
+        }
+        Some(file) => eprintln!("{} is up to date; no files changed since the last run", file.display()),
+        None => {
+            eprintln!("README is up to date; no files changed since the last run");
+            println!("{}", readme);
+        }
+    }
+    Ok(())
+}
+
+/// Tokens and cost of sending the prompt of every README, summed over the languages
+#[derive(Debug)]
+struct CostEstimate {
+    files: usize,
+    requests: usize,
+    tokens: u64,
+    /// `None` when the model's pricing is unknown
+    input_cost_usd: Option<f64>,
+    max_cost_usd: Option<f64>,
+}
+
+impl CostEstimate {
+    fn new(targets: &[ReadmeTarget], base: &ReadmePrompt, plan: &PromptPlan, model: &str) -> Self {
+        let mut estimate = Self {
+            files: plan.included().count(),
+            requests: targets.len(),
+            tokens: 0,
+            input_cost_usd: Some(0.0),
+            max_cost_usd: Some(0.0),
+        };
+        for target in targets {
+            let prompt = target.prompt(base);
+            let mut report = TokenReport::new(plan, &prompt.system, model);
+            report.set_total(prompt.estimated_tokens(), false);
+            estimate.tokens += report.total_tokens;
+            estimate.input_cost_usd = estimate.input_cost_usd.zip(report.input_cost_usd).map(|(a, b)| a + b);
+            estimate.max_cost_usd = estimate.max_cost_usd.zip(report.max_cost_usd).map(|(a, b)| a + b);
+        }
+        estimate
+    }
+
+    /// Fail when the requests could cost more than `ceiling`, or when `model` has no
+    /// known pricing to tell
+    fn check_ceiling(&self, ceiling: f64, model: &str) -> TechDocsResult<()> {
+        match self.max_cost_usd {
+            Some(cost) if cost > ceiling => Err(TechDocsError::Budget(format!(
+                "estimated cost ${:.4} exceeds --max-cost-usd ${:.4}",
+                cost, ceiling
+            ))),
+            Some(_) => Ok(()),
+            None => Err(TechDocsError::Budget(format!(
+                "no pricing known for model {}, so --max-cost-usd cannot be enforced",
+                model
+            ))),
+        }
+    }
+
+    /// What is about to be sent and what it costs, for --confirm
+    fn describe(&self, model: &str) -> String {
+        let cost = match (self.input_cost_usd, self.max_cost_usd) {
+            (Some(input), Some(max)) => format!("${:.4} input, up to ${:.4}", input, max),
+            _ => "unknown pricing".to_string(),
+        };
+        let requests = match self.requests {
+            1 => String::new(),
+            n => format!(" over {} languages", n),
+        };
+        format!("{} files, ~{} input tokens{}, {} with {}", self.files, self.tokens, requests, cost, model)
+    }
+}
+
+/// Generate each target's README from `base` with Claude, one language at a time, and
+/// finish it with [`ReadmeTarget::finish`] and `post_processors`
+async fn generate_readmes(
+    targets: &[ReadmeTarget],
+    base: &ReadmePrompt,
+    sections: &[String],
+    post_processors: &PostProcessors,
+    progress: &Progress,
+    on_progress: ProgressFn<'_>,
+) -> TechDocsResult<GeneratedReadmes> {
+    let client = ClaudeClient::new()?;
+    let mut readmes = Vec::new();
+    let mut generated_by = None;
+    for target in targets {
+        let prompt = target.prompt(base);
+        progress.spin(&match &target.lang {
+            Some(code) => format!("Generating README ({}) with Claude", code),
+            None => "Generating README with Claude".to_string(),
+        });
+        let result = prompt.generate_with_progress(&client, None, on_progress).await;
+        progress.finish();
+        let mut result = result?;
+        report_stop(result.stop_reason.as_deref(), result.stop_sequence.as_deref());
+        report_thinking(result.thinking_tokens);
+        if let Some(code) = &target.lang {
+            eprintln!(
+                "{}: {} input tokens, {} output tokens",
+                code, result.usage.input_tokens, result.usage.output_tokens
+            );
+        }
+        generated_by.get_or_insert_with(|| (result.model.clone(), result.usage));
+        result.content = target.finish(std::mem::take(&mut result.content), sections);
+        post_processors.run(&mut result)?;
+        readmes.push(result.content);
+    }
+    let (model, usage) = generated_by.expect("every run has at least one language");
+    Ok(GeneratedReadmes { readmes, model, usage })
+}
+
+/// Warn about broken markdown and links in each README, or with --strict-output fail
+/// so that none is written
+async fn validate_readmes(
+    targets: &[ReadmeTarget],
+    readmes: &[String],
+    readme_dir: &Path,
+    run: &ReadmeRun<'_>,
+    progress: &Progress,
+) -> TechDocsResult<()> {
+    let mut invalid = 0;
+    for (target, readme) in targets.iter().zip(readmes) {
+        let mut issues = validate_markdown(readme, readme_dir);
+        if run.check_links {
+            progress.spin("Checking links");
+            issues.extend(check_links(readme).await);
+            progress.finish();
+        }
+        for issue in &issues {
+            eprintln!("Warning: {} {}", target.file_name(), issue);
+        }
+        invalid += issues.len();
+    }
+    if run.strict_output && invalid > 0 {
+        return Err(TechDocsError::Validation(format!(
+            "{} problem(s) in the generated README; nothing was written",
+            invalid
+        )));
+    }
+    Ok(())
+}
+
+/// The unified diff of each README against the file it replaces, or `None` where
+/// nothing changed
+fn readme_diffs(targets: &[ReadmeTarget], readmes: &[String], color: bool) -> Vec<Option<String>> {
+    targets
+        .iter()
+        .zip(readmes)
+        .map(|(target, readme)| {
+            let name = target.file_name();
+            unified_diff(target.existing.as_deref(), readme, &format!("a/{}", name), &format!("b/{}", name), color)
+        })
+        .collect()
+}
+
+/// Write each `changed` README over the file it replaces with --write, write every
+/// README to --output, or print them all to stdout
+fn write_readmes(
+    targets: &[ReadmeTarget],
+    readmes: &[String],
+    changed: &[bool],
+    run: &ReadmeRun<'_>,
+) -> TechDocsResult<()> {
+    for (index, (target, readme)) in targets.iter().zip(readmes).enumerate() {
+        if run.write {
+            if !changed[index] {
+                continue;
+            }
+            let outcome = write_with_backup(&target.file, readme, run.overwrite())?;
+            if let Some(backup) = &outcome.backup {
+                eprintln!("Backed up existing README to {}", backup.display());
+            }
+            eprintln!("Wrote {} bytes to {}", outcome.bytes, outcome.path.display());
+        } else if let Some(output) = run.output {
+            let output = language_file(output, index, target.lang.as_deref());
+            write_atomic(&output, readme)?;
+            eprintln!("Wrote {} bytes to {}", readme.len(), output.display());
+        } else {
+            if targets.len() > 1 {
+                println!("===== {} =====\n", target.file_name());
+            }
+            println!("{}", readme);
+        }
+    }
+    Ok(())
+}
+
+/// Names of the built-in prompt templates, for --template
+fn template_names() -> PossibleValuesParser {
+    PossibleValuesParser::new(BUILTIN_TEMPLATES.iter().map(|(name, _)| *name))
+}
+
+fn preset_names() -> PossibleValuesParser {
+    PossibleValuesParser::new(PRESETS.iter().map(|preset| preset.name))
+}
+
+/// Languages requested with --lang in order, without duplicates. No --lang means a
+/// single README in the prompt's own language.
+fn readme_languages(codes: &[String]) -> Vec<Option<String>> {
+    if codes.is_empty() {
+        return vec![None];
+    }
+    let mut languages: Vec<Option<String>> = Vec::new();
+    for code in codes {
+        if languages.iter().flatten().any(|l| l.eq_ignore_ascii_case(code)) {
+            continue;
+        }
+        if language_name(code).is_none() {
+            eprintln!("Warning: unknown language code `{}`; asking Claude for it anyway", code);
+        }
+        languages.push(Some(code.clone()));
+    }
+    languages
+}
+
+/// `base` for the first language, `base` with the language code inserted for the rest
+fn language_file(base: &Path, index: usize, lang: Option<&str>) -> PathBuf {
+    match lang {
+        Some(code) if index > 0 => localized_path(base, code),
+        _ => base.to_path_buf(),
+    }
+}
+
+/// Ask a yes/no question on stderr; anything but "y" or "yes" is a no.
+/// Without a terminal to ask on, this fails rather than waiting on stdin.
+fn confirm(question: &str) -> TechDocsResult<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Err(TechDocsError::Other(
+            format!("{} Cannot ask: stdin is not a terminal (pass --yes to proceed)", question).into(),
+        ));
+    }
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
 }
 
 #[tokio::main]
-async fn main() -> TechDocsResult<()> {
+async fn main() -> ExitCode {
     // Load environment variables from .env file
     dotenv::dotenv().ok();
 
     let args = Args::parse();
-    let exclude_patterns = args.exclude.unwrap_or_default();
+    let json_output = matches!(
+        &args.command,
+        Commands::Summary { format: OutputFormat::Json, .. }
+            | Commands::Review { format: OutputFormat::Json, .. }
+            | Commands::Tokens { format: OutputFormat::Json, .. }
+    );
+    let progress = Progress::new(!args.quiet && !json_output);
+    let cancel = CancellationToken::new();
+    tokio::spawn(cancel_on_ctrl_c(cancel.clone()));
+    let registry = args.print_metrics.then(|| {
+        let registry = Arc::new(Registry::default());
+        metrics::set_recorder(registry.clone());
+        registry
+    });
+
+    let result = tokio::select! {
+        biased;
+        result = run(args, &progress, &cancel) => result,
+        // Dropping the command stops its clone or Claude request and removes its temp dirs
+        _ = cancel.cancelled() => Err(TechDocsError::Cancelled),
+    };
+    if let Some(registry) = registry {
+        progress.finish();
+        eprint!("{}", registry.render_prometheus());
+    }
+    match result {
+        Ok(Outcome::Done) => ExitCode::SUCCESS,
+        Ok(Outcome::ReadmeChanged) => ExitCode::from(EXIT_README_CHANGED),
+        Err(e) => {
+            if matches!(e, TechDocsError::Cancelled) {
+                progress.report_interrupted();
+            }
+            eprintln!("Error: {}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// Cancel `cancel` on the first Ctrl-C, and exit on the second, for a run waiting
+/// somewhere it cannot notice, such as a confirmation prompt
+async fn cancel_on_ctrl_c(cancel: CancellationToken) {
+    if tokio::signal::ctrl_c().await.is_err() {
+        return;
+    }
+    cancel.cancel();
+    if tokio::signal::ctrl_c().await.is_ok() {
+        std::process::exit(EXIT_INTERRUPTED.into());
+    }
+}
+
+async fn run(args: Args, progress: &Progress, cancel: &CancellationToken) -> TechDocsResult<Outcome> {
+    init_logging(&LogOptions {
+        targets: vec!["techdocs".into()],
+        verbose: args.verbose,
+        quiet: args.quiet,
+        log_level: args.log_level.clone(),
+        format: match args.log_format {
+            LogFormatArg::Text => LogFormat::Text,
+            LogFormatArg::Json => LogFormat::Json,
+        },
+        no_color: args.no_color,
+    });
+    let no_color = args.no_color;
+    let on_progress = |event: &ProgressEvent| progress.handle(event);
+    let cancelled = || cancel.is_cancelled();
+    // Patterns from --exclude-from come first, then --exclude
+    let exclude = match (args.exclude_from.as_deref(), args.exclude) {
+        (None, exclude) => exclude,
+        (Some(file), exclude) => {
+            let mut patterns = read_exclude_file(file)?;
+            patterns.extend(exclude.unwrap_or_default());
+            Some(patterns)
+        }
+    };
+    let cli_config = PartialConfig {
+        exclude,
+        include: args.include,
+        preset: args.preset,
+        max_depth: args.max_depth,
+        max_walk_entries: args.max_walk_entries,
+        max_walk_secs: args.max_walk_secs,
+        ..Default::default()
+    };
+    // Not configuration file settings: a cloned project must not be able to turn them on
+    let (follow_symlinks, allow_external_symlinks) = (args.follow_symlinks, args.allow_external_symlinks);
 
     match args.command {
         Commands::List { path_or_url } => {
-            let (path, _temp_dir) = resolve_path(&path_or_url).await?;
+            let (path, _temp_dir) = resolve_path_cancellable(&path_or_url, &on_progress, cancel).await?;
+            progress.finish();
             validate_directory(&path)?;
-            list_files(&path, &exclude_patterns)?;
+            let config = Config::load(Some(&path), cli_config)?;
+            let options =
+                PromptOptions::from_config(&config).max_file_kb(u64::MAX).max_total_mb(u64::MAX).normalize(Normalize::Off);
+            for file in PromptPlan::collect(&path, &options)?.included() {
+                println!("{}", file.path.display());
+            }
         }
         Commands::Prompt {
             path_or_url,
             max_file_size_kb,
             max_total_size_mb,
+            output,
+            append,
+            gzip,
+            line_numbers,
+            max_files_per_dir,
+            group_by_dir,
+            large_files,
+            head_percent,
+            no_normalize,
+            keep_trailing_whitespace,
+            clipboard,
+            watch,
+            watch_generate,
+            yes,
+            member,
+            serve,
+            serve_timeout,
+            open,
         } => {
-            let (path, _temp_dir) = resolve_path(&path_or_url).await?;
+            let gzip = gzip || output.as_ref().is_some_and(|o| o.extension().is_some_and(|ext| ext == "gz"));
+            let (path, temp_dir) = resolve_path_cancellable(&path_or_url, &on_progress, cancel).await?;
             validate_directory(&path)?;
-            list_files_prompt(
-                &path,
-                &exclude_patterns,
+            if watch && temp_dir.is_some() {
+                return Err(TechDocsError::Config(
+                    "--watch needs a local directory; a repository URL is cloned once and never changes".into(),
+                ));
+            }
+            let mut config = Config::load(Some(&path), PartialConfig {
+                max_file_size_kb,
+                max_total_size_mb,
+                ..cli_config
+            })?;
+            // Our own output must not feed back into the prompt, whether appended to or
+            // rewritten on every change
+            let own_files: Vec<&Path> = output.iter().chain(watch_generate.iter()).map(PathBuf::as_path).collect();
+            config.exclude.extend(own_file_excludes(&path, &own_files));
+            let member = member.map(|name| workspace_member(&path, &name)).transpose()?;
+            let selection = PromptOptions {
+                max_files_per_dir: max_files_per_dir.map(NonZeroUsize::get),
+                ..PromptOptions::from_config(&config)
+            }
+            .include(member.iter().flat_map(Member::include_patterns))
+            .group_by_dir(group_by_dir)
+                .large_files(large_files.policy(head_percent))
+                .line_numbers(line_numbers)
+                .normalize(normalize_policy(no_normalize, keep_trailing_whitespace))
+                // Without --watch the prompt is written once, right after the walk
+                .keep_content(!watch)
+                .follow_symlinks(follow_symlinks)
+                .allow_external_symlinks(allow_external_symlinks);
+            let plan = PromptPlan::collect_cancellable(&path, &selection, &on_progress, &cancelled)?;
+            progress.finish();
+            progress.report_skipped(&plan);
+
+            if !watch {
+                let mut prompt = Vec::new();
+                plan.write_prompt(&mut prompt)?;
+                let prompt = String::from_utf8_lossy(&prompt);
+                if let Some(output) = &output {
+                    let part = append.then(|| prompt_part_header(&path_or_url));
+                    let written = write_prompt_file(output, &prompt, part.as_deref(), gzip)?;
+                    let verb = if append { "Appended" } else { "Wrote" };
+                    eprintln!("{} {} bytes to {}", verb, written, output.display());
+                }
+                if clipboard {
+                    match copy_to_clipboard(&prompt) {
+                        Ok(()) => {
+                            eprintln!(
+                                "Copied {} bytes (~{} tokens, {} files) to the clipboard",
+                                prompt.len(),
+                                estimate_tokens(prompt.len() as u64),
+                                plan.included().count(),
+                            );
+                            return Ok(Outcome::Done);
+                        }
+                        // Still deliver the prompt somewhere useful
+                        Err(e) if output.is_none() => eprintln!("Warning: {}; printing to stdout", e),
+                        Err(e) => eprintln!("Warning: {}", e),
+                    }
+                }
+                if serve {
+                    let mut stats = serde_json::json!(PromptStats::from(&plan));
+                    stats["estimated_tokens"] = estimate_tokens(prompt.len() as u64).into();
+                    let preview = Preview { title: "Prompt".into(), markdown: prompt.into_owned(), stats };
+                    serve_and_report(preview, serve_timeout, open, cancel).await?;
+                    return Ok(Outcome::Done);
+                }
+                if output.is_none() {
+                    print!("{}", prompt);
+                }
+                return Ok(Outcome::Done);
+            }
+
+            // Generating on every change spends money, so agree on the price up front
+            let options = GenerateOptions { cancel: cancel.clone(), ..GenerateOptions::from_config(&config)? };
+            if watch_generate.is_some() {
+                let model = config.model.as_deref().unwrap_or(DEFAULT_MODEL);
+                let system_prompt = options.system_prompt.as_deref().unwrap_or(DEFAULT_README_PROMPT);
+                let report = TokenReport::new(&plan, system_prompt, model);
+                let cost = match report.max_cost_usd {
+                    Some(max) => format!("up to ${:.4}", max),
+                    None => "an unknown cost".to_string(),
+                };
+                eprintln!(
+                    "--watch-generate sends ~{} tokens to {} on every change, {} each time",
+                    report.total_tokens, model, cost
+                );
+                if !yes && !confirm("Generate a README after every change?")? {
+                    eprintln!("Aborted; nothing was sent");
+                    return Ok(Outcome::Done);
+                }
+            }
+            let client = match &watch_generate {
+                Some(_) => Some(ClaudeClient::new()?),
+                None => None,
+            };
+
+            let mut watcher = ChangeWatcher::new(&path, &config.exclude, WATCH_DEBOUNCE)?;
+            let clear_screen = output.is_none() && std::io::stdout().is_terminal();
+            eprintln!("Watching {} for changes; press Ctrl-C to stop", path.display());
+
+            let mut plan = plan;
+            let mut previous: Option<(usize, usize)> = None;
+            loop {
+                let readme_prompt = ReadmePrompt::from_plan(&path_or_url, &plan, &options)?;
+                let prompt = &readme_prompt.content;
+                let files = readme_prompt.stats.files_included;
+
+                match &output {
+                    Some(output) => {
+                        write_prompt_file(output, prompt, None, gzip)?;
+                    }
+                    None => {
+                        if clear_screen {
+                            print!("\x1b[2J\x1b[H");
+                        }
+                        print!("{}", prompt);
+                    }
+                }
+                match previous {
+                    Some((previous_files, previous_bytes)) => eprintln!(
+                        "Rebuilt prompt: {} files ({:+}), {} bytes ({:+})",
+                        files,
+                        files as i64 - previous_files as i64,
+                        prompt.len(),
+                        prompt.len() as i64 - previous_bytes as i64,
+                    ),
+                    None => eprintln!("Built prompt: {} files, {} bytes", files, prompt.len()),
+                }
+                previous = Some((files, prompt.len()));
+
+                if let (Some(client), Some(readme_output)) = (&client, &watch_generate) {
+                    progress.spin("Generating README with Claude");
+                    let result = readme_prompt.generate_with_progress(client, None, &on_progress).await;
+                    progress.finish();
+                    // A failed generation should not end the watch
+                    match result {
+                        Ok(result) => {
+                            report_stop(result.stop_reason.as_deref(), result.stop_sequence.as_deref());
+                            write_atomic(readme_output, &result.content)?;
+                            eprintln!(
+                                "Wrote {} bytes to {} ({} input tokens, {} output tokens)",
+                                result.content.len(),
+                                readme_output.display(),
+                                result.usage.input_tokens,
+                                result.usage.output_tokens,
+                            );
+                        }
+                        Err(e) => eprintln!("Error: {}", e),
+                    }
+                }
+
+                let changed = tokio::select! {
+                    changed = watcher.next_change() => changed?,
+                    _ = cancel.cancelled() => return Ok(Outcome::Done),
+                };
+                tracing::debug!("{} paths changed, first {}", changed.len(), changed[0].display());
+                plan = PromptPlan::collect(&path, &selection)?;
+            }
+        }
+        Commands::Readme {
+            path_or_url,
+            output,
+            write,
+            force,
+            max_file_size_kb,
+            max_total_size_mb,
+            prompt_file,
+            template,
+            model,
+            max_tokens,
+            thinking_budget,
+            lang,
+            sections,
+            badges: add_badges,
+            toc,
+            footer,
+            max_files_per_dir,
+            group_by_dir,
+            large_files,
+            head_percent,
+            no_normalize,
+            keep_trailing_whitespace,
+            dry_run,
+            diff,
+            confirm: ask_first,
+            yes,
+            max_cost_usd,
+            color,
+            create_pr,
+            incremental,
+            plan_out,
+            plan_in,
+            git_context: _,
+            no_git_context,
+            no_infra_context,
+            no_remote_metadata,
+            ignore_existing_readme,
+            check_links: check_web_links,
+            strict_output,
+            member,
+            all_members,
+            context_root,
+            no_context_root,
+            serve,
+            serve_timeout,
+            open,
+        } => {
+            // Check before cloning so a bad combination fails fast
+            if write && is_remote(&path_or_url) {
+                return Err(TechDocsError::Output(
+                    "--write cannot be used with a repository URL: it is cloned into a temporary \
+                     directory that is deleted on exit, so there is nowhere persistent to write. \
+                     Use --output <path> instead".into(),
+                ));
+            }
+            if incremental && is_remote(&path_or_url) && output.is_none() {
+                return Err(TechDocsError::Output(
+                    "--incremental keeps its state beside the README, and a cloned repository is \
+                     deleted on exit. Use --output <path> instead".into(),
+                ));
+            }
+            if incremental && lang.len() > 1 {
+                return Err(TechDocsError::Config(
+                    "--incremental keeps the state of one README; pass at most one --lang".into(),
+                ));
+            }
+            let pull_request_repo = if create_pr {
+                if lang.len() > 1 {
+                    return Err(TechDocsError::Config(
+                        "--create-pr opens one pull request for README.md; pass at most one --lang".into(),
+                    ));
+                }
+                Some(Repository::from_url(&path_or_url)?)
+            } else {
+                None
+            };
+            let (path, _temp_dir) = resolve_path_cancellable(&path_or_url, &on_progress, cancel).await?;
+            let dir = inspect_directory(&path, if write { DirCheck::Capabilities } else { DirCheck::Readable })?;
+            let config = Config::load(Some(&path), PartialConfig {
                 max_file_size_kb,
                 max_total_size_mb,
-                std::io::stdout(),
+                prompt_file,
+                model,
+                footer,
+                ..cli_config
+            })?;
+
+            // Fail before calling Claude if the result has nowhere to go
+            if dir.writable == Some(false) {
+                return Err(TechDocsError::Output(format!(
+                    "--write cannot create files in {}; use --output <path> instead",
+                    path.display()
+                )));
+            }
+
+            // Load system prompt
+            let (max_tokens, thinking) = thinking_limits(max_tokens, thinking_budget)?;
+            let mut options = GenerateOptions {
+                max_tokens,
+                thinking,
+                git_context: !no_git_context,
+                infra_context: !no_infra_context,
+                cancel: cancel.clone(),
+                cost: CostTracker::new(max_cost_usd),
+                ..GenerateOptions::from_config(&config)?
+            };
+            options.files.max_files_per_dir = max_files_per_dir.map(NonZeroUsize::get);
+            options.files.group_by_dir = group_by_dir;
+            options.files.large_files = large_files.policy(head_percent);
+            options.files.normalize = normalize_policy(no_normalize, keep_trailing_whitespace);
+            options.files.follow_symlinks = follow_symlinks;
+            options.files.allow_external_symlinks = allow_external_symlinks;
+            options.context_root = readme_context_root(context_root, &path, no_context_root || all_members)?;
+            if let Some(root) = &options.context_root {
+                tracing::info!("Sending the manifests and README of {} as context", root.display());
+            }
+            if !no_remote_metadata {
+                options.remote_metadata = fetch_remote_metadata(&path_or_url).await;
+            }
+            // Run on each finished README, after any sections are spliced in and kept
+            options.post_processors = PostProcessors::builtin(
+                toc,
+                add_badges.then(|| badges(&detect_metadata(&path).with_remote(options.remote_metadata.as_ref()))),
+                config.footer.as_deref().map(Footer::new).transpose()?,
+            );
+
+            if all_members {
+                let members = detect_workspace(&path).ok_or_else(|| {
+                    TechDocsError::Config(format!("{} is not a Cargo, npm, or pnpm workspace", path.display()))
+                })?;
+                let context = MemberReadmes {
+                    source: &path_or_url,
+                    template: template.as_deref(),
+                    force,
+                    ignore_existing_readme,
+                    check_links: check_web_links,
+                    strict_output,
+                };
+                write_member_readmes(&path, &members, options, &context, progress, &on_progress, &cancelled).await?;
+                return Ok(Outcome::Done);
+            }
+            let run = ReadmeRun {
+                source: &path_or_url,
+                member: member.map(|name| workspace_member(&path, &name)).transpose()?,
+                languages: &lang,
+                template: template.as_deref(),
+                sections: sections.iter().map(|s| s.trim().to_string()).collect(),
+                ignore_existing_readme,
+                plan_in: plan_in.as_deref(),
+                plan_out: plan_out.as_deref(),
+                dry_run,
+                incremental,
+                confirm: ask_first,
+                yes,
+                max_cost_usd,
+                check_links: check_web_links,
+                strict_output,
+                diff,
+                color_diff: !no_color && color.enabled(),
+                pull_request: pull_request_repo,
+                write,
+                output: output.as_deref(),
+                force,
+                serve,
+                serve_timeout,
+                open,
+            };
+            return generate_project_readmes(&path, options, &run, progress, &on_progress, cancel).await;
+        }
+        Commands::Summary { path_or_url, format, model } => {
+            let (path, _temp_dir) = resolve_path_cancellable(&path_or_url, &on_progress, cancel).await?;
+            validate_directory(&path)?;
+            let config = Config::load(Some(&path), PartialConfig {
+                model,
+                ..cli_config
+            })?;
+
+            progress.spin("Generating summary with Claude");
+            let summary = generate_summary(&path, config.model.as_deref()).await;
+            progress.finish();
+            let summary = summary?;
+            match format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&summary)
+                        .map_err(|e| TechDocsError::Output(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => {
+                    println!("{}", summary.description);
+                    if !summary.topics.is_empty() {
+                        println!("\nTopics: {}", summary.topics.join(", "));
+                    }
+                    if let Some(license) = &summary.license {
+                        println!("License: {}", license);
+                    }
+                }
+            }
+        }
+        Commands::Review {
+            path_or_url,
+            base,
+            format,
+            max_file_size_kb,
+            max_total_size_mb,
+            model,
+        } => {
+            let (path, _temp_dir) = resolve_path_cancellable(&path_or_url, &on_progress, cancel).await?;
+            progress.finish();
+            validate_directory(&path)?;
+            let config = Config::load(Some(&path), PartialConfig {
+                max_file_size_kb,
+                max_total_size_mb,
+                model,
+                ..cli_config
+            })?;
+
+            let repo = open_repository(&path)?;
+            let base = match base.or_else(|| default_branch(&repo)) {
+                Some(base) => base,
+                None => {
+                    return Err(TechDocsError::Other(
+                        "Could not determine the default branch; pass --base <ref>".into(),
+                    ))
+                }
+            };
+            let base_commit = resolve_commit(&repo, &base)?;
+            let head_commit = resolve_commit(&repo, "HEAD")?;
+            let patches = diff_against_base(&repo, base_commit, head_commit, 5)?;
+
+            if patches.is_empty() {
+                eprintln!("No changes between {} and HEAD", base);
+                if format == OutputFormat::Json {
+                    println!("[]");
+                }
+                return Ok(Outcome::Done);
+            }
+
+            let mut diff_content = Vec::new();
+            let stats = diff_prompt(
+                &patches,
+                config.max_file_size_kb,
+                config.max_total_size_mb,
+                &mut diff_content,
             )?;
+            eprintln!(
+                "Reviewing {} files against {} ({} summarized, {} omitted)",
+                stats.included, base, stats.summarized, stats.omitted
+            );
+
+            let findings =
+                generate_review(&String::from_utf8_lossy(&diff_content), config.model.as_deref())
+                    .await?;
+
+            match format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&findings)
+                        .map_err(|e| TechDocsError::Output(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => {
+                    if findings.is_empty() {
+                        println!("No issues found.");
+                    }
+                    for (file, entries) in group_by_file(&findings) {
+                        println!("{}", file);
+                        for finding in entries {
+                            match finding.line {
+                                Some(line) => println!(
+                                    "  {}: [{}] {}",
+                                    line, finding.severity, finding.comment
+                                ),
+                                None => println!("  [{}] {}", finding.severity, finding.comment),
+                            }
+                        }
+                        println!();
+                    }
+                }
+            }
+        }
+        Commands::Architecture {
+            path_or_url,
+            output,
+            max_file_size_kb,
+            max_total_size_mb,
+            model,
+            max_tokens,
+            thinking_budget,
+        } => {
+            let (max_tokens, thinking) = thinking_limits(max_tokens, thinking_budget)?;
+            let (path, _temp_dir) = resolve_path_cancellable(&path_or_url, &on_progress, cancel).await?;
+            progress.finish();
+            validate_directory(&path)?;
+            let config = Config::load(Some(&path), PartialConfig {
+                max_file_size_kb,
+                max_total_size_mb,
+                model,
+                ..cli_config
+            })?;
+
+            let files = collect_config_files(&path, &config)?;
+            let graph = module_graph(&path, architecture_modules(&path, files));
+            if graph.modules.is_empty() {
+                return Err(TechDocsError::Other(
+                    format!("No modules found in {}", path.display()).into(),
+                ));
+            }
+            eprintln!("Found {} modules and {} dependencies", graph.modules.len(), graph.edges.len());
+
+            progress.spin("Generating architecture overview...");
+            let overview = generate_architecture(
+                &ClaudeClient::new()?,
+                &path,
+                &graph,
+                config.model.as_deref(),
+                config.max_total_size_mb.saturating_mul(1024 * 1024),
+                max_tokens,
+                thinking,
+            )
+            .await;
+            progress.finish();
+            let overview = overview?;
+
+            match output {
+                Some(output) => {
+                    write_atomic(&output, &overview)?;
+                    eprintln!("Wrote {} bytes to {}", overview.len(), output.display());
+                }
+                None => println!("{}", overview),
+            }
         }
-        Commands::Readme { path_or_url } => {
-            let (path, _temp_dir) = resolve_path(&path_or_url).await?;
+        Commands::Contributing {
+            path_or_url,
+            output,
+            write,
+            force,
+            model,
+        } => {
+            let (path, temp_dir) = resolve_path_cancellable(&path_or_url, &on_progress, cancel).await?;
+            progress.finish();
             validate_directory(&path)?;
+            let config = Config::load(Some(&path), PartialConfig { model, ..cli_config })?;
 
-            // Load system prompt
-            let mut system_prompt = String::new();
-            std::fs::File::open("prompts/readme.txt")?
-                .read_to_string(&mut system_prompt)?;
+            // Fail before calling Claude if the result has nowhere to go
+            let target = path.join("CONTRIBUTING.md");
+            if write {
+                if temp_dir.is_some() {
+                    return Err(TechDocsError::Output(
+                        "--write cannot be used with a repository URL: it was cloned into a temporary \
+                         directory that is deleted on exit. Use --output <path> instead".into(),
+                    ));
+                }
+                ensure_writable(&target, force)?;
+            }
+
+            let facts = detect_tooling(&path);
+            let files = collect_config_files(&path, &config)?;
+            let tree = directory_tree(&path, &files, TREE_DEPTH, TREE_LINES);
+            let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            eprintln!(
+                "Found {} commands, {} tool configs, and {} CI workflows",
+                facts.commands.len(),
+                facts.tool_configs.len(),
+                facts.ci.len()
+            );
+
+            progress.spin("Generating CONTRIBUTING.md...");
+            let request = contributing_request(&name, &facts, &tree, config.model.as_deref());
+            let result = ClaudeClient::new()?.complete(&request).await;
+            progress.finish();
+            let result = result?;
+            report_stop(result.stop_reason.as_deref(), result.stop_sequence.as_deref());
 
-            // Generate file list with prompt
-            let mut file_list = Vec::new();
-            list_files_prompt(&path, &exclude_patterns, 100, 10, &mut file_list)?;
+            if write {
+                let outcome = write_with_backup(&target, &result.text, force)?;
+                if let Some(backup) = &outcome.backup {
+                    eprintln!("Backed up existing CONTRIBUTING.md to {}", backup.display());
+                }
+                eprintln!("Wrote {} bytes to {}", outcome.bytes, outcome.path.display());
+            } else if let Some(output) = output {
+                write_atomic(&output, &result.text)?;
+                eprintln!("Wrote {} bytes to {}", result.text.len(), output.display());
+            } else {
+                println!("{}", result.text);
+            }
+        }
+        Commands::CommitMsg {
+            path,
+            commit_type,
+            max_diff_tokens,
+            model,
+        } => {
+            let repo = open_repository(&path)?;
+            let workdir = repo.workdir().map(Path::to_path_buf).unwrap_or_else(|| path.clone());
+            let config = Config::load(Some(&workdir), PartialConfig { model, ..cli_config })?;
+            let patches = staged_changes(&repo, 3)?;
+
+            progress.spin(&format!("Writing a commit message for {} staged files...", patches.len()));
+            let message = generate_commit_message(
+                &patches,
+                commit_type.map(CommitType::from),
+                config.model.as_deref(),
+                max_diff_tokens,
+            )
+            .await;
+            progress.finish();
+            println!("{}", message?);
+        }
+        Commands::Docs {
+            path_or_url,
+            output_dir,
+            max_file_size_kb,
+            max_total_size_mb,
+            model,
+            max_cost_usd,
+        } => {
+            let (path, temp_dir) = resolve_path_cancellable(&path_or_url, &on_progress, cancel).await?;
+            progress.finish();
+            validate_directory(&path)?;
+            let config = Config::load(Some(&path), PartialConfig {
+                max_file_size_kb,
+                max_total_size_mb,
+                model,
+                ..cli_config
+            })?;
 
-            // Generate README using Claude
-            let readme = generate_readme(&system_prompt, &String::from_utf8_lossy(&file_list))
+            let out_dir = match output_dir {
+                Some(dir) => dir,
+                None if temp_dir.is_some() => {
+                    return Err(TechDocsError::Output(
+                        "A repository URL is cloned into a temporary directory that is deleted on exit; \
+                         pass --output-dir <dir> to choose where the pages are written".into(),
+                    ));
+                }
+                None => path.join("docs"),
+            };
+
+            // Previously generated pages must not be fed back into the prompt
+            let files: Vec<_> = collect_config_files(&path, &config)?
+                .into_iter()
+                .filter(|f| !f.path.starts_with(&out_dir))
+                .collect();
+            let partitions = partition_files(&path, files);
+            if partitions.is_empty() {
+                return Err(TechDocsError::Other(
+                    format!("No modules found in {}", path.display()).into(),
+                ));
+            }
+            eprintln!("Documenting {} modules", partitions.len());
+
+            let cost = CostTracker::new(max_cost_usd);
+            let client = CostTrackedClient::new(ClaudeClient::new()?, cost.clone());
+            let outcomes = generate_docs(
+                &client,
+                &path,
+                &partitions,
+                &out_dir,
+                config.model.as_deref(),
+                config.max_total_size_mb.saturating_mul(1024 * 1024),
+            )
+            .await?;
+
+            let mut written = 0;
+            for outcome in &outcomes {
+                match &outcome.status {
+                    PageStatus::Written(page) => {
+                        written += 1;
+                        eprintln!("Wrote {} ({})", page.display(), outcome.module);
+                    }
+                    PageStatus::Skipped(reason) => {
+                        eprintln!("Skipped {}: {}", outcome.module, reason);
+                    }
+                }
+            }
+            eprintln!("Wrote {} of {} pages and {}", written, outcomes.len(), out_dir.join("index.md").display());
+            report_spend(&cost);
+            if written == 0 {
+                return Err(TechDocsError::Output("No documentation pages were written".into()));
+            }
+        }
+        Commands::Changelog {
+            path_or_url,
+            from,
+            to,
+            no_llm,
+            include_merges,
+            max_commits,
+            output,
+            append,
+            model,
+        } => {
+            let (path, _temp_dir) = resolve_path_cancellable(&path_or_url, &on_progress, cancel).await?;
+            progress.finish();
+            validate_directory(&path)?;
+            let config = Config::load(Some(&path), PartialConfig {
+                model,
+                ..cli_config
+            })?;
+
+            let repo = open_repository(&path)?;
+            let to_commit = resolve_commit(&repo, &to)?;
+
+            // Default to the most recent tag; when documenting a specific release, skip its own tag
+            let (from_commit, from_label) = match from {
+                Some(from) => (Some(resolve_commit(&repo, &from)?), from),
+                None => match latest_tag(&repo, to_commit, to != "HEAD")? {
+                    Some(tag) => (Some(tag.commit), tag.name),
+                    None => (None, "(first commit)".to_string()),
+                },
+            };
+
+            let commits = commits_between(&repo, from_commit, to_commit, max_commits)?;
+            let grouped = group_commits(&commits, include_merges);
+            let range = format!("{}..{}", from_label, to);
+            let title = if to == "HEAD" { "Unreleased" } else { to.as_str() };
+            let date = format_date(repo.find_commit(to_commit)?.time().seconds());
+            eprintln!("Found {} commits in {}", grouped.len(), range);
+            if commits.omitted > 0 {
+                eprintln!("Left out {} older commits past --max-commits {}", commits.omitted, max_commits);
+            }
+
+            let section = if no_llm || grouped.is_empty() {
+                render_markdown(title, &date, &grouped)
+            } else {
+                let input = format!(
+                    "Release title: {}\nDate: {}\n{}",
+                    title,
+                    date,
+                    render_prompt_input(&range, &grouped)
+                );
+                let result =
+                    generate_document_with_profile(DEFAULT_CHANGELOG_PROMPT, &input, config.model.as_deref(), &CHANGELOG_PROFILE)
+                        .await?;
+                report_stop(result.stop_reason.as_deref(), result.stop_sequence.as_deref());
+                result.text
+            };
+
+            match output {
+                Some(output) => {
+                    let content = if append {
+                        let existing = match std::fs::read_to_string(&output) {
+                            Ok(existing) => existing,
+                            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                                "# Changelog\n\n".to_string()
+                            }
+                            Err(e) => return Err(TechDocsError::File { action: "read", path: output, source: e }),
+                        };
+                        insert_section(&existing, &section)
+                    } else {
+                        section
+                    };
+                    write_atomic(&output, &content)?;
+                    eprintln!("Wrote {} bytes to {}", content.len(), output.display());
+                }
+                None => println!("{}", section),
+            }
+        }
+        Commands::ReleaseNotes {
+            path_or_url,
+            from,
+            to,
+            no_llm,
+            max_commits,
+            output,
+            model,
+        } => {
+            let (path, _temp_dir) = resolve_path_cancellable(&path_or_url, &on_progress, cancel).await?;
+            progress.finish();
+            validate_directory(&path)?;
+            let config = Config::load(Some(&path), PartialConfig {
+                model,
+                ..cli_config
+            })?;
+
+            let repo = open_repository(&path)?;
+            let head = resolve_commit(&repo, "HEAD")?;
+            let (to_commit, to_label) = match to {
+                Some(to) => (resolve_commit(&repo, &to)?, to),
+                None => match latest_tag(&repo, head, false)? {
+                    Some(tag) => (tag.commit, tag.name),
+                    None => (head, "HEAD".to_string()),
+                },
+            };
+            // With no earlier tag this is the first release, covering all history
+            let (from_commit, from_label) = match from {
+                Some(from) => (Some(resolve_commit(&repo, &from)?), Some(from)),
+                None => match latest_tag(&repo, to_commit, true)? {
+                    Some(tag) => (Some(tag.commit), Some(tag.name)),
+                    None => (None, None),
+                },
+            };
+
+            let commits = commits_between(&repo, from_commit, to_commit, max_commits)?;
+            let entries = classify_commits(&commits.commits);
+            let stats = ChangeStats::new(commits.total(), &diff_commits(&repo, from_commit, to_commit)?);
+            let title = if to_label == "HEAD" { "Unreleased" } else { to_label.as_str() };
+            let date = format_date(repo.find_commit(to_commit)?.time().seconds());
+            eprintln!(
+                "Found {} commits in {}..{}",
+                commits.total(),
+                from_label.as_deref().unwrap_or("(first commit)"),
+                to_label
+            );
+            if commits.omitted > 0 {
+                eprintln!("Left out {} older commits past --max-commits {}", commits.omitted, max_commits);
+            }
+
+            let notes = if no_llm || entries.is_empty() {
+                release_notes::render_markdown(title, &date, &entries, &stats)
+            } else {
+                let input = release_notes::render_prompt_input(
+                    title,
+                    &date,
+                    from_label.as_deref(),
+                    &entries,
+                    &stats,
+                );
+                let result = generate_document_with_profile(
+                    DEFAULT_RELEASE_NOTES_PROMPT,
+                    &input,
+                    config.model.as_deref(),
+                    &RELEASE_NOTES_PROFILE,
+                )
                 .await?;
+                report_stop(result.stop_reason.as_deref(), result.stop_sequence.as_deref());
+                result.text
+            };
 
-            println!("{}", readme);
+            match output {
+                Some(output) => {
+                    write_atomic(&output, &notes)?;
+                    eprintln!("Wrote {} bytes to {}", notes.len(), output.display());
+                }
+                None => println!("{}", notes),
+            }
+        }
+        Commands::Batch {
+            manifest,
+            output_dir,
+            kind,
+            jobs,
+            requests_per_minute,
+            model,
+            use_batch_api,
+            batch_timeout_minutes,
+            max_cost_usd,
+            resume,
+            retry_failed_only,
+        } => {
+            let entries = read_manifest(&manifest)?;
+            let kind = match kind {
+                BatchKindArg::Readme => BatchKind::Readme,
+                BatchKindArg::Summary => BatchKind::Summary,
+            };
+
+            // Progress is saved after every repository, so any run can be resumed
+            let (state_path, state) = match resume {
+                Some(path) => {
+                    let state = BatchState::load(&path)?;
+                    if state.kind != kind {
+                        return Err(TechDocsError::Config(format!(
+                            "{} is the state of a {} batch; pass the same --kind to resume it",
+                            path.display(),
+                            format!("{:?}", state.kind).to_lowercase()
+                        )));
+                    }
+                    let done = state.repos.values().filter(|repo| repo.status == RepoStatus::Done).count();
+                    eprintln!(
+                        "Resuming: {} repositories done, {} failed, ${:.4} already spent",
+                        done,
+                        state.repos.len() - done,
+                        state.spend.cost_usd
+                    );
+                    (path, state)
+                }
+                None => {
+                    fs::create_dir_all(&output_dir).map_err(|e| {
+                        TechDocsError::Output(format!("Failed to create {}: {}", output_dir.display(), e))
+                    })?;
+                    (output_dir.join(BATCH_STATE_FILE), BatchState::new(kind))
+                }
+            };
+            let cost = CostTracker::resume(max_cost_usd, state.spend);
+            let state = Arc::new(StateFile::new(state_path, state, cost.clone()));
+            let options = BatchOptions {
+                kind,
+                out_dir: output_dir,
+                jobs,
+                defaults: PartialConfig {
+                    model,
+                    ..cli_config
+                },
+                normalize: Normalize::Full,
+                state: Some(Arc::clone(&state)),
+                retry_failed_only,
+            };
+
+            let total = entries.len();
+            let outcomes = if use_batch_api {
+                progress.spin(&format!("Preparing and submitting {} repositories as a message batch", total));
+                let timeout = Duration::from_secs(batch_timeout_minutes.saturating_mul(60));
+                run_batch_api(&ClaudeClient::new()?, entries, options, timeout, &cost).await?
+            } else {
+                progress.spin(&format!("Processing {} repositories", total));
+                let client = RateLimitedClient::new(ClaudeClient::new()?, requests_per_minute);
+                run_batch(Arc::new(CostTrackedClient::new(client, cost.clone())), entries, options).await
+            };
+            progress.finish();
+
+            println!("{:<24}  {:<7}  {:>8}  {:>10}  {:>10}  Detail", "Name", "Status", "Seconds", "Input", "Output");
+            for outcome in &outcomes {
+                let (status, detail) = match &outcome.result {
+                    Ok(path) if outcome.resumed => ("done", path.display().to_string()),
+                    Ok(path) => ("ok", path.display().to_string()),
+                    Err(e) => ("failed", e.clone()),
+                };
+                println!(
+                    "{:<24}  {:<7}  {:>8.1}  {:>10}  {:>10}  {}",
+                    outcome.name,
+                    status,
+                    outcome.duration.as_secs_f64(),
+                    outcome.usage.input_tokens,
+                    outcome.usage.output_tokens,
+                    detail,
+                );
+            }
+
+            report_spend(&cost);
+            if outcomes.len() < total {
+                eprintln!(
+                    "{} repositories no earlier run reached were left out by --retry-failed-only",
+                    total - outcomes.len()
+                );
+            }
+            let failed = outcomes.iter().filter(|o| o.result.is_err()).count();
+            if failed > 0 {
+                eprintln!("Retry them with --resume {}", state.path().display());
+                return Err(TechDocsError::Other(
+                    format!("{} of {} repositories failed", failed, outcomes.len()).into(),
+                ));
+            }
+        }
+        Commands::Tokens {
+            path_or_url,
+            format,
+            exact,
+            max_file_size_kb,
+            max_total_size_mb,
+            prompt_file,
+            model,
+        } => {
+            let (path, _temp_dir) = resolve_path_cancellable(&path_or_url, &on_progress, cancel).await?;
+            validate_directory(&path)?;
+            let config = Config::load(Some(&path), PartialConfig {
+                max_file_size_kb,
+                max_total_size_mb,
+                prompt_file,
+                model,
+                ..cli_config
+            })?;
+
+            // Same selection as a real run
+            let mut options = GenerateOptions::from_config(&config)?;
+            options.files.normalize = Normalize::Full;
+            options.files.follow_symlinks = follow_symlinks;
+            options.files.allow_external_symlinks = allow_external_symlinks;
+            let system_prompt = options.system_prompt.as_deref().unwrap_or(DEFAULT_README_PROMPT);
+            let plan = PromptPlan::collect_cancellable(&path, &options.files, &on_progress, &cancelled)?;
+            progress.finish();
+            let model = config.model.as_deref().unwrap_or(DEFAULT_MODEL);
+            let mut report = TokenReport::new(&plan, system_prompt, model);
+
+            if exact {
+                match ClaudeClient::new() {
+                    Ok(client) => {
+                        let options = GenerateOptions { model: Some(model.to_string()), ..options.clone() };
+                        let request = ReadmePrompt::from_plan(&path_or_url, &plan, &options)?.request();
+                        report.set_total(client.count_tokens(&request).await?, true);
+                    }
+                    Err(e) => eprintln!("Warning: {}; showing the estimate instead", e),
+                }
+            }
+
+            match format {
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&report)
+                        .map_err(|e| TechDocsError::Output(e.to_string()))?;
+                    println!("{}", json);
+                }
+                OutputFormat::Text => print!("{}", report.render_table()),
+            }
+        }
+        Commands::Manifest {
+            path_or_url,
+            output,
+            no_content,
+            line_numbers,
+            max_file_size_kb,
+            max_total_size_mb,
+        } => {
+            let (path, _temp_dir) = resolve_path_cancellable(&path_or_url, &on_progress, cancel).await?;
+            validate_directory(&path)?;
+            let config = Config::load(Some(&path), PartialConfig {
+                max_file_size_kb,
+                max_total_size_mb,
+                ..cli_config
+            })?;
+            let options = PromptOptions::from_config(&config)
+                .line_numbers(line_numbers)
+                .normalize(Normalize::Full)
+                .follow_symlinks(follow_symlinks)
+                .allow_external_symlinks(allow_external_symlinks);
+            let manifest = FileManifest::collect(&path_or_url, &path, &options, !no_content, &on_progress, &cancelled)?;
+            progress.finish();
+            let json = serde_json::to_string_pretty(&manifest).map_err(|e| TechDocsError::Output(e.to_string()))?;
+            match output {
+                Some(output) => {
+                    write_atomic(&output, &json)?;
+                    eprintln!("Wrote {} bytes to {}", json.len(), output.display());
+                }
+                None => println!("{}", json),
+            }
+        }
+        Commands::Doctor { path_or_url, online } => {
+            let resolved = match &path_or_url {
+                Some(path_or_url) => Some(resolve_path(path_or_url).await?),
+                None => None,
+            };
+            let project_dir = resolved.as_ref().map(|(path, _)| path.as_path());
+            let (checks, config) = run_checks(project_dir, cli_config, online).await;
+
+            println!("{:<20}  {:<6}  Detail", "Check", "Status");
+            for check in &checks {
+                println!("{:<20}  {:<6}  {}", check.name, check.status, check.detail);
+            }
+            if let Some(config) = config {
+                println!("\n# effective configuration");
+                print!("{}", config.to_toml()?);
+            }
+
+            let failed = checks.iter().filter(|c| c.is_blocking()).count();
+            if failed > 0 {
+                return Err(TechDocsError::Other(
+                    format!("{} required check(s) failed", failed).into(),
+                ));
+            }
+        }
+        Commands::Config { command: ConfigCommands::Show { path_or_url } } => {
+            let resolved = match &path_or_url {
+                Some(path_or_url) => Some(resolve_path(path_or_url).await?),
+                None => None,
+            };
+            let project_dir = resolved.as_ref().map(|(path, _)| path.as_path());
+            let config = Config::load(project_dir, cli_config)?;
+
+            println!("# sources: {}", config.sources.join(" < "));
+            print!("{}", config.to_toml()?);
+        }
+        Commands::Config { command: ConfigCommands::ShowFilters { path_or_url } } => {
+            let resolved = match &path_or_url {
+                Some(path_or_url) => Some(resolve_path(path_or_url).await?),
+                None => None,
+            };
+            let project_dir = resolved.as_ref().map(|(path, _)| path.as_path());
+            let config = Config::load(project_dir, cli_config)?;
+            let options = PromptOptions::from_config(&config);
+
+            println!("# sources: {}", config.sources.join(" < "));
+            println!("# preset: {} ({})", options.preset.name, options.preset.description);
+            println!("# .gitignore, .ignore, and hidden files are left out before these rules");
+            for rule in filter_rules(&options) {
+                match rule.preset {
+                    Some(preset) => println!("{:<8} {:<24} preset {}", rule.action, rule.pattern, preset),
+                    None => println!("{:<8} {}", rule.action, rule.pattern),
+                }
+            }
+            if options.include.is_empty() && options.preset.include.is_empty() {
+                println!("# no include rules: every file not excluded is considered");
+            }
+            println!(
+                "# files over {} KB are skipped; the prompt stops at {} MB",
+                options.max_file_size_kb, options.max_total_size_mb
+            );
         }
     }
 
-    Ok(())
+    Ok(Outcome::Done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn project() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("Cargo.toml"), "[package]\nname = \"demo\"\n").unwrap();
+        fs::write(dir.path().join("README.md"), "# Demo\n\n## Usage\n\nRun it.\n\n## License\n\nMIT\n").unwrap();
+        dir
+    }
+
+    fn prompt(dir: &Path) -> (PromptPlan, ReadmePrompt) {
+        let plan = PromptPlan::collect(dir, &PromptOptions::new()).unwrap();
+        let prompt = ReadmePrompt::from_plan("demo", &plan, &GenerateOptions::default()).unwrap();
+        (plan, prompt)
+    }
+
+    #[test]
+    fn context_roots_must_be_above_the_project() {
+        let root = TempDir::new().unwrap();
+        let member = root.path().join("crates/demo");
+        fs::create_dir_all(&member).unwrap();
+
+        let found = readme_context_root(Some(root.path().to_path_buf()), &member, false).unwrap();
+        assert_eq!(found.as_deref(), Some(root.path()));
+        let error = readme_context_root(Some(member.clone()), root.path(), false).unwrap_err();
+        assert!(
+            matches!(&error, TechDocsError::Config(message) if message.contains("is not a directory above")),
+            "{}",
+            error
+        );
+        assert_eq!(readme_context_root(None, &member, true).unwrap(), None);
+    }
+
+    #[test]
+    fn targets_read_the_readmes_they_replace_only_when_needed() {
+        let project = project();
+        let readme = project.path().join("README.md");
+        let languages = ["en".to_string(), "ja".to_string()];
+
+        let printed = readme_targets(&readme, &ReadmeRun { languages: &languages, ..Default::default() }).unwrap();
+        assert_eq!(printed.iter().map(|target| target.file_name()).collect::<Vec<_>>(), ["README.md", "README.ja.md"]);
+        assert!(printed.iter().all(|target| target.existing.is_none()));
+
+        let run = ReadmeRun { languages: &languages, diff: true, ..Default::default() };
+        let diffed = readme_targets(&readme, &run).unwrap();
+        assert!(diffed[0].existing.as_deref().is_some_and(|existing| existing.starts_with("# Demo")));
+        assert!(diffed[1].existing.is_none());
+
+        // Writing over an existing README takes --force, or --diff to review it first
+        let error = readme_targets(&readme, &ReadmeRun { write: true, ..Default::default() }).unwrap_err();
+        assert!(error.to_string().contains("README.md"), "{}", error);
+        readme_targets(&readme, &ReadmeRun { write: true, diff: true, ..Default::default() }).unwrap();
+    }
+
+    #[test]
+    fn requested_sections_are_spliced_into_the_current_readme() {
+        let project = project();
+        let target = ReadmeTarget::read(None, project.path().join("README.md"), true).unwrap();
+        let sections = ["Usage".to_string()];
+
+        let readme = target.finish("## Usage\n\nRun `demo --help`.\n".into(), &sections);
+        assert_eq!(readme, "# Demo\n\n## Usage\n\nRun `demo --help`.\n\n## License\n\nMIT\n");
+        // Without sections the generated README replaces the whole file
+        assert_eq!(target.finish("# New\n".into(), &[]), "# New\n");
+    }
+
+    #[test]
+    fn the_system_prompt_comes_from_the_template_then_the_configured_prompt() {
+        let project = project();
+        let (plan, _) = prompt(project.path());
+        let rust = builtin_template("rust").unwrap();
+
+        let detected = readme_system_prompt(None, &plan, &ReadmeRun::default()).unwrap();
+        assert_eq!(detected, rust);
+        let configured = readme_system_prompt(Some("Be brief.".into()), &plan, &ReadmeRun::default()).unwrap();
+        assert_eq!(configured, "Be brief.");
+        let run = ReadmeRun { template: Some("rust"), sections: vec!["Usage".into()], ..Default::default() };
+        let chosen = readme_system_prompt(Some("Be brief.".into()), &plan, &run).unwrap();
+        assert_eq!(chosen, format!("{}{}", rust, section_instructions(&run.sections)));
+    }
+
+    #[test]
+    fn incremental_runs_compare_files_and_settings_with_the_last_run() {
+        let project = project();
+        let (plan, base) = prompt(project.path());
+        let files: BTreeMap<String, String> =
+            ["a", "b", "c", "d"].iter().map(|name| (name.to_string(), "hash".to_string())).collect();
+        let state = ReadmeState::new("settings".into(), files.clone(), "# Demo\n".into());
+
+        let run = |state: Option<ReadmeState>, settings: &str, files: &BTreeMap<String, String>| {
+            incremental_run(state, settings, files, &base, &plan).unwrap()
+        };
+        assert!(matches!(run(None, "settings", &files), Incremental::Regenerate));
+        let unchanged = run(Some(state.clone()), "settings", &files);
+        assert!(matches!(&unchanged, Incremental::UpToDate(readme) if readme == "# Demo\n"), "{:?}", unchanged);
+        assert!(matches!(run(Some(state.clone()), "other settings", &files), Incremental::Regenerate));
+
+        let mut changed = files.clone();
+        changed.insert("a".into(), "new hash".into());
+        assert!(matches!(run(Some(state.clone()), "settings", &changed), Incremental::Update(_)));
+        changed.insert("b".into(), "new hash".into());
+        assert!(matches!(run(Some(state), "settings", &changed), Incremental::Regenerate));
+    }
+
+    #[test]
+    fn cost_ceilings_fail_over_budget_or_without_pricing() {
+        let estimate = CostEstimate {
+            files: 3,
+            requests: 2,
+            tokens: 1200,
+            input_cost_usd: Some(0.01),
+            max_cost_usd: Some(0.05),
+        };
+        estimate.check_ceiling(0.05, "model").unwrap();
+        let error = estimate.check_ceiling(0.04, "model").unwrap_err();
+        assert!(matches!(&error, TechDocsError::Budget(message) if message.contains("$0.0500 exceeds")), "{}", error);
+        assert_eq!(
+            estimate.describe("model"),
+            "3 files, ~1200 input tokens over 2 languages, $0.0100 input, up to $0.0500 with model"
+        );
+
+        let unpriced = CostEstimate { requests: 1, input_cost_usd: None, max_cost_usd: None, ..estimate };
+        let error = unpriced.check_ceiling(100.0, "model").unwrap_err();
+        assert!(matches!(&error, TechDocsError::Budget(message) if message.contains("no pricing known")), "{}", error);
+        assert_eq!(unpriced.describe("model"), "3 files, ~1200 input tokens, unknown pricing with model");
+    }
+
+    #[test]
+    fn only_changed_readmes_have_diffs() {
+        let project = project();
+        let target = ReadmeTarget::read(None, project.path().join("README.md"), true).unwrap();
+        let current = target.existing.clone().unwrap();
+
+        let diffs = readme_diffs(std::slice::from_ref(&target), &[current], false);
+        assert_eq!(diffs, [None]);
+        let diffs = readme_diffs(&[target], &["# Demo\n".to_string()], false);
+        let diff = diffs[0].as_deref().unwrap();
+        assert!(diff.contains("--- a/README.md") && diff.contains("-## Usage"), "{}", diff);
+    }
 }
@@ -0,0 +1,268 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{write_atomic_bytes, Result, TechDocsError};
+
+/// Builds a cache key by hashing everything a result depends on
+#[derive(Default)]
+pub struct CacheKey(Sha256);
+
+impl CacheKey {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one input. Inputs are length-prefixed, so `("ab", "c")` and `("a", "bc")` differ.
+    pub fn part(mut self, part: impl AsRef<[u8]>) -> Self {
+        let part = part.as_ref();
+        self.0.update((part.len() as u64).to_le_bytes());
+        self.0.update(part);
+        self
+    }
+
+    /// The key as lowercase hex, safe to use as a file name
+    pub fn finish(self) -> String {
+        self.0.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+struct Entry<V> {
+    value: V,
+    stored_at: SystemTime,
+    last_used: u64,
+}
+
+struct Memory<V> {
+    entries: HashMap<String, Entry<V>>,
+    /// Bumped on every use, so the entry with the lowest `last_used` is the least recent
+    clock: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Stored<V> {
+    /// Seconds since the Unix epoch
+    stored_at: u64,
+    value: V,
+}
+
+/// Results kept in memory up to `capacity` entries, least recently used evicted first,
+/// and optionally as JSON files in a directory so they survive restarts. Entries older
+/// than `ttl` are treated as missing.
+pub struct ResultCache<V> {
+    memory: Mutex<Memory<V>>,
+    capacity: usize,
+    ttl: Duration,
+    dir: Option<PathBuf>,
+}
+
+impl<V> ResultCache<V>
+where
+    V: Clone + Serialize + DeserializeOwned,
+{
+    /// Create a cache, creating `dir` when given
+    pub fn new(capacity: usize, ttl: Duration, dir: Option<PathBuf>) -> Result<Self> {
+        if let Some(dir) = &dir {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                TechDocsError::Config(format!("cannot create cache directory {}: {}", dir.display(), e))
+            })?;
+        }
+        Ok(Self {
+            memory: Mutex::new(Memory {
+                entries: HashMap::new(),
+                clock: 0,
+            }),
+            capacity,
+            ttl,
+            dir,
+        })
+    }
+
+    /// The value stored under `key`, unless there is none or it expired
+    pub fn get(&self, key: &str) -> Option<V> {
+        {
+            let mut memory = self.memory.lock().unwrap();
+            memory.clock += 1;
+            let clock = memory.clock;
+            match memory.entries.get_mut(key) {
+                Some(entry) if self.is_fresh(entry.stored_at) => {
+                    entry.last_used = clock;
+                    return Some(entry.value.clone());
+                }
+                Some(_) => {
+                    memory.entries.remove(key);
+                }
+                None => {}
+            }
+        }
+
+        let path = self.file(key)?;
+        let stored: Stored<V> = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())?;
+        let stored_at = UNIX_EPOCH + Duration::from_secs(stored.stored_at);
+        if !self.is_fresh(stored_at) {
+            let _ = std::fs::remove_file(&path);
+            return None;
+        }
+        self.remember(key, stored.value.clone(), stored_at);
+        Some(stored.value)
+    }
+
+    /// Store `value` under `key`. Failing to write the file is logged, not returned,
+    /// because the result itself is fine.
+    pub fn insert(&self, key: &str, value: V) {
+        let stored_at = SystemTime::now();
+        if let Some(path) = self.file(key) {
+            let stored = Stored {
+                stored_at: stored_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+                value: &value,
+            };
+            let written = serde_json::to_vec(&stored)
+                .map_err(|e| TechDocsError::Output(e.to_string()))
+                .and_then(|bytes| write_atomic_bytes(&path, &bytes));
+            if let Err(e) = written {
+                tracing::warn!("cannot write cache entry {}: {}", path.display(), e);
+            }
+        }
+        self.remember(key, value, stored_at);
+    }
+
+    /// Remove every entry, in memory and on disk, returning how many there were
+    pub fn clear(&self) -> usize {
+        let mut removed: HashSet<String> = self.memory.lock().unwrap().entries.drain().map(|(key, _)| key).collect();
+        if let Some(dir) = &self.dir {
+            for path in cache_files(dir) {
+                if std::fs::remove_file(&path).is_ok() {
+                    if let Some(key) = path.file_stem().and_then(|stem| stem.to_str()) {
+                        removed.insert(key.to_string());
+                    }
+                }
+            }
+        }
+        removed.len()
+    }
+
+    fn remember(&self, key: &str, value: V, stored_at: SystemTime) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut memory = self.memory.lock().unwrap();
+        memory.clock += 1;
+        let last_used = memory.clock;
+        memory.entries.insert(key.to_string(), Entry { value, stored_at, last_used });
+        while memory.entries.len() > self.capacity {
+            let oldest = memory
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => memory.entries.remove(&oldest),
+                None => break,
+            };
+        }
+    }
+
+    fn is_fresh(&self, stored_at: SystemTime) -> bool {
+        stored_at.elapsed().is_ok_and(|age| age < self.ttl)
+    }
+
+    fn file(&self, key: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(format!("{}.json", key)))
+    }
+}
+
+/// The entry files in a cache directory
+fn cache_files(dir: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn key(content: &str) -> String {
+        CacheKey::new().part("https://github.com/acme/demo").part(content).finish()
+    }
+
+    #[test]
+    fn keys_change_with_any_input() {
+        assert_eq!(key("fn main() {}"), key("fn main() {}"));
+        assert_ne!(key("fn main() {}"), key("fn main() { run() }"));
+        assert_ne!(CacheKey::new().part("ab").part("c").finish(), CacheKey::new().part("a").part("bc").finish());
+        assert_eq!(key("x").len(), 64);
+    }
+
+    #[test]
+    fn a_hit_needs_the_same_content() {
+        let cache = ResultCache::new(8, DAY, None).unwrap();
+        cache.insert(&key("v1"), "README for v1".to_string());
+        assert_eq!(cache.get(&key("v1")).as_deref(), Some("README for v1"));
+        assert_eq!(cache.get(&key("v2")), None);
+    }
+
+    #[test]
+    fn the_least_recently_used_entry_is_evicted() {
+        let cache = ResultCache::new(2, DAY, None).unwrap();
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("c", 3);
+        assert_eq!((cache.get("a"), cache.get("b"), cache.get("c")), (Some(1), None, Some(3)));
+    }
+
+    #[test]
+    fn expired_entries_are_missing() {
+        let dir = FixtureTree::new();
+        let cache = ResultCache::new(8, Duration::ZERO, Some(dir.path().to_path_buf())).unwrap();
+        cache.insert("a", 1);
+        assert_eq!(cache.get("a"), None);
+        assert!(!dir.join("a.json").exists());
+    }
+
+    #[test]
+    fn entries_on_disk_outlive_the_cache() {
+        let dir = FixtureTree::new();
+        ResultCache::new(8, DAY, Some(dir.path().to_path_buf())).unwrap().insert("a", "kept".to_string());
+        let reopened: ResultCache<String> = ResultCache::new(0, DAY, Some(dir.path().to_path_buf())).unwrap();
+        assert_eq!(reopened.get("a").as_deref(), Some("kept"));
+    }
+
+    #[test]
+    fn a_corrupt_entry_file_is_a_miss() {
+        let dir = FixtureTree::new().file("a.json", "{\"stored_at\": 1").file("b.json", "{\"stored_at\": 1, \"value\": 7}");
+        let cache: ResultCache<String> = ResultCache::new(8, DAY, Some(dir.path().to_path_buf())).unwrap();
+        assert_eq!(cache.get("a"), None);
+        // A value of the wrong type is as unusable as a truncated file
+        assert_eq!(cache.get("b"), None);
+        cache.insert("a", "fresh".to_string());
+        assert_eq!(cache.get("a").as_deref(), Some("fresh"));
+    }
+
+    #[test]
+    fn clearing_removes_memory_and_disk_entries() {
+        let dir = FixtureTree::new().file("notes.txt", "not an entry");
+        let cache = ResultCache::new(8, DAY, Some(dir.path().to_path_buf())).unwrap();
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.clear(), 2);
+        assert_eq!(cache.get("a"), None);
+        assert!(dir.join("notes.txt").exists());
+        assert_eq!(cache.clear(), 0);
+    }
+}
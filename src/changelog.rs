@@ -0,0 +1,261 @@
+use std::fmt::Write;
+
+use crate::git::{CommitInfo, CommitRange};
+#[cfg(feature = "claude")]
+use crate::provider::GenerationProfile;
+
+/// Default system prompt for changelog generation, embedded at compile time
+pub const DEFAULT_CHANGELOG_PROMPT: &str = include_str!("../prompts/changelog.txt");
+#[cfg(feature = "claude")]
+/// A changelog entry is one level-2 section, so a second one ends it
+pub const CHANGELOG_PROFILE: GenerationProfile = GenerationProfile {
+    stop_sequences: &["\n## "],
+    target_length: None,
+    max_tokens: None,
+};
+
+/// A commit message parsed according to the Conventional Commits format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConventionalCommit {
+    /// Commit type such as `feat` or `fix`
+    pub kind: String,
+    /// Optional scope in parentheses
+    pub scope: Option<String>,
+    /// Whether the commit is marked as a breaking change
+    pub breaking: bool,
+    /// Description after the colon
+    pub description: String,
+}
+
+/// Parse a commit summary like `feat(cli)!: add changelog`.
+/// Returns `None` for messages that don't follow the convention.
+pub fn parse_conventional(summary: &str, body: &str) -> Option<ConventionalCommit> {
+    let (header, description) = summary.split_once(':')?;
+    let description = description.trim();
+    if description.is_empty() {
+        return None;
+    }
+
+    let (header, bang) = match header.strip_suffix('!') {
+        Some(header) => (header, true),
+        None => (header, false),
+    };
+    let (kind, scope) = match header.split_once('(') {
+        Some((kind, rest)) => {
+            let scope = rest.strip_suffix(')')?;
+            (kind, Some(scope.trim().to_string()).filter(|s| !s.is_empty()))
+        }
+        None => (header, None),
+    };
+    if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let breaking = bang
+        || body.lines().any(|line| {
+            line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:")
+        });
+
+    Some(ConventionalCommit {
+        kind: kind.to_ascii_lowercase(),
+        scope,
+        breaking,
+        description: description.to_string(),
+    })
+}
+
+/// Changelog section a commit belongs to, in display order
+const GROUPS: &[(&str, &[&str])] = &[
+    ("Breaking Changes", &[]),
+    ("Features", &["feat", "feature"]),
+    ("Bug Fixes", &["fix", "bugfix"]),
+    ("Performance", &["perf"]),
+    ("Refactoring", &["refactor"]),
+    ("Documentation", &["docs", "doc"]),
+    ("Tests", &["test", "tests"]),
+    ("Build & CI", &["build", "ci"]),
+    ("Chores", &["chore", "style", "revert"]),
+    ("Other Changes", &[]),
+];
+
+/// A commit placed into a changelog group
+#[derive(Debug, Clone)]
+pub struct ChangelogEntry {
+    pub commit: CommitInfo,
+    pub conventional: Option<ConventionalCommit>,
+}
+
+impl ChangelogEntry {
+    /// Human-readable description: the conventional description with its scope, or the raw summary
+    pub fn description(&self) -> String {
+        match &self.conventional {
+            Some(c) => match &c.scope {
+                Some(scope) => format!("**{}:** {}", scope, c.description),
+                None => c.description.clone(),
+            },
+            None => self.commit.summary.clone(),
+        }
+    }
+}
+
+/// Commits grouped into changelog sections, in display order
+#[derive(Debug, Clone, Default)]
+pub struct GroupedCommits {
+    pub groups: Vec<(String, Vec<ChangelogEntry>)>,
+    /// Number of merge commits left out of the groups
+    pub skipped_merges: usize,
+    /// Number of older commits past the commit limit, which are not in the groups
+    pub omitted: usize,
+}
+
+impl GroupedCommits {
+    /// Total number of commits across all groups
+    pub fn len(&self) -> usize {
+        self.groups.iter().map(|(_, entries)| entries.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Group commits by conventional-commit type, leaving out merge commits unless `include_merges`
+pub fn group_commits(range: &CommitRange, include_merges: bool) -> GroupedCommits {
+    let mut buckets: Vec<Vec<ChangelogEntry>> = vec![Vec::new(); GROUPS.len()];
+    let mut skipped_merges = 0;
+
+    for commit in &range.commits {
+        if commit.is_merge && !include_merges {
+            skipped_merges += 1;
+            continue;
+        }
+        let conventional = parse_conventional(&commit.summary, &commit.body);
+        let index = match &conventional {
+            Some(c) if c.breaking => 0,
+            Some(c) => GROUPS
+                .iter()
+                .position(|(_, kinds)| kinds.contains(&c.kind.as_str()))
+                .unwrap_or(GROUPS.len() - 1),
+            None => GROUPS.len() - 1,
+        };
+        buckets[index].push(ChangelogEntry {
+            commit: commit.clone(),
+            conventional,
+        });
+    }
+
+    let groups = GROUPS
+        .iter()
+        .zip(buckets)
+        .filter(|(_, entries)| !entries.is_empty())
+        .map(|((title, _), entries)| (title.to_string(), entries))
+        .collect();
+
+    GroupedCommits { groups, skipped_merges, omitted: range.omitted }
+}
+
+/// Format a Unix timestamp as a `YYYY-MM-DD` date
+pub fn format_date(seconds: i64) -> String {
+    chrono::DateTime::from_timestamp(seconds, 0)
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+/// Render grouped commits as a markdown CHANGELOG section without using the LLM
+pub fn render_markdown(title: &str, date: &str, grouped: &GroupedCommits) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "## {} - {}", title, date);
+    if grouped.is_empty() {
+        let _ = writeln!(out, "\nNo changes.");
+    }
+    for (group, entries) in &grouped.groups {
+        let _ = writeln!(out, "\n### {}\n", group);
+        for entry in entries {
+            let _ = writeln!(out, "- {} ({})", entry.description(), entry.commit.short_id());
+        }
+    }
+    if grouped.omitted > 0 {
+        let _ = writeln!(out, "\n{} older commits not listed.", grouped.omitted);
+    }
+    out
+}
+
+/// Render grouped commits with diff statistics as input for the changelog prompt
+pub fn render_prompt_input(range: &str, grouped: &GroupedCommits) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Range: {} ({} commits)", range, grouped.len());
+    if grouped.skipped_merges > 0 {
+        let _ = writeln!(out, "Merge commits omitted: {}", grouped.skipped_merges);
+    }
+    if grouped.omitted > 0 {
+        let _ = writeln!(out, "Older commits omitted: {} (past the commit limit)", grouped.omitted);
+    }
+    for (group, entries) in &grouped.groups {
+        let _ = writeln!(out, "\n## {}", group);
+        for entry in entries {
+            let commit = &entry.commit;
+            let _ = writeln!(
+                out,
+                "- {} {} ({} files, +{}/-{}, {})",
+                commit.short_id(),
+                commit.summary,
+                commit.files_changed,
+                commit.insertions,
+                commit.deletions,
+                format_date(commit.time),
+            );
+            for line in commit.body.lines().filter(|l| !l.trim().is_empty()).take(5) {
+                let _ = writeln!(out, "    {}", line.trim());
+            }
+        }
+    }
+    out
+}
+
+/// Insert a new section into an existing CHANGELOG, below its title and intro
+/// and above the previous release sections
+pub fn insert_section(existing: &str, section: &str) -> String {
+    let section = section.trim_end();
+    let insert_at = existing
+        .match_indices("\n## ")
+        .next()
+        .map(|(i, _)| i + 1)
+        .or_else(|| existing.starts_with("## ").then_some(0));
+
+    match insert_at {
+        Some(i) => format!("{}{}\n\n{}", &existing[..i], section, &existing[i..]),
+        None if existing.trim().is_empty() => format!("{}\n", section),
+        None => format!("{}\n\n{}\n", existing.trim_end(), section),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(summary: &str) -> CommitInfo {
+        CommitInfo {
+            id: "0123456789abcdef".to_string(),
+            summary: summary.to_string(),
+            body: String::new(),
+            author: "Test".to_string(),
+            time: 0,
+            is_merge: false,
+            files_changed: 1,
+            insertions: 1,
+            deletions: 0,
+        }
+    }
+
+    #[test]
+    fn omitted_commits_are_noted() {
+        let range = CommitRange { commits: vec![commit("feat: add a flag")], omitted: 42 };
+        let grouped = group_commits(&range, false);
+        assert_eq!(grouped.len(), 1);
+        assert!(render_prompt_input("v1.0..HEAD", &grouped).contains("Older commits omitted: 42"));
+        assert!(render_markdown("Unreleased", "2024-01-01", &grouped).ends_with("\n42 older commits not listed.\n"));
+
+        let grouped = group_commits(&CommitRange { omitted: 0, ..range }, false);
+        assert!(!render_prompt_input("v1.0..HEAD", &grouped).contains("Older commits"));
+    }
+}
@@ -0,0 +1,837 @@
+use std::borrow::Cow;
+use std::env;
+use std::time::Duration;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::plan::estimate_tokens;
+use crate::provider::{CompletionRequest, CompletionResult, LlmClient, TextFn, ToolSpec, Usage};
+use crate::{Result, TechDocsError};
+
+/// Model used when none is configured
+pub const DEFAULT_MODEL: &str = "claude-3-5-sonnet-latest";
+/// Output token limit used when none is configured
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+/// Anthropic API base URL, overridable with `ANTHROPIC_BASE_URL`
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// First wait between checks of a message batch in [`ClaudeClient::wait_for_batch`]
+pub const BATCH_POLL_INITIAL: Duration = Duration::from_secs(5);
+/// Longest wait between checks of a message batch; each wait doubles up to this
+pub const BATCH_POLL_MAX: Duration = Duration::from_secs(60);
+
+/// Pricing and limits for a family of Claude models
+#[derive(Debug, Clone, Copy)]
+pub struct ModelInfo {
+    /// Model name prefix, matched against the configured model
+    pub prefix: &'static str,
+    /// Price of one million input tokens in USD
+    pub input_usd_per_mtok: f64,
+    /// Price of one million output tokens in USD
+    pub output_usd_per_mtok: f64,
+    /// Maximum prompt plus output tokens
+    pub context_window: u64,
+}
+
+impl ModelInfo {
+    /// Estimated cost in USD for the given token counts
+    pub fn cost_usd(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input_usd_per_mtok
+            + output_tokens as f64 * self.output_usd_per_mtok)
+            / 1_000_000.0
+    }
+}
+
+/// Known models, most specific prefix first
+pub const MODELS: &[ModelInfo] = &[
+    ModelInfo { prefix: "claude-opus-4", input_usd_per_mtok: 15.0, output_usd_per_mtok: 75.0, context_window: 200_000 },
+    ModelInfo { prefix: "claude-sonnet-4", input_usd_per_mtok: 3.0, output_usd_per_mtok: 15.0, context_window: 200_000 },
+    ModelInfo { prefix: "claude-3-7-sonnet", input_usd_per_mtok: 3.0, output_usd_per_mtok: 15.0, context_window: 200_000 },
+    ModelInfo { prefix: "claude-3-5-sonnet", input_usd_per_mtok: 3.0, output_usd_per_mtok: 15.0, context_window: 200_000 },
+    ModelInfo { prefix: "claude-3-5-haiku", input_usd_per_mtok: 0.8, output_usd_per_mtok: 4.0, context_window: 200_000 },
+    ModelInfo { prefix: "claude-3-opus", input_usd_per_mtok: 15.0, output_usd_per_mtok: 75.0, context_window: 200_000 },
+    ModelInfo { prefix: "claude-3-haiku", input_usd_per_mtok: 0.25, output_usd_per_mtok: 1.25, context_window: 200_000 },
+];
+
+/// Look up pricing and limits for a model name such as `claude-3-5-haiku-latest`
+pub fn model_info(model: &str) -> Option<&'static ModelInfo> {
+    MODELS.iter().find(|info| model.starts_with(info.prefix))
+}
+
+/// Client for the Anthropic Messages API.
+///
+/// This replaces the `claude-client` crate, which only sends a system prompt and one
+/// message and returns the text. Summaries need the usage and stop reason of a response
+/// and a forced tool call for structured output; later commands add streaming, extended
+/// thinking, token counting, message batches, and a base URL for tests and proxies. The
+/// client implements [`LlmClient`], so code that generates documents does not depend on it.
+#[derive(Clone)]
+pub struct ClaudeClient {
+    http: reqwest::Client,
+    api_key: String,
+    base_url: String,
+}
+
+impl std::fmt::Debug for ClaudeClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the API key
+        f.debug_struct("ClaudeClient")
+            .field("base_url", &self.base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ClaudeClient {
+    /// Create a client from `ANTHROPIC_API_KEY` and the optional `ANTHROPIC_BASE_URL`
+    pub fn new() -> Result<Self> {
+        let api_key = env::var("ANTHROPIC_API_KEY")
+            .map_err(|_| TechDocsError::ClaudeClient("ANTHROPIC_API_KEY is not set".into()))?;
+        let base_url = env::var("ANTHROPIC_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.into());
+        Ok(Self::with_base_url(api_key, base_url))
+    }
+
+    /// Create a client with an explicit API key and base URL
+    pub fn with_base_url(api_key: impl Into<String>, base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            api_key: api_key.into(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Count the input tokens of a request with the token counting endpoint
+    pub async fn count_tokens(&self, request: &CompletionRequest) -> Result<u64> {
+        let body = CountTokensRequest {
+            model: request.model.as_deref().unwrap_or(DEFAULT_MODEL),
+            system: request.system_prompt(),
+            messages: vec![Message {
+                role: "user",
+                content: &request.content,
+            }],
+        };
+        let bytes = self.post("/v1/messages/count_tokens", &body).await?;
+        let parsed: CountTokensResponse = serde_json::from_slice(&bytes)
+            .map_err(|e| TechDocsError::Claude(format!("Invalid response: {}", e)))?;
+        Ok(parsed.input_tokens)
+    }
+
+    /// Submit `requests` as one message batch, processed within a day at half the price
+    /// of sending them one by one
+    pub async fn create_batch(&self, requests: &[BatchRequest]) -> Result<BatchHandle> {
+        let body = CreateBatchRequest {
+            requests: requests
+                .iter()
+                .map(|request| BatchRequestBody {
+                    custom_id: &request.custom_id,
+                    params: MessagesRequest::new(&request.request),
+                })
+                .collect(),
+        };
+        let bytes = self.post("/v1/messages/batches", &body).await?;
+        parse_batch(&bytes)
+    }
+
+    /// The current state of the batch `id`
+    pub async fn poll_batch(&self, id: &str) -> Result<BatchHandle> {
+        let response = self.get(&format!("/v1/messages/batches/{}", id)).await?;
+        let bytes = response.bytes().await.map_err(|e| TechDocsError::ClaudeClient(e.to_string()))?;
+        parse_batch(&bytes)
+    }
+
+    /// Ask for the batch `id` to stop; requests already processed keep their results
+    pub async fn cancel_batch(&self, id: &str) -> Result<BatchHandle> {
+        let bytes = self.post(&format!("/v1/messages/batches/{}/cancel", id), &serde_json::json!({})).await?;
+        parse_batch(&bytes)
+    }
+
+    /// Poll the batch `id` until it has ended, waiting [`BATCH_POLL_INITIAL`] at first
+    /// and twice as long after each check up to [`BATCH_POLL_MAX`]. A batch still
+    /// running after `timeout` is cancelled and fails with [`TechDocsError::Claude`].
+    pub async fn wait_for_batch(&self, id: &str, timeout: Duration) -> Result<BatchHandle> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut wait = BATCH_POLL_INITIAL;
+        loop {
+            let batch = self.poll_batch(id).await?;
+            if batch.is_ended() {
+                return Ok(batch);
+            }
+            let counts = &batch.request_counts;
+            tracing::info!(
+                "Batch {} is {}: {} processing, {} succeeded, {} errored",
+                id,
+                batch.processing_status,
+                counts.processing,
+                counts.succeeded,
+                counts.errored
+            );
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                if let Err(e) = self.cancel_batch(id).await {
+                    tracing::warn!("Cannot cancel batch {}: {}", id, e);
+                }
+                return Err(TechDocsError::Claude(format!(
+                    "batch {} did not end within {} seconds and was cancelled",
+                    id,
+                    timeout.as_secs()
+                )));
+            }
+            tokio::time::sleep(wait.min(deadline - now)).await;
+            wait = (wait * 2).min(BATCH_POLL_MAX);
+        }
+    }
+
+    /// Read the results of an ended batch, passing each to `on_result` as it arrives.
+    /// Results come in no particular order; match them to requests by `custom_id`.
+    pub async fn fetch_batch_results(
+        &self,
+        id: &str,
+        mut on_result: impl FnMut(BatchResult) -> Result<()>,
+    ) -> Result<()> {
+        let mut response = self.get(&format!("/v1/messages/batches/{}/results", id)).await?;
+        let mut buffer = Vec::new();
+        let mut handle_line = |line: &[u8]| -> Result<()> {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                return Ok(());
+            }
+            let parsed: BatchResultLine = serde_json::from_slice(line)
+                .map_err(|e| TechDocsError::Claude(format!("Invalid batch result: {}", e)))?;
+            on_result(parsed.into())
+        };
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| TechDocsError::ClaudeClient(e.to_string()))?
+        {
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                handle_line(&line)?;
+            }
+        }
+        handle_line(&buffer)
+    }
+
+    /// POST a JSON body to the API and return the successful response body
+    async fn post<T: Serialize>(&self, path: &str, body: &T) -> Result<Vec<u8>> {
+        let response = self.send(path, body).await?;
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| TechDocsError::ClaudeClient(e.to_string()))?;
+        Ok(bytes.to_vec())
+    }
+
+    /// POST a JSON body to the API and return the response, failing on an error status
+    async fn send<T: Serialize>(&self, path: &str, body: &T) -> Result<reqwest::Response> {
+        self.execute(self.http.post(format!("{}{}", self.base_url, path)).json(body)).await
+    }
+
+    /// GET from the API and return the response, failing on an error status
+    async fn get(&self, path: &str) -> Result<reqwest::Response> {
+        self.execute(self.http.get(format!("{}{}", self.base_url, path))).await
+    }
+
+    /// Send a request with the API key and version headers, failing on an error status
+    async fn execute(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let response = request
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .send()
+            .await
+            .map_err(|e| TechDocsError::ClaudeClient(e.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let bytes = response
+                .bytes()
+                .await
+                .map_err(|e| TechDocsError::ClaudeClient(e.to_string()))?;
+            let message = match serde_json::from_slice::<ErrorEnvelope>(&bytes) {
+                Ok(envelope) => format!("{} ({}): {}", status, envelope.error.kind, envelope.error.message),
+                Err(_) => format!("{}: {}", status, String::from_utf8_lossy(&bytes)),
+            };
+            if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                return Err(TechDocsError::RateLimited(message));
+            }
+            return Err(TechDocsError::Claude(message));
+        }
+        Ok(response)
+    }
+
+    /// Send a single user message and return the text of the reply
+    pub async fn send_message(
+        &self,
+        model: Option<&str>,
+        system_prompt: &str,
+        content: &str,
+    ) -> Result<String> {
+        let mut request = CompletionRequest::new(system_prompt, content);
+        request.model = model.map(String::from);
+        Ok(self.complete(&request).await?.text)
+    }
+}
+
+/// One request of a message batch
+#[derive(Debug, Clone)]
+pub struct BatchRequest {
+    /// Names the request's result: 1 to 64 ASCII letters, digits, `-`, or `_`, unique
+    /// in the batch
+    pub custom_id: String,
+    pub request: CompletionRequest,
+}
+
+/// A submitted message batch and how far it has got
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchHandle {
+    /// Such as `msgbatch_01HkcTjaV5uDC8jWR4ZsDV8d`
+    pub id: String,
+    /// `in_progress`, `canceling`, or `ended`
+    pub processing_status: String,
+    pub request_counts: BatchRequestCounts,
+}
+
+impl BatchHandle {
+    /// Whether every request has a result, so [`ClaudeClient::fetch_batch_results`] can
+    /// read them
+    pub fn is_ended(&self) -> bool {
+        self.processing_status == "ended"
+    }
+}
+
+/// How many requests of a batch are in each state
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct BatchRequestCounts {
+    pub processing: u64,
+    pub succeeded: u64,
+    pub errored: u64,
+    pub canceled: u64,
+    pub expired: u64,
+}
+
+/// The outcome of one request of a batch
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    /// The [`BatchRequest::custom_id`] of the request
+    pub custom_id: String,
+    /// The response, or why there is none: an API error, or the batch being cancelled or
+    /// expiring first
+    pub result: std::result::Result<CompletionResult, String>,
+}
+
+impl From<BatchResultLine> for BatchResult {
+    fn from(line: BatchResultLine) -> Self {
+        let result = match line.result {
+            BatchResultBody::Succeeded { message } => Ok(message.into()),
+            BatchResultBody::Errored { error } => Err(format!("{}: {}", error.error.kind, error.error.message)),
+            BatchResultBody::Canceled => Err("the batch was cancelled first".to_string()),
+            BatchResultBody::Expired => Err("the batch expired first".to_string()),
+        };
+        Self { custom_id: line.custom_id, result }
+    }
+}
+
+fn parse_batch(bytes: &[u8]) -> Result<BatchHandle> {
+    serde_json::from_slice(bytes).map_err(|e| TechDocsError::Claude(format!("Invalid batch: {}", e)))
+}
+
+#[derive(Serialize)]
+struct CreateBatchRequest<'a> {
+    requests: Vec<BatchRequestBody<'a>>,
+}
+
+#[derive(Serialize)]
+struct BatchRequestBody<'a> {
+    custom_id: &'a str,
+    params: MessagesRequest<'a>,
+}
+
+/// A line of a batch's JSONL results
+#[derive(Deserialize)]
+struct BatchResultLine {
+    custom_id: String,
+    result: BatchResultBody,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BatchResultBody {
+    Succeeded { message: MessagesResponse },
+    Errored { error: ErrorEnvelope },
+    Canceled,
+    Expired,
+}
+
+#[derive(Serialize)]
+struct CountTokensRequest<'a> {
+    model: &'a str,
+    system: Cow<'a, str>,
+    messages: Vec<Message<'a>>,
+}
+
+#[derive(Deserialize)]
+struct CountTokensResponse {
+    input_tokens: u64,
+}
+
+#[derive(Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    system: Cow<'a, str>,
+    messages: Vec<Message<'a>>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop_sequences: &'a [String],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<&'a ToolSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<ToolChoice<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<Thinking>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+impl<'a> MessagesRequest<'a> {
+    fn new(request: &'a CompletionRequest) -> Self {
+        Self {
+            model: request.model.as_deref().unwrap_or(DEFAULT_MODEL),
+            max_tokens: request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            system: request.system_prompt(),
+            messages: vec![Message {
+                role: "user",
+                content: &request.content,
+            }],
+            stop_sequences: &request.stop_sequences,
+            tools: request.tool.iter().collect(),
+            tool_choice: request.tool.as_ref().map(|tool| ToolChoice {
+                kind: "tool",
+                name: &tool.name,
+            }),
+            thinking: request.thinking.map(|thinking| Thinking {
+                kind: "enabled",
+                budget_tokens: thinking.budget_tokens,
+            }),
+            stream: false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Message<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct ToolChoice<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct Thinking {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    budget_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct MessagesResponse {
+    model: String,
+    content: Vec<ContentBlock>,
+    stop_reason: Option<String>,
+    stop_sequence: Option<String>,
+    #[serde(default)]
+    usage: Usage,
+}
+
+impl From<MessagesResponse> for CompletionResult {
+    fn from(response: MessagesResponse) -> Self {
+        let mut result = CompletionResult {
+            model: response.model,
+            usage: response.usage,
+            stop_reason: response.stop_reason,
+            stop_sequence: response.stop_sequence,
+            ..Default::default()
+        };
+        for block in response.content {
+            match block {
+                ContentBlock::Text { text } => result.text.push_str(&text),
+                ContentBlock::ToolUse { input } => result.tool_input = Some(input),
+                ContentBlock::Thinking { thinking } => result.thinking_tokens += estimate_tokens(thinking.len() as u64),
+                ContentBlock::Other => {}
+            }
+        }
+        result
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text { text: String },
+    ToolUse { input: serde_json::Value },
+    /// The model's reasoning under extended thinking, kept out of the text
+    Thinking { thinking: String },
+    #[serde(other)]
+    Other,
+}
+
+/// An event of a streamed Messages response
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart { message: StreamMessage },
+    ContentBlockDelta { delta: BlockDelta },
+    MessageDelta {
+        delta: MessageDeltaBody,
+        usage: Option<OutputUsage>,
+    },
+    Error { error: ApiError },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct StreamMessage {
+    model: String,
+    #[serde(default)]
+    usage: Usage,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta { text: String },
+    InputJsonDelta { partial_json: String },
+    ThinkingDelta { thinking: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct MessageDeltaBody {
+    stop_reason: Option<String>,
+    stop_sequence: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OutputUsage {
+    output_tokens: u64,
+}
+
+/// Splits a `text/event-stream` body into the `data` of each event. Chunks may end
+/// anywhere, including inside a UTF-8 character, so only complete events are decoded.
+#[derive(Default)]
+struct EventStreamParser {
+    buffer: Vec<u8>,
+}
+
+impl EventStreamParser {
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend(chunk.iter().filter(|&&b| b != b'\r'));
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let event: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let event = String::from_utf8_lossy(&event);
+            let data: Vec<&str> = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            if !data.is_empty() {
+                events.push(data.join("\n"));
+            }
+        }
+        events
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    error: ApiError,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+#[async_trait]
+impl LlmClient for ClaudeClient {
+    async fn complete(&self, request: &CompletionRequest) -> Result<CompletionResult> {
+        let body = MessagesRequest::new(request);
+        let bytes = self.post("/v1/messages", &body).await?;
+        let parsed: MessagesResponse = serde_json::from_slice(&bytes)
+            .map_err(|e| TechDocsError::Claude(format!("Invalid response: {}", e)))?;
+        Ok(parsed.into())
+    }
+
+    async fn complete_streaming(&self, request: &CompletionRequest, on_text: TextFn<'_>) -> Result<CompletionResult> {
+        let body = MessagesRequest {
+            stream: true,
+            ..MessagesRequest::new(request)
+        };
+        // Dropping this future drops the response, which closes the connection and
+        // stops generation on the server side
+        let mut response = self.send("/v1/messages", &body).await?;
+
+        let mut parser = EventStreamParser::default();
+        let mut result = CompletionResult::default();
+        let mut tool_json = String::new();
+        let mut thinking_bytes = 0;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| TechDocsError::ClaudeClient(e.to_string()))?
+        {
+            for data in parser.push(&chunk) {
+                let event: StreamEvent = serde_json::from_str(&data)
+                    .map_err(|e| TechDocsError::Claude(format!("Invalid stream event: {}", e)))?;
+                match event {
+                    StreamEvent::MessageStart { message } => {
+                        result.model = message.model;
+                        result.usage = message.usage;
+                    }
+                    StreamEvent::ContentBlockDelta { delta } => match delta {
+                        BlockDelta::TextDelta { text } => {
+                            on_text(&text);
+                            result.text.push_str(&text);
+                        }
+                        BlockDelta::InputJsonDelta { partial_json } => tool_json.push_str(&partial_json),
+                        BlockDelta::ThinkingDelta { thinking } => thinking_bytes += thinking.len() as u64,
+                        BlockDelta::Other => {}
+                    },
+                    StreamEvent::MessageDelta { delta, usage } => {
+                        result.stop_reason = delta.stop_reason;
+                        result.stop_sequence = delta.stop_sequence;
+                        if let Some(usage) = usage {
+                            result.usage.output_tokens = usage.output_tokens;
+                        }
+                    }
+                    StreamEvent::Error { error } => {
+                        let message = format!("{}: {}", error.kind, error.message);
+                        if error.kind == "rate_limit_error" {
+                            return Err(TechDocsError::RateLimited(message));
+                        }
+                        return Err(TechDocsError::Claude(message));
+                    }
+                    StreamEvent::Other => {}
+                }
+            }
+        }
+        result.thinking_tokens = estimate_tokens(thinking_bytes);
+        if !tool_json.is_empty() {
+            result.tool_input = Some(
+                serde_json::from_str(&tool_json)
+                    .map_err(|e| TechDocsError::Claude(format!("Invalid tool input: {}", e)))?,
+            );
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+    use crate::provider::{GenerationProfile, TargetLength, ThinkingConfig};
+
+    /// A local API answering one request with `status` and `body`, its base URL, and the
+    /// head and body of the request it received
+    async fn server(status: &str, content_type: &str, body: &str) -> (String, oneshot::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let response = format!(
+            "HTTP/1.1 {}\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            body
+        );
+        let (sent, received) = oneshot::channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            while reader.read_line(&mut head).await.unwrap() > 0 && !head.ends_with("\r\n\r\n") {}
+            let length = head
+                .lines()
+                .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|n| n.trim().parse().unwrap()))
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.unwrap();
+            reader.into_inner().write_all(response.as_bytes()).await.unwrap();
+            let _ = sent.send((head, String::from_utf8(body).unwrap()));
+        });
+        (base_url, received)
+    }
+
+    fn client(base_url: &str) -> ClaudeClient {
+        ClaudeClient::with_base_url("test-key", format!("{}/", base_url))
+    }
+
+    #[test]
+    fn a_plain_request_leaves_out_the_optional_fields() {
+        let request = CompletionRequest::new("Write a README.", "files");
+        let json = serde_json::to_value(MessagesRequest::new(&request)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "model": DEFAULT_MODEL,
+                "max_tokens": DEFAULT_MAX_TOKENS,
+                "system": "Write a README.",
+                "messages": [{"role": "user", "content": "files"}],
+            })
+        );
+    }
+
+    #[test]
+    fn stop_sequences_and_length_are_sent() {
+        let profile = GenerationProfile {
+            stop_sequences: &["\n## "],
+            target_length: Some(TargetLength::Words(200)),
+            max_tokens: Some(1024),
+        };
+        let request = CompletionRequest {
+            model: Some("claude-3-5-haiku-latest".into()),
+            ..CompletionRequest::new("Write release notes.", "commits")
+        }
+        .with_profile(&profile);
+        let json = serde_json::to_value(MessagesRequest { stream: true, ..MessagesRequest::new(&request) }).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "model": "claude-3-5-haiku-latest",
+                "max_tokens": 1024,
+                "system": "Write release notes.\n\nKeep your answer to about 200 words.",
+                "messages": [{"role": "user", "content": "commits"}],
+                "stop_sequences": ["\n## "],
+                "stream": true,
+            })
+        );
+    }
+
+    #[test]
+    fn a_tool_is_forced_and_thinking_enabled() {
+        let request = CompletionRequest {
+            max_tokens: Some(8192),
+            tool: Some(ToolSpec {
+                name: "record".into(),
+                description: "Record it".into(),
+                input_schema: serde_json::json!({"type": "object"}),
+            }),
+            thinking: Some(ThinkingConfig::new(2048, 8192).unwrap()),
+            ..CompletionRequest::new("s", "c")
+        };
+        let json = serde_json::to_value(MessagesRequest::new(&request)).unwrap();
+        assert_eq!(json["tools"], serde_json::json!([{"name": "record", "description": "Record it", "input_schema": {"type": "object"}}]));
+        assert_eq!(json["tool_choice"], serde_json::json!({"type": "tool", "name": "record"}));
+        assert_eq!(json["thinking"], serde_json::json!({"type": "enabled", "budget_tokens": 2048}));
+    }
+
+    #[test]
+    fn a_stop_sequence_is_reported_with_the_response() {
+        let response: MessagesResponse = serde_json::from_value(serde_json::json!({
+            "model": "claude-3-5-haiku-20241022",
+            "content": [{"type": "text", "text": "## 1.2.0\n\n- Fixes"}],
+            "stop_reason": "stop_sequence",
+            "stop_sequence": "\n## ",
+            "usage": {"input_tokens": 10, "output_tokens": 5},
+        }))
+        .unwrap();
+        let result = CompletionResult::from(response);
+        assert_eq!(result.text, "## 1.2.0\n\n- Fixes");
+        assert_eq!(result.stop_reason.as_deref(), Some("stop_sequence"));
+        assert_eq!(result.stop_sequence.as_deref(), Some("\n## "));
+        assert_eq!((result.usage.input_tokens, result.usage.output_tokens), (10, 5));
+    }
+
+    #[tokio::test]
+    async fn thinking_is_kept_out_of_the_text() {
+        let body = serde_json::json!({
+            "model": "claude-sonnet-4-20250514",
+            "content": [
+                {"type": "thinking", "thinking": "The project is a CLI, so lead with usage.", "signature": "sig"},
+                {"type": "text", "text": "# Demo\n\n"},
+                {"type": "redacted_thinking", "data": "opaque"},
+                {"type": "text", "text": "A command-line tool."},
+            ],
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "usage": {"input_tokens": 200, "output_tokens": 80},
+        });
+        let (base_url, received) = server("200 OK", "application/json", &body.to_string()).await;
+        let request = CompletionRequest {
+            max_tokens: Some(8192),
+            thinking: Some(ThinkingConfig::new(2048, 8192).unwrap()),
+            ..CompletionRequest::new("Write a README.", "files")
+        };
+
+        let result = client(&base_url).complete(&request).await.unwrap();
+        assert_eq!(result.text, "# Demo\n\nA command-line tool.");
+        assert!(result.thinking_tokens > 0);
+        assert_eq!(result.usage.output_tokens, 80);
+
+        let (head, body) = received.await.unwrap();
+        assert!(head.starts_with("POST /v1/messages HTTP/1.1"), "{}", head);
+        assert!(head.contains("x-api-key: test-key"), "{}", head);
+        assert!(head.contains(&format!("anthropic-version: {}", ANTHROPIC_VERSION)), "{}", head);
+        let sent: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(sent["thinking"], serde_json::json!({"type": "enabled", "budget_tokens": 2048}));
+    }
+
+    #[tokio::test]
+    async fn streamed_thinking_is_kept_out_of_the_text() {
+        let events = [
+            r#"{"type":"message_start","message":{"model":"claude-sonnet-4-20250514","usage":{"input_tokens":200,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Lead with usage."}}"#,
+            r##"{"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"# Demo\n"}}"##,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"A tool."}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn","stop_sequence":null},"usage":{"output_tokens":42}}"#,
+            r#"{"type":"message_stop"}"#,
+        ];
+        let body: String = events.iter().map(|data| format!("event: x\r\ndata: {}\r\n\r\n", data)).collect();
+        let (base_url, _) = server("200 OK", "text/event-stream", &body).await;
+
+        let deltas = std::sync::Mutex::new(Vec::new());
+        let on_text = |text: &str| deltas.lock().unwrap().push(text.to_string());
+        let result = client(&base_url).complete_streaming(&CompletionRequest::new("s", "c"), &on_text).await.unwrap();
+        assert_eq!(result.text, "# Demo\nA tool.");
+        assert_eq!(deltas.into_inner().unwrap(), ["# Demo\n", "A tool."]);
+        assert_eq!(result.model, "claude-sonnet-4-20250514");
+        assert_eq!((result.usage.input_tokens, result.usage.output_tokens), (200, 42));
+        assert_eq!(result.stop_reason.as_deref(), Some("end_turn"));
+        assert!(result.thinking_tokens > 0);
+    }
+
+    #[tokio::test]
+    async fn api_errors_are_mapped() {
+        let envelope = r#"{"type":"error","error":{"type":"rate_limit_error","message":"Slow down"}}"#;
+        let (base_url, _) = server("429 Too Many Requests", "application/json", envelope).await;
+        let error = client(&base_url).complete(&CompletionRequest::new("s", "c")).await.unwrap_err();
+        assert!(matches!(&error, TechDocsError::RateLimited(message) if message.contains("rate_limit_error): Slow down")), "{}", error);
+
+        let envelope = r#"{"type":"error","error":{"type":"invalid_request_error","message":"max_tokens: too large"}}"#;
+        let (base_url, _) = server("400 Bad Request", "application/json", envelope).await;
+        let error = client(&base_url).complete(&CompletionRequest::new("s", "c")).await.unwrap_err();
+        assert!(
+            matches!(&error, TechDocsError::Claude(message) if message == "400 Bad Request (invalid_request_error): max_tokens: too large"),
+            "{}",
+            error
+        );
+
+        let (base_url, _) = server("502 Bad Gateway", "text/plain", "upstream down").await;
+        let error = client(&base_url).complete(&CompletionRequest::new("s", "c")).await.unwrap_err();
+        assert!(matches!(&error, TechDocsError::Claude(message) if message == "502 Bad Gateway: upstream down"), "{}", error);
+
+        let (base_url, _) = server("200 OK", "application/json", "{\"unexpected\": true}").await;
+        let error = client(&base_url).complete(&CompletionRequest::new("s", "c")).await.unwrap_err();
+        assert!(error.to_string().contains("Invalid response"), "{}", error);
+    }
+}
@@ -0,0 +1,18 @@
+use crate::{Result, TechDocsError};
+
+/// Copy `text` to the system clipboard. On X11 the text is handed to a running clipboard
+/// manager when the process exits; without one it is lost once techdocs exits.
+#[cfg(feature = "clipboard")]
+pub fn copy_to_clipboard(text: &str) -> Result<()> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .map_err(|e| TechDocsError::Other(format!("could not copy to the clipboard: {}", e).into()))
+}
+
+/// Copy `text` to the system clipboard. Always fails: this build has no clipboard support.
+#[cfg(not(feature = "clipboard"))]
+pub fn copy_to_clipboard(_text: &str) -> Result<()> {
+    Err(TechDocsError::Other(
+        "this build has no clipboard support; rebuild with `--features clipboard`".into(),
+    ))
+}
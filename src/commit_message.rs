@@ -0,0 +1,301 @@
+use serde::Deserialize;
+
+use crate::claude::ClaudeClient;
+use crate::git::FilePatch;
+use crate::provider::{CompletionRequest, CompletionResult, LlmClient, ToolSpec, Usage};
+use crate::review::write_patches;
+use crate::{Result, TechDocsError};
+
+/// Default system prompt for commit messages, embedded at compile time
+pub const DEFAULT_COMMIT_MESSAGE_PROMPT: &str = include_str!("../prompts/commit-message.txt");
+
+/// Default number of tokens of diff sent with a commit message request
+pub const DEFAULT_MAX_DIFF_TOKENS: usize = 8_000;
+
+/// Longest subject line a generated commit message may have
+pub const MAX_SUBJECT_CHARS: usize = 72;
+
+/// Longest diff of a single file sent in full, as a share of the whole budget
+const FILE_SHARE: usize = 2;
+
+/// A Conventional Commits type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommitType {
+    Feat,
+    Fix,
+    Docs,
+    Style,
+    Refactor,
+    Perf,
+    Test,
+    Build,
+    Ci,
+    Chore,
+    Revert,
+}
+
+impl CommitType {
+    pub const ALL: [CommitType; 11] = [
+        CommitType::Feat,
+        CommitType::Fix,
+        CommitType::Docs,
+        CommitType::Style,
+        CommitType::Refactor,
+        CommitType::Perf,
+        CommitType::Test,
+        CommitType::Build,
+        CommitType::Ci,
+        CommitType::Chore,
+        CommitType::Revert,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CommitType::Feat => "feat",
+            CommitType::Fix => "fix",
+            CommitType::Docs => "docs",
+            CommitType::Style => "style",
+            CommitType::Refactor => "refactor",
+            CommitType::Perf => "perf",
+            CommitType::Test => "test",
+            CommitType::Build => "build",
+            CommitType::Ci => "ci",
+            CommitType::Chore => "chore",
+            CommitType::Revert => "revert",
+        }
+    }
+}
+
+impl std::fmt::Display for CommitType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A generated commit message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitMessage {
+    /// `type(scope): description`, at most [`MAX_SUBJECT_CHARS`] characters
+    pub subject: String,
+    pub body: Option<String>,
+}
+
+/// Formats as `git commit -F` expects: the subject, then the body after a blank line
+impl std::fmt::Display for CommitMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.subject)?;
+        if let Some(body) = &self.body {
+            write!(f, "\n\n{}", body)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct CommitMessageToolInput {
+    #[serde(rename = "type")]
+    commit_type: CommitType,
+    #[serde(default)]
+    scope: Option<String>,
+    description: String,
+    #[serde(default)]
+    body: Option<String>,
+}
+
+fn commit_message_tool(commit_type: Option<CommitType>) -> ToolSpec {
+    let types: Vec<&str> = match commit_type {
+        Some(commit_type) => vec![commit_type.as_str()],
+        None => CommitType::ALL.iter().map(|t| t.as_str()).collect(),
+    };
+    ToolSpec {
+        name: "record_commit_message".into(),
+        description: "Record the commit message for the staged change".into(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "type": { "type": "string", "enum": types },
+                "scope": { "type": "string", "description": "Optional one-word scope, lowercase" },
+                "description": {
+                    "type": "string",
+                    "description": "Imperative, lowercase summary without a trailing period"
+                },
+                "body": {
+                    "type": "string",
+                    "description": "What changed and why, wrapped at 72 characters; empty for a trivial change"
+                }
+            },
+            "required": ["type", "description"]
+        }),
+    }
+}
+
+/// Generate a commit message for the staged `patches` using Claude
+pub async fn generate_commit_message(
+    patches: &[FilePatch],
+    commit_type: Option<CommitType>,
+    model: Option<&str>,
+    max_diff_tokens: usize,
+) -> Result<CommitMessage> {
+    let client = ClaudeClient::new()?;
+    Ok(commit_message(&client, patches, commit_type, model, max_diff_tokens).await?.0)
+}
+
+/// Generate a commit message with `client`, returning the token usage alongside it
+pub async fn commit_message(
+    client: &dyn LlmClient,
+    patches: &[FilePatch],
+    commit_type: Option<CommitType>,
+    model: Option<&str>,
+    max_diff_tokens: usize,
+) -> Result<(CommitMessage, Usage)> {
+    let request = commit_message_request(patches, commit_type, model, max_diff_tokens)?;
+    let result = client.complete(&request).await?;
+    let message = parse_commit_message(&result, commit_type)?;
+    Ok((message, result.usage))
+}
+
+/// The request [`commit_message`] sends: every changed file with its line counts, then
+/// as many diffs as fit in `max_diff_tokens`. Binary files are listed by name only.
+pub fn commit_message_request(
+    patches: &[FilePatch],
+    commit_type: Option<CommitType>,
+    model: Option<&str>,
+    max_diff_tokens: usize,
+) -> Result<CompletionRequest> {
+    if patches.is_empty() {
+        return Err(TechDocsError::Other("nothing staged; stage changes with `git add` first".into()));
+    }
+
+    let mut content = String::from("Changed files:\n");
+    for patch in patches {
+        if patch.binary {
+            content.push_str(&format!("- {} (binary)\n", patch.path));
+        } else {
+            content.push_str(&format!("- {} (+{} -{})\n", patch.path, patch.insertions, patch.deletions));
+        }
+    }
+    if let Some(commit_type) = commit_type {
+        content.push_str(&format!("\nThe commit type is `{}`.\n", commit_type));
+    }
+
+    let max_total_size = (max_diff_tokens * 4) as u64;
+    let mut diffs = Vec::new();
+    write_patches(patches, max_total_size / FILE_SHARE as u64, max_total_size, &mut diffs)?;
+    content.push_str(&String::from_utf8_lossy(&diffs));
+
+    Ok(CompletionRequest {
+        model: model.map(String::from),
+        tool: Some(commit_message_tool(commit_type)),
+        ..CompletionRequest::new(DEFAULT_COMMIT_MESSAGE_PROMPT, content)
+    })
+}
+
+/// The commit message in the model's answer to a [`commit_message_request`]. The type is
+/// forced to `commit_type` when given, and the subject is cut at a word boundary to
+/// [`MAX_SUBJECT_CHARS`].
+pub fn parse_commit_message(result: &CompletionResult, commit_type: Option<CommitType>) -> Result<CommitMessage> {
+    let input = result
+        .tool_input
+        .clone()
+        .ok_or_else(|| TechDocsError::Claude("Response did not include a commit message".into()))?;
+    let input: CommitMessageToolInput = serde_json::from_value(input)
+        .map_err(|e| TechDocsError::Claude(format!("Invalid commit message from model: {}", e)))?;
+
+    let description = input.description.trim().trim_end_matches('.').trim();
+    if description.is_empty() {
+        return Err(TechDocsError::Claude("Commit message from model has an empty description".into()));
+    }
+    let prefix = match input.scope.as_deref().map(str::trim).filter(|scope| !scope.is_empty()) {
+        Some(scope) => format!("{}({}): ", commit_type.unwrap_or(input.commit_type), scope),
+        None => format!("{}: ", commit_type.unwrap_or(input.commit_type)),
+    };
+    let subject = shorten(&format!("{}{}", prefix, description), MAX_SUBJECT_CHARS);
+    let body = input.body.map(|body| body.trim().to_string()).filter(|body| !body.is_empty());
+    Ok(CommitMessage { subject, body })
+}
+
+/// `line` cut to at most `max` characters, at the last space when there is one
+fn shorten(line: &str, max: usize) -> String {
+    if line.chars().count() <= max {
+        return line.to_string();
+    }
+    let cut: String = line.chars().take(max).collect();
+    match cut.rfind(' ') {
+        Some(space) if space > 0 => cut[..space].trim_end().to_string(),
+        _ => cut,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::staged_changes;
+    use crate::testing::{FixtureTree, MockClient};
+
+    /// A repository with `src/lib.rs` and a binary logo staged but not committed
+    fn staged() -> (FixtureTree, Vec<FilePatch>) {
+        let tree = FixtureTree::new()
+            .file("src/lib.rs", "pub fn parse() {}\n")
+            .file("logo.png", [0x89, b'P', b'N', b'G', 0, 0, 0, 13, 0, 0xff]);
+        let repo = git2::Repository::init(tree.path()).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let patches = staged_changes(&repo, 3).unwrap();
+        (tree, patches)
+    }
+
+    #[tokio::test]
+    async fn the_staged_diff_becomes_a_conventional_message() {
+        let (_tree, patches) = staged();
+        let client = MockClient::tool(serde_json::json!({
+            "type": "feat",
+            "scope": "parser",
+            "description": "add a parse entry point.",
+            "body": "The crate had no public entry point.\n",
+        }));
+        let (message, _) = commit_message(&client, &patches, None, None, DEFAULT_MAX_DIFF_TOKENS).await.unwrap();
+        assert_eq!(message.to_string(), "feat(parser): add a parse entry point\n\nThe crate had no public entry point.");
+
+        let request = &client.requests()[0];
+        assert_eq!(request.tool.as_ref().unwrap().name, "record_commit_message");
+        assert!(request.content.starts_with("Changed files:\n- logo.png (binary)\n- src/lib.rs (+1 -0)\n"), "{}", request.content);
+        assert!(request.content.contains("\nFile: logo.png (binary file changed)\n"), "{}", request.content);
+        assert!(request.content.contains("+pub fn parse() {}"), "{}", request.content);
+    }
+
+    #[tokio::test]
+    async fn a_chosen_type_constrains_the_prefix() {
+        let (_tree, patches) = staged();
+        let client = MockClient::tool(serde_json::json!({"type": "feat", "description": "handle empty input", "body": "  "}));
+        let (message, _) =
+            commit_message(&client, &patches, Some(CommitType::Fix), None, DEFAULT_MAX_DIFF_TOKENS).await.unwrap();
+        assert_eq!(message, CommitMessage { subject: "fix: handle empty input".into(), body: None });
+
+        let request = &client.requests()[0];
+        assert_eq!(request.tool.as_ref().unwrap().input_schema["properties"]["type"]["enum"], serde_json::json!(["fix"]));
+        assert!(request.content.contains("The commit type is `fix`."), "{}", request.content);
+    }
+
+    #[test]
+    fn an_empty_index_is_refused() {
+        let error = commit_message_request(&[], None, None, DEFAULT_MAX_DIFF_TOKENS).unwrap_err();
+        assert!(matches!(&error, TechDocsError::Other(_)), "{}", error);
+        assert_eq!(error.to_string(), "nothing staged; stage changes with `git add` first");
+    }
+
+    #[test]
+    fn long_subjects_are_cut_at_a_word() {
+        let result = |input: serde_json::Value| CompletionResult { tool_input: Some(input), ..Default::default() };
+        let description = "replace the hand written tokenizer with one generated from the grammar file";
+        let message = parse_commit_message(&result(serde_json::json!({"type": "refactor", "description": description})), None).unwrap();
+        assert_eq!(message.subject, "refactor: replace the hand written tokenizer with one generated from");
+        assert!(message.subject.chars().count() <= MAX_SUBJECT_CHARS);
+
+        let error = parse_commit_message(&result(serde_json::json!({"type": "wip", "description": "x"})), None).unwrap_err();
+        assert!(matches!(&error, TechDocsError::Claude(message) if message.starts_with("Invalid commit message")), "{}", error);
+        let error = parse_commit_message(&CompletionResult::default(), None).unwrap_err();
+        assert!(matches!(&error, TechDocsError::Claude(message) if message == "Response did not include a commit message"));
+    }
+}
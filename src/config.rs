@@ -0,0 +1,445 @@
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use ignore::overrides::OverrideBuilder;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::filters::{FilterPreset, DEFAULT_PRESET};
+use crate::{FileContext, Result, TechDocsError};
+
+/// Name of the per-project configuration file looked up in the resolved project root
+pub const PROJECT_CONFIG_FILE: &str = ".techdocs.toml";
+
+/// Files and directories a walk may find before the source is given up on as too large
+pub const DEFAULT_MAX_WALK_ENTRIES: u64 = 200_000;
+
+/// Seconds file selection may take before the source is given up on as too large
+pub const DEFAULT_MAX_WALK_SECS: u64 = 300;
+
+/// Effective configuration after all layers have been merged
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    /// Additional patterns to exclude (in .gitignore format)
+    pub exclude: Vec<String>,
+    /// Glob patterns a file must match to be considered, on top of the preset's; empty
+    /// considers every file
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Name of the [`FilterPreset`] deciding which files are left out by default
+    pub preset: String,
+    /// Maximum size of a single file in KB
+    pub max_file_size_kb: u64,
+    /// Maximum total size of all files in MB
+    pub max_total_size_mb: u64,
+    /// How many directories deep the walk goes; unlimited when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<u64>,
+    /// Give up on a source once the walk finds this many files and directories; 0 never
+    /// gives up
+    pub max_walk_entries: u64,
+    /// Give up on a source once selecting its files takes this many seconds; 0 never
+    /// gives up
+    pub max_walk_secs: u64,
+    /// Claude model to use (the client default when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Custom system prompt file for README generation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_file: Option<PathBuf>,
+    /// Footer template appended to generated READMEs, such as
+    /// `Generated for version {version} on {date}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footer: Option<String>,
+    /// Layers that contributed to this configuration, lowest precedence first
+    #[serde(skip)]
+    pub sources: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            exclude: Vec::new(),
+            include: Vec::new(),
+            preset: DEFAULT_PRESET.to_string(),
+            max_file_size_kb: 100,
+            max_total_size_mb: 10,
+            max_depth: None,
+            max_walk_entries: DEFAULT_MAX_WALK_ENTRIES,
+            max_walk_secs: DEFAULT_MAX_WALK_SECS,
+            model: None,
+            prompt_file: None,
+            footer: None,
+            sources: vec!["defaults".to_string()],
+        }
+    }
+}
+
+/// A single configuration layer where every setting is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartialConfig {
+    /// A list of patterns, or a string in the `--exclude-from` file format
+    #[serde(default, deserialize_with = "deserialize_exclude")]
+    pub exclude: Option<Vec<String>>,
+    pub include: Option<Vec<String>>,
+    pub preset: Option<String>,
+    pub max_file_size_kb: Option<u64>,
+    pub max_total_size_mb: Option<u64>,
+    pub max_depth: Option<u64>,
+    pub max_walk_entries: Option<u64>,
+    pub max_walk_secs: Option<u64>,
+    pub model: Option<String>,
+    pub prompt_file: Option<PathBuf>,
+    pub footer: Option<String>,
+}
+
+impl PartialConfig {
+    /// Parse a TOML configuration file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).file_context("read", path)?;
+        toml::from_str(&content).map_err(|e| {
+            TechDocsError::Config(format!("{}: {}", path.display(), e.message()))
+        })
+    }
+
+    /// Read settings from `TECHDOCS_*` environment variables
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            exclude: env::var("TECHDOCS_EXCLUDE").ok().map(|v| split_list(&v)),
+            include: env::var("TECHDOCS_INCLUDE").ok().map(|v| split_list(&v)),
+            preset: env::var("TECHDOCS_PRESET").ok(),
+            max_file_size_kb: env_u64("TECHDOCS_MAX_FILE_SIZE_KB")?,
+            max_total_size_mb: env_u64("TECHDOCS_MAX_TOTAL_SIZE_MB")?,
+            max_depth: env_u64("TECHDOCS_MAX_DEPTH")?,
+            max_walk_entries: env_u64("TECHDOCS_MAX_WALK_ENTRIES")?,
+            max_walk_secs: env_u64("TECHDOCS_MAX_WALK_SECS")?,
+            model: env::var("TECHDOCS_MODEL").ok(),
+            prompt_file: env::var_os("TECHDOCS_PROMPT_FILE").map(PathBuf::from),
+            footer: env::var("TECHDOCS_FOOTER").ok(),
+        })
+    }
+
+    /// Check the values in this layer, naming the offending key on failure
+    fn validate(&self, source: &str) -> Result<()> {
+        let invalid = |key: &str, reason: &str| {
+            Err(TechDocsError::Config(format!("{}: invalid value for `{}`: {}", source, key, reason)))
+        };
+        if self.max_file_size_kb == Some(0) {
+            return invalid("max_file_size_kb", "must be greater than 0");
+        }
+        if self.max_total_size_mb == Some(0) {
+            return invalid("max_total_size_mb", "must be greater than 0");
+        }
+        if self.max_depth == Some(0) {
+            return invalid("max_depth", "must be greater than 0");
+        }
+        if let Some(model) = &self.model {
+            if model.trim().is_empty() {
+                return invalid("model", "must not be empty");
+            }
+        }
+        if let Some(patterns) = &self.exclude {
+            if patterns.iter().any(|p| p.trim().is_empty()) {
+                return invalid("exclude", "patterns must not be empty");
+            }
+        }
+        if let Some(patterns) = &self.include {
+            if patterns.iter().any(|p| p.trim().is_empty()) {
+                return invalid("include", "patterns must not be empty");
+            }
+        }
+        if let Some(preset) = &self.preset {
+            if let Err(TechDocsError::Config(reason)) = FilterPreset::find(preset) {
+                return invalid("preset", &reason);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Merge all configuration layers, each overriding the previous:
+    /// built-in defaults, the user config file, `.techdocs.toml` in `project_dir`,
+    /// `TECHDOCS_*` environment variables, and finally `cli` (command-line flags)
+    pub fn load(project_dir: Option<&Path>, cli: PartialConfig) -> Result<Self> {
+        let mut config = Config::default();
+
+        if let Some(path) = user_config_path() {
+            if path.is_file() {
+                let layer = PartialConfig::from_file(&path)?;
+                config.apply(layer, &path.display().to_string())?;
+            }
+        }
+
+        if let Some(dir) = project_dir {
+            let path = dir.join(PROJECT_CONFIG_FILE);
+            if path.is_file() {
+                let mut layer = PartialConfig::from_file(&path)?;
+                // Project files are untrusted when the source is a cloned repository,
+                // so prompt files must stay inside the project
+                if let Some(prompt_file) = layer.prompt_file.take() {
+                    if prompt_file.is_absolute()
+                        || prompt_file.components().any(|c| c == Component::ParentDir)
+                    {
+                        return Err(TechDocsError::Config(format!(
+                            "{}: invalid value for `prompt_file`: must be a relative path inside the project",
+                            path.display()
+                        )));
+                    }
+                    layer.prompt_file = Some(dir.join(prompt_file));
+                }
+                config.apply(layer, &path.display().to_string())?;
+            }
+        }
+
+        config.apply(PartialConfig::from_env()?, "environment")?;
+        config.apply(cli, "command line")?;
+
+        Ok(config)
+    }
+
+    /// Apply a layer on top of the current configuration
+    pub fn apply(&mut self, layer: PartialConfig, source: &str) -> Result<()> {
+        layer.validate(source)?;
+
+        let mut changed = false;
+        if let Some(exclude) = layer.exclude {
+            self.exclude = exclude;
+            changed = true;
+        }
+        if let Some(include) = layer.include {
+            self.include = include;
+            changed = true;
+        }
+        if let Some(preset) = layer.preset {
+            self.preset = preset;
+            changed = true;
+        }
+        if let Some(max_file_size_kb) = layer.max_file_size_kb {
+            self.max_file_size_kb = max_file_size_kb;
+            changed = true;
+        }
+        if let Some(max_total_size_mb) = layer.max_total_size_mb {
+            self.max_total_size_mb = max_total_size_mb;
+            changed = true;
+        }
+        if let Some(max_depth) = layer.max_depth {
+            self.max_depth = Some(max_depth);
+            changed = true;
+        }
+        if let Some(max_walk_entries) = layer.max_walk_entries {
+            self.max_walk_entries = max_walk_entries;
+            changed = true;
+        }
+        if let Some(max_walk_secs) = layer.max_walk_secs {
+            self.max_walk_secs = max_walk_secs;
+            changed = true;
+        }
+        if let Some(model) = layer.model {
+            self.model = Some(model);
+            changed = true;
+        }
+        if let Some(prompt_file) = layer.prompt_file {
+            self.prompt_file = Some(prompt_file);
+            changed = true;
+        }
+        if let Some(footer) = layer.footer {
+            self.footer = Some(footer);
+            changed = true;
+        }
+
+        if changed {
+            self.sources.push(source.to_string());
+        }
+        Ok(())
+    }
+
+    /// Render the effective configuration as TOML
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).map_err(|e| TechDocsError::Config(e.to_string()))
+    }
+}
+
+/// Location of the user configuration file: `$TECHDOCS_CONFIG`, or `techdocs/config.toml`
+/// under `$XDG_CONFIG_HOME` (defaulting to `~/.config`)
+pub fn user_config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("TECHDOCS_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("techdocs").join("config.toml"))
+}
+
+/// Parse exclude patterns in the `--exclude-from` format: one gitignore-style pattern
+/// per line, with blank lines and `#` comments ignored
+pub fn parse_exclude_patterns(content: &str, source: &str) -> Result<Vec<String>> {
+    let mut patterns = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let pattern = line.trim();
+        if pattern.is_empty() || pattern.starts_with('#') {
+            continue;
+        }
+        if let Err(e) = OverrideBuilder::new("/").add(pattern) {
+            return Err(TechDocsError::Config(format!(
+                "{}:{}: invalid exclude pattern `{}`: {}",
+                source,
+                index + 1,
+                pattern,
+                e
+            )));
+        }
+        patterns.push(pattern.to_string());
+    }
+    Ok(patterns)
+}
+
+/// Read exclude patterns from a file in the `--exclude-from` format
+pub fn read_exclude_file(path: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        TechDocsError::Config(format!("Failed to read {}: {}", path.display(), e))
+    })?;
+    parse_exclude_patterns(&content, &path.display().to_string())
+}
+
+fn deserialize_exclude<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Exclude {
+        List(Vec<String>),
+        Text(String),
+    }
+
+    match Option::<Exclude>::deserialize(deserializer)? {
+        Some(Exclude::List(patterns)) => Ok(Some(patterns)),
+        Some(Exclude::Text(text)) => parse_exclude_patterns(&text, "exclude")
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn env_u64(name: &str) -> Result<Option<u64>> {
+    match env::var(name) {
+        Ok(value) => value.trim().parse().map(Some).map_err(|_| {
+            TechDocsError::Config(format!("{}: expected a non-negative integer, got `{}`", name, value))
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    fn config_error<T: std::fmt::Debug>(result: Result<T>) -> String {
+        match result {
+            Err(TechDocsError::Config(message)) => message,
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn command_line_overrides_the_project_file() {
+        let tree = FixtureTree::new().file(
+            PROJECT_CONFIG_FILE,
+            "model = \"project-model\"\nmax_file_size_kb = 50\nexclude = [\"*.lock\"]\n",
+        );
+        let cli = PartialConfig {
+            model: Some("cli-model".into()),
+            exclude: Some(vec!["dist/".into()]),
+            ..Default::default()
+        };
+        let config = Config::load(Some(tree.path()), cli).unwrap();
+
+        assert_eq!(config.model.as_deref(), Some("cli-model"));
+        assert_eq!(config.exclude, ["dist/"]);
+        assert_eq!(config.max_file_size_kb, 50);
+        let project = tree.join(PROJECT_CONFIG_FILE).display().to_string();
+        let project_at = config.sources.iter().position(|source| *source == project).unwrap();
+        let cli_at = config.sources.iter().position(|source| source == "command line").unwrap();
+        assert!(project_at < cli_at, "{:?}", config.sources);
+    }
+
+    #[test]
+    fn layers_apply_in_order() {
+        let mut config = Config::default();
+        let user = PartialConfig { max_total_size_mb: Some(20), model: Some("user-model".into()), ..Default::default() };
+        let project = PartialConfig { model: Some("project-model".into()), ..Default::default() };
+        config.apply(user, "user").unwrap();
+        config.apply(project, "project").unwrap();
+        config.apply(PartialConfig::default(), "environment").unwrap();
+
+        assert_eq!(config.max_total_size_mb, 20);
+        assert_eq!(config.model.as_deref(), Some("project-model"));
+        assert_eq!(config.max_file_size_kb, Config::default().max_file_size_kb);
+        // A layer that sets nothing is not listed
+        assert_eq!(config.sources, ["defaults", "user", "project"]);
+    }
+
+    #[test]
+    fn invalid_values_name_the_key_and_file() {
+        let tree = FixtureTree::new().file(PROJECT_CONFIG_FILE, "max_file_size_kb = 0\n");
+        let message = config_error(Config::load(Some(tree.path()), PartialConfig::default()));
+        assert!(message.contains("`max_file_size_kb`"), "{}", message);
+        assert!(message.contains(PROJECT_CONFIG_FILE), "{}", message);
+
+        let layer = PartialConfig { preset: Some("everything".into()), ..Default::default() };
+        let message = config_error(Config::default().apply(layer, "command line"));
+        assert!(message.starts_with("command line: invalid value for `preset`"), "{}", message);
+    }
+
+    #[test]
+    fn unknown_keys_are_refused() {
+        let tree = FixtureTree::new().file(PROJECT_CONFIG_FILE, "max_size = 10\n");
+        let message = config_error(Config::load(Some(tree.path()), PartialConfig::default()));
+        assert!(message.contains("max_size"), "{}", message);
+    }
+
+    #[test]
+    fn project_prompt_file_must_stay_inside_the_project() {
+        for prompt_file in ["/etc/passwd", "../outside.txt", "prompts/../../outside.txt"] {
+            let tree = FixtureTree::new().file(PROJECT_CONFIG_FILE, format!("prompt_file = {:?}\n", prompt_file));
+            let message = config_error(Config::load(Some(tree.path()), PartialConfig::default()));
+            assert!(message.contains("`prompt_file`"), "{}: {}", prompt_file, message);
+        }
+
+        let tree = FixtureTree::new().file(PROJECT_CONFIG_FILE, "prompt_file = \"prompts/readme.txt\"\n");
+        let config = Config::load(Some(tree.path()), PartialConfig::default()).unwrap();
+        assert_eq!(config.prompt_file, Some(tree.join("prompts/readme.txt")));
+    }
+
+    #[test]
+    fn exclude_may_be_a_pattern_file() {
+        let tree = FixtureTree::new().file(PROJECT_CONFIG_FILE, "exclude = \"\"\"\n# generated\n*.lock\n\ndist/\n\"\"\"\n");
+        let layer = PartialConfig::from_file(&tree.join(PROJECT_CONFIG_FILE)).unwrap();
+        assert_eq!(layer.exclude.unwrap(), ["*.lock", "dist/"]);
+    }
+
+    #[test]
+    fn bad_exclude_patterns_name_the_line() {
+        let message = config_error(parse_exclude_patterns("*.lock\n# comment\nsrc/[\n", "excludes.txt"));
+        assert!(message.starts_with("excludes.txt:3: invalid exclude pattern `src/[`"), "{}", message);
+    }
+
+    #[test]
+    fn effective_config_renders_as_toml() {
+        let config = Config { model: Some("claude-test".into()), ..Config::default() };
+        let toml = config.to_toml().unwrap();
+        assert!(toml.contains("model = \"claude-test\""), "{}", toml);
+        assert!(toml.contains("max_file_size_kb = 100"), "{}", toml);
+        assert!(!toml.contains("sources"), "{}", toml);
+    }
+}
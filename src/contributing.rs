@@ -0,0 +1,65 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::provider::{CompletionRequest, GenerationProfile};
+use crate::tooling::ToolingFacts;
+use crate::CollectedFile;
+
+/// Default system prompt for contributing guides, embedded at compile time
+pub const DEFAULT_CONTRIBUTING_PROMPT: &str = include_str!("../prompts/contributing.txt");
+/// The guide has a single level-1 heading
+pub const CONTRIBUTING_PROFILE: GenerationProfile = GenerationProfile {
+    stop_sequences: &["\n# "],
+    target_length: None,
+    max_tokens: None,
+};
+/// Levels of the directory tree shown to the model
+pub const TREE_DEPTH: usize = 3;
+/// Lines of the directory tree shown to the model
+pub const TREE_LINES: usize = 200;
+
+/// The files under `dir` as an indented tree, directories ending in `/`, at most
+/// `max_depth` levels deep and `max_lines` lines long
+pub fn directory_tree(dir: &Path, files: &[CollectedFile], max_depth: usize, max_lines: usize) -> String {
+    let mut entries = BTreeSet::new();
+    for file in files {
+        let relative = file.path.strip_prefix(dir).unwrap_or(&file.path);
+        let components: Vec<_> = relative.components().collect();
+        // Every directory on the way is an entry of its own; deeper files are left out
+        for depth in 1..=components.len().min(max_depth) {
+            let path: PathBuf = components[..depth].iter().collect();
+            entries.insert((path, depth < components.len()));
+        }
+    }
+
+    let mut tree = String::new();
+    for (index, (path, is_dir)) in entries.iter().enumerate() {
+        if index == max_lines {
+            let _ = writeln!(tree, "... {} more", entries.len() - max_lines);
+            break;
+        }
+        let indent = "  ".repeat(path.components().count() - 1);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let _ = writeln!(tree, "{}{}{}", indent, name, if *is_dir { "/" } else { "" });
+    }
+    tree
+}
+
+/// The request for a CONTRIBUTING.md of the project named `name`, from its tooling
+/// facts and directory tree
+pub fn contributing_request(name: &str, facts: &ToolingFacts, tree: &str, model: Option<&str>) -> CompletionRequest {
+    let mut content = format!("Project: {}\n\n", name);
+    if facts.is_empty() {
+        content.push_str("No build, test, lint, or CI configuration was found.\n");
+    } else {
+        content.push_str(&facts.to_prompt());
+    }
+    let _ = write!(content, "\nDirectory tree:\n```\n{}```\n", tree);
+
+    CompletionRequest {
+        model: model.map(String::from),
+        ..CompletionRequest::new(DEFAULT_CONTRIBUTING_PROMPT, content)
+    }
+    .with_profile(&CONTRIBUTING_PROFILE)
+}
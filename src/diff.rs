@@ -0,0 +1,91 @@
+use std::fmt::Write;
+use similar::{ChangeTag, TextDiff};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Render a unified diff from `old` to `new`, or `None` when they are identical.
+/// A missing `old` file is diffed as empty, so every line shows as added.
+pub fn unified_diff(
+    old: Option<&str>,
+    new: &str,
+    old_name: &str,
+    new_name: &str,
+    color: bool,
+) -> Option<String> {
+    let old = old.unwrap_or("");
+    if old == new {
+        return None;
+    }
+
+    let diff = TextDiff::from_lines(old, new);
+    let paint = |code: &str, line: &str| {
+        if color {
+            format!("{}{}{}", code, line, RESET)
+        } else {
+            line.to_string()
+        }
+    };
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", paint(RED, &format!("--- {}", old_name)));
+    let _ = writeln!(out, "{}", paint(GREEN, &format!("+++ {}", new_name)));
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        let _ = writeln!(out, "{}", paint(CYAN, &hunk.header().to_string()));
+        for change in hunk.iter_changes() {
+            let (sign, code) = match change.tag() {
+                ChangeTag::Delete => ("-", Some(RED)),
+                ChangeTag::Insert => ("+", Some(GREEN)),
+                ChangeTag::Equal => (" ", None),
+            };
+            let line = format!("{}{}", sign, change.value().trim_end_matches('\n'));
+            let _ = match code {
+                Some(code) => writeln!(out, "{}", paint(code, &line)),
+                None => writeln!(out, "{}", line),
+            };
+            if change.missing_newline() {
+                let _ = writeln!(out, "\\ No newline at end of file");
+            }
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_text_has_no_diff() {
+        assert_eq!(unified_diff(Some("# Demo\n"), "# Demo\n", "a", "b", false), None);
+        assert_eq!(unified_diff(None, "", "a", "b", false), None);
+    }
+
+    #[test]
+    fn changes_are_shown_in_hunks() {
+        let old = "# Demo\n\nOne\nTwo\nThree\n";
+        let new = "# Demo\n\nOne\n2\nThree\n";
+        assert_eq!(
+            unified_diff(Some(old), new, "README.md", "README.md (generated)", false).unwrap(),
+            "--- README.md\n+++ README.md (generated)\n@@ -1,5 +1,5 @@\n # Demo\n \n One\n-Two\n+2\n Three\n"
+        );
+    }
+
+    #[test]
+    fn a_missing_file_is_all_additions() {
+        let diff = unified_diff(None, "# Demo\nText", "/dev/null", "README.md", false).unwrap();
+        assert_eq!(diff, "--- /dev/null\n+++ README.md\n@@ -0,0 +1,2 @@\n+# Demo\n+Text\n\\ No newline at end of file\n");
+    }
+
+    #[test]
+    fn color_marks_each_kind_of_line() {
+        let diff = unified_diff(Some("a\nb\n"), "a\nc\n", "old", "new", true).unwrap();
+        let lines: Vec<_> = diff.lines().collect();
+        assert_eq!(lines[0], "\x1b[31m--- old\x1b[0m");
+        assert_eq!(lines[1], "\x1b[32m+++ new\x1b[0m");
+        assert_eq!(lines[2], "\x1b[36m@@ -1,2 +1,2 @@\x1b[0m");
+        assert_eq!(lines[3..], [" a", "\x1b[31m-b\x1b[0m", "\x1b[32m+c\x1b[0m"]);
+    }
+}
@@ -0,0 +1,159 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use serde::Serialize;
+
+/// Bytes at the start of a file searched for a [`Directive`]
+pub const DIRECTIVE_SCAN_BYTES: u64 = 512;
+
+/// Comment openers a directive may follow, longest first so `<!--` wins over `-`
+const COMMENT_OPENERS: &[&str] = &["<!--", "/*", "//", "--", "#", ";", "%"];
+
+/// An instruction to techdocs written into a file itself, as a comment on its first
+/// line, or on its second when the first is a `#!` interpreter line. The grammar is
+///
+/// ```text
+/// <comment opener> techdocs:<directive> [<comment closer>]
+/// directive = "include" | "exclude" | "priority=" ("high" | "normal" | "low")
+/// ```
+///
+/// where the opener is any of `<!--`, `/*`, `//`, `--`, `#`, `;`, or `%`, so
+/// `// techdocs:include`, `# techdocs:exclude`, and
+/// `<!-- techdocs:priority=high -->` all work. Only the first
+/// [`DIRECTIVE_SCAN_BYTES`] bytes are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Directive {
+    /// Never put the file in a prompt
+    Exclude,
+    /// Send the file whatever its size, as long as the total budget allows
+    Include,
+    /// Rank the file above or below others when choosing and ordering files
+    Priority(Priority),
+}
+
+/// How important a file marked with `techdocs:priority=` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Ranked with manifests, ahead of everything else
+    High,
+    /// Ranked as the file would be without a directive
+    Normal,
+    /// Ranked after every other file
+    Low,
+}
+
+/// The directive in `line`, `None` when it is not a techdocs comment, or the text of
+/// a directive that is not understood
+pub fn parse_directive(line: &str) -> Option<Result<Directive, String>> {
+    let line = line.trim();
+    let body = COMMENT_OPENERS.iter().find_map(|opener| line.strip_prefix(opener))?;
+    // Doc comments and repeated markers, as in `//!` or `##`
+    let body = body.trim_start_matches(['/', '!', '#', '*', ';', '-', '%']).trim_start();
+    let body = body.strip_prefix("techdocs:")?;
+    let body = body.trim_end().trim_end_matches("-->").trim_end_matches("*/").trim();
+
+    Some(match body.split_once('=').map(|(key, value)| (key.trim(), value.trim())) {
+        None if body == "include" => Ok(Directive::Include),
+        None if body == "exclude" => Ok(Directive::Exclude),
+        Some(("priority", "high")) => Ok(Directive::Priority(Priority::High)),
+        Some(("priority", "normal")) => Ok(Directive::Priority(Priority::Normal)),
+        Some(("priority", "low")) => Ok(Directive::Priority(Priority::Low)),
+        _ => Err(body.to_string()),
+    })
+}
+
+/// The directive at the top of `content`, the first bytes of a file
+pub fn find_directive(content: &[u8]) -> Option<Result<Directive, String>> {
+    let content = content.strip_prefix("\u{feff}".as_bytes()).unwrap_or(content);
+    let mut lines = content.split(|&byte| byte == b'\n');
+    let mut line = lines.next()?;
+    if line.starts_with(b"#!") {
+        line = lines.next()?;
+    }
+    // The scan may stop inside a multi-byte character; that only matters past the line
+    let line = String::from_utf8_lossy(line);
+    parse_directive(&line)
+}
+
+/// The directive at the top of the file at `path`, reading no more than
+/// [`DIRECTIVE_SCAN_BYTES`] of it. A file that cannot be read has none; reading it for
+/// the prompt reports the error.
+pub fn read_directive(path: &Path) -> Option<Result<Directive, String>> {
+    let mut head = Vec::with_capacity(DIRECTIVE_SCAN_BYTES as usize);
+    fs::File::open(path).ok()?.take(DIRECTIVE_SCAN_BYTES).read_to_end(&mut head).ok()?;
+    find_directive(&head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{PromptOptions, PromptPlan, SkipReason};
+    use crate::testing::FixtureTree;
+
+    #[test]
+    fn directives_follow_any_comment_opener() {
+        for (line, directive) in [
+            ("// techdocs:include", Directive::Include),
+            ("# techdocs:exclude", Directive::Exclude),
+            ("<!-- techdocs:priority=high -->", Directive::Priority(Priority::High)),
+            ("/* techdocs:priority = low */", Directive::Priority(Priority::Low)),
+            ("//! techdocs:priority=normal", Directive::Priority(Priority::Normal)),
+            ("  -- techdocs:exclude  ", Directive::Exclude),
+            (";; techdocs:include", Directive::Include),
+        ] {
+            assert_eq!(parse_directive(line), Some(Ok(directive)), "{}", line);
+        }
+    }
+
+    #[test]
+    fn other_lines_are_not_directives() {
+        for line in ["techdocs:include", "// see techdocs:include", "# TODO", "", "fn main() {}", "// techdocs include"] {
+            assert_eq!(parse_directive(line), None, "{}", line);
+        }
+    }
+
+    #[test]
+    fn unknown_directives_are_returned_as_written() {
+        assert_eq!(parse_directive("// techdocs:skip"), Some(Err("skip".to_string())));
+        assert_eq!(parse_directive("<!-- techdocs:priority=urgent -->"), Some(Err("priority=urgent".to_string())));
+        assert_eq!(parse_directive("# techdocs:"), Some(Err(String::new())));
+    }
+
+    #[test]
+    fn only_the_first_line_after_a_shebang_is_read() {
+        assert_eq!(find_directive(b"#!/bin/sh\n# techdocs:exclude\necho"), Some(Ok(Directive::Exclude)));
+        assert_eq!(find_directive("\u{feff}// techdocs:include\n".as_bytes()), Some(Ok(Directive::Include)));
+        assert_eq!(find_directive(b"fn main() {}\n// techdocs:exclude\n"), None);
+        assert_eq!(find_directive(b"#!/bin/sh"), None);
+    }
+
+    #[test]
+    fn each_directive_changes_what_is_collected() {
+        let large = format!("// techdocs:include\n{}", "x".repeat(4096));
+        let tree = FixtureTree::new()
+            .file("src/main.rs", "fn main() {}\n")
+            .file("src/generated.rs", "// techdocs:exclude\npub const TABLE: [u8; 0] = [];\n")
+            .file("src/large.rs", &large)
+            .file("src/huge.rs", "x".repeat(4096))
+            .file("scripts/a.sh", "#!/bin/sh\n# techdocs:priority=low\necho a\n")
+            .file("scripts/b.sh", "#!/bin/sh\n# techdocs:priority=high\necho b\n")
+            .file("notes.md", "<!-- techdocs:shout -->\n# Notes\n");
+        let options = PromptOptions::default().max_file_kb(1).max_files_per_dir(1);
+        let plan = PromptPlan::collect(tree.path(), &options).unwrap();
+        let decided = |name: &str| {
+            plan.files.iter().find(|file| file.path == tree.join(name)).map(|file| (file.skipped, file.directive))
+        };
+
+        assert_eq!(decided("src/generated.rs"), None);
+        assert_eq!(decided("src/large.rs"), Some((None, Some(Directive::Include))));
+        assert_eq!(decided("src/huge.rs"), Some((Some(SkipReason::TooLarge), None)));
+        assert_eq!(decided("scripts/b.sh"), Some((None, Some(Directive::Priority(Priority::High)))));
+        assert_eq!(decided("scripts/a.sh"), Some((Some(SkipReason::DirLimit), Some(Directive::Priority(Priority::Low)))));
+        // An unknown directive is ignored rather than failing the walk
+        assert_eq!(decided("notes.md"), Some((None, None)));
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::monorepo::detect_workspace;
+use crate::provider::{CompletionRequest, GenerationProfile, LlmClient, TargetLength};
+use crate::toc::{render_toc, toc_entries};
+use crate::{format_file_content, read_file_lossy, write_atomic, CollectedFile, Result, TechDocsError};
+
+/// Default system prompt for per-module documentation, embedded at compile time
+pub const DEFAULT_MODULE_PROMPT: &str = include_str!("../prompts/module.txt");
+/// System prompt used to condense one chunk of an oversized module
+pub const DEFAULT_CHUNK_SUMMARY_PROMPT: &str = include_str!("../prompts/chunk-summary.txt");
+/// A module page has a single level-1 heading, so a second one would start another page
+pub const MODULE_PROFILE: GenerationProfile = GenerationProfile {
+    stop_sequences: &["\n# "],
+    target_length: None,
+    max_tokens: None,
+};
+/// Chunk notes are read by the module step, which needs room for every chunk
+pub const CHUNK_SUMMARY_PROFILE: GenerationProfile = GenerationProfile {
+    stop_sequences: &[],
+    target_length: Some(TargetLength::Words(400)),
+    max_tokens: None,
+};
+
+/// A group of files documented together on one page
+#[derive(Debug, Clone)]
+pub struct Partition {
+    /// Module name, the directory path relative to the project root
+    pub name: String,
+    pub files: Vec<CollectedFile>,
+}
+
+impl Partition {
+    /// File name of the generated page, e.g. `crates-core.md` for `crates/core`
+    pub fn page_name(&self) -> String {
+        let slug: String = self
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+            .collect();
+        format!("{}.md", slug.trim_matches('-'))
+    }
+
+    fn total_size(&self) -> u64 {
+        self.files.iter().map(|f| f.size).sum()
+    }
+}
+
+/// Member directories of a Cargo, npm, or pnpm workspace rooted at `dir`, relative to
+/// `dir`; see [`detect_workspace`]
+pub fn workspace_members(dir: &Path) -> Option<Vec<PathBuf>> {
+    detect_workspace(dir).map(|members| members.into_iter().map(|member| member.path).collect())
+}
+
+/// Partition collected files by workspace member, or by top-level directory when `dir`
+/// is not a workspace. Files directly in the root are left out.
+pub fn partition_files(dir: &Path, files: Vec<CollectedFile>) -> Vec<Partition> {
+    let members = workspace_members(dir);
+    let mut partitions: BTreeMap<String, Vec<CollectedFile>> = BTreeMap::new();
+
+    for file in files {
+        let relative = file.path.strip_prefix(dir).unwrap_or(&file.path).to_path_buf();
+        let key = match &members {
+            Some(members) => members
+                .iter()
+                .filter(|m| relative.starts_with(m))
+                .max_by_key(|m| m.components().count())
+                .map(|m| m.display().to_string()),
+            None => {
+                let mut components = relative.components();
+                let first = components.next();
+                // A second component means the file lives inside a directory
+                components.next().and(first).map(|c| c.as_os_str().to_string_lossy().into_owned())
+            }
+        };
+        if let Some(key) = key {
+            partitions.entry(key).or_default().push(file);
+        }
+    }
+
+    partitions
+        .into_iter()
+        .map(|(name, files)| Partition { name, files })
+        .collect()
+}
+
+/// Write the files as a prompt body with paths relative to `dir`
+fn files_prompt(dir: &Path, files: &[CollectedFile]) -> Result<String> {
+    let mut out = String::new();
+    for file in files {
+        let content = read_file_lossy(&file.path)?;
+        let relative = file.path.strip_prefix(dir).unwrap_or(&file.path);
+        let _ = writeln!(out, "\nFile: {}", relative.display());
+        let _ = writeln!(out, "{}", format_file_content(&file.path, &content));
+    }
+    Ok(out)
+}
+
+/// Split files into chunks whose total size stays within `budget` bytes
+fn chunk_files(files: &[CollectedFile], budget: u64) -> Vec<&[CollectedFile]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut size = 0;
+    for (index, file) in files.iter().enumerate() {
+        if index > start && size + file.size > budget {
+            chunks.push(&files[start..index]);
+            start = index;
+            size = 0;
+        }
+        size += file.size;
+    }
+    if start < files.len() {
+        chunks.push(&files[start..]);
+    }
+    chunks
+}
+
+/// Generate the documentation page for one partition. Partitions larger than
+/// `budget` bytes are summarized chunk by chunk before the page is written.
+pub async fn generate_module_page(
+    client: &dyn LlmClient,
+    dir: &Path,
+    partition: &Partition,
+    model: Option<&str>,
+    budget: u64,
+) -> Result<String> {
+    let content = if partition.total_size() <= budget {
+        files_prompt(dir, &partition.files)?
+    } else {
+        let chunks = chunk_files(&partition.files, budget);
+        let mut notes = String::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            let request = CompletionRequest {
+                model: model.map(String::from),
+                ..CompletionRequest::new(DEFAULT_CHUNK_SUMMARY_PROMPT, files_prompt(dir, chunk)?)
+            }
+            .with_profile(&CHUNK_SUMMARY_PROFILE);
+            let summary = client.complete(&request).await?;
+            let _ = writeln!(notes, "\n## Notes, part {} of {}\n\n{}", index + 1, chunks.len(), summary.text);
+        }
+        notes
+    };
+
+    let request = CompletionRequest {
+        model: model.map(String::from),
+        ..CompletionRequest::new(
+            DEFAULT_MODULE_PROMPT,
+            format!("Module: {}\n{}", partition.name, content),
+        )
+    }
+    .with_profile(&MODULE_PROFILE);
+    Ok(client.complete(&request).await?.text)
+}
+
+/// What happened to one module's page
+#[derive(Debug)]
+pub enum PageStatus {
+    Written(PathBuf),
+    Skipped(String),
+}
+
+/// Outcome of documenting one module
+#[derive(Debug)]
+pub struct PageOutcome {
+    pub module: String,
+    pub status: PageStatus,
+}
+
+/// Generate one page per partition into `out_dir`, plus an `index.md` linking them.
+/// A failure on one module is recorded and does not stop the others, except for
+/// [`TechDocsError::CostLimitExceeded`], which is returned once the index of the pages
+/// written so far is.
+pub async fn generate_docs(
+    client: &dyn LlmClient,
+    dir: &Path,
+    partitions: &[Partition],
+    out_dir: &Path,
+    model: Option<&str>,
+    budget: u64,
+) -> Result<Vec<PageOutcome>> {
+    fs::create_dir_all(out_dir)
+        .map_err(|e| TechDocsError::Output(format!("Failed to create {}: {}", out_dir.display(), e)))?;
+
+    let mut outcomes = Vec::new();
+    let mut index = String::from("# Documentation\n\n");
+    let mut stopped = None;
+    for partition in partitions {
+        let status = match generate_module_page(client, dir, partition, model, budget).await {
+            Ok(page) => {
+                let path = out_dir.join(partition.page_name());
+                match write_atomic(&path, &page) {
+                    Ok(()) => {
+                        let _ = writeln!(index, "- [{}]({})", partition.name, partition.page_name());
+                        // The page's sections nest under it
+                        for line in render_toc(&toc_entries(&page), &partition.page_name()).lines() {
+                            let _ = writeln!(index, "  {}", line);
+                        }
+                        PageStatus::Written(path)
+                    }
+                    Err(e) => PageStatus::Skipped(e.to_string()),
+                }
+            }
+            Err(e @ TechDocsError::CostLimitExceeded { .. }) => {
+                stopped = Some(e);
+                break;
+            }
+            Err(e) => PageStatus::Skipped(e.to_string()),
+        };
+        outcomes.push(PageOutcome {
+            module: partition.name.clone(),
+            status,
+        });
+    }
+
+    write_atomic(&out_dir.join("index.md"), &index)?;
+
+    match stopped {
+        Some(e) => Err(e),
+        None => Ok(outcomes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_files;
+    use crate::provider::CompletionResult;
+    use crate::testing::{FixtureTree, MockClient};
+
+    fn project() -> FixtureTree {
+        FixtureTree::new()
+            .file("README.md", "# Demo\n")
+            .file("cli/main.rs", "fn main() {}\n")
+            .file("core/lib.rs", "pub fn run() {}\n")
+            .file("core/parse.rs", "pub fn parse() {}\n")
+            .file("web/app.js", "console.log('hi');\n")
+    }
+
+    fn partitions(tree: &FixtureTree) -> Vec<Partition> {
+        partition_files(tree.path(), collect_files(tree.path(), &[], 1024).unwrap())
+    }
+
+    /// Answers with a page for the module named in the prompt, failing for `failing`
+    fn pages(failing: &'static str) -> MockClient {
+        MockClient::new(move |request| {
+            let module = request.content.lines().next().unwrap_or_default().trim_start_matches("Module: ").to_string();
+            match module.as_str() {
+                name if name == failing => Err(TechDocsError::Claude("overloaded".into())),
+                "spent" => Err(TechDocsError::CostLimitExceeded { spent: 1.0, limit: 1.0 }),
+                _ => Ok(CompletionResult { text: format!("# {}\n\n## Usage\n\nText\n", module), ..Default::default() }),
+            }
+        })
+    }
+
+    #[test]
+    fn files_are_partitioned_by_top_level_directory_or_member() {
+        let tree = project();
+        let names: Vec<_> = partitions(&tree).into_iter().map(|p| (p.name, p.files.len())).collect();
+        assert_eq!(names, [("cli".to_string(), 1), ("core".to_string(), 2), ("web".to_string(), 1)]);
+
+        let tree = FixtureTree::new()
+            .file("Cargo.toml", "[workspace]\nmembers = [\"crates/*\"]\n")
+            .file("crates/core/Cargo.toml", "[package]\nname = \"core\"\n")
+            .file("crates/core/src/lib.rs", "")
+            .file("crates/cli/Cargo.toml", "[package]\nname = \"cli\"\n")
+            .file("scripts/release.sh", "");
+        let partitions = partitions(&tree);
+        let names: Vec<_> = partitions.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["crates/cli", "crates/core"]);
+        assert_eq!(partitions[1].page_name(), "crates-core.md");
+    }
+
+    #[tokio::test]
+    async fn a_failing_module_does_not_stop_the_others() {
+        let tree = project();
+        let out = tree.join("docs");
+        let client = pages("core");
+        let outcomes = generate_docs(&client, tree.path(), &partitions(&tree), &out, None, 64 * 1024).await.unwrap();
+
+        let statuses: Vec<_> = outcomes
+            .iter()
+            .map(|outcome| match &outcome.status {
+                PageStatus::Written(path) => (outcome.module.as_str(), path.file_name().unwrap().to_string_lossy().into_owned()),
+                PageStatus::Skipped(reason) => (outcome.module.as_str(), reason.clone()),
+            })
+            .collect();
+        assert_eq!(
+            statuses,
+            [("cli", "cli.md".into()), ("core", "Claude error: overloaded".into()), ("web", "web.md".into())]
+        );
+        assert_eq!(fs::read_to_string(out.join("web.md")).unwrap(), "# web\n\n## Usage\n\nText\n");
+        assert_eq!(
+            fs::read_to_string(out.join("index.md")).unwrap(),
+            "# Documentation\n\n- [cli](cli.md)\n  - [Usage](cli.md#usage)\n- [web](web.md)\n  - [Usage](web.md#usage)\n"
+        );
+        let request = &client.requests()[0];
+        assert_eq!(request.system, DEFAULT_MODULE_PROMPT);
+        assert!(request.content.contains("\nFile: cli/main.rs\n"), "{}", request.content);
+    }
+
+    #[tokio::test]
+    async fn oversized_modules_are_summarized_in_chunks() {
+        let tree = project();
+        let core = partitions(&tree).into_iter().find(|p| p.name == "core").unwrap();
+        let client = MockClient::new(|request| {
+            let text = if request.system == DEFAULT_CHUNK_SUMMARY_PROMPT { "notes" } else { "# core\n" };
+            Ok(CompletionResult { text: text.into(), ..Default::default() })
+        });
+        let page = generate_module_page(&client, tree.path(), &core, Some("claude-3-5-haiku-latest"), 20).await.unwrap();
+        assert_eq!(page, "# core\n");
+
+        let requests = client.requests();
+        let systems: Vec<_> = requests.iter().map(|r| r.system == DEFAULT_CHUNK_SUMMARY_PROMPT).collect();
+        assert_eq!(systems, [true, true, false]);
+        assert!(requests[0].content.contains("File: core/lib.rs") && !requests[0].content.contains("core/parse.rs"));
+        assert_eq!(requests[2].content, "Module: core\n\n## Notes, part 1 of 2\n\nnotes\n\n## Notes, part 2 of 2\n\nnotes\n");
+        assert!(requests.iter().all(|r| r.model.as_deref() == Some("claude-3-5-haiku-latest")));
+    }
+
+    #[tokio::test]
+    async fn the_cost_limit_stops_the_run_after_writing_the_index() {
+        let tree = FixtureTree::new().file("alpha/a.rs", "").file("spent/b.rs", "").file("zeta/c.rs", "");
+        let out = tree.join("docs");
+        let error = generate_docs(&pages(""), tree.path(), &partitions(&tree), &out, None, 1024).await.unwrap_err();
+        assert!(matches!(error, TechDocsError::CostLimitExceeded { .. }), "{}", error);
+        assert!(out.join("alpha.md").exists() && !out.join("zeta.md").exists());
+        assert_eq!(fs::read_to_string(out.join("index.md")).unwrap(), "# Documentation\n\n- [alpha](alpha.md)\n  - [Usage](alpha.md#usage)\n");
+    }
+}
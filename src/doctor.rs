@@ -0,0 +1,330 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use temp_dir::TempDir;
+use url::Url;
+
+use crate::claude::{ClaudeClient, DEFAULT_BASE_URL, DEFAULT_MODEL};
+use crate::config::{Config, PartialConfig};
+use crate::load_readme_prompt;
+use crate::provider::CompletionRequest;
+
+/// Outcome of a single diagnostic check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    /// Something looks wrong but techdocs can still run
+    Warn,
+    Fail,
+    /// The check was not run
+    Skip,
+}
+
+impl std::fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            CheckStatus::Pass => "pass",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Fail => "FAIL",
+            CheckStatus::Skip => "skip",
+        })
+    }
+}
+
+/// Result of one diagnostic check
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    /// Human-readable explanation; never contains secrets
+    pub detail: String,
+    /// Whether a failure means techdocs cannot work
+    pub required: bool,
+}
+
+impl CheckResult {
+    fn new(name: &'static str, required: bool, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+            required,
+        }
+    }
+
+    /// Whether this is a failed hard requirement
+    pub fn is_blocking(&self) -> bool {
+        self.required && self.status == CheckStatus::Fail
+    }
+}
+
+/// Directory for cached results: `$XDG_CACHE_HOME/techdocs`, defaulting to `~/.cache/techdocs`
+pub fn cache_dir() -> Option<PathBuf> {
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_home.join("techdocs"))
+}
+
+/// Whether `ANTHROPIC_API_KEY` is set; the key itself is never shown
+pub fn check_api_key() -> CheckResult {
+    const NAME: &str = "API key";
+    match env::var("ANTHROPIC_API_KEY") {
+        Ok(key) if key.trim().is_empty() => {
+            CheckResult::new(NAME, true, CheckStatus::Fail, "ANTHROPIC_API_KEY is set but empty")
+        }
+        Ok(key) if !key.starts_with("sk-ant-") => CheckResult::new(
+            NAME,
+            true,
+            CheckStatus::Warn,
+            "ANTHROPIC_API_KEY is present but does not look like an Anthropic key",
+        ),
+        Ok(_) => CheckResult::new(NAME, true, CheckStatus::Pass, "ANTHROPIC_API_KEY is present"),
+        Err(_) => CheckResult::new(NAME, true, CheckStatus::Fail, "ANTHROPIC_API_KEY is absent"),
+    }
+}
+
+/// Validate the API key with a token count request, which is free and generates nothing
+pub async fn check_api_key_online(model: Option<&str>) -> CheckResult {
+    const NAME: &str = "API key (online)";
+    let client = match ClaudeClient::new() {
+        Ok(client) => client,
+        Err(e) => return CheckResult::new(NAME, true, CheckStatus::Fail, e.to_string()),
+    };
+    let request = CompletionRequest {
+        model: Some(model.unwrap_or(DEFAULT_MODEL).to_string()),
+        ..CompletionRequest::new("", "ping")
+    };
+    match client.count_tokens(&request).await {
+        Ok(_) => CheckResult::new(NAME, true, CheckStatus::Pass, "the API accepted the key"),
+        Err(e) => CheckResult::new(NAME, true, CheckStatus::Fail, e.to_string()),
+    }
+}
+
+/// Whether a TCP connection to the provider base URL can be opened
+pub async fn check_base_url() -> CheckResult {
+    const NAME: &str = "Provider reachable";
+    let base_url = env::var("ANTHROPIC_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+    let url = match Url::parse(&base_url) {
+        Ok(url) => url,
+        Err(e) => {
+            return CheckResult::new(NAME, true, CheckStatus::Fail, format!("invalid base URL {}: {}", base_url, e));
+        }
+    };
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return CheckResult::new(NAME, true, CheckStatus::Fail, format!("base URL {} has no host", base_url));
+    };
+
+    let connect = tokio::net::TcpStream::connect((host, port));
+    match tokio::time::timeout(Duration::from_secs(5), connect).await {
+        Ok(Ok(_)) => CheckResult::new(NAME, true, CheckStatus::Pass, format!("connected to {}:{}", host, port)),
+        Ok(Err(e)) => CheckResult::new(NAME, true, CheckStatus::Fail, format!("{}:{}: {}", host, port, e)),
+        Err(_) => CheckResult::new(NAME, true, CheckStatus::Fail, format!("{}:{}: timed out", host, port)),
+    }
+}
+
+/// Whether libgit2 works, by initializing a repository in a temporary directory
+pub fn check_git() -> CheckResult {
+    const NAME: &str = "Git";
+    let result = TempDir::new()
+        .map_err(|e| e.to_string())
+        .and_then(|dir| git2::Repository::init(dir.path()).map(|_| ()).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => {
+            let version = git2::Version::get();
+            let (major, minor, patch) = version.libgit2_version();
+            CheckResult::new(NAME, true, CheckStatus::Pass, format!("libgit2 {}.{}.{}", major, minor, patch))
+        }
+        Err(e) => CheckResult::new(NAME, true, CheckStatus::Fail, e),
+    }
+}
+
+/// Whether the cache directory can be created and written to
+pub fn check_cache_dir() -> CheckResult {
+    const NAME: &str = "Cache directory";
+    let Some(dir) = cache_dir() else {
+        return CheckResult::new(NAME, false, CheckStatus::Warn, "no HOME or XDG_CACHE_HOME set");
+    };
+    let probe = dir.join(format!(".doctor-{}", std::process::id()));
+    let result = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&probe, b"ok"))
+        .and_then(|_| fs::remove_file(&probe));
+    match result {
+        Ok(()) => CheckResult::new(NAME, false, CheckStatus::Pass, format!("{} is writable", dir.display())),
+        Err(e) => CheckResult::new(NAME, false, CheckStatus::Warn, format!("{}: {}", dir.display(), e)),
+    }
+}
+
+/// Whether `dir` can be written to and its file system has at least `min_free_bytes`
+/// free. Where free space cannot be found out, only writing is checked.
+pub fn check_disk_space(name: &'static str, dir: &Path, min_free_bytes: u64) -> CheckResult {
+    let probe = dir.join(format!(".doctor-{}", uuid::Uuid::new_v4()));
+    if let Err(e) = fs::write(&probe, b"ok").and_then(|_| fs::remove_file(&probe)) {
+        return CheckResult::new(name, true, CheckStatus::Fail, format!("{} is not writable: {}", dir.display(), e));
+    }
+    let mb = |bytes: u64| bytes / (1024 * 1024);
+    match free_space(dir) {
+        Some(free) if free < min_free_bytes => CheckResult::new(
+            name,
+            true,
+            CheckStatus::Fail,
+            format!("{} MB free on {}, under the {} MB required", mb(free), dir.display(), mb(min_free_bytes)),
+        ),
+        Some(free) => CheckResult::new(name, true, CheckStatus::Pass, format!("{} MB free on {}", mb(free), dir.display())),
+        None => CheckResult::new(name, true, CheckStatus::Pass, format!("{} is writable", dir.display())),
+    }
+}
+
+/// Bytes available to this process on the file system holding `path`
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    let stats = rustix::fs::statvfs(path).ok()?;
+    Some(stats.f_bavail.saturating_mul(stats.f_frsize))
+}
+
+/// Bytes available to this process on the file system holding `path`
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Whether the configuration layers merge cleanly and the README prompt can be loaded
+pub fn check_config(project_dir: Option<&Path>, cli: PartialConfig) -> (CheckResult, Option<Config>) {
+    const NAME: &str = "Configuration";
+    let config = match Config::load(project_dir, cli) {
+        Ok(config) => config,
+        Err(e) => return (CheckResult::new(NAME, true, CheckStatus::Fail, e.to_string()), None),
+    };
+    let result = match load_readme_prompt(config.prompt_file.as_deref()) {
+        Ok(_) => CheckResult::new(NAME, true, CheckStatus::Pass, config.sources.join(" < ")),
+        Err(e) => {
+            let prompt_file = config.prompt_file.as_deref().unwrap_or(Path::new("")).display();
+            CheckResult::new(NAME, true, CheckStatus::Fail, format!("prompt file {}: {}", prompt_file, e))
+        }
+    };
+    (result, Some(config))
+}
+
+/// Run every check, returning the results and the merged configuration when it loaded.
+/// The check that sends an API request only runs when `online` is set.
+pub async fn run_checks(
+    project_dir: Option<&Path>,
+    cli: PartialConfig,
+    online: bool,
+) -> (Vec<CheckResult>, Option<Config>) {
+    let (config_check, config) = check_config(project_dir, cli);
+    let api_key = check_api_key();
+    let online_check = if online {
+        check_api_key_online(config.as_ref().and_then(|c| c.model.as_deref())).await
+    } else {
+        CheckResult::new("API key (online)", false, CheckStatus::Skip, "pass --online to verify the key")
+    };
+
+    let checks = vec![
+        config_check,
+        api_key,
+        online_check,
+        check_base_url().await,
+        check_git(),
+        check_cache_dir(),
+    ];
+    (checks, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PROJECT_CONFIG_FILE;
+    use crate::testing::FixtureTree;
+
+    #[test]
+    fn only_required_failures_block() {
+        let failed = CheckResult::new("a", true, CheckStatus::Fail, "broken");
+        let optional = CheckResult::new("b", false, CheckStatus::Fail, "broken");
+        let warned = CheckResult::new("c", true, CheckStatus::Warn, "odd");
+        assert!(failed.is_blocking());
+        assert!(!optional.is_blocking());
+        assert!(!warned.is_blocking());
+
+        // Statuses are padded so a report lines up
+        assert_eq!(format!("[{:<5}]", CheckStatus::Pass), "[pass ]");
+        assert_eq!(CheckStatus::Fail.to_string(), "FAIL");
+    }
+
+    #[test]
+    fn the_api_key_is_never_shown() {
+        let check = check_api_key();
+        if let Ok(key) = env::var("ANTHROPIC_API_KEY") {
+            if !key.trim().is_empty() {
+                assert!(!check.detail.contains(key.trim()), "{}", check.detail);
+            }
+        }
+        assert!(check.detail.contains("ANTHROPIC_API_KEY"), "{}", check.detail);
+    }
+
+    #[test]
+    fn git_check_reports_the_libgit2_version() {
+        let check = check_git();
+        assert_eq!(check.status, CheckStatus::Pass, "{}", check.detail);
+        assert!(check.detail.starts_with("libgit2 "), "{}", check.detail);
+    }
+
+    #[test]
+    fn disk_space_checks_writability_and_the_minimum() {
+        let tree = FixtureTree::new().dir("work");
+        let check = check_disk_space("Work dir", &tree.join("work"), 0);
+        assert_eq!(check.status, CheckStatus::Pass, "{}", check.detail);
+        assert_eq!(check.name, "Work dir");
+
+        let check = check_disk_space("Work dir", &tree.join("work"), u64::MAX);
+        if free_space(&tree.join("work")).is_some() {
+            assert_eq!(check.status, CheckStatus::Fail);
+            assert!(check.detail.contains("MB required"), "{}", check.detail);
+        }
+
+        let check = check_disk_space("Work dir", &tree.join("missing"), 0);
+        assert!(check.is_blocking());
+        assert!(check.detail.contains("is not writable"), "{}", check.detail);
+        // The probe file is cleaned up
+        assert_eq!(fs::read_dir(tree.join("work")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn config_check_lists_sources_or_the_failure() {
+        let tree = FixtureTree::new().file(PROJECT_CONFIG_FILE, "max_file_size_kb = 10\n");
+        let (check, config) = check_config(Some(tree.path()), PartialConfig::default());
+        assert_eq!(check.status, CheckStatus::Pass, "{}", check.detail);
+        assert!(check.detail.starts_with("defaults < "), "{}", check.detail);
+        assert!(check.detail.contains(PROJECT_CONFIG_FILE), "{}", check.detail);
+        assert_eq!(config.unwrap().max_file_size_kb, 10);
+
+        let tree = FixtureTree::new().file(PROJECT_CONFIG_FILE, "max_file_size_kb = 0\n");
+        let (check, config) = check_config(Some(tree.path()), PartialConfig::default());
+        assert!(check.is_blocking());
+        assert!(check.detail.contains("`max_file_size_kb`"), "{}", check.detail);
+        assert!(config.is_none());
+
+        let tree = FixtureTree::new().file(PROJECT_CONFIG_FILE, "prompt_file = \"prompts/missing.txt\"\n");
+        let (check, config) = check_config(Some(tree.path()), PartialConfig::default());
+        assert!(check.is_blocking());
+        assert!(check.detail.starts_with("prompt file "), "{}", check.detail);
+        assert!(check.detail.contains("missing.txt"), "{}", check.detail);
+        assert!(config.is_some());
+    }
+
+    #[tokio::test]
+    async fn offline_runs_skip_the_api_request() {
+        let tree = FixtureTree::new();
+        let (checks, config) = run_checks(Some(tree.path()), PartialConfig::default(), false).await;
+        let names: Vec<_> = checks.iter().map(|check| check.name).collect();
+        assert_eq!(
+            names,
+            ["Configuration", "API key", "API key (online)", "Provider reachable", "Git", "Cache directory"]
+        );
+        assert_eq!(checks[2].status, CheckStatus::Skip);
+        assert!(!checks[2].is_blocking());
+        assert!(config.is_some());
+    }
+}
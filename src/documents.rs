@@ -0,0 +1,272 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::provider::Usage;
+use crate::{write_atomic_bytes, FileContext, Result, TechDocsError};
+
+/// Where a stored document came from
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DocumentInfo {
+    /// Identifier the document is fetched by
+    #[schema(example = "0b7c1f6e-2f9d-4d4b-9a57-3c1e6f0d2a41")]
+    pub id: String,
+    /// Path, repository URL, or upload the document was generated from
+    #[schema(example = "https://github.com/username/repo")]
+    pub source: String,
+    /// `owner/name`, when the source is a GitHub repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "username/repo")]
+    pub repository: Option<String>,
+    /// Commit the document describes, when the source is a git repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub model: String,
+    pub template: String,
+    pub usage: Usage,
+    #[schema(value_type = String, example = "2025-01-31T12:00:00Z")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// A generated document and where it came from
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Document {
+    #[serde(flatten)]
+    pub info: DocumentInfo,
+    /// The markdown
+    pub content: String,
+}
+
+/// A new random document id
+pub fn new_document_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Somewhere generated documents are kept
+#[async_trait]
+pub trait DocumentStore: Send + Sync {
+    /// Store `document` under its id, replacing any document with the same id
+    async fn put(&self, document: &Document) -> Result<()>;
+
+    /// The document with `id`, or `None` when there is none
+    async fn get(&self, id: &str) -> Result<Option<Document>>;
+
+    /// Every stored document, newest first
+    async fn list(&self) -> Result<Vec<DocumentInfo>>;
+
+    /// Remove the document with `id`, returning whether there was one
+    async fn delete(&self, id: &str) -> Result<bool>;
+}
+
+/// How many documents are kept, and for how long
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Retention {
+    /// Most documents kept; older ones are deleted first
+    pub max_documents: Option<usize>,
+    /// Documents older than this are deleted
+    pub max_age: Option<Duration>,
+}
+
+impl Retention {
+    pub fn is_unbounded(&self) -> bool {
+        self.max_documents.is_none() && self.max_age.is_none()
+    }
+
+    /// Delete the documents in `store` the retention does not keep, returning how many
+    pub async fn sweep(&self, store: &dyn DocumentStore) -> Result<usize> {
+        if self.is_unbounded() {
+            return Ok(0);
+        }
+        let now = Utc::now();
+        let documents = store.list().await?;
+        let mut removed = 0;
+        for (index, info) in documents.iter().enumerate() {
+            let too_many = self.max_documents.is_some_and(|max| index >= max);
+            let too_old = self.max_age.is_some_and(|max_age| {
+                (now - info.created_at).to_std().is_ok_and(|age| age > max_age)
+            });
+            if (too_many || too_old) && store.delete(&info.id).await? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Documents in a local directory: the metadata as `<id>.json` next to the markdown
+/// as `<id>.md`
+pub struct LocalDocumentStore {
+    dir: PathBuf,
+}
+
+impl LocalDocumentStore {
+    /// Create a store, creating `dir` when needed
+    pub fn new(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir).map_err(|e| {
+            TechDocsError::Config(format!("cannot create documents directory {}: {}", dir.display(), e))
+        })?;
+        Ok(Self { dir })
+    }
+
+    /// The metadata and markdown files of `id`, or `None` for an id this store never
+    /// hands out, so a request cannot name a path outside the directory
+    fn files(&self, id: &str) -> Option<(PathBuf, PathBuf)> {
+        uuid::Uuid::parse_str(id).ok()?;
+        Some((self.dir.join(format!("{}.json", id)), self.dir.join(format!("{}.md", id))))
+    }
+}
+
+#[async_trait]
+impl DocumentStore for LocalDocumentStore {
+    async fn put(&self, document: &Document) -> Result<()> {
+        let (info_file, content_file) = self
+            .files(&document.info.id)
+            .ok_or_else(|| TechDocsError::Output(format!("invalid document id {}", document.info.id)))?;
+        // The metadata is written last, so a listed document always has its markdown
+        write_atomic_bytes(&content_file, document.content.as_bytes())?;
+        let info = serde_json::to_vec_pretty(&document.info).map_err(|e| TechDocsError::Output(e.to_string()))?;
+        write_atomic_bytes(&info_file, &info)
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Document>> {
+        let Some((info_file, content_file)) = self.files(id) else {
+            return Ok(None);
+        };
+        let info = match fs::read(&info_file) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| TechDocsError::Output(format!("{}: {}", info_file.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(TechDocsError::File { action: "read", path: info_file, source: e }),
+        };
+        let content = fs::read_to_string(&content_file).file_context("read", &content_file)?;
+        Ok(Some(Document { info, content }))
+    }
+
+    async fn list(&self) -> Result<Vec<DocumentInfo>> {
+        let mut documents = Vec::new();
+        for entry in fs::read_dir(&self.dir).file_context("read", &self.dir)?.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            match fs::read(&path).map(|bytes| serde_json::from_slice::<DocumentInfo>(&bytes)) {
+                Ok(Ok(info)) => documents.push(info),
+                Ok(Err(e)) => tracing::warn!("skipping unreadable document {}: {}", path.display(), e),
+                // Deleted since the directory was read
+                Err(_) => {}
+            }
+        }
+        documents.sort_by_key(|info| std::cmp::Reverse(info.created_at));
+        Ok(documents)
+    }
+
+    async fn delete(&self, id: &str) -> Result<bool> {
+        let Some((info_file, content_file)) = self.files(id) else {
+            return Ok(false);
+        };
+        // Without its metadata the document is no longer listed, even if the markdown
+        // cannot be removed
+        let existed = match fs::remove_file(&info_file) {
+            Ok(()) => true,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e.into()),
+        };
+        if let Err(e) = fs::remove_file(&content_file) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("cannot remove {}: {}", content_file.display(), e);
+            }
+        }
+        Ok(existed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    fn document(age: chrono::Duration, content: &str) -> Document {
+        Document {
+            info: DocumentInfo {
+                id: new_document_id(),
+                source: "https://github.com/acme/demo".into(),
+                repository: Some("acme/demo".into()),
+                commit: None,
+                model: "claude-test".into(),
+                template: "readme".into(),
+                usage: Usage::default(),
+                created_at: Utc::now() - age,
+            },
+            content: content.into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn stored_documents_are_fetched_and_listed_newest_first() {
+        let dir = FixtureTree::new();
+        let store = LocalDocumentStore::new(dir.join("documents")).unwrap();
+        let (old, new) = (document(chrono::Duration::hours(2), "# Old\n"), document(chrono::Duration::zero(), "# New\n"));
+        store.put(&old).await.unwrap();
+        store.put(&new).await.unwrap();
+
+        let fetched = store.get(&old.info.id).await.unwrap().unwrap();
+        assert_eq!((fetched.content.as_str(), fetched.info.repository.as_deref()), ("# Old\n", Some("acme/demo")));
+        let listed: Vec<String> = store.list().await.unwrap().into_iter().map(|info| info.id).collect();
+        assert_eq!(listed, [new.info.id.clone(), old.info.id.clone()]);
+
+        // Replacing keeps one copy
+        store.put(&Document { content: "# Newer\n".into(), ..new.clone() }).await.unwrap();
+        assert_eq!(store.get(&new.info.id).await.unwrap().unwrap().content, "# Newer\n");
+        assert_eq!(store.list().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn missing_and_invalid_ids_are_not_found() {
+        let dir = FixtureTree::new().file("notes.json", "not a document");
+        let store = LocalDocumentStore::new(dir.path().to_path_buf()).unwrap();
+        assert!(store.get(&new_document_id()).await.unwrap().is_none());
+        assert!(store.get("../notes").await.unwrap().is_none());
+        assert!(!store.delete(&new_document_id()).await.unwrap());
+        assert!(!store.delete("../notes").await.unwrap());
+        // An unreadable file in the directory is skipped rather than failing the list
+        assert!(store.list().await.unwrap().is_empty());
+
+        let mut invalid = document(chrono::Duration::zero(), "# Demo\n");
+        invalid.info.id = "../escape".into();
+        assert!(store.put(&invalid).await.unwrap_err().to_string().contains("invalid document id"));
+    }
+
+    #[tokio::test]
+    async fn deleted_documents_are_gone() {
+        let dir = FixtureTree::new();
+        let store = LocalDocumentStore::new(dir.path().to_path_buf()).unwrap();
+        let stored = document(chrono::Duration::zero(), "# Demo\n");
+        store.put(&stored).await.unwrap();
+        assert!(store.delete(&stored.info.id).await.unwrap());
+        assert!(store.get(&stored.info.id).await.unwrap().is_none());
+        assert!(!dir.join(format!("{}.md", stored.info.id)).exists());
+    }
+
+    #[tokio::test]
+    async fn retention_removes_the_oldest_and_the_expired() {
+        let dir = FixtureTree::new();
+        let store = LocalDocumentStore::new(dir.path().to_path_buf()).unwrap();
+        let ages = [0, 1, 2, 48].map(chrono::Duration::hours);
+        let documents: Vec<Document> = ages.into_iter().map(|age| document(age, "# Demo\n")).collect();
+        for document in &documents {
+            store.put(document).await.unwrap();
+        }
+
+        assert_eq!(Retention::default().sweep(&store).await.unwrap(), 0);
+        let by_age = Retention { max_documents: None, max_age: Some(Duration::from_secs(24 * 60 * 60)) };
+        assert_eq!(by_age.sweep(&store).await.unwrap(), 1);
+        let by_count = Retention { max_documents: Some(2), max_age: None };
+        assert_eq!(by_count.sweep(&store).await.unwrap(), 1);
+        let kept: Vec<String> = store.list().await.unwrap().into_iter().map(|info| info.id).collect();
+        assert_eq!(kept, [documents[0].info.id.clone(), documents[1].info.id.clone()]);
+    }
+}
@@ -0,0 +1,257 @@
+use std::path::{Path, PathBuf};
+use url::Url;
+
+#[cfg(feature = "claude")]
+use crate::{net, FileContext, Result, TechDocsError};
+
+/// Largest file a single-file URL may point at
+pub const MAX_DOWNLOAD_BYTES: u64 = 10 * 1024 * 1024;
+
+/// How long a single-file download may take
+#[cfg(feature = "claude")]
+pub const DOWNLOAD_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Redirects a download follows, each checked like the URL it started from
+#[cfg(feature = "claude")]
+const MAX_REDIRECTS: usize = 5;
+
+/// Extensions of the source and text files a URL on any host may be downloaded for
+pub const TEXT_EXTENSIONS: &[&str] = &[
+    "bash", "c", "cc", "cfg", "clj", "cpp", "cs", "css", "dart", "ex", "exs", "go", "gradle", "h", "hpp", "hs",
+    "html", "ini", "java", "js", "json", "jsx", "kt", "kts", "lua", "md", "mjs", "ml", "nix", "php", "pl", "proto",
+    "ps1", "py", "r", "rb", "rs", "rst", "scala", "scss", "sh", "sql", "svelte", "swift", "tf", "toml", "ts", "tsx",
+    "txt", "vue", "xml", "yaml", "yml", "zig", "zsh",
+];
+
+/// Hosts that serve raw files whatever their extension
+const RAW_HOSTS: &[&str] = &["raw.githubusercontent.com", "gist.githubusercontent.com"];
+
+/// Content types other than `text/*` accepted for a download
+#[cfg(feature = "claude")]
+const TEXT_CONTENT_TYPES: &[&str] = &[
+    "application/json",
+    "application/javascript",
+    "application/toml",
+    "application/x-sh",
+    "application/x-yaml",
+    "application/xml",
+    "application/yaml",
+];
+
+/// Whether a response of `content_type`, lowercase and without parameters, is text
+#[cfg(feature = "claude")]
+fn is_text_content_type(content_type: &str) -> bool {
+    content_type.starts_with("text/")
+        || content_type.ends_with("+json")
+        || content_type.ends_with("+xml")
+        || TEXT_CONTENT_TYPES.contains(&content_type)
+}
+
+/// Whether `url` points at a single text file: anything on a raw file host, or a path
+/// ending in one of [`TEXT_EXTENSIONS`]
+pub fn is_file_url(url: &Url) -> bool {
+    if url.host_str().is_some_and(|host| RAW_HOSTS.contains(&host)) {
+        return true;
+    }
+    Path::new(url.path())
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| TEXT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Name the file downloaded from `url` is saved under: the last segment of its path,
+/// or `file.txt` when that is empty or not a plain name
+pub fn file_name(url: &Url) -> PathBuf {
+    let name = url.path_segments().and_then(|mut segments| segments.next_back()).unwrap_or_default();
+    match Path::new(name).file_name() {
+        Some(file_name) if file_name == name => PathBuf::from(name),
+        _ => PathBuf::from("file.txt"),
+    }
+}
+
+/// Download the text file at `url` into `dir` and return its path. Fails for a file
+/// over [`MAX_DOWNLOAD_BYTES`], checked against the announced length and again while
+/// reading, and for a response that is not text by its content type or, when the
+/// server does not say, by its content. Only https URLs whose host resolves to public
+/// addresses are fetched, redirects included, so a URL cannot make the caller reach
+/// its own network.
+#[cfg(feature = "claude")]
+pub async fn download_file(url: &Url, dir: &Path) -> Result<PathBuf> {
+    download_from(url, dir, None).await
+}
+
+/// [`download_file`], also fetching from the `trusted` host over http and whatever
+/// address it resolves to; the tests serve files from it locally
+#[cfg(feature = "claude")]
+async fn download_from(url: &Url, dir: &Path, trusted: Option<&str>) -> Result<PathBuf> {
+    let failed = |reason: String| TechDocsError::Url(format!("cannot download {}: {}", url, reason));
+    let mut current = url.clone();
+    let mut redirects = 0;
+    let mut response = loop {
+        let addr = address(&current, trusted).await.map_err(|reason| match redirects {
+            0 => failed(reason),
+            _ => failed(format!("redirected to {}: {}", current, reason)),
+        })?;
+        // Redirects are followed here rather than by the client, so each one is checked
+        let mut client = reqwest::Client::builder()
+            .user_agent(concat!("techdocs/", env!("CARGO_PKG_VERSION")))
+            .timeout(DOWNLOAD_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none());
+        if let Some(domain) = current.domain() {
+            client = client.resolve(domain, addr);
+        }
+        let client = client.build().map_err(|e| failed(e.to_string()))?;
+        let response = client.get(current.clone()).send().await.map_err(|e| failed(e.to_string()))?;
+        if !response.status().is_redirection() {
+            break response;
+        }
+        if redirects == MAX_REDIRECTS {
+            return Err(failed(format!("more than {} redirects", MAX_REDIRECTS)));
+        }
+        redirects += 1;
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| failed(format!("server answered {} without a location", response.status())))?;
+        current = current.join(location).map_err(|e| failed(format!("redirected to an invalid URL: {}", e)))?;
+    };
+    if !response.status().is_success() {
+        return Err(failed(format!("server answered {}", response.status())));
+    }
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(';').next().unwrap_or_default().trim().to_ascii_lowercase());
+    // Servers that do not know an extension call the file `application/octet-stream`;
+    // such a file is kept when its content turns out to be text
+    let sniff = match content_type.as_deref() {
+        None | Some("application/octet-stream") => true,
+        Some(content_type) if is_text_content_type(content_type) => false,
+        Some(content_type) => return Err(failed(format!("{} is not a text file", content_type))),
+    };
+    let too_large = || failed(format!("larger than {} bytes", MAX_DOWNLOAD_BYTES));
+    if response.content_length().is_some_and(|length| length > MAX_DOWNLOAD_BYTES) {
+        return Err(too_large());
+    }
+
+    let mut content = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| failed(e.to_string()))? {
+        if (content.len() + chunk.len()) as u64 > MAX_DOWNLOAD_BYTES {
+            return Err(too_large());
+        }
+        content.extend_from_slice(&chunk);
+    }
+    if sniff && (content.contains(&0) || std::str::from_utf8(&content).is_err()) {
+        return Err(failed("the content is not text".to_string()));
+    }
+    let path = dir.join(file_name(url));
+    tokio::fs::write(&path, &content).await.file_context("write", &path)?;
+    Ok(path)
+}
+
+/// The address to fetch `url` from: its host's, when the URL is https and the host
+/// resolves to public addresses only, or when the host is `trusted`
+#[cfg(feature = "claude")]
+async fn address(url: &Url, trusted: Option<&str>) -> std::result::Result<std::net::SocketAddr, String> {
+    let host = url.host_str().ok_or("the URL has no host")?;
+    let trusted = trusted == Some(host);
+    if url.scheme() != "https" && !trusted {
+        return Err("only https URLs are downloaded".to_string());
+    }
+    let port = url.port_or_known_default().ok_or("the URL has no port")?;
+    net::resolve(host, port, !trusted).await
+}
+
+#[cfg(all(test, feature = "claude"))]
+mod tests {
+    use super::*;
+    use temp_dir::TempDir;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// A local server answering one connection per response in turn, and its address.
+    /// `responses` gets the address, for redirects back to the server.
+    async fn server(responses: impl FnOnce(&str) -> Vec<String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let responses = responses(&addr);
+        tokio::spawn(async move {
+            for response in responses {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).await.unwrap() > 0 && !line.ends_with("\r\n\r\n") {}
+                reader.into_inner().write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    fn redirect(location: &str) -> String {
+        format!("HTTP/1.1 302 Found\r\nlocation: {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", location)
+    }
+
+    fn text(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    #[tokio::test]
+    async fn loopback_and_link_local_urls_are_refused() {
+        let dir = TempDir::new().unwrap();
+        for url in [
+            "https://127.0.0.1/notes.md",
+            "https://localhost:8443/notes.md",
+            "https://[::1]/notes.md",
+            "https://169.254.169.254/latest/meta-data.txt",
+            "https://10.0.0.5/config.yaml",
+        ] {
+            let error = download_file(&Url::parse(url).unwrap(), dir.path()).await.unwrap_err().to_string();
+            assert!(error.contains("non-public address"), "{}: {}", url, error);
+        }
+        let error = download_file(&Url::parse("http://example.com/notes.md").unwrap(), dir.path()).await.unwrap_err();
+        assert!(error.to_string().contains("only https"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn redirects_are_checked_like_the_first_url() {
+        let dir = TempDir::new().unwrap();
+        let private = server(|_| vec![text("secret")]).await;
+        let port = private.rsplit(':').next().unwrap();
+        let start = server(|_| vec![redirect(&format!("https://localhost:{}/secret.txt", port))]).await;
+
+        let url = Url::parse(&format!("http://{}/notes.md", start)).unwrap();
+        let error = download_from(&url, dir.path(), Some("127.0.0.1")).await.unwrap_err().to_string();
+        assert!(error.contains("redirected to https://localhost"), "{}", error);
+        assert!(error.contains("non-public address"), "{}", error);
+        assert!(!dir.path().join("notes.md").exists());
+    }
+
+    #[tokio::test]
+    async fn redirects_within_the_rules_are_followed() {
+        let dir = TempDir::new().unwrap();
+        let target = server(|_| vec![text("# Notes\n")]).await;
+        let start = server(|_| vec![redirect(&format!("http://{}/final.md", target))]).await;
+
+        let url = Url::parse(&format!("http://{}/notes.md", start)).unwrap();
+        let path = download_from(&url, dir.path(), Some("127.0.0.1")).await.unwrap();
+        assert_eq!(path, dir.path().join("notes.md"));
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "# Notes\n");
+    }
+
+    #[tokio::test]
+    async fn redirect_loops_stop() {
+        let dir = TempDir::new().unwrap();
+        let start =
+            server(|addr| vec![redirect(&format!("http://{}/again.md", addr)); MAX_REDIRECTS + 1]).await;
+        let url = Url::parse(&format!("http://{}/notes.md", start)).unwrap();
+        let error = download_from(&url, dir.path(), Some("127.0.0.1")).await.unwrap_err().to_string();
+        assert!(error.contains(&format!("more than {} redirects", MAX_REDIRECTS)), "{}", error);
+    }
+}
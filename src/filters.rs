@@ -0,0 +1,256 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::Serialize;
+
+use crate::plan::PromptOptions;
+use crate::{Result, TechDocsError};
+
+/// Name of the preset used unless another is chosen
+pub const DEFAULT_PRESET: &str = "default";
+
+/// Build output, dependency, and editor directories, left out by every preset but `none`.
+/// Cargo's `src/bin` holds sources, so it is kept.
+const BUILD_DIRS: &[&str] = &[
+    "target/", "node_modules/", "dist/", "build/", "out/", "bin/", "!**/src/bin/", "Debug/", "Release/", ".git/",
+    ".idea/", ".vscode/",
+];
+
+/// The presets that can be chosen by name, [`DEFAULT_PRESET`] first
+pub const PRESETS: &[FilterPreset] = &[
+    FilterPreset {
+        name: DEFAULT_PRESET,
+        description: "Leave out build output, dependency, and editor directories",
+        exclude: BUILD_DIRS,
+        include: &[],
+    },
+    FilterPreset {
+        name: "strict",
+        description: "Also leave out tests, fixtures, examples, and benchmarks",
+        exclude: &[
+            "target/", "node_modules/", "dist/", "build/", "out/", "bin/", "!**/src/bin/", "Debug/", "Release/",
+            ".git/", ".idea/", ".vscode/", "tests/", "test/", "__tests__/", "spec/", "fixtures/", "testdata/",
+            "examples/", "example/", "benches/", "*_test.go", "*_test.py", "test_*.py", "*.test.*", "*.spec.*",
+            "*_spec.rb",
+        ],
+        include: &[],
+    },
+    FilterPreset {
+        name: "docs-only",
+        description: "Only documentation, licenses, and package manifests",
+        exclude: BUILD_DIRS,
+        include: &[
+            "*.md", "*.mdx", "*.rst", "*.adoc", "*.txt", "docs/**", "doc/**", "LICENSE*", "COPYING*", "Cargo.toml",
+            "package.json", "pyproject.toml", "go.mod",
+        ],
+    },
+    FilterPreset {
+        name: "code-only",
+        description: "Leave out documentation as well as build output",
+        exclude: &[
+            "target/", "node_modules/", "dist/", "build/", "out/", "bin/", "!**/src/bin/", "Debug/", "Release/",
+            ".git/", ".idea/", ".vscode/", "*.md", "*.mdx", "*.rst", "*.adoc", "docs/", "doc/", "LICENSE*",
+            "COPYING*", "CHANGELOG*",
+        ],
+        include: &[],
+    },
+    FilterPreset {
+        name: "none",
+        description: "Nothing beyond .gitignore, hidden files, and your own patterns",
+        exclude: &[],
+        include: &[],
+    },
+];
+
+/// A named set of file filters, applied on top of .gitignore and the exclude and include
+/// patterns of [`PromptOptions`]. A file matching `exclude` is skipped with
+/// [`SkipReason::BuildArtifact`](crate::plan::SkipReason::BuildArtifact); when `include`
+/// is not empty, only files matching it or one of the options' include patterns are
+/// considered. Patterns are in .gitignore format, and a later `!` pattern takes a match
+/// back. Add a preset by adding an entry to [`PRESETS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FilterPreset {
+    pub name: &'static str,
+    /// One line on what the preset sends
+    pub description: &'static str,
+    pub exclude: &'static [&'static str],
+    pub include: &'static [&'static str],
+}
+
+impl Default for FilterPreset {
+    fn default() -> Self {
+        PRESETS[0]
+    }
+}
+
+impl FilterPreset {
+    /// The preset called `name`, failing with [`TechDocsError::Config`] that lists the
+    /// presets when there is none
+    pub fn find(name: &str) -> Result<Self> {
+        PRESETS.iter().find(|preset| preset.name == name).copied().ok_or_else(|| {
+            let names: Vec<&str> = PRESETS.iter().map(|preset| preset.name).collect();
+            TechDocsError::Config(format!("unknown filter preset `{}`; presets: {}", name, names.join(", ")))
+        })
+    }
+}
+
+/// Matches files against the exclude patterns of a [`FilterPreset`]
+pub(crate) struct PresetMatcher {
+    root: PathBuf,
+    matcher: Gitignore,
+}
+
+impl PresetMatcher {
+    pub(crate) fn new(dir: &Path, preset: &FilterPreset) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(dir);
+        for pattern in preset.exclude {
+            builder.add_line(None, pattern)?;
+        }
+        Ok(Self { root: dir.to_path_buf(), matcher: builder.build()? })
+    }
+
+    /// Whether the file at `path`, under the walked directory, is left out
+    pub(crate) fn is_excluded(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        if relative.has_root() {
+            return false;
+        }
+        self.matcher.matched_path_or_any_parents(relative, false).is_ignore()
+    }
+}
+
+/// What a [`FilterRule`] does to the files it matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    Exclude,
+    Include,
+}
+
+impl fmt::Display for RuleAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleAction::Exclude => write!(f, "exclude"),
+            RuleAction::Include => write!(f, "include"),
+        }
+    }
+}
+
+/// One pattern deciding which files are considered, and where it came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FilterRule {
+    pub action: RuleAction,
+    pub pattern: String,
+    /// Name of the preset the rule comes from, or `None` for a configured pattern
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<&'static str>,
+}
+
+/// Every rule `options` applies after .gitignore and hidden files, in the order they
+/// are checked: the configured exclude patterns, then the include patterns of the
+/// preset and the configuration, then the preset's exclusions
+pub fn filter_rules(options: &PromptOptions) -> Vec<FilterRule> {
+    let preset = Some(options.preset.name);
+    let rule = |action, pattern: &str, preset| FilterRule { action, pattern: pattern.to_string(), preset };
+    let mut rules: Vec<FilterRule> =
+        options.exclude.iter().map(|pattern| rule(RuleAction::Exclude, pattern, None)).collect();
+    rules.extend(options.preset.include.iter().map(|pattern| rule(RuleAction::Include, pattern, preset)));
+    rules.extend(options.include.iter().map(|pattern| rule(RuleAction::Include, pattern, None)));
+    rules.extend(options.preset.exclude.iter().map(|pattern| rule(RuleAction::Exclude, pattern, preset)));
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{PromptPlan, SkipReason};
+    use crate::testing::FixtureTree;
+
+    fn project() -> FixtureTree {
+        FixtureTree::new()
+            .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+            .file("README.md", "# Demo\n")
+            .file("src/lib.rs", "")
+            .file("src/bin/tool.rs", "fn main() {}\n")
+            .file("bin/run.sh", "echo\n")
+            .file("tests/smoke.rs", "")
+            .file("docs/guide.md", "# Guide\n")
+            .file("target/debug/out.txt", "")
+    }
+
+    /// Relative paths of the plan's files with their skip reasons, sorted
+    fn decisions(tree: &FixtureTree, options: &PromptOptions) -> Vec<(String, Option<SkipReason>)> {
+        let plan = PromptPlan::collect(tree.path(), options).unwrap();
+        let mut decided: Vec<_> = plan
+            .files
+            .iter()
+            .map(|file| (file.path.strip_prefix(tree.path()).unwrap().to_string_lossy().replace('\\', "/"), file.skipped))
+            .collect();
+        decided.sort_by(|a, b| a.0.cmp(&b.0));
+        decided
+    }
+
+    fn sent(tree: &FixtureTree, options: &PromptOptions) -> Vec<String> {
+        decisions(tree, options).into_iter().filter(|(_, skipped)| skipped.is_none()).map(|(path, _)| path).collect()
+    }
+
+    #[test]
+    fn the_default_preset_skips_build_output_but_keeps_cargo_binaries() {
+        let tree = project();
+        let decided = decisions(&tree, &PromptOptions::default());
+        let artifacts: Vec<_> =
+            decided.iter().filter(|(_, skipped)| *skipped == Some(SkipReason::BuildArtifact)).map(|(path, _)| path.as_str()).collect();
+        assert_eq!(artifacts, ["bin/run.sh", "target/debug/out.txt"]);
+        assert!(decided.contains(&("src/bin/tool.rs".to_string(), None)), "{:?}", decided);
+    }
+
+    #[test]
+    fn presets_narrow_or_widen_the_files_sent() {
+        let tree = project();
+        let with = |name: &str| sent(&tree, &PromptOptions::default().preset(FilterPreset::find(name).unwrap()));
+        assert_eq!(with("docs-only"), ["Cargo.toml", "README.md", "docs/guide.md"]);
+        assert_eq!(with("code-only"), ["Cargo.toml", "src/bin/tool.rs", "src/lib.rs", "tests/smoke.rs"]);
+        assert_eq!(with("strict"), ["Cargo.toml", "README.md", "docs/guide.md", "src/bin/tool.rs", "src/lib.rs"]);
+        assert_eq!(with("none").len(), 8);
+    }
+
+    #[test]
+    fn exclude_patterns_win_over_include_patterns() {
+        let tree = project();
+        let options = PromptOptions::default().include(["src/**", "*.md"]).exclude(["src/bin/"]);
+        assert_eq!(sent(&tree, &options), ["README.md", "docs/guide.md", "src/lib.rs"]);
+
+        // A preset's include list is widened by configured includes, and still loses to excludes
+        let options = PromptOptions::default()
+            .preset(FilterPreset::find("docs-only").unwrap())
+            .include(["src/lib.rs"])
+            .exclude(["docs/"]);
+        assert_eq!(sent(&tree, &options), ["Cargo.toml", "README.md", "src/lib.rs"]);
+    }
+
+    #[test]
+    fn rules_are_listed_in_the_order_they_are_checked() {
+        let options = PromptOptions::default()
+            .preset(FilterPreset::find("docs-only").unwrap())
+            .include(["src/lib.rs"])
+            .exclude(["docs/"]);
+        let rules = filter_rules(&options);
+        assert_eq!(rules[0], FilterRule { action: RuleAction::Exclude, pattern: "docs/".into(), preset: None });
+        assert_eq!(rules[1], FilterRule { action: RuleAction::Include, pattern: "*.md".into(), preset: Some("docs-only") });
+        let configured = rules.iter().position(|rule| rule.pattern == "src/lib.rs").unwrap();
+        assert_eq!(rules[configured].preset, None);
+        assert!(rules[configured + 1..].iter().all(|rule| rule.action == RuleAction::Exclude && rule.preset.is_some()));
+    }
+
+    #[test]
+    fn an_unknown_preset_lists_the_known_ones() {
+        let error = FilterPreset::find("tiny").unwrap_err();
+        assert!(
+            matches!(&error, TechDocsError::Config(message)
+                if message == "unknown filter preset `tiny`; presets: default, strict, docs-only, code-only, none"),
+            "{}",
+            error
+        );
+        assert_eq!(FilterPreset::default().name, DEFAULT_PRESET);
+    }
+}
@@ -0,0 +1,523 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::git::{head_commit, history};
+use crate::github::RemoteMetadata;
+use crate::infra::detect_infra;
+use crate::monorepo::ParentContext;
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::plan::{estimate_tokens, PromptOptions, PromptPlan, METADATA_HEADING};
+use crate::postprocess::{Footer, PostProcessors};
+pub use crate::plan::PromptStats;
+use crate::progress::{no_progress, ProgressEvent, ProgressFn};
+use crate::project::project_version;
+use crate::provider::{CompletionRequest, CostTracker, LlmClient, TextFn, ThinkingConfig, Usage};
+#[cfg(feature = "rust-api")]
+use crate::rust_api::rust_api_block;
+use crate::{
+    load_readme_prompt, resolve_path_cancellable, validate_directory, CancellationToken, Result, TechDocsError,
+    DEFAULT_README_PROMPT,
+};
+
+/// How a README is generated: which files go into the prompt, how large it may grow,
+/// and what the model is asked
+#[derive(Debug, Clone)]
+pub struct GenerateOptions {
+    /// Which files go into the prompt
+    pub files: PromptOptions,
+    /// Maximum output tokens, or `None` for the provider default
+    pub max_tokens: Option<u32>,
+    /// System prompt, or `None` for the built-in README prompt
+    pub system_prompt: Option<String>,
+    /// Model to use, or `None` for the provider default
+    pub model: Option<String>,
+    /// Let the model think before it writes, for large or complex projects. The budget
+    /// comes out of `max_tokens`; see [`ThinkingConfig::new`].
+    pub thinking: Option<ThinkingConfig>,
+    /// Append the project's commit history and top contributors to the prompt, when
+    /// it is a git repository
+    pub git_context: bool,
+    /// Summarize a Rust crate's public items and their doc comments in the prompt, with
+    /// the `rust-api` feature
+    pub rust_api: bool,
+    /// Summarize the project's CI workflows, Dockerfiles, Compose files, and Kubernetes
+    /// manifests in the prompt, including ones `files` leaves out
+    pub infra_context: bool,
+    /// Directory above the project whose manifests and README are sent as labeled
+    /// context, for a project that is one directory of a larger one; see
+    /// [`find_context_root`](crate::monorepo::find_context_root). They are capped apart
+    /// from the size limits of `files`.
+    pub context_root: Option<PathBuf>,
+    /// The project's current README, given to the model to keep its tone and structure.
+    /// Leave it out of `files` so it is not sent twice.
+    pub existing_readme: Option<String>,
+    /// What GitHub says about the repository, stated in the prompt's metadata block and
+    /// kept in the provenance; see [`fetch_remote_metadata`](crate::github::fetch_remote_metadata)
+    pub remote_metadata: Option<RemoteMetadata>,
+    /// Stops the clone, the file walk, and the provider call when cancelled
+    pub cancel: CancellationToken,
+    /// Adds up the cost of the provider calls and fails the next one that would pass
+    /// its limit. Share one tracker between the options of a run.
+    pub cost: CostTracker,
+    /// Fix-ups run on the finished README by [`generate_readme`], in order. Callers that
+    /// generate with [`ReadmePrompt`] and edit the result run them when they are done.
+    pub post_processors: PostProcessors,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        Self {
+            files: PromptOptions::default(),
+            max_tokens: None,
+            system_prompt: None,
+            model: None,
+            thinking: None,
+            git_context: true,
+            rust_api: true,
+            infra_context: true,
+            context_root: None,
+            existing_readme: None,
+            remote_metadata: None,
+            cancel: CancellationToken::new(),
+            cost: CostTracker::default(),
+            post_processors: PostProcessors::default(),
+        }
+    }
+}
+
+impl GenerateOptions {
+    /// The options a loaded configuration describes, reading its prompt file when it
+    /// names one and appending its footer
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self {
+            files: PromptOptions::from_config(config),
+            max_tokens: None,
+            system_prompt: config
+                .prompt_file
+                .as_deref()
+                .map(|file| load_readme_prompt(Some(file)))
+                .transpose()?,
+            model: config.model.clone(),
+            thinking: None,
+            git_context: true,
+            rust_api: true,
+            infra_context: true,
+            context_root: None,
+            existing_readme: None,
+            remote_metadata: None,
+            cancel: CancellationToken::new(),
+            cost: CostTracker::default(),
+            post_processors: PostProcessors::builtin(false, None, config.footer.as_deref().map(Footer::new).transpose()?),
+        })
+    }
+}
+
+/// Largest existing README sent with [`GenerateOptions::existing_readme`], in bytes
+pub const EXISTING_README_MAX_BYTES: usize = 16 * 1024;
+
+/// `readme` as context for regenerating it, asking the model to keep its voice. A README
+/// over [`EXISTING_README_MAX_BYTES`] loses lines from the middle, marked where they were.
+pub fn existing_readme_block(readme: &str) -> String {
+    let readme = readme.trim_end();
+    let shown = if readme.len() <= EXISTING_README_MAX_BYTES {
+        readme.to_string()
+    } else {
+        // Keep whole lines from each end, half the budget each
+        let half = EXISTING_README_MAX_BYTES / 2;
+        let mut head = half;
+        while !readme.is_char_boundary(head) {
+            head -= 1;
+        }
+        head = readme[..head].rfind('\n').map_or(head, |newline| newline + 1);
+        let mut tail = readme.len() - half;
+        while !readme.is_char_boundary(tail) {
+            tail += 1;
+        }
+        tail = readme[tail..].find('\n').map_or(tail, |newline| tail + newline + 1);
+        format!(
+            "{}\n[... {} bytes of the current README omitted ...]\n\n{}",
+            &readme[..head],
+            tail - head,
+            &readme[tail..]
+        )
+    };
+    format!(
+        "\n\nCurrent README: preserve its tone, structure, and any factual content that is still \
+         accurate, and change what the files above show to be out of date or missing.\n\n\
+         ----- current README -----\n{}\n----- end of current README -----\n",
+        shown
+    )
+}
+
+/// Where a generated document came from
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct Provenance {
+    /// Path or repository URL the project was read from
+    pub source: String,
+    /// Commit the project was at, when it is a git repository
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// SPDX license detected in the project
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Version in the project's manifest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Directory whose manifests and README were sent as context, for a project inside it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_root: Option<String>,
+    /// The project's directory relative to `context_root`, with `/` separators
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// What GitHub says about the repository, when it was asked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository: Option<RemoteMetadata>,
+}
+
+/// A generated README with what it took to make it
+#[derive(Debug, Clone)]
+pub struct GeneratedDoc {
+    /// The README in Markdown
+    pub content: String,
+    /// Model that generated it
+    pub model: String,
+    pub usage: Usage,
+    /// Why generation stopped (`end_turn`, `max_tokens`, `stop_sequence`, ...)
+    pub stop_reason: Option<String>,
+    /// The stop sequence that ended the README, when `stop_reason` is `stop_sequence`
+    pub stop_sequence: Option<String>,
+    /// Estimated tokens the model spent thinking, included in `usage.output_tokens`
+    pub thinking_tokens: u64,
+    pub stats: PromptStats,
+    pub provenance: Provenance,
+}
+
+/// A README prompt assembled from a project, ready to send. Callers that show, price,
+/// or cache the prompt before sending it build one of these and call
+/// [`ReadmePrompt::generate`]; [`generate_readme`] does both in one step.
+#[derive(Debug, Clone)]
+pub struct ReadmePrompt {
+    pub system: String,
+    /// The project's files, and anything appended to them
+    pub content: String,
+    pub model: Option<String>,
+    pub max_tokens: Option<u32>,
+    pub thinking: Option<ThinkingConfig>,
+    pub stats: PromptStats,
+    pub provenance: Provenance,
+    /// Stops [`ReadmePrompt::generate`] when cancelled
+    pub cancel: CancellationToken,
+    /// Checked before the request is sent and given its usage after
+    pub cost: CostTracker,
+}
+
+impl ReadmePrompt {
+    /// Choose the files under `dir`, read from `source`, and assemble the prompt
+    pub fn build(source: &str, dir: &Path, options: &GenerateOptions, progress: ProgressFn<'_>) -> Result<Self> {
+        let plan = PromptPlan::collect_cancellable(dir, &options.files, progress, &|| options.cancel.is_cancelled())?;
+        Self::from_plan(source, &plan, options)
+    }
+
+    /// Assemble the prompt for files already chosen. The plan's limits and filters
+    /// are used as they are; only the prompt, model, remote metadata, API summary, build
+    /// configuration, context root, existing README, and git context settings of
+    /// `options` apply. The content is the metadata block with the remote metadata, the
+    /// files, then the public API, then the build configuration, then the context root's
+    /// files, then the existing README, then the history.
+    pub fn from_plan(source: &str, plan: &PromptPlan, options: &GenerateOptions) -> Result<Self> {
+        let mut content = Vec::new();
+        plan.write_prompt(&mut content)?;
+        let mut content = String::from_utf8_lossy(&content).into_owned();
+        if let Some(remote) = &options.remote_metadata {
+            // A license found in the files is stated already and wins over GitHub's
+            let facts = remote.prompt_facts(plan.license.is_none());
+            match plan.metadata_block().len() {
+                0 => content.insert_str(0, &format!("{}{}", METADATA_HEADING, facts)),
+                end => content.insert_str(end, &facts),
+            }
+        }
+        #[cfg(feature = "rust-api")]
+        if options.rust_api {
+            if let Some(api) = rust_api_block(&plan.root) {
+                content.push_str(&api);
+            }
+        }
+        if options.infra_context {
+            if let Some(infra) = detect_infra(&plan.root).prompt_block() {
+                content.push_str(&infra);
+            }
+        }
+        let parent = options.context_root.as_deref().and_then(|root| ParentContext::read(root, &plan.root));
+        if let Some(block) = parent.as_ref().and_then(ParentContext::prompt_block) {
+            content.push_str(&block);
+        }
+        if let Some(readme) = &options.existing_readme {
+            content.push_str(&existing_readme_block(readme));
+        }
+        if options.git_context {
+            if let Some(history) = history(&plan.root) {
+                content.push_str(&history.prompt_block());
+            }
+        }
+        Ok(Self {
+            system: options.system_prompt.clone().unwrap_or_else(|| DEFAULT_README_PROMPT.to_string()),
+            content,
+            model: options.model.clone(),
+            max_tokens: options.max_tokens,
+            thinking: options.thinking,
+            stats: PromptStats::from(plan),
+            provenance: Provenance {
+                source: source.to_string(),
+                commit: head_commit(&plan.root),
+                license: plan
+                    .license
+                    .as_ref()
+                    .map(|license| license.spdx.clone())
+                    .or_else(|| options.remote_metadata.as_ref().and_then(|remote| remote.license.clone())),
+                // A workspace member may inherit its version from the root
+                version: project_version(&plan.root)
+                    .or_else(|| parent.as_ref().and_then(|parent| project_version(&parent.root))),
+                context_root: parent.as_ref().map(|parent| parent.root.display().to_string()),
+                target: parent.map(|parent| parent.target),
+                repository: options.remote_metadata.clone(),
+            },
+            cancel: options.cancel.clone(),
+            cost: options.cost.clone(),
+        })
+    }
+
+    /// Rough token estimate for the system prompt and content together
+    pub fn estimated_tokens(&self) -> u64 {
+        estimate_tokens((self.system.len() + self.content.len()) as u64)
+    }
+
+    /// The provider request for this prompt
+    pub fn request(&self) -> CompletionRequest {
+        CompletionRequest {
+            model: self.model.clone(),
+            max_tokens: self.max_tokens,
+            thinking: self.thinking,
+            ..CompletionRequest::new(self.system.clone(), self.content.clone())
+        }
+    }
+
+    /// Send the prompt to `provider`, passing the text to `on_text` as it is generated
+    /// when given one. Cancelling the prompt's token drops the request.
+    pub async fn generate(&self, provider: &dyn LlmClient, on_text: Option<TextFn<'_>>) -> Result<GeneratedDoc> {
+        self.generate_with_progress(provider, on_text, &no_progress).await
+    }
+
+    /// [`ReadmePrompt::generate`], reporting the request's start, each streamed delta,
+    /// and its completion to `progress`. Fails without sending anything when the
+    /// prompt's cost tracker has no room for the request.
+    pub async fn generate_with_progress(
+        &self,
+        provider: &dyn LlmClient,
+        on_text: Option<TextFn<'_>>,
+        progress: ProgressFn<'_>,
+    ) -> Result<GeneratedDoc> {
+        let request = self.request();
+        self.cost.check(&request)?;
+        progress(&ProgressEvent::LlmRequestStarted { model: request.model.clone() });
+        let completion = async {
+            match on_text {
+                Some(on_text) => {
+                    let on_delta = |text: &str| {
+                        progress(&ProgressEvent::LlmDelta { bytes: text.len() });
+                        on_text(text);
+                    };
+                    provider.complete_streaming(&request, &on_delta).await
+                }
+                None => provider.complete(&request).await,
+            }
+        };
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+        let result = self.cancel.run_until_cancelled(completion).await.ok_or(TechDocsError::Cancelled)??;
+        self.cost.record_result(&request, &result);
+        #[cfg(feature = "metrics")]
+        {
+            metrics::record_histogram(metrics::LLM_REQUEST_SECONDS, &[], started.elapsed().as_secs_f64());
+            metrics::increment_counter(metrics::LLM_INPUT_TOKENS, &[], result.usage.input_tokens);
+            metrics::increment_counter(metrics::LLM_OUTPUT_TOKENS, &[], result.usage.output_tokens);
+        }
+        progress(&ProgressEvent::Completed {
+            stats: self.stats,
+            input_tokens: result.usage.input_tokens,
+            output_tokens: result.usage.output_tokens,
+            stop_reason: result.stop_reason.clone(),
+        });
+        Ok(GeneratedDoc {
+            content: tidy_markdown(&result.text),
+            model: result.model,
+            usage: result.usage,
+            stop_reason: result.stop_reason,
+            stop_sequence: result.stop_sequence,
+            thinking_tokens: result.thinking_tokens,
+            stats: self.stats,
+            provenance: self.provenance.clone(),
+        })
+    }
+}
+
+/// Generate a README for `source`, a local directory or GitHub repository URL: resolve
+/// and check it, choose its files, assemble the prompt, have `provider` write the
+/// README, and run the options' post-processors on it. A cloned repository is removed before this returns. Cancelling the options'
+/// token stops whichever step is running with [`TechDocsError::Cancelled`].
+pub async fn generate_readme(source: &str, options: &GenerateOptions, provider: &dyn LlmClient) -> Result<GeneratedDoc> {
+    generate_readme_with_progress(source, options, provider, &no_progress).await
+}
+
+/// [`generate_readme`], reporting the clone, the file walk, and the provider request to
+/// `progress` as they happen
+pub async fn generate_readme_with_progress(
+    source: &str,
+    options: &GenerateOptions,
+    provider: &dyn LlmClient,
+    progress: ProgressFn<'_>,
+) -> Result<GeneratedDoc> {
+    let (path, _temp_dir) = resolve_path_cancellable(source, progress, &options.cancel).await?;
+    validate_directory(&path)?;
+    let prompt = ReadmePrompt::build(source, &path, options, progress)?;
+    let mut doc = prompt.generate_with_progress(provider, None, progress).await?;
+    options.post_processors.run(&mut doc)?;
+    Ok(doc)
+}
+
+/// Clean up generated Markdown: unwrap a document the model fenced as a whole code
+/// block, and end it with exactly one newline
+pub(crate) fn tidy_markdown(text: &str) -> String {
+    let text = text.trim();
+    let unfenced = text
+        .strip_prefix("```")
+        .and_then(|rest| rest.split_once('\n'))
+        .filter(|(language, _)| matches!(language.trim(), "" | "markdown" | "md"))
+        .and_then(|(_, body)| body.strip_suffix("```"))
+        // A body with fences of its own started and ended with separate code blocks
+        .filter(|body| !body.lines().any(|line| line.trim_start().starts_with("```")));
+    format!("{}\n", unfenced.unwrap_or(text).trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::provider::CompletionResult;
+    use crate::testing::{FixtureTree, MockClient};
+
+    fn project() -> FixtureTree {
+        FixtureTree::new()
+            .file("Cargo.toml", "[package]\nname = \"demo\"\nversion = \"1.2.3\"\nlicense = \"MIT\"\n")
+            .file("src/main.rs", "fn main() {\n    println!(\"demo\");\n}\n")
+            .sized_file("assets/blob.txt", 2048)
+    }
+
+    fn options() -> GenerateOptions {
+        GenerateOptions {
+            files: PromptOptions::default().max_file_kb(1),
+            git_context: false,
+            post_processors: PostProcessors::builtin(false, None, Some(Footer::new("Version {version}, {license}").unwrap())),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn a_readme_is_generated_from_a_directory() {
+        let tree = project();
+        let source = tree.path().to_str().unwrap();
+        let provider = MockClient::new(|_| {
+            Ok(CompletionResult {
+                text: "```markdown\n# Demo\n\nPrints a greeting.\n```".into(),
+                model: "mock".into(),
+                usage: Usage { input_tokens: 120, output_tokens: 30 },
+                stop_reason: Some("end_turn".into()),
+                ..Default::default()
+            })
+        });
+        let events = Mutex::new(Vec::new());
+        let progress = |event: &ProgressEvent| {
+            events.lock().unwrap().push(serde_json::to_value(event).unwrap()["event"].as_str().unwrap().to_string());
+        };
+
+        let doc = generate_readme_with_progress(source, &options(), &provider, &progress).await.unwrap();
+        assert_eq!(doc.content, "# Demo\n\nPrints a greeting.\n\nVersion 1.2.3, MIT\n");
+        assert_eq!((doc.model.as_str(), doc.usage.input_tokens, doc.usage.output_tokens), ("mock", 120, 30));
+        assert_eq!(doc.stop_reason.as_deref(), Some("end_turn"));
+        assert_eq!((doc.stats.files_included, doc.stats.files_skipped), (2, 1));
+        assert!(!doc.stats.budget_reached);
+        assert_eq!(doc.provenance.source, source);
+        assert_eq!(doc.provenance.license.as_deref(), Some("MIT"));
+        assert_eq!(doc.provenance.version.as_deref(), Some("1.2.3"));
+        assert_eq!(doc.provenance.commit, None);
+
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].system, DEFAULT_README_PROMPT);
+        assert!(requests[0].content.contains("main.rs"), "{}", requests[0].content);
+        assert!(!requests[0].content.contains("blob.txt"), "{}", requests[0].content);
+
+        let events = events.into_inner().unwrap();
+        let milestones: Vec<&str> = events
+            .iter()
+            .map(String::as_str)
+            .filter(|event| matches!(*event, "walk_finished" | "llm_request_started" | "completed"))
+            .collect();
+        assert_eq!(milestones, ["walk_finished", "llm_request_started", "completed"]);
+    }
+
+    #[tokio::test]
+    async fn the_cost_limit_stops_the_request_before_it_is_sent() {
+        let tree = project();
+        let options = GenerateOptions { cost: CostTracker::new(Some(0.000001)), ..options() };
+        let provider = MockClient::text("# Demo\n");
+        let error = generate_readme(tree.path().to_str().unwrap(), &options, &provider).await.unwrap_err();
+        assert!(matches!(error, TechDocsError::CostLimitExceeded { .. }), "{}", error);
+        assert!(provider.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_generation_sends_nothing() {
+        let tree = project();
+        let options = options();
+        options.cancel.cancel();
+        let provider = MockClient::text("# Demo\n");
+        let error = generate_readme(tree.path().to_str().unwrap(), &options, &provider).await.unwrap_err();
+        assert!(matches!(error, TechDocsError::Cancelled), "{}", error);
+        assert!(provider.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancelling_during_collection_stops_before_anything_is_written() {
+        let tree = project();
+        let source = tree.path().to_str().unwrap();
+        let options = options();
+        let events = Mutex::new(Vec::new());
+        let progress = |event: &ProgressEvent| {
+            if let ProgressEvent::Scan { entries_seen: 2 } = event {
+                options.cancel.cancel();
+            }
+            events.lock().unwrap().push(serde_json::to_value(event).unwrap()["event"].as_str().unwrap().to_string());
+        };
+        let provider = MockClient::text("# Demo\n");
+
+        let error = generate_readme_with_progress(source, &options, &provider, &progress).await.unwrap_err();
+        assert!(matches!(error, TechDocsError::Cancelled), "{}", error);
+        assert!(provider.requests().is_empty());
+        let events = events.into_inner().unwrap();
+        assert!(events.iter().all(|event| event == "scan"), "{:?}", events);
+        assert_eq!(events.len(), 2);
+        assert!(!tree.join("README.md").exists());
+    }
+
+    #[test]
+    fn a_large_existing_readme_loses_its_middle() {
+        let readme = (0..4000).map(|line| format!("line {}\n", line)).collect::<String>();
+        let block = existing_readme_block(&readme);
+        assert!(block.contains("line 0\n") && block.contains("line 3999\n"));
+        assert!(!block.contains("line 2000\n"));
+        assert!(block.contains("bytes of the current README omitted"));
+    }
+}
@@ -0,0 +1,512 @@
+use std::collections::HashMap;
+use std::path::Path;
+use git2::{Diff, DiffOptions, Oid, Patch, Repository, Sort};
+
+use crate::{Result, TechDocsError};
+
+/// A commit with the metadata needed for changelogs and release notes
+#[derive(Debug, Clone)]
+pub struct CommitInfo {
+    /// Full commit hash
+    pub id: String,
+    /// First line of the commit message
+    pub summary: String,
+    /// Remainder of the commit message after the summary
+    pub body: String,
+    /// Author name
+    pub author: String,
+    /// Commit time in seconds since the Unix epoch
+    pub time: i64,
+    /// Whether the commit has more than one parent
+    pub is_merge: bool,
+    /// Number of files changed relative to the first parent
+    pub files_changed: usize,
+    /// Lines added relative to the first parent
+    pub insertions: usize,
+    /// Lines removed relative to the first parent
+    pub deletions: usize,
+}
+
+impl CommitInfo {
+    /// Abbreviated commit hash
+    pub fn short_id(&self) -> &str {
+        &self.id[..self.id.len().min(7)]
+    }
+}
+
+/// A tag and the commit it points at
+#[derive(Debug, Clone)]
+pub struct TagInfo {
+    pub name: String,
+    pub commit: Oid,
+}
+
+/// Open the git repository containing `dir`
+pub fn open_repository(dir: &Path) -> Result<Repository> {
+    Repository::discover(dir).map_err(|e| {
+        TechDocsError::Git(git2::Error::from_str(&format!(
+            "{} is not inside a git repository: {}",
+            dir.display(),
+            e.message()
+        )))
+    })
+}
+
+/// Resolve a revision (branch, tag, or hash) to the commit it refers to
+pub fn resolve_commit(repo: &Repository, rev: &str) -> Result<Oid> {
+    let object = repo.revparse_single(rev)?;
+    Ok(object.peel_to_commit()?.id())
+}
+
+/// The commit checked out in the repository containing `dir`, when there is one
+pub fn head_commit(dir: &Path) -> Option<String> {
+    open_repository(dir)
+        .and_then(|repo| resolve_commit(&repo, "HEAD"))
+        .map(|commit| commit.to_string())
+        .ok()
+}
+
+/// All tags in the repository, keyed by the commit they point at.
+/// Both annotated and lightweight tags are peeled to their commit.
+pub fn tags_by_commit(repo: &Repository) -> Result<HashMap<Oid, Vec<String>>> {
+    let mut tags: HashMap<Oid, Vec<String>> = HashMap::new();
+    for name in repo.tag_names(None)?.iter().flatten() {
+        let reference = format!("refs/tags/{}", name);
+        let commit = match repo.revparse_single(&reference).and_then(|o| o.peel_to_commit()) {
+            Ok(commit) => commit.id(),
+            // Tags pointing at trees or blobs can't bound a commit range
+            Err(_) => continue,
+        };
+        tags.entry(commit).or_default().push(name.to_string());
+    }
+    Ok(tags)
+}
+
+/// Find the most recent tag reachable from `start`.
+/// When `skip_start` is set, tags on `start` itself are ignored.
+pub fn latest_tag(repo: &Repository, start: Oid, skip_start: bool) -> Result<Option<TagInfo>> {
+    let tags = tags_by_commit(repo)?;
+    if tags.is_empty() {
+        return Ok(None);
+    }
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    revwalk.push(start)?;
+
+    for oid in revwalk {
+        let oid = oid?;
+        if skip_start && oid == start {
+            continue;
+        }
+        if let Some(names) = tags.get(&oid) {
+            // Prefer the lexically greatest name when several tags share a commit
+            let name = names.iter().max().cloned().unwrap_or_default();
+            return Ok(Some(TagInfo { name, commit: oid }));
+        }
+    }
+    Ok(None)
+}
+
+/// Commits [`commits_between`] collects by default; older ones in a range are only counted
+pub const DEFAULT_MAX_COMMITS: usize = 500;
+
+/// The commits of a range, newest first, as far as the commit limit allows
+#[derive(Debug, Clone, Default)]
+pub struct CommitRange {
+    /// The newest commits of the range, with diff statistics
+    pub commits: Vec<CommitInfo>,
+    /// Older commits of the range past the limit, which were neither diffed nor collected
+    pub omitted: usize,
+}
+
+impl CommitRange {
+    /// Number of commits in the range, collected or not
+    pub fn total(&self) -> usize {
+        self.commits.len() + self.omitted
+    }
+}
+
+/// Collect commits reachable from `to` but not from `from`, newest first.
+/// With no `from`, history is walked back to the root commit. Only the newest
+/// `max_commits` are diffed and collected; the rest are counted in [`CommitRange::omitted`].
+pub fn commits_between(repo: &Repository, from: Option<Oid>, to: Oid, max_commits: usize) -> Result<CommitRange> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    revwalk.push(to)?;
+    if let Some(from) = from {
+        revwalk.hide(from)?;
+    }
+
+    let mut range = CommitRange::default();
+    for oid in revwalk {
+        let oid = oid?;
+        // Walking is cheap; diffing every commit of a long history is not
+        if range.commits.len() >= max_commits {
+            range.omitted += 1;
+            continue;
+        }
+        let commit = repo.find_commit(oid)?;
+        let message = commit.message().unwrap_or("");
+        let (summary, body) = match message.split_once('\n') {
+            Some((summary, body)) => (summary.trim(), body.trim()),
+            None => (message.trim(), ""),
+        };
+
+        // Diff against the first parent (or the empty tree for the root commit)
+        let tree = commit.tree()?;
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let diff = repo.diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&tree),
+            Some(&mut DiffOptions::new()),
+        )?;
+        let stats = diff.stats()?;
+
+        range.commits.push(CommitInfo {
+            id: commit.id().to_string(),
+            summary: summary.to_string(),
+            body: body.to_string(),
+            author: commit.author().name().unwrap_or("unknown").to_string(),
+            time: commit.time().seconds(),
+            is_merge: commit.parent_count() > 1,
+            files_changed: stats.files_changed(),
+            insertions: stats.insertions(),
+            deletions: stats.deletions(),
+        });
+    }
+    Ok(range)
+}
+
+/// Name of the repository's default branch: the target of `origin/HEAD`, else a local `main` or `master`
+pub fn default_branch(repo: &Repository) -> Option<String> {
+    if let Ok(reference) = repo.find_reference("refs/remotes/origin/HEAD") {
+        if let Some(target) = reference.symbolic_target() {
+            if let Some(name) = target.strip_prefix("refs/remotes/") {
+                return Some(name.to_string());
+            }
+        }
+    }
+    ["main", "master"]
+        .into_iter()
+        .find(|name| repo.find_branch(name, git2::BranchType::Local).is_ok())
+        .map(String::from)
+}
+
+/// Commit subjects listed in a [`History`]
+pub const HISTORY_RECENT_COMMITS: usize = 15;
+/// Contributors listed in a [`History`]
+pub const HISTORY_TOP_CONTRIBUTORS: usize = 10;
+/// Largest [`History::prompt_block`], in bytes
+pub const HISTORY_BLOCK_MAX_BYTES: usize = 2048;
+/// Commits a [`History`] looks at, newest first; older ones are not counted
+pub const HISTORY_MAX_COMMITS: usize = 5000;
+/// Longer commit subjects are cut, so one cannot crowd the others out of the block
+const HISTORY_SUBJECT_MAX_CHARS: usize = 120;
+
+/// How active a project is and who works on it, for the README prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct History {
+    /// Commits reachable from HEAD, up to [`HISTORY_MAX_COMMITS`]
+    pub commit_count: usize,
+    /// Whether HEAD has more commits than were counted
+    pub capped: bool,
+    /// Time of the oldest commit counted in seconds since the Unix epoch
+    pub first_commit: i64,
+    /// Time of the newest commit in seconds since the Unix epoch
+    pub last_commit: i64,
+    /// Subjects of the most recent commits, newest first
+    pub recent_subjects: Vec<String>,
+    /// Author names and their commit counts, most commits first
+    pub contributors: Vec<(String, usize)>,
+    /// Whether the repository is a shallow clone, so older history is missing
+    pub shallow: bool,
+}
+
+impl History {
+    /// The history as a `Project history:` block for the prompt, at most
+    /// [`HISTORY_BLOCK_MAX_BYTES`] long. Lines that would pass the limit are left out.
+    pub fn prompt_block(&self) -> String {
+        let date = |seconds| {
+            chrono::DateTime::from_timestamp(seconds, 0)
+                .map(|time| time.format("%Y-%m-%d").to_string())
+                .unwrap_or_default()
+        };
+        let count = match self.capped {
+            true => format!("more than {} commits; the latest {}", self.commit_count, self.commit_count),
+            false => format!("{} commits", self.commit_count),
+        };
+        let mut lines = vec![format!(
+            "- {}{} from {} to {}",
+            count,
+            if self.shallow { " (shallow clone; older history is missing)" } else { "" },
+            date(self.first_commit),
+            date(self.last_commit)
+        )];
+        if !self.contributors.is_empty() {
+            let contributors: Vec<String> =
+                self.contributors.iter().map(|(name, count)| format!("{} ({})", name, count)).collect();
+            lines.push(format!("- Top contributors: {}", contributors.join(", ")));
+        }
+        if !self.recent_subjects.is_empty() {
+            lines.push("- Recent commits, newest first:".to_string());
+            lines.extend(self.recent_subjects.iter().map(|subject| format!("  - {}", subject)));
+        }
+
+        let mut block = String::from("\n\nProject history:\n");
+        for line in lines {
+            if block.len() + line.len() + 1 > HISTORY_BLOCK_MAX_BYTES {
+                break;
+            }
+            block.push_str(&line);
+            block.push('\n');
+        }
+        block
+    }
+}
+
+/// The history of the repository containing `dir`, or `None` when it is not a git
+/// repository or has no commits. A shallow clone gives the history it has, and a long
+/// one the latest [`HISTORY_MAX_COMMITS`] commits.
+pub fn history(dir: &Path) -> Option<History> {
+    history_within(dir, HISTORY_MAX_COMMITS)
+}
+
+fn history_within(dir: &Path, max_commits: usize) -> Option<History> {
+    let repo = Repository::discover(dir).ok()?;
+    let head = repo.head().ok()?.peel_to_commit().ok()?;
+    let mut revwalk = repo.revwalk().ok()?;
+    revwalk.set_sorting(Sort::TIME).ok()?;
+    revwalk.push(head.id()).ok()?;
+
+    let mut history = History {
+        commit_count: 0,
+        capped: false,
+        first_commit: head.time().seconds(),
+        last_commit: head.time().seconds(),
+        recent_subjects: Vec::new(),
+        contributors: Vec::new(),
+        shallow: repo.is_shallow(),
+    };
+    let mut authors: HashMap<String, usize> = HashMap::new();
+    for oid in revwalk {
+        if history.commit_count == max_commits {
+            history.capped = true;
+            break;
+        }
+        // Stop at whatever history is missing rather than failing
+        let Ok(commit) = oid.and_then(|oid| repo.find_commit(oid)) else {
+            break;
+        };
+        history.commit_count += 1;
+        history.first_commit = history.first_commit.min(commit.time().seconds());
+        history.last_commit = history.last_commit.max(commit.time().seconds());
+        if history.recent_subjects.len() < HISTORY_RECENT_COMMITS {
+            let subject = commit.summary().unwrap_or_default().trim();
+            history.recent_subjects.push(subject.chars().take(HISTORY_SUBJECT_MAX_CHARS).collect());
+        }
+        *authors.entry(commit.author().name().unwrap_or("unknown").to_string()).or_default() += 1;
+    }
+
+    let mut contributors: Vec<(String, usize)> = authors.into_iter().collect();
+    contributors.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    contributors.truncate(HISTORY_TOP_CONTRIBUTORS);
+    history.contributors = contributors;
+    Some(history)
+}
+
+/// The changes to a single file in a diff
+#[derive(Debug, Clone)]
+pub struct FilePatch {
+    /// Path of the file after the change (before, for deletions)
+    pub path: String,
+    /// Unified diff text for the file, empty for binary files
+    pub patch: String,
+    /// Whether git considers the file binary
+    pub binary: bool,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// Split a diff into per-file patches
+pub fn file_patches(diff: &Diff) -> Result<Vec<FilePatch>> {
+    let mut patches = Vec::new();
+    for index in 0..diff.deltas().len() {
+        let delta = diff.get_delta(index).expect("delta index in range");
+        let path = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+
+        // Binary detection needs the file content, which generating the patch loads
+        let mut patch = Patch::from_diff(diff, index)?;
+        let binary = delta.flags().is_binary()
+            || patch.as_ref().is_some_and(|p| p.delta().flags().is_binary());
+
+        let (patch, insertions, deletions) = match patch.as_mut() {
+            Some(patch) if !binary => {
+                let (_, insertions, deletions) = patch.line_stats()?;
+                let text = String::from_utf8_lossy(&patch.to_buf()?).into_owned();
+                (text, insertions, deletions)
+            }
+            _ => (String::new(), 0, 0),
+        };
+
+        patches.push(FilePatch {
+            path,
+            patch,
+            binary,
+            insertions,
+            deletions,
+        });
+    }
+    Ok(patches)
+}
+
+/// Diff the merge base of `base` and `head` against `head`, like a pull request would show it
+pub fn diff_against_base(
+    repo: &Repository,
+    base: Oid,
+    head: Oid,
+    context_lines: u32,
+) -> Result<Vec<FilePatch>> {
+    let merge_base = repo.merge_base(base, head)?;
+    let base_tree = repo.find_commit(merge_base)?.tree()?;
+    let head_tree = repo.find_commit(head)?.tree()?;
+
+    let mut options = DiffOptions::new();
+    options.context_lines(context_lines);
+    let diff = repo.diff_tree_to_tree(Some(&base_tree), Some(&head_tree), Some(&mut options))?;
+    file_patches(&diff)
+}
+
+/// Per-file changes staged in the index of `repo`, against HEAD or, before the first
+/// commit, against the empty tree
+pub fn staged_changes(repo: &Repository, context_lines: u32) -> Result<Vec<FilePatch>> {
+    let head_tree = match repo.head() {
+        Ok(head) => Some(head.peel_to_tree()?),
+        Err(e) if matches!(e.code(), git2::ErrorCode::UnbornBranch | git2::ErrorCode::NotFound) => None,
+        Err(e) => return Err(e.into()),
+    };
+    let mut options = DiffOptions::new();
+    options.context_lines(context_lines);
+    let diff = repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut options))?;
+    file_patches(&diff)
+}
+
+/// Per-file changes between two commits; with no `from`, against the empty tree
+pub fn diff_commits(repo: &Repository, from: Option<Oid>, to: Oid) -> Result<Vec<FilePatch>> {
+    let from_tree = match from {
+        Some(from) => Some(repo.find_commit(from)?.tree()?),
+        None => None,
+    };
+    let to_tree = repo.find_commit(to)?.tree()?;
+    let diff = repo.diff_tree_to_tree(from_tree.as_ref(), Some(&to_tree), None)?;
+    file_patches(&diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    /// A repository with one commit a day per message, each adding a file, and the ids
+    /// newest first
+    fn repository(messages: &[&str]) -> (FixtureTree, Repository, Vec<Oid>) {
+        let tree = FixtureTree::new();
+        let repo = Repository::init(tree.path()).unwrap();
+        let mut ids = Vec::new();
+        for (n, message) in messages.iter().enumerate() {
+            let time = git2::Time::new(1_700_000_000 + n as i64 * 86_400, 0);
+            let signature = git2::Signature::new("Test", "test@example.com", &time).unwrap();
+            std::fs::write(tree.join(format!("file{}.txt", n)), format!("line {}\n", n)).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+            index.write().unwrap();
+            let snapshot = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let parents: Vec<_> = ids.last().map(|id| repo.find_commit(*id).unwrap()).into_iter().collect();
+            let parents: Vec<_> = parents.iter().collect();
+            ids.push(repo.commit(Some("HEAD"), &signature, &signature, message, &snapshot, &parents).unwrap());
+        }
+        ids.reverse();
+        (tree, repo, ids)
+    }
+
+    #[test]
+    fn commits_are_collected_newest_first_with_stats() {
+        let (_tree, repo, ids) = repository(&["feat: one", "fix: two\n\nDetails"]);
+        let range = commits_between(&repo, None, ids[0], DEFAULT_MAX_COMMITS).unwrap();
+        assert_eq!(range.omitted, 0);
+        let summaries: Vec<_> = range.commits.iter().map(|c| c.summary.as_str()).collect();
+        assert_eq!(summaries, ["fix: two", "feat: one"]);
+        assert_eq!(range.commits[0].body, "Details");
+        assert_eq!((range.commits[0].files_changed, range.commits[0].insertions), (1, 1));
+    }
+
+    #[test]
+    fn commits_past_the_limit_are_counted_but_not_collected() {
+        let (_tree, repo, ids) = repository(&["one", "two", "three", "four", "five"]);
+        let range = commits_between(&repo, None, ids[0], 2).unwrap();
+        let summaries: Vec<_> = range.commits.iter().map(|c| c.summary.as_str()).collect();
+        assert_eq!(summaries, ["five", "four"]);
+        assert_eq!(range.omitted, 3);
+        assert_eq!(range.total(), 5);
+
+        let range = commits_between(&repo, None, ids[0], 0).unwrap();
+        assert!(range.commits.is_empty());
+        assert_eq!(range.omitted, 5);
+    }
+
+    #[test]
+    fn the_limit_applies_within_the_range() {
+        let (_tree, repo, ids) = repository(&["one", "two", "three", "four", "five"]);
+        let range = commits_between(&repo, Some(ids[3]), ids[0], 2).unwrap();
+        assert_eq!(range.commits.len(), 2);
+        assert_eq!(range.omitted, 1);
+    }
+
+    #[test]
+    fn repositories_without_commits_have_no_history() {
+        let tree = FixtureTree::new();
+        Repository::init(tree.path()).unwrap();
+        assert_eq!(history(tree.path()), None);
+    }
+
+    #[test]
+    fn history_counts_commits_and_authors() {
+        let (tree, _repo, _ids) = repository(&["feat: one", "fix: two", "docs: three"]);
+        let history = history(tree.path()).unwrap();
+        assert_eq!(history.commit_count, 3);
+        assert!(!history.capped && !history.shallow);
+        assert_eq!(history.recent_subjects, ["docs: three", "fix: two", "feat: one"]);
+        assert_eq!(history.contributors, [("Test".to_string(), 3)]);
+        assert_eq!((history.first_commit, history.last_commit), (1_700_000_000, 1_700_172_800));
+
+        let block = history.prompt_block();
+        assert!(block.contains("- 3 commits from 2023-11-14 to 2023-11-16\n"), "{}", block);
+        assert!(block.contains("- Top contributors: Test (3)\n"), "{}", block);
+        assert!(block.contains("  - docs: three\n"), "{}", block);
+    }
+
+    #[test]
+    fn history_stops_at_the_commit_bound() {
+        let (tree, _repo, _ids) = repository(&["one", "two", "three", "four", "five"]);
+        let history = history_within(tree.path(), 3).unwrap();
+        assert_eq!(history.commit_count, 3);
+        assert!(history.capped);
+        assert_eq!(history.recent_subjects, ["five", "four", "three"]);
+        assert_eq!(history.contributors, [("Test".to_string(), 3)]);
+        let block = history.prompt_block();
+        assert!(block.contains("- more than 3 commits; the latest 3 from 2023-11-16 to 2023-11-18\n"), "{}", block);
+
+        // A history exactly as long as the bound is complete
+        let history = history_within(tree.path(), 5).unwrap();
+        assert_eq!(history.commit_count, 5);
+        assert!(!history.capped);
+    }
+}
@@ -0,0 +1,685 @@
+use std::env;
+use std::time::Duration;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::provider::Usage;
+use crate::{Result, TechDocsError};
+
+/// GitHub REST API base URL, overridable with `GITHUB_API_URL`
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+/// File the pull request replaces
+const README_PATH: &str = "README.md";
+/// How long [`fetch_remote_metadata`] waits for GitHub before going on without it
+pub const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A repository on GitHub, as `owner/name`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repository {
+    pub owner: String,
+    pub name: String,
+}
+
+impl Repository {
+    /// The repository a `https://github.com/owner/name` URL points at
+    pub fn from_url(url: &str) -> Result<Self> {
+        let not_github = || TechDocsError::Url(format!("{} is not a GitHub repository URL", url));
+        let parsed = Url::parse(url).map_err(|_| not_github())?;
+        let mut segments = parsed.path_segments().into_iter().flatten().filter(|s| !s.is_empty());
+        match (parsed.host_str(), segments.next(), segments.next()) {
+            (Some("github.com"), Some(owner), Some(name)) => Ok(Self {
+                owner: owner.to_string(),
+                name: name.trim_end_matches(".git").to_string(),
+            }),
+            _ => Err(not_github()),
+        }
+    }
+}
+
+impl std::fmt::Display for Repository {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
+
+/// What came of asking for a README pull request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PullRequestOutcome {
+    /// A pull request was opened
+    Opened {
+        #[cfg_attr(feature = "server", schema(example = "https://github.com/username/repo/pull/42"))]
+        url: String,
+        number: u64,
+        /// Branch holding the new README
+        branch: String,
+    },
+    /// The default branch already has this README, so nothing was opened
+    Unchanged,
+}
+
+/// What GitHub says about a repository: authoritative facts for a README's introduction
+/// that the files may not state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct RemoteMetadata {
+    /// `owner/name`
+    #[cfg_attr(feature = "server", schema(example = "username/repo"))]
+    pub repository: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub topics: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    /// SPDX id of the license GitHub detected, when it recognized one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    pub default_branch: String,
+    pub stars: u64,
+}
+
+impl RemoteMetadata {
+    /// Lines for the prompt's metadata block. The license is only stated when `license`
+    /// is set, so a license detected in the files is not contradicted.
+    pub fn prompt_facts(&self, license: bool) -> String {
+        let mut facts = format!(
+            "GitHub repository: {} ({} stars, default branch {}); the facts below come from the GitHub API, \
+             so prefer them to guesses\n",
+            self.repository, self.stars, self.default_branch
+        );
+        if let Some(description) = &self.description {
+            facts.push_str(&format!("Description: {}\n", description));
+        }
+        if !self.topics.is_empty() {
+            facts.push_str(&format!("Topics: {}\n", self.topics.join(", ")));
+        }
+        if let Some(homepage) = &self.homepage {
+            facts.push_str(&format!("Homepage: {}\n", homepage));
+        }
+        if let (true, Some(spdx)) = (license, &self.license) {
+            facts.push_str(&format!(
+                "License: {} (SPDX, determined by GitHub). Use exactly this license; do not infer another.\n",
+                spdx
+            ));
+        }
+        facts
+    }
+}
+
+/// Title and description of a README pull request
+#[derive(Debug, Clone)]
+pub struct PullRequestText {
+    pub title: String,
+    pub body: String,
+    /// Message of the commit that changes the README
+    pub commit_message: String,
+}
+
+impl PullRequestText {
+    /// The text for a README generated by `model` with `usage`
+    pub fn for_readme(model: &str, usage: &Usage) -> Self {
+        let version = env!("CARGO_PKG_VERSION");
+        Self {
+            title: "Update README".to_string(),
+            body: format!(
+                "This README was generated by techdocs {} with {}.\n\n\
+                 Generation used {} input and {} output tokens. Review it before merging: \
+                 generated documentation can be wrong.",
+                version, model, usage.input_tokens, usage.output_tokens
+            ),
+            commit_message: format!("Update README (generated by techdocs {})", version),
+        }
+    }
+}
+
+/// Client for the parts of the GitHub REST API needed to propose a README and to read a
+/// repository's metadata
+#[derive(Clone)]
+pub struct GitHubClient {
+    http: reqwest::Client,
+    /// `None` for anonymous requests, which GitHub rate-limits more tightly
+    token: Option<String>,
+    api_url: String,
+}
+
+impl std::fmt::Debug for GitHubClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the token
+        f.debug_struct("GitHubClient")
+            .field("api_url", &self.api_url)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize)]
+struct RepositoryInfo {
+    default_branch: String,
+    /// Only present when the request is authenticated
+    permissions: Option<Permissions>,
+}
+
+#[derive(Deserialize)]
+struct RepositoryDetails {
+    full_name: String,
+    description: Option<String>,
+    #[serde(default)]
+    topics: Vec<String>,
+    homepage: Option<String>,
+    license: Option<LicenseDetails>,
+    default_branch: String,
+    stargazers_count: u64,
+}
+
+#[derive(Deserialize)]
+struct LicenseDetails {
+    /// `NOASSERTION` when GitHub found a license it does not recognize
+    spdx_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Permissions {
+    push: bool,
+}
+
+#[derive(Deserialize)]
+struct FileContents {
+    sha: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct GitRef {
+    object: GitObject,
+}
+
+#[derive(Deserialize)]
+struct GitObject {
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct PullRequest {
+    html_url: String,
+    number: u64,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+impl GitHubClient {
+    /// Create a client from `TECHDOCS_GITHUB_TOKEN`, or `GITHUB_TOKEN`, and the optional
+    /// `GITHUB_API_URL`
+    pub fn new() -> Result<Self> {
+        let token = env::var("TECHDOCS_GITHUB_TOKEN")
+            .or_else(|_| env::var("GITHUB_TOKEN"))
+            .map_err(|_| {
+                TechDocsError::Config("opening a pull request needs TECHDOCS_GITHUB_TOKEN or GITHUB_TOKEN".into())
+            })?;
+        let api_url = env::var("GITHUB_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.into());
+        Ok(Self::with_api_url(token, api_url))
+    }
+
+    /// A client for reading public repositories: authenticated like [`GitHubClient::new`]
+    /// when a token is set, anonymous otherwise
+    pub fn for_reading() -> Self {
+        let token = env::var("TECHDOCS_GITHUB_TOKEN").or_else(|_| env::var("GITHUB_TOKEN")).ok();
+        let api_url = env::var("GITHUB_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.into());
+        Self {
+            token,
+            ..Self::with_api_url(String::new(), api_url)
+        }
+    }
+
+    /// Create a client with an explicit token and API URL
+    pub fn with_api_url(token: impl Into<String>, api_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            token: Some(token.into()),
+            api_url: api_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Propose `readme` as the README of `repo`: commit it to a new branch off the
+    /// default branch and open a pull request. Nothing is created when the default
+    /// branch already has the same README.
+    pub async fn open_readme_pull_request(
+        &self,
+        repo: &Repository,
+        readme: &str,
+        text: &PullRequestText,
+    ) -> Result<PullRequestOutcome> {
+        let info: RepositoryInfo = self.request(Method::GET, &format!("/repos/{}", repo), None).await?;
+        if info.permissions.is_some_and(|permissions| !permissions.push) {
+            return Err(TechDocsError::GitHub(format!(
+                "the GitHub token cannot push to {}; it needs write access to contents and pull requests",
+                repo
+            )));
+        }
+        let base = info.default_branch;
+
+        let existing = self.readme(repo, &base).await?;
+        if let Some(existing) = &existing {
+            if decode_contents(&existing.content)?.trim_end() == readme.trim_end() {
+                tracing::info!("{} already has this README; not opening a pull request", repo);
+                return Ok(PullRequestOutcome::Unchanged);
+            }
+        }
+
+        let head: GitRef = self
+            .request(Method::GET, &format!("/repos/{}/git/ref/heads/{}", repo, base), None)
+            .await?;
+        let branch = format!("techdocs/readme-{}", chrono::Utc::now().format("%Y%m%d%H%M%S"));
+        let _: serde_json::Value = self
+            .request(
+                Method::POST,
+                &format!("/repos/{}/git/refs", repo),
+                Some(serde_json::json!({ "ref": format!("refs/heads/{}", branch), "sha": head.object.sha })),
+            )
+            .await?;
+
+        let mut commit = serde_json::json!({
+            "message": text.commit_message,
+            "content": BASE64.encode(readme),
+            "branch": branch,
+        });
+        if let Some(existing) = &existing {
+            commit["sha"] = existing.sha.clone().into();
+        }
+        let _: serde_json::Value = self
+            .request(Method::PUT, &format!("/repos/{}/contents/{}", repo, README_PATH), Some(commit))
+            .await?;
+
+        let pull: PullRequest = self
+            .request(
+                Method::POST,
+                &format!("/repos/{}/pulls", repo),
+                Some(serde_json::json!({ "title": text.title, "body": text.body, "head": branch, "base": base })),
+            )
+            .await?;
+        tracing::info!("Opened {}", pull.html_url);
+        Ok(PullRequestOutcome::Opened {
+            url: pull.html_url,
+            number: pull.number,
+            branch,
+        })
+    }
+
+    /// What GitHub says about `repo`
+    pub async fn repository_metadata(&self, repo: &Repository) -> Result<RemoteMetadata> {
+        let details: RepositoryDetails = self.request(Method::GET, &format!("/repos/{}", repo), None).await?;
+        let non_empty = |text: Option<String>| text.map(|text| text.trim().to_string()).filter(|text| !text.is_empty());
+        Ok(RemoteMetadata {
+            repository: details.full_name,
+            description: non_empty(details.description),
+            topics: details.topics,
+            homepage: non_empty(details.homepage),
+            license: details.license.and_then(|license| license.spdx_id).filter(|spdx| spdx != "NOASSERTION"),
+            default_branch: details.default_branch,
+            stars: details.stargazers_count,
+        })
+    }
+
+    /// The README on `branch`, or `None` when there is none
+    async fn readme(&self, repo: &Repository, branch: &str) -> Result<Option<FileContents>> {
+        let path = format!("/repos/{}/contents/{}?ref={}", repo, README_PATH, branch);
+        match self.request(Method::GET, &path, None).await {
+            Ok(contents) => Ok(Some(contents)),
+            Err(TechDocsError::GitHub(message)) if message.starts_with(NOT_FOUND) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Send a request and parse the successful response, describing failures in terms
+    /// of what the token may be missing
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<T> {
+        let mut request = self
+            .http
+            .request(method, format!("{}{}", self.api_url, path))
+            .header("accept", "application/vnd.github+json")
+            .header("user-agent", concat!("techdocs/", env!("CARGO_PKG_VERSION")));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| TechDocsError::GitHub(format!("request failed: {}", e)))?;
+
+        let status = response.status();
+        let rate_limited = response
+            .headers()
+            .get("x-ratelimit-remaining")
+            .is_some_and(|remaining| remaining.as_bytes() == b"0");
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| TechDocsError::GitHub(format!("reading the response failed: {}", e)))?;
+        if !status.is_success() {
+            let message = serde_json::from_slice::<ErrorBody>(&bytes)
+                .map(|error| error.message)
+                .unwrap_or_else(|_| String::from_utf8_lossy(&bytes).into_owned());
+            return Err(TechDocsError::GitHub(match status {
+                StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS if rate_limited => format!(
+                    "GitHub rate limit reached: {}; set TECHDOCS_GITHUB_TOKEN or GITHUB_TOKEN for a higher limit",
+                    message
+                ),
+                StatusCode::UNAUTHORIZED => format!("GitHub rejected the token: {}", message),
+                StatusCode::FORBIDDEN => format!(
+                    "the GitHub token is not allowed to {} {}: {}; it needs write access to contents and pull requests",
+                    path_action(path),
+                    path,
+                    message
+                ),
+                StatusCode::NOT_FOUND => format!("{}: {} ({})", NOT_FOUND, path, message),
+                _ => format!("{} from {}: {}", status, path, message),
+            }));
+        }
+        serde_json::from_slice(&bytes).map_err(|e| TechDocsError::GitHub(format!("invalid response from {}: {}", path, e)))
+    }
+}
+
+/// What GitHub says about `source` when it is a GitHub repository URL. Rate limits,
+/// network failures, and a [`METADATA_TIMEOUT`] without an answer are logged as
+/// warnings and give `None`, so generation goes on without the metadata.
+pub async fn fetch_remote_metadata(source: &str) -> Option<RemoteMetadata> {
+    let repo = Repository::from_url(source).ok()?;
+    let client = GitHubClient::for_reading();
+    match tokio::time::timeout(METADATA_TIMEOUT, client.repository_metadata(&repo)).await {
+        Ok(Ok(metadata)) => Some(metadata),
+        Ok(Err(e)) => {
+            tracing::warn!("Going on without GitHub metadata for {}: {}", repo, e);
+            None
+        }
+        Err(_) => {
+            tracing::warn!(
+                "Going on without GitHub metadata for {}: no answer within {} seconds",
+                repo,
+                METADATA_TIMEOUT.as_secs()
+            );
+            None
+        }
+    }
+}
+
+/// Start of the error message for a missing resource. GitHub also answers 404 for
+/// private repositories the token cannot see.
+const NOT_FOUND: &str = "not found or not visible to the token";
+
+/// What a request to `path` does, for error messages
+fn path_action(path: &str) -> &'static str {
+    if path.ends_with("/pulls") {
+        "open pull requests on"
+    } else if path.contains("/git/refs") {
+        "create branches on"
+    } else if path.contains("/contents/") {
+        "write"
+    } else {
+        "read"
+    }
+}
+
+/// Decode file contents, which GitHub sends as base64 split over lines
+fn decode_contents(content: &str) -> Result<String> {
+    let compact: String = content.split_whitespace().collect();
+    let bytes = BASE64
+        .decode(compact)
+        .map_err(|e| TechDocsError::GitHub(format!("invalid README contents: {}", e)))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// A local GitHub API giving `responses`, as (status line, extra headers, body), to
+    /// successive requests, its URL, and the request line and body of each request
+    async fn api(responses: Vec<(&'static str, &'static str, String)>) -> (String, mpsc::UnboundedReceiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        let (sent, received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for (status, headers, body) in responses {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut head = String::new();
+                while reader.read_line(&mut head).await.unwrap() > 0 && !head.ends_with("\r\n\r\n") {}
+                let length = head
+                    .lines()
+                    .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|n| n.trim().parse().unwrap()))
+                    .unwrap_or(0);
+                let mut request_body = vec![0; length];
+                reader.read_exact(&mut request_body).await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\n{}content-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    headers,
+                    body.len(),
+                    body
+                );
+                reader.into_inner().write_all(response.as_bytes()).await.unwrap();
+                let request_line = head.lines().next().unwrap_or_default().to_string();
+                let _ = sent.send((request_line, String::from_utf8(request_body).unwrap()));
+            }
+        });
+        (api_url, received)
+    }
+
+    fn repo() -> Repository {
+        Repository::from_url("https://github.com/username/repo").unwrap()
+    }
+
+    fn github_error(result: Result<impl std::fmt::Debug>) -> String {
+        match result.unwrap_err() {
+            TechDocsError::GitHub(message) => message,
+            other => panic!("expected a GitHub error, got {}", other),
+        }
+    }
+
+    #[test]
+    fn repository_urls_are_parsed() {
+        for url in [
+            "https://github.com/username/repo",
+            "https://github.com/username/repo.git",
+            "https://github.com/username/repo/",
+            "https://github.com/username/repo/tree/main/src",
+        ] {
+            assert_eq!(
+                Repository::from_url(url).unwrap(),
+                Repository { owner: "username".into(), name: "repo".into() },
+                "{}",
+                url
+            );
+        }
+        assert_eq!(repo().to_string(), "username/repo");
+
+        for url in ["https://gitlab.com/username/repo", "https://github.com/username", "github.com/username/repo", "/tmp/repo"] {
+            let error = Repository::from_url(url).unwrap_err();
+            assert!(
+                matches!(&error, TechDocsError::Url(message) if message == &format!("{} is not a GitHub repository URL", url)),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn metadata_is_read_from_the_repository() {
+        let details = serde_json::json!({
+            "full_name": "username/repo",
+            "description": "  A tool for docs  ",
+            "topics": ["docs", "rust"],
+            "homepage": "",
+            "license": {"spdx_id": "MIT"},
+            "default_branch": "main",
+            "stargazers_count": 42,
+        });
+        let (api_url, mut requests) = api(vec![("200 OK", "", details.to_string())]).await;
+        let metadata = GitHubClient::with_api_url("token", format!("{}/", api_url))
+            .repository_metadata(&repo())
+            .await
+            .unwrap();
+        assert_eq!(
+            metadata,
+            RemoteMetadata {
+                repository: "username/repo".into(),
+                description: Some("A tool for docs".into()),
+                topics: vec!["docs".into(), "rust".into()],
+                homepage: None,
+                license: Some("MIT".into()),
+                default_branch: "main".into(),
+                stars: 42,
+            }
+        );
+        assert_eq!(requests.recv().await.unwrap().0, "GET /repos/username/repo HTTP/1.1");
+
+        assert_eq!(
+            metadata.prompt_facts(true),
+            "GitHub repository: username/repo (42 stars, default branch main); the facts below come from the GitHub API, \
+             so prefer them to guesses\nDescription: A tool for docs\nTopics: docs, rust\n\
+             License: MIT (SPDX, determined by GitHub). Use exactly this license; do not infer another.\n"
+        );
+        assert!(!metadata.prompt_facts(false).contains("License:"));
+    }
+
+    #[tokio::test]
+    async fn unrecognized_licenses_are_left_out() {
+        let details = serde_json::json!({
+            "full_name": "username/repo",
+            "description": null,
+            "homepage": null,
+            "license": {"spdx_id": "NOASSERTION"},
+            "default_branch": "trunk",
+            "stargazers_count": 0,
+        });
+        let (api_url, _requests) = api(vec![("200 OK", "", details.to_string())]).await;
+        let metadata = GitHubClient::with_api_url("token", api_url).repository_metadata(&repo()).await.unwrap();
+        assert_eq!((metadata.license, metadata.topics.len(), metadata.description), (None, 0, None));
+    }
+
+    #[tokio::test]
+    async fn failures_are_explained() {
+        let message = |text: &str| serde_json::json!({"message": text}).to_string();
+        let (api_url, _requests) = api(vec![
+            ("403 Forbidden", "x-ratelimit-remaining: 0\r\n", message("API rate limit exceeded")),
+            ("401 Unauthorized", "", message("Bad credentials")),
+            ("404 Not Found", "", message("Not Found")),
+            ("200 OK", "", "not json".to_string()),
+        ])
+        .await;
+        let client = GitHubClient::with_api_url("token", api_url);
+
+        let error = github_error(client.repository_metadata(&repo()).await);
+        assert!(error.starts_with("GitHub rate limit reached: API rate limit exceeded; set TECHDOCS_GITHUB_TOKEN"), "{}", error);
+        let error = github_error(client.repository_metadata(&repo()).await);
+        assert_eq!(error, "GitHub rejected the token: Bad credentials");
+        let error = github_error(client.repository_metadata(&repo()).await);
+        assert_eq!(error, "not found or not visible to the token: /repos/username/repo (Not Found)");
+        let error = github_error(client.repository_metadata(&repo()).await);
+        assert!(error.starts_with("invalid response from /repos/username/repo: "), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn a_pull_request_is_opened_for_a_changed_readme() {
+        let (api_url, mut requests) = api(vec![
+            ("200 OK", "", serde_json::json!({"default_branch": "main", "permissions": {"push": true}}).to_string()),
+            ("200 OK", "", serde_json::json!({"sha": "old-sha", "content": "IyBPbGQK\n"}).to_string()),
+            ("200 OK", "", serde_json::json!({"object": {"sha": "head-sha"}}).to_string()),
+            ("201 Created", "", "{}".to_string()),
+            ("200 OK", "", "{}".to_string()),
+            ("201 Created", "", serde_json::json!({"html_url": "https://github.com/username/repo/pull/7", "number": 7}).to_string()),
+        ])
+        .await;
+        let text = PullRequestText::for_readme("test-model", &Usage::default());
+        let outcome = GitHubClient::with_api_url("token", api_url)
+            .open_readme_pull_request(&repo(), "# New\n", &text)
+            .await
+            .unwrap();
+        let PullRequestOutcome::Opened { url, number, branch } = outcome else {
+            panic!("expected a pull request");
+        };
+        assert_eq!((url.as_str(), number), ("https://github.com/username/repo/pull/7", 7));
+        assert!(branch.starts_with("techdocs/readme-"), "{}", branch);
+
+        let mut lines = Vec::new();
+        let mut bodies = Vec::new();
+        for _ in 0..6 {
+            let (line, body) = requests.recv().await.unwrap();
+            lines.push(line);
+            bodies.push(body);
+        }
+        assert_eq!(
+            lines,
+            [
+                "GET /repos/username/repo HTTP/1.1",
+                "GET /repos/username/repo/contents/README.md?ref=main HTTP/1.1",
+                "GET /repos/username/repo/git/ref/heads/main HTTP/1.1",
+                "POST /repos/username/repo/git/refs HTTP/1.1",
+                "PUT /repos/username/repo/contents/README.md HTTP/1.1",
+                "POST /repos/username/repo/pulls HTTP/1.1",
+            ]
+        );
+        let commit: serde_json::Value = serde_json::from_str(&bodies[4]).unwrap();
+        assert_eq!(commit["sha"], "old-sha");
+        assert_eq!(commit["branch"], branch.as_str());
+        assert_eq!(decode_contents(commit["content"].as_str().unwrap()).unwrap(), "# New\n");
+        let pull: serde_json::Value = serde_json::from_str(&bodies[5]).unwrap();
+        assert_eq!((pull["head"].as_str(), pull["base"].as_str()), (Some(branch.as_str()), Some("main")));
+    }
+
+    #[tokio::test]
+    async fn an_identical_readme_opens_nothing() {
+        let (api_url, _requests) = api(vec![
+            ("200 OK", "", serde_json::json!({"default_branch": "main"}).to_string()),
+            ("200 OK", "", serde_json::json!({"sha": "old-sha", "content": "IyBO\nZXcK"}).to_string()),
+        ])
+        .await;
+        let text = PullRequestText::for_readme("test-model", &Usage::default());
+        let outcome = GitHubClient::with_api_url("token", api_url)
+            .open_readme_pull_request(&repo(), "# New", &text)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, PullRequestOutcome::Unchanged), "{:?}", outcome);
+    }
+
+    #[tokio::test]
+    async fn a_token_without_push_access_is_refused() {
+        let (api_url, _requests) = api(vec![(
+            "200 OK",
+            "",
+            serde_json::json!({"default_branch": "main", "permissions": {"push": false}}).to_string(),
+        )])
+        .await;
+        let text = PullRequestText::for_readme("test-model", &Usage::default());
+        let error = github_error(
+            GitHubClient::with_api_url("token", api_url)
+                .open_readme_pull_request(&repo(), "# New", &text)
+                .await,
+        );
+        assert!(error.starts_with("the GitHub token cannot push to username/repo"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn only_github_sources_have_metadata() {
+        assert_eq!(fetch_remote_metadata("/tmp/project").await, None);
+        assert_eq!(fetch_remote_metadata("https://gitlab.com/username/repo").await, None);
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::generate::ReadmePrompt;
+use crate::plan::PromptPlan;
+use crate::{write_atomic, FileContext, Result, TechDocsError};
+
+/// Version of the [`ReadmeState`] format. A state file with any other version is
+/// ignored, so the next run regenerates from scratch.
+pub const STATE_VERSION: u32 = 1;
+
+/// Where the state is kept, relative to the directory the README is written to
+pub const STATE_FILE: &str = ".techdocs/state.json";
+
+/// Largest share of the project's files that may change for a README to be updated
+/// from the previous one rather than regenerated
+pub const UPDATE_MAX_CHANGED_FRACTION: f64 = 0.25;
+
+/// What the last incremental run generated from, kept so the next run can tell which
+/// files changed since
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadmeState {
+    /// Format version, [`STATE_VERSION`] when written by this build
+    pub version: u32,
+    /// Hash of the system prompt and model; a change to either means a full run
+    pub settings: String,
+    /// SHA-256 of each included file, by its path relative to the project root
+    pub files: BTreeMap<String, String>,
+    /// The README that was generated
+    pub readme: String,
+}
+
+/// Files that differ between a [`ReadmeState`] and the project as it is now, each
+/// list sorted by path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileChanges {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl FileChanges {
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.added.len() + self.changed.len() + self.removed.len()
+    }
+}
+
+impl ReadmeState {
+    pub fn new(settings: String, files: BTreeMap<String, String>, readme: String) -> Self {
+        Self { version: STATE_VERSION, settings, files, readme }
+    }
+
+    /// The state saved at `path`. A missing file, one that cannot be parsed, or one
+    /// from another format version all give `None`, logging why for the last two.
+    pub fn load(path: &Path) -> Option<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return None,
+            Err(e) => {
+                tracing::warn!("Ignoring {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        match serde_json::from_str::<Self>(&content) {
+            Ok(state) if state.version == STATE_VERSION => Some(state),
+            Ok(state) => {
+                tracing::warn!("Ignoring {}: state version {} is not {}", path.display(), state.version, STATE_VERSION);
+                None
+            }
+            Err(e) => {
+                tracing::warn!("Ignoring {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Write the state to `path`, creating its directory
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).file_context("create", parent)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| TechDocsError::Output(e.to_string()))?;
+        write_atomic(path, &json)
+    }
+
+    /// Which of `files`, as returned by [`file_hashes`], were added, changed, or
+    /// removed since this state was saved
+    pub fn changes(&self, files: &BTreeMap<String, String>) -> FileChanges {
+        let mut changes = FileChanges::default();
+        for (path, hash) in files {
+            match self.files.get(path) {
+                None => changes.added.push(path.clone()),
+                Some(previous) if previous != hash => changes.changed.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        changes.removed = self.files.keys().filter(|path| !files.contains_key(*path)).cloned().collect();
+        changes
+    }
+
+    /// Whether `changes` are few enough to update the README rather than regenerate it
+    pub fn can_update(&self, changes: &FileChanges) -> bool {
+        let total = self.files.len().max(1) as f64;
+        (changes.len() as f64 / total) <= UPDATE_MAX_CHANGED_FRACTION
+    }
+}
+
+/// Path of the state file for READMEs written to `dir`
+pub fn state_path(dir: &Path) -> PathBuf {
+    dir.join(STATE_FILE)
+}
+
+/// SHA-256 of each file the plan includes, keyed by its path relative to the plan's root.
+/// Files in `outputs`, such as the README being regenerated and its backup, are left
+/// out so that writing them does not count as a change.
+pub fn file_hashes(plan: &PromptPlan, outputs: &[PathBuf]) -> Result<BTreeMap<String, String>> {
+    let root = fs::canonicalize(&plan.root).unwrap_or_else(|_| plan.root.clone());
+    let outputs: Vec<PathBuf> =
+        outputs.iter().map(|path| fs::canonicalize(path).unwrap_or_else(|_| path.clone())).collect();
+    let mut hashes = BTreeMap::new();
+    for file in plan.included() {
+        let relative = relative_path(&plan.root, &file.path);
+        if outputs.contains(&root.join(&relative)) {
+            continue;
+        }
+        let content = fs::read(&file.path).file_context("read", &file.path)?;
+        hashes.insert(relative, hex(&Sha256::digest(&content)));
+    }
+    Ok(hashes)
+}
+
+/// Hash of what shapes a README besides the files: the system prompt and the model
+pub fn settings_hash(prompt: &ReadmePrompt) -> String {
+    let mut hasher = Sha256::new();
+    for part in [prompt.system.as_str(), prompt.model.as_deref().unwrap_or_default()] {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hex(&hasher.finalize())
+}
+
+/// `prompt` turned into a request to revise `previous` for `changes`, sending only the
+/// added and changed files of `plan` instead of the whole project
+pub fn update_prompt(
+    prompt: &ReadmePrompt,
+    plan: &PromptPlan,
+    previous: &str,
+    changes: &FileChanges,
+) -> Result<ReadmePrompt> {
+    let touched = PromptPlan {
+        files: plan
+            .included()
+            .filter(|file| {
+                let path = relative_path(&plan.root, &file.path);
+                changes.added.contains(&path) || changes.changed.contains(&path)
+            })
+            .cloned()
+            .collect(),
+        budget_reached: false,
+        ..plan.clone()
+    };
+    let mut content = Vec::new();
+    touched.write_prompt(&mut content)?;
+
+    let mut update = prompt.clone();
+    update.system.push_str(&update_instructions(changes));
+    update.content = format!(
+        "Current README:\n\n{}\n\nAdded and changed files:\n\n{}",
+        previous,
+        String::from_utf8_lossy(&content)
+    );
+    Ok(update)
+}
+
+fn update_instructions(changes: &FileChanges) -> String {
+    let mut out = String::from(
+        "\n\nA README was already written for this project. You are given that README and only \
+         the files added or changed since. Update the README so it is accurate for the project \
+         as it is now, keeping every part that is still correct as it is, and respond with the \
+         complete updated README.",
+    );
+    if !changes.removed.is_empty() {
+        out.push_str("\n\nThese files were removed; drop anything that describes only them:\n");
+        for path in &changes.removed {
+            let _ = writeln!(out, "- {}", path);
+        }
+    }
+    out
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative.components().map(|component| component.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::GenerateOptions;
+    use crate::plan::PromptOptions;
+    use crate::testing::FixtureTree;
+
+    fn project() -> FixtureTree {
+        FixtureTree::new()
+            .file("README.md", "# Old\n")
+            .file("src/main.rs", "fn main() {}\n")
+            .file("src/lib.rs", "pub fn lib() {}\n")
+            .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+    }
+
+    fn hashes(tree: &FixtureTree) -> BTreeMap<String, String> {
+        let plan = PromptPlan::collect(tree.path(), &PromptOptions::default()).unwrap();
+        file_hashes(&plan, &[tree.join("README.md")]).unwrap()
+    }
+
+    fn prompt(tree: &FixtureTree, model: Option<&str>) -> (PromptPlan, ReadmePrompt) {
+        let plan = PromptPlan::collect(tree.path(), &PromptOptions::default()).unwrap();
+        let options = GenerateOptions { model: model.map(String::from), git_context: false, ..Default::default() };
+        let prompt = ReadmePrompt::from_plan("demo", &plan, &options).unwrap();
+        (plan, prompt)
+    }
+
+    #[test]
+    fn only_what_changed_is_reported() {
+        let tree = project();
+        let before = hashes(&tree);
+        assert_eq!(before.keys().collect::<Vec<_>>(), ["Cargo.toml", "src/lib.rs", "src/main.rs"]);
+        let state = ReadmeState::new("settings".into(), before.clone(), "# Old\n".into());
+        assert!(state.changes(&before).is_empty());
+
+        // Rewriting the README being regenerated is not a change
+        std::fs::write(tree.join("README.md"), "# New\n").unwrap();
+        std::fs::write(tree.join("src/main.rs"), "fn main() { run() }\n").unwrap();
+        std::fs::remove_file(tree.join("src/lib.rs")).unwrap();
+        std::fs::write(tree.join("src/run.rs"), "pub fn run() {}\n").unwrap();
+        let changes = state.changes(&hashes(&tree));
+        assert_eq!(
+            changes,
+            FileChanges { added: vec!["src/run.rs".into()], changed: vec!["src/main.rs".into()], removed: vec!["src/lib.rs".into()] }
+        );
+        assert_eq!(changes.len(), 3);
+    }
+
+    #[test]
+    fn many_changes_mean_a_full_run() {
+        let files: BTreeMap<String, String> = (0..8).map(|index| (format!("f{}.rs", index), "hash".to_string())).collect();
+        let state = ReadmeState::new("settings".into(), files, String::new());
+        let changes = |count: usize| FileChanges { changed: (0..count).map(|index| format!("f{}.rs", index)).collect(), ..Default::default() };
+        assert!(state.can_update(&changes(2)));
+        assert!(!state.can_update(&changes(3)));
+    }
+
+    #[test]
+    fn a_new_prompt_or_model_invalidates_the_state() {
+        let tree = project();
+        let (_, default) = prompt(&tree, None);
+        let (_, other_model) = prompt(&tree, Some("claude-b"));
+        let mut other_system = default.clone();
+        other_system.system.push_str("\nUse British spelling.");
+        assert_eq!(settings_hash(&default), settings_hash(&prompt(&tree, None).1));
+        assert_ne!(settings_hash(&default), settings_hash(&other_model));
+        assert_ne!(settings_hash(&default), settings_hash(&other_system));
+    }
+
+    #[test]
+    fn saved_states_load_unless_missing_corrupt_or_from_another_version() {
+        let dir = FixtureTree::new();
+        let path = state_path(dir.path());
+        assert_eq!(ReadmeState::load(&path), None);
+
+        let state = ReadmeState::new("settings".into(), BTreeMap::from([("a.rs".into(), "hash".into())]), "# Demo\n".into());
+        state.save(&path).unwrap();
+        assert_eq!(ReadmeState::load(&path), Some(state.clone()));
+
+        std::fs::write(&path, "{\"version\": 1, \"settings\": ").unwrap();
+        assert_eq!(ReadmeState::load(&path), None);
+        let future = ReadmeState { version: STATE_VERSION + 1, ..state };
+        std::fs::write(&path, serde_json::to_string(&future).unwrap()).unwrap();
+        assert_eq!(ReadmeState::load(&path), None);
+    }
+
+    #[test]
+    fn an_update_sends_only_the_touched_files() {
+        let tree = project();
+        let (plan, prompt) = prompt(&tree, None);
+        let changes = FileChanges { changed: vec!["src/main.rs".into()], removed: vec!["src/old.rs".into()], ..Default::default() };
+        let update = update_prompt(&prompt, &plan, "# Old\n", &changes).unwrap();
+        assert!(update.content.starts_with("Current README:\n\n# Old\n"), "{}", update.content);
+        assert!(update.content.contains("fn main() {}"));
+        assert!(!update.content.contains("pub fn lib()"));
+        assert!(update.system.starts_with(&prompt.system));
+        assert!(update.system.ends_with("drop anything that describes only them:\n- src/old.rs\n"), "{}", update.system);
+    }
+}
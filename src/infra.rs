@@ -0,0 +1,627 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use ignore::WalkBuilder;
+
+/// Largest CI, container, or manifest file read for the summary
+const MAX_INFRA_FILE_BYTES: u64 = 256 * 1024;
+/// Most files read while looking for Kubernetes manifests, which may be any YAML file
+const MAX_INFRA_FILES: usize = 200;
+/// Deepest directory searched below the root
+const MAX_INFRA_DEPTH: usize = 5;
+/// Longest summary [`InfraSummary::prompt_block`] produces, in bytes
+pub const INFRA_MAX_BYTES: usize = 4 * 1024;
+/// Top-level keys of .gitlab-ci.yml that configure the pipeline rather than name a job
+const GITLAB_KEYWORDS: &[&str] = &[
+    "after_script", "before_script", "cache", "default", "image", "include", "pages", "services", "stages",
+    "variables", "workflow",
+];
+
+/// The CI system a pipeline runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CiSystem {
+    GitHubActions,
+    GitLab,
+    Jenkins,
+}
+
+/// What a CI pipeline is called, what starts it, and what it runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipeline {
+    /// Path of the pipeline file relative to the repository root
+    pub file: String,
+    pub system: CiSystem,
+    /// The workflow's `name`, when it has one
+    pub name: Option<String>,
+    /// Events that start it, such as `push` or `pull_request`
+    pub triggers: Vec<String>,
+    /// Job names, or stage names for Jenkins, in file order
+    pub jobs: Vec<String>,
+}
+
+/// The images a Dockerfile builds on and the ports it exposes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerImage {
+    pub file: String,
+    /// Images of the `FROM` lines, without earlier build stages
+    pub base_images: Vec<String>,
+    pub ports: Vec<String>,
+}
+
+/// A service of a Compose file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComposeService {
+    pub file: String,
+    pub name: String,
+    pub image: Option<String>,
+    /// Whether the service is built from a Dockerfile in the repository
+    pub build: bool,
+    /// Port mappings as written, such as `8080:80`
+    pub ports: Vec<String>,
+}
+
+/// A resource of a Kubernetes manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KubernetesResource {
+    pub file: String,
+    /// Such as `Deployment` or `Service`
+    pub kind: String,
+    pub name: Option<String>,
+    pub images: Vec<String>,
+    /// `containerPort` values
+    pub ports: Vec<String>,
+}
+
+/// How a repository is built, tested, and deployed, summarized from its CI workflows,
+/// Dockerfiles, Compose files, Jenkinsfiles, and Kubernetes manifests
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InfraSummary {
+    pub pipelines: Vec<Pipeline>,
+    pub containers: Vec<ContainerImage>,
+    pub services: Vec<ComposeService>,
+    pub kubernetes: Vec<KubernetesResource>,
+    /// Files that were recognized by name but could not be summarized
+    pub unparsed: Vec<String>,
+}
+
+impl InfraSummary {
+    /// Whether nothing was found
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+            && self.containers.is_empty()
+            && self.services.is_empty()
+            && self.kubernetes.is_empty()
+            && self.unparsed.is_empty()
+    }
+
+    /// Workflow files of GitHub Actions started by pushes or pull requests
+    pub fn push_workflows(&self) -> impl Iterator<Item = &Pipeline> {
+        self.pipelines.iter().filter(|pipeline| {
+            pipeline.system == CiSystem::GitHubActions
+                && pipeline.triggers.iter().any(|t| t == "push" || t == "pull_request")
+        })
+    }
+
+    /// One line per pipeline, image, service, and resource, each starting with `- `
+    pub fn to_prompt(&self) -> String {
+        let mut out = String::new();
+        for pipeline in &self.pipelines {
+            let system = match pipeline.system {
+                CiSystem::GitHubActions => "GitHub Actions workflow",
+                CiSystem::GitLab => "GitLab CI pipeline",
+                CiSystem::Jenkins => "Jenkins pipeline",
+            };
+            let name = pipeline.name.as_deref().map(|name| format!(" {}", name)).unwrap_or_default();
+            let mut facts = Vec::new();
+            if !pipeline.triggers.is_empty() {
+                facts.push(format!("on {}", pipeline.triggers.join(", ")));
+            }
+            if !pipeline.jobs.is_empty() {
+                let label = if pipeline.system == CiSystem::Jenkins { "stages" } else { "jobs" };
+                facts.push(format!("{} {}", label, pipeline.jobs.join(", ")));
+            }
+            let _ = writeln!(out, "- {}{} ({}): {}", system, name, pipeline.file, facts.join("; "));
+        }
+        for container in &self.containers {
+            let mut facts = vec![format!("from {}", container.base_images.join(", "))];
+            if !container.ports.is_empty() {
+                facts.push(format!("exposes {}", container.ports.join(", ")));
+            }
+            let _ = writeln!(out, "- {}: {}", container.file, facts.join("; "));
+        }
+        for service in &self.services {
+            let mut facts = Vec::new();
+            match (&service.image, service.build) {
+                (Some(image), false) => facts.push(format!("image {}", image)),
+                (Some(image), true) => facts.push(format!("built from the repository as {}", image)),
+                (None, true) => facts.push("built from the repository".to_string()),
+                (None, false) => {}
+            }
+            if !service.ports.is_empty() {
+                facts.push(format!("ports {}", service.ports.join(", ")));
+            }
+            let _ = writeln!(out, "- Compose service {} ({}): {}", service.name, service.file, facts.join("; "));
+        }
+        for resource in &self.kubernetes {
+            let name = resource.name.as_deref().map(|name| format!(" {}", name)).unwrap_or_default();
+            let mut facts = Vec::new();
+            if !resource.images.is_empty() {
+                facts.push(format!("images {}", resource.images.join(", ")));
+            }
+            if !resource.ports.is_empty() {
+                facts.push(format!("ports {}", resource.ports.join(", ")));
+            }
+            let _ = write!(out, "- Kubernetes {}{} ({})", resource.kind, name, resource.file);
+            if !facts.is_empty() {
+                let _ = write!(out, ": {}", facts.join("; "));
+            }
+            out.push('\n');
+        }
+        if !self.unparsed.is_empty() {
+            let _ = writeln!(out, "- Not summarized: {}", self.unparsed.join(", "));
+        }
+        out
+    }
+
+    /// The summary as a block for the README prompt, at most [`INFRA_MAX_BYTES`] long,
+    /// or `None` when nothing was found
+    pub fn prompt_block(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut block = String::from(
+            "\n\nBuild, test, and deployment configuration, summarized from the CI and container files \
+             (some of which may not be among the files above):\n",
+        );
+        for line in self.to_prompt().lines() {
+            if block.len() + line.len() + 1 > INFRA_MAX_BYTES {
+                break;
+            }
+            block.push_str(line);
+            block.push('\n');
+        }
+        Some(block)
+    }
+}
+
+/// Summarize CI, container, and Kubernetes configuration from `files`, pairs of a path
+/// relative to the repository root (with `/` separators) and the file's content. Files
+/// are recognized by name: `.github/workflows/*.yml`, `.gitlab-ci.yml`, `Jenkinsfile`,
+/// `Dockerfile*`, `docker-compose*.yml`, and `compose*.yml`; any other YAML file with
+/// `apiVersion` and `kind` is read as a Kubernetes manifest. A recognized file that
+/// cannot be read this way is listed in [`InfraSummary::unparsed`].
+pub fn summarize_infra<'a>(files: impl IntoIterator<Item = (&'a str, &'a str)>) -> InfraSummary {
+    let mut summary = InfraSummary::default();
+    for (path, content) in files {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let yaml = name.ends_with(".yml") || name.ends_with(".yaml");
+        let parsed = if path.starts_with(".github/workflows/") && yaml {
+            github_workflow(path, content).map(|pipeline| summary.pipelines.push(pipeline))
+        } else if name == ".gitlab-ci.yml" {
+            gitlab_pipeline(path, content).map(|pipeline| summary.pipelines.push(pipeline))
+        } else if name.starts_with("Jenkinsfile") {
+            jenkins_pipeline(path, content).map(|pipeline| summary.pipelines.push(pipeline))
+        } else if name.starts_with("Dockerfile") || name.ends_with(".Dockerfile") || name.ends_with(".dockerfile") {
+            dockerfile(path, content).map(|container| summary.containers.push(container))
+        } else if yaml && (name.starts_with("docker-compose") || name.starts_with("compose.")) {
+            compose_services(path, content).map(|services| summary.services.extend(services))
+        } else if yaml {
+            summary.kubernetes.extend(kubernetes_resources(path, content));
+            continue;
+        } else {
+            continue;
+        };
+        if parsed.is_none() {
+            summary.unparsed.push(path.to_string());
+        }
+    }
+    summary
+}
+
+/// Find and summarize the CI, container, and Kubernetes configuration of the repository
+/// in `dir`, whatever the prompt's own size and exclusion rules leave out. Files
+/// ignored by git, hidden directories other than `.github/workflows`, and
+/// `node_modules` are not searched.
+pub fn detect_infra(dir: &Path) -> InfraSummary {
+    let mut paths: Vec<_> = fs::read_dir(dir.join(".github/workflows"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+    paths.push(dir.join(".gitlab-ci.yml"));
+
+    let walker = WalkBuilder::new(dir)
+        .standard_filters(true)
+        .max_depth(Some(MAX_INFRA_DEPTH))
+        .filter_entry(|entry| entry.file_name() != "node_modules" && entry.file_name() != "vendor")
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .build();
+    let candidates = walker.flatten().filter(|entry| entry.file_type().is_some_and(|t| t.is_file())).filter(|entry| {
+        let name = entry.file_name().to_string_lossy();
+        name.starts_with("Jenkinsfile")
+            || name.starts_with("Dockerfile")
+            || name.ends_with(".Dockerfile")
+            || name.ends_with(".dockerfile")
+            || name.ends_with(".yml")
+            || name.ends_with(".yaml")
+    });
+    paths.extend(candidates.take(MAX_INFRA_FILES).map(|entry| entry.into_path()));
+
+    let files: Vec<(String, String)> = paths
+        .iter()
+        .filter_map(|path| {
+            let metadata = fs::metadata(path).ok()?;
+            if !metadata.is_file() || metadata.len() > MAX_INFRA_FILE_BYTES {
+                return None;
+            }
+            let relative = path.strip_prefix(dir).unwrap_or(path).to_string_lossy().replace('\\', "/");
+            Some((relative, fs::read_to_string(path).ok()?))
+        })
+        .collect();
+    summarize_infra(files.iter().map(|(path, content)| (path.as_str(), content.as_str())))
+}
+
+fn github_workflow(path: &str, source: &str) -> Option<Pipeline> {
+    let lines = significant_lines(source);
+    let top = mapping(&lines);
+    let entry = |key: &str| top.iter().find(|entry| entry.key == key);
+    // YAML 1.1 reads an unquoted `on` as true, so some workflows quote it
+    let triggers = entry("on").map(|on| {
+        if on.value.is_empty() && !on.nested.iter().all(|line| line.trim_start().starts_with("- ")) {
+            mapping(&on.nested).into_iter().map(|trigger| trigger.key).collect()
+        } else {
+            list(on)
+        }
+    });
+    let jobs = entry("jobs").map(|jobs| mapping(&jobs.nested).into_iter().map(|job| job.key).collect());
+    if triggers.is_none() && jobs.is_none() {
+        return None;
+    }
+    Some(Pipeline {
+        file: path.to_string(),
+        system: CiSystem::GitHubActions,
+        name: entry("name").map(|name| name.value.clone()).filter(|name| !name.is_empty()),
+        triggers: triggers.unwrap_or_default(),
+        jobs: jobs.unwrap_or_default(),
+    })
+}
+
+fn gitlab_pipeline(path: &str, source: &str) -> Option<Pipeline> {
+    let lines = significant_lines(source);
+    let jobs: Vec<String> = mapping(&lines)
+        .into_iter()
+        .filter(|entry| !entry.key.starts_with('.') && !GITLAB_KEYWORDS.contains(&entry.key.as_str()))
+        .filter(|entry| !entry.nested.is_empty())
+        .map(|entry| entry.key)
+        .collect();
+    if jobs.is_empty() {
+        return None;
+    }
+    Some(Pipeline {
+        file: path.to_string(),
+        system: CiSystem::GitLab,
+        name: None,
+        triggers: Vec::new(),
+        jobs,
+    })
+}
+
+/// Stage names of a declarative or scripted Jenkinsfile, from `stage('...')`
+fn jenkins_pipeline(path: &str, source: &str) -> Option<Pipeline> {
+    let stages: Vec<String> = source
+        .match_indices("stage(")
+        .filter_map(|(at, call)| {
+            let rest = source[at + call.len()..].trim_start();
+            let quote = rest.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+            let name = rest[1..].split(quote).next()?;
+            Some(name.to_string())
+        })
+        .collect();
+    if stages.is_empty() {
+        return None;
+    }
+    Some(Pipeline {
+        file: path.to_string(),
+        system: CiSystem::Jenkins,
+        name: None,
+        triggers: Vec::new(),
+        jobs: stages,
+    })
+}
+
+fn dockerfile(path: &str, source: &str) -> Option<ContainerImage> {
+    let mut stages: Vec<String> = Vec::new();
+    let mut base_images: Vec<String> = Vec::new();
+    let mut ports = Vec::new();
+    for line in source.lines() {
+        let mut words = line.split_whitespace();
+        let Some(instruction) = words.next() else {
+            continue;
+        };
+        if instruction.eq_ignore_ascii_case("FROM") {
+            let mut words = words.filter(|word| !word.starts_with("--"));
+            let Some(image) = words.next() else {
+                continue;
+            };
+            // A stage built on an earlier one says nothing new about the image
+            if !stages.iter().any(|stage| stage.eq_ignore_ascii_case(image)) && !base_images.iter().any(|i| i == image)
+            {
+                base_images.push(image.to_string());
+            }
+            if let (Some(as_), Some(stage)) = (words.next(), words.next()) {
+                if as_.eq_ignore_ascii_case("AS") {
+                    stages.push(stage.to_string());
+                }
+            }
+        } else if instruction.eq_ignore_ascii_case("EXPOSE") {
+            ports.extend(words.map(String::from));
+        }
+    }
+    if base_images.is_empty() {
+        return None;
+    }
+    Some(ContainerImage { file: path.to_string(), base_images, ports })
+}
+
+fn compose_services(path: &str, source: &str) -> Option<Vec<ComposeService>> {
+    let lines = significant_lines(source);
+    let top = mapping(&lines);
+    let services = top.iter().find(|entry| entry.key == "services")?;
+    let services: Vec<ComposeService> = mapping(&services.nested)
+        .into_iter()
+        .map(|service| {
+            let settings = mapping(&service.nested);
+            let setting = |key: &str| settings.iter().find(|entry| entry.key == key);
+            ComposeService {
+                file: path.to_string(),
+                name: service.key.clone(),
+                image: setting("image").map(|image| image.value.clone()).filter(|image| !image.is_empty()),
+                build: setting("build").is_some(),
+                ports: setting("ports").map(list).unwrap_or_default(),
+            }
+        })
+        .collect();
+    if services.is_empty() {
+        return None;
+    }
+    Some(services)
+}
+
+/// The resources of each `---` separated document with `apiVersion` and `kind`
+fn kubernetes_resources(path: &str, source: &str) -> Vec<KubernetesResource> {
+    let lines = significant_lines(source);
+    lines
+        .split(|line| line.trim_end() == "---")
+        .filter_map(|document| {
+            let top = mapping(document);
+            let entry = |key: &str| top.iter().find(|entry| entry.key == key);
+            entry("apiVersion")?;
+            let kind = entry("kind").map(|kind| kind.value.clone()).filter(|kind| !kind.is_empty())?;
+            let name = entry("metadata")
+                .and_then(|metadata| mapping(&metadata.nested).into_iter().find(|entry| entry.key == "name"))
+                .map(|name| name.value)
+                .filter(|name| !name.is_empty());
+            // Containers may sit at any depth, as in a Deployment's pod template
+            let values = |key: &str| -> Vec<String> {
+                let mut values: Vec<String> = Vec::new();
+                for line in document {
+                    let line = line.trim_start().trim_start_matches("- ");
+                    if let Some(value) = line.strip_prefix(key).and_then(|rest| rest.strip_prefix(':')) {
+                        let value = unquote(value);
+                        if !value.is_empty() && !values.contains(&value) {
+                            values.push(value);
+                        }
+                    }
+                }
+                values
+            };
+            Some(KubernetesResource {
+                file: path.to_string(),
+                kind,
+                name,
+                images: values("image"),
+                ports: values("containerPort"),
+            })
+        })
+        .collect()
+}
+
+/// A key of a YAML block mapping, its inline value, and the lines nested under it
+struct Entry<'a> {
+    key: String,
+    value: String,
+    nested: Vec<&'a str>,
+}
+
+/// The lines of `source` without blank lines, comment lines, and trailing comments
+fn significant_lines(source: &str) -> Vec<&str> {
+    source
+        .lines()
+        .map(|line| line.split(" #").next().unwrap_or_default().trim_end())
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .collect()
+}
+
+/// The keys of the block mapping in `lines`, those at its outermost indent. Only as
+/// much YAML is understood as CI and deployment files commonly use: no anchors, flow
+/// mappings, or multi-line keys.
+fn mapping<'a>(lines: &[&'a str]) -> Vec<Entry<'a>> {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let Some(base) = lines.iter().map(|line| indent(line)).min() else {
+        return Vec::new();
+    };
+    let mut entries: Vec<Entry> = Vec::new();
+    for line in lines {
+        let at_base = indent(line) == base;
+        // A list may sit at the same indent as the key that holds it
+        let list_item = line.trim_start().starts_with("- ") || line.trim() == "-";
+        if at_base && !list_item {
+            let (key, value) = match line.trim().split_once(':') {
+                Some((key, value)) if value.is_empty() || value.starts_with(' ') => (key, value),
+                _ => continue,
+            };
+            entries.push(Entry { key: unquote(key), value: unquote(value), nested: Vec::new() });
+        } else if let Some(entry) = entries.last_mut() {
+            entry.nested.push(line);
+        }
+    }
+    entries
+}
+
+/// The items of a list held by `entry`, either `[a, b]`, a single scalar, or `- ` lines
+fn list(entry: &Entry) -> Vec<String> {
+    let value = entry.value.trim();
+    if let Some(items) = value.strip_prefix('[').and_then(|items| items.strip_suffix(']')) {
+        return items.split(',').map(unquote).filter(|item| !item.is_empty()).collect();
+    }
+    if !value.is_empty() && !value.starts_with(['|', '>']) {
+        return vec![value.to_string()];
+    }
+    let indent = |line: &&str| line.len() - line.trim_start().len();
+    let base = entry.nested.iter().map(indent).min().unwrap_or_default();
+    entry
+        .nested
+        .iter()
+        .filter(|line| indent(line) == base)
+        .filter_map(|line| line.trim_start().strip_prefix("- "))
+        .map(unquote)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn unquote(text: &str) -> String {
+    text.trim().trim_matches(['"', '\'']).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    const WORKFLOW: &str = "name: CI
+on:
+  push:
+    branches: [main]
+  pull_request:
+jobs:
+  test:
+    runs-on: ubuntu-latest
+    steps:
+      - run: cargo test # every target
+  lint:
+    runs-on: ubuntu-latest
+";
+    const DOCKERFILE: &str = "FROM rust:1.80 AS build
+RUN cargo build --release
+FROM --platform=linux/amd64 debian:bookworm-slim
+COPY --from=build /app /app
+EXPOSE 8080 9090
+";
+    const COMPOSE: &str = "services:
+  api:
+    build: .
+    image: demo/api
+    ports:
+      - \"8080:8080\"
+  db:
+    image: postgres:16
+";
+    const DEPLOYMENT: &str = "apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: demo
+spec:
+  template:
+    spec:
+      containers:
+        - name: api
+          image: demo/api:1.0
+          ports:
+            - containerPort: 8080
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: demo
+";
+
+    #[test]
+    fn ci_and_container_files_are_summarized() {
+        let tree = FixtureTree::new()
+            .file(".github/workflows/ci.yml", WORKFLOW)
+            .file("Dockerfile", DOCKERFILE)
+            .file("docker-compose.yml", COMPOSE)
+            .file("deploy/k8s.yaml", DEPLOYMENT)
+            .file("node_modules/dep/Dockerfile", "FROM node:20\n");
+        let summary = detect_infra(tree.path());
+        assert_eq!(
+            summary.pipelines,
+            [Pipeline {
+                file: ".github/workflows/ci.yml".into(),
+                system: CiSystem::GitHubActions,
+                name: Some("CI".into()),
+                triggers: vec!["push".into(), "pull_request".into()],
+                jobs: vec!["test".into(), "lint".into()],
+            }]
+        );
+        assert_eq!(summary.push_workflows().count(), 1);
+        assert_eq!(
+            summary.to_prompt(),
+            "- GitHub Actions workflow CI (.github/workflows/ci.yml): on push, pull_request; jobs test, lint
+- Dockerfile: from rust:1.80, debian:bookworm-slim; exposes 8080, 9090
+- Compose service api (docker-compose.yml): built from the repository as demo/api; ports 8080:8080
+- Compose service db (docker-compose.yml): image postgres:16
+- Kubernetes Deployment demo (deploy/k8s.yaml): images demo/api:1.0; ports 8080
+- Kubernetes Service demo (deploy/k8s.yaml)
+"
+        );
+        assert!(summary.prompt_block().unwrap().ends_with(&summary.to_prompt()));
+    }
+
+    #[test]
+    fn a_project_without_infrastructure_has_no_summary() {
+        let tree = FixtureTree::new()
+            .file("src/main.rs", "fn main() {}\n")
+            .file("config/settings.yml", "log_level: debug\nkind: local\n");
+        let summary = detect_infra(tree.path());
+        assert!(summary.is_empty(), "{:?}", summary);
+        assert_eq!(summary.prompt_block(), None);
+    }
+
+    #[test]
+    fn files_that_cannot_be_read_are_listed_by_name() {
+        let summary = summarize_infra([
+            (".github/workflows/broken.yml", "this is not a workflow\n"),
+            ("Dockerfile.dev", "# no base image\nRUN true\n"),
+            ("Jenkinsfile", "pipeline {\n  stages {\n    stage('Build') { }\n    stage(\"Test\") { }\n  }\n}\n"),
+            (".gitlab-ci.yml", "stages: [build]\nvariables:\n  X: 1\nbuild:\n  script: make\n.hidden:\n  script: true\n"),
+        ]);
+        assert_eq!(summary.unparsed, [".github/workflows/broken.yml", "Dockerfile.dev"]);
+        let jobs: Vec<_> = summary.pipelines.iter().map(|pipeline| (pipeline.system, pipeline.jobs.clone())).collect();
+        assert_eq!(jobs, [(CiSystem::Jenkins, vec!["Build".into(), "Test".into()]), (CiSystem::GitLab, vec!["build".into()])]);
+        assert!(summary.to_prompt().ends_with("- Not summarized: .github/workflows/broken.yml, Dockerfile.dev\n"));
+    }
+
+    #[test]
+    fn trigger_lists_take_any_form() {
+        let triggers = |on: &str| {
+            let summary = summarize_infra([(".github/workflows/a.yml", on)]);
+            summary.pipelines[0].triggers.clone()
+        };
+        assert_eq!(triggers("on: push\n"), ["push"]);
+        assert_eq!(triggers("\"on\": [push, 'workflow_dispatch']\n"), ["push", "workflow_dispatch"]);
+        assert_eq!(triggers("on:\n- release\n- schedule\n"), ["release", "schedule"]);
+    }
+
+    #[test]
+    fn the_prompt_block_is_capped() {
+        let files: Vec<(String, String)> =
+            (0..200).map(|n| (format!("Dockerfile.{}", n), format!("FROM registry.example.com/base-image-{}:latest\n", n))).collect();
+        let summary = summarize_infra(files.iter().map(|(path, content)| (path.as_str(), content.as_str())));
+        let block = summary.prompt_block().unwrap();
+        assert!(block.len() <= INFRA_MAX_BYTES, "{}", block.len());
+        assert!(block.ends_with(":latest\n"));
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use utoipa::ToSchema;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::callback::{CallbackDelivery, CallbackSender};
+use crate::progress::ProgressEvent;
+
+/// Where a job is in its lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Cloning,
+    Collecting,
+    Generating,
+    Done,
+    Failed,
+}
+
+impl JobStatus {
+    /// The status as it appears in JSON
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Cloning => "cloning",
+            JobStatus::Collecting => "collecting",
+            JobStatus::Generating => "generating",
+            JobStatus::Done => "done",
+            JobStatus::Failed => "failed",
+        }
+    }
+}
+
+/// The state of a job as reported to clients
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct JobSnapshot<R> {
+    #[schema(format = "uuid")]
+    pub id: String,
+    pub status: JobStatus,
+    /// Latest progress event of the current step
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<ProgressEvent>,
+    /// Output of a finished job
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<R>,
+    /// Why the job failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Delivery of the completion callback, when the job was submitted with one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callback: Option<CallbackDelivery>,
+}
+
+/// What a completion callback POSTs
+#[derive(Serialize)]
+struct CallbackPayload<'a, R> {
+    id: &'a str,
+    status: JobStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'a R>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+    /// From submission to the end of the job
+    duration_ms: u128,
+}
+
+struct Entry<R> {
+    snapshot: JobSnapshot<R>,
+    submitted_at: Instant,
+    finished_at: Option<Instant>,
+}
+
+type Jobs<R> = Arc<Mutex<HashMap<String, Entry<R>>>>;
+
+/// The queue has no room for another job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFull;
+
+/// Lets a running job report its status and progress
+pub struct JobHandle<R> {
+    id: String,
+    jobs: Jobs<R>,
+}
+
+impl<R> JobHandle<R> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Move the job to `status`, clearing the progress of the previous step
+    pub fn set_status(&self, status: JobStatus) {
+        self.update(|snapshot| {
+            snapshot.status = status;
+            snapshot.progress = None;
+        });
+    }
+
+    /// Record the latest progress of the current step
+    pub fn set_progress(&self, event: &ProgressEvent) {
+        self.update(|snapshot| snapshot.progress = Some(event.clone()));
+    }
+
+    fn update(&self, f: impl FnOnce(&mut JobSnapshot<R>)) {
+        if let Some(entry) = self.jobs.lock().unwrap().get_mut(&self.id) {
+            f(&mut entry.snapshot);
+        }
+    }
+}
+
+/// In-memory jobs processed by a fixed number of workers. At most `capacity` jobs wait
+/// at a time, and finished jobs are forgotten `ttl` after they finish. Jobs submitted
+/// with a callback URL are reported to it through `callbacks` when they finish.
+pub struct JobQueue<T, R> {
+    jobs: Jobs<R>,
+    callbacks: Option<Arc<CallbackSender>>,
+    sender: mpsc::Sender<(String, T)>,
+    ttl: Duration,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+    stopping: watch::Sender<bool>,
+}
+
+impl<T, R> JobQueue<T, R>
+where
+    T: Send + 'static,
+    R: Clone + Serialize + Send + Sync + 'static,
+{
+    /// Start `workers` tasks that run each submitted job through `run`
+    pub fn start<F, Fut>(
+        workers: usize,
+        capacity: usize,
+        ttl: Duration,
+        callbacks: Option<Arc<CallbackSender>>,
+        run: F,
+    ) -> Self
+    where
+        F: Fn(T, JobHandle<R>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = std::result::Result<R, String>> + Send + 'static,
+    {
+        let jobs: Jobs<R> = Arc::default();
+        let (sender, receiver) = mpsc::channel::<(String, T)>(capacity.max(1));
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        let run = Arc::new(run);
+        let (stopping, _) = watch::channel(false);
+
+        let mut handles = Vec::new();
+        for _ in 0..workers.max(1) {
+            let receiver = Arc::clone(&receiver);
+            let jobs = Arc::clone(&jobs);
+            let run = Arc::clone(&run);
+            let callbacks = callbacks.clone();
+            let mut stopping = stopping.subscribe();
+            handles.push(tokio::spawn(async move {
+                loop {
+                    // Holding the lock only while waiting hands each job to one idle worker
+                    let next = tokio::select! {
+                        biased;
+                        _ = stopping.wait_for(|stopping| *stopping) => return,
+                        next = async { receiver.lock().await.recv().await } => next,
+                    };
+                    let Some((id, input)) = next else {
+                        return;
+                    };
+                    let handle = JobHandle {
+                        id: id.clone(),
+                        jobs: Arc::clone(&jobs),
+                    };
+                    let outcome = run(input, handle).await;
+                    let mut notify = None;
+                    if let Some(entry) = jobs.lock().unwrap().get_mut(&id) {
+                        let snapshot = &mut entry.snapshot;
+                        snapshot.progress = None;
+                        match outcome {
+                            Ok(result) => {
+                                snapshot.status = JobStatus::Done;
+                                snapshot.result = Some(result);
+                            }
+                            Err(error) => {
+                                snapshot.status = JobStatus::Failed;
+                                snapshot.error = Some(error);
+                            }
+                        }
+                        entry.finished_at = Some(Instant::now());
+                        notify = entry.callback_payload().map(|body| (entry.snapshot.callback.clone(), body));
+                    }
+                    if let (Some(callbacks), Some((Some(callback), body))) = (&callbacks, notify) {
+                        // Retries take a while, and must not hold up the next job
+                        tokio::spawn(deliver(Arc::clone(callbacks), Arc::clone(&jobs), id, callback.url, body));
+                    }
+                }
+            }));
+        }
+
+        Self {
+            jobs,
+            callbacks,
+            sender,
+            ttl,
+            workers: Mutex::new(handles),
+            stopping,
+        }
+    }
+
+    /// Stop taking jobs and give running ones up to `timeout` to finish. Jobs still
+    /// running then are cancelled, which drops everything they hold, and every job that
+    /// did not finish, queued ones included, is marked failed. Returns how many that was.
+    pub async fn shutdown(&self, timeout: Duration) -> usize {
+        self.stopping.send_replace(true);
+        let mut workers: Vec<JoinHandle<()>> = std::mem::take(&mut *self.workers.lock().unwrap());
+        let finished = tokio::time::timeout(timeout, async {
+            for worker in &mut workers {
+                let _ = worker.await;
+            }
+        })
+        .await;
+        if finished.is_err() {
+            for worker in &workers {
+                worker.abort();
+            }
+        }
+
+        let mut interrupted = 0;
+        for entry in self.jobs.lock().unwrap().values_mut() {
+            if entry.finished_at.is_none() {
+                entry.snapshot.status = JobStatus::Failed;
+                entry.snapshot.progress = None;
+                entry.snapshot.error = Some("interrupted by server shutdown".to_string());
+                entry.finished_at = Some(Instant::now());
+                interrupted += 1;
+            }
+        }
+        interrupted
+    }
+
+    /// Queue a job and return its initial state, or [`QueueFull`] when no slot is free.
+    /// With `callback_url`, the job's final state is POSTed there when it finishes; the
+    /// URL must already have passed the [`CallbackSender`]'s policy.
+    pub fn submit(&self, input: T, callback_url: Option<String>) -> std::result::Result<JobSnapshot<R>, QueueFull> {
+        self.evict_expired();
+        let id = uuid::Uuid::new_v4().to_string();
+        let snapshot = JobSnapshot {
+            id: id.clone(),
+            status: JobStatus::Queued,
+            progress: None,
+            result: None,
+            error: None,
+            callback: callback_url.filter(|_| self.callbacks.is_some()).map(CallbackDelivery::pending),
+        };
+        // Register first so a fast worker always finds the entry
+        self.jobs.lock().unwrap().insert(
+            id.clone(),
+            Entry {
+                snapshot: snapshot.clone(),
+                submitted_at: Instant::now(),
+                finished_at: None,
+            },
+        );
+        match self.sender.try_send((id.clone(), input)) {
+            Ok(()) => Ok(snapshot),
+            Err(TrySendError::Full(_)) | Err(TrySendError::Closed(_)) => {
+                self.jobs.lock().unwrap().remove(&id);
+                Err(QueueFull)
+            }
+        }
+    }
+
+    /// The current state of job `id`, unless it is unknown or expired
+    pub fn get(&self, id: &str) -> Option<JobSnapshot<R>> {
+        self.evict_expired();
+        self.jobs.lock().unwrap().get(id).map(|entry| entry.snapshot.clone())
+    }
+
+    fn evict_expired(&self) {
+        let ttl = self.ttl;
+        self.jobs
+            .lock()
+            .unwrap()
+            .retain(|_, entry| entry.finished_at.is_none_or(|finished| finished.elapsed() < ttl));
+    }
+}
+
+impl<R: Serialize> Entry<R> {
+    /// The body of the completion callback, when the job has one
+    fn callback_payload(&self) -> Option<Vec<u8>> {
+        self.snapshot.callback.as_ref()?;
+        let finished_at = self.finished_at?;
+        let payload = CallbackPayload {
+            id: &self.snapshot.id,
+            status: self.snapshot.status,
+            result: self.snapshot.result.as_ref(),
+            error: self.snapshot.error.as_deref(),
+            duration_ms: finished_at.duration_since(self.submitted_at).as_millis(),
+        };
+        match serde_json::to_vec(&payload) {
+            Ok(body) => Some(body),
+            Err(e) => {
+                tracing::warn!("Cannot serialize the callback for job {}: {}", self.snapshot.id, e);
+                None
+            }
+        }
+    }
+}
+
+/// Deliver a finished job's callback and record how it went on the job
+async fn deliver<R>(callbacks: Arc<CallbackSender>, jobs: Jobs<R>, id: String, url: String, body: Vec<u8>) {
+    let delivery = callbacks.deliver(&id, &url, body).await;
+    tracing::info!("Callback for job {} to {}: {:?} after {} attempts", id, url, delivery.status, delivery.attempts);
+    if let Some(entry) = jobs.lock().unwrap().get_mut(&id) {
+        entry.snapshot.callback = Some(delivery);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    type Release = oneshot::Receiver<std::result::Result<String, String>>;
+
+    /// A queue whose jobs report that they are generating, then wait to be released
+    /// with their outcome
+    fn queue(workers: usize, capacity: usize, ttl: Duration) -> JobQueue<Release, String> {
+        JobQueue::start(workers, capacity, ttl, None, |release: Release, handle: JobHandle<String>| async move {
+            handle.set_status(JobStatus::Generating);
+            handle.set_progress(&ProgressEvent::LlmDelta { bytes: 5 });
+            release.await.unwrap_or_else(|_| Err("released without an outcome".to_string()))
+        })
+    }
+
+    /// Wait until job `id` is in `status`, and return its state then
+    async fn wait_for(queue: &JobQueue<Release, String>, id: &str, status: JobStatus) -> JobSnapshot<String> {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match queue.get(id) {
+                    Some(job) if job.status == status => return job,
+                    _ => tokio::time::sleep(Duration::from_millis(5)).await,
+                }
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("job {} never became {}: {:?}", id, status.as_str(), queue.get(id)))
+    }
+
+    #[tokio::test]
+    async fn a_job_moves_from_queued_to_done() {
+        let queue = queue(1, 4, Duration::from_secs(60));
+        let (release, waiting) = oneshot::channel();
+        let job = queue.submit(waiting, None).unwrap();
+        assert_eq!((job.status, job.result.as_deref()), (JobStatus::Queued, None));
+
+        let running = wait_for(&queue, &job.id, JobStatus::Generating).await;
+        assert!(matches!(running.progress, Some(ProgressEvent::LlmDelta { bytes: 5 })));
+
+        release.send(Ok("# README".to_string())).unwrap();
+        let done = wait_for(&queue, &job.id, JobStatus::Done).await;
+        assert_eq!(done.result.as_deref(), Some("# README"));
+        assert!(done.progress.is_none() && done.error.is_none());
+        let json = serde_json::to_value(&done).unwrap();
+        assert_eq!(json["status"], "done");
+        assert!(json.get("error").is_none());
+    }
+
+    #[tokio::test]
+    async fn a_failing_job_keeps_its_error() {
+        let queue = queue(1, 4, Duration::from_secs(60));
+        let (release, waiting) = oneshot::channel();
+        let job = queue.submit(waiting, None).unwrap();
+        release.send(Err("clone failed".to_string())).unwrap();
+        let failed = wait_for(&queue, &job.id, JobStatus::Failed).await;
+        assert_eq!((failed.error.as_deref(), failed.result), (Some("clone failed"), None));
+        assert!(queue.get("no-such-job").is_none());
+    }
+
+    #[tokio::test]
+    async fn a_full_queue_refuses_jobs() {
+        let queue = queue(1, 1, Duration::from_secs(60));
+        let (first_release, first) = oneshot::channel();
+        let running = queue.submit(first, None).unwrap();
+        wait_for(&queue, &running.id, JobStatus::Generating).await;
+        let (_second_release, second) = oneshot::channel();
+        queue.submit(second, None).unwrap();
+        let (_, third) = oneshot::channel();
+        assert_eq!(queue.submit(third, None).unwrap_err(), QueueFull);
+
+        first_release.send(Ok(String::new())).unwrap();
+        wait_for(&queue, &running.id, JobStatus::Done).await;
+    }
+
+    #[tokio::test]
+    async fn finished_jobs_are_forgotten_after_the_ttl() {
+        let queue = queue(1, 4, Duration::from_millis(50));
+        let (release, waiting) = oneshot::channel();
+        let job = queue.submit(waiting, None).unwrap();
+        release.send(Ok(String::new())).unwrap();
+        wait_for(&queue, &job.id, JobStatus::Done).await;
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(queue.get(&job.id).is_none());
+    }
+
+    #[tokio::test]
+    async fn shutdown_cancels_running_and_queued_jobs() {
+        let queue = queue(1, 4, Duration::from_secs(60));
+        let (_running_release, running) = oneshot::channel();
+        let running = queue.submit(running, None).unwrap();
+        wait_for(&queue, &running.id, JobStatus::Generating).await;
+        let (_queued_release, queued) = oneshot::channel();
+        let queued = queue.submit(queued, None).unwrap();
+
+        assert_eq!(queue.shutdown(Duration::from_millis(50)).await, 2);
+        for id in [&running.id, &queued.id] {
+            let job = queue.get(id).unwrap();
+            assert_eq!(job.status, JobStatus::Failed);
+            assert_eq!(job.error.as_deref(), Some("interrupted by server shutdown"));
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::fmt::Write;
+
+use crate::{Result, TechDocsError};
+
+/// Marker opening a hand-written README region that regeneration must keep
+pub const KEEP_START: &str = "<!-- techdocs:keep:start -->";
+/// Marker closing a kept region
+pub const KEEP_END: &str = "<!-- techdocs:keep:end -->";
+
+/// A hand-written region of a README, markers included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeptSection {
+    /// The region text from the start marker line through the end marker line
+    pub content: String,
+    /// The closest heading line above the region, if any
+    pub heading: Option<String>,
+}
+
+/// Find the kept regions in `readme`, in document order
+pub fn extract_kept_sections(readme: &str) -> Result<Vec<KeptSection>> {
+    let mut sections = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut current: Option<(usize, String)> = None;
+
+    for (index, line) in readme.lines().enumerate() {
+        let line_number = index + 1;
+        let marker = line.trim();
+
+        if marker == KEEP_START {
+            if let Some((start, _)) = current {
+                return Err(unbalanced(format!(
+                    "line {} opens a kept section inside the one opened on line {}",
+                    line_number, start
+                )));
+            }
+            current = Some((line_number, format!("{}\n", line)));
+        } else if marker == KEEP_END {
+            let Some((_, mut content)) = current.take() else {
+                return Err(unbalanced(format!(
+                    "line {} closes a kept section that was never opened",
+                    line_number
+                )));
+            };
+            content.push_str(line);
+            sections.push(KeptSection {
+                content,
+                heading: heading.clone(),
+            });
+        } else if let Some((_, content)) = current.as_mut() {
+            content.push_str(line);
+            content.push('\n');
+        } else if line.starts_with('#') {
+            heading = Some(line.trim_end().to_string());
+        }
+    }
+
+    if let Some((start, _)) = current {
+        return Err(unbalanced(format!("the kept section opened on line {} is never closed", start)));
+    }
+    Ok(sections)
+}
+
+fn unbalanced(detail: String) -> TechDocsError {
+    TechDocsError::Output(format!(
+        "Unbalanced {} / {} markers in README.md: {}",
+        KEEP_START, KEEP_END, detail
+    ))
+}
+
+/// Instructions appended to the prompt asking the model to keep `sections` verbatim
+pub fn preserve_instructions(sections: &[KeptSection]) -> String {
+    let mut out = String::from(
+        "\n\nThe existing README contains hand-written sections that must be preserved verbatim, \
+         including their marker comments. Place each one under the same heading as before:\n",
+    );
+    for section in sections {
+        let heading = section.heading.as_deref().unwrap_or("(top of the document)");
+        let _ = write!(out, "\nUnder heading: {}\n{}\n", heading, section.content);
+    }
+    out
+}
+
+/// Put every kept section back into `generated`. Sections the model reproduced exactly
+/// are left alone; altered or invented kept regions are dropped, and missing sections are
+/// inserted after their original heading, at the top when they had none, or at the end
+/// when the heading no longer exists.
+pub fn merge_kept_sections(generated: &str, sections: &[KeptSection]) -> String {
+    let mut output = strip_unknown_regions(generated, sections);
+
+    for section in sections {
+        if output.contains(&section.content) {
+            continue;
+        }
+        let block = format!("{}\n\n", section.content);
+        match &section.heading {
+            None => output.insert_str(0, &block),
+            Some(heading) => match find_line(&output, heading) {
+                Some(line_end) => {
+                    let insert_at = skip_blank_lines(&output, line_end);
+                    output.insert_str(insert_at, &block);
+                }
+                None => {
+                    if !output.ends_with('\n') {
+                        output.push('\n');
+                    }
+                    output.push('\n');
+                    output.push_str(&section.content);
+                    output.push('\n');
+                }
+            },
+        }
+    }
+    output
+}
+
+/// Remove kept regions from `generated` whose text does not match any of `sections`
+fn strip_unknown_regions(generated: &str, sections: &[KeptSection]) -> String {
+    let Ok(regions) = extract_kept_sections(generated) else {
+        // Leave malformed model output as it is; the missing sections are still inserted
+        return generated.to_string();
+    };
+    let mut output = generated.to_string();
+    for region in regions {
+        if !sections.iter().any(|s| s.content == region.content) {
+            output = output.replacen(&format!("{}\n", region.content), "", 1);
+            output = output.replacen(&region.content, "", 1);
+        }
+    }
+    output
+}
+
+/// Byte offset just past the line equal to `line`, including its newline
+fn find_line(text: &str, line: &str) -> Option<usize> {
+    let mut offset = 0;
+    for candidate in text.split_inclusive('\n') {
+        offset += candidate.len();
+        if candidate.trim_end() == line {
+            return Some(offset);
+        }
+    }
+    None
+}
+
+/// Advance `offset` past blank lines so inserted blocks follow the heading's spacing
+fn skip_blank_lines(text: &str, mut offset: usize) -> usize {
+    for line in text[offset..].split_inclusive('\n') {
+        if !line.trim().is_empty() {
+            break;
+        }
+        offset += line.len();
+    }
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const README: &str = "\
+<!-- techdocs:keep:start -->
+Top banner
+<!-- techdocs:keep:end -->
+
+# Project
+
+## Support
+
+<!-- techdocs:keep:start -->
+Call the on-call engineer.
+<!-- techdocs:keep:end -->
+";
+
+    fn kept(content: &str, heading: Option<&str>) -> KeptSection {
+        KeptSection {
+            content: content.to_string(),
+            heading: heading.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn kept_sections_are_found_with_their_headings() {
+        let sections = extract_kept_sections(README).unwrap();
+        assert_eq!(
+            sections,
+            vec![
+                kept("<!-- techdocs:keep:start -->\nTop banner\n<!-- techdocs:keep:end -->", None),
+                kept(
+                    "<!-- techdocs:keep:start -->\nCall the on-call engineer.\n<!-- techdocs:keep:end -->",
+                    Some("## Support")
+                ),
+            ]
+        );
+        assert!(extract_kept_sections("# Title\n\ntext\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn unbalanced_markers_are_rejected() {
+        let cases = [
+            (format!("{}\n{}\n{}\n", KEEP_START, KEEP_START, KEEP_END), "line 2 opens a kept section inside the one opened on line 1"),
+            (format!("text\n{}\n", KEEP_END), "line 2 closes a kept section that was never opened"),
+            (format!("# A\n{}\ntext\n", KEEP_START), "the kept section opened on line 2 is never closed"),
+        ];
+        for (readme, detail) in cases {
+            let error = extract_kept_sections(&readme).unwrap_err();
+            assert!(
+                matches!(&error, TechDocsError::Output(message) if message.ends_with(detail)),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn instructions_list_each_section_under_its_heading() {
+        let sections = extract_kept_sections(README).unwrap();
+        let instructions = preserve_instructions(&sections);
+        assert!(instructions.contains("must be preserved verbatim"));
+        assert!(instructions.contains("Under heading: (top of the document)\n<!-- techdocs:keep:start -->\nTop banner\n"));
+        assert!(instructions.contains("Under heading: ## Support\n<!-- techdocs:keep:start -->\nCall the on-call"));
+    }
+
+    #[test]
+    fn reproduced_sections_are_left_alone() {
+        let sections = extract_kept_sections(README).unwrap();
+        assert_eq!(merge_kept_sections(README, &sections), README);
+    }
+
+    #[test]
+    fn missing_sections_are_put_back_in_place() {
+        let sections = extract_kept_sections(README).unwrap();
+        let generated = "# Project\n\nNew intro.\n\n## Support\n\nOpen an issue.\n";
+        assert_eq!(
+            merge_kept_sections(generated, &sections),
+            "\
+<!-- techdocs:keep:start -->
+Top banner
+<!-- techdocs:keep:end -->
+
+# Project
+
+New intro.
+
+## Support
+
+<!-- techdocs:keep:start -->
+Call the on-call engineer.
+<!-- techdocs:keep:end -->
+
+Open an issue.
+"
+        );
+    }
+
+    #[test]
+    fn sections_whose_heading_is_gone_are_appended() {
+        let sections = vec![kept(
+            "<!-- techdocs:keep:start -->\nCall the on-call engineer.\n<!-- techdocs:keep:end -->",
+            Some("## Support"),
+        )];
+        assert_eq!(
+            merge_kept_sections("# Project\n\nIntro.", &sections),
+            "# Project\n\nIntro.\n\n<!-- techdocs:keep:start -->\nCall the on-call engineer.\n<!-- techdocs:keep:end -->\n"
+        );
+    }
+
+    #[test]
+    fn altered_regions_are_replaced_with_the_original() {
+        let sections = extract_kept_sections(README).unwrap();
+        let generated = "\
+# Project
+
+## Support
+
+<!-- techdocs:keep:start -->
+Call the on-call engineer, or email ops.
+<!-- techdocs:keep:end -->
+Open an issue.
+";
+        let merged = merge_kept_sections(generated, &sections);
+        assert!(!merged.contains("email ops"), "{}", merged);
+        assert!(merged.contains(&sections[1].content), "{}", merged);
+        assert!(merged.starts_with(&sections[0].content), "{}", merged);
+        assert_eq!(extract_kept_sections(&merged).unwrap(), sections);
+    }
+}
@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+
+/// Languages README generation knows by name, as (code, English name)
+pub const LANGUAGES: &[(&str, &str)] = &[
+    ("ar", "Arabic"),
+    ("bn", "Bengali"),
+    ("cs", "Czech"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fa", "Persian"),
+    ("fi", "Finnish"),
+    ("fr", "French"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("hu", "Hungarian"),
+    ("id", "Indonesian"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("no", "Norwegian"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("pt-BR", "Brazilian Portuguese"),
+    ("ro", "Romanian"),
+    ("ru", "Russian"),
+    ("sv", "Swedish"),
+    ("th", "Thai"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("vi", "Vietnamese"),
+    ("zh", "Chinese"),
+    ("zh-CN", "Simplified Chinese"),
+    ("zh-TW", "Traditional Chinese"),
+];
+
+/// English name of a language code, matched case-insensitively
+pub fn language_name(code: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map(|(_, name)| *name)
+}
+
+/// Text appended to the system prompt asking for the README in `code`.
+/// Unknown codes are passed through for the model to interpret.
+pub fn language_instructions(code: &str) -> String {
+    let language = match language_name(code) {
+        Some(name) => format!("{} ({})", name, code),
+        None => format!("the language with code `{}`", code),
+    };
+    format!(
+        "\n\nWrite the entire README in {}. Keep code blocks, commands, file paths, \
+         identifiers, and HTML comments exactly as they are.",
+        language
+    )
+}
+
+/// `path` with the language code inserted before the extension: `README.md` becomes `README.ja.md`
+pub fn localized_path(path: &Path, code: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, code, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, code),
+    };
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_codes_are_matched_case_insensitively() {
+        assert_eq!(language_name("ja"), Some("Japanese"));
+        assert_eq!(language_name("JA"), Some("Japanese"));
+        assert_eq!(language_name("pt-br"), Some("Brazilian Portuguese"));
+        assert_eq!(language_name("zh-TW"), Some("Traditional Chinese"));
+        assert_eq!(language_name("xx"), None);
+        assert_eq!(language_name(""), None);
+    }
+
+    #[test]
+    fn instructions_name_the_language() {
+        let known = language_instructions("ja");
+        assert!(known.contains("Write the entire README in Japanese (ja)."), "{}", known);
+        assert!(known.contains("HTML comments exactly as they are"), "{}", known);
+
+        let unknown = language_instructions("tlh");
+        assert!(
+            unknown.contains("in the language with code `tlh`."),
+            "{}",
+            unknown
+        );
+    }
+
+    #[test]
+    fn localized_paths_insert_the_code_before_the_extension() {
+        assert_eq!(
+            localized_path(Path::new("README.md"), "ja"),
+            PathBuf::from("README.ja.md")
+        );
+        assert_eq!(
+            localized_path(Path::new("docs/README.md"), "pt-BR"),
+            PathBuf::from("docs/README.pt-BR.md")
+        );
+        assert_eq!(
+            localized_path(Path::new("README"), "de"),
+            PathBuf::from("README.de")
+        );
+    }
+}
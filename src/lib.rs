@@ -1,18 +1,186 @@
+//! Technical documentation generated from a project's files. The library chooses which
+//! files to send, assembles the prompt, and has a model write the document; the
+//! techdocs-cli and techdocs-api binaries are built on it.
+//!
+//! [`prelude`] has what most callers need. With the `git` and `claude` features, a README
+//! for a directory, written here by a stand-in for a real provider:
+//!
+//! ```
+//! use techdocs::prelude::*;
+//!
+//! struct Canned;
+//!
+//! #[async_trait::async_trait]
+//! impl LlmClient for Canned {
+//!     async fn complete(&self, request: &CompletionRequest) -> Result<CompletionResult> {
+//!         assert!(request.content.contains("main.rs"));
+//!         Ok(CompletionResult {
+//!             text: "# Hello\n\nPrints a greeting.".into(),
+//!             model: "canned".into(),
+//!             ..Default::default()
+//!         })
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let project = temp_dir::TempDir::new()?;
+//! std::fs::create_dir(project.child("src"))?;
+//! std::fs::write(project.child("src/main.rs"), "fn main() { println!(\"hello\"); }\n")?;
+//!
+//! let files = collect_files(project.path(), &[], 100)?;
+//! assert_eq!(files.len(), 1);
+//!
+//! let source = project.path().to_str().unwrap();
+//! let doc = generate_readme(source, &GenerateOptions::default(), &Canned).await?;
+//! assert_eq!(doc.content, "# Hello\n\nPrints a greeting.\n");
+//! assert_eq!(doc.stats.files_included, 1);
+//! assert_eq!(doc.provenance.source, source);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
-use std::io::{self, Read};
-use ignore::{WalkBuilder, overrides::OverrideBuilder};
+use std::io;
+use ignore::overrides::{Override, OverrideBuilder};
+#[cfg(feature = "git")]
 use url::Url;
-use git2::Repository;
+#[cfg(feature = "git")]
 use temp_dir::TempDir;
 use std::fs;
 use std::error::Error as StdError;
-use claude_client::claude::ClaudeClient;
+#[cfg(feature = "git")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "git")]
+use std::sync::Arc;
+#[cfg(feature = "git")]
+use tokio::sync::mpsc::UnboundedSender;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+#[cfg(feature = "claude")]
+use crate::claude::ClaudeClient;
+#[cfg(feature = "claude")]
+use crate::provider::{CompletionRequest, CompletionResult, GenerationProfile, LlmClient};
+use crate::config::Config;
+use crate::plan::{Normalize, PromptPlan};
+#[cfg(feature = "git")]
+use crate::progress::{no_progress, ProgressEvent, ProgressFn};
+#[cfg(feature = "server")]
+use crate::workspace::{Workspace, WorkspaceDir};
+
+#[cfg(all(feature = "claude", feature = "rust-api"))]
+pub mod architecture;
+#[cfg(feature = "server")]
+pub mod archive;
+#[cfg(feature = "git")]
+pub mod badges;
+#[cfg(all(feature = "git", feature = "claude"))]
+pub mod batch;
+#[cfg(feature = "server")]
+pub mod cache;
+#[cfg(feature = "server")]
+pub mod callback;
+#[cfg(feature = "git")]
+pub mod changelog;
+#[cfg(feature = "claude")]
+pub mod claude;
+#[cfg(feature = "cli")]
+pub mod clipboard;
+#[cfg(all(feature = "git", feature = "claude"))]
+pub mod commit_message;
+pub mod config;
+#[cfg(feature = "claude")]
+pub mod contributing;
+pub mod diff;
+pub mod directives;
+#[cfg(feature = "claude")]
+pub mod docs;
+#[cfg(feature = "server")]
+pub mod documents;
+#[cfg(feature = "git")]
+pub mod download;
+#[cfg(any(feature = "cli", feature = "server"))]
+pub mod doctor;
+pub mod filters;
+#[cfg(all(feature = "git", feature = "claude"))]
+pub mod generate;
+#[cfg(feature = "git")]
+pub mod git;
+#[cfg(feature = "claude")]
+pub mod github;
+#[cfg(feature = "cli")]
+pub mod incremental;
+pub mod infra;
+#[cfg(feature = "server")]
+pub mod jobs;
+pub mod keep;
+pub mod lang;
+pub mod license;
+#[cfg(any(feature = "cli", feature = "server"))]
+pub mod logging;
+#[cfg(feature = "git")]
+pub mod manifest;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod monorepo;
+#[cfg(feature = "claude")]
+pub mod net;
+pub mod plan;
+#[cfg(feature = "cli")]
+pub mod plan_file;
+#[cfg(all(feature = "git", feature = "claude"))]
+pub mod postprocess;
+/// The types and entry points most programs using the library need, for
+/// `use techdocs::prelude::*`; what it holds depends on the enabled features
+pub mod prelude;
+#[cfg(feature = "cli")]
+pub mod preview;
+pub mod progress;
+pub mod project;
+#[cfg(feature = "claude")]
+pub mod provider;
+#[cfg(feature = "git")]
+pub mod release_notes;
+#[cfg(all(feature = "git", feature = "claude"))]
+pub mod review;
+#[cfg(feature = "rust-api")]
+pub mod rust_api;
+pub mod sections;
+#[cfg(feature = "claude")]
+pub mod summary;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod toc;
+pub mod tooling;
+#[cfg(feature = "claude")]
+pub mod tokens;
+pub mod validate;
+#[cfg(feature = "cli")]
+pub mod watch;
+#[cfg(feature = "server")]
+pub mod workspace;
 
+#[cfg(all(feature = "git", feature = "claude"))]
+pub use generate::{generate_readme, generate_readme_with_progress, GenerateOptions, GeneratedDoc};
+pub use plan::PromptOptions;
+#[cfg(any(feature = "git", feature = "claude"))]
+pub use tokio_util::sync::CancellationToken;
 
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum TechDocsError {
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
+    /// An IO failure on a known file, such as `failed to read src/main.rs: Permission denied`
+    #[error("failed to {action} {}: {source}", .path.display())]
+    File {
+        /// What was being done, as a verb: `read`, `open`, `create`
+        action: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[cfg(feature = "git")]
     #[error("Git error: {0}")]
     Git(#[from] git2::Error),
     #[error("Claude error: {0}")]
@@ -23,184 +191,1040 @@ pub enum TechDocsError {
     Ignore(#[from] ignore::Error),
     #[error("Claude client error: {0}")]
     ClaudeClient(String),
+    #[error("Rate limited by Claude: {0}")]
+    RateLimited(String),
+    #[error("Output error: {0}")]
+    Output(String),
+    #[error("Config error: {0}")]
+    Config(String),
+    #[error("Budget exceeded: {0}")]
+    Budget(String),
+    /// The next provider call would take a run's spending past its cost limit
+    #[error("Cost limit exceeded: ${spent:.4} spent of a ${limit:.4} limit")]
+    CostLimitExceeded {
+        /// USD spent by the run's calls so far
+        spent: f64,
+        limit: f64,
+    },
+    #[error("Workspace full: {0}")]
+    Quota(String),
+    #[error("GitHub error: {0}")]
+    GitHub(String),
+    #[error("Invalid archive: {0}")]
+    Archive(String),
+    /// Generated output that failed validation in strict mode
+    #[error("Invalid output: {0}")]
+    Validation(String),
+    /// A file listed in a reviewed plan is missing or no longer matches its hash
+    #[error("Files changed since the plan was written: {0}")]
+    PlanChanged(String),
+    /// The source is too large to walk within the configured ceilings
+    #[error("Source too large: {0}")]
+    SourceTooLarge(String),
+    #[error("Cancelled")]
+    Cancelled,
     #[error("{0}")]
     Other(#[from] Box<dyn StdError>),
 }
 
 pub type Result<T> = std::result::Result<T, TechDocsError>;
 
-/// Resolve a path or GitHub URL to a local directory path
+/// Name the file an IO operation was working on in its error
+pub(crate) trait FileContext<T> {
+    /// Turn an IO error into [`TechDocsError::File`] for `action` on `path`
+    fn file_context(self, action: &'static str, path: &Path) -> Result<T>;
+}
+
+impl<T> FileContext<T> for io::Result<T> {
+    fn file_context(self, action: &'static str, path: &Path) -> Result<T> {
+        self.map_err(|source| TechDocsError::File {
+            action,
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// Default system prompt for README generation, embedded at compile time
+pub const DEFAULT_README_PROMPT: &str = include_str!("../prompts/readme.txt");
+
+/// Load the README system prompt from `prompt_file`, falling back to the embedded default
+pub fn load_readme_prompt(prompt_file: Option<&Path>) -> Result<String> {
+    match prompt_file {
+        Some(path) => {
+            fs::read_to_string(path).file_context("read prompt file", path)
+        }
+        None => Ok(DEFAULT_README_PROMPT.to_string()),
+    }
+}
+
+/// Load every `.txt` file in `dir` as a prompt template named after the file, so
+/// `readme-minimal.txt` becomes `readme-minimal`
+pub fn load_prompt_templates(dir: &Path) -> Result<BTreeMap<String, String>> {
+    let entries = fs::read_dir(dir).map_err(|e| {
+        TechDocsError::Config(format!("cannot read prompt directory {}: {}", dir.display(), e))
+    })?;
+    let mut templates = BTreeMap::new();
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_file() || path.extension().is_none_or(|ext| ext != "txt") {
+            continue;
+        }
+        if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            templates.insert(name.to_string(), fs::read_to_string(&path).file_context("read", &path)?);
+        }
+    }
+    Ok(templates)
+}
+
+/// A path or URL as typed, cleaned of what shells and copy-paste leave around it:
+/// surrounding whitespace and newlines, and one pair of matching quotes. Fails for
+/// input with nothing left.
+pub fn normalize_source(path_or_url: &str) -> Result<&str> {
+    let trimmed = path_or_url.trim();
+    let unquoted = ['"', '\'']
+        .iter()
+        .find_map(|&quote| trimmed.strip_prefix(quote)?.strip_suffix(quote))
+        .map_or(trimmed, str::trim);
+    if unquoted.is_empty() {
+        return Err(TechDocsError::Url("empty source; give a directory, a GitHub repository or gist URL, or a file URL".into()));
+    }
+    Ok(unquoted)
+}
+
+#[cfg(feature = "git")]
+/// Resolve a path or URL to a local directory path. GitHub repositories and gists are
+/// cloned; a URL to a single text file is downloaded into a directory of its own.
 pub async fn resolve_path(path_or_url: &str) -> Result<(PathBuf, Option<TempDir>)> {
-    // Check if the input is a URL
-    if let Ok(url) = Url::parse(path_or_url) {
-        if url.scheme() == "https" && url.host_str() == Some("github.com") {
-            // Create a temporary directory
-            let temp_dir = TempDir::new()?;
-            let temp_path = temp_dir.path().to_path_buf();
+    resolve_path_with_progress(path_or_url, &no_progress).await
+}
 
-            // Clone the repository
-            Repository::clone(path_or_url, &temp_path)?;
+#[cfg(feature = "git")]
+/// Resolve a path or GitHub URL to a local directory path, reporting clone progress.
+///
+/// The clone runs on a blocking thread. Dropping the returned future, as a timeout
+/// does, stops the clone at its next progress report and removes the partial clone.
+pub async fn resolve_path_with_progress(
+    path_or_url: &str,
+    progress: ProgressFn<'_>,
+) -> Result<(PathBuf, Option<TempDir>)> {
+    resolve_path_cancellable(path_or_url, progress, &CancellationToken::new()).await
+}
 
-            Ok((temp_path, Some(temp_dir)))
-        } else {
-            Err(TechDocsError::Url("Only GitHub URLs are supported".into()))
+#[cfg(feature = "git")]
+/// Like [`resolve_path_with_progress`], stopping the clone and failing with
+/// [`TechDocsError::Cancelled`] once `cancel` is cancelled
+pub async fn resolve_path_cancellable(
+    path_or_url: &str,
+    progress: ProgressFn<'_>,
+    cancel: &CancellationToken,
+) -> Result<(PathBuf, Option<TempDir>)> {
+    let path_or_url = normalize_source(path_or_url)?;
+    let source = remote_source(path_or_url)?;
+    match source {
+        Some(RemoteSource::Repository(url)) => {
+            tracing::info!("Cloning {}", url);
+            let temp_dir = TempDir::new().file_context("create a directory in", &std::env::temp_dir())?;
+            let clone = clone_repository(&url, temp_dir, progress, |_| true);
+            let temp_dir = cancel.run_until_cancelled(clone).await.ok_or(TechDocsError::Cancelled)??;
+            Ok((temp_dir.path().to_path_buf(), Some(temp_dir)))
         }
-    } else {
-        // It's a local path
-        Ok((PathBuf::from(path_or_url), None))
+        #[cfg(feature = "claude")]
+        Some(RemoteSource::File(url)) => {
+            tracing::info!("Downloading {}", url);
+            let temp_dir = TempDir::new().file_context("create a directory in", &std::env::temp_dir())?;
+            let download = download::download_file(&url, temp_dir.path());
+            cancel.run_until_cancelled(download).await.ok_or(TechDocsError::Cancelled)??;
+            Ok((temp_dir.path().to_path_buf(), Some(temp_dir)))
+        }
+        #[cfg(not(feature = "claude"))]
+        Some(RemoteSource::File(url)) => {
+            Err(TechDocsError::Url(format!("cannot download {}: built without the claude feature", url)))
+        }
+        None => Ok((PathBuf::from(path_or_url), None)),
+    }
+}
+
+#[cfg(feature = "git")]
+/// Whether [`resolve_path`] fetches `path_or_url` into a temporary directory, which is
+/// deleted on exit, rather than reading a local directory
+pub fn is_remote(path_or_url: &str) -> bool {
+    normalize_source(path_or_url).and_then(remote_source).is_ok_and(|source| source.is_some())
+}
+
+#[cfg(feature = "git")]
+/// What a URL source is fetched as
+enum RemoteSource<'a> {
+    /// A repository or gist, cloned from this URL
+    Repository(std::borrow::Cow<'a, str>),
+    /// A single text file, downloaded
+    File(Url),
+}
+
+#[cfg(feature = "git")]
+/// What `path_or_url` is fetched as: `None` for a local path, or an error for a URL
+/// that is none of a GitHub repository, a gist, or a text file over https
+fn remote_source(path_or_url: &str) -> Result<Option<RemoteSource<'_>>> {
+    let Ok(url) = Url::parse(path_or_url) else {
+        return Ok(None);
+    };
+    let unsupported = || {
+        TechDocsError::Url("Only GitHub repository, gist, and https text file URLs are supported".into())
+    };
+    if url.scheme() != "https" {
+        return Err(unsupported());
+    }
+    match url.host_str() {
+        Some("github.com") => Ok(Some(RemoteSource::Repository(path_or_url.into()))),
+        // gist.github.com/<id> or gist.github.com/<owner>/<id>
+        Some("gist.github.com") => {
+            let id = url
+                .path_segments()
+                .and_then(|mut segments| segments.rfind(|segment| !segment.is_empty()))
+                .map(|segment| segment.trim_end_matches(".git"))
+                .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric()))
+                .ok_or_else(|| TechDocsError::Url(format!("{} does not name a gist", path_or_url)))?;
+            Ok(Some(RemoteSource::Repository(format!("https://gist.github.com/{}.git", id).into())))
+        }
+        _ if download::is_file_url(&url) => Ok(Some(RemoteSource::File(url))),
+        _ => Err(unsupported()),
+    }
+}
+
+#[cfg(feature = "git")]
+/// Clone `url` into `dir` on a blocking thread, reporting transfer progress. `keep_going`
+/// sees every transfer report and stops the clone by returning false, as does dropping
+/// the returned future. The blocking thread owns `dir` until the clone stops, so a
+/// cancelled clone is removed only after it stops writing.
+pub(crate) async fn clone_repository<D, F>(url: &str, dir: D, progress: ProgressFn<'_>, keep_going: F) -> Result<D>
+where
+    D: AsRef<Path> + Send + 'static,
+    F: Fn(&git2::Progress<'_>) -> bool + Send + 'static,
+{
+    #[cfg(feature = "metrics")]
+    let started = std::time::Instant::now();
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let cancel = CancelClone::default();
+    let cancelled = Arc::clone(&cancel.0);
+    let url = url.to_string();
+    let clone = tokio::task::spawn_blocking(move || {
+        let cloned = clone_into(&url, dir.as_ref(), &sender, |stats| {
+            !cancelled.load(Ordering::Relaxed) && keep_going(stats)
+        });
+        (dir, cloned)
+    });
+    // Ends when the clone finishes and drops the sender
+    while let Some(event) = receiver.recv().await {
+        progress(&event);
+    }
+    let (dir, cloned) = clone
+        .await
+        .map_err(|e| TechDocsError::Other(format!("clone task failed: {}", e).into()))?;
+    cloned?;
+    #[cfg(feature = "metrics")]
+    metrics::record_histogram(metrics::CLONE_SECONDS, &[], started.elapsed().as_secs_f64());
+    Ok(dir)
+}
+
+#[cfg(feature = "git")]
+/// Asks a running clone to stop when the future waiting for it is dropped
+#[derive(Default)]
+struct CancelClone(Arc<AtomicBool>);
+
+#[cfg(feature = "git")]
+impl Drop for CancelClone {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "git")]
+/// Clone `url` into `dir`, sending transfer progress to `progress`. The transfer is
+/// aborted as soon as `keep_going` returns false.
+fn clone_into(
+    url: &str,
+    dir: &Path,
+    progress: &UnboundedSender<ProgressEvent>,
+    keep_going: impl Fn(&git2::Progress<'_>) -> bool,
+) -> std::result::Result<(), git2::Error> {
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.transfer_progress(|stats| {
+        let _ = progress.send(ProgressEvent::Clone {
+            received_objects: stats.received_objects(),
+            total_objects: stats.total_objects(),
+            received_bytes: stats.received_bytes(),
+        });
+        // Returning false aborts the transfer
+        keep_going(&stats)
+    });
+    let mut fetch_options = git2::FetchOptions::new();
+    fetch_options.remote_callbacks(callbacks);
+    git2::build::RepoBuilder::new()
+        .fetch_options(fetch_options)
+        .clone(url, dir)?;
+    Ok(())
+}
+
+#[cfg(feature = "server")]
+/// A cloned repository, deleted from disk when this is dropped
+pub enum Checkout {
+    Temp(TempDir),
+    Workspace(WorkspaceDir),
+}
+
+#[cfg(feature = "server")]
+/// Resolve a path or GitHub URL like [`resolve_path_with_progress`], then check out
+/// `revision` (a commit, branch, or tag) in the clone. Local directories are never
+/// checked out, so a revision is only accepted with a URL. With a `workspace`, the
+/// clone is made there and counts against its quota. Cancelling `cancel` stops the clone.
+pub async fn resolve_path_at(
+    path_or_url: &str,
+    revision: Option<&str>,
+    workspace: Option<&Arc<Workspace>>,
+    progress: ProgressFn<'_>,
+    cancel: &CancellationToken,
+) -> Result<(PathBuf, Option<Checkout>)> {
+    let path_or_url = normalize_source(path_or_url)?;
+    let repository = match remote_source(path_or_url)? {
+        Some(RemoteSource::Repository(url)) => Some(url),
+        _ => None,
+    };
+    let (path, checkout) = match (&repository, workspace) {
+        (Some(url), Some(workspace)) => {
+            let clone = workspace.clone_repository(url, progress);
+            let dir = cancel.run_until_cancelled(clone).await.ok_or(TechDocsError::Cancelled)??;
+            (dir.path().to_path_buf(), Some(Checkout::Workspace(dir)))
+        }
+        _ => {
+            let (path, temp_dir) = resolve_path_cancellable(path_or_url, progress, cancel).await?;
+            (path, temp_dir.map(Checkout::Temp))
+        }
+    };
+    if let Some(revision) = revision {
+        if repository.is_none() {
+            return Err(TechDocsError::Config(format!(
+                "cannot check out {} in a local directory or downloaded file; only repository URLs take a revision",
+                revision
+            )));
+        }
+        let repo = git2::Repository::open(&path)?;
+        let commit = repo.revparse_single(revision)?.peel_to_commit()?;
+        repo.checkout_tree(commit.as_object(), Some(git2::build::CheckoutBuilder::new().force()))?;
+        repo.set_head_detached(commit.id())?;
     }
+    Ok((path, checkout))
 }
 
-pub fn validate_directory(path: &Path) -> io::Result<()> {
+/// Check that `path` is a directory that can be listed
+pub fn validate_directory(path: &Path) -> Result<()> {
+    inspect_directory(path, DirCheck::Readable).map(drop)
+}
+
+/// How much [`inspect_directory`] checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirCheck {
+    /// Only that the directory exists and can be listed
+    Readable,
+    /// Also whether it is writable and inside a git work tree
+    Capabilities,
+}
+
+/// What a project directory allows, from [`inspect_directory`]. Fields left `None`
+/// were not checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirInfo {
+    /// Whether files can be created in it
+    pub writable: Option<bool>,
+    /// Whether it is inside a git work tree
+    pub git_work_tree: Option<bool>,
+}
+
+/// Check that `path` is a directory that can be listed and, with
+/// [`DirCheck::Capabilities`], what else it allows. Writability is tested by creating
+/// and removing a file, since permission bits say nothing of ACLs, read-only mounts,
+/// or running as root.
+pub fn inspect_directory(path: &Path, check: DirCheck) -> Result<DirInfo> {
     if !path.exists() {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Path does not exist"
-        ));
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no such directory")).file_context("open", path);
     }
     if !path.is_dir() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "Path is not a directory"
-        ));
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a directory")).file_context("open", path);
     }
-    Ok(())
+    fs::read_dir(path).file_context("read", path)?;
+    if check == DirCheck::Readable {
+        return Ok(DirInfo::default());
+    }
+    // The ancestors of a relative path such as `.` stop at the current directory, so
+    // walk up from the real path to find a `.git` above it
+    let real = fs::canonicalize(path).file_context("resolve", path)?;
+    Ok(DirInfo {
+        writable: Some(is_writable(path)),
+        git_work_tree: Some(real.ancestors().any(|dir| dir.join(".git").exists())),
+    })
 }
 
-pub fn is_build_executable(file_name: &str) -> bool {
-    let build_executables = [
-        "target", "node_modules", "build", "dist", "out", "bin",
-        "Debug", "Release", ".git", ".idea", ".vscode"
-    ];
-    build_executables.iter().any(|&x| file_name.contains(x))
+fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".techdocs-write-check-{}", std::process::id()));
+    match fs::OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            true
+        }
+        Err(e) => {
+            tracing::debug!("{} is not writable: {}", dir.display(), e);
+            false
+        }
+    }
+}
+
+/// Build walker overrides that exclude files matching `exclude_patterns`.
+/// Override globs whitelist by default, so each pattern is negated with `!`;
+/// patterns that already start with `!` are used as given.
+pub(crate) fn exclude_overrides(dir: &Path, exclude_patterns: &[String]) -> Result<Override> {
+    let mut override_builder = OverrideBuilder::new(dir);
+    for pattern in exclude_patterns {
+        if pattern.starts_with('!') {
+            override_builder.add(pattern)?;
+        } else {
+            override_builder.add(&format!("!{}", pattern))?;
+        }
+    }
+    Ok(override_builder.build()?)
 }
 
 /// Format file contents for LLM consumption, including language detection
 pub fn format_file_content(path: &Path, content: &str) -> String {
-    let extension = path.extension()
+    format_code_block(code_language(path), content)
+}
+
+/// Language tag for a file's code fence: its extension, or `txt` without one
+pub fn code_language(path: &Path) -> &str {
+    path.extension()
         .and_then(|e| e.to_str())
-        .unwrap_or("txt");
-    
-    format!("```{}\n{}\n```", extension, content)
+        .unwrap_or("txt")
+}
+
+/// Wrap content in a fenced code block tagged with `language`
+pub fn format_code_block(language: &str, content: &str) -> String {
+    format!("```{}\n{}\n```", language, content)
+}
+
+/// Write what [`format_file_content`] returns for `content` straight to `writer`.
+/// Valid UTF-8 is written as it is; only invalid sequences cost a lossy copy.
+pub(crate) fn write_file_content<W: io::Write>(writer: &mut W, path: &Path, content: &[u8]) -> io::Result<()> {
+    writer.write_all(b"```")?;
+    writer.write_all(code_language(path).as_bytes())?;
+    writer.write_all(b"\n")?;
+    match std::str::from_utf8(content) {
+        Ok(_) => writer.write_all(content)?,
+        Err(_) => writer.write_all(String::from_utf8_lossy(content).as_bytes())?,
+    }
+    writer.write_all(b"\n```")
+}
+
+/// `content` without what editors on some platforms leave in it: a leading UTF-8 byte
+/// order mark is dropped and CRLF and lone CR line endings become LF. With
+/// `trim_trailing_whitespace`, spaces and tabs at the end of each line go too.
+pub fn normalize_content(content: &[u8], trim_trailing_whitespace: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(content.len());
+    normalize_content_into(content, trim_trailing_whitespace, &mut out);
+    out
+}
+
+/// [`normalize_content`] into `out`, replacing what it held
+pub(crate) fn normalize_content_into(content: &[u8], trim_trailing_whitespace: bool, out: &mut Vec<u8>) {
+    let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(content);
+    let trim = |out: &mut Vec<u8>| {
+        while trim_trailing_whitespace && matches!(out.last(), Some(b' ' | b'\t')) {
+            out.pop();
+        }
+    };
+    out.clear();
+    out.reserve(content.len());
+    let mut bytes = content.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'\r' | b'\n' => {
+                if byte == b'\r' {
+                    bytes.next_if_eq(&&b'\n');
+                }
+                trim(out);
+                out.push(b'\n');
+            }
+            _ => out.push(byte),
+        }
+    }
+    trim(out);
+}
+
+/// Narrowest column [`number_lines`] right-aligns line numbers in
+const LINE_NUMBER_MIN_WIDTH: usize = 4;
+
+/// `content` with each line prefixed by its number, right-aligned, and ` | `, as in
+/// `  42 | fn main() {`. A `gap` of `(line, omitted)` says the line after line `line`
+/// stands in for `omitted` lines that were left out: it gets no number, and numbering
+/// resumes after the gap.
+pub fn number_lines(content: &[u8], gap: Option<(usize, usize)>) -> Vec<u8> {
+    let mut out = Vec::new();
+    number_lines_into(content, gap, &mut out);
+    out
+}
+
+/// [`number_lines`] appended to `out`
+pub(crate) fn number_lines_into(content: &[u8], gap: Option<(usize, usize)>, out: &mut Vec<u8>) {
+    use io::Write;
+
+    let body = content.strip_suffix(b"\n").unwrap_or(content);
+    let lines = if content.is_empty() { 0 } else { body.iter().filter(|&&b| b == b'\n').count() + 1 };
+    let number = |index: usize| match gap {
+        Some((line, _)) if index < line => Some(index + 1),
+        Some((line, _)) if index == line => None,
+        Some((_, omitted)) => Some(index + omitted),
+        None => Some(index + 1),
+    };
+    let last = (0..lines).rev().find_map(number).unwrap_or(0);
+    let width = last.checked_ilog10().map_or(1, |digits| digits as usize + 1).max(LINE_NUMBER_MIN_WIDTH);
+
+    out.reserve(content.len() + lines * (width + 3));
+    for (index, line) in body.split(|&b| b == b'\n').take(lines).enumerate() {
+        // Writing to a Vec cannot fail
+        let _ = match number(index) {
+            Some(number) => write!(out, "{:>width$} | ", number),
+            None => write!(out, "{:width$} | ", ""),
+        };
+        out.extend_from_slice(line);
+        if index + 1 < lines || content.ends_with(b"\n") {
+            out.push(b'\n');
+        }
+    }
+}
+
+/// A file selected for inclusion in a prompt
+#[derive(Debug, Clone)]
+pub struct CollectedFile {
+    /// Path to the file, including the walked directory
+    pub path: PathBuf,
+    /// Size of the file in bytes
+    pub size: u64,
+}
+
+/// Collect the files under `dir` that pass the ignore rules, exclude patterns,
+/// default filter preset, and per-file size limit, in walk order
+pub fn collect_files(
+    dir: &Path,
+    exclude_patterns: &[String],
+    max_file_size_kb: u64,
+) -> Result<Vec<CollectedFile>> {
+    let options = PromptOptions::unlimited()
+        .exclude(exclude_patterns)
+        .max_file_kb(max_file_size_kb)
+        .normalize(Normalize::Off);
+    collected_files(dir, &options)
+}
+
+/// [`collect_files`] with the exclude and include patterns, filter preset, and per-file
+/// size limit of a loaded configuration
+pub fn collect_config_files(dir: &Path, config: &Config) -> Result<Vec<CollectedFile>> {
+    let options = PromptOptions::from_config(config).max_total_mb(u64::MAX).normalize(Normalize::Off);
+    collected_files(dir, &options)
+}
+
+fn collected_files(dir: &Path, options: &PromptOptions) -> Result<Vec<CollectedFile>> {
+    Ok(PromptPlan::collect(dir, options)?
+        .included()
+        .map(|f| CollectedFile {
+            path: f.path.clone(),
+            size: f.size,
+        })
+        .collect())
+}
+
+/// Read a file as UTF-8, replacing invalid sequences
+pub fn read_file_lossy(path: &Path) -> Result<String> {
+    let content = fs::read(path).file_context("read", path)?;
+    // Valid UTF-8 becomes the String without a copy
+    Ok(String::from_utf8(content).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()))
+}
+
+/// Read a file into `buffer`, replacing what it held, so one allocation can serve many
+/// files
+pub(crate) fn read_file_into(path: &Path, buffer: &mut Vec<u8>) -> Result<()> {
+    buffer.clear();
+    fs::File::open(path)
+        .and_then(|mut file| io::Read::read_to_end(&mut file, buffer))
+        .file_context("read", path)?;
+    Ok(())
+}
+
+/// Write the files under `dir` that `options` lets in, in the format prompts use
+pub fn write_files_prompt<W: io::Write>(dir: &Path, options: &PromptOptions, writer: W) -> Result<()> {
+    PromptPlan::collect(dir, options)?.write_prompt(writer)
+}
+
+/// [`write_files_prompt`] for async writers. The walk only measures the files; their
+/// content is read again with `tokio::fs` and streamed to `writer` one at a time.
+#[cfg(any(feature = "git", feature = "claude"))]
+pub async fn write_files_prompt_async<W: tokio::io::AsyncWrite + Unpin>(
+    dir: &Path,
+    options: &PromptOptions,
+    writer: W,
+) -> Result<()> {
+    let plan = PromptPlan::collect(dir, options)?;
+    plan.write_prompt_async(writer).await
 }
 
 /// List files in a format suitable for prompts
+#[deprecated(since = "0.1.0", note = "use `write_files_prompt` with `PromptOptions`")]
 pub fn list_files_prompt<W: io::Write>(
     dir: &Path,
     exclude_patterns: &[String],
     max_file_size_kb: u64,
     max_total_size_mb: u64,
-    mut writer: W,
+    writer: W,
 ) -> Result<()> {
-    let mut override_builder = OverrideBuilder::new(dir);
-    for pattern in exclude_patterns {
-        override_builder.add(pattern)?;
+    let options = PromptOptions::new()
+        .exclude(exclude_patterns)
+        .max_file_kb(max_file_size_kb)
+        .max_total_mb(max_total_size_mb)
+        .normalize(Normalize::Off);
+    write_files_prompt(dir, &options, writer)
+}
+
+/// Line separating the system prompt from the file listing in dry-run output
+pub const DRY_RUN_DELIMITER: &str = "===== END OF SYSTEM PROMPT =====";
+
+/// Render a prompt the way `--dry-run` prints it instead of sending it to Claude
+pub fn render_dry_run(system_prompt: &str, content: &str) -> String {
+    format!("{}\n{}\n{}", system_prompt.trim_end(), DRY_RUN_DELIMITER, content)
+}
+
+/// List files in the directory
+pub fn list_files(dir: &Path, exclude_patterns: &[String]) -> Result<()> {
+    let plan = PromptPlan::collect(dir, &PromptOptions::unlimited().exclude(exclude_patterns).normalize(Normalize::Off))?;
+    for file in plan.included() {
+        println!("{}", file.path.display());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "claude")]
+/// Send a system prompt and content to Claude and return the generated document
+pub async fn generate_document(
+    system_prompt: &str,
+    content: &str,
+    model: Option<&str>,
+) -> Result<String> {
+    // Initialize Claude client
+    let client = ClaudeClient::new()?;
+    
+    // Send request to Claude
+    let document = client
+        .send_message(
+            model,
+            system_prompt,
+            content
+        )
+        .await?;
+    
+    Ok(document)
+}
+
+#[cfg(feature = "claude")]
+/// [`generate_document`] with the stop sequences and length of `profile`, returning
+/// the whole result so callers can tell why generation stopped
+pub async fn generate_document_with_profile(
+    system_prompt: &str,
+    content: &str,
+    model: Option<&str>,
+    profile: &GenerationProfile,
+) -> Result<CompletionResult> {
+    let request = CompletionRequest {
+        model: model.map(String::from),
+        ..CompletionRequest::new(system_prompt, content)
     }
-    let overrides = override_builder.build()?;
+    .with_profile(profile);
+    ClaudeClient::new()?.complete(&request).await
+}
 
-    let max_file_size = max_file_size_kb * 1024;
-    let max_total_size = max_total_size_mb * 1024 * 1024;
-    let mut total_size = 0;
+/// Result of writing a generated document to disk
+#[derive(Debug)]
+pub struct WriteOutcome {
+    /// Where the document was written
+    pub path: PathBuf,
+    /// Number of bytes written
+    pub bytes: usize,
+    /// Where the previous file was backed up to, if one was replaced
+    pub backup: Option<PathBuf>,
+}
 
-    let walker = WalkBuilder::new(dir)
-        .standard_filters(true)
-        .overrides(overrides)
-        .build();
+/// Write content to a file atomically by writing a sibling temp file and renaming it over the target
+pub fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    write_atomic_bytes(path, content.as_bytes())
+}
 
-    for entry in walker {
-        let entry = entry?;
-        let path = entry.path();
+/// Like [`write_atomic`], for binary content such as gzip output
+pub fn write_atomic_bytes(path: &Path, content: &[u8]) -> Result<()> {
+    let file_name = path.file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| TechDocsError::Output(format!("Invalid output path: {}", path.display())))?;
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let temp_path = parent.join(format!(".{}.techdocs-{}.tmp", file_name, std::process::id()));
 
-        if path.is_file() {
-            let file_name = path.file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("");
+    let result = (|| -> io::Result<()> {
+        let mut file = fs::File::create(&temp_path)?;
+        io::Write::write_all(&mut file, content)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+    })();
 
-            if is_build_executable(file_name) {
-                continue;
-            }
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(TechDocsError::Output(format!("Failed to write {}: {}", path.display(), e)));
+    }
+    Ok(())
+}
 
-            let metadata = entry.metadata()?;
-            let file_size = metadata.len();
+/// Append content to a file, creating it if needed. Unlike [`write_atomic`], a failure
+/// part way through can leave a partial write at the end of the file.
+pub fn append_to_file(path: &Path, content: &[u8]) -> Result<()> {
+    let result = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| {
+            io::Write::write_all(&mut file, content)?;
+            file.sync_all()
+        });
+    result.map_err(|e| TechDocsError::Output(format!("Failed to append to {}: {}", path.display(), e)))
+}
 
-            if file_size > max_file_size {
-                continue;
-            }
+/// Compress content as a single gzip member. Members can be concatenated, so gzip
+/// output can be appended to an existing `.gz` file.
+pub fn gzip_bytes(content: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    io::Write::write_all(&mut encoder, content)?;
+    Ok(encoder.finish()?)
+}
+
+/// Return the README.md path inside `dir`, failing if one already exists and `force` is not set
+pub fn ensure_readme_writable(dir: &Path, force: bool) -> Result<PathBuf> {
+    let readme_path = dir.join("README.md");
+    ensure_writable(&readme_path, force)?;
+    Ok(readme_path)
+}
+
+/// Fail if `path` already exists and `force` is not set
+pub fn ensure_writable(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        return Err(TechDocsError::Output(format!(
+            "{} already exists; pass --force to overwrite it (the old file is kept as {}.bak)",
+            path.display(),
+            path.file_name().unwrap_or_default().to_string_lossy()
+        )));
+    }
+    Ok(())
+}
+
+/// Write README.md into `dir`, backing up an existing README to README.md.bak when `force` is set
+pub fn write_readme(dir: &Path, content: &str, force: bool) -> Result<WriteOutcome> {
+    write_with_backup(&dir.join("README.md"), content, force)
+}
+
+/// Write `path` atomically, backing up an existing file to `<path>.bak` when `force` is set
+pub fn write_with_backup(path: &Path, content: &str, force: bool) -> Result<WriteOutcome> {
+    ensure_writable(path, force)?;
+
+    let backup = if path.exists() {
+        let mut backup_path = path.as_os_str().to_owned();
+        backup_path.push(".bak");
+        let backup_path = PathBuf::from(backup_path);
+        fs::copy(path, &backup_path).map_err(|e| {
+            TechDocsError::Output(format!("Failed to back up {}: {}", path.display(), e))
+        })?;
+        Some(backup_path)
+    } else {
+        None
+    };
 
-            if total_size + file_size > max_total_size {
-                writeln!(writer, "Warning: Total size limit reached, some files omitted.")?;
-                break;
+    write_atomic(path, content)?;
+
+    Ok(WriteOutcome {
+        path: path.to_path_buf(),
+        bytes: content.len(),
+        backup,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    #[test]
+    fn readme_prompt_defaults_to_the_embedded_text() {
+        assert!(!DEFAULT_README_PROMPT.trim().is_empty());
+        assert_eq!(load_readme_prompt(None).unwrap(), DEFAULT_README_PROMPT);
+    }
+
+    #[test]
+    fn readme_prompt_file_replaces_the_default() {
+        let tree = FixtureTree::new().file("prompt.txt", "Describe the project in one line.\n");
+        let prompt = load_readme_prompt(Some(&tree.join("prompt.txt"))).unwrap();
+        assert_eq!(prompt, "Describe the project in one line.\n");
+    }
+
+    #[test]
+    fn missing_readme_prompt_file_names_the_file() {
+        let tree = FixtureTree::new();
+        let missing = tree.join("missing.txt");
+        match load_readme_prompt(Some(&missing)) {
+            Err(TechDocsError::File { action, path, .. }) => {
+                assert_eq!(action, "read prompt file");
+                assert_eq!(path, missing);
             }
+            other => panic!("expected a file error, got {:?}", other),
+        }
+        let error = load_readme_prompt(Some(&missing)).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            format!("failed to read prompt file {}: No such file or directory (os error 2)", missing.display())
+        );
+    }
 
-            total_size += file_size;
+    #[test]
+    fn file_errors_name_the_action_and_path() {
+        let denied = || Err::<(), _>(io::Error::from(io::ErrorKind::PermissionDenied));
+        let error = denied().file_context("read", Path::new("src/secret.bin")).unwrap_err();
+        assert_eq!(error.to_string(), "failed to read src/secret.bin: permission denied");
+        let error = denied().file_context("write", Path::new("out/README.md")).unwrap_err();
+        assert_eq!(error.to_string(), "failed to write out/README.md: permission denied");
 
-            let mut content = Vec::new();
-            fs::File::open(path)?.read_to_end(&mut content)?;
-            let content_str = String::from_utf8_lossy(&content);
+        let tree = FixtureTree::new();
+        let nested = tree.join("missing/clone");
+        let error = fs::create_dir(&nested).file_context("create", &nested).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("failed to create {}: No such file or directory (os error 2)", nested.display())
+        );
+    }
 
-            writeln!(writer, "\nFile: {}", path.display())?;
-            writeln!(writer, "{}", format_file_content(path, &content_str))?;
+    #[cfg(feature = "git")]
+    #[test]
+    fn only_fetched_sources_are_remote() {
+        assert!(is_remote("https://github.com/username/repo"));
+        assert!(is_remote(" 'https://gist.github.com/username/0123abcd' "));
+        assert!(!is_remote("./project"));
+        assert!(!is_remote("/tmp/project"));
+        // Unsupported URLs fail when resolved, not as a remote source
+        assert!(!is_remote("ftp://example.com/repo"));
+    }
+
+    #[test]
+    fn sources_are_trimmed_and_unquoted() {
+        let cases = [
+            ("./project", "./project"),
+            ("  ./project\t", "./project"),
+            ("https://github.com/username/repo \n", "https://github.com/username/repo"),
+            ("https://github.com/username/repo\r\n", "https://github.com/username/repo"),
+            ("\"./my project\"", "./my project"),
+            (" ' ./project ' ", "./project"),
+            // Only a matching pair is stripped, and only one
+            ("\"./project'", "\"./project'"),
+            ("\"\"./project\"\"", "\"./project\""),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize_source(input).unwrap(), expected, "{:?}", input);
         }
     }
 
-    Ok(())
-}
+    #[test]
+    fn empty_sources_are_refused() {
+        for input in ["", "   ", "\n", "\t\r\n", "\"\"", "' '", " \"  \" "] {
+            match normalize_source(input) {
+                Err(TechDocsError::Url(message)) => assert!(message.starts_with("empty source"), "{}", message),
+                other => panic!("expected {:?} to be refused, got {:?}", input, other),
+            }
+        }
+    }
 
-/// List files in the directory
-pub fn list_files(dir: &Path, exclude_patterns: &[String]) -> Result<()> {
-    let mut override_builder = OverrideBuilder::new(dir);
-    for pattern in exclude_patterns {
-        override_builder.add(pattern)?;
+    #[cfg(feature = "git")]
+    #[tokio::test]
+    async fn resolve_path_refuses_an_empty_source_before_looking_for_it() {
+        assert!(matches!(resolve_path("  \n").await, Err(TechDocsError::Url(_))));
+        let tree = FixtureTree::new().file("notes.txt", "notes\n");
+        let quoted = format!("\"{}\"\n", tree.path().display());
+        let (path, temp) = resolve_path(&quoted).await.unwrap();
+        assert_eq!(path, tree.path());
+        assert!(temp.is_none());
+    }
+
+    #[test]
+    fn capabilities_report_a_writable_directory_outside_git() {
+        let tree = FixtureTree::new().file("notes.txt", "notes\n");
+        let info = inspect_directory(tree.path(), DirCheck::Capabilities).unwrap();
+        assert_eq!(info, DirInfo { writable: Some(true), git_work_tree: Some(false) });
+        // The write probe is removed again
+        assert_eq!(fs::read_dir(tree.path()).unwrap().count(), 1);
+        assert_eq!(inspect_directory(tree.path(), DirCheck::Readable).unwrap(), DirInfo::default());
     }
-    let overrides = override_builder.build()?;
 
-    let walker = WalkBuilder::new(dir)
-        .standard_filters(true)
-        .overrides(overrides)
-        .build();
+    #[test]
+    fn capabilities_find_the_work_tree_above_a_subdirectory() {
+        let tree = FixtureTree::new().dir(".git").dir("src/nested");
+        let info = inspect_directory(&tree.join("src/nested"), DirCheck::Capabilities).unwrap();
+        assert_eq!(info.git_work_tree, Some(true));
+        // `..` is resolved before walking up, so a path leaving the work tree is outside it
+        let outside = FixtureTree::new();
+        let to_root: PathBuf = std::iter::repeat_n("..", tree.path().components().count()).collect();
+        let escaped = tree.join("src").join(to_root).join(outside.path().strip_prefix("/").unwrap());
+        assert_eq!(inspect_directory(&escaped, DirCheck::Capabilities).unwrap().git_work_tree, Some(false));
+    }
 
-    for entry in walker {
-        let entry = entry?;
-        let path = entry.path();
+    #[test]
+    fn inspecting_a_file_or_missing_path_fails() {
+        let tree = FixtureTree::new().file("notes.txt", "notes\n");
+        assert!(inspect_directory(&tree.join("notes.txt"), DirCheck::Readable).is_err());
+        assert!(inspect_directory(&tree.join("missing"), DirCheck::Capabilities).is_err());
+    }
 
-        if path.is_file() {
-            let file_name = path.file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("");
+    /// Set `mode` on `dir` and report whether permissions apply to this process; root
+    /// reads and writes regardless, so the callers skip their assertions there
+    #[cfg(unix)]
+    fn restrict(dir: &Path, mode: u32) -> bool {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(mode)).unwrap();
+        let probe = dir.join(".probe");
+        let enforced = fs::write(&probe, "").is_err();
+        let _ = fs::remove_file(probe);
+        enforced
+    }
 
-            if is_build_executable(file_name) {
-                continue;
+    #[cfg(unix)]
+    #[test]
+    fn unlistable_directory_is_an_error() {
+        use std::os::unix::fs::PermissionsExt;
+        let tree = FixtureTree::new().file("locked/notes.txt", "notes\n");
+        let locked = tree.join("locked");
+        let enforced = restrict(&locked, 0o000);
+        let result = inspect_directory(&locked, DirCheck::Readable);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        if enforced {
+            match result {
+                Err(TechDocsError::File { path, .. }) => assert_eq!(path, locked),
+                other => panic!("expected a file error, got {:?}", other),
             }
+        }
+    }
 
-            println!("{}", path.display());
+    #[cfg(unix)]
+    #[test]
+    fn read_only_directory_is_not_writable() {
+        use std::os::unix::fs::PermissionsExt;
+        let tree = FixtureTree::new().file("readonly/notes.txt", "notes\n");
+        let readonly = tree.join("readonly");
+        let enforced = restrict(&readonly, 0o555);
+        let info = inspect_directory(&readonly, DirCheck::Capabilities);
+        fs::set_permissions(&readonly, fs::Permissions::from_mode(0o755)).unwrap();
+        if enforced {
+            assert_eq!(info.unwrap().writable, Some(false));
         }
     }
 
-    Ok(())
-}
+    /// A project touching every default: an ignored file, a build directory, a file
+    /// over the default size limit, and CRLF line endings
+    fn compatibility_project() -> FixtureTree {
+        FixtureTree::new()
+            .gitignore(["*.log"])
+            .file("src/main.rs", "fn main() {\r\n    println!(\"hello\");\r\n}\r\n")
+            .file("src/lib.rs", "pub mod docs;\n")
+            .file("docs/guide.md", "# Guide\n")
+            .file("debug.log", "ignored\n")
+            .file("target/debug/out.txt", "built\n")
+            .sized_file("data/large.txt", 150 * 1024)
+    }
 
-/// Generate a README.md file using Claude AI based on the codebase content
-/// 
-/// # Arguments
-/// * `system_prompt` - The system prompt to use for Claude
-/// * `files_content` - The content of the files to analyze
-/// 
-/// # Returns
-/// A string containing the generated README.md content
-pub async fn generate_readme(system_prompt: &str, files_content: &str) -> Result<String> {
-    // Initialize Claude client
-    let client = ClaudeClient::new()
-        .map_err(|e| TechDocsError::ClaudeClient(e.to_string()))?;
-    
-    // Send request to Claude
-    let readme_content = client
-        .send_message(
-            None, // Use default model
-            system_prompt,
-            files_content
-        )
-        .await
-        .map_err(|e| TechDocsError::ClaudeClient(e.to_string()))?;
-    
-    Ok(readme_content)
+    #[test]
+    #[allow(deprecated)]
+    fn positional_prompt_matches_prompt_options() {
+        let project = compatibility_project();
+        let cases: [(Vec<String>, u64, u64); 3] = [
+            (Vec::new(), 100, 10),
+            (vec!["docs/".to_string()], 100, 10),
+            (vec!["!*.sh".to_string()], 1, 1),
+        ];
+        for (exclude, kb, mb) in cases {
+            let mut positional = Vec::new();
+            list_files_prompt(project.path(), &exclude, kb, mb, &mut positional).unwrap();
+            let options =
+                PromptOptions::new().exclude(&exclude).max_file_kb(kb).max_total_mb(mb).normalize(Normalize::Off);
+            let mut built = Vec::new();
+            write_files_prompt(project.path(), &options, &mut built).unwrap();
+            assert_eq!(String::from_utf8(positional).unwrap(), String::from_utf8(built).unwrap(), "{:?}", exclude);
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn positional_prompt_sends_content_as_it_is() {
+        // A byte order mark, CRLF endings, and trailing whitespace all reach the prompt
+        let tree = FixtureTree::new().file("notes.txt", "\u{FEFF}first  \r\nsecond\t\r\n");
+        let mut positional = Vec::new();
+        list_files_prompt(tree.path(), &[], 100, 10, &mut positional).unwrap();
+        let expected = format!(
+            "\nFile: {}\n```txt\n\u{FEFF}first  \r\nsecond\t\r\n\n```\n",
+            tree.join("notes.txt").display()
+        );
+        assert_eq!(String::from_utf8(positional).unwrap(), expected);
+
+        let plan = PromptPlan::build(tree.path(), &[], 100, 10).unwrap();
+        assert_eq!(rendered(&plan), expected);
+        assert_eq!(plan.prompt_bytes(), expected.len() as u64);
+    }
+
+    fn rendered(plan: &PromptPlan) -> String {
+        let mut prompt = Vec::new();
+        plan.write_prompt(&mut prompt).unwrap();
+        String::from_utf8(prompt).unwrap()
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn positional_plans_match_collected_plans() {
+        let project = compatibility_project();
+        let exclude = vec!["docs/".to_string()];
+        let options = PromptOptions::new().exclude(&exclude).normalize(Normalize::Off);
+
+        let built = PromptPlan::build(project.path(), &exclude, 100, 10).unwrap();
+        let collected = PromptPlan::collect(project.path(), &options).unwrap();
+        assert_eq!(rendered(&built), rendered(&collected));
+
+        let selection = plan::FileSelection { include: vec!["src/**".to_string()], max_files: Some(1) };
+        let selected = PromptPlan::build_selected(project.path(), &exclude, &selection, 100, 10, &progress::no_progress).unwrap();
+        let options = options.include(["src/**"]).max_files(1);
+        let collected = PromptPlan::collect(project.path(), &options).unwrap();
+        assert_eq!(rendered(&selected), rendered(&collected));
+        assert_eq!(selected.included().count(), 1);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn prompt_options_default_to_the_positional_limits() {
+        let options = PromptOptions::new();
+        assert_eq!((options.max_file_size_kb, options.max_total_size_mb), (100, 10));
+        assert_eq!(options.normalize, Normalize::Off);
+
+        let project = compatibility_project();
+        let mut positional = Vec::new();
+        list_files_prompt(project.path(), &[], 100, 10, &mut positional).unwrap();
+        let mut defaults = Vec::new();
+        write_files_prompt(project.path(), &options, &mut defaults).unwrap();
+        assert_eq!(positional, defaults);
+        let listed = String::from_utf8(defaults).unwrap();
+        assert!(listed.contains("src/main.rs"));
+        assert!(!listed.contains("debug.log"));
+        assert!(!listed.contains("target/debug"));
+    }
 }
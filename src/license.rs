@@ -0,0 +1,209 @@
+use std::fs;
+use std::path::Path;
+use serde::Serialize;
+
+/// A project license and where it was determined from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LicenseInfo {
+    /// SPDX identifier or expression, e.g. `MIT` or `MIT OR Apache-2.0`
+    pub spdx: String,
+    /// File the license was read from, relative to the project root
+    pub source: String,
+}
+
+/// Fingerprints of common license texts, most specific first. Every phrase must appear
+/// in the whitespace-normalized text.
+const FINGERPRINTS: &[(&str, &[&str])] = &[
+    ("Apache-2.0", &["Apache License", "Version 2.0"]),
+    ("MPL-2.0", &["Mozilla Public License Version 2.0"]),
+    ("LGPL-3.0", &["GNU LESSER GENERAL PUBLIC LICENSE", "Version 3"]),
+    ("AGPL-3.0", &["GNU AFFERO GENERAL PUBLIC LICENSE", "Version 3"]),
+    ("GPL-3.0", &["GNU GENERAL PUBLIC LICENSE", "Version 3"]),
+    ("GPL-2.0", &["GNU GENERAL PUBLIC LICENSE", "Version 2"]),
+    ("Unlicense", &["This is free and unencumbered software released into the public domain"]),
+    ("MIT", &["Permission is hereby granted, free of charge"]),
+    ("ISC", &["Permission to use, copy, modify, and/or distribute this software for any purpose"]),
+    ("BSD-3-Clause", &["Redistribution and use in source and binary forms", "Neither the name"]),
+    ("BSD-2-Clause", &["Redistribution and use in source and binary forms"]),
+];
+
+/// Determine the project license in `dir`. The `license` field of Cargo.toml or
+/// package.json wins, since it is an exact SPDX expression; otherwise license files are
+/// fingerprinted, and several different licenses (e.g. `LICENSE-MIT` and `LICENSE-APACHE`)
+/// are combined with `OR`.
+pub fn detect_license(dir: &Path) -> Option<LicenseInfo> {
+    if let Some(spdx) = cargo_license(dir) {
+        return Some(LicenseInfo { spdx, source: "Cargo.toml".to_string() });
+    }
+    if let Some(spdx) = package_json_license(dir) {
+        return Some(LicenseInfo { spdx, source: "package.json".to_string() });
+    }
+
+    let mut identified: Vec<(&str, String)> = Vec::new();
+    for name in license_files(dir) {
+        let Ok(text) = fs::read_to_string(dir.join(&name)) else {
+            continue;
+        };
+        if let Some(spdx) = identify_license(&text) {
+            if !identified.iter().any(|(known, _)| *known == spdx) {
+                identified.push((spdx, name));
+            }
+        }
+    }
+    if identified.is_empty() {
+        return None;
+    }
+    Some(LicenseInfo {
+        spdx: identified.iter().map(|(spdx, _)| *spdx).collect::<Vec<_>>().join(" OR "),
+        source: identified.into_iter().map(|(_, name)| name).collect::<Vec<_>>().join(", "),
+    })
+}
+
+/// `LICENSE*` and `COPYING*` files in the root of `dir`, sorted
+pub fn license_files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| {
+            let upper = name.to_ascii_uppercase();
+            upper.starts_with("LICENSE") || upper.starts_with("LICENCE") || upper.starts_with("COPYING")
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// SPDX identifier of a license text, if it matches one of the known fingerprints
+pub fn identify_license(text: &str) -> Option<&'static str> {
+    let normalized = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    FINGERPRINTS
+        .iter()
+        .find(|(_, phrases)| phrases.iter().all(|phrase| normalized.contains(phrase)))
+        .map(|(spdx, _)| *spdx)
+}
+
+/// Lines added to a prompt so the model states the license instead of guessing it
+pub fn license_fact(license: &LicenseInfo) -> String {
+    format!(
+        "License: {} (SPDX, determined from {}). Use exactly this license; do not infer another.\n",
+        license.spdx, license.source
+    )
+}
+
+fn cargo_license(dir: &Path) -> Option<String> {
+    let manifest: toml::Table = fs::read_to_string(dir.join("Cargo.toml")).ok()?.parse().ok()?;
+    let license = manifest.get("package")?.get("license")?.as_str()?.trim();
+    (!license.is_empty()).then(|| license.to_string())
+}
+
+fn package_json_license(dir: &Path) -> Option<String> {
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("package.json")).ok()?).ok()?;
+    // Older packages use `{ "type": "MIT", "url": ... }`
+    let license = match manifest.get("license")? {
+        serde_json::Value::Object(object) => object.get("type")?.as_str()?,
+        value => value.as_str()?,
+    }
+    .trim();
+    // `SEE LICENSE IN <file>` and `UNLICENSED` are not SPDX identifiers for a public license
+    if license.is_empty() || license.starts_with("SEE LICENSE") || license == "UNLICENSED" {
+        return None;
+    }
+    Some(license.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    const MIT: &str = "MIT License
+
+Copyright (c) 2024 Acme
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the \"Software\"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+";
+    const APACHE: &str = "
+                                 Apache License
+                           Version 2.0, January 2004
+                        http://www.apache.org/licenses/
+
+   TERMS AND CONDITIONS FOR USE, REPRODUCTION, AND DISTRIBUTION
+";
+    const BSD_2: &str = "Copyright (c) 2024, Acme
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice, this
+   list of conditions and the following disclaimer.
+";
+    const BSD_3_CLAUSE: &str = "3. Neither the name of the copyright holder nor the names of its
+   contributors may be used to endorse or promote products derived from
+   this software without specific prior written permission.
+";
+    const LGPL_3: &str = "                   GNU LESSER GENERAL PUBLIC LICENSE
+                       Version 3, 29 June 2007
+
+ Copyright (C) 2007 Free Software Foundation, Inc. <https://fsf.org/>
+
+  This version of the GNU Lesser General Public License incorporates
+the terms and conditions of version 3 of the GNU General Public
+License, supplemented by the additional permissions listed below.
+";
+
+    #[test]
+    fn license_files_are_identified_by_their_text() {
+        let tree = FixtureTree::new().file("LICENSE.md", MIT).file("src/lib.rs", "");
+        let license = detect_license(tree.path()).unwrap();
+        assert_eq!(license, LicenseInfo { spdx: "MIT".into(), source: "LICENSE.md".into() });
+        assert_eq!(
+            license_fact(&license),
+            "License: MIT (SPDX, determined from LICENSE.md). Use exactly this license; do not infer another.\n"
+        );
+
+        // Dual licensing is read from one file per license
+        let tree = FixtureTree::new().file("LICENSE-APACHE", APACHE).file("LICENSE-MIT", MIT);
+        let license = detect_license(tree.path()).unwrap();
+        assert_eq!((license.spdx.as_str(), license.source.as_str()), ("Apache-2.0 OR MIT", "LICENSE-APACHE, LICENSE-MIT"));
+    }
+
+    #[test]
+    fn nothing_is_detected_without_a_known_license() {
+        assert_eq!(detect_license(FixtureTree::new().file("README.md", "# Demo\n").path()), None);
+        let tree = FixtureTree::new()
+            .file("COPYING", "All rights reserved. Do not redistribute.\n")
+            .file("package.json", r#"{"name": "demo", "license": "SEE LICENSE IN COPYING"}"#);
+        assert_eq!(license_files(tree.path()), ["COPYING"]);
+        assert_eq!(detect_license(tree.path()), None);
+    }
+
+    #[test]
+    fn similar_licenses_are_told_apart() {
+        // The BSD variants share their first clause, and the LGPL names version 3 of the GPL
+        assert_eq!(identify_license(BSD_2), Some("BSD-2-Clause"));
+        assert_eq!(identify_license(&format!("{}{}", BSD_2, BSD_3_CLAUSE)), Some("BSD-3-Clause"));
+        assert_eq!(identify_license(LGPL_3), Some("LGPL-3.0"));
+        // Line breaks differ between copies of the same text
+        assert_eq!(identify_license(&MIT.replace(' ', "\n  ")), Some("MIT"));
+    }
+
+    #[test]
+    fn a_manifest_license_wins_over_the_files() {
+        let tree = FixtureTree::new()
+            .file("LICENSE", MIT)
+            .file("Cargo.toml", "[package]\nname = \"demo\"\nlicense = \"Apache-2.0\"\n");
+        assert_eq!(detect_license(tree.path()).unwrap(), LicenseInfo { spdx: "Apache-2.0".into(), source: "Cargo.toml".into() });
+
+        let tree = FixtureTree::new().file("package.json", r#"{"license": {"type": "ISC", "url": "https://example.com"}}"#);
+        assert_eq!(detect_license(tree.path()).unwrap().spdx, "ISC");
+    }
+}
@@ -0,0 +1,135 @@
+use std::io::IsTerminal;
+use std::str::FromStr;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::TechDocsError;
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, for log aggregation
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = TechDocsError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(TechDocsError::Config(format!(
+                "invalid log format `{}`: expected `text` or `json`",
+                other
+            ))),
+        }
+    }
+}
+
+/// Logging settings shared by both binaries
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+    /// Crates whose logs follow the verbosity; other crates stay one level quieter
+    pub targets: Vec<String>,
+    /// Number of `-v` flags: 0 logs info, 1 debug, 2 trace, 3 or more trace for every crate
+    pub verbose: u8,
+    /// Log only warnings and errors, whatever `verbose` says
+    pub quiet: bool,
+    /// Filter from the deprecated `--log-level` flag, used instead of `verbose` when set
+    pub log_level: Option<String>,
+    pub format: LogFormat,
+    /// Never color output, even on a terminal
+    pub no_color: bool,
+}
+
+/// Filter directives for a verbosity, e.g. `techdocs=debug,info` for one `-v`
+pub fn verbosity_filter(targets: &[String], verbose: u8, quiet: bool) -> String {
+    let (level, others) = match (quiet, verbose) {
+        (true, _) => return "warn".to_string(),
+        (false, 0) => ("info", "warn"),
+        (false, 1) => ("debug", "info"),
+        (false, 2) => ("trace", "debug"),
+        (false, _) => return "trace".to_string(),
+    };
+    let mut directives: Vec<String> = targets.iter().map(|t| format!("{}={}", t, level)).collect();
+    directives.push(others.to_string());
+    directives.join(",")
+}
+
+/// Install the global tracing subscriber. Logs always go to stderr so stdout carries only
+/// generated content. The filter is the first of `TECHDOCS_LOG_LEVEL`, `RUST_LOG`, the
+/// deprecated `--log-level`, and the verbosity flags.
+pub fn init_logging(options: &LogOptions) {
+    let filter = std::env::var("TECHDOCS_LOG_LEVEL")
+        .ok()
+        .and_then(|level| EnvFilter::try_new(level).ok())
+        .or_else(|| EnvFilter::try_from_default_env().ok())
+        .or_else(|| options.log_level.as_ref().and_then(|level| EnvFilter::try_new(level).ok()))
+        .unwrap_or_else(|| {
+            EnvFilter::new(verbosity_filter(&options.targets, options.verbose, options.quiet))
+        });
+
+    let registry = tracing_subscriber::registry().with(filter);
+    match options.format {
+        LogFormat::Text => {
+            let ansi = !options.no_color
+                && std::env::var_os("NO_COLOR").is_none()
+                && std::io::stderr().is_terminal();
+            registry
+                .with(fmt::layer().with_writer(std::io::stderr).with_ansi(ansi))
+                .init();
+        }
+        LogFormat::Json => {
+            registry
+                .with(fmt::layer().json().with_writer(std::io::stderr))
+                .init();
+        }
+    }
+
+    if options.log_level.is_some() {
+        tracing::warn!("--log-level is deprecated and will be removed in the next release; use -v or -vv instead");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_parse_case_insensitively() {
+        assert_eq!("text".parse::<LogFormat>().unwrap(), LogFormat::Text);
+        assert_eq!(" JSON ".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!(LogFormat::default(), LogFormat::Text);
+
+        let error = "yaml".parse::<LogFormat>().unwrap_err();
+        assert!(
+            matches!(&error, TechDocsError::Config(message) if message == "invalid log format `yaml`: expected `text` or `json`"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn verbosity_raises_our_crates_a_level_above_the_rest() {
+        let targets = vec!["techdocs".to_string(), "techdocs_api".to_string()];
+        assert_eq!(verbosity_filter(&targets, 0, false), "techdocs=info,techdocs_api=info,warn");
+        assert_eq!(verbosity_filter(&targets, 1, false), "techdocs=debug,techdocs_api=debug,info");
+        assert_eq!(verbosity_filter(&targets, 2, false), "techdocs=trace,techdocs_api=trace,debug");
+        assert_eq!(verbosity_filter(&targets, 3, false), "trace");
+        assert_eq!(verbosity_filter(&targets, 2, true), "warn");
+        assert_eq!(verbosity_filter(&[], 0, false), "warn");
+    }
+
+    #[test]
+    fn every_verbosity_is_a_valid_filter() {
+        let targets = vec!["techdocs".to_string()];
+        for verbose in 0..5 {
+            for quiet in [false, true] {
+                let filter = verbosity_filter(&targets, verbose, quiet);
+                assert!(EnvFilter::try_new(&filter).is_ok(), "{}", filter);
+            }
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::git::head_commit;
+use crate::plan::{PromptOptions, PromptPlan, SkipReason};
+use crate::progress::ProgressFn;
+use crate::{code_language, read_file_lossy, Result};
+
+/// Version of the [`FileManifest`] format. Bumped whenever a field is renamed, removed,
+/// or changes meaning; adding an optional field does not bump it.
+pub const MANIFEST_SCHEMA_VERSION: u32 = 1;
+
+/// A project's files as techdocs sees them, for tools that consume the selection as
+/// data rather than as a prompt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct FileManifest {
+    /// Format version, [`MANIFEST_SCHEMA_VERSION`] when written by this build
+    #[cfg_attr(feature = "server", schema(example = 1))]
+    pub schema_version: u32,
+    /// Path or repository URL the project was read from
+    pub source: String,
+    /// Commit the project was at, when it is a git repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[cfg_attr(feature = "server", schema(value_type = String, example = "2025-01-31T12:00:00Z"))]
+    pub generated_at: DateTime<Utc>,
+    /// Every file considered, in walk order
+    pub entries: Vec<FileEntry>,
+    pub stats: ManifestStats,
+}
+
+/// A file considered for the prompt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct FileEntry {
+    /// Path relative to the project root, separated by `/`
+    #[cfg_attr(feature = "server", schema(example = "src/main.rs"))]
+    pub path: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Code fence language the prompt tags the file with
+    #[cfg_attr(feature = "server", schema(example = "rs"))]
+    pub language: String,
+    /// Why the file was left out; omitted for included files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<SkipReason>,
+    /// Tokens the file adds to the prompt, including its header and fence
+    pub estimated_tokens: u64,
+    /// The file's text, for included files when content was asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Whether the prompt numbers the file's lines. `content` stays the file's text, so
+    /// consumers number it themselves.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub line_numbers: bool,
+}
+
+impl FileEntry {
+    pub fn is_included(&self) -> bool {
+        self.skipped.is_none()
+    }
+}
+
+/// Totals over a manifest's entries
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ManifestStats {
+    pub files_included: usize,
+    pub files_skipped: usize,
+    /// Bytes of included file content
+    pub included_bytes: u64,
+    /// Tokens the included files add to the prompt
+    pub estimated_tokens: u64,
+    /// Whether the total size budget left files out
+    pub budget_reached: bool,
+    /// SPDX license detected in the project
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+}
+
+impl FileManifest {
+    /// The manifest of the files under `dir`, read from `source`. With `content`, each
+    /// included entry carries its file's text.
+    pub fn collect(
+        source: &str,
+        dir: &Path,
+        options: &PromptOptions,
+        content: bool,
+        progress: ProgressFn<'_>,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<Self> {
+        let plan = PromptPlan::collect_cancellable(dir, options, progress, cancelled)?;
+        Self::from_plan(source, &plan, content)
+    }
+
+    /// The manifest of files already chosen. With `content`, each included file is read.
+    pub fn from_plan(source: &str, plan: &PromptPlan, content: bool) -> Result<Self> {
+        let entries = plan
+            .files
+            .iter()
+            .map(|file| {
+                let relative = file.path.strip_prefix(&plan.root).unwrap_or(&file.path);
+                Ok(FileEntry {
+                    path: relative
+                        .components()
+                        .map(|component| component.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/"),
+                    size: file.size,
+                    language: code_language(&file.path).to_string(),
+                    skipped: file.skipped,
+                    estimated_tokens: file.estimated_tokens,
+                    content: (content && file.is_included()).then(|| read_file_lossy(&file.path)).transpose()?,
+                    line_numbers: plan.line_numbers && file.is_included(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            schema_version: MANIFEST_SCHEMA_VERSION,
+            source: source.to_string(),
+            commit: head_commit(&plan.root),
+            generated_at: Utc::now(),
+            entries,
+            stats: ManifestStats {
+                files_included: plan.included().count(),
+                files_skipped: plan.skipped().count(),
+                included_bytes: plan.included_bytes(),
+                estimated_tokens: plan.included().map(|file| file.estimated_tokens).sum(),
+                budget_reached: plan.budget_reached,
+                license: plan.license.as_ref().map(|license| license.spdx.clone()),
+            },
+        })
+    }
+
+    /// Entries whose files go into the prompt, in order
+    pub fn included(&self) -> impl Iterator<Item = &FileEntry> {
+        self.entries.iter().filter(|entry| entry.is_included())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    /// A manifest with every field set, at a fixed time, so its JSON is stable
+    fn sample() -> FileManifest {
+        FileManifest {
+            schema_version: MANIFEST_SCHEMA_VERSION,
+            source: "https://github.com/example/project".to_string(),
+            commit: Some("0123456789abcdef0123456789abcdef01234567".to_string()),
+            generated_at: DateTime::parse_from_rfc3339("2025-01-31T12:00:00Z").unwrap().with_timezone(&Utc),
+            entries: vec![
+                FileEntry {
+                    path: "src/main.rs".to_string(),
+                    size: 13,
+                    language: "rs".to_string(),
+                    skipped: None,
+                    estimated_tokens: 12,
+                    content: Some("fn main() {}\n".to_string()),
+                    line_numbers: true,
+                },
+                FileEntry {
+                    path: "data/large.csv".to_string(),
+                    size: 204_800,
+                    language: "csv".to_string(),
+                    skipped: Some(SkipReason::TooLarge),
+                    estimated_tokens: 51_210,
+                    content: None,
+                    line_numbers: false,
+                },
+            ],
+            stats: ManifestStats {
+                files_included: 1,
+                files_skipped: 1,
+                included_bytes: 13,
+                estimated_tokens: 12,
+                budget_reached: false,
+                license: Some("MIT".to_string()),
+            },
+        }
+    }
+
+    #[test]
+    fn manifest_json_matches_the_schema_snapshot() {
+        // A change here is a change to the published format: bump MANIFEST_SCHEMA_VERSION
+        // unless the change only adds an optional field
+        insta::assert_snapshot!("manifest_schema_v1", serde_json::to_string_pretty(&sample()).unwrap());
+    }
+
+    #[test]
+    fn manifest_round_trips_through_json() {
+        let manifest = sample();
+        let json = serde_json::to_string(&manifest).unwrap();
+        assert_eq!(serde_json::from_str::<FileManifest>(&json).unwrap(), manifest);
+    }
+
+    #[test]
+    fn optional_fields_may_be_left_out() {
+        let json = r#"{
+            "schema_version": 1,
+            "source": ".",
+            "generated_at": "2025-01-31T12:00:00Z",
+            "entries": [{"path": "notes.txt", "size": 6, "language": "txt", "estimated_tokens": 10}],
+            "stats": {
+                "files_included": 1,
+                "files_skipped": 0,
+                "included_bytes": 6,
+                "estimated_tokens": 10,
+                "budget_reached": false
+            }
+        }"#;
+        let manifest: FileManifest = serde_json::from_str(json).unwrap();
+        assert_eq!(manifest.commit, None);
+        assert_eq!(manifest.stats.license, None);
+        let entry = &manifest.entries[0];
+        assert!(entry.is_included());
+        assert_eq!((entry.content.as_deref(), entry.line_numbers), (None, false));
+    }
+
+    #[test]
+    fn from_plan_lists_every_file_with_content_only_when_asked() {
+        let tree = FixtureTree::new().file("src/main.rs", "fn main() {}\n").sized_file("large.txt", 2048);
+        let plan = PromptPlan::collect(tree.path(), &PromptOptions::new().max_file_kb(1)).unwrap();
+
+        let manifest = FileManifest::from_plan("project", &plan, true).unwrap();
+        assert_eq!(manifest.schema_version, MANIFEST_SCHEMA_VERSION);
+        assert_eq!(manifest.source, "project");
+        assert_eq!(manifest.commit, None);
+        let main = manifest.entries.iter().find(|entry| entry.path == "src/main.rs").unwrap();
+        assert_eq!((main.language.as_str(), main.content.as_deref()), ("rs", Some("fn main() {}\n")));
+        let large = manifest.entries.iter().find(|entry| entry.path == "large.txt").unwrap();
+        assert_eq!((large.skipped, large.content.as_deref()), (Some(SkipReason::TooLarge), None));
+        assert_eq!(
+            (manifest.stats.files_included, manifest.stats.files_skipped, manifest.stats.included_bytes),
+            (1, 1, 13)
+        );
+        assert_eq!(manifest.stats.estimated_tokens, main.estimated_tokens);
+
+        let inventory = FileManifest::from_plan("project", &plan, false).unwrap();
+        assert!(inventory.entries.iter().all(|entry| entry.content.is_none()));
+        assert_eq!(inventory.stats, manifest.stats);
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// A metric label, such as `("reason", "too_large")`
+pub type Label = (&'static str, &'static str);
+
+/// A metric's name and labels
+type Key = (&'static str, Vec<Label>);
+
+/// Files chosen for a prompt
+pub const FILES_INCLUDED: &str = "techdocs_files_included_total";
+/// Files left out of a prompt, labeled with the `reason`
+pub const FILES_SKIPPED: &str = "techdocs_files_skipped_total";
+/// Bytes of prompt written
+pub const BYTES_EMITTED: &str = "techdocs_prompt_bytes_total";
+/// Time taken by each repository clone
+pub const CLONE_SECONDS: &str = "techdocs_clone_seconds";
+/// Time taken by each README request to the provider
+pub const LLM_REQUEST_SECONDS: &str = "techdocs_llm_request_seconds";
+/// Input tokens the provider billed for README requests
+pub const LLM_INPUT_TOKENS: &str = "techdocs_llm_input_tokens_total";
+/// Output tokens the provider billed for README requests
+pub const LLM_OUTPUT_TOKENS: &str = "techdocs_llm_output_tokens_total";
+
+/// Help text for each metric, in the order they are rendered
+const DESCRIPTIONS: &[(&str, &str)] = &[
+    (FILES_INCLUDED, "Files chosen for a prompt"),
+    (FILES_SKIPPED, "Files left out of a prompt"),
+    (BYTES_EMITTED, "Bytes of prompt written"),
+    (CLONE_SECONDS, "Time taken by each repository clone"),
+    (LLM_REQUEST_SECONDS, "Time taken by each README request to the provider"),
+    (LLM_INPUT_TOKENS, "Input tokens billed for README requests"),
+    (LLM_OUTPUT_TOKENS, "Output tokens billed for README requests"),
+];
+
+/// Receives the library's metrics, for forwarding to whatever backend an application
+/// uses. Until one is installed with [`set_recorder`], metrics are dropped.
+pub trait Recorder: Send + Sync {
+    /// Add `value` to the counter `name`
+    fn increment_counter(&self, name: &'static str, labels: &[Label], value: u64);
+
+    /// Record one observation, such as a duration in seconds, of the histogram `name`
+    fn record_histogram(&self, name: &'static str, labels: &[Label], value: f64);
+}
+
+static RECORDER: OnceLock<Arc<dyn Recorder>> = OnceLock::new();
+
+/// Install the process-wide recorder. Returns false, leaving the first in place, when
+/// one was already installed.
+pub fn set_recorder(recorder: Arc<dyn Recorder>) -> bool {
+    RECORDER.set(recorder).is_ok()
+}
+
+pub(crate) fn increment_counter(name: &'static str, labels: &[Label], value: u64) {
+    if let Some(recorder) = RECORDER.get() {
+        recorder.increment_counter(name, labels, value);
+    }
+}
+
+#[cfg(any(feature = "git", feature = "claude"))]
+pub(crate) fn record_histogram(name: &'static str, labels: &[Label], value: f64) {
+    if let Some(recorder) = RECORDER.get() {
+        recorder.record_histogram(name, labels, value);
+    }
+}
+
+/// A recorder that keeps totals in memory and renders them in the Prometheus text
+/// format. Histograms are kept as a count and a sum, and rendered as summaries.
+#[derive(Debug, Default)]
+pub struct Registry {
+    counters: Mutex<BTreeMap<Key, u64>>,
+    /// Count and sum of the observations
+    histograms: Mutex<BTreeMap<Key, (u64, f64)>>,
+}
+
+impl Recorder for Registry {
+    fn increment_counter(&self, name: &'static str, labels: &[Label], value: u64) {
+        let mut counters = self.counters.lock().expect("metrics lock");
+        *counters.entry((name, labels.to_vec())).or_default() += value;
+    }
+
+    fn record_histogram(&self, name: &'static str, labels: &[Label], value: f64) {
+        let mut histograms = self.histograms.lock().expect("metrics lock");
+        let (count, sum) = histograms.entry((name, labels.to_vec())).or_default();
+        *count += 1;
+        *sum += value;
+    }
+}
+
+impl Registry {
+    /// Everything recorded so far in the Prometheus text format. Metrics nothing was
+    /// recorded for are left out.
+    pub fn render_prometheus(&self) -> String {
+        let counters = self.counters.lock().expect("metrics lock");
+        let histograms = self.histograms.lock().expect("metrics lock");
+        let mut out = String::new();
+        for (name, help) in DESCRIPTIONS {
+            let counted: Vec<_> = counters.iter().filter(|((n, _), _)| n == name).collect();
+            let observed: Vec<_> = histograms.iter().filter(|((n, _), _)| n == name).collect();
+            if !counted.is_empty() {
+                let _ = write!(out, "# HELP {name} {help}\n# TYPE {name} counter\n");
+                for ((_, labels), value) in counted {
+                    let _ = writeln!(out, "{}{} {}", name, render_labels(labels), value);
+                }
+            }
+            if !observed.is_empty() {
+                let _ = write!(out, "# HELP {name} {help}\n# TYPE {name} summary\n");
+                for ((_, labels), (count, sum)) in observed {
+                    let labels = render_labels(labels);
+                    let _ = write!(out, "{name}_sum{labels} {sum}\n{name}_count{labels} {count}\n");
+                }
+            }
+        }
+        out
+    }
+}
+
+fn render_labels(labels: &[Label]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels.iter().map(|(key, value)| format!("{}=\"{}\"", key, value)).collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_add_up_per_label_set() {
+        let registry = Registry::default();
+        registry.increment_counter(FILES_INCLUDED, &[], 3);
+        registry.increment_counter(FILES_INCLUDED, &[], 2);
+        registry.increment_counter(FILES_SKIPPED, &[("reason", "too_large")], 1);
+        registry.increment_counter(FILES_SKIPPED, &[("reason", "binary")], 4);
+        registry.increment_counter(FILES_SKIPPED, &[("reason", "too_large")], 1);
+
+        let counters = registry.counters.lock().unwrap();
+        assert_eq!(counters[&(FILES_INCLUDED, vec![])], 5);
+        assert_eq!(counters[&(FILES_SKIPPED, vec![("reason", "too_large")])], 2);
+        assert_eq!(counters[&(FILES_SKIPPED, vec![("reason", "binary")])], 4);
+    }
+
+    #[test]
+    fn rendering_follows_the_prometheus_text_format() {
+        let registry = Registry::default();
+        // Recorded out of order, rendered in description order
+        registry.record_histogram(LLM_REQUEST_SECONDS, &[], 1.5);
+        registry.record_histogram(LLM_REQUEST_SECONDS, &[], 0.5);
+        registry.increment_counter(FILES_SKIPPED, &[("reason", "too_large"), ("preset", "default")], 2);
+        registry.increment_counter(FILES_INCLUDED, &[], 7);
+
+        assert_eq!(
+            registry.render_prometheus(),
+            "\
+# HELP techdocs_files_included_total Files chosen for a prompt
+# TYPE techdocs_files_included_total counter
+techdocs_files_included_total 7
+# HELP techdocs_files_skipped_total Files left out of a prompt
+# TYPE techdocs_files_skipped_total counter
+techdocs_files_skipped_total{reason=\"too_large\",preset=\"default\"} 2
+# HELP techdocs_llm_request_seconds Time taken by each README request to the provider
+# TYPE techdocs_llm_request_seconds summary
+techdocs_llm_request_seconds_sum 2
+techdocs_llm_request_seconds_count 2
+"
+        );
+    }
+
+    #[test]
+    fn nothing_recorded_renders_nothing() {
+        let registry = Registry::default();
+        // Metrics without a description are kept but never rendered
+        registry.increment_counter("techdocs_unknown_total", &[], 1);
+        assert_eq!(registry.render_prometheus(), "");
+    }
+
+    #[test]
+    fn the_first_recorder_receives_library_metrics() {
+        let registry = Arc::new(Registry::default());
+        // No other test installs one
+        assert!(set_recorder(registry.clone()));
+        assert!(!set_recorder(Arc::new(Registry::default())));
+
+        increment_counter("techdocs_forwarded_total", &[], 2);
+        let counters = registry.counters.lock().unwrap();
+        assert_eq!(counters[&("techdocs_forwarded_total", vec![])], 2);
+    }
+}
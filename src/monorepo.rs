@@ -0,0 +1,465 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use serde::Serialize;
+
+use crate::{code_language, Result, TechDocsError};
+
+/// Files at the root of a workspace that describe it as a whole, sent along with a
+/// member's own files
+pub const WORKSPACE_MANIFESTS: &[&str] = &["Cargo.toml", "package.json", "pnpm-workspace.yaml"];
+
+/// Files at a context root sent with the files of a directory inside it, in this order
+pub const CONTEXT_ROOT_FILES: &[&str] =
+    &["Cargo.toml", "package.json", "pnpm-workspace.yaml", "pyproject.toml", "go.work", "go.mod", "README.md"];
+
+/// Longest block [`ParentContext::prompt_block`] produces, in bytes. It is kept apart
+/// from the size limit on the directory's own files.
+pub const CONTEXT_ROOT_MAX_BYTES: usize = 16 * 1024;
+
+/// The tool whose workspace configuration lists a member
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkspaceKind {
+    /// `[workspace].members` in Cargo.toml
+    Cargo,
+    /// `workspaces` in package.json, as used by npm and Yarn
+    Npm,
+    /// `packages` in pnpm-workspace.yaml
+    Pnpm,
+}
+
+impl WorkspaceKind {
+    /// The file every member directory has
+    pub fn manifest(self) -> &'static str {
+        match self {
+            WorkspaceKind::Cargo => "Cargo.toml",
+            WorkspaceKind::Npm | WorkspaceKind::Pnpm => "package.json",
+        }
+    }
+}
+
+/// A package in a workspace
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Member {
+    /// Package name from the member's manifest, or its path when the manifest has none
+    pub name: String,
+    /// Directory of the member, relative to the workspace root
+    pub path: PathBuf,
+    pub kind: WorkspaceKind,
+}
+
+impl Member {
+    /// The member's path with `/` between directories, as patterns write it
+    pub fn slash_path(&self) -> String {
+        slash_path(&self.path)
+    }
+
+    /// Include patterns, relative to the workspace root, for the member's files and the
+    /// workspace manifests
+    pub fn include_patterns(&self) -> Vec<String> {
+        let mut patterns = vec![format!("/{}/**", self.slash_path())];
+        patterns.extend(WORKSPACE_MANIFESTS.iter().map(|name| format!("/{}", name)));
+        patterns
+    }
+
+    /// Text appended to the system prompt so a README describes this member rather than
+    /// the whole workspace
+    pub fn instructions(&self) -> String {
+        format!(
+            "\n\nThe files are one member of a workspace: the `{}` package in `{}/`, with the \
+             workspace manifests at the root for context. Write the README for this package \
+             alone, to be placed in its directory; mention the workspace only where it helps \
+             to build or use the package.",
+            self.name,
+            self.slash_path()
+        )
+    }
+}
+
+/// The members of a Cargo, npm, Yarn, or pnpm workspace rooted at `dir`, sorted by path,
+/// or `None` when `dir` is not a workspace. Glob patterns such as `crates/*` or
+/// `packages/**` are expanded to the directories they match that have a manifest, and
+/// Cargo `exclude` entries and pnpm `!` patterns are left out.
+pub fn detect_workspace(dir: &Path) -> Option<Vec<Member>> {
+    let mut members = Vec::new();
+
+    if let Some(workspace) = fs::read_to_string(dir.join("Cargo.toml"))
+        .ok()
+        .and_then(|manifest| manifest.parse::<toml::Table>().ok())
+        .and_then(|mut manifest| manifest.remove("workspace"))
+    {
+        let strings = |key: &str| -> Vec<String> {
+            let list = workspace.get(key).and_then(|value| value.as_array());
+            list.into_iter().flatten().filter_map(|value| value.as_str()).map(String::from).collect()
+        };
+        let mut patterns = strings("members");
+        patterns.extend(strings("exclude").into_iter().map(|path| format!("!{}", path)));
+        members.extend(expand(dir, &patterns, WorkspaceKind::Cargo));
+    }
+
+    if let Some(manifest) = fs::read_to_string(dir.join("package.json"))
+        .ok()
+        .and_then(|manifest| serde_json::from_str::<serde_json::Value>(&manifest).ok())
+    {
+        // Either a list of patterns or, with Yarn, an object holding one
+        let workspaces = manifest.get("workspaces");
+        let list = workspaces
+            .and_then(|w| w.as_array())
+            .or_else(|| workspaces.and_then(|w| w.get("packages")).and_then(|p| p.as_array()));
+        let patterns: Vec<String> =
+            list.into_iter().flatten().filter_map(|value| value.as_str()).map(String::from).collect();
+        members.extend(expand(dir, &patterns, WorkspaceKind::Npm));
+    }
+
+    if let Ok(config) = fs::read_to_string(dir.join("pnpm-workspace.yaml")) {
+        members.extend(expand(dir, &pnpm_packages(&config), WorkspaceKind::Pnpm));
+    }
+
+    members.sort_by(|a, b| a.path.cmp(&b.path));
+    // A directory listed by both npm and pnpm is one member
+    members.dedup_by(|a, b| a.path == b.path);
+    if members.is_empty() {
+        None
+    } else {
+        Some(members)
+    }
+}
+
+/// The member called `name`, matched by package name or by path
+pub fn find_member<'a>(members: &'a [Member], name: &str) -> Result<&'a Member> {
+    let path = name.trim_start_matches("./").trim_end_matches('/');
+    members
+        .iter()
+        .find(|member| member.name == name)
+        .or_else(|| members.iter().find(|member| member.slash_path() == path))
+        .ok_or_else(|| {
+            let names: Vec<&str> = members.iter().map(|member| member.name.as_str()).collect();
+            TechDocsError::Config(format!("no workspace member named `{}`; members: {}", name, names.join(", ")))
+        })
+}
+
+/// The member called `name` of the workspace at `dir`, failing when `dir` is not a
+/// workspace or has no such member
+pub fn workspace_member(dir: &Path, name: &str) -> Result<Member> {
+    let members = detect_workspace(dir).ok_or_else(|| {
+        TechDocsError::Config(format!("{} is not a Cargo, npm, or pnpm workspace", dir.display()))
+    })?;
+    find_member(&members, name).cloned()
+}
+
+/// The directory above `dir` that holds the configuration it shares with the rest of
+/// its project: the nearest ancestor that is a workspace or the root of a git
+/// repository. `None` when `dir` is itself the root of a repository or nothing above
+/// it qualifies.
+pub fn find_context_root(dir: &Path) -> Option<PathBuf> {
+    let dir = dir.canonicalize().ok()?;
+    if dir.join(".git").exists() {
+        return None;
+    }
+    dir.ancestors()
+        .skip(1)
+        .find(|ancestor| ancestor.join(".git").exists() || detect_workspace(ancestor).is_some())
+        .map(Path::to_path_buf)
+}
+
+/// The manifests and README at a context root, sent as labeled context with the files
+/// of a directory inside it so a README of one package gets its real name, version,
+/// and shared dependencies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParentContext {
+    /// Directory the files were read from
+    pub root: PathBuf,
+    /// The documented directory, relative to `root`, with `/` separators
+    pub target: String,
+    /// Name and content of each of the [`CONTEXT_ROOT_FILES`] found at `root`
+    pub files: Vec<(String, String)>,
+}
+
+impl ParentContext {
+    /// The context `root` gives `dir`, or `None` when `dir` is not below `root`
+    pub fn read(root: &Path, dir: &Path) -> Option<Self> {
+        let root = root.canonicalize().ok()?;
+        let relative = dir.canonicalize().ok()?.strip_prefix(&root).ok()?.to_path_buf();
+        if relative.as_os_str().is_empty() {
+            return None;
+        }
+        let files = CONTEXT_ROOT_FILES
+            .iter()
+            .filter_map(|name| {
+                let content = fs::read(root.join(name)).ok()?;
+                Some((name.to_string(), String::from_utf8_lossy(&content).into_owned()))
+            })
+            .collect();
+        Some(Self { root, target: slash_path(&relative), files })
+    }
+
+    /// The files as a block for the README prompt, at most [`CONTEXT_ROOT_MAX_BYTES`]
+    /// long, or `None` when the root has none of them. A file that does not fit is cut
+    /// at a line and marked.
+    pub fn prompt_block(&self) -> Option<String> {
+        if self.files.is_empty() {
+            return None;
+        }
+        let mut block = format!(
+            "\n\nContext from the enclosing project: the files above are its `{}/` directory, which \
+             is what the README is for. These files from the project root are for reference only, \
+             for the package name, version, license, and dependencies the directory shares with \
+             the rest of the project; do not document them as part of the directory.\n",
+            self.target
+        );
+        for (name, content) in &self.files {
+            let header = format!("\nContext file: {}\n```{}\n", name, code_language(Path::new(name)));
+            let footer = "\n```\n";
+            let room = CONTEXT_ROOT_MAX_BYTES.saturating_sub(block.len() + header.len() + footer.len());
+            if room == 0 {
+                break;
+            }
+            block.push_str(&header);
+            if content.len() <= room {
+                block.push_str(content.trim_end());
+            } else {
+                let omitted = "\n[... the rest of this file is omitted ...]";
+                let mut end = room.saturating_sub(omitted.len());
+                while !content.is_char_boundary(end) {
+                    end -= 1;
+                }
+                let end = content[..end].rfind('\n').unwrap_or(0);
+                block.push_str(&content[..end]);
+                block.push_str(omitted);
+            }
+            block.push_str(footer);
+        }
+        Some(block)
+    }
+}
+
+/// The member directories that `patterns` select under `dir`: literal paths as they are,
+/// globs by walking the tree. Patterns starting with `!` take matches away again.
+fn expand(dir: &Path, patterns: &[String], kind: WorkspaceKind) -> Vec<Member> {
+    let (excluded, included): (Vec<&str>, Vec<&str>) = patterns
+        .iter()
+        .map(|pattern| pattern.trim())
+        .filter(|pattern| !pattern.is_empty())
+        .partition(|pattern| pattern.starts_with('!'));
+
+    let mut builder = OverrideBuilder::new(dir);
+    // Anchored at the root, as workspace patterns are, with later exclusions winning
+    for pattern in included.iter().chain(&excluded) {
+        let (negate, pattern) = match pattern.strip_prefix('!') {
+            Some(pattern) => ("!", pattern),
+            None => ("", *pattern),
+        };
+        let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+        // As in pnpm and npm, `dir/**` also matches `dir` itself
+        let base = pattern.strip_suffix("/**").filter(|base| !base.is_empty());
+        for pattern in std::iter::once(pattern).chain(base) {
+            if let Err(e) = builder.add(&format!("{}/{}", negate, pattern)) {
+                tracing::warn!("Ignoring workspace pattern `{}`: {}", pattern, e);
+            }
+        }
+    }
+    let Ok(matcher) = builder.build() else {
+        return Vec::new();
+    };
+    let selected = |path: &Path| matcher.matched(path, true).is_whitelist() && path.join(kind.manifest()).is_file();
+
+    let mut paths = Vec::new();
+    let is_glob = |pattern: &&str| pattern.contains(['*', '?', '[', '{']);
+    for pattern in included.iter().filter(|pattern| !is_glob(pattern)) {
+        let path = dir.join(pattern.trim_start_matches("./"));
+        if selected(&path) {
+            paths.push(path);
+        }
+    }
+    let globs: Vec<&str> = included.iter().copied().filter(|pattern| is_glob(pattern)).collect();
+    if !globs.is_empty() {
+        // Deep enough for the longest pattern, or the whole tree under `**`
+        let depth = globs
+            .iter()
+            .map(|pattern| (!pattern.contains("**")).then(|| Path::new(pattern).components().count()))
+            .try_fold(0, |deepest, depth| depth.map(|depth| deepest.max(depth)));
+        let walker = WalkBuilder::new(dir)
+            .standard_filters(true)
+            .max_depth(depth)
+            .filter_entry(|entry| entry.file_name() != "node_modules")
+            .build();
+        for entry in walker.flatten() {
+            if entry.depth() > 0 && entry.file_type().is_some_and(|t| t.is_dir()) && selected(entry.path()) {
+                paths.push(entry.into_path());
+            }
+        }
+    }
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let relative = normalize(path.strip_prefix(dir).ok()?);
+            // The root itself is the workspace, not one of its members
+            if relative.as_os_str().is_empty() {
+                return None;
+            }
+            let name = package_name(&path, kind).unwrap_or_else(|| slash_path(&relative));
+            Some(Member { name, path: relative, kind })
+        })
+        .collect()
+}
+
+/// The `name` in the manifest of the member at `path`
+fn package_name(path: &Path, kind: WorkspaceKind) -> Option<String> {
+    let manifest = fs::read_to_string(path.join(kind.manifest())).ok()?;
+    let name = match kind {
+        WorkspaceKind::Cargo => manifest
+            .parse::<toml::Table>()
+            .ok()?
+            .get("package")?
+            .get("name")?
+            .as_str()?
+            .to_string(),
+        WorkspaceKind::Npm | WorkspaceKind::Pnpm => serde_json::from_str::<serde_json::Value>(&manifest)
+            .ok()?
+            .get("name")?
+            .as_str()?
+            .to_string(),
+    };
+    Some(name)
+}
+
+/// The entries of the `packages` list in a pnpm-workspace.yaml, which is all techdocs
+/// needs from it, so YAML is read only as far as a block list of strings
+fn pnpm_packages(config: &str) -> Vec<String> {
+    let mut packages = Vec::new();
+    let mut in_packages = false;
+    for line in config.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with([' ', '\t', '-']) {
+            in_packages = trimmed.trim_end_matches(':').trim() == "packages";
+            continue;
+        }
+        if let (true, Some(item)) = (in_packages, trimmed.strip_prefix('-')) {
+            let item = item.split(" #").next().unwrap_or_default().trim();
+            packages.push(item.trim_matches(['\'', '"']).to_string());
+        }
+    }
+    packages
+}
+
+/// `path` without `.` components
+fn normalize(path: &Path) -> PathBuf {
+    path.components().filter(|component| *component != Component::CurDir).collect()
+}
+
+/// `path` with `/` between its components on every platform
+fn slash_path(path: &Path) -> String {
+    let parts: Vec<_> = path.components().map(|component| component.as_os_str().to_string_lossy()).collect();
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    fn crate_manifest(name: &str) -> String {
+        format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name)
+    }
+
+    fn names(members: &[Member]) -> Vec<(&str, String)> {
+        members.iter().map(|member| (member.name.as_str(), member.slash_path())).collect()
+    }
+
+    #[test]
+    fn cargo_member_globs_are_expanded() {
+        let tree = FixtureTree::new()
+            .file("Cargo.toml", "[workspace]\nmembers = [\"crates/*\", \"tools/gen\"]\nexclude = [\"crates/scratch\"]\n")
+            .file("crates/core/Cargo.toml", crate_manifest("demo-core"))
+            .file("crates/cli/Cargo.toml", crate_manifest("demo-cli"))
+            .file("crates/scratch/Cargo.toml", crate_manifest("scratch"))
+            .file("crates/docs/README.md", "not a crate\n")
+            .file("tools/gen/Cargo.toml", "[lib]\npath = \"gen.rs\"\n");
+        let members = detect_workspace(tree.path()).unwrap();
+        assert_eq!(
+            names(&members),
+            [("demo-cli", "crates/cli".into()), ("demo-core", "crates/core".into()), ("tools/gen", "tools/gen".into())]
+        );
+        assert!(members.iter().all(|member| member.kind == WorkspaceKind::Cargo));
+        assert_eq!(members[1].include_patterns()[..2], ["/crates/core/**".to_string(), "/Cargo.toml".to_string()]);
+    }
+
+    #[test]
+    fn npm_and_pnpm_workspaces_are_read() {
+        let package = |name: &str| format!(r#"{{"name": "{}"}}"#, name);
+        let tree = FixtureTree::new()
+            .file("package.json", r#"{"name": "root", "workspaces": {"packages": ["packages/**"]}}"#)
+            .file("pnpm-workspace.yaml", "packages:\n  - 'packages/**' # all of them\n  - apps/web\n  - '!packages/legacy'\n")
+            .file("packages/ui/package.json", package("@demo/ui"))
+            .file("packages/ui/icons/package.json", package("@demo/icons"))
+            .file("packages/legacy/package.json", package("legacy"))
+            .file("apps/web/package.json", package("web"));
+        let members = detect_workspace(tree.path()).unwrap();
+        // Every package listed by npm, plus the app only pnpm lists, once each
+        assert_eq!(
+            names(&members),
+            [
+                ("web", "apps/web".into()),
+                ("legacy", "packages/legacy".into()),
+                ("@demo/ui", "packages/ui".into()),
+                ("@demo/icons", "packages/ui/icons".into()),
+            ]
+        );
+        assert_eq!(pnpm_packages("packages:\n  - 'packages/**' # all of them\n"), ["packages/**"]);
+    }
+
+    #[test]
+    fn a_single_package_is_not_a_workspace() {
+        let tree = FixtureTree::new()
+            .file("Cargo.toml", crate_manifest("demo"))
+            .file("package.json", r#"{"name": "demo"}"#)
+            .file("sub/Cargo.toml", crate_manifest("sub"));
+        assert_eq!(detect_workspace(tree.path()), None);
+        let error = workspace_member(tree.path(), "sub").unwrap_err();
+        assert!(matches!(&error, TechDocsError::Config(message) if message.contains("is not a Cargo, npm, or pnpm workspace")), "{}", error);
+    }
+
+    #[test]
+    fn members_are_found_by_name_or_path() {
+        let tree = FixtureTree::new()
+            .file("Cargo.toml", "[workspace]\nmembers = [\"crates/*\"]\n")
+            .file("crates/core/Cargo.toml", crate_manifest("demo-core"))
+            .file("crates/cli/Cargo.toml", crate_manifest("demo-cli"));
+        let members = detect_workspace(tree.path()).unwrap();
+        assert_eq!(find_member(&members, "demo-core").unwrap().slash_path(), "crates/core");
+        assert_eq!(find_member(&members, "./crates/cli/").unwrap().name, "demo-cli");
+
+        let error = workspace_member(tree.path(), "core").unwrap_err();
+        assert!(
+            matches!(&error, TechDocsError::Config(message)
+                if message == "no workspace member named `core`; members: demo-cli, demo-core"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn a_member_gets_the_workspace_root_as_context() {
+        let tree = FixtureTree::new()
+            .file("Cargo.toml", "[workspace]\nmembers = [\"crates/*\"]\n")
+            .file("README.md", "# Demo workspace\n")
+            .file("crates/core/Cargo.toml", crate_manifest("demo-core"));
+        let member = tree.join("crates/core");
+        assert_eq!(find_context_root(&member), Some(tree.path().canonicalize().unwrap()));
+
+        let context = ParentContext::read(tree.path(), &member).unwrap();
+        assert_eq!(context.target, "crates/core");
+        let files: Vec<_> = context.files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(files, ["Cargo.toml", "README.md"]);
+        let block = context.prompt_block().unwrap();
+        assert!(block.contains("its `crates/core/` directory"), "{}", block);
+        assert!(block.contains("Context file: README.md\n```md\n# Demo workspace\n```"), "{}", block);
+        assert_eq!(ParentContext::read(tree.path(), tree.path()), None);
+    }
+}
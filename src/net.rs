@@ -0,0 +1,93 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// The address to connect to for `host` and `port`. With `public_only`, fails when the
+/// host resolves to any address that is not [`is_public`], so a name cannot smuggle in
+/// one private address among public ones. Connecting to the returned address rather than
+/// resolving again keeps a changed DNS answer from reaching another address.
+pub async fn resolve(host: &str, port: u16, public_only: bool) -> Result<SocketAddr, String> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("cannot resolve the host: {}", e))?
+        .collect();
+    let addr = *addrs.first().ok_or("the host has no addresses")?;
+    if public_only {
+        if let Some(private) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+            return Err(format!("the host resolves to the non-public address {}", private.ip()));
+        }
+    }
+    Ok(addr)
+}
+
+/// Whether `ip` is reachable on the public internet, rather than loopback, private,
+/// link-local, or otherwise reserved
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Carrier-grade NAT, 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments, 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking, 198.18.0.0/15
+        || (a == 198 && (18..20).contains(&b))
+        // Reserved, 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // Link-local, fe80::/10
+        || (first & 0xffc0) == 0xfe80
+        // Documentation, 2001:db8::/32
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_and_reserved_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0",
+            "::1", "fd00::1", "fe80::1", "::ffff:10.0.0.1", "2001:db8::1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["93.184.216.34", "8.8.8.8", "2606:4700:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn names_resolving_to_private_addresses_are_refused_when_public_only() {
+        for host in ["localhost", "127.0.0.1", "[::1]", "169.254.169.254"] {
+            let error = resolve(host, 443, true).await.unwrap_err();
+            assert!(error.contains("non-public address"), "{}: {}", host, error);
+        }
+        let addr = resolve("127.0.0.1", 8080, false).await.unwrap();
+        assert_eq!(addr, "127.0.0.1:8080".parse().unwrap());
+    }
+}
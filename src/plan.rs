@@ -0,0 +1,1660 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, DEFAULT_MAX_WALK_ENTRIES, DEFAULT_MAX_WALK_SECS};
+use crate::filters::{FilterPreset, PresetMatcher};
+use crate::directives::{find_directive, read_directive, Directive, Priority, DIRECTIVE_SCAN_BYTES};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::license::{detect_license, license_fact, LicenseInfo};
+use crate::progress::{no_progress, ProgressEvent, ProgressFn};
+use crate::project::{is_manifest, ProjectType};
+use crate::{
+    code_language, exclude_overrides, normalize_content, normalize_content_into, number_lines_into,
+    read_file_into, write_file_content, FileContext, Result, TechDocsError,
+};
+
+/// First line of [`PromptPlan::metadata_block`]
+pub(crate) const METADATA_HEADING: &str = "Project metadata:\n";
+
+/// Why a file was left out of the prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The file matches an exclusion of the [`FilterPreset`], such as a build output or
+    /// editor directory
+    BuildArtifact,
+    /// The file is larger than the per-file size limit
+    TooLarge,
+    /// The total size budget was reached before this file
+    TotalBudget,
+    /// The maximum number of files was reached before this file
+    FileLimit,
+    /// Its directory already gave the most files allowed from one directory
+    DirLimit,
+    /// It is reached through a symlink and its real path is outside the walked directory
+    OutsideRoot,
+}
+
+impl SkipReason {
+    /// The reason as it appears in JSON
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SkipReason::BuildArtifact => "build_artifact",
+            SkipReason::TooLarge => "too_large",
+            SkipReason::TotalBudget => "total_budget",
+            SkipReason::FileLimit => "file_limit",
+            SkipReason::DirLimit => "dir_limit",
+            SkipReason::OutsideRoot => "outside_root",
+        }
+    }
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::BuildArtifact => write!(f, "excluded by filter preset"),
+            SkipReason::TooLarge => write!(f, "too large"),
+            SkipReason::TotalBudget => write!(f, "total size limit reached"),
+            SkipReason::FileLimit => write!(f, "file count limit reached"),
+            SkipReason::DirLimit => write!(f, "directory file limit reached"),
+            SkipReason::OutsideRoot => write!(f, "outside source root"),
+        }
+    }
+}
+
+/// A file considered for the prompt and what was decided about it
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedFile {
+    /// Path to the file, including the walked directory
+    pub path: PathBuf,
+    /// Size of the file in bytes
+    pub size: u64,
+    /// Why the file was skipped, or `None` if it is included
+    pub skipped: Option<SkipReason>,
+    /// Estimated tokens the file contributes to the prompt, including its header and fence
+    pub estimated_tokens: u64,
+    /// The part of an oversized file that is sent, under [`LargeFiles::HeadTail`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<Truncation>,
+    /// The `techdocs:` comment at the top of the file, if it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub directive: Option<Directive>,
+    /// Bytes of the file's content in the prompt: normalized, cut when truncated, and
+    /// with line numbers if asked for. Equal to `size` for a file that was not read.
+    pub prompt_size: u64,
+}
+
+impl PlannedFile {
+    /// The file at `path`, whose bytes are `content`, included as `options` would show
+    /// it and cut as `truncated` says. For a plan built from a list of files rather than
+    /// a walk, see [`PromptPlan::from_files`].
+    pub fn included(path: PathBuf, content: &[u8], truncated: Option<Truncation>, options: &PromptOptions) -> Self {
+        let scanned = &content[..content.len().min(DIRECTIVE_SCAN_BYTES as usize)];
+        let directive = find_directive(scanned).and_then(|directive| directive.ok());
+        let mut buffers = ContentBuffers { read: content.to_vec(), ..ContentBuffers::default() };
+        let prompt_size = buffers.shown(options.normalize, truncated, options.line_numbers).len() as u64;
+        Self {
+            estimated_tokens: estimate_tokens(prompt_size + prompt_overhead(&path)),
+            path,
+            size: content.len() as u64,
+            skipped: None,
+            truncated,
+            directive,
+            prompt_size,
+        }
+    }
+
+    /// The file at `path`, `size` bytes long, left out for `reason`
+    pub fn skipped(path: PathBuf, size: u64, reason: SkipReason) -> Self {
+        Self {
+            path,
+            size,
+            skipped: Some(reason),
+            estimated_tokens: 0,
+            truncated: None,
+            directive: None,
+            prompt_size: size,
+        }
+    }
+
+    pub fn is_included(&self) -> bool {
+        self.skipped.is_none()
+    }
+}
+
+/// Append a file's normalized `content` to `out` as the prompt shows it: cut by
+/// `truncated`, and with line numbers. `cut` holds the cut content until it is numbered.
+fn append_shown(
+    content: &[u8],
+    truncated: Option<Truncation>,
+    line_numbers: bool,
+    cut: &mut Vec<u8>,
+    out: &mut Vec<u8>,
+) {
+    match (truncated, line_numbers) {
+        (None, false) => out.extend_from_slice(content),
+        (Some(truncation), false) => truncation.apply_into(content, out),
+        (None, true) => number_lines_into(content, None, out),
+        (Some(truncation), true) => {
+            cut.clear();
+            truncation.apply_into(content, cut);
+            number_lines_into(cut, Some((truncation.head_lines, truncation.omitted_lines)), out);
+        }
+    }
+}
+
+/// Buffers a file's content passes through on its way into the prompt. They are reused
+/// from file to file, so once they have grown to the largest file, reading, cleaning
+/// up, cutting, and numbering another allocates nothing.
+#[derive(Default)]
+struct ContentBuffers {
+    read: Vec<u8>,
+    normalized: Vec<u8>,
+    cut: Vec<u8>,
+    shown: Vec<u8>,
+}
+
+impl ContentBuffers {
+    /// What the prompt shows of the file in `read`: cleaned up by `normalize`, cut by
+    /// `truncated`, and with line numbers
+    fn shown(&mut self, normalize: Normalize, truncated: Option<Truncation>, line_numbers: bool) -> &[u8] {
+        let content = normalize.apply_to(&self.read, &mut self.normalized);
+        if truncated.is_none() && !line_numbers {
+            return content;
+        }
+        self.shown.clear();
+        append_shown(content, truncated, line_numbers, &mut self.cut, &mut self.shown);
+        &self.shown
+    }
+}
+
+/// What the prompt shows of the files a walk read to measure, in one buffer, so writing
+/// the prompt does not read and clean them up again. Only included files are kept, and
+/// only under [`PromptOptions::keep_content`].
+#[derive(Clone, Default)]
+pub(crate) struct KeptContent {
+    bytes: Vec<u8>,
+    files: HashMap<PathBuf, Range<usize>>,
+}
+
+impl KeptContent {
+    fn get(&self, path: &Path) -> Option<&[u8]> {
+        self.files.get(path).map(|range| &self.bytes[range.clone()])
+    }
+}
+
+impl std::fmt::Debug for KeptContent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeptContent").field("files", &self.files.len()).field("bytes", &self.bytes.len()).finish()
+    }
+}
+
+/// What happens to a file larger than the per-file size limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LargeFiles {
+    /// Leave it out of the prompt
+    #[default]
+    Skip,
+    /// Send its first and last lines, as much as the limit allows, with a marker where
+    /// the middle was left out. `head_percent` of the limit goes to the first lines.
+    HeadTail { head_percent: u8 },
+}
+
+/// How file content is cleaned up before it goes into the prompt. The library sends
+/// content as it is unless asked otherwise; techdocs-cli and techdocs-api ask for
+/// [`Normalize::Full`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalize {
+    /// Send the bytes as they are
+    #[default]
+    Off,
+    /// Strip a leading byte order mark and end every line with LF
+    LineEndings,
+    /// Also trim spaces and tabs at the end of each line
+    Full,
+}
+
+impl Normalize {
+    /// `content` cleaned up as this says
+    pub fn apply(self, content: Vec<u8>) -> Vec<u8> {
+        match self {
+            Self::Off => content,
+            Self::LineEndings => normalize_content(&content, false),
+            Self::Full => normalize_content(&content, true),
+        }
+    }
+
+    /// [`Normalize::apply`] into `buffer`, or `content` itself when it is sent as it is
+    pub(crate) fn apply_to<'a>(self, content: &'a [u8], buffer: &'a mut Vec<u8>) -> &'a [u8] {
+        match self {
+            Self::Off => return content,
+            Self::LineEndings => normalize_content_into(content, false, buffer),
+            Self::Full => normalize_content_into(content, true, buffer),
+        }
+        buffer
+    }
+}
+
+/// Share of the per-file limit [`LargeFiles::HeadTail`] gives the first lines unless told otherwise
+pub const DEFAULT_HEAD_PERCENT: u8 = 60;
+
+/// The first and last lines of a file kept under [`LargeFiles::HeadTail`]. Both ends
+/// are cut at line boundaries, so multi-byte characters are never split.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Truncation {
+    /// Bytes kept from the start of the file
+    pub head_bytes: u64,
+    /// Lines kept from the start of the file
+    pub head_lines: usize,
+    /// Offset the kept end of the file starts at
+    pub tail_start: u64,
+    /// Lines left out between them
+    pub omitted_lines: usize,
+}
+
+impl Truncation {
+    /// How `content` is cut to fit `limit` bytes with `head_percent` of them for the first
+    /// lines, or `None` when it already fits
+    pub fn head_tail(content: &[u8], limit: u64, head_percent: u8) -> Option<Self> {
+        let len = content.len() as u64;
+        if len <= limit {
+            return None;
+        }
+        let head_limit = (limit as u128 * head_percent.min(100) as u128 / 100) as u64;
+        let tail_limit = limit - head_limit;
+
+        // Whole lines up to the head limit
+        let head = content[..head_limit as usize].iter().rposition(|&b| b == b'\n').map_or(0, |end| end + 1);
+        // Whole lines from where the tail limit begins
+        let from = (len - tail_limit) as usize;
+        let tail = match from {
+            0 => 0,
+            _ => content[from - 1..].iter().position(|&b| b == b'\n').map_or(content.len(), |at| from + at),
+        }
+        .max(head);
+
+        let omitted = &content[head..tail];
+        let unterminated = omitted.last().is_some_and(|&b| b != b'\n');
+        let omitted_lines = omitted.iter().filter(|&&b| b == b'\n').count() + usize::from(unterminated);
+        Some(Self {
+            head_bytes: head as u64,
+            head_lines: content[..head].iter().filter(|&&b| b == b'\n').count(),
+            tail_start: tail as u64,
+            omitted_lines,
+        })
+    }
+
+    /// The line put where the middle of the file was
+    pub fn marker(&self) -> String {
+        let lines = if self.omitted_lines == 1 { "line" } else { "lines" };
+        format!("... [{} {} omitted] ...\n", thousands(self.omitted_lines), lines)
+    }
+
+    /// `content` with the middle replaced by the marker. Offsets past the end, from a file
+    /// that shrank since it was planned, are clamped.
+    pub fn apply(&self, content: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.apply_into(content, &mut out);
+        out
+    }
+
+    /// [`Truncation::apply`] appended to `out`
+    pub(crate) fn apply_into(&self, content: &[u8], out: &mut Vec<u8>) {
+        let head = (self.head_bytes as usize).min(content.len());
+        let tail = (self.tail_start as usize).clamp(head, content.len());
+        out.reserve(head + content.len() - tail + 32);
+        out.extend_from_slice(&content[..head]);
+        out.extend_from_slice(self.marker().as_bytes());
+        out.extend_from_slice(&content[tail..]);
+    }
+}
+
+/// `1240` as `1,240`
+fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+/// What went into a prompt
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct PromptStats {
+    pub files_included: usize,
+    pub files_skipped: usize,
+    /// Bytes of file content in the prompt
+    pub included_bytes: u64,
+    /// Whether the total size budget left files out
+    pub budget_reached: bool,
+}
+
+impl From<&PromptPlan> for PromptStats {
+    fn from(plan: &PromptPlan) -> Self {
+        Self {
+            files_included: plan.included().count(),
+            files_skipped: plan.skipped().count(),
+            included_bytes: plan.included_bytes(),
+            budget_reached: plan.budget_reached,
+        }
+    }
+}
+
+/// The selection of files for a prompt. Files are read only when their content changes
+/// what they take up: to normalize, cut, or number it. Under
+/// [`PromptOptions::keep_content`], what an included file was read as is kept for
+/// writing the prompt. Building the prompt from a plan produces exactly what a real run
+/// sends.
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptPlan {
+    /// Directory that was walked
+    pub root: PathBuf,
+    /// Every file considered, in walk order
+    pub files: Vec<PlannedFile>,
+    /// Whether the total size budget cut the file list short
+    pub budget_reached: bool,
+    /// License detected in the project root, stated in the prompt as a fact
+    pub license: Option<LicenseInfo>,
+    /// Kinds of project the considered files show, stated in the prompt as a fact
+    pub project: ProjectType,
+    /// Whether the prompt lists the files by directory, as set with
+    /// [`PromptOptions::group_by_dir`]
+    pub group_by_dir: bool,
+    /// Whether the prompt numbers the lines of each file, as set with
+    /// [`PromptOptions::line_numbers`]
+    pub line_numbers: bool,
+    /// How file content is cleaned up, as set with [`PromptOptions::normalize`]
+    pub normalize: Normalize,
+    /// Content of the included files the walk read, as the prompt shows it, when
+    /// [`PromptOptions::keep_content`] asked for it
+    #[serde(skip)]
+    pub(crate) kept: Arc<KeptContent>,
+}
+
+/// Files of one directory in a prompt grouped by directory
+#[derive(Debug, Clone)]
+pub struct DirSection<'a> {
+    /// Top-level directory relative to the root and separated by `/`, or the top-level
+    /// directory and its subdirectory when the top-level one holds no files itself, as
+    /// in `services/auth`; `.` for files directly in the root
+    pub name: String,
+    /// Included files, in walk order
+    pub files: Vec<&'a PlannedFile>,
+}
+
+impl DirSection<'_> {
+    /// The `## name (N files, size)` line written before the section's files
+    pub fn header(&self) -> String {
+        let bytes: u64 = self.files.iter().map(|file| file.prompt_size).sum();
+        let count = match self.files.len() {
+            1 => "1 file".to_string(),
+            n => format!("{} files", n),
+        };
+        format!("\n## {} ({}, {})\n", self.name, count, format_size(bytes))
+    }
+
+    /// Whether the section holds a manifest, an entry point, or a file marked important,
+    /// which puts it first
+    fn priority(&self) -> u8 {
+        self.files.iter().map(|file| file_priority(&file.path, file.directive)).min().unwrap_or(2)
+    }
+}
+
+/// 0 for a manifest, 1 for an entry point, and 2 for any other file, unless a
+/// directive says otherwise: 0 for `include` or `priority=high` and 3 for `priority=low`
+fn file_priority(path: &Path, directive: Option<Directive>) -> u8 {
+    match directive {
+        Some(Directive::Include | Directive::Priority(Priority::High)) => return 0,
+        Some(Directive::Priority(Priority::Low)) => return 3,
+        _ => {}
+    }
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if is_manifest(name) => 0,
+        Some(name) if ENTRY_POINTS.contains(&name) => 1,
+        _ => 2,
+    }
+}
+
+/// Which files go into a prompt and how large it may grow. The defaults match
+/// [`Config::default`]; set only what differs, as in
+/// `PromptOptions::new().exclude(["*.lock"]).max_file_kb(50)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptOptions {
+    /// Patterns to exclude, in .gitignore format
+    pub exclude: Vec<String>,
+    /// Glob patterns a file must match to be considered, on top of the preset's; when
+    /// both are empty every file is considered
+    pub include: Vec<String>,
+    /// Named rules leaving out build output and the like, applied after `exclude`
+    pub preset: FilterPreset,
+    /// Maximum number of files to include; later files are skipped
+    pub max_files: Option<usize>,
+    /// Maximum number of files to include from any one directory, not counting its
+    /// subdirectories. The rest are named in a note after the directory's last file.
+    pub max_files_per_dir: Option<usize>,
+    /// Skip files larger than this
+    pub max_file_size_kb: u64,
+    /// Stop adding files once their content reaches this size
+    pub max_total_size_mb: u64,
+    /// List the files under a heading for each directory instead of in one run; the
+    /// same files are included either way
+    pub group_by_dir: bool,
+    /// What to do with files over `max_file_size_kb`
+    pub large_files: LargeFiles,
+    /// Number the lines of each file, as in `  42 | fn main() {`
+    pub line_numbers: bool,
+    /// How file content is cleaned up; sizes and budgets count the cleaned content
+    pub normalize: Normalize,
+    /// Keep what the prompt shows of each file the walk reads, so writing the prompt
+    /// does not read it again. The plan then holds up to the total size budget in
+    /// memory for as long as it lives, so this suits a prompt written once, right away.
+    pub keep_content: bool,
+    /// Walk into directories that symlinks point at
+    pub follow_symlinks: bool,
+    /// Read files whose real path, once symlinks are resolved, is outside the walked
+    /// directory. Off by default, so a link to `/etc` or `~/.ssh` in an untrusted tree
+    /// cannot pull those files into the prompt.
+    pub allow_external_symlinks: bool,
+    /// How many directories deep the walk goes, 1 being the walked directory's own files
+    pub max_depth: Option<usize>,
+    /// Fail with [`TechDocsError::SourceTooLarge`] once the walk finds more files and
+    /// directories than this, so a path pointing at `$HOME` by mistake is given up on
+    /// early instead of walked for minutes
+    pub max_walk_entries: Option<usize>,
+    /// Fail with [`TechDocsError::SourceTooLarge`] once selecting files takes longer
+    pub max_walk_time: Option<Duration>,
+}
+
+impl Default for PromptOptions {
+    fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
+
+impl PromptOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The filters and size limits of a loaded configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            exclude: config.exclude.clone(),
+            include: config.include.clone(),
+            // Config::apply refuses unknown presets, so this only falls back for a
+            // Config built by hand
+            preset: FilterPreset::find(&config.preset).unwrap_or_default(),
+            max_files: None,
+            max_files_per_dir: None,
+            max_file_size_kb: config.max_file_size_kb,
+            max_total_size_mb: config.max_total_size_mb,
+            group_by_dir: false,
+            large_files: LargeFiles::Skip,
+            line_numbers: false,
+            normalize: Normalize::default(),
+            keep_content: false,
+            follow_symlinks: false,
+            allow_external_symlinks: false,
+            max_depth: config.max_depth.map(|depth| usize::try_from(depth).unwrap_or(usize::MAX)),
+            max_walk_entries: walk_ceiling(config.max_walk_entries)
+                .map(|entries| usize::try_from(entries).unwrap_or(usize::MAX)),
+            max_walk_time: walk_ceiling(config.max_walk_secs).map(Duration::from_secs),
+        }
+    }
+
+    /// No size limits: every file that passes the filters is included
+    pub fn unlimited() -> Self {
+        Self::new().max_file_kb(u64::MAX).max_total_mb(u64::MAX)
+    }
+
+    /// Add patterns to exclude, in .gitignore format
+    pub fn exclude<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Add glob patterns a file must match to be considered
+    pub fn include<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.include.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn preset(mut self, preset: FilterPreset) -> Self {
+        self.preset = preset;
+        self
+    }
+
+    /// The include patterns of the preset followed by those of the options
+    pub fn effective_include(&self) -> Vec<String> {
+        self.preset.include.iter().map(|pattern| pattern.to_string()).chain(self.include.iter().cloned()).collect()
+    }
+
+    pub fn max_files(mut self, max_files: usize) -> Self {
+        self.max_files = Some(max_files);
+        self
+    }
+
+    pub fn max_files_per_dir(mut self, max_files: usize) -> Self {
+        self.max_files_per_dir = Some(max_files);
+        self
+    }
+
+    pub fn max_file_kb(mut self, kb: u64) -> Self {
+        self.max_file_size_kb = kb;
+        self
+    }
+
+    pub fn max_total_mb(mut self, mb: u64) -> Self {
+        self.max_total_size_mb = mb;
+        self
+    }
+
+    pub fn group_by_dir(mut self, group: bool) -> Self {
+        self.group_by_dir = group;
+        self
+    }
+
+    pub fn large_files(mut self, policy: LargeFiles) -> Self {
+        self.large_files = policy;
+        self
+    }
+
+    pub fn line_numbers(mut self, line_numbers: bool) -> Self {
+        self.line_numbers = line_numbers;
+        self
+    }
+
+    pub fn normalize(mut self, normalize: Normalize) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    pub fn keep_content(mut self, keep: bool) -> Self {
+        self.keep_content = keep;
+        self
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Lower the walk ceilings to at most `entries` and `time`, keeping any that are
+    /// already lower
+    pub fn walk_ceilings(mut self, entries: usize, time: Duration) -> Self {
+        self.max_walk_entries = Some(self.max_walk_entries.map_or(entries, |max| max.min(entries)));
+        self.max_walk_time = Some(self.max_walk_time.map_or(time, |max| max.min(time)));
+        self
+    }
+
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    pub fn allow_external_symlinks(mut self, allow: bool) -> Self {
+        self.allow_external_symlinks = allow;
+        self
+    }
+}
+
+/// Narrows a plan beyond the exclude patterns and size limits
+#[deprecated(since = "0.1.0", note = "set `include` and `max_files` on `PromptOptions`")]
+#[derive(Debug, Clone, Default)]
+pub struct FileSelection {
+    /// Glob patterns a file must match to be considered; empty considers every file
+    pub include: Vec<String>,
+    /// Maximum number of files to include; later files are skipped
+    pub max_files: Option<usize>,
+}
+
+/// File names that mark where a program or library starts, for ordering
+/// [`DirSection`]s
+const ENTRY_POINTS: &[&str] = &[
+    "main.rs", "lib.rs", "main.go", "main.py", "__main__.py", "app.py", "index.js", "index.ts", "main.ts",
+];
+
+/// Text appended to the prompt when the total size budget is reached
+pub const BUDGET_WARNING: &str = "Warning: Total size limit reached, some files omitted.";
+
+/// Rough token estimate for text of the given byte length (about four bytes per token)
+pub fn estimate_tokens(bytes: u64) -> u64 {
+    bytes.div_ceil(4)
+}
+
+impl PromptPlan {
+    /// Walk `dir` and decide which files `options` lets into the prompt
+    pub fn collect(dir: &Path, options: &PromptOptions) -> Result<Self> {
+        Self::collect_with_progress(dir, options, &no_progress)
+    }
+
+    /// Walk `dir` and decide which files fit the per-file and total size limits
+    #[deprecated(since = "0.1.0", note = "use `PromptPlan::collect` with `PromptOptions`")]
+    pub fn build(
+        dir: &Path,
+        exclude_patterns: &[String],
+        max_file_size_kb: u64,
+        max_total_size_mb: u64,
+    ) -> Result<Self> {
+        Self::collect(dir, &positional_options(exclude_patterns, max_file_size_kb, max_total_size_mb))
+    }
+
+    /// Like [`PromptPlan::build`], reporting each visited file to `progress`
+    #[deprecated(since = "0.1.0", note = "use `PromptPlan::collect_with_progress` with `PromptOptions`")]
+    pub fn build_with_progress(
+        dir: &Path,
+        exclude_patterns: &[String],
+        max_file_size_kb: u64,
+        max_total_size_mb: u64,
+        progress: ProgressFn<'_>,
+    ) -> Result<Self> {
+        let options = positional_options(exclude_patterns, max_file_size_kb, max_total_size_mb);
+        Self::collect_with_progress(dir, &options, progress)
+    }
+
+    /// Like [`PromptPlan::build_with_progress`], considering only the files picked by `selection`
+    #[deprecated(since = "0.1.0", note = "use `PromptPlan::collect_with_progress` with `PromptOptions`")]
+    #[allow(deprecated)]
+    pub fn build_selected(
+        dir: &Path,
+        exclude_patterns: &[String],
+        selection: &FileSelection,
+        max_file_size_kb: u64,
+        max_total_size_mb: u64,
+        progress: ProgressFn<'_>,
+    ) -> Result<Self> {
+        let options = PromptOptions {
+            include: selection.include.clone(),
+            max_files: selection.max_files,
+            ..positional_options(exclude_patterns, max_file_size_kb, max_total_size_mb)
+        };
+        Self::collect_with_progress(dir, &options, progress)
+    }
+
+    /// Like [`PromptPlan::collect`], reporting each visited file to `progress`
+    pub fn collect_with_progress(dir: &Path, options: &PromptOptions, progress: ProgressFn<'_>) -> Result<Self> {
+        Self::collect_cancellable(dir, options, progress, &|| false)
+    }
+
+    /// Like [`PromptPlan::collect_with_progress`], asking `cancelled` before each file and
+    /// failing with [`TechDocsError::Cancelled`] once it returns true
+    pub fn collect_cancellable(
+        dir: &Path,
+        options: &PromptOptions,
+        progress: ProgressFn<'_>,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<Self> {
+        let overrides = exclude_overrides(dir, &options.exclude)?;
+        // Kept apart from the exclude overrides: a whitelist override would also
+        // re-include files that .gitignore leaves out
+        let include_patterns = options.effective_include();
+        let include = include_overrides(dir, &include_patterns)?;
+        let preset = PresetMatcher::new(dir, &options.preset)?;
+
+        let max_file_size = options.max_file_size_kb.saturating_mul(1024);
+        let max_total_size = options.max_total_size_mb.saturating_mul(1024 * 1024);
+        let mut total_size = 0;
+        let mut budget_reached = false;
+        let mut files = Vec::new();
+        let mut files_included = 0;
+        let mut buffers = ContentBuffers::default();
+        let mut kept = KeptContent::default();
+
+        let walker = WalkBuilder::new(dir)
+            .standard_filters(true)
+            .follow_links(options.follow_symlinks)
+            .max_depth(options.max_depth)
+            .overrides(overrides)
+            .build();
+        let started = Instant::now();
+        let mut entries_seen = 0;
+        // Real paths are compared against the real root, which may itself be a symlink
+        let root = dir.canonicalize().file_context("resolve", dir)?;
+
+        // Every file is seen before any is decided on, so each directory's best files can
+        // be picked for max_files_per_dir
+        let mut entries = Vec::new();
+        // Each unknown directive is reported once, however many files carry it
+        let mut unknown_directives = HashSet::new();
+        for entry in walker {
+            if cancelled() {
+                tracing::debug!("Collection cancelled after {} files", entries.len());
+                return Err(TechDocsError::Cancelled);
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                // A link back to a directory being walked is left out, not fatal
+                Err(e) if is_loop(&e) => {
+                    tracing::warn!("Skipping a symlink loop: {}", e);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            entries_seen += 1;
+            progress(&ProgressEvent::Scan { entries_seen });
+            if options.max_walk_entries.is_some_and(|max| entries_seen > max) {
+                let reason = format!("{} holds more than {} files and directories", dir.display(), entries_seen - 1);
+                return Err(source_too_large(&reason));
+            }
+            check_walk_time(dir, options, started)?;
+            let path = entry.path();
+
+            if !path.is_file() {
+                continue;
+            }
+
+            if !include_patterns.is_empty() && !include.matched(path, false).is_whitelist() {
+                continue;
+            }
+
+            // Below a followed directory link every path may lead elsewhere; otherwise
+            // only a link to a file can
+            let mut outside = false;
+            if !options.allow_external_symlinks && (options.follow_symlinks || entry.path_is_symlink()) {
+                match path.canonicalize() {
+                    Ok(real) if real.starts_with(&root) => {}
+                    Ok(real) => {
+                        tracing::warn!(
+                            "Skipping {}: it resolves to {}, outside {}",
+                            path.display(),
+                            real.display(),
+                            root.display()
+                        );
+                        outside = true;
+                    }
+                    Err(e) => {
+                        tracing::warn!("Skipping {}: cannot resolve it: {}", path.display(), e);
+                        outside = true;
+                    }
+                }
+            }
+
+            // Only the head of a file that can still be sent is read for a directive
+            let excluded = preset.is_excluded(path);
+            let directive = if outside || excluded {
+                None
+            } else {
+                match read_directive(path) {
+                    Some(Ok(directive)) => Some(directive),
+                    Some(Err(unknown)) => {
+                        if unknown_directives.insert(unknown.clone()) {
+                            tracing::warn!("Ignoring unknown directive techdocs:{} in {}", unknown, path.display());
+                        }
+                        None
+                    }
+                    None => None,
+                }
+            };
+            if directive == Some(Directive::Exclude) {
+                tracing::debug!("Leaving out {}: it is marked techdocs:exclude", path.display());
+                continue;
+            }
+
+            entries.push(WalkEntry {
+                path: path.to_path_buf(),
+                size: entry.metadata()?.len(),
+                outside,
+                excluded,
+                directive,
+            });
+        }
+        let over_dir_limit = over_dir_limit(&entries, options, max_file_size);
+
+        for (index, entry) in entries.iter().enumerate() {
+            if cancelled() {
+                tracing::debug!("Collection cancelled after {} files", files.len());
+                return Err(TechDocsError::Cancelled);
+            }
+            check_walk_time(dir, options, started)?;
+            let (path, file_size, outside, excluded) = (entry.path.as_path(), entry.size, entry.outside, entry.excluded);
+            let dir_limit_reached = over_dir_limit.contains(&index);
+            // A file marked techdocs:include is sent whole, whatever its size
+            let over_limit = file_size > max_file_size && entry.directive != Some(Directive::Include);
+            let file_limit_reached = options.max_files.is_some_and(|max| files_included >= max);
+
+            // Only a file that could still be included is read, to measure what it takes
+            // up once normalized, cut, and numbered. Normalizing never grows a file, so the
+            // size on disk is enough to tell one too large to be worth reading.
+            let mut truncated = None;
+            let mut prompt_size = file_size;
+            let mut shown = None;
+            let too_large = over_limit && options.large_files == LargeFiles::Skip;
+            let candidate = !(outside
+                || excluded
+                || too_large
+                || dir_limit_reached
+                || file_limit_reached
+                || budget_reached);
+            if candidate && (over_limit || options.line_numbers || options.normalize != Normalize::Off) {
+                read_file_into(path, &mut buffers.read)?;
+                let content = options.normalize.apply_to(&buffers.read, &mut buffers.normalized);
+                if let (true, LargeFiles::HeadTail { head_percent }) = (over_limit, options.large_files) {
+                    truncated = Truncation::head_tail(content, max_file_size, head_percent);
+                }
+                if options.keep_content {
+                    let start = kept.bytes.len();
+                    append_shown(content, truncated, options.line_numbers, &mut buffers.cut, &mut kept.bytes);
+                    prompt_size = (kept.bytes.len() - start) as u64;
+                    shown = Some(start..kept.bytes.len());
+                } else {
+                    buffers.shown.clear();
+                    append_shown(content, truncated, options.line_numbers, &mut buffers.cut, &mut buffers.shown);
+                    prompt_size = buffers.shown.len() as u64;
+                }
+            }
+
+            let skipped = if outside {
+                Some(SkipReason::OutsideRoot)
+            } else if excluded {
+                Some(SkipReason::BuildArtifact)
+            } else if too_large {
+                Some(SkipReason::TooLarge)
+            } else if dir_limit_reached {
+                Some(SkipReason::DirLimit)
+            } else if file_limit_reached {
+                Some(SkipReason::FileLimit)
+            } else if budget_reached || total_size + prompt_size > max_total_size {
+                // Once the budget is hit, later files are left out even if they would fit
+                if !budget_reached {
+                    budget_reached = true;
+                    progress(&ProgressEvent::BudgetReached { included_bytes: total_size });
+                }
+                Some(SkipReason::TotalBudget)
+            } else {
+                total_size += prompt_size;
+                None
+            };
+
+            if let Some(range) = shown {
+                match skipped {
+                    None => {
+                        kept.files.insert(path.to_path_buf(), range);
+                    }
+                    // Only the file just read is at the end of the buffer
+                    Some(_) => kept.bytes.truncate(range.start),
+                }
+            }
+
+            let estimated_tokens = if skipped.is_none() {
+                estimate_tokens(prompt_size + prompt_overhead(path))
+            } else {
+                0
+            };
+
+            progress(&match skipped {
+                Some(reason) => ProgressEvent::FileSkipped { path: path.to_path_buf(), size: file_size, reason },
+                None => ProgressEvent::FileIncluded { path: path.to_path_buf(), size: file_size },
+            });
+            #[cfg(feature = "metrics")]
+            match skipped {
+                Some(reason) => metrics::increment_counter(metrics::FILES_SKIPPED, &[("reason", reason.as_str())], 1),
+                None => metrics::increment_counter(metrics::FILES_INCLUDED, &[], 1),
+            }
+            files.push(PlannedFile {
+                path: path.to_path_buf(),
+                size: file_size,
+                skipped,
+                estimated_tokens,
+                truncated: truncated.filter(|_| skipped.is_none()),
+                directive: entry.directive,
+                prompt_size: if skipped.is_none() { prompt_size } else { file_size },
+            });
+            if skipped.is_none() {
+                files_included += 1;
+            }
+            progress(&ProgressEvent::Walk {
+                files_seen: files.len(),
+                files_included,
+            });
+        }
+
+        tracing::debug!(
+            "Selected {} of {} files under {}",
+            files_included,
+            files.len(),
+            dir.display()
+        );
+        progress(&ProgressEvent::WalkFinished {
+            files_included,
+            files_skipped: files.len() - files_included,
+        });
+
+        Ok(Self { kept: Arc::new(kept), ..Self::from_files(dir, files, options) })
+    }
+
+    /// A plan for `dir` of exactly `files`, in their order, decided on elsewhere: by a
+    /// walk, or read back from a list such as a
+    /// [`PlanFile`](crate::plan_file::PlanFile). `options` says how the prompt shows
+    /// them; its filters and limits are not applied.
+    pub fn from_files(dir: &Path, files: Vec<PlannedFile>, options: &PromptOptions) -> Self {
+        Self {
+            root: dir.to_path_buf(),
+            project: ProjectType::from_paths(dir, files.iter().map(|file| file.path.as_path())),
+            budget_reached: files.iter().any(|file| file.skipped == Some(SkipReason::TotalBudget)),
+            files,
+            license: detect_license(dir),
+            group_by_dir: options.group_by_dir,
+            line_numbers: options.line_numbers,
+            normalize: options.normalize,
+            kept: Arc::default(),
+        }
+    }
+
+    /// Files that will be sent, in order
+    pub fn included(&self) -> impl Iterator<Item = &PlannedFile> {
+        self.files.iter().filter(|f| f.is_included())
+    }
+
+    /// Files that were left out, with their reasons
+    pub fn skipped(&self) -> impl Iterator<Item = &PlannedFile> {
+        self.files.iter().filter(|f| !f.is_included())
+    }
+
+    /// Total bytes of included file content, as the prompt shows it
+    pub fn included_bytes(&self) -> u64 {
+        self.included().map(|f| f.prompt_size).sum()
+    }
+
+    /// Estimated tokens of the assembled file listing
+    pub fn estimated_tokens(&self) -> u64 {
+        let metadata = estimate_tokens(self.metadata_block().len() as u64);
+        let headers = estimate_tokens(self.listing_bytes());
+        metadata + headers + self.included().map(|f| f.estimated_tokens).sum::<u64>()
+    }
+
+    /// The included files grouped by directory, the sections with a manifest first,
+    /// then those with an entry point, then the rest; the root leads its group and the
+    /// others are in walk order
+    pub fn dir_sections(&self) -> Vec<DirSection<'_>> {
+        let relative = |file: &PlannedFile| file.path.strip_prefix(&self.root).unwrap_or(&file.path).to_path_buf();
+        let top = |path: &Path| {
+            let mut components = path.components();
+            let first = components.next()?;
+            components.next().map(|_| first.as_os_str().to_string_lossy().into_owned())
+        };
+        // Top-level directories holding files of their own stay whole
+        let with_files: Vec<String> = self
+            .included()
+            .filter_map(|file| {
+                let path = relative(file);
+                (path.components().count() == 2).then(|| top(&path)).flatten()
+            })
+            .collect();
+
+        let mut sections: Vec<DirSection> = Vec::new();
+        for file in self.included() {
+            let path = relative(file);
+            let name = match top(&path) {
+                None => ".".to_string(),
+                Some(top) if with_files.contains(&top) => top,
+                Some(top) => {
+                    let sub = path.components().nth(1).map(|c| c.as_os_str().to_string_lossy().into_owned());
+                    format!("{}/{}", top, sub.unwrap_or_default())
+                }
+            };
+            match sections.iter_mut().find(|section| section.name == name) {
+                Some(section) => section.files.push(file),
+                None => sections.push(DirSection { name, files: vec![file] }),
+            }
+        }
+        sections.sort_by_key(|section| (section.priority(), section.name != "."));
+        sections
+    }
+
+    /// Included files in the order they are written, each with the section header that
+    /// precedes it and the [`dir_limit_note`] that follows it, if any
+    fn prompt_files(&self) -> Vec<(Option<String>, &PlannedFile, Option<String>)> {
+        let files: Vec<(Option<String>, &PlannedFile)> = if self.group_by_dir {
+            self.dir_sections()
+                .into_iter()
+                .flat_map(|section| {
+                    let header = section.header();
+                    section.files.into_iter().enumerate().map(move |(index, file)| ((index == 0).then(|| header.clone()), file))
+                })
+                .collect()
+        } else {
+            self.included().map(|file| (None, file)).collect()
+        };
+
+        // Files left out for the directory limit, by directory
+        let mut left_out: BTreeMap<&Path, Vec<&PlannedFile>> = BTreeMap::new();
+        for file in self.files.iter().filter(|file| file.skipped == Some(SkipReason::DirLimit)) {
+            left_out.entry(file.path.parent().unwrap_or(&file.path)).or_default().push(file);
+        }
+        // Each note goes after the last file written from its directory
+        let mut noted = HashSet::new();
+        let mut out: Vec<_> = files
+            .into_iter()
+            .rev()
+            .map(|(header, file)| {
+                let dir = file.path.parent().unwrap_or(&file.path);
+                let note = left_out.get(dir).filter(|_| noted.insert(dir)).map(|files| dir_limit_note(dir, files));
+                (header, file, note)
+            })
+            .collect();
+        out.reverse();
+        out
+    }
+
+    /// Bytes of the section headers and directory limit notes
+    fn listing_bytes(&self) -> u64 {
+        self.prompt_files()
+            .iter()
+            .map(|(header, _, note)| (header.as_ref().map_or(0, String::len) + note.as_ref().map_or(0, String::len)) as u64)
+            .sum()
+    }
+
+    /// Facts about the project that precede the files, empty when nothing was detected
+    pub fn metadata_block(&self) -> String {
+        let facts: String = [self.project.fact(), self.license.as_ref().map(license_fact)].into_iter().flatten().collect();
+        if facts.is_empty() {
+            return String::new();
+        }
+        format!("{}{}", METADATA_HEADING, facts)
+    }
+
+    /// Length of the prompt body in bytes, exact unless a file holds invalid UTF-8
+    pub fn prompt_bytes(&self) -> u64 {
+        let files: u64 = self.included().map(|f| f.prompt_size + prompt_overhead(&f.path)).sum();
+        let warning = if self.budget_reached { BUDGET_WARNING.len() as u64 + 1 } else { 0 };
+        self.metadata_block().len() as u64 + self.listing_bytes() + files + warning
+    }
+
+    /// Write the prompt body: the metadata block, then the included files
+    pub fn write_prompt<W: io::Write>(&self, mut writer: W) -> Result<()> {
+        write!(writer, "{}", self.metadata_block())?;
+        let mut buffers = ContentBuffers::default();
+        for (header, file, note) in self.prompt_files() {
+            if let Some(header) = header {
+                write!(writer, "{}", header)?;
+            }
+            let content = match self.kept.get(&file.path) {
+                Some(content) => content,
+                None => {
+                    read_file_into(&file.path, &mut buffers.read)?;
+                    buffers.shown(self.normalize, file.truncated, self.line_numbers)
+                }
+            };
+            write_file_block(&mut writer, &file.path, content)?;
+            if let Some(note) = note {
+                write!(writer, "{}", note)?;
+            }
+        }
+
+        if self.budget_reached {
+            writeln!(writer, "{}", BUDGET_WARNING)?;
+        }
+        #[cfg(feature = "metrics")]
+        metrics::increment_counter(metrics::BYTES_EMITTED, &[], self.prompt_bytes());
+
+        Ok(())
+    }
+
+    /// [`PromptPlan::write_prompt`] for async writers, such as a response body being
+    /// streamed. Every file is read again with `tokio::fs`, even one the plan kept, and
+    /// written as soon as it is ready, so the prompt is never held whole.
+    #[cfg(any(feature = "git", feature = "claude"))]
+    pub async fn write_prompt_async<W: tokio::io::AsyncWrite + Unpin>(&self, mut writer: W) -> Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        writer.write_all(self.metadata_block().as_bytes()).await?;
+        let mut buffers = ContentBuffers::default();
+        let mut block = Vec::new();
+        for (header, file, note) in self.prompt_files() {
+            if let Some(header) = header {
+                writer.write_all(header.as_bytes()).await?;
+            }
+            buffers.read.clear();
+            let read = async { tokio::fs::File::open(&file.path).await?.read_to_end(&mut buffers.read).await };
+            read.await.file_context("read", &file.path)?;
+            let content = buffers.shown(self.normalize, file.truncated, self.line_numbers);
+            block.clear();
+            write_file_block(&mut block, &file.path, content)?;
+            writer.write_all(&block).await?;
+            if let Some(note) = note {
+                writer.write_all(note.as_bytes()).await?;
+            }
+        }
+
+        if self.budget_reached {
+            writer.write_all(format!("{}\n", BUDGET_WARNING).as_bytes()).await?;
+        }
+        writer.flush().await?;
+        #[cfg(feature = "metrics")]
+        metrics::increment_counter(metrics::BYTES_EMITTED, &[], self.prompt_bytes());
+
+        Ok(())
+    }
+}
+
+/// Write one file of the prompt: its `File:` header and fenced content
+fn write_file_block<W: io::Write>(writer: &mut W, path: &Path, content: &[u8]) -> io::Result<()> {
+    writeln!(writer, "\nFile: {}", path.display())?;
+    write_file_content(writer, path, content)?;
+    writeln!(writer)
+}
+
+/// The `(+ 763 more files in this directory)` note naming the `files` of `dir` left out
+/// for [`PromptOptions::max_files_per_dir`]
+fn dir_limit_note(dir: &Path, files: &[&PlannedFile]) -> String {
+    let count = match files.len() {
+        1 => "1 more file".to_string(),
+        n => format!("{} more files", n),
+    };
+    let mut note = format!("\n{} (+ {} in this directory)\n", dir.display(), count);
+    for file in files {
+        let name = file.path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        note.push_str(&format!("- {}\n", name));
+    }
+    note
+}
+
+/// A file found by the walk, before anything is decided about it
+struct WalkEntry {
+    path: PathBuf,
+    size: u64,
+    /// Whether the file resolves to somewhere outside the walked root
+    outside: bool,
+    /// Whether the filter preset leaves the file out
+    excluded: bool,
+    directive: Option<Directive>,
+}
+
+/// Indexes of the `entries` past [`PromptOptions::max_files_per_dir`] in their directory.
+/// Only files that could be included compete for the places: manifests and files
+/// marked important first, then entry points, then the rest in walk order.
+fn over_dir_limit(entries: &[WalkEntry], options: &PromptOptions, max_file_size: u64) -> HashSet<usize> {
+    let Some(max) = options.max_files_per_dir else {
+        return HashSet::new();
+    };
+    let mut by_dir: HashMap<&Path, Vec<usize>> = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let path = entry.path.as_path();
+        let too_large = entry.size > max_file_size
+            && options.large_files == LargeFiles::Skip
+            && entry.directive != Some(Directive::Include);
+        if !(entry.outside || entry.excluded || too_large) {
+            by_dir.entry(path.parent().unwrap_or(path)).or_default().push(index);
+        }
+    }
+    by_dir
+        .into_values()
+        .flat_map(|mut indexes| {
+            // A stable sort keeps walk order within each priority
+            indexes.sort_by_key(|&index| file_priority(&entries[index].path, entries[index].directive));
+            indexes.into_iter().skip(max)
+        })
+        .collect()
+}
+
+/// Fail with [`TechDocsError::SourceTooLarge`] once selection has run past
+/// [`PromptOptions::max_walk_time`]
+fn check_walk_time(dir: &Path, options: &PromptOptions, started: Instant) -> Result<()> {
+    match options.max_walk_time {
+        Some(max) if started.elapsed() > max => {
+            let reason = format!("selecting the files of {} took more than {} seconds", dir.display(), max.as_secs_f64());
+            Err(source_too_large(&reason))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The error for a source given up on, saying how to walk less of it
+fn source_too_large(reason: &str) -> TechDocsError {
+    TechDocsError::SourceTooLarge(format!(
+        "{}; point at a narrower path, or set a lower max depth, a stricter filter preset, or exclude patterns",
+        reason
+    ))
+}
+
+/// Whether a walk error is a symlink pointing back at a directory being walked
+fn is_loop(error: &ignore::Error) -> bool {
+    match error {
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => is_loop(err),
+        _ => false,
+    }
+}
+
+/// A size for people, such as `512 bytes`, `4.2 KB`, or `1.5 MB`
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{} bytes", bytes),
+        1024..1_048_576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// Options for the positional `build` functions, which take no include patterns
+fn positional_options(exclude_patterns: &[String], max_file_size_kb: u64, max_total_size_mb: u64) -> PromptOptions {
+    PromptOptions {
+        exclude: exclude_patterns.to_vec(),
+        include: Vec::new(),
+        preset: FilterPreset::default(),
+        max_files: None,
+        max_files_per_dir: None,
+        max_file_size_kb,
+        max_total_size_mb,
+        group_by_dir: false,
+        large_files: LargeFiles::Skip,
+        line_numbers: false,
+        // Content went into prompts as it was before normalizing existed
+        normalize: Normalize::Off,
+        keep_content: false,
+        follow_symlinks: false,
+        allow_external_symlinks: false,
+        max_depth: None,
+        max_walk_entries: usize::try_from(DEFAULT_MAX_WALK_ENTRIES).ok(),
+        max_walk_time: Some(Duration::from_secs(DEFAULT_MAX_WALK_SECS)),
+    }
+}
+
+/// A configured ceiling, where 0 means none
+fn walk_ceiling(value: u64) -> Option<u64> {
+    (value > 0).then_some(value)
+}
+
+/// Bytes the prompt adds around a file's content: the `File:` header and the code fence
+fn prompt_overhead(path: &Path) -> u64 {
+    let extension = code_language(path);
+    let header = "\nFile: \n".len() + path.display().to_string().len();
+    let fence = "```\n".len() + extension.len() + "\n```\n".len();
+    (header + fence) as u64
+}
+
+/// Overrides that whitelist files matching any of the include `patterns`
+fn include_overrides(dir: &Path, patterns: &[String]) -> Result<Override> {
+    let mut builder = OverrideBuilder::new(dir);
+    for pattern in patterns {
+        builder.add(pattern)?;
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::collections::{BTreeMap, BTreeSet};
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    const DIRS: &[&str] = &["src", "docs", "lib", "target", "node_modules"];
+    const STEMS: &[&str] = &["main", "util", "notes", "mod"];
+    const EXTENSIONS: &[&str] = &["rs", "md", "log", "toml"];
+    /// Directories the default preset leaves out, among [`DIRS`]
+    const PRESET_DIRS: &[&str] = &["target", "node_modules"];
+
+    /// A relative file path up to two directories deep
+    fn file_path() -> impl Strategy<Value = String> {
+        let dirs = prop::collection::vec(prop::sample::select(DIRS), 0..=2);
+        (dirs, prop::sample::select(STEMS), prop::sample::select(EXTENSIONS)).prop_map(|(dirs, stem, extension)| {
+            dirs.iter().map(|dir| format!("{}/", dir)).collect::<String>() + &format!("{}.{}", stem, extension)
+        })
+    }
+
+    /// Files of a fixture with their sizes in bytes
+    fn files(max_size: usize) -> impl Strategy<Value = BTreeMap<String, usize>> {
+        prop::collection::btree_map(file_path(), 0..max_size, 0..16)
+    }
+
+    /// A .gitignore pattern, with the paths it matches worked out by hand
+    #[derive(Debug, Clone, Copy)]
+    enum Pattern {
+        Extension(&'static str),
+        Dir(&'static str),
+    }
+
+    impl Pattern {
+        fn glob(self) -> String {
+            match self {
+                Pattern::Extension(extension) => format!("*.{}", extension),
+                Pattern::Dir(dir) => format!("{}/", dir),
+            }
+        }
+
+        fn matches(self, relative: &str) -> bool {
+            match self {
+                Pattern::Extension(extension) => relative.ends_with(&format!(".{}", extension)),
+                Pattern::Dir(dir) => relative.split('/').rev().skip(1).any(|component| component == dir),
+            }
+        }
+    }
+
+    fn pattern() -> impl Strategy<Value = Pattern> {
+        prop_oneof![
+            prop::sample::select(EXTENSIONS).prop_map(Pattern::Extension),
+            prop::sample::select(DIRS).prop_map(Pattern::Dir),
+        ]
+    }
+
+    /// A list and the same list in another order
+    fn shuffled<S: Strategy>(
+        element: S,
+        len: std::ops::Range<usize>,
+    ) -> impl Strategy<Value = (Vec<S::Value>, Vec<S::Value>)>
+    where
+        S::Value: Clone,
+    {
+        prop::collection::vec(element, len).prop_flat_map(|list| (Just(list.clone()), Just(list).prop_shuffle()))
+    }
+
+    fn globs(patterns: &[Pattern]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.glob()).collect()
+    }
+
+    fn tree(files: &BTreeMap<String, usize>, ignored: &[Pattern]) -> FixtureTree {
+        files.iter().fold(FixtureTree::new().gitignore(globs(ignored)), |tree, (path, &size)| tree.sized_file(path, size))
+    }
+
+    /// Included files, relative to the root and separated by `/`
+    fn included(tree: &FixtureTree, plan: &PromptPlan) -> BTreeSet<String> {
+        plan.included()
+            .map(|file| file.path.strip_prefix(tree.path()).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    fn prompt(plan: &PromptPlan) -> String {
+        let mut out = Vec::new();
+        plan.write_prompt(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(48))]
+
+        #[test]
+        fn excluded_files_never_reach_the_prompt(
+            files in files(64),
+            exclude in prop::collection::vec(pattern(), 0..3),
+            ignored in prop::collection::vec(pattern(), 0..2),
+        ) {
+            let tree = tree(&files, &ignored);
+            let plan = PromptPlan::collect(tree.path(), &PromptOptions::unlimited().exclude(globs(&exclude))).unwrap();
+            let prompt = prompt(&plan);
+
+            let left_out = |path: &str| {
+                exclude.iter().chain(&ignored).any(|pattern| pattern.matches(path))
+                    || PRESET_DIRS.iter().any(|&dir| Pattern::Dir(dir).matches(path))
+            };
+            for path in files.keys() {
+                let header = format!("File: {}\n", tree.join(path).display());
+                prop_assert_eq!(prompt.contains(&header), !left_out(path), "{}", path);
+            }
+            let expected: BTreeSet<String> = files.keys().filter(|path| !left_out(path)).cloned().collect();
+            prop_assert_eq!(included(&tree, &plan), expected);
+        }
+
+        #[test]
+        fn pattern_order_does_not_matter(
+            files in files(64),
+            (exclude, shuffled_exclude) in shuffled(pattern(), 0..4),
+            (include, shuffled_include) in shuffled(prop::sample::select(EXTENSIONS), 1..3),
+        ) {
+            let tree = tree(&files, &[]);
+            let include_globs = |extensions: &[&str]| -> Vec<String> {
+                extensions.iter().map(|extension| format!("*.{}", extension)).collect()
+            };
+            let options = PromptOptions::unlimited().exclude(globs(&exclude)).include(include_globs(&include));
+            let reordered =
+                PromptOptions::unlimited().exclude(globs(&shuffled_exclude)).include(include_globs(&shuffled_include));
+
+            let plan = PromptPlan::collect(tree.path(), &options).unwrap();
+            let other = PromptPlan::collect(tree.path(), &reordered).unwrap();
+            prop_assert_eq!(included(&tree, &plan), included(&tree, &other));
+            prop_assert_eq!(prompt(&plan), prompt(&other));
+        }
+
+        #[test]
+        fn prompt_is_deterministic_and_within_size_limits(files in files(3000), line_numbers in any::<bool>()) {
+            let tree = tree(&files, &[]);
+            let options = PromptOptions::unlimited().max_file_kb(1).line_numbers(line_numbers);
+            let plan = PromptPlan::collect(tree.path(), &options).unwrap();
+            let first = prompt(&plan);
+            prop_assert_eq!(&first, &prompt(&PromptPlan::collect(tree.path(), &options).unwrap()));
+            prop_assert_eq!(first.len() as u64, plan.prompt_bytes());
+
+            for file in plan.files.iter().filter(|file| file.skipped != Some(SkipReason::BuildArtifact)) {
+                prop_assert_eq!(file.skipped == Some(SkipReason::TooLarge), file.size > 1024, "{}", file.path.display());
+            }
+        }
+    }
+
+    /// A small Rust project with a file of every kind the prompt treats differently
+    fn fixture() -> FixtureTree {
+        let long: String = (1..=40).map(|line| format!("line {:>2} of a file over the 1 KB limit\n", line)).collect();
+        FixtureTree::new()
+            .gitignore(["*.log"])
+            .file("Cargo.toml", "[package]\nname = \"fixture\"\nversion = \"0.1.0\"\nlicense = \"MIT\"\n")
+            .file("src/main.rs", "fn main() {\r\n    println!(\"{}\", fixture::answer());   \r\n}\r\n")
+            .file("src/lib.rs", "pub fn answer() -> u32 {\n    42\n}\n")
+            .file("docs/guide.md", "# Guide\n\nRun `fixture`.\n")
+            .file("docs/long.txt", long)
+            .file("debug.log", "left out by .gitignore\n")
+            .file("target/debug/fixture", "left out by the preset\n")
+    }
+
+    /// The prompt for `fixture` with its files in path order, so the snapshot does not
+    /// depend on the order the file system lists them in
+    fn snapshot_prompt(tree: &FixtureTree, options: &PromptOptions) -> String {
+        let mut files = PromptPlan::collect(tree.path(), options).unwrap().files;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let plan = PromptPlan::from_files(tree.path(), files, options);
+        prompt(&plan).replace(&tree.path().display().to_string(), "<root>")
+    }
+
+    #[test]
+    fn prompt_snapshots() {
+        let tree = fixture();
+        // As the binaries write them
+        let full = PromptOptions::new().normalize(Normalize::Full);
+        let formats = [
+            ("plain", full.clone()),
+            ("normalize_off", PromptOptions::new()),
+            ("group_by_dir", full.clone().group_by_dir(true)),
+            ("line_numbers", full.clone().line_numbers(true)),
+            ("head_tail", full.clone().max_file_kb(1).large_files(LargeFiles::HeadTail { head_percent: 60 })),
+            ("max_files_per_dir", full.max_files_per_dir(1)),
+        ];
+        for (name, options) in formats {
+            insta::assert_snapshot!(format!("prompt_{}", name), snapshot_prompt(&tree, &options));
+        }
+    }
+
+    /// The prompt section of the one file in `tree`
+    fn file_section(tree: &FixtureTree, options: &PromptOptions) -> String {
+        let plan = PromptPlan::collect(tree.path(), options).unwrap();
+        let prompt = prompt(&plan);
+        prompt[prompt.find("```txt\n").unwrap()..].to_string()
+    }
+
+    #[test]
+    fn byte_order_marks_are_stripped_when_normalizing() {
+        let tree = FixtureTree::new().file("notes.txt", "\u{FEFF}# Notes  \r\nbody\n");
+        let full = file_section(&tree, &PromptOptions::new().normalize(Normalize::Full));
+        assert_eq!(full, "```txt\n# Notes\nbody\n\n```\n");
+        let line_endings = file_section(&tree, &PromptOptions::new().normalize(Normalize::LineEndings));
+        assert_eq!(line_endings, "```txt\n# Notes  \nbody\n\n```\n");
+        let off = file_section(&tree, &PromptOptions::new().normalize(Normalize::Off));
+        assert_eq!(off, "```txt\n\u{FEFF}# Notes  \r\nbody\n\n```\n");
+    }
+
+    #[test]
+    fn bare_carriage_returns_end_lines_when_normalizing() {
+        // Classic Mac line endings, and one stray CR next to a CRLF
+        let tree = FixtureTree::new().file("notes.txt", "one\rtwo \rthree\r\r\nfour");
+        let full = file_section(&tree, &PromptOptions::new().normalize(Normalize::Full));
+        assert_eq!(full, "```txt\none\ntwo\nthree\n\nfour\n```\n");
+        let line_endings = file_section(&tree, &PromptOptions::new().normalize(Normalize::LineEndings));
+        assert_eq!(line_endings, "```txt\none\ntwo \nthree\n\nfour\n```\n");
+    }
+
+    /// A project with links to a secret outside it, one to the file and one to its
+    /// directory, and a link to a file of its own
+    #[cfg(unix)]
+    fn linked_project() -> (FixtureTree, FixtureTree) {
+        let outside = FixtureTree::new().file("private/secret.txt", "hunter2\n");
+        let project = FixtureTree::new().file("notes.txt", "notes\n");
+        std::os::unix::fs::symlink(outside.join("private/secret.txt"), project.join("leak.txt")).unwrap();
+        std::os::unix::fs::symlink(outside.join("private"), project.join("private")).unwrap();
+        std::os::unix::fs::symlink(project.join("notes.txt"), project.join("alias.txt")).unwrap();
+        (project, outside)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_the_root_never_reach_the_prompt() {
+        let (project, _outside) = linked_project();
+        for follow in [false, true] {
+            let options = PromptOptions::new().follow_symlinks(follow);
+            let plan = PromptPlan::collect(project.path(), &options).unwrap();
+            let out = prompt(&plan);
+            assert!(!out.contains("hunter2"), "follow_symlinks({}): {}", follow, out);
+            assert!(out.contains(&format!("File: {}", project.join("alias.txt").display())));
+            let skipped = plan.files.iter().find(|file| file.path == project.join("leak.txt")).unwrap();
+            assert_eq!(skipped.skipped, Some(SkipReason::OutsideRoot));
+        }
+        // Through a followed directory link, the file below it is left out too
+        let plan = PromptPlan::collect(project.path(), &PromptOptions::new().follow_symlinks(true)).unwrap();
+        let below = plan.files.iter().find(|file| file.path == project.join("private/secret.txt")).unwrap();
+        assert_eq!(below.skipped, Some(SkipReason::OutsideRoot));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn external_symlinks_are_read_when_allowed() {
+        let (project, _outside) = linked_project();
+        let options = PromptOptions::new().follow_symlinks(true).allow_external_symlinks(true);
+        let plan = PromptPlan::collect(project.path(), &options).unwrap();
+        assert!(plan.files.iter().all(|file| file.skipped != Some(SkipReason::OutsideRoot)));
+        assert_eq!(prompt(&plan).matches("hunter2").count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_root_reached_through_a_symlink_is_not_outside_itself() {
+        let project = FixtureTree::new().file("notes.txt", "notes\n");
+        let links = FixtureTree::new();
+        std::os::unix::fs::symlink(project.path(), links.join("project")).unwrap();
+        let plan = PromptPlan::collect(&links.join("project"), &PromptOptions::new().follow_symlinks(true)).unwrap();
+        assert_eq!(plan.included().count(), 1);
+    }
+
+    #[test]
+    fn content_kept_from_the_walk_matches_reading_the_files_again() {
+        let tree = fixture();
+        let formats = [
+            PromptOptions::new(),
+            PromptOptions::new().normalize(Normalize::Off),
+            PromptOptions::new().line_numbers(true),
+            PromptOptions::new().max_file_kb(1).large_files(LargeFiles::HeadTail { head_percent: 60 }).line_numbers(true),
+            PromptOptions::new().max_total_mb(0),
+        ];
+        for options in formats {
+            let unkept = PromptPlan::collect(tree.path(), &options).unwrap();
+            assert!(unkept.kept.files.is_empty() && unkept.kept.bytes.is_empty());
+            let options = options.keep_content(true);
+            let walked = PromptPlan::collect(tree.path(), &options).unwrap();
+            let listed = PromptPlan::from_files(tree.path(), walked.files.clone(), &options);
+            assert!(listed.kept.files.is_empty());
+            assert_eq!(prompt(&walked), prompt(&unkept), "{:?}", options);
+            assert_eq!(prompt(&walked), prompt(&listed), "{:?}", options);
+            // Files read and then left out are not kept
+            assert!(walked.kept.files.keys().all(|path| walked.included().any(|file| &file.path == path)));
+            let kept: u64 =
+                walked.included().filter(|file| walked.kept.files.contains_key(&file.path)).map(|file| file.prompt_size).sum();
+            assert_eq!(walked.kept.bytes.len() as u64, kept);
+        }
+    }
+
+    #[cfg(any(feature = "git", feature = "claude"))]
+    #[tokio::test]
+    async fn async_prompt_matches_the_sync_prompt() {
+        let tree = fixture();
+        for options in [PromptOptions::new(), PromptOptions::new().line_numbers(true).keep_content(true)] {
+            let plan = PromptPlan::collect(tree.path(), &options).unwrap();
+            let mut streamed = Vec::new();
+            plan.write_prompt_async(&mut streamed).await.unwrap();
+            assert_eq!(String::from_utf8(streamed).unwrap(), prompt(&plan), "{:?}", options);
+        }
+    }
+
+    /// `dirs` directories of `files` small files each
+    fn wide_tree(dirs: usize, files: usize) -> FixtureTree {
+        (0..dirs * files).fold(FixtureTree::new(), |tree, index| {
+            tree.file(format!("dir{:02}/file{:02}.txt", index / files, index % files), "x\n")
+        })
+    }
+
+    #[test]
+    fn cancelling_from_the_progress_callback_stops_the_walk() {
+        let tree = wide_tree(20, 10);
+        let cancelled = AtomicBool::new(false);
+        let last_seen = AtomicUsize::new(0);
+        let progress = |event: &ProgressEvent| {
+            if let ProgressEvent::Scan { entries_seen } = *event {
+                last_seen.store(entries_seen, Ordering::Relaxed);
+                cancelled.store(entries_seen >= 10, Ordering::Relaxed);
+            }
+        };
+        let is_cancelled = || cancelled.load(Ordering::Relaxed);
+
+        let error = PromptPlan::collect_cancellable(tree.path(), &PromptOptions::new(), &progress, &is_cancelled).unwrap_err();
+        assert!(matches!(error, TechDocsError::Cancelled), "{}", error);
+        assert_eq!(last_seen.load(Ordering::Relaxed), 10);
+    }
+
+    #[test]
+    fn walks_past_the_entry_ceiling_are_too_large() {
+        let tree = wide_tree(20, 10);
+        let options = PromptOptions::new().walk_ceilings(5, Duration::from_secs(60));
+        let error = PromptPlan::collect(tree.path(), &options).unwrap_err();
+        assert!(
+            matches!(&error, TechDocsError::SourceTooLarge(message) if message.contains("more than 5 files and directories")),
+            "{}",
+            error
+        );
+        assert!(error.to_string().contains("point at a narrower path"), "{}", error);
+
+        let options = PromptOptions::new().walk_ceilings(1000, Duration::from_secs(60));
+        assert_eq!(PromptPlan::collect(tree.path(), &options).unwrap().included().count(), 200);
+    }
+
+    #[test]
+    fn walks_past_the_time_ceiling_are_too_large() {
+        let tree = wide_tree(20, 10);
+        let options = PromptOptions::new().walk_ceilings(usize::MAX, Duration::ZERO);
+        let error = PromptPlan::collect(tree.path(), &options).unwrap_err();
+        assert!(
+            matches!(&error, TechDocsError::SourceTooLarge(message) if message.contains("took more than 0 seconds")),
+            "{}",
+            error
+        );
+    }
+}
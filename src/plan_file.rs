@@ -0,0 +1,280 @@
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::git::head_commit;
+use crate::plan::{Normalize, PlannedFile, PromptOptions, PromptPlan, SkipReason, Truncation};
+use crate::{write_atomic, FileContext, Result, TechDocsError};
+
+/// Version of the [`PlanFile`] format. A plan file with any other version is refused.
+pub const PLAN_FILE_VERSION: u32 = 1;
+
+/// Every file a README run considered and what it decided about each, written before
+/// anything is sent so the selection can be reviewed, and read back to send exactly the
+/// files that were approved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanFile {
+    /// Format version, [`PLAN_FILE_VERSION`] when written by this build
+    pub version: u32,
+    /// Path or repository URL the project was read from
+    pub source: String,
+    /// Commit the project was at, when it is a git repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub generated_at: DateTime<Utc>,
+    /// Whether the prompt lists the files by directory
+    pub group_by_dir: bool,
+    /// Whether the prompt numbers the lines of each file
+    pub line_numbers: bool,
+    /// How file content is cleaned up before it is sent
+    pub normalize: Normalize,
+    /// Every file considered, in the order the prompt lists them
+    pub files: Vec<PlanFileEntry>,
+}
+
+/// A file considered for the prompt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanFileEntry {
+    /// Path relative to the project root, separated by `/`
+    pub path: String,
+    /// Size in bytes
+    pub size: u64,
+    /// SHA-256 of the file's bytes on disk, for included files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    pub disposition: Disposition,
+    /// Why the file was left out; omitted for included files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<SkipReason>,
+    /// The part of an oversized file that is sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<Truncation>,
+}
+
+/// Whether a file goes into the prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Disposition {
+    Included,
+    Skipped,
+}
+
+impl PlanFile {
+    /// The plan file for `plan`, read from `source`. Each included file is read to hash it.
+    pub fn from_plan(source: &str, plan: &PromptPlan) -> Result<Self> {
+        let files = plan
+            .files
+            .iter()
+            .map(|file| {
+                let sha256 = match file.is_included() {
+                    true => Some(hex(&Sha256::digest(fs::read(&file.path).file_context("read", &file.path)?))),
+                    false => None,
+                };
+                Ok(PlanFileEntry {
+                    path: relative_path(&plan.root, &file.path),
+                    size: file.size,
+                    sha256,
+                    disposition: if file.is_included() { Disposition::Included } else { Disposition::Skipped },
+                    reason: file.skipped,
+                    truncated: file.truncated,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            version: PLAN_FILE_VERSION,
+            source: source.to_string(),
+            commit: head_commit(&plan.root),
+            generated_at: Utc::now(),
+            group_by_dir: plan.group_by_dir,
+            line_numbers: plan.line_numbers,
+            normalize: plan.normalize,
+            files,
+        })
+    }
+
+    /// The plan file at `path`, failing with [`TechDocsError::Config`] when it cannot be
+    /// parsed or is from another format version
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).file_context("read", path)?;
+        let plan: Self = serde_json::from_str(&content)
+            .map_err(|e| TechDocsError::Config(format!("invalid plan file {}: {}", path.display(), e)))?;
+        if plan.version != PLAN_FILE_VERSION {
+            return Err(TechDocsError::Config(format!(
+                "plan file {} has version {}; this build reads version {}",
+                path.display(),
+                plan.version,
+                PLAN_FILE_VERSION
+            )));
+        }
+        Ok(plan)
+    }
+
+    /// Write the plan to `path` as JSON
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| TechDocsError::Output(e.to_string()))?;
+        write_atomic(path, &format!("{}\n", json))
+    }
+
+    /// The plan for the project in `dir` that sends exactly the included files listed,
+    /// shown as they were when the plan was written. Fails with
+    /// [`TechDocsError::PlanChanged`] when an included file is missing or its hash no
+    /// longer matches. Skipped files are carried over without being read.
+    pub fn replay(&self, dir: &Path) -> Result<PromptPlan> {
+        let options = PromptOptions::new()
+            .group_by_dir(self.group_by_dir)
+            .line_numbers(self.line_numbers)
+            .normalize(self.normalize);
+        let mut changed = Vec::new();
+        let mut files = Vec::new();
+        for entry in &self.files {
+            let path = dir.join(&entry.path);
+            match (entry.disposition, entry.reason) {
+                (Disposition::Included, _) => {
+                    let Some(expected) = &entry.sha256 else {
+                        return Err(TechDocsError::Config(format!(
+                            "plan file lists {} as included without a sha256",
+                            entry.path
+                        )));
+                    };
+                    let content = match fs::read(&path) {
+                        Ok(content) => content,
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                            changed.push(format!("{} was removed", entry.path));
+                            continue;
+                        }
+                        Err(e) => return Err(TechDocsError::File { action: "read", path, source: e }),
+                    };
+                    if hex(&Sha256::digest(&content)) != *expected {
+                        changed.push(format!("{} changed", entry.path));
+                        continue;
+                    }
+                    files.push(PlannedFile::included(path, &content, entry.truncated, &options));
+                }
+                (Disposition::Skipped, Some(reason)) => files.push(PlannedFile::skipped(path, entry.size, reason)),
+                (Disposition::Skipped, None) => {
+                    return Err(TechDocsError::Config(format!(
+                        "plan file lists {} as skipped without a reason",
+                        entry.path
+                    )));
+                }
+            }
+        }
+        if !changed.is_empty() {
+            return Err(TechDocsError::PlanChanged(changed.join(", ")));
+        }
+        Ok(PromptPlan::from_files(dir, files, &options))
+    }
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative.components().map(|component| component.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::LargeFiles;
+    use crate::testing::FixtureTree;
+
+    fn project() -> FixtureTree {
+        let long: String = (1..=40).map(|line| format!("line {:>2} of a file over the 1 KB limit\n", line)).collect();
+        FixtureTree::new()
+            .file("src/main.rs", "fn main() {\r\n    println!(\"hello\");   \r\n}\r\n")
+            .file("notes.txt", "Notes\n")
+            .file("docs/long.txt", long)
+            .sized_file("data/large.bin", 4096)
+    }
+
+    fn options() -> PromptOptions {
+        PromptOptions::new().max_file_kb(2).line_numbers(true)
+    }
+
+    fn prompt(plan: &PromptPlan) -> String {
+        let mut out = Vec::new();
+        plan.write_prompt(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn plan_file_lists_every_file_with_hashes_of_the_included_ones() {
+        let project = project();
+        let plan = PromptPlan::collect(project.path(), &options()).unwrap();
+        let file = PlanFile::from_plan("project", &plan).unwrap();
+        assert_eq!((file.version, file.source.as_str(), file.line_numbers), (PLAN_FILE_VERSION, "project", true));
+        assert_eq!(file.files.len(), 4);
+
+        let notes = file.files.iter().find(|entry| entry.path == "notes.txt").unwrap();
+        assert_eq!(notes.disposition, Disposition::Included);
+        assert_eq!(notes.sha256.as_deref(), Some(hex(&Sha256::digest(b"Notes\n")).as_str()));
+        assert_eq!(notes.reason, None);
+        let large = file.files.iter().find(|entry| entry.path == "data/large.bin").unwrap();
+        assert_eq!((large.disposition, large.reason), (Disposition::Skipped, Some(SkipReason::TooLarge)));
+        assert_eq!(large.sha256, None);
+
+        let saved = FixtureTree::new();
+        file.save(&saved.join("plan.json")).unwrap();
+        assert_eq!(PlanFile::load(&saved.join("plan.json")).unwrap(), file);
+    }
+
+    #[test]
+    fn replay_sends_exactly_what_was_planned() {
+        let project = project();
+        let formats = [
+            options(),
+            PromptOptions::new().normalize(Normalize::Off).group_by_dir(true),
+            PromptOptions::new().max_file_kb(1).large_files(LargeFiles::HeadTail { head_percent: 60 }),
+        ];
+        for options in formats {
+            let plan = PromptPlan::collect(project.path(), &options).unwrap();
+            let replayed = PlanFile::from_plan("project", &plan).unwrap().replay(project.path()).unwrap();
+            assert_eq!(prompt(&replayed), prompt(&plan), "{:?}", options);
+            assert_eq!(replayed.skipped().count(), plan.skipped().count());
+        }
+    }
+
+    #[test]
+    fn replay_leaves_out_files_added_since() {
+        let project = project();
+        let file = PlanFile::from_plan("project", &PromptPlan::collect(project.path(), &options()).unwrap()).unwrap();
+        std::fs::write(project.join("added.txt"), "not reviewed\n").unwrap();
+        // A skipped file may change: it is not sent either way
+        std::fs::write(project.join("data/large.bin"), "changed").unwrap();
+        let replayed = file.replay(project.path()).unwrap();
+        assert!(!prompt(&replayed).contains("not reviewed"));
+    }
+
+    #[test]
+    fn replay_refuses_changed_and_removed_files() {
+        let project = project();
+        let file = PlanFile::from_plan("project", &PromptPlan::collect(project.path(), &options()).unwrap()).unwrap();
+        std::fs::write(project.join("notes.txt"), "Notes, edited after review\n").unwrap();
+        std::fs::remove_file(project.join("src/main.rs")).unwrap();
+        match file.replay(project.path()) {
+            Err(TechDocsError::PlanChanged(changes)) => {
+                assert!(changes.contains("notes.txt changed"), "{}", changes);
+                assert!(changes.contains("src/main.rs was removed"), "{}", changes);
+            }
+            other => panic!("expected the plan to be refused, got {:?}", other.map(|plan| plan.files.len())),
+        }
+    }
+
+    #[test]
+    fn plan_file_of_another_version_is_refused() {
+        let project = project();
+        let mut file = PlanFile::from_plan("project", &PromptPlan::collect(project.path(), &options()).unwrap()).unwrap();
+        file.version = PLAN_FILE_VERSION + 1;
+        let saved = FixtureTree::new();
+        file.save(&saved.join("plan.json")).unwrap();
+        match PlanFile::load(&saved.join("plan.json")) {
+            Err(TechDocsError::Config(message)) => assert!(message.contains("this build reads version 1"), "{}", message),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::badges::{insert_badges, Badge};
+use crate::generate::{tidy_markdown, GeneratedDoc};
+use crate::toc::insert_toc;
+use crate::{Result, TechDocsError};
+
+/// Variables a [`Footer`] template may use, each written in braces
+pub const FOOTER_VARIABLES: &[&str] = &["version", "commit", "date", "model", "source", "license"];
+
+/// A deterministic fix-up of a generated document, such as a heading style, a standard
+/// footer, or links rewritten for a docs site. Register one on
+/// [`GenerateOptions::post_processors`](crate::generate::GenerateOptions::post_processors).
+pub trait PostProcessor: Send + Sync {
+    /// Short name for errors and logs
+    fn name(&self) -> &str;
+
+    /// Change `doc` in place; an error stops the document from being used
+    fn process(&self, doc: &mut GeneratedDoc) -> Result<()>;
+}
+
+/// Post-processors run one after another, in the order they were added. Built-ins go
+/// first, in the order [`PostProcessors::builtin`] declares; processors added with
+/// [`PostProcessors::push`] follow.
+#[derive(Clone, Default)]
+pub struct PostProcessors {
+    processors: Vec<Arc<dyn PostProcessor>>,
+}
+
+impl PostProcessors {
+    /// The built-ins that are enabled, in the order they run: fence stripping, the table
+    /// of contents, badges, and the footer. The footer comes after the table of contents
+    /// so that headings of its own stay out of it.
+    pub fn builtin(toc: bool, badges: Option<Vec<Badge>>, footer: Option<Footer>) -> Self {
+        let mut processors = Self::default();
+        processors.push(StripFences);
+        if toc {
+            processors.push(TableOfContents);
+        }
+        if let Some(badges) = badges {
+            processors.push(Badges(badges));
+        }
+        if let Some(footer) = footer {
+            processors.push(footer);
+        }
+        processors
+    }
+
+    /// Run `processor` after the ones already added
+    pub fn push(&mut self, processor: impl PostProcessor + 'static) -> &mut Self {
+        self.processors.push(Arc::new(processor));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    /// Names of the processors, in the order they run
+    pub fn names(&self) -> Vec<&str> {
+        self.processors.iter().map(|processor| processor.name()).collect()
+    }
+
+    /// Run every processor on `doc`, stopping at the first that fails
+    pub fn run(&self, doc: &mut GeneratedDoc) -> Result<()> {
+        for processor in &self.processors {
+            tracing::debug!("Running post-processor {}", processor.name());
+            processor.process(doc).map_err(|e| match e {
+                TechDocsError::Other(e) => {
+                    TechDocsError::Other(format!("post-processor {}: {}", processor.name(), e).into())
+                }
+                e => e,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for PostProcessors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+/// Unwraps a document fenced as a whole code block and ends it with one newline
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StripFences;
+
+impl PostProcessor for StripFences {
+    fn name(&self) -> &str {
+        "strip-fences"
+    }
+
+    fn process(&self, doc: &mut GeneratedDoc) -> Result<()> {
+        doc.content = tidy_markdown(&doc.content);
+        Ok(())
+    }
+}
+
+/// Inserts or refreshes a table of contents with [`insert_toc`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TableOfContents;
+
+impl PostProcessor for TableOfContents {
+    fn name(&self) -> &str {
+        "toc"
+    }
+
+    fn process(&self, doc: &mut GeneratedDoc) -> Result<()> {
+        doc.content = insert_toc(&doc.content);
+        Ok(())
+    }
+}
+
+/// Puts badges under the title with [`insert_badges`], leaving out ones already there
+#[derive(Debug, Clone, Default)]
+pub struct Badges(pub Vec<Badge>);
+
+impl PostProcessor for Badges {
+    fn name(&self) -> &str {
+        "badges"
+    }
+
+    fn process(&self, doc: &mut GeneratedDoc) -> Result<()> {
+        doc.content = insert_badges(&doc.content, &self.0);
+        Ok(())
+    }
+}
+
+/// Appends a footer rendered from a template such as
+/// `Generated from {commit} of version {version} on {date}`. The variables are those in
+/// [`FOOTER_VARIABLES`]: the project's version, the short commit, today's date, the
+/// model, the source path or URL, and the license; unknown values read `unknown`. `{{`
+/// and `}}` write literal braces. A document that already ends with the footer keeps
+/// just the one.
+#[derive(Debug, Clone)]
+pub struct Footer {
+    template: String,
+}
+
+impl Footer {
+    /// A footer from `template`, failing on a variable it does not know
+    pub fn new(template: &str) -> Result<Self> {
+        let unknown = parse(template).into_iter().find_map(|part| match part {
+            Part::Variable(name) if !FOOTER_VARIABLES.contains(&name) => Some(name.to_string()),
+            _ => None,
+        });
+        if let Some(name) = unknown {
+            return Err(TechDocsError::Config(format!(
+                "unknown footer variable `{{{}}}`; use one of {}",
+                name,
+                FOOTER_VARIABLES.iter().map(|name| format!("{{{}}}", name)).collect::<Vec<_>>().join(", ")
+            )));
+        }
+        Ok(Self { template: template.to_string() })
+    }
+
+    /// The footer for `doc`
+    pub fn render(&self, doc: &GeneratedDoc) -> String {
+        let provenance = &doc.provenance;
+        let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        parse(&self.template)
+            .into_iter()
+            .map(|part| match part {
+                Part::Text(text) => text.to_string(),
+                Part::Variable(name) => {
+                    let value = match name {
+                        "version" => provenance.version.as_deref(),
+                        "commit" => provenance.commit.as_deref().map(|commit| &commit[..commit.len().min(7)]),
+                        "date" => Some(date.as_str()),
+                        "model" => Some(doc.model.as_str()),
+                        "source" => Some(provenance.source.as_str()),
+                        "license" => provenance.license.as_deref(),
+                        _ => None,
+                    };
+                    value.unwrap_or("unknown").to_string()
+                }
+            })
+            .collect()
+    }
+}
+
+impl PostProcessor for Footer {
+    fn name(&self) -> &str {
+        "footer"
+    }
+
+    fn process(&self, doc: &mut GeneratedDoc) -> Result<()> {
+        let footer = self.render(doc);
+        let footer = footer.trim();
+        if footer.is_empty() || doc.content.trim_end().ends_with(footer) {
+            return Ok(());
+        }
+        doc.content = format!("{}\n\n{}\n", doc.content.trim_end(), footer);
+        Ok(())
+    }
+}
+
+/// A piece of a footer template
+enum Part<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+/// The text and `{variable}` parts of `template`; `{{` and `}}` are literal braces, and
+/// a `{` that is never closed is text
+fn parse(template: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(at) = rest.find(['{', '}']) {
+        if at > 0 {
+            parts.push(Part::Text(&rest[..at]));
+        }
+        let brace = &rest[at..];
+        if brace.starts_with("{{") || brace.starts_with("}}") {
+            parts.push(Part::Text(&brace[..1]));
+            rest = &brace[2..];
+        } else if let Some(end) = brace.strip_prefix('{').and_then(|body| body.find('}')) {
+            parts.push(Part::Variable(brace[1..1 + end].trim()));
+            rest = &brace[end + 2..];
+        } else {
+            parts.push(Part::Text(&brace[..1]));
+            rest = &brace[1..];
+        }
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::Provenance;
+    use crate::toc::{TOC_END, TOC_START};
+
+    fn doc(content: &str) -> GeneratedDoc {
+        GeneratedDoc {
+            content: content.to_string(),
+            model: "claude-test".into(),
+            usage: Default::default(),
+            stop_reason: None,
+            stop_sequence: None,
+            thinking_tokens: 0,
+            stats: Default::default(),
+            provenance: Provenance {
+                source: "https://github.com/acme/demo".into(),
+                commit: Some("0123456789abcdef".into()),
+                license: Some("MIT".into()),
+                version: Some("1.2.3".into()),
+                context_root: None,
+                target: None,
+                repository: None,
+            },
+        }
+    }
+
+    fn ci_badge() -> Badge {
+        Badge {
+            label: "CI".into(),
+            image_url: "https://github.com/acme/demo/actions/workflows/ci.yml/badge.svg".into(),
+            link: "https://github.com/acme/demo/actions".into(),
+        }
+    }
+
+    const GENERATED: &str = "```markdown\n# Demo\n\nA tool.\n\n## Install\n\nRun it.\n\n## Usage\n\nUse it.\n```";
+
+    struct Shout;
+
+    impl PostProcessor for Shout {
+        fn name(&self) -> &str {
+            "shout"
+        }
+
+        fn process(&self, doc: &mut GeneratedDoc) -> Result<()> {
+            doc.content = doc.content.to_uppercase();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn built_ins_run_first_in_their_declared_order() {
+        let footer = Footer::new("Generated from {commit}").unwrap();
+        let mut processors = PostProcessors::builtin(true, Some(vec![ci_badge()]), Some(footer));
+        processors.push(Shout);
+        assert_eq!(processors.names(), ["strip-fences", "toc", "badges", "footer", "shout"]);
+        assert_eq!(PostProcessors::builtin(false, None, None).names(), ["strip-fences"]);
+        assert!(PostProcessors::default().is_empty());
+    }
+
+    #[test]
+    fn the_toc_badges_and_footer_are_inserted() {
+        let footer = Footer::new("## About\n\nVersion {version} at {commit}, {license}, by {model}").unwrap();
+        let processors = PostProcessors::builtin(true, Some(vec![ci_badge()]), Some(footer));
+        let mut generated = doc(GENERATED);
+        processors.run(&mut generated).unwrap();
+
+        let content = &generated.content;
+        assert!(!content.contains("```"), "{}", content);
+        assert!(content.starts_with(&format!("# Demo\n\n{}\n", ci_badge().to_markdown())), "{}", content);
+        let toc = &content[content.find(TOC_START).unwrap()..content.find(TOC_END).unwrap()];
+        assert!(toc.contains("[Install](#install)") && toc.contains("[Usage](#usage)"), "{}", toc);
+        // The footer's own heading stays out of the table of contents
+        assert!(!toc.contains("About"), "{}", toc);
+        assert!(content.ends_with("## About\n\nVersion 1.2.3 at 0123456, MIT, by claude-test\n"), "{}", content);
+    }
+
+    #[test]
+    fn running_twice_changes_nothing() {
+        let footer = Footer::new("Generated from {source}").unwrap();
+        let processors = PostProcessors::builtin(true, Some(vec![ci_badge()]), Some(footer));
+        let mut generated = doc(GENERATED);
+        processors.run(&mut generated).unwrap();
+        let once = generated.content.clone();
+        processors.run(&mut generated).unwrap();
+        assert_eq!(generated.content, once);
+        assert_eq!(once.matches("Generated from").count(), 1);
+        assert_eq!(once.matches(TOC_START).count(), 1);
+        assert_eq!(once.matches("badge.svg").count(), 1);
+    }
+
+    #[test]
+    fn footer_templates_are_checked_and_rendered() {
+        let error = Footer::new("Built on {branch}").unwrap_err();
+        assert!(error.to_string().contains("unknown footer variable `{branch}`"), "{}", error);
+
+        let footer = Footer::new("{{literal}} {version} {unclosed").unwrap();
+        let mut generated = doc("# Demo\n");
+        generated.provenance.version = None;
+        assert_eq!(footer.render(&generated), "{literal} unknown {unclosed");
+    }
+
+    #[test]
+    fn a_failing_processor_is_named_and_stops_the_rest() {
+        struct Refuse;
+
+        impl PostProcessor for Refuse {
+            fn name(&self) -> &str {
+                "refuse"
+            }
+
+            fn process(&self, _: &mut GeneratedDoc) -> Result<()> {
+                Err(TechDocsError::Other("no license section".into()))
+            }
+        }
+
+        let mut processors = PostProcessors::default();
+        processors.push(Refuse).push(Shout);
+        let mut generated = doc("# Demo\n");
+        let error = processors.run(&mut generated).unwrap_err();
+        assert_eq!(error.to_string(), "post-processor refuse: no license section");
+        assert_eq!(generated.content, "# Demo\n");
+    }
+}
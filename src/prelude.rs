@@ -0,0 +1,26 @@
+pub use crate::config::Config;
+pub use crate::filters::FilterPreset;
+pub use crate::plan::{PromptOptions, PromptPlan, PromptStats, SkipReason};
+pub use crate::progress::{no_progress, ProgressEvent, ProgressFn};
+pub use crate::{
+    collect_files, inspect_directory, validate_directory, write_files_prompt, CollectedFile, DirCheck, DirInfo,
+    Result, TechDocsError,
+};
+
+#[cfg(any(feature = "git", feature = "claude"))]
+pub use crate::CancellationToken;
+
+#[cfg(feature = "git")]
+pub use crate::manifest::FileManifest;
+#[cfg(feature = "git")]
+pub use crate::{resolve_path, resolve_path_cancellable, resolve_path_with_progress};
+
+#[cfg(feature = "claude")]
+pub use crate::claude::ClaudeClient;
+#[cfg(feature = "claude")]
+pub use crate::provider::{CompletionRequest, CompletionResult, LlmClient, ThinkingConfig, Usage};
+
+#[cfg(all(feature = "git", feature = "claude"))]
+pub use crate::generate::{
+    generate_readme, generate_readme_with_progress, GenerateOptions, GeneratedDoc, ReadmePrompt,
+};
@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+use crate::{CancellationToken, Result, TechDocsError};
+
+/// Stylesheet embedded in every previewed page
+#[cfg(feature = "preview")]
+const PREVIEW_STYLE: &str = "\
+body { max-width: 52rem; margin: 2rem auto; padding: 0 1rem; font: 16px/1.6 system-ui, sans-serif; color: #1f2328; }
+h1, h2 { border-bottom: 1px solid #d1d9e0; padding-bottom: .3em; }
+code, pre { font-family: ui-monospace, monospace; font-size: 85%; background: #f6f8fa; border-radius: 6px; }
+code { padding: .2em .4em; }
+pre { padding: 1rem; overflow: auto; }
+pre code { padding: 0; background: none; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #d1d9e0; padding: .4em .8em; }
+blockquote { margin: 0; padding: 0 1em; color: #59636e; border-left: .25em solid #d1d9e0; }
+img { max-width: 100%; }
+";
+
+/// A generated document to preview: served as HTML at `/`, as it was generated at
+/// `/raw`, and with the run's statistics at `/stats`
+#[derive(Debug, Clone)]
+pub struct Preview {
+    /// Page title, such as the document's file name
+    pub title: String,
+    pub markdown: String,
+    pub stats: serde_json::Value,
+}
+
+/// `markdown` as a standalone HTML page with a minimal stylesheet
+#[cfg(feature = "preview")]
+pub fn render_html(title: &str, markdown: &str) -> String {
+    use pulldown_cmark::{html, Options, Parser};
+
+    let mut body = String::new();
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    html::push_html(&mut body, Parser::new_ext(markdown, options));
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_html(title),
+        PREVIEW_STYLE,
+        body
+    )
+}
+
+/// Routes of the preview server for `preview`
+#[cfg(feature = "preview")]
+pub fn preview_router(preview: Preview) -> axum::Router {
+    use axum::http::header;
+    use axum::response::Html;
+    use axum::routing::get;
+    use std::sync::Arc;
+
+    let html = Arc::new(render_html(&preview.title, &preview.markdown));
+    let markdown = Arc::new(preview.markdown);
+    let stats = Arc::new(preview.stats);
+    axum::Router::new()
+        .route("/", get(move || async move { Html(html.as_str().to_owned()) }))
+        .route(
+            "/raw",
+            get(move || async move {
+                ([(header::CONTENT_TYPE, "text/markdown; charset=utf-8")], markdown.as_str().to_owned())
+            }),
+        )
+        .route("/stats", get(move || async move { axum::Json(stats.as_ref().clone()) }))
+}
+
+/// Serve `preview` on an ephemeral port of localhost until `cancel` fires or `timeout`
+/// passes. `on_ready` is given the page's URL once the server is listening.
+#[cfg(feature = "preview")]
+pub async fn serve_preview(
+    preview: Preview,
+    timeout: Option<Duration>,
+    cancel: &CancellationToken,
+    on_ready: impl FnOnce(&str),
+) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .map_err(|e| TechDocsError::Other(format!("could not start the preview server: {}", e).into()))?;
+    let address = listener
+        .local_addr()
+        .map_err(|e| TechDocsError::Other(format!("could not start the preview server: {}", e).into()))?;
+    on_ready(&format!("http://{}/", address));
+
+    // The shutdown signal must own what it waits on
+    let cancel = cancel.clone();
+    let stop = async move {
+        match timeout {
+            Some(timeout) => tokio::select! {
+                _ = cancel.cancelled() => {}
+                _ = tokio::time::sleep(timeout) => tracing::info!("Preview timed out after {:?}", timeout),
+            },
+            None => cancel.cancelled().await,
+        }
+    };
+    axum::serve(listener, preview_router(preview))
+        .with_graceful_shutdown(stop)
+        .await
+        .map_err(|e| TechDocsError::Other(format!("preview server failed: {}", e).into()))
+}
+
+/// Serve `preview` on localhost. Always fails: this build has no preview support.
+#[cfg(not(feature = "preview"))]
+pub async fn serve_preview(
+    _preview: Preview,
+    _timeout: Option<Duration>,
+    _cancel: &CancellationToken,
+    _on_ready: impl FnOnce(&str),
+) -> Result<()> {
+    Err(TechDocsError::Other(
+        "this build has no preview server; rebuild with `--features preview`".into(),
+    ))
+}
+
+/// Open `url` in the default browser, without waiting for it
+pub fn open_in_browser(url: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    command
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map(drop)
+        .map_err(|e| TechDocsError::Other(format!("could not open a browser: {}", e).into()))
+}
+
+#[cfg(feature = "preview")]
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(all(test, feature = "preview"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_becomes_a_styled_page() {
+        let markdown = "# Title\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n- [x] done\n\n~~old~~ `code`\n";
+        let html = render_html("README <draft> & \"notes\"", markdown);
+        assert!(html.starts_with("<!DOCTYPE html>\n"), "{}", html);
+        assert!(html.contains("<title>README &lt;draft&gt; &amp; &quot;notes&quot;</title>"), "{}", html);
+        assert!(html.contains(PREVIEW_STYLE), "{}", html);
+        assert!(html.contains("<h1>Title</h1>"), "{}", html);
+        assert!(html.contains("<table>"), "{}", html);
+        assert!(html.contains("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>"), "{}", html);
+        assert!(html.contains("<del>old</del>"), "{}", html);
+        assert!(html.contains("<code>code</code>"), "{}", html);
+        assert!(html.ends_with("</body>\n</html>\n"), "{}", html);
+    }
+
+    #[tokio::test]
+    async fn the_server_serves_the_page_raw_markdown_and_stats() {
+        let preview = Preview {
+            title: "README.md".to_string(),
+            markdown: "# Hello\n".to_string(),
+            stats: serde_json::json!({"files": 3}),
+        };
+        let cancel = CancellationToken::new();
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let server = serve_preview(preview, None, &cancel, |url| sender.send(url.to_string()).unwrap());
+        let client = async {
+            let url = receiver.await.unwrap();
+            assert!(url.starts_with("http://127.0.0.1:"), "{}", url);
+
+            let page = reqwest::get(&url).await.unwrap();
+            assert!(page.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+            assert!(page.text().await.unwrap().contains("<h1>Hello</h1>"));
+
+            let raw = reqwest::get(format!("{}raw", url)).await.unwrap();
+            assert_eq!(raw.headers()["content-type"], "text/markdown; charset=utf-8");
+            assert_eq!(raw.text().await.unwrap(), "# Hello\n");
+
+            let stats: serde_json::Value = reqwest::get(format!("{}stats", url)).await.unwrap().json().await.unwrap();
+            assert_eq!(stats, serde_json::json!({"files": 3}));
+
+            cancel.cancel();
+        };
+        let (served, ()) = tokio::join!(server, client);
+        served.unwrap();
+    }
+
+    #[tokio::test]
+    async fn the_server_stops_after_the_timeout() {
+        let preview = Preview {
+            title: String::new(),
+            markdown: String::new(),
+            stats: serde_json::Value::Null,
+        };
+        let cancel = CancellationToken::new();
+        let run = serve_preview(preview, Some(Duration::from_millis(50)), &cancel, |_| {});
+        tokio::time::timeout(Duration::from_secs(5), run).await.unwrap().unwrap();
+    }
+}
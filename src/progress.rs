@@ -0,0 +1,135 @@
+use std::path::PathBuf;
+use serde::Serialize;
+
+use crate::plan::{PromptStats, SkipReason};
+
+/// A step of a long-running operation, reported to a progress callback. Events carry
+/// counts and paths, never file content or generated text.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ProgressEvent {
+    /// Objects received so far while cloning a repository
+    Clone {
+        received_objects: usize,
+        total_objects: usize,
+        received_bytes: usize,
+    },
+    /// Files and directories found so far while walking the project, before any file
+    /// is decided on
+    Scan {
+        entries_seen: usize,
+    },
+    /// Files visited so far while walking the project
+    Walk {
+        files_seen: usize,
+        files_included: usize,
+    },
+    /// A file was chosen for the prompt
+    FileIncluded {
+        #[cfg_attr(feature = "server", schema(value_type = String))]
+        path: PathBuf,
+        size: u64,
+    },
+    /// A file was left out of the prompt
+    FileSkipped {
+        #[cfg_attr(feature = "server", schema(value_type = String))]
+        path: PathBuf,
+        size: u64,
+        reason: SkipReason,
+    },
+    /// The total size budget was reached; every later file is skipped. Sent once.
+    BudgetReached {
+        /// Bytes of file content included before the budget ran out
+        included_bytes: u64,
+    },
+    /// The walk is complete
+    WalkFinished {
+        files_included: usize,
+        files_skipped: usize,
+    },
+    /// The prompt was sent to the provider
+    LlmRequestStarted {
+        /// Model asked for, or `None` for the provider default
+        model: Option<String>,
+    },
+    /// More of the response arrived while streaming; the text itself goes to the text
+    /// callback
+    LlmDelta {
+        bytes: usize,
+    },
+    /// The provider finished the response
+    Completed {
+        stats: PromptStats,
+        input_tokens: u64,
+        output_tokens: u64,
+        /// Why generation stopped, such as `end_turn` or `stop_sequence`
+        stop_reason: Option<String>,
+    },
+}
+
+/// Callback receiving progress events. It is called on the working thread, so keep it cheap.
+pub type ProgressFn<'a> = &'a (dyn Fn(&ProgressEvent) + Send + Sync);
+
+/// Progress callback that ignores every event
+pub fn no_progress(_: &ProgressEvent) {}
+
+#[cfg(all(test, feature = "git", feature = "claude"))]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::generate::{generate_readme, generate_readme_with_progress, GenerateOptions};
+    use crate::plan::PromptOptions;
+    use crate::testing::{FixtureTree, MockClient};
+
+    #[tokio::test]
+    async fn a_run_reports_its_steps_in_order() {
+        // The second large file no longer fits the 1 MB budget
+        let tree = FixtureTree::new()
+            .file("src/main.rs", "fn main() {}\n")
+            .sized_file("data/a.txt", 600 * 1024)
+            .sized_file("data/b.txt", 600 * 1024)
+            .sized_file("data/c.txt", 600 * 1024);
+        let options = GenerateOptions {
+            files: PromptOptions::default().max_file_kb(1024).max_total_mb(1),
+            git_context: false,
+            ..Default::default()
+        };
+        let source = tree.path().to_str().unwrap();
+        let events = Mutex::new(Vec::new());
+        let progress = |event: &ProgressEvent| events.lock().unwrap().push(event.clone());
+        let doc = generate_readme_with_progress(source, &options, &MockClient::text("# Demo\n"), &progress).await.unwrap();
+        let events = events.into_inner().unwrap();
+
+        let position = |matches: fn(&ProgressEvent) -> bool| events.iter().position(matches);
+        let last = |matches: fn(&ProgressEvent) -> bool| events.iter().rposition(matches);
+        let finished = position(|event| matches!(event, ProgressEvent::WalkFinished { .. })).unwrap();
+        let budget = position(|event| matches!(event, ProgressEvent::BudgetReached { .. })).unwrap();
+        let started = position(|event| matches!(event, ProgressEvent::LlmRequestStarted { .. })).unwrap();
+        assert!(last(|event| matches!(event, ProgressEvent::FileIncluded { .. } | ProgressEvent::FileSkipped { .. })).unwrap() < finished);
+        assert!(last(|event| matches!(event, ProgressEvent::FileIncluded { .. })).unwrap() < budget);
+        assert!(finished < started);
+        assert!(matches!(events.last(), Some(ProgressEvent::Completed { .. })), "{:?}", events.last());
+        assert_eq!(events.iter().filter(|event| matches!(event, ProgressEvent::BudgetReached { .. })).count(), 1);
+        assert_eq!(events.iter().filter(|event| matches!(event, ProgressEvent::Completed { .. })).count(), 1);
+
+        // The totals match the events, and the events match the run without a listener
+        let included = events.iter().filter(|event| matches!(event, ProgressEvent::FileIncluded { .. })).count();
+        let skipped = events.iter().filter(|event| matches!(event, ProgressEvent::FileSkipped { .. })).count();
+        assert!(matches!(events[finished], ProgressEvent::WalkFinished { files_included, files_skipped }
+            if (files_included, files_skipped) == (included, skipped)));
+        assert_eq!((doc.stats.files_included, doc.stats.files_skipped), (included, skipped));
+        let quiet = generate_readme(source, &options, &MockClient::text("# Demo\n")).await.unwrap();
+        assert_eq!((quiet.stats.files_included, quiet.stats.files_skipped, quiet.content), (included, skipped, doc.content));
+    }
+
+    #[test]
+    fn events_are_tagged_by_kind() {
+        let event = ProgressEvent::FileSkipped { path: "src/big.rs".into(), size: 10, reason: SkipReason::TooLarge };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"event": "file_skipped", "path": "src/big.rs", "size": 10, "reason": "too_large"})
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::DEFAULT_README_PROMPT;
+
+/// Name of the built-in template that does not depend on the project type
+pub const GENERIC_TEMPLATE: &str = "generic";
+
+/// README system prompts embedded in the binary, by name: [`GENERIC_TEMPLATE`] and one
+/// for each [`ProjectKind`]
+pub const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    (GENERIC_TEMPLATE, DEFAULT_README_PROMPT),
+    ("go", include_str!("../prompts/readme-go.txt")),
+    ("java", include_str!("../prompts/readme-java.txt")),
+    ("node", include_str!("../prompts/readme-node.txt")),
+    ("python", include_str!("../prompts/readme-python.txt")),
+    ("rust", include_str!("../prompts/readme-rust.txt")),
+    ("terraform", include_str!("../prompts/readme-terraform.txt")),
+];
+
+/// Manifests further below the root than this are not looked for
+const MAX_DEPTH: usize = 4;
+
+/// A kind of project, recognized by its manifest files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectKind {
+    Rust,
+    Node,
+    Python,
+    Go,
+    /// Java or another JVM language built with Maven or Gradle
+    Java,
+    Terraform,
+}
+
+impl ProjectKind {
+    /// Name for people, such as `Node.js`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rust => "Rust",
+            Self::Node => "Node.js",
+            Self::Python => "Python",
+            Self::Go => "Go",
+            Self::Java => "Java",
+            Self::Terraform => "Terraform",
+        }
+    }
+
+    /// Name of the built-in template for this kind of project
+    pub fn template(self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::Node => "node",
+            Self::Python => "python",
+            Self::Go => "go",
+            Self::Java => "java",
+            Self::Terraform => "terraform",
+        }
+    }
+
+    /// The kind a file marks a directory as
+    fn of_file(file_name: &str) -> Option<Self> {
+        match file_name {
+            "Cargo.toml" => Some(Self::Rust),
+            "package.json" => Some(Self::Node),
+            "pyproject.toml" | "setup.py" | "setup.cfg" | "requirements.txt" | "Pipfile" => Some(Self::Python),
+            "go.mod" => Some(Self::Go),
+            "pom.xml" | "build.gradle" | "build.gradle.kts" => Some(Self::Java),
+            name if name.ends_with(".tf") => Some(Self::Terraform),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ProjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The kinds of project a directory holds. A monorepo has a primary kind and others
+/// beside it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ProjectType {
+    /// The kind with a manifest in the root directory, else the one in the most
+    /// directories; `None` when no manifest was found
+    pub primary: Option<ProjectKind>,
+    /// Other kinds found, in the most directories first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secondary: Vec<ProjectKind>,
+}
+
+impl ProjectType {
+    /// The type shown by the files at `paths`, all under `root`
+    pub fn from_paths<'a>(root: &Path, paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        let mut at_root = BTreeSet::new();
+        let mut directories: BTreeMap<ProjectKind, BTreeSet<&Path>> = BTreeMap::new();
+        for path in paths {
+            let Some(kind) = path.file_name().and_then(|name| name.to_str()).and_then(ProjectKind::of_file) else {
+                continue;
+            };
+            let Some(dir) = path.parent() else {
+                continue;
+            };
+            if dir == root {
+                at_root.insert(kind);
+            }
+            directories.entry(kind).or_default().insert(dir);
+        }
+
+        // Root manifests first, then by how many directories have one; ties in declaration order
+        let mut kinds: Vec<(ProjectKind, usize)> = directories.into_iter().map(|(kind, dirs)| (kind, dirs.len())).collect();
+        kinds.sort_by(|a, b| at_root.contains(&b.0).cmp(&at_root.contains(&a.0)).then(b.1.cmp(&a.1)).then(a.0.cmp(&b.0)));
+        let mut kinds = kinds.into_iter().map(|(kind, _)| kind);
+        Self { primary: kinds.next(), secondary: kinds.collect() }
+    }
+
+    /// Whether more than one kind of project was found
+    pub fn is_mixed(&self) -> bool {
+        !self.secondary.is_empty()
+    }
+
+    /// Name of the built-in template for the primary kind, or [`GENERIC_TEMPLATE`]
+    pub fn template(&self) -> &'static str {
+        self.primary.map_or(GENERIC_TEMPLATE, ProjectKind::template)
+    }
+
+    /// A line for the prompt's metadata block naming the type, or `None` when unknown
+    pub fn fact(&self) -> Option<String> {
+        let primary = self.primary?;
+        if self.secondary.is_empty() {
+            return Some(format!("Project type: {}\n", primary));
+        }
+        let others: Vec<&str> = self.secondary.iter().map(|kind| kind.name()).collect();
+        Some(format!("Project type: {}, with {} parts\n", primary, others.join(", ")))
+    }
+}
+
+/// Whether `file_name` is a manifest that [`detect_project_type`] recognizes
+pub fn is_manifest(file_name: &str) -> bool {
+    ProjectKind::of_file(file_name).is_some()
+}
+
+/// Detect the kinds of project in `dir` from the manifests in it and the directories
+/// below, honoring .gitignore
+pub fn detect_project_type(dir: &Path) -> ProjectType {
+    let paths: Vec<_> = WalkBuilder::new(dir)
+        .max_depth(Some(MAX_DEPTH))
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+        .map(|entry| entry.into_path())
+        .collect();
+    ProjectType::from_paths(dir, paths.iter().map(|path| path.as_path()))
+}
+
+/// The version in the manifest at the root of `dir`: Cargo.toml (`[package]`, or
+/// `[workspace.package]` in a workspace), package.json, or pyproject.toml
+pub fn project_version(dir: &Path) -> Option<String> {
+    let toml_version = |file: &str, paths: &[&[&str]]| {
+        let manifest = fs::read_to_string(dir.join(file)).ok()?.parse::<toml::Table>().ok()?;
+        paths.iter().find_map(|path| {
+            let value = path.iter().try_fold(None::<&toml::Value>, |value, key| match value {
+                None => manifest.get(*key).map(Some),
+                Some(value) => value.get(key).map(Some),
+            })??;
+            value.as_str().map(String::from)
+        })
+    };
+    toml_version("Cargo.toml", &[&["package", "version"], &["workspace", "package", "version"]])
+        .or_else(|| {
+            let package = fs::read_to_string(dir.join("package.json")).ok()?;
+            let package: serde_json::Value = serde_json::from_str(&package).ok()?;
+            package.get("version")?.as_str().map(String::from)
+        })
+        .or_else(|| toml_version("pyproject.toml", &[&["project", "version"], &["tool", "poetry", "version"]]))
+}
+
+/// The built-in template called `name`
+pub fn builtin_template(name: &str) -> Option<&'static str> {
+    BUILTIN_TEMPLATES.iter().find(|(template, _)| *template == name).map(|(_, text)| *text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    #[test]
+    fn a_root_manifest_sets_the_type_and_template() {
+        let tree = FixtureTree::new().file("Cargo.toml", "[package]\nname = \"demo\"\nversion = \"1.2.3\"\n").file("src/lib.rs", "");
+        let detected = detect_project_type(tree.path());
+        assert_eq!(detected, ProjectType { primary: Some(ProjectKind::Rust), secondary: vec![] });
+        assert_eq!(detected.template(), "rust");
+        assert_eq!(builtin_template(detected.template()), Some(include_str!("../prompts/readme-rust.txt")));
+        assert_eq!(detected.fact().as_deref(), Some("Project type: Rust\n"));
+        assert_eq!(project_version(tree.path()).as_deref(), Some("1.2.3"));
+
+        let tree = FixtureTree::new().file("main.tf", "").file("variables.tf", "");
+        assert_eq!(detect_project_type(tree.path()).template(), "terraform");
+    }
+
+    #[test]
+    fn without_a_manifest_the_generic_template_is_used() {
+        let tree = FixtureTree::new().file("README.md", "# Notes\n").file("notes/today.txt", "");
+        let detected = detect_project_type(tree.path());
+        assert_eq!(detected, ProjectType::default());
+        assert_eq!(detected.template(), GENERIC_TEMPLATE);
+        assert_eq!(builtin_template(GENERIC_TEMPLATE), Some(DEFAULT_README_PROMPT));
+        assert_eq!(detected.fact(), None);
+        assert_eq!(project_version(tree.path()), None);
+    }
+
+    #[test]
+    fn a_monorepo_reports_its_dominant_type_first() {
+        // Two Python services outnumber the one Go tool; a root package.json outranks both
+        let tree = FixtureTree::new()
+            .file("services/api/pyproject.toml", "")
+            .file("services/worker/requirements.txt", "")
+            .file("tools/cli/go.mod", "");
+        let detected = detect_project_type(tree.path());
+        assert_eq!(detected.primary, Some(ProjectKind::Python));
+        assert_eq!(detected.secondary, [ProjectKind::Go]);
+        assert!(detected.is_mixed());
+        assert_eq!(detected.fact().as_deref(), Some("Project type: Python, with Go parts\n"));
+
+        let tree = FixtureTree::new()
+            .file("package.json", r#"{"version": "0.4.0"}"#)
+            .file("services/api/pyproject.toml", "")
+            .file("services/worker/pyproject.toml", "");
+        let detected = detect_project_type(tree.path());
+        assert_eq!((detected.primary, detected.secondary), (Some(ProjectKind::Node), vec![ProjectKind::Python]));
+        assert_eq!(project_version(tree.path()).as_deref(), Some("0.4.0"));
+    }
+
+    #[test]
+    fn every_kind_has_a_builtin_template() {
+        for kind in [
+            ProjectKind::Rust,
+            ProjectKind::Node,
+            ProjectKind::Python,
+            ProjectKind::Go,
+            ProjectKind::Java,
+            ProjectKind::Terraform,
+        ] {
+            assert!(builtin_template(kind.template()).is_some(), "{}", kind);
+        }
+        assert_eq!(builtin_template("cobol"), None);
+    }
+}
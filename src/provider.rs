@@ -0,0 +1,485 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::claude::{model_info, DEFAULT_MODEL};
+use crate::plan::estimate_tokens;
+use crate::{Result, TechDocsError};
+
+/// A tool the model is forced to call, used to get structured JSON output
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    /// JSON Schema for the tool input
+    pub input_schema: serde_json::Value,
+}
+
+/// A single-turn request to an LLM provider
+#[derive(Debug, Clone, Default)]
+pub struct CompletionRequest {
+    /// Model to use, or `None` for the provider default
+    pub model: Option<String>,
+    /// System prompt
+    pub system: String,
+    /// User message content
+    pub content: String,
+    /// Maximum number of output tokens, or `None` for the provider default
+    pub max_tokens: Option<u32>,
+    /// Tool the model must call; its input is returned in `CompletionResult::tool_input`
+    pub tool: Option<ToolSpec>,
+    /// Text that ends the response when the model writes it; the sequence itself is
+    /// left out of the text
+    pub stop_sequences: Vec<String>,
+    /// How long the answer should be, given to the model as an instruction rather than
+    /// enforced
+    pub target_length: Option<TargetLength>,
+    /// Let the model think before it answers, for providers that support it
+    pub thinking: Option<ThinkingConfig>,
+}
+
+impl CompletionRequest {
+    pub fn new(system: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            system: system.into(),
+            content: content.into(),
+            ..Default::default()
+        }
+    }
+
+    /// The request with the stop sequences, length, and output limit of `profile`
+    pub fn with_profile(mut self, profile: &GenerationProfile) -> Self {
+        self.stop_sequences = profile.stop_sequences.iter().map(|stop| stop.to_string()).collect();
+        self.target_length = profile.target_length;
+        self.max_tokens = self.max_tokens.or(profile.max_tokens);
+        self
+    }
+
+    /// The system prompt followed by the instruction for `target_length`, if any
+    pub fn system_prompt(&self) -> Cow<'_, str> {
+        match self.target_length {
+            Some(length) => Cow::Owned(format!("{}\n\nKeep your answer to about {}.", self.system.trim_end(), length)),
+            None => Cow::Borrowed(&self.system),
+        }
+    }
+}
+
+/// Smallest thinking budget Claude accepts
+pub const MIN_THINKING_BUDGET: u32 = 1024;
+
+/// Extended thinking: the model reasons before it answers, spending up to
+/// `budget_tokens` of the request's `max_tokens` on it. The thinking is left out of
+/// [`CompletionResult::text`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThinkingConfig {
+    pub budget_tokens: u32,
+}
+
+impl ThinkingConfig {
+    /// Thinking with `budget_tokens`, which must be at least [`MIN_THINKING_BUDGET`] and
+    /// below the `max_tokens` of the request, leaving room for the answer
+    pub fn new(budget_tokens: u32, max_tokens: u32) -> Result<Self> {
+        if budget_tokens < MIN_THINKING_BUDGET {
+            return Err(TechDocsError::Config(format!(
+                "thinking budget {} is below the minimum of {} tokens",
+                budget_tokens, MIN_THINKING_BUDGET
+            )));
+        }
+        if budget_tokens >= max_tokens {
+            return Err(TechDocsError::Config(format!(
+                "thinking budget {} must be below max_tokens {}",
+                budget_tokens, max_tokens
+            )));
+        }
+        Ok(Self { budget_tokens })
+    }
+}
+
+/// A soft limit on the length of an answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetLength {
+    Sentences(u32),
+    Words(u32),
+}
+
+impl fmt::Display for TargetLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sentences(1) => write!(f, "one sentence"),
+            Self::Sentences(n) => write!(f, "{} sentences", n),
+            Self::Words(n) => write!(f, "{} words", n),
+        }
+    }
+}
+
+/// How a kind of document is generated: where the model should stop and how long the
+/// answer should be. Each command that calls the model has one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GenerationProfile {
+    /// Becomes [`CompletionRequest::stop_sequences`]
+    pub stop_sequences: &'static [&'static str],
+    pub target_length: Option<TargetLength>,
+    /// Output token limit unless the caller sets one
+    pub max_tokens: Option<u32>,
+}
+
+/// Token usage reported by the provider
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// The provider's response to a `CompletionRequest`
+#[derive(Debug, Clone, Default)]
+pub struct CompletionResult {
+    /// Concatenated text content
+    pub text: String,
+    /// Input of the forced tool call, when a tool was requested
+    pub tool_input: Option<serde_json::Value>,
+    /// Model that produced the response
+    pub model: String,
+    pub usage: Usage,
+    /// Why generation stopped (`end_turn`, `max_tokens`, `stop_sequence`, `tool_use`, ...)
+    pub stop_reason: Option<String>,
+    /// The stop sequence that ended the response, when `stop_reason` is `stop_sequence`
+    pub stop_sequence: Option<String>,
+    /// Estimated tokens of the model's thinking, from the thinking it returned; they
+    /// are already counted in `usage.output_tokens`. 0 without extended thinking.
+    pub thinking_tokens: u64,
+}
+
+/// Callback receiving generated text as it arrives
+pub type TextFn<'a> = &'a (dyn Fn(&str) + Send + Sync);
+
+/// An LLM backend capable of single-turn completions
+#[async_trait]
+pub trait LlmClient: Send + Sync {
+    async fn complete(&self, request: &CompletionRequest) -> Result<CompletionResult>;
+
+    /// Like [`LlmClient::complete`], passing text to `on_text` as it is generated.
+    /// Clients that cannot stream pass the whole text at once.
+    async fn complete_streaming(&self, request: &CompletionRequest, on_text: TextFn<'_>) -> Result<CompletionResult> {
+        let result = self.complete(request).await?;
+        on_text(&result.text);
+        Ok(result)
+    }
+}
+
+/// Wraps a client so requests start at most `requests_per_minute` times a minute.
+/// Share one instance between concurrent tasks to share the limit.
+pub struct RateLimitedClient<C> {
+    inner: C,
+    interval: Duration,
+    next_slot: Mutex<Instant>,
+}
+
+impl<C: LlmClient> RateLimitedClient<C> {
+    pub fn new(inner: C, requests_per_minute: u32) -> Self {
+        Self {
+            inner,
+            interval: Duration::from_secs(60) / requests_per_minute.max(1),
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Reserve the next free slot, then wait for it outside the lock
+    async fn wait_for_slot(&self) {
+        let start = {
+            let mut next_slot = self.next_slot.lock().await;
+            let start = (*next_slot).max(Instant::now());
+            *next_slot = start + self.interval;
+            start
+        };
+        tokio::time::sleep_until(start).await;
+    }
+}
+
+#[async_trait]
+impl<C: LlmClient> LlmClient for RateLimitedClient<C> {
+    async fn complete(&self, request: &CompletionRequest) -> Result<CompletionResult> {
+        self.wait_for_slot().await;
+        self.inner.complete(request).await
+    }
+
+    async fn complete_streaming(&self, request: &CompletionRequest, on_text: TextFn<'_>) -> Result<CompletionResult> {
+        self.wait_for_slot().await;
+        self.inner.complete_streaming(request, on_text).await
+    }
+}
+
+/// What the provider calls of a run have cost so far
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct Spend {
+    /// Completed provider calls
+    pub calls: u64,
+    pub usage: Usage,
+    /// Cost of `usage` in USD at the price of the models that answered
+    pub cost_usd: f64,
+}
+
+/// Adds up what every provider call of a run costs and enforces an optional ceiling
+/// on it. Clones share the running total, so give one tracker to everything a run
+/// calls the provider from.
+#[derive(Debug, Clone, Default)]
+pub struct CostTracker {
+    limit_usd: Option<f64>,
+    spend: Arc<std::sync::Mutex<Spend>>,
+}
+
+impl CostTracker {
+    /// A tracker that fails calls once spending would pass `limit_usd`, or never
+    /// fails without one
+    pub fn new(limit_usd: Option<f64>) -> Self {
+        Self { limit_usd, spend: Arc::default() }
+    }
+
+    /// A tracker that starts from what an earlier run of the same job spent, so a
+    /// resumed run keeps to the ceiling of the whole job
+    pub fn resume(limit_usd: Option<f64>, spent: Spend) -> Self {
+        Self { limit_usd, spend: Arc::new(std::sync::Mutex::new(spent)) }
+    }
+
+    pub fn limit_usd(&self) -> Option<f64> {
+        self.limit_usd
+    }
+
+    /// What the calls recorded so far cost
+    pub fn spend(&self) -> Spend {
+        *self.spend.lock().expect("cost lock")
+    }
+
+    /// Check that the estimated input of `request` keeps spending within the limit.
+    /// Fails with [`TechDocsError::CostLimitExceeded`] when it would not, and with
+    /// [`TechDocsError::Budget`] when the model's price is unknown, since the limit
+    /// could not be kept.
+    pub fn check(&self, request: &CompletionRequest) -> Result<()> {
+        self.check_all(std::iter::once(request))
+    }
+
+    /// [`CostTracker::check`] for requests sent together, such as a message batch
+    pub fn check_all<'a>(&self, requests: impl IntoIterator<Item = &'a CompletionRequest>) -> Result<()> {
+        let Some(limit) = self.limit_usd else {
+            return Ok(());
+        };
+        let mut input_cost = 0.0;
+        for request in requests {
+            let model = request.model.as_deref().unwrap_or(DEFAULT_MODEL);
+            let info = model_info(model).ok_or_else(|| {
+                TechDocsError::Budget(format!("no pricing known for model {}, so the cost limit cannot be enforced", model))
+            })?;
+            let bytes = request.system_prompt().len() + request.content.len();
+            input_cost += info.cost_usd(estimate_tokens(bytes as u64), 0);
+        }
+        let spent = self.spend().cost_usd;
+        if spent + input_cost > limit {
+            return Err(TechDocsError::CostLimitExceeded { spent, limit });
+        }
+        Ok(())
+    }
+
+    /// [`CostTracker::record`] for the answer to `request`, priced for the model that
+    /// answered, or the one asked for when the provider does not say
+    pub fn record_result(&self, request: &CompletionRequest, result: &CompletionResult) {
+        let model = match result.model.as_str() {
+            "" => request.model.as_deref().unwrap_or(DEFAULT_MODEL),
+            model => model,
+        };
+        self.record(model, result.usage);
+    }
+
+    /// Add a completed call's `usage`, priced for `model`. Usage of models without a
+    /// known price is counted at no cost.
+    pub fn record(&self, model: &str, usage: Usage) {
+        let cost = model_info(model).map_or(0.0, |info| info.cost_usd(usage.input_tokens, usage.output_tokens));
+        let mut spend = self.spend.lock().expect("cost lock");
+        spend.calls += 1;
+        spend.usage.input_tokens += usage.input_tokens;
+        spend.usage.output_tokens += usage.output_tokens;
+        spend.cost_usd += cost;
+    }
+}
+
+/// Wraps a client so every request is checked against and recorded in a
+/// [`CostTracker`]
+pub struct CostTrackedClient<C> {
+    inner: C,
+    tracker: CostTracker,
+}
+
+impl<C: LlmClient> CostTrackedClient<C> {
+    pub fn new(inner: C, tracker: CostTracker) -> Self {
+        Self { inner, tracker }
+    }
+}
+
+#[async_trait]
+impl<C: LlmClient> LlmClient for CostTrackedClient<C> {
+    async fn complete(&self, request: &CompletionRequest) -> Result<CompletionResult> {
+        self.tracker.check(request)?;
+        let result = self.inner.complete(request).await?;
+        self.tracker.record_result(request, &result);
+        Ok(result)
+    }
+
+    async fn complete_streaming(&self, request: &CompletionRequest, on_text: TextFn<'_>) -> Result<CompletionResult> {
+        self.tracker.check(request)?;
+        let result = self.inner.complete_streaming(request, on_text).await?;
+        self.tracker.record_result(request, &result);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockClient;
+
+    const PROFILE: GenerationProfile = GenerationProfile {
+        stop_sequences: &["\n## "],
+        target_length: Some(TargetLength::Sentences(3)),
+        max_tokens: Some(512),
+    };
+
+    #[test]
+    fn a_profile_sets_the_stop_sequences_and_length() {
+        let request = CompletionRequest::new("Describe the project.\n", "files").with_profile(&PROFILE);
+        assert_eq!(request.stop_sequences, ["\n## "]);
+        assert_eq!(request.max_tokens, Some(512));
+        assert_eq!(request.system_prompt(), "Describe the project.\n\nKeep your answer to about 3 sentences.");
+
+        // An output limit the caller chose is kept
+        let request = CompletionRequest { max_tokens: Some(100), ..CompletionRequest::new("s", "c") }.with_profile(&PROFILE);
+        assert_eq!(request.max_tokens, Some(100));
+        assert_eq!(CompletionRequest::new("s", "c").system_prompt(), "s");
+        assert_eq!(TargetLength::Sentences(1).to_string(), "one sentence");
+        assert_eq!(TargetLength::Words(50).to_string(), "50 words");
+    }
+
+    #[test]
+    fn thinking_budgets_outside_the_limits_are_config_errors() {
+        assert_eq!(ThinkingConfig::new(2048, 8192).unwrap().budget_tokens, 2048);
+        assert_eq!(ThinkingConfig::new(MIN_THINKING_BUDGET, MIN_THINKING_BUDGET + 1).unwrap().budget_tokens, MIN_THINKING_BUDGET);
+
+        let error = ThinkingConfig::new(MIN_THINKING_BUDGET - 1, 8192).unwrap_err();
+        assert!(matches!(&error, TechDocsError::Config(message) if message.contains("below the minimum")), "{}", error);
+        let error = ThinkingConfig::new(4096, 4096).unwrap_err();
+        assert!(matches!(&error, TechDocsError::Config(message) if message.contains("must be below max_tokens 4096")), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn clients_that_cannot_stream_pass_the_whole_text_at_once() {
+        let client = MockClient::text("# Title\n");
+        let received = std::sync::Mutex::new(Vec::new());
+        let on_text = |text: &str| received.lock().unwrap().push(text.to_string());
+        let result = client.complete_streaming(&CompletionRequest::new("s", "c"), &on_text).await.unwrap();
+        assert_eq!(result.text, "# Title\n");
+        assert_eq!(received.into_inner().unwrap(), ["# Title\n"]);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_spaced_apart() {
+        let client = RateLimitedClient::new(MockClient::text("ok"), 600);
+        let request = CompletionRequest::new("s", "c");
+        let started = Instant::now();
+        for _ in 0..3 {
+            client.complete(&request).await.unwrap();
+        }
+        // The first starts at once, then one every 100ms
+        assert!(started.elapsed() >= Duration::from_millis(200), "{:?}", started.elapsed());
+    }
+
+    const HAIKU: &str = "claude-3-5-haiku-latest";
+
+    /// A request whose input is estimated at `tokens` tokens of Claude 3.5 Haiku
+    fn request_of(tokens: usize) -> CompletionRequest {
+        CompletionRequest { model: Some(HAIKU.into()), ..CompletionRequest::new("", "x".repeat(tokens * 4)) }
+    }
+
+    fn usage(input_tokens: u64, output_tokens: u64) -> Usage {
+        Usage { input_tokens, output_tokens }
+    }
+
+    #[test]
+    fn spending_accumulates_across_calls_and_clones() {
+        let tracker = CostTracker::new(None);
+        let shared = tracker.clone();
+        tracker.record(HAIKU, usage(1_000_000, 0));
+        shared.record(HAIKU, usage(0, 250_000));
+        shared.record("unpriced-model", usage(500, 500));
+
+        let spend = tracker.spend();
+        assert_eq!(spend.calls, 3);
+        assert_eq!((spend.usage.input_tokens, spend.usage.output_tokens), (1_000_500, 250_500));
+        assert!((spend.cost_usd - 1.8).abs() < 1e-9, "{}", spend.cost_usd);
+
+        // A result without a model is priced for the model that was asked for
+        let tracker = CostTracker::new(None);
+        tracker.record_result(&request_of(1), &CompletionResult { usage: usage(1_000_000, 0), ..Default::default() });
+        assert!((tracker.spend().cost_usd - 0.8).abs() < 1e-9);
+        let resumed = CostTracker::resume(Some(5.0), tracker.spend());
+        assert_eq!(resumed.spend().calls, 1);
+        assert_eq!(resumed.limit_usd(), Some(5.0));
+    }
+
+    #[test]
+    fn a_request_may_reach_the_limit_exactly() {
+        // 100,000 input tokens of Haiku cost $0.08
+        let tracker = CostTracker::new(Some(0.08));
+        tracker.check(&request_of(100_000)).unwrap();
+        let error = tracker.check(&request_of(100_001)).unwrap_err();
+        assert!(matches!(error, TechDocsError::CostLimitExceeded { spent, limit } if spent == 0.0 && limit == 0.08));
+
+        tracker.record(HAIKU, usage(100_000, 0));
+        tracker.check(&request_of(0)).unwrap();
+        let error = tracker.check(&request_of(1)).unwrap_err();
+        assert!(matches!(error, TechDocsError::CostLimitExceeded { spent, .. } if (spent - 0.08).abs() < 1e-12));
+    }
+
+    #[test]
+    fn requests_sent_together_are_checked_together() {
+        let tracker = CostTracker::new(Some(0.08));
+        let requests = [request_of(50_000), request_of(50_000)];
+        tracker.check_all(&requests).unwrap();
+        let requests = [request_of(50_000), request_of(50_001)];
+        assert!(matches!(tracker.check_all(&requests), Err(TechDocsError::CostLimitExceeded { .. })));
+    }
+
+    #[test]
+    fn a_limit_cannot_be_kept_for_a_model_without_a_price() {
+        let request = CompletionRequest { model: Some("unpriced-model".into()), ..CompletionRequest::new("s", "c") };
+        CostTracker::new(None).check(&request).unwrap();
+        let error = CostTracker::new(Some(1.0)).check(&request).unwrap_err();
+        assert!(matches!(&error, TechDocsError::Budget(message) if message.contains("unpriced-model")), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn calls_are_refused_once_the_limit_is_spent() {
+        // Each call reports 50,000 input and 10,000 output tokens: $0.04 + $0.04
+        let provider = MockClient::new(|_| {
+            Ok(CompletionResult { text: "ok".into(), model: HAIKU.into(), usage: usage(50_000, 10_000), ..Default::default() })
+        });
+        let tracker = CostTracker::new(Some(0.1));
+        let client = CostTrackedClient::new(provider, tracker.clone());
+        // $0.024 of input fits the limit once, but not after the first call's $0.08
+        let request = request_of(30_000);
+
+        client.complete(&request).await.unwrap();
+        let spent = tracker.spend();
+        assert_eq!(spent.calls, 1);
+        assert!((spent.cost_usd - 0.08).abs() < 1e-9);
+
+        let error = client.complete(&request).await.unwrap_err();
+        assert!(matches!(error, TechDocsError::CostLimitExceeded { spent, limit } if (spent - 0.08).abs() < 1e-9 && limit == 0.1));
+        assert_eq!(client.inner.requests().len(), 1);
+        assert_eq!(tracker.spend().calls, 1);
+    }
+}
+
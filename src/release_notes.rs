@@ -0,0 +1,389 @@
+use std::fmt::Write;
+
+use crate::changelog::{format_date, parse_conventional};
+use crate::git::{CommitInfo, FilePatch};
+#[cfg(feature = "claude")]
+use crate::provider::GenerationProfile;
+
+/// Default system prompt for release notes, embedded at compile time
+pub const DEFAULT_RELEASE_NOTES_PROMPT: &str = include_str!("../prompts/release-notes.txt");
+#[cfg(feature = "claude")]
+/// Release notes cover one release under one level-2 heading, so a second one ends them
+pub const RELEASE_NOTES_PROFILE: GenerationProfile = GenerationProfile {
+    stop_sequences: &["\n## "],
+    target_length: None,
+    max_tokens: None,
+};
+
+/// Release notes section a change belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReleaseSection {
+    Added,
+    Changed,
+    Fixed,
+}
+
+impl ReleaseSection {
+    pub fn title(&self) -> &'static str {
+        match self {
+            ReleaseSection::Added => "Added",
+            ReleaseSection::Changed => "Changed",
+            ReleaseSection::Fixed => "Fixed",
+        }
+    }
+}
+
+/// A user-facing change derived from a commit
+#[derive(Debug, Clone)]
+pub struct ReleaseEntry {
+    pub section: ReleaseSection,
+    pub description: String,
+    pub breaking: bool,
+    /// Pull request numbers referenced by the commit message
+    pub pull_requests: Vec<u32>,
+    pub commit: CommitInfo,
+}
+
+/// Pull request numbers referenced in a commit message, such as
+/// `Merge pull request #12 from ...` or a squash-merge suffix `(#12)`
+pub fn parse_pr_references(message: &str) -> Vec<u32> {
+    let mut numbers = Vec::new();
+    for (index, _) in message.match_indices('#') {
+        let preceded_ok = message[..index]
+            .chars()
+            .next_back()
+            .is_none_or(|c| c.is_whitespace() || c == '(' || c == '[');
+        if !preceded_ok {
+            continue;
+        }
+        let digits: String = message[index + 1..].chars().take_while(|c| c.is_ascii_digit()).collect();
+        if let Ok(number) = digits.parse::<u32>() {
+            if !numbers.contains(&number) {
+                numbers.push(number);
+            }
+        }
+    }
+    numbers
+}
+
+/// Remove trailing squash-merge references like ` (#12)`, which are rendered separately
+fn strip_pr_suffix(description: &str) -> &str {
+    let mut description = description.trim_end();
+    while let Some(open) = description.rfind(" (#") {
+        let tail = &description[open + 3..];
+        match tail.strip_suffix(')') {
+            Some(digits) if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) => {
+                description = description[..open].trim_end();
+            }
+            _ => break,
+        }
+    }
+    description
+}
+
+/// Classify commits into Added/Changed/Fixed entries, oldest first.
+/// Merge commits are kept only when they reference a pull request.
+pub fn classify_commits(commits: &[CommitInfo]) -> Vec<ReleaseEntry> {
+    let mut entries = Vec::new();
+
+    for commit in commits.iter().rev() {
+        let pull_requests =
+            parse_pr_references(&format!("{}\n{}", commit.summary, commit.body));
+
+        // GitHub merge commits carry the PR title as the first body line
+        let (summary, body) = if commit.is_merge {
+            if pull_requests.is_empty() {
+                continue;
+            }
+            let title = commit.body.lines().find(|l| !l.trim().is_empty()).unwrap_or(&commit.summary);
+            (title.trim(), "")
+        } else {
+            (commit.summary.as_str(), commit.body.as_str())
+        };
+
+        let (section, description, breaking) = match parse_conventional(summary, body) {
+            Some(c) => {
+                let section = match c.kind.as_str() {
+                    "feat" | "feature" => ReleaseSection::Added,
+                    "fix" | "bugfix" => ReleaseSection::Fixed,
+                    _ => ReleaseSection::Changed,
+                };
+                (section, c.description, c.breaking)
+            }
+            None => {
+                let lower = summary.to_ascii_lowercase();
+                let section = if lower.starts_with("add") || lower.starts_with("introduce") {
+                    ReleaseSection::Added
+                } else if lower.starts_with("fix") {
+                    ReleaseSection::Fixed
+                } else {
+                    ReleaseSection::Changed
+                };
+                (section, summary.to_string(), false)
+            }
+        };
+
+        entries.push(ReleaseEntry {
+            section,
+            description: strip_pr_suffix(&description).to_string(),
+            breaking,
+            pull_requests,
+            commit: commit.clone(),
+        });
+    }
+    entries
+}
+
+/// Aggregate statistics about the files changed in a release
+#[derive(Debug, Clone, Default)]
+pub struct ChangeStats {
+    pub commits: usize,
+    pub files_changed: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+    /// Files with the most changed lines, largest first
+    pub top_files: Vec<(String, usize)>,
+}
+
+impl ChangeStats {
+    pub fn new(commits: usize, patches: &[FilePatch]) -> Self {
+        let mut top_files: Vec<(String, usize)> = patches
+            .iter()
+            .map(|p| (p.path.clone(), p.insertions + p.deletions))
+            .collect();
+        top_files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_files.truncate(10);
+
+        Self {
+            commits,
+            files_changed: patches.len(),
+            insertions: patches.iter().map(|p| p.insertions).sum(),
+            deletions: patches.iter().map(|p| p.deletions).sum(),
+            top_files,
+        }
+    }
+}
+
+fn entry_line(entry: &ReleaseEntry) -> String {
+    let mut line = String::new();
+    if entry.breaking {
+        line.push_str("**Breaking:** ");
+    }
+    line.push_str(&entry.description);
+    for number in &entry.pull_requests {
+        let _ = write!(line, " (#{})", number);
+    }
+    let _ = write!(line, " ({})", entry.commit.short_id());
+    line
+}
+
+/// Render release notes from the classified commits without using the LLM
+pub fn render_markdown(title: &str, date: &str, entries: &[ReleaseEntry], stats: &ChangeStats) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "## {} - {}", title, date);
+
+    for section in [ReleaseSection::Added, ReleaseSection::Changed, ReleaseSection::Fixed] {
+        let mut section_entries: Vec<&ReleaseEntry> =
+            entries.iter().filter(|e| e.section == section).collect();
+        if section_entries.is_empty() {
+            continue;
+        }
+        // Breaking changes lead their section
+        section_entries.sort_by_key(|e| !e.breaking);
+        let _ = writeln!(out, "\n### {}\n", section.title());
+        for entry in section_entries {
+            let _ = writeln!(out, "- {}", entry_line(entry));
+        }
+    }
+
+    let _ = writeln!(
+        out,
+        "\n{} commits, {} files changed, +{}/-{} lines",
+        stats.commits, stats.files_changed, stats.insertions, stats.deletions
+    );
+    out
+}
+
+/// Render the classified commits and statistics as input for the release notes prompt
+pub fn render_prompt_input(
+    title: &str,
+    date: &str,
+    previous: Option<&str>,
+    entries: &[ReleaseEntry],
+    stats: &ChangeStats,
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Release title: {}", title);
+    let _ = writeln!(out, "Date: {}", date);
+    match previous {
+        Some(previous) => {
+            let _ = writeln!(out, "Previous release: {}", previous);
+        }
+        None => {
+            let _ = writeln!(out, "This is the first release.");
+        }
+    }
+    let _ = writeln!(
+        out,
+        "Statistics: {} commits, {} files changed, +{}/-{} lines",
+        stats.commits, stats.files_changed, stats.insertions, stats.deletions
+    );
+    if !stats.top_files.is_empty() {
+        let _ = writeln!(out, "\nMost changed files:");
+        for (path, lines) in &stats.top_files {
+            let _ = writeln!(out, "- {} ({} lines)", path, lines);
+        }
+    }
+
+    let _ = writeln!(out, "\nCommits (oldest first):");
+    for entry in entries {
+        let _ = writeln!(
+            out,
+            "- [{}] {} ({})",
+            entry.section.title(),
+            entry_line(entry),
+            format_date(entry.commit.time)
+        );
+        for line in entry.commit.body.lines().filter(|l| !l.trim().is_empty()).take(5) {
+            let _ = writeln!(out, "    {}", line.trim());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{commits_between, latest_tag, resolve_commit};
+    use crate::testing::FixtureTree;
+
+    fn commit(id: &str, summary: &str, body: &str) -> CommitInfo {
+        CommitInfo {
+            id: id.repeat(40),
+            summary: summary.into(),
+            body: body.into(),
+            author: "Test".into(),
+            time: 1_700_000_000,
+            is_merge: false,
+            files_changed: 1,
+            insertions: 1,
+            deletions: 0,
+        }
+    }
+
+    /// Newest first, as [`commits_between`] returns them
+    fn history() -> Vec<CommitInfo> {
+        vec![
+            CommitInfo { is_merge: true, ..commit("e", "Merge branch 'wip'", "") },
+            CommitInfo { is_merge: true, ..commit("d", "Merge pull request #42 from acme/retry", "Retry failed uploads\n") },
+            commit("c", "Add a --quiet flag", ""),
+            commit("b", "fix(cli): handle empty input (#17)", ""),
+            commit("a", "feat!: rename the config file", "BREAKING CHANGE: techdocs.toml is now .techdocs.toml"),
+        ]
+    }
+
+    #[test]
+    fn commits_are_grouped_into_sections() {
+        let entries = classify_commits(&history());
+        let classified: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.section, entry.description.as_str(), entry.breaking, entry.pull_requests.clone()))
+            .collect();
+        assert_eq!(
+            classified,
+            [
+                (ReleaseSection::Added, "rename the config file", true, vec![]),
+                (ReleaseSection::Fixed, "handle empty input", false, vec![17]),
+                (ReleaseSection::Added, "Add a --quiet flag", false, vec![]),
+                (ReleaseSection::Changed, "Retry failed uploads", false, vec![42]),
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_notes_list_each_section_with_breaking_changes_first() {
+        let entries = classify_commits(&history());
+        let stats = ChangeStats::new(5, &[]);
+        assert_eq!(
+            render_markdown("v1.0.0", "2024-01-01", &entries, &stats),
+            "## v1.0.0 - 2024-01-01
+
+### Added
+
+- **Breaking:** rename the config file (aaaaaaa)
+- Add a --quiet flag (ccccccc)
+
+### Changed
+
+- Retry failed uploads (#42) (ddddddd)
+
+### Fixed
+
+- handle empty input (#17) (bbbbbbb)
+
+5 commits, 0 files changed, +0/-0 lines
+"
+        );
+    }
+
+    #[test]
+    fn the_prompt_says_when_there_is_no_previous_release() {
+        let entries = classify_commits(&history()[2..]);
+        let patches = [
+            FilePatch { path: "src/cli.rs".into(), patch: String::new(), binary: false, insertions: 8, deletions: 2 },
+            FilePatch { path: "README.md".into(), patch: String::new(), binary: false, insertions: 3, deletions: 0 },
+        ];
+        let input = render_prompt_input("v0.1.0", "2024-01-01", None, &entries, &ChangeStats::new(3, &patches));
+        assert!(input.contains("This is the first release.\nStatistics: 3 commits, 2 files changed, +11/-2 lines\n"), "{}", input);
+        assert!(input.contains("Most changed files:\n- src/cli.rs (10 lines)\n- README.md (3 lines)\n"), "{}", input);
+        assert!(input.contains("- [Added] **Breaking:** rename the config file (aaaaaaa) (2023-11-14)\n    BREAKING CHANGE:"), "{}", input);
+
+        let input = render_prompt_input("v0.2.0", "2024-02-01", Some("v0.1.0"), &[], &ChangeStats::default());
+        assert!(input.contains("Previous release: v0.1.0\n"), "{}", input);
+    }
+
+    #[test]
+    fn pull_requests_are_read_from_references_only() {
+        assert_eq!(parse_pr_references("Fix crash (#12) and #7, see [#12]"), [12, 7]);
+        assert!(parse_pr_references("Use color#fff and issue#3").is_empty());
+    }
+
+    #[test]
+    fn annotated_and_lightweight_tags_bound_releases() {
+        let tree = FixtureTree::new();
+        let repo = git2::Repository::init(tree.path()).unwrap();
+        let signature = git2::Signature::new("Test", "test@example.com", &git2::Time::new(1_700_000_000, 0)).unwrap();
+        let mut parent: Option<git2::Oid> = None;
+        for (n, message) in ["feat: first", "fix: second", "feat: third"].iter().enumerate() {
+            std::fs::write(tree.join("file.txt"), format!("{}\n", n)).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(std::path::Path::new("file.txt")).unwrap();
+            index.write().unwrap();
+            let snapshot = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let parents: Vec<_> = parent.map(|id| repo.find_commit(id).unwrap()).into_iter().collect();
+            let parents: Vec<_> = parents.iter().collect();
+            let id = repo.commit(Some("HEAD"), &signature, &signature, message, &snapshot, &parents).unwrap();
+            match n {
+                0 => {
+                    repo.tag_lightweight("v0.1.0", &repo.find_object(id, None).unwrap(), false).unwrap();
+                }
+                2 => {
+                    repo.tag("v0.2.0", &repo.find_object(id, None).unwrap(), &signature, "Release 0.2.0", false).unwrap();
+                }
+                _ => {}
+            }
+            parent = Some(id);
+        }
+
+        let to = resolve_commit(&repo, "v0.2.0").unwrap();
+        let previous = latest_tag(&repo, to, true).unwrap().unwrap();
+        assert_eq!(previous.name, "v0.1.0");
+        let range = commits_between(&repo, Some(previous.commit), to, 10).unwrap();
+        let sections: Vec<_> = classify_commits(&range.commits).iter().map(|entry| entry.section).collect();
+        assert_eq!(sections, [ReleaseSection::Fixed, ReleaseSection::Added]);
+
+        // The first release has no tag before it
+        let first = resolve_commit(&repo, "v0.1.0").unwrap();
+        assert!(latest_tag(&repo, first, true).unwrap().is_none());
+    }
+}
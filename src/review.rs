@@ -0,0 +1,208 @@
+use std::io;
+use serde::{Deserialize, Serialize};
+
+use crate::claude::ClaudeClient;
+use crate::git::FilePatch;
+use crate::provider::{CompletionRequest, LlmClient, ToolSpec};
+use crate::{format_code_block, Result, TechDocsError};
+
+/// Default system prompt for code review, embedded at compile time
+pub const DEFAULT_REVIEW_PROMPT: &str = include_str!("../prompts/review.txt");
+
+/// How serious a review finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Info => write!(f, "info"),
+        }
+    }
+}
+
+/// A single review comment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewFinding {
+    /// Path of the file the finding refers to
+    pub file: String,
+    /// Line number in the new version of the file, if the finding is line-specific
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    pub severity: Severity,
+    pub comment: String,
+}
+
+#[derive(Deserialize)]
+struct ReviewToolInput {
+    findings: Vec<ReviewFinding>,
+}
+
+/// What was included in a diff prompt
+#[derive(Debug, Clone, Default)]
+pub struct DiffPromptStats {
+    /// Files whose patch was included in full
+    pub included: usize,
+    /// Files listed by name only (binary or over the per-file limit)
+    pub summarized: usize,
+    /// Files left out because the total budget was reached
+    pub omitted: usize,
+}
+
+/// Write changed files as fenced diffs, honoring the per-file and total size budgets
+pub fn diff_prompt<W: io::Write>(
+    patches: &[FilePatch],
+    max_file_size_kb: u64,
+    max_total_size_mb: u64,
+    writer: W,
+) -> Result<DiffPromptStats> {
+    write_patches(
+        patches,
+        max_file_size_kb.saturating_mul(1024),
+        max_total_size_mb.saturating_mul(1024 * 1024),
+        writer,
+    )
+}
+
+/// [`diff_prompt`] with the budgets in bytes
+pub(crate) fn write_patches<W: io::Write>(
+    patches: &[FilePatch],
+    max_file_size: u64,
+    max_total_size: u64,
+    mut writer: W,
+) -> Result<DiffPromptStats> {
+    let mut total_size = 0;
+    let mut stats = DiffPromptStats::default();
+
+    for (index, patch) in patches.iter().enumerate() {
+        let size = patch.patch.len() as u64;
+
+        if patch.binary {
+            writeln!(writer, "\nFile: {} (binary file changed)", patch.path)?;
+            stats.summarized += 1;
+            continue;
+        }
+        if size > max_file_size {
+            writeln!(
+                writer,
+                "\nFile: {} (diff too large to include: +{}/-{} lines)",
+                patch.path, patch.insertions, patch.deletions
+            )?;
+            stats.summarized += 1;
+            continue;
+        }
+        if total_size + size > max_total_size {
+            writeln!(writer, "Warning: Total size limit reached, some files omitted.")?;
+            stats.omitted = patches.len() - index;
+            break;
+        }
+        total_size += size;
+
+        writeln!(writer, "\nFile: {}", patch.path)?;
+        writeln!(writer, "{}", format_code_block("diff", patch.patch.trim_end()))?;
+        stats.included += 1;
+    }
+
+    Ok(stats)
+}
+
+fn review_tool() -> ToolSpec {
+    ToolSpec {
+        name: "record_review".into(),
+        description: "Record the code review findings".into(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "findings": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "file": { "type": "string", "description": "Path of the file" },
+                            "line": { "type": "integer", "description": "Line number in the new file" },
+                            "severity": { "type": "string", "enum": ["error", "warning", "info"] },
+                            "comment": { "type": "string", "description": "The review comment" }
+                        },
+                        "required": ["file", "severity", "comment"]
+                    }
+                }
+            },
+            "required": ["findings"]
+        }),
+    }
+}
+
+/// Ask Claude to review the diff content produced by `diff_prompt`
+pub async fn generate_review(diff_content: &str, model: Option<&str>) -> Result<Vec<ReviewFinding>> {
+    let client = ClaudeClient::new()?;
+    let request = CompletionRequest {
+        model: model.map(String::from),
+        tool: Some(review_tool()),
+        ..CompletionRequest::new(DEFAULT_REVIEW_PROMPT, diff_content)
+    };
+    let result = client.complete(&request).await?;
+
+    let input = result
+        .tool_input
+        .ok_or_else(|| TechDocsError::Claude("Response did not include review findings".into()))?;
+    let review: ReviewToolInput = serde_json::from_value(input)
+        .map_err(|e| TechDocsError::Claude(format!("Invalid review from model: {}", e)))?;
+    Ok(review.findings)
+}
+
+/// Group findings by file, ordering each file's findings by line
+pub fn group_by_file(findings: &[ReviewFinding]) -> Vec<(&str, Vec<&ReviewFinding>)> {
+    let mut groups: Vec<(&str, Vec<&ReviewFinding>)> = Vec::new();
+    for finding in findings {
+        match groups.iter_mut().find(|(file, _)| *file == finding.file) {
+            Some((_, entries)) => entries.push(finding),
+            None => groups.push((&finding.file, vec![finding])),
+        }
+    }
+    for (_, entries) in &mut groups {
+        entries.sort_by_key(|f| (f.line.unwrap_or(0), f.severity));
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(path: &str, lines: usize) -> FilePatch {
+        FilePatch {
+            path: path.to_string(),
+            patch: "+added line\n".repeat(lines),
+            binary: false,
+            insertions: lines,
+            deletions: 0,
+        }
+    }
+
+    #[test]
+    fn huge_limits_saturate_instead_of_overflowing() {
+        let patches = [patch("src/lib.rs", 3), patch("src/main.rs", 2)];
+        let mut out = Vec::new();
+        let stats = diff_prompt(&patches, u64::MAX, u64::MAX, &mut out).unwrap();
+        assert_eq!((stats.included, stats.summarized, stats.omitted), (2, 0, 0));
+        assert!(String::from_utf8(out).unwrap().contains("File: src/main.rs"));
+    }
+
+    #[test]
+    fn limits_summarize_and_omit_patches() {
+        let patches = [patch("big.rs", 200), patch("a.rs", 50), patch("b.rs", 50)];
+        let mut out = Vec::new();
+        let stats = write_patches(&patches, 1024, 1000, &mut out).unwrap();
+        assert_eq!((stats.included, stats.summarized, stats.omitted), (1, 1, 1));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("File: big.rs (diff too large to include: +200/-0 lines)"), "{}", out);
+        assert!(out.contains("Total size limit reached"), "{}", out);
+    }
+}
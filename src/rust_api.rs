@@ -0,0 +1,355 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use ignore::WalkBuilder;
+use quote::ToTokens;
+use syn::{Attribute, Expr, ExprLit, ImplItem, Item, Lit, Meta, TraitItem, Visibility};
+
+/// Largest [`rust_api_block`], in bytes. Items past the limit are left out.
+pub const RUST_API_MAX_BYTES: usize = 16 * 1024;
+/// Source files larger than this are not parsed
+const MAX_SOURCE_BYTES: u64 = 1024 * 1024;
+
+/// A public item of a Rust crate and the first paragraph of its doc comment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiItem {
+    /// File the item is declared in, relative to the crate root and separated by `/`
+    pub file: String,
+    /// Declaration without its body, such as `pub fn parse(input: &str) -> Result<Ast>`
+    pub signature: String,
+    /// First paragraph of the doc comment, on one line; empty when undocumented
+    pub docs: String,
+}
+
+/// The public items declared in `src/**/*.rs` under `root`, in file and declaration
+/// order, or nothing when `root` has no Cargo.toml. Files that cannot be read or
+/// parsed are logged and skipped.
+pub fn extract_rust_api(root: &Path) -> Vec<ApiItem> {
+    if !root.join("Cargo.toml").is_file() {
+        return Vec::new();
+    }
+    let mut files: Vec<_> = WalkBuilder::new(root.join("src"))
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "rs"))
+        .filter(|entry| entry.metadata().is_ok_and(|meta| meta.is_file() && meta.len() <= MAX_SOURCE_BYTES))
+        .map(|entry| entry.into_path())
+        .collect();
+    files.sort();
+
+    let mut items = Vec::new();
+    for path in files {
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                tracing::warn!("Skipping {} for the API summary: {}", path.display(), e);
+                continue;
+            }
+        };
+        let parsed = match syn::parse_file(&source) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                tracing::warn!("Skipping {} for the API summary: {}", path.display(), e);
+                continue;
+            }
+        };
+        let file = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        collect_items(&parsed.items, &file, &mut items);
+    }
+    items
+}
+
+/// The crate's public API as a `Public API` block for the prompt, at most
+/// [`RUST_API_MAX_BYTES`] long, or `None` when `root` is not a Rust crate or exports
+/// nothing
+pub fn rust_api_block(root: &Path) -> Option<String> {
+    let items = extract_rust_api(root);
+    if items.is_empty() {
+        return None;
+    }
+    let mut block = String::from(
+        "\n\nPublic API, from the crate's signatures and doc comments; refer to these names \
+         exactly as written:\n",
+    );
+    let mut file = "";
+    for item in &items {
+        let mut entry = String::new();
+        if item.file != file {
+            let _ = write!(entry, "\n{}\n", item.file);
+        }
+        let _ = writeln!(entry, "- `{}`", item.signature);
+        if !item.docs.is_empty() {
+            let _ = writeln!(entry, "  {}", item.docs);
+        }
+        if block.len() + entry.len() > RUST_API_MAX_BYTES {
+            break;
+        }
+        block.push_str(&entry);
+        file = &item.file;
+    }
+    Some(block)
+}
+
+fn collect_items(items: &[Item], file: &str, out: &mut Vec<ApiItem>) {
+    let mut push = |signature: String, attrs: &[Attribute]| {
+        out.push(ApiItem { file: file.to_string(), signature, docs: doc_summary(attrs) });
+    };
+    let mut nested = Vec::new();
+    for item in items {
+        match item {
+            Item::Fn(item) if is_public(&item.vis, &item.attrs) => {
+                push(format!("pub {}", tokens(&item.sig)), &item.attrs);
+            }
+            Item::Struct(item) if is_public(&item.vis, &item.attrs) => {
+                push(format!("pub struct {}{}", item.ident, tokens(&item.generics)), &item.attrs);
+            }
+            Item::Enum(item) if is_public(&item.vis, &item.attrs) => {
+                push(format!("pub enum {}{}", item.ident, tokens(&item.generics)), &item.attrs);
+            }
+            Item::Type(item) if is_public(&item.vis, &item.attrs) => {
+                push(
+                    format!("pub type {}{} = {}", item.ident, tokens(&item.generics), tokens(&item.ty)),
+                    &item.attrs,
+                );
+            }
+            Item::Const(item) if is_public(&item.vis, &item.attrs) => {
+                push(format!("pub const {}: {}", item.ident, tokens(&item.ty)), &item.attrs);
+            }
+            Item::Static(item) if is_public(&item.vis, &item.attrs) => {
+                push(format!("pub static {}: {}", item.ident, tokens(&item.ty)), &item.attrs);
+            }
+            Item::Trait(item) if is_public(&item.vis, &item.attrs) => {
+                push(format!("pub trait {}{}", item.ident, tokens(&item.generics)), &item.attrs);
+                for method in &item.items {
+                    if let TraitItem::Fn(method) = method {
+                        if !is_hidden(&method.attrs) {
+                            push(format!("trait {} {{ {} }}", item.ident, tokens(&method.sig)), &method.attrs);
+                        }
+                    }
+                }
+            }
+            // Inherent methods only; trait impls are described by the trait
+            Item::Impl(item) if item.trait_.is_none() && !is_hidden(&item.attrs) => {
+                for method in &item.items {
+                    if let ImplItem::Fn(method) = method {
+                        if is_public(&method.vis, &method.attrs) {
+                            push(
+                                format!("impl {} {{ pub {} }}", tokens(&item.self_ty), tokens(&method.sig)),
+                                &method.attrs,
+                            );
+                        }
+                    }
+                }
+            }
+            Item::Mod(item) if is_public(&item.vis, &item.attrs) => {
+                if let Some((_, items)) = &item.content {
+                    nested.push((&item.ident, items));
+                }
+            }
+            _ => {}
+        }
+    }
+    // Items of inline modules are shown inside their module
+    for (module, items) in nested {
+        let start = out.len();
+        collect_items(items, file, out);
+        for item in &mut out[start..] {
+            item.signature = format!("pub mod {} {{ {} }}", module, item.signature);
+        }
+    }
+}
+
+/// Whether an item is `pub`, rather than restricted or private, and not hidden from docs
+fn is_public(vis: &Visibility, attrs: &[Attribute]) -> bool {
+    matches!(vis, Visibility::Public(_)) && !is_hidden(attrs)
+}
+
+/// Whether an item is `#[doc(hidden)]` or only compiled for tests
+fn is_hidden(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| match &attr.meta {
+        Meta::List(list) if list.path.is_ident("doc") => list.tokens.to_string() == "hidden",
+        Meta::List(list) if list.path.is_ident("cfg") => list.tokens.to_string() == "test",
+        _ => false,
+    })
+}
+
+/// The first paragraph of the `///` comments in `attrs`, joined into one line
+fn doc_summary(attrs: &[Attribute]) -> String {
+    let lines = attrs.iter().filter_map(|attr| match &attr.meta {
+        Meta::NameValue(doc) if doc.path.is_ident("doc") => match &doc.value {
+            Expr::Lit(ExprLit { lit: Lit::Str(text), .. }) => Some(text.value()),
+            _ => None,
+        },
+        _ => None,
+    });
+    let mut paragraph = Vec::new();
+    for line in lines {
+        let line = line.trim().to_string();
+        if line.is_empty() && !paragraph.is_empty() {
+            break;
+        }
+        if !line.is_empty() {
+            paragraph.push(line);
+        }
+    }
+    paragraph.join(" ")
+}
+
+/// Source text for a piece of syntax. Tokens print with a space between each pair, so
+/// the spaces Rust style leaves out are removed.
+fn tokens(node: &impl ToTokens) -> String {
+    let mut text = node.to_token_stream().to_string();
+    for (spaced, tight) in [
+        (" ,", ","),
+        (" ;", ";"),
+        (" :", ":"),
+        (":: ", "::"),
+        (" ::", "::"),
+        ("( ", "("),
+        (" (", "("),
+        (" )", ")"),
+        ("[ ", "["),
+        (" ]", "]"),
+        ("< ", "<"),
+        (" <", "<"),
+        (" >", ">"),
+        ("& ", "&"),
+        ("? ", "?"),
+    ] {
+        text = text.replace(spaced, tight);
+    }
+    // `-> (A, B)` lost its space to the rule for `(`
+    text.replace("->", " -> ").replace("  ", " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    const LIB: &str = r#"//! Demo crate
+
+/// Parse `input` into tokens.
+///
+/// Whitespace is skipped.
+pub fn parse<'a>(input: &'a str, limit: Option<usize>) -> Result<Vec<&'a str>, String> {
+    Ok(input.split_whitespace().take(limit.unwrap_or(usize::MAX)).collect())
+}
+
+fn private_helper() {}
+
+pub(crate) fn internal() {}
+
+#[doc(hidden)]
+pub fn hidden() {}
+
+/// A parsed token stream
+pub struct Tokens<T: Clone> {
+    items: Vec<T>,
+}
+
+impl<T: Clone> Tokens<T> {
+    /// Number of tokens
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn secret(&self) {}
+}
+
+impl<T: Clone> Default for Tokens<T> {
+    fn default() -> Self {
+        Self { items: Vec::new() }
+    }
+}
+
+/// Things that can be rendered
+pub trait Render {
+    /// Render to a string
+    fn render(&self) -> String;
+}
+
+pub mod limits {
+    /// Longest input accepted
+    pub const MAX_INPUT: usize = 1024;
+}
+
+#[cfg(test)]
+pub mod tests {
+    pub fn fixture() {}
+}
+"#;
+
+    fn signatures(items: &[ApiItem]) -> Vec<&str> {
+        items.iter().map(|item| item.signature.as_str()).collect()
+    }
+
+    #[test]
+    fn public_items_are_extracted_with_their_docs() {
+        let tree = FixtureTree::new().file("Cargo.toml", "[package]\nname = \"demo\"\n").file("src/lib.rs", LIB);
+        let items = extract_rust_api(tree.path());
+        assert_eq!(
+            signatures(&items),
+            [
+                "pub fn parse<'a>(input: &'a str, limit: Option<usize>) -> Result<Vec<&'a str>, String>",
+                "pub struct Tokens<T: Clone>",
+                "impl Tokens<T> { pub fn len(&self) -> usize }",
+                "pub trait Render",
+                "trait Render { fn render(&self) -> String }",
+                "pub mod limits { pub const MAX_INPUT: usize }",
+            ]
+        );
+        assert_eq!(items[0].docs, "Parse `input` into tokens.");
+        assert_eq!(items[0].file, "src/lib.rs");
+        assert_eq!(items[2].docs, "Number of tokens");
+    }
+
+    #[test]
+    fn a_file_that_does_not_parse_is_skipped() {
+        let tree = FixtureTree::new()
+            .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+            .file("src/broken.rs", "pub fn broken( {\n")
+            .file("src/lib.rs", "/// Entry point\npub fn run() {}\n");
+        let items = extract_rust_api(tree.path());
+        assert_eq!(items, [ApiItem { file: "src/lib.rs".into(), signature: "pub fn run()".into(), docs: "Entry point".into() }]);
+    }
+
+    #[test]
+    fn only_rust_crates_get_an_api_block() {
+        let tree = FixtureTree::new().file("src/lib.rs", "pub fn run() {}\n");
+        assert_eq!(rust_api_block(tree.path()), None);
+        let tree = FixtureTree::new().file("Cargo.toml", "[package]\nname = \"demo\"\n").file("src/main.rs", "fn main() {}\n");
+        assert_eq!(rust_api_block(tree.path()), None);
+    }
+
+    #[cfg(all(feature = "git", feature = "claude"))]
+    #[test]
+    fn the_api_block_reaches_the_readme_prompt() {
+        use crate::generate::{GenerateOptions, ReadmePrompt};
+
+        // The API is summarized even when the sources themselves are not sent
+        let tree = FixtureTree::new().file("Cargo.toml", "[package]\nname = \"demo\"\n").file("src/lib.rs", LIB);
+        let options = GenerateOptions {
+            files: crate::plan::PromptOptions::default().exclude(["src/"]),
+            git_context: false,
+            ..Default::default()
+        };
+        let prompt = ReadmePrompt::build("demo", tree.path(), &options, &crate::progress::no_progress).unwrap();
+        assert!(!prompt.content.contains(&format!("File: {}", tree.join("src/lib.rs").display())), "{}", prompt.content);
+        assert!(
+            prompt.content.contains("\nsrc/lib.rs\n- `pub fn parse<'a>(input: &'a str, limit: Option<usize>) -> Result<Vec<&'a str>, String>`\n  Parse `input` into tokens.\n"),
+            "{}",
+            prompt.content
+        );
+
+        let options = GenerateOptions { rust_api: false, ..options };
+        let prompt = ReadmePrompt::build("demo", tree.path(), &options, &crate::progress::no_progress).unwrap();
+        assert!(!prompt.content.contains("Public API"), "{}", prompt.content);
+    }
+}
@@ -0,0 +1,292 @@
+use std::fmt::Write;
+
+/// Section names the README prompt produces, in normalized form
+pub const KNOWN_SECTIONS: &[&str] = &[
+    "overview",
+    "features",
+    "key features",
+    "installation",
+    "getting started",
+    "quick start",
+    "usage",
+    "examples",
+    "configuration",
+    "api",
+    "architecture",
+    "project structure",
+    "development",
+    "testing",
+    "deployment",
+    "contributing",
+    "license",
+];
+
+/// An ATX heading (`## Title`) in a markdown document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    /// Number of `#` characters, 1 to 6
+    pub level: usize,
+    /// Heading text without the markers
+    pub text: String,
+    /// Zero-based line index
+    pub line: usize,
+}
+
+/// Normalize heading text for matching: lowercase, punctuation and emoji dropped,
+/// `-` and `_` treated as spaces, whitespace collapsed
+pub fn normalize_heading(text: &str) -> String {
+    text.chars()
+        .map(|c| if c == '-' || c == '_' { ' ' } else { c.to_ascii_lowercase() })
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The ATX headings in `markdown`, skipping lines inside fenced code blocks
+pub fn headings(markdown: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut fence: Option<&str> = None;
+
+    for (line_index, line) in markdown.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") {
+            fence = Some("```");
+            continue;
+        }
+        if trimmed.starts_with("~~~") {
+            fence = Some("~~~");
+            continue;
+        }
+
+        // Up to three spaces of indentation, then 1-6 `#` followed by a space or the end
+        if line.len() - trimmed.len() > 3 {
+            continue;
+        }
+        let level = trimmed.chars().take_while(|&c| c == '#').count();
+        if level == 0 || level > 6 {
+            continue;
+        }
+        let rest = &trimmed[level..];
+        if !rest.is_empty() && !rest.starts_with(' ') && !rest.starts_with('\t') {
+            continue;
+        }
+        let text = rest.trim().trim_end_matches('#').trim_end().to_string();
+        headings.push(Heading {
+            level,
+            text,
+            line: line_index,
+        });
+    }
+    headings
+}
+
+/// Instructions appended to the system prompt to produce only `sections`
+pub fn section_instructions(sections: &[String]) -> String {
+    let mut out = String::from(
+        "\n\nDo not write a complete README. Write only the following top-level sections, \
+         each under a `##` heading with exactly this name, in this order, and nothing else \
+         (no title, introduction, or other sections):\n",
+    );
+    for section in sections {
+        let _ = writeln!(out, "- {}", section);
+    }
+    out
+}
+
+/// Replace the sections of `existing` named in `sections` with the same-named sections of
+/// `generated`. Headings match case-insensitively after [`normalize_heading`]; when a name
+/// appears more than once in `existing`, the shallowest occurrence is replaced, the first
+/// one on a tie. Regenerated sections keep the existing heading line and are re-leveled to
+/// it; ones with no counterpart are appended at the end. Everything else is left untouched.
+pub fn splice_sections(existing: &str, generated: &str, sections: &[String]) -> String {
+    let wanted: Vec<String> = sections.iter().map(|s| normalize_heading(s)).collect();
+    let generated_lines: Vec<&str> = generated.lines().collect();
+    let generated_headings = headings(generated);
+
+    // Regenerated sections in order: (normalized name, level, lines including the heading)
+    let mut replacements: Vec<(String, usize, Vec<&str>)> = Vec::new();
+    for (index, heading) in generated_headings.iter().enumerate() {
+        let name = normalize_heading(&heading.text);
+        if !wanted.contains(&name) || replacements.iter().any(|(n, _, _)| *n == name) {
+            continue;
+        }
+        let end = section_end(&generated_headings, index, generated_lines.len());
+        let mut lines = generated_lines[heading.line..end].to_vec();
+        while lines.last().is_some_and(|l| l.trim().is_empty()) {
+            lines.pop();
+        }
+        replacements.push((name, heading.level, lines));
+    }
+
+    let mut lines: Vec<String> = existing.lines().map(String::from).collect();
+    let mut appended = Vec::new();
+    for (name, level, section) in replacements {
+        let existing_headings = headings(&lines.join("\n"));
+        let target = existing_headings
+            .iter()
+            .enumerate()
+            .filter(|(_, h)| normalize_heading(&h.text) == name)
+            .min_by_key(|(_, h)| h.level);
+
+        match target {
+            Some((index, heading)) => {
+                let end = section_end(&existing_headings, index, lines.len());
+                // Keep the blank lines that separated the old section from the next one
+                let trailing_blanks = lines[heading.line..end]
+                    .iter()
+                    .rev()
+                    .take_while(|l| l.trim().is_empty())
+                    .count();
+                let mut replacement = relevel(&section, level, heading.level);
+                // The existing heading line stays as written, emoji and all
+                replacement[0] = lines[heading.line].clone();
+                replacement.extend(std::iter::repeat_n(String::new(), trailing_blanks));
+                lines.splice(heading.line..end, replacement);
+            }
+            None => appended.push(section.iter().map(|l| l.to_string()).collect::<Vec<_>>()),
+        }
+    }
+
+    let mut output = lines.join("\n");
+    for section in appended {
+        let trimmed = output.trim_end().len();
+        output.truncate(trimmed);
+        if !output.is_empty() {
+            output.push_str("\n\n");
+        }
+        output.push_str(&section.join("\n"));
+    }
+    if !output.ends_with('\n') {
+        output.push('\n');
+    }
+    output
+}
+
+/// Line index where the section starting at `headings[index]` ends: the next heading
+/// at the same or a shallower level, or the end of the document
+fn section_end(headings: &[Heading], index: usize, line_count: usize) -> usize {
+    let level = headings[index].level;
+    headings[index + 1..]
+        .iter()
+        .find(|h| h.level <= level)
+        .map_or(line_count, |h| h.line)
+}
+
+/// Shift every heading in `section` so its first heading moves from level `from` to `to`
+fn relevel(section: &[&str], from: usize, to: usize) -> Vec<String> {
+    let text = section.join("\n");
+    let section_headings = headings(&text);
+    let mut lines: Vec<String> = section.iter().map(|l| l.to_string()).collect();
+    for heading in section_headings {
+        let level = (heading.level + to).saturating_sub(from).clamp(1, 6);
+        lines[heading.line] = format!("{} {}", "#".repeat(level), heading.text);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(sections: &[&str]) -> Vec<String> {
+        sections.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn headings_are_normalized_for_matching() {
+        assert_eq!(normalize_heading("🚀 Getting-Started!"), "getting started");
+        assert_eq!(normalize_heading("  API_Reference  "), "api reference");
+        assert_eq!(normalize_heading("Usage"), normalize_heading("USAGE:"));
+    }
+
+    #[test]
+    fn headings_skip_code_blocks_and_non_headings() {
+        let markdown = "# Title ##\n#hashtag\n    # indented code\n```sh\n# comment\n```\n~~~\n## Not this\n~~~\n### Deep\n";
+        let found = headings(markdown);
+        assert_eq!(
+            found,
+            [
+                Heading { level: 1, text: "Title".into(), line: 0 },
+                Heading { level: 3, text: "Deep".into(), line: 9 },
+            ]
+        );
+    }
+
+    #[test]
+    fn section_instructions_list_the_sections_in_order() {
+        let instructions = section_instructions(&names(&["Installation", "Usage"]));
+        assert!(instructions.ends_with("- Installation\n- Usage\n"), "{}", instructions);
+    }
+
+    #[test]
+    fn a_section_and_its_subsections_are_replaced() {
+        let existing = "# Tool\n\nIntro.\n\n## Usage\n\nOld usage.\n\n### Flags\n\nOld flags.\n\n## License\n\nMIT\n";
+        let generated = "## Usage\n\nNew usage.\n\n### Examples\n\nNew examples.\n";
+        assert_eq!(
+            splice_sections(existing, generated, &names(&["usage"])),
+            "# Tool\n\nIntro.\n\n## Usage\n\nNew usage.\n\n### Examples\n\nNew examples.\n\n## License\n\nMIT\n"
+        );
+    }
+
+    #[test]
+    fn sections_not_asked_for_are_left_alone() {
+        let existing = "# Tool\n\n## Installation\n\nOld install.\n\n## Usage\n\nOld usage.\n";
+        let generated = "## Installation\n\nNew install.\n\n## Usage\n\nNew usage.\n";
+        let spliced = splice_sections(existing, generated, &names(&["Installation"]));
+        assert!(spliced.contains("New install."), "{}", spliced);
+        assert!(spliced.contains("Old usage."), "{}", spliced);
+    }
+
+    #[test]
+    fn generated_sections_are_releveled_to_the_existing_heading() {
+        let existing = "# Tool\n\n## Guide\n\n### 🔧 Configuration\n\nOld.\n\n## License\n";
+        let generated = "## Configuration\n\nNew.\n\n### Environment\n\nVars.\n";
+        assert_eq!(
+            splice_sections(existing, generated, &names(&["configuration"])),
+            "# Tool\n\n## Guide\n\n### 🔧 Configuration\n\nNew.\n\n#### Environment\n\nVars.\n\n## License\n"
+        );
+    }
+
+    #[test]
+    fn the_shallowest_duplicate_is_replaced() {
+        let existing = "# Tool\n\n## Setup\n\n### Usage\n\nNested usage.\n\n## Usage\n\nTop usage.\n";
+        let generated = "## Usage\n\nNew usage.\n";
+        let spliced = splice_sections(existing, generated, &names(&["usage"]));
+        assert!(spliced.contains("### Usage\n\nNested usage."), "{}", spliced);
+        assert!(spliced.ends_with("## Usage\n\nNew usage.\n"), "{}", spliced);
+    }
+
+    #[test]
+    fn the_first_of_equally_deep_duplicates_is_replaced() {
+        let existing = "## Usage\n\nFirst.\n\n## Usage\n\nSecond.\n";
+        let generated = "## Usage\n\nNew.\n\n## Usage\n\nIgnored.\n";
+        assert_eq!(
+            splice_sections(existing, generated, &names(&["usage"])),
+            "## Usage\n\nNew.\n\n## Usage\n\nSecond.\n"
+        );
+    }
+
+    #[test]
+    fn sections_missing_from_the_readme_are_appended() {
+        let existing = "# Tool\n\nIntro.\n\n\n";
+        let generated = "## Testing\n\nRun cargo test.\n\n";
+        assert_eq!(
+            splice_sections(existing, generated, &names(&["testing"])),
+            "# Tool\n\nIntro.\n\n## Testing\n\nRun cargo test.\n"
+        );
+    }
+
+    #[test]
+    fn sections_the_model_left_out_change_nothing() {
+        let existing = "# Tool\n\n## Usage\n\nOld.\n";
+        assert_eq!(splice_sections(existing, "Nothing useful.\n", &names(&["usage"])), existing);
+    }
+}
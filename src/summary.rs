@@ -0,0 +1,296 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::claude::ClaudeClient;
+use crate::license::detect_license;
+use crate::provider::{CompletionRequest, CompletionResult, GenerationProfile, LlmClient, TargetLength, ToolSpec, Usage};
+use crate::{format_file_content, FileContext, Result, TechDocsError};
+
+/// Default system prompt for project summaries, embedded at compile time
+pub const DEFAULT_SUMMARY_PROMPT: &str = include_str!("../prompts/summary.txt");
+/// The answer is a tool call, so the description is held to a few sentences by
+/// instruction rather than a stop sequence
+pub const SUMMARY_PROFILE: GenerationProfile = GenerationProfile {
+    stop_sequences: &[],
+    target_length: Some(TargetLength::Sentences(3)),
+    max_tokens: Some(512),
+};
+
+/// Maximum bytes of any single file included in the summary prompt
+pub const SUMMARY_MAX_FILE_BYTES: usize = 8 * 1024;
+/// Maximum bytes of file content in the summary prompt
+pub const SUMMARY_MAX_TOTAL_BYTES: usize = 32 * 1024;
+/// Deepest a README, manifest, or entry point can be and still describe the project
+const SUMMARY_MAX_DEPTH: usize = 3;
+
+const MANIFESTS: &[&str] = &[
+    "Cargo.toml", "package.json", "pyproject.toml", "setup.py", "setup.cfg", "go.mod",
+    "pom.xml", "build.gradle", "build.gradle.kts", "Gemfile", "composer.json", "mix.exs",
+    "Package.swift", "CMakeLists.txt", "main.tf",
+];
+
+const ENTRY_POINTS: &[&str] = &[
+    "main.rs", "lib.rs", "main.py", "__main__.py", "app.py", "index.js", "index.ts",
+    "main.ts", "main.go", "main.c", "main.cpp", "Main.java", "Program.cs",
+];
+
+/// A short project description for catalogs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ProjectSummary {
+    /// Two to three sentence description
+    pub description: String,
+    /// Topics or keywords describing the project
+    #[cfg_attr(feature = "server", schema(example = json!(["rust", "cli", "documentation"])))]
+    pub topics: Vec<String>,
+    /// SPDX license detected in the project, not generated by the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+}
+
+/// Pick the files that best describe a project: the root README, manifests, and entry points
+pub fn summary_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let walker = WalkBuilder::new(dir)
+        .standard_filters(true)
+        .max_depth(Some(SUMMARY_MAX_DEPTH))
+        .build();
+
+    let mut paths = Vec::new();
+    for entry in walker {
+        let entry = entry?;
+        if entry.path().is_file() {
+            paths.push(entry.into_path());
+        }
+    }
+    Ok(pick_summary_files(dir, paths))
+}
+
+/// The files among `paths` under `dir` that [`summary_files`] would pick, best first,
+/// for a caller that has already walked the tree with its own limits
+pub fn pick_summary_files(dir: &Path, paths: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    for path in paths {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let depth = relative.components().count();
+        if depth > SUMMARY_MAX_DEPTH {
+            continue;
+        }
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+        let priority = if depth == 1 && file_name.to_ascii_lowercase().starts_with("readme") {
+            0
+        } else if MANIFESTS.contains(&file_name) {
+            1
+        } else if ENTRY_POINTS.contains(&file_name) {
+            2
+        } else {
+            continue;
+        };
+        candidates.push((priority, depth, path));
+    }
+
+    candidates.sort();
+    candidates.into_iter().map(|(_, _, path)| path).collect()
+}
+
+/// Write the summary prompt content for `files` of the project in `dir`, returning the
+/// number of files included
+pub fn summary_prompt<W: io::Write>(dir: &Path, files: &[PathBuf], mut writer: W) -> Result<usize> {
+    let mut total_size = 0;
+    let mut included = 0;
+
+    for path in files {
+        if total_size >= SUMMARY_MAX_TOTAL_BYTES {
+            break;
+        }
+
+        let mut content = Vec::new();
+        fs::File::open(path)
+            .and_then(|file| file.take(SUMMARY_MAX_FILE_BYTES as u64 + 1).read_to_end(&mut content))
+            .file_context("read", path)?;
+        let mut content_str = String::from_utf8_lossy(&content).into_owned();
+
+        let limit = SUMMARY_MAX_FILE_BYTES.min(SUMMARY_MAX_TOTAL_BYTES - total_size);
+        if content_str.len() > limit {
+            let mut end = limit;
+            while !content_str.is_char_boundary(end) {
+                end -= 1;
+            }
+            content_str.truncate(end);
+            content_str.push_str("\n... [truncated]");
+        }
+        total_size += content_str.len();
+        included += 1;
+
+        let display_path = path.strip_prefix(dir).unwrap_or(path);
+        writeln!(writer, "\nFile: {}", display_path.display())?;
+        writeln!(writer, "{}", format_file_content(path, &content_str))?;
+    }
+
+    Ok(included)
+}
+
+fn summary_tool() -> ToolSpec {
+    ToolSpec {
+        name: "record_summary".into(),
+        description: "Record the project description and topics".into(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "description": {
+                    "type": "string",
+                    "description": "Two to three sentence description of the project"
+                },
+                "topics": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Short lowercase topics or keywords"
+                }
+            },
+            "required": ["description", "topics"]
+        }),
+    }
+}
+
+/// Generate a short description and topic list for the project in `dir` using Claude
+pub async fn generate_summary(dir: &Path, model: Option<&str>) -> Result<ProjectSummary> {
+    let client = ClaudeClient::new()?;
+    Ok(summarize(&client, dir, &summary_files(dir)?, model).await?.0)
+}
+
+/// Generate a summary of the project in `dir` from `files` with `client`, returning the
+/// token usage alongside it
+pub async fn summarize(
+    client: &dyn LlmClient,
+    dir: &Path,
+    files: &[PathBuf],
+    model: Option<&str>,
+) -> Result<(ProjectSummary, Usage)> {
+    let request = summary_request(dir, files, model)?;
+    let result = client.complete(&request).await?;
+    let summary = parse_summary(&result, detect_license(dir).map(|license| license.spdx))?;
+    Ok((summary, result.usage))
+}
+
+/// The request [`summarize`] sends for `files` of the project in `dir`
+pub fn summary_request(dir: &Path, files: &[PathBuf], model: Option<&str>) -> Result<CompletionRequest> {
+    let mut content = Vec::new();
+    let included = summary_prompt(dir, files, &mut content)?;
+    if included == 0 {
+        return Err(TechDocsError::Other(
+            format!("No README, manifest, or entry point found in {}", dir.display()).into(),
+        ));
+    }
+
+    Ok(CompletionRequest {
+        model: model.map(String::from),
+        tool: Some(summary_tool()),
+        ..CompletionRequest::new(DEFAULT_SUMMARY_PROMPT, String::from_utf8_lossy(&content))
+    }
+    .with_profile(&SUMMARY_PROFILE))
+}
+
+/// The summary in the model's answer to a [`summary_request`], with `license` as the
+/// project's license
+pub fn parse_summary(result: &CompletionResult, license: Option<String>) -> Result<ProjectSummary> {
+    let input = result
+        .tool_input
+        .clone()
+        .ok_or_else(|| TechDocsError::Claude("Response did not include a summary".into()))?;
+    let mut summary: ProjectSummary = serde_json::from_value(input)
+        .map_err(|e| TechDocsError::Claude(format!("Invalid summary from model: {}", e)))?;
+    summary.license = license;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FixtureTree, MockClient};
+
+    fn project() -> FixtureTree {
+        FixtureTree::new()
+            .file("Cargo.toml", "[package]\nname = \"demo\"\nlicense = \"MIT\"\n")
+            .file("README.md", "# Demo\n")
+            .file("src/main.rs", "fn main() {}\n")
+            .file("src/util.rs", "pub fn util() {}\n")
+            .file("a/b/c/Cargo.toml", "[package]\nname = \"deep\"\n")
+    }
+
+    #[test]
+    fn files_are_picked_readme_first_and_not_too_deep() {
+        let tree = project();
+        let files = summary_files(tree.path()).unwrap();
+        assert_eq!(files, vec![tree.join("README.md"), tree.join("Cargo.toml"), tree.join("src/main.rs")]);
+    }
+
+    #[test]
+    fn picking_from_a_walk_matches_the_summary_walk() {
+        let tree = project();
+        let walked = vec![tree.join("src/util.rs"), tree.join("src/main.rs"), tree.join("Cargo.toml"), tree.join("README.md")];
+        assert_eq!(pick_summary_files(tree.path(), walked), summary_files(tree.path()).unwrap());
+        assert!(pick_summary_files(tree.path(), vec![PathBuf::from("/elsewhere/README.md")]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn summarize_returns_the_tool_input_with_the_detected_license() {
+        let tree = project();
+        let client = MockClient::tool(serde_json::json!({
+            "description": "A demo project.",
+            "topics": ["rust", "demo"],
+        }));
+
+        let files = summary_files(tree.path()).unwrap();
+        let (summary, _) = summarize(&client, tree.path(), &files, Some("claude-test")).await.unwrap();
+        assert_eq!(summary.description, "A demo project.");
+        assert_eq!(summary.topics, ["rust", "demo"]);
+        assert_eq!(summary.license.as_deref(), Some("MIT"));
+
+        let requests = client.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].model.as_deref(), Some("claude-test"));
+        assert_eq!(requests[0].tool.as_ref().map(|tool| tool.name.as_str()), Some("record_summary"));
+        assert!(requests[0].content.contains("File: README.md"));
+        assert!(!requests[0].content.contains("util.rs"));
+    }
+
+    #[tokio::test]
+    async fn nothing_to_summarize_is_an_error_before_any_request() {
+        let tree = FixtureTree::new().file("notes.txt", "nothing here\n");
+        let client = MockClient::text("unused");
+        let files = summary_files(tree.path()).unwrap();
+        let error = summarize(&client, tree.path(), &files, None).await.unwrap_err();
+        assert!(error.to_string().contains("No README, manifest, or entry point"), "{}", error);
+        assert!(client.requests().is_empty());
+    }
+
+    #[test]
+    fn an_answer_without_a_valid_tool_call_is_an_error() {
+        let missing = CompletionResult { text: "A demo project.".into(), ..Default::default() };
+        let error = parse_summary(&missing, None).unwrap_err();
+        assert!(error.to_string().contains("did not include a summary"), "{}", error);
+
+        let invalid = CompletionResult {
+            tool_input: Some(serde_json::json!({"description": "A demo project."})),
+            ..Default::default()
+        };
+        let error = parse_summary(&invalid, None).unwrap_err();
+        assert!(error.to_string().contains("Invalid summary"), "{}", error);
+    }
+
+    #[test]
+    fn long_files_are_cut_to_the_prompt_budget() {
+        let tree = FixtureTree::new().sized_file("README.md", SUMMARY_MAX_FILE_BYTES * 2);
+        let mut out = Vec::new();
+        let included = summary_prompt(tree.path(), &[tree.join("README.md")], &mut out).unwrap();
+        assert_eq!(included, 1);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("... [truncated]"));
+        assert!(out.len() < SUMMARY_MAX_FILE_BYTES + 200);
+    }
+}
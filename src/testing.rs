@@ -0,0 +1,145 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "claude")]
+use std::sync::Mutex;
+use temp_dir::TempDir;
+
+#[cfg(feature = "claude")]
+use async_trait::async_trait;
+
+#[cfg(feature = "claude")]
+use crate::provider::{CompletionRequest, CompletionResult, LlmClient};
+#[cfg(feature = "claude")]
+use crate::Result;
+
+/// A project directory built file by file for tests, removed when dropped. Builder
+/// methods panic on IO failures, naming the file, since a fixture that cannot be
+/// written leaves nothing to test.
+pub struct FixtureTree {
+    dir: TempDir,
+}
+
+impl FixtureTree {
+    /// An empty directory
+    #[track_caller]
+    pub fn new() -> Self {
+        Self {
+            dir: TempDir::with_prefix("techdocs-fixture-").expect("failed to create a fixture directory"),
+        }
+    }
+
+    /// Add a file at `path`, relative to the root, creating its parent directories
+    #[track_caller]
+    pub fn file(self, path: impl AsRef<Path>, content: impl AsRef<[u8]>) -> Self {
+        let target = self.join(path.as_ref());
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).unwrap_or_else(|e| panic!("failed to create {}: {}", parent.display(), e));
+        }
+        fs::write(&target, content).unwrap_or_else(|e| panic!("failed to write {}: {}", target.display(), e));
+        self
+    }
+
+    /// Add a file of exactly `size` bytes, for exercising size limits
+    #[track_caller]
+    pub fn sized_file(self, path: impl AsRef<Path>, size: usize) -> Self {
+        self.file(path, vec![b'x'; size])
+    }
+
+    /// Add an empty directory
+    #[track_caller]
+    pub fn dir(self, path: impl AsRef<Path>) -> Self {
+        let target = self.join(path.as_ref());
+        fs::create_dir_all(&target).unwrap_or_else(|e| panic!("failed to create {}: {}", target.display(), e));
+        self
+    }
+
+    /// Write a `.gitignore` at the root with one pattern per line. The walker honors
+    /// it only inside a git repository, so this also adds an empty `.git` directory.
+    #[track_caller]
+    pub fn gitignore<I, S>(self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let content: String = patterns.into_iter().map(|pattern| format!("{}\n", pattern.as_ref())).collect();
+        self.dir(".git").file(".gitignore", content)
+    }
+
+    /// Root of the tree
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// `path` under the root
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.dir.path().join(path)
+    }
+}
+
+impl Default for FixtureTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Answers a [`MockClient`] gives
+#[cfg(feature = "claude")]
+type Respond = dyn Fn(&CompletionRequest) -> Result<CompletionResult> + Send + Sync;
+
+/// An [`LlmClient`] that answers without a provider and keeps every request it was
+/// sent, for tests of code that calls a model
+#[cfg(feature = "claude")]
+pub struct MockClient {
+    respond: Box<Respond>,
+    requests: Mutex<Vec<CompletionRequest>>,
+}
+
+#[cfg(feature = "claude")]
+impl MockClient {
+    /// A client answering each request with what `respond` returns for it
+    pub fn new(respond: impl Fn(&CompletionRequest) -> Result<CompletionResult> + Send + Sync + 'static) -> Self {
+        Self {
+            respond: Box::new(respond),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A client answering every request with `text`
+    pub fn text(text: &str) -> Self {
+        let text = text.to_string();
+        Self::new(move |_| {
+            Ok(CompletionResult {
+                text: text.clone(),
+                model: "mock".into(),
+                stop_reason: Some("end_turn".into()),
+                ..Default::default()
+            })
+        })
+    }
+
+    /// A client answering every request with a call of the requested tool with `input`
+    pub fn tool(input: serde_json::Value) -> Self {
+        Self::new(move |_| {
+            Ok(CompletionResult {
+                tool_input: Some(input.clone()),
+                model: "mock".into(),
+                stop_reason: Some("tool_use".into()),
+                ..Default::default()
+            })
+        })
+    }
+
+    /// The requests sent so far, oldest first
+    pub fn requests(&self) -> Vec<CompletionRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[cfg(feature = "claude")]
+#[async_trait]
+impl LlmClient for MockClient {
+    async fn complete(&self, request: &CompletionRequest) -> Result<CompletionResult> {
+        self.requests.lock().unwrap().push(request.clone());
+        (self.respond)(request)
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::Range;
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+/// Marker opening the table of contents, which [`insert_toc`] fills in
+pub const TOC_START: &str = "<!-- toc -->";
+/// Marker closing the table of contents
+pub const TOC_END: &str = "<!-- tocstop -->";
+
+/// A heading of a markdown document and the anchor GitHub gives it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TocEntry {
+    /// 1 to 6
+    pub level: usize,
+    /// Heading text with inline markup removed
+    pub text: String,
+    /// Fragment linking to the heading, without the `#`
+    pub anchor: String,
+}
+
+/// Makes heading anchors the way GitHub does: lowercase, with punctuation dropped and
+/// spaces turned into `-`. A repeated anchor gets `-1`, `-2`, and so on.
+#[derive(Debug, Default)]
+pub struct Slugger {
+    seen: HashMap<String, usize>,
+}
+
+impl Slugger {
+    /// The anchor for the next heading with `text`
+    pub fn slug(&mut self, text: &str) -> String {
+        let base: String = text
+            .trim()
+            .to_lowercase()
+            .chars()
+            .filter(|&c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_')
+            .map(|c| if c == ' ' { '-' } else { c })
+            .collect();
+        let mut slug = base.clone();
+        while self.seen.contains_key(&slug) {
+            let count = self.seen.entry(base.clone()).or_default();
+            *count += 1;
+            slug = format!("{}-{}", base, count);
+        }
+        self.seen.insert(slug.clone(), 0);
+        slug
+    }
+}
+
+/// Every heading in `markdown` with its anchor, in document order. Headings inside
+/// code blocks are not headings and are left out.
+pub fn toc_entries(markdown: &str) -> Vec<TocEntry> {
+    let mut slugger = Slugger::default();
+    let mut entries = Vec::new();
+    let mut heading: Option<(HeadingLevel, String)> = None;
+    for event in Parser::new_ext(markdown, Options::ENABLE_TABLES) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => heading = Some((level, String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, heading_text)) = &mut heading {
+                    heading_text.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((level, text)) = heading.take() {
+                    let anchor = slugger.slug(&text);
+                    entries.push(TocEntry { level: level as usize, text: text.trim().to_string(), anchor });
+                }
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+/// A nested list linking the level 2 and 3 entries, one line each. Links point into
+/// `page`, or into the same document when it is empty.
+pub fn render_toc(entries: &[TocEntry], page: &str) -> String {
+    let mut out = String::new();
+    for entry in entries.iter().filter(|entry| (2..=3).contains(&entry.level)) {
+        let text = entry.text.replace('[', "\\[").replace(']', "\\]");
+        let indent = "  ".repeat(entry.level - 2);
+        let _ = writeln!(out, "{}- [{}]({}#{})", indent, text, page, entry.anchor);
+    }
+    out
+}
+
+/// `markdown` with a table of its level 2 and 3 headings between [`TOC_START`] and
+/// [`TOC_END`]. An existing table between the markers is replaced. Without markers the
+/// table goes after the paragraph introducing the document, or before its first section
+/// when there is none. A document without such headings is returned as it is.
+pub fn insert_toc(markdown: &str) -> String {
+    let list = render_toc(&toc_entries(markdown), "");
+    let blocks = top_level_blocks(markdown);
+    let markers = |marker: &str| {
+        blocks.iter().find_map(|(tag, range)| {
+            (matches!(tag, Some(Tag::HtmlBlock)) && markdown[range.clone()].trim() == marker).then(|| range.clone())
+        })
+    };
+
+    let toc = format!("{}\n{}{}", TOC_START, list, TOC_END);
+    if let Some(start) = markers(TOC_START) {
+        let end = markers(TOC_END).filter(|end| end.start >= start.end).map_or(start.end, |end| end.end);
+        let end = markdown[..end].trim_end().len();
+        return format!("{}{}{}", &markdown[..start.start], toc, &markdown[end..]);
+    }
+    if list.is_empty() {
+        return markdown.to_string();
+    }
+
+    let is_heading = |tag: &Option<Tag>| matches!(tag, Some(Tag::Heading { .. }));
+    let is_title = |tag: &Option<Tag>| matches!(tag, Some(Tag::Heading { level: HeadingLevel::H1, .. }));
+    let title = blocks.iter().position(|(tag, _)| is_heading(tag)).filter(|&index| is_title(&blocks[index].0));
+    let at = match title {
+        Some(index) => match blocks.get(index + 1) {
+            Some((Some(Tag::Paragraph), intro)) => intro.end,
+            Some((_, next)) => next.start,
+            None => blocks[index].1.end,
+        },
+        None => match blocks.iter().find(|(tag, _)| is_heading(tag)) {
+            Some((_, first)) => first.start,
+            None => markdown.len(),
+        },
+    };
+
+    let before = markdown[..at].trim_end();
+    let after = markdown[at..].trim_start_matches(['\r', '\n']);
+    let mut out = String::from(before);
+    if !out.is_empty() {
+        out.push_str("\n\n");
+    }
+    out.push_str(&toc);
+    out.push('\n');
+    if !after.is_empty() {
+        out.push('\n');
+        out.push_str(after);
+    }
+    out
+}
+
+/// The blocks directly in the document, each with its byte range. Blocks that are a
+/// single event, such as a thematic break, have no tag.
+fn top_level_blocks(markdown: &str) -> Vec<(Option<Tag<'_>>, Range<usize>)> {
+    let mut blocks = Vec::new();
+    let mut depth = 0usize;
+    for (event, range) in Parser::new_ext(markdown, Options::ENABLE_TABLES).into_offset_iter() {
+        match event {
+            Event::Start(tag) => {
+                if depth == 0 {
+                    blocks.push((Some(tag), range));
+                }
+                depth += 1;
+            }
+            Event::End(_) => depth = depth.saturating_sub(1),
+            _ if depth == 0 => blocks.push((None, range)),
+            _ => {}
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slugs(headings: &[&str]) -> Vec<String> {
+        let mut slugger = Slugger::default();
+        headings.iter().map(|heading| slugger.slug(heading)).collect()
+    }
+
+    #[test]
+    fn slugs_follow_github_rules() {
+        assert_eq!(
+            slugs(&["Getting Started", "Hello, World!", "API & CLI", "C++ Support", "snake_case-names", "  Über Café  "]),
+            ["getting-started", "hello-world", "api--cli", "c-support", "snake_case-names", "über-café"]
+        );
+    }
+
+    #[test]
+    fn repeated_slugs_get_numbered_suffixes() {
+        assert_eq!(
+            slugs(&["Usage", "Usage", "Usage 1", "usage", "Usage-1"]),
+            ["usage", "usage-1", "usage-1-1", "usage-2", "usage-1-2"]
+        );
+    }
+
+    #[test]
+    fn entries_strip_inline_markup_and_skip_code_blocks() {
+        let markdown = "# Title\n\n## Run `cargo test` *now*\n\n```sh\n## not a heading\n```\n\n### [Links](https://example.com)\n";
+        let entries = toc_entries(markdown);
+        let summary: Vec<(usize, &str, &str)> =
+            entries.iter().map(|entry| (entry.level, entry.text.as_str(), entry.anchor.as_str())).collect();
+        assert_eq!(summary, [(1, "Title", "title"), (2, "Run cargo test now", "run-cargo-test-now"), (3, "Links", "links")]);
+    }
+
+    #[test]
+    fn render_lists_levels_two_and_three() {
+        let entries = toc_entries("# Title\n## Install\n### From [source]\n#### Details\n## Usage\n");
+        assert_eq!(
+            render_toc(&entries, ""),
+            "- [Install](#install)\n  - [From \\[source\\]](#from-source)\n- [Usage](#usage)\n"
+        );
+        assert!(render_toc(&entries, "README.md").starts_with("- [Install](README.md#install)\n"));
+    }
+
+    #[test]
+    fn toc_goes_after_the_introduction() {
+        let markdown = "# Project\n\nWhat it does.\n\n## Install\n\nRun it.\n\n## Usage\n";
+        assert_eq!(
+            insert_toc(markdown),
+            "# Project\n\nWhat it does.\n\n<!-- toc -->\n- [Install](#install)\n- [Usage](#usage)\n<!-- tocstop -->\n\n## Install\n\nRun it.\n\n## Usage\n"
+        );
+    }
+
+    #[test]
+    fn toc_goes_before_the_first_section_without_a_title() {
+        let markdown = "Some notes.\n\n## Install\n";
+        assert_eq!(insert_toc(markdown), "Some notes.\n\n<!-- toc -->\n- [Install](#install)\n<!-- tocstop -->\n\n## Install\n");
+    }
+
+    #[test]
+    fn markers_are_filled_in_and_a_stale_table_is_replaced() {
+        let markdown = "# Project\n\n<!-- toc -->\n- [Old](#old)\n<!-- tocstop -->\n\nIntro.\n\n## New\n";
+        let expected = "# Project\n\n<!-- toc -->\n- [New](#new)\n<!-- tocstop -->\n\nIntro.\n\n## New\n";
+        assert_eq!(insert_toc(markdown), expected);
+        // Running it again changes nothing
+        assert_eq!(insert_toc(expected), expected);
+    }
+
+    #[test]
+    fn document_without_sections_is_unchanged() {
+        let markdown = "# Project\n\nOnly an introduction.\n\n#### Too deep\n";
+        assert_eq!(insert_toc(markdown), markdown);
+    }
+}
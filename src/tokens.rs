@@ -0,0 +1,247 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use serde::Serialize;
+
+use crate::claude::{model_info, DEFAULT_MAX_TOKENS};
+use crate::plan::{estimate_tokens, PromptPlan, BUDGET_WARNING};
+
+/// Number of files listed in the per-file breakdown
+pub const TOP_FILES: usize = 20;
+
+/// Token count for one directory or file of the prompt
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenCount {
+    /// Directory or file path relative to the project root
+    pub name: String,
+    pub files: usize,
+    pub tokens: u64,
+}
+
+/// Estimated prompt size and cost for a README run
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenReport {
+    pub model: String,
+    pub files_included: usize,
+    pub files_skipped: usize,
+    pub included_bytes: u64,
+    pub system_prompt_tokens: u64,
+    pub file_tokens: u64,
+    /// Total prompt tokens, estimated or counted by the API
+    pub total_tokens: u64,
+    /// Whether `total_tokens` came from the token counting endpoint
+    pub exact: bool,
+    /// Tokens per top-level directory, largest first
+    pub by_directory: Vec<TokenCount>,
+    /// The largest files, at most [`TOP_FILES`]
+    pub top_files: Vec<TokenCount>,
+    /// Context window of the model, if it is known
+    pub context_window: Option<u64>,
+    /// Whether the prompt plus the output token limit exceeds the context window
+    pub exceeds_context_window: Option<bool>,
+    /// Cost of the prompt tokens in USD, if the model's pricing is known
+    pub input_cost_usd: Option<f64>,
+    /// Cost in USD if the reply uses the whole output token limit
+    pub max_cost_usd: Option<f64>,
+}
+
+impl TokenReport {
+    /// Build a report from the plan a real run would use
+    pub fn new(plan: &PromptPlan, system_prompt: &str, model: &str) -> Self {
+        let mut directories: BTreeMap<String, TokenCount> = BTreeMap::new();
+        let mut files = Vec::new();
+
+        for file in plan.included() {
+            let relative = file.path.strip_prefix(&plan.root).unwrap_or(&file.path);
+            let mut components = relative.components();
+            let first = components.next();
+            // Files directly in the root are grouped under "."
+            let directory = components
+                .next()
+                .and(first)
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .unwrap_or_else(|| ".".to_string());
+
+            let entry = directories.entry(directory.clone()).or_insert(TokenCount {
+                name: directory,
+                files: 0,
+                tokens: 0,
+            });
+            entry.files += 1;
+            entry.tokens += file.estimated_tokens;
+
+            files.push(TokenCount {
+                name: relative.display().to_string(),
+                files: 1,
+                tokens: file.estimated_tokens,
+            });
+        }
+
+        let mut by_directory: Vec<TokenCount> = directories.into_values().collect();
+        by_directory.sort_by_key(|d| Reverse(d.tokens));
+        files.sort_by_key(|f| Reverse(f.tokens));
+        files.truncate(TOP_FILES);
+
+        let mut file_tokens = plan.estimated_tokens();
+        if plan.budget_reached {
+            file_tokens += estimate_tokens(BUDGET_WARNING.len() as u64 + 1);
+        }
+        let system_prompt_tokens = estimate_tokens(system_prompt.len() as u64);
+
+        let mut report = Self {
+            model: model.to_string(),
+            files_included: plan.included().count(),
+            files_skipped: plan.skipped().count(),
+            included_bytes: plan.included_bytes(),
+            system_prompt_tokens,
+            file_tokens,
+            total_tokens: 0,
+            exact: false,
+            by_directory,
+            top_files: files,
+            context_window: None,
+            exceeds_context_window: None,
+            input_cost_usd: None,
+            max_cost_usd: None,
+        };
+        report.set_total(system_prompt_tokens + file_tokens, false);
+        report
+    }
+
+    /// Replace the total, e.g. with the count from the token counting endpoint
+    pub fn set_total(&mut self, tokens: u64, exact: bool) {
+        self.total_tokens = tokens;
+        self.exact = exact;
+
+        let info = model_info(&self.model);
+        let output_tokens = u64::from(DEFAULT_MAX_TOKENS);
+        self.context_window = info.map(|i| i.context_window);
+        self.exceeds_context_window = info.map(|i| tokens + output_tokens > i.context_window);
+        self.input_cost_usd = info.map(|i| i.cost_usd(tokens, 0));
+        self.max_cost_usd = info.map(|i| i.cost_usd(tokens, output_tokens));
+    }
+
+    /// Render the report as plain-text tables
+    pub fn render_table(&self) -> String {
+        let mut out = String::new();
+        let kind = if self.exact { "counted" } else { "estimated" };
+
+        let _ = writeln!(out, "Model:          {}", self.model);
+        let _ = writeln!(out, "Files:          {} included, {} skipped ({} bytes)",
+            self.files_included, self.files_skipped, self.included_bytes);
+        let _ = writeln!(out, "System prompt:  {} tokens", self.system_prompt_tokens);
+        let _ = writeln!(out, "File contents:  {} tokens", self.file_tokens);
+        let _ = writeln!(out, "Total:          {} tokens ({})", self.total_tokens, kind);
+
+        match (self.input_cost_usd, self.max_cost_usd) {
+            (Some(input), Some(max)) => {
+                let _ = writeln!(out, "Cost:           ${:.4} input, up to ${:.4} with {} output tokens",
+                    input, max, DEFAULT_MAX_TOKENS);
+            }
+            _ => {
+                let _ = writeln!(out, "Cost:           unknown pricing for this model");
+            }
+        }
+        match (self.context_window, self.exceeds_context_window) {
+            (Some(window), Some(true)) => {
+                let _ = writeln!(out, "Context window: {} tokens, EXCEEDED", window);
+            }
+            (Some(window), _) => {
+                let _ = writeln!(out, "Context window: {} tokens, fits", window);
+            }
+            _ => {
+                let _ = writeln!(out, "Context window: unknown for this model");
+            }
+        }
+
+        let _ = writeln!(out, "\n{:>10}  {:>6}  Directory", "Tokens", "Files");
+        for dir in &self.by_directory {
+            let _ = writeln!(out, "{:>10}  {:>6}  {}", dir.tokens, dir.files, dir.name);
+        }
+
+        let _ = writeln!(out, "\n{:>10}  File", "Tokens");
+        for file in &self.top_files {
+            let _ = writeln!(out, "{:>10}  {}", file.tokens, file.name);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::PromptOptions;
+    use crate::testing::FixtureTree;
+
+    const HAIKU: &str = "claude-3-5-haiku-latest";
+
+    fn report(model: &str) -> (FixtureTree, TokenReport) {
+        let line = "abcdefghijklmnopqrstuvwxyz0123456789abc\n";
+        let tree = FixtureTree::new()
+            .file("Cargo.toml", "[package]\nname = \"demo\"\n")
+            .file("src/lib.rs", line.repeat(100))
+            .file("src/util.rs", line.repeat(10))
+            .file("docs/guide.md", line.repeat(50))
+            .sized_file("assets/big.bin", 200 * 1024);
+        let plan = PromptPlan::collect(tree.path(), &PromptOptions::default()).unwrap();
+        let report = TokenReport::new(&plan, &"word".repeat(250), model);
+        (tree, report)
+    }
+
+    #[test]
+    fn tokens_are_estimated_at_four_bytes_each() {
+        assert_eq!(estimate_tokens(0), 0);
+        assert_eq!(estimate_tokens(1), 1);
+        assert_eq!(estimate_tokens(4000), 1000);
+        assert_eq!(estimate_tokens(4001), 1001);
+    }
+
+    #[test]
+    fn the_report_breaks_tokens_down_by_directory_and_file() {
+        let (tree, report) = report(HAIKU);
+        assert_eq!((report.files_included, report.files_skipped), (4, 1));
+        assert_eq!(report.included_bytes, 4000 + 400 + 2000 + 24);
+        assert_eq!(report.system_prompt_tokens, 250);
+        assert_eq!(report.total_tokens, report.system_prompt_tokens + report.file_tokens);
+        assert!(!report.exact);
+
+        let directories: Vec<_> = report.by_directory.iter().map(|d| (d.name.as_str(), d.files)).collect();
+        assert_eq!(directories, [("src", 2), ("docs", 1), (".", 1)]);
+        let files: Vec<_> = report.top_files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(files, ["src/lib.rs", "docs/guide.md", "src/util.rs", "Cargo.toml"]);
+        // Each file's share is its content plus the header and fence around it
+        let path = tree.join("src/lib.rs").display().to_string().len() as u64;
+        let share = report.top_files[0].tokens;
+        assert!(share > estimate_tokens(4000 + path) && share <= estimate_tokens(4000 + path + 64), "{}", share);
+        assert_eq!(report.by_directory[0].tokens, report.top_files[0].tokens + report.top_files[2].tokens);
+        assert!(report.file_tokens >= report.by_directory.iter().map(|d| d.tokens).sum::<u64>());
+    }
+
+    #[test]
+    fn cost_and_context_window_follow_the_model() {
+        let (_tree, mut report) = report(HAIKU);
+        assert_eq!(report.context_window, Some(200_000));
+        assert_eq!(report.exceeds_context_window, Some(false));
+        assert!(report.render_table().contains("Context window: 200000 tokens, fits\n"));
+
+        report.set_total(1_000_000, true);
+        assert!(report.exact);
+        assert_eq!(report.exceeds_context_window, Some(true));
+        assert!((report.input_cost_usd.unwrap() - 0.8).abs() < 1e-9);
+        assert!((report.max_cost_usd.unwrap() - (0.8 + 4096.0 * 4.0 / 1_000_000.0)).abs() < 1e-9);
+        let table = report.render_table();
+        assert!(table.contains("Total:          1000000 tokens (counted)\n"), "{}", table);
+        assert!(table.contains("Cost:           $0.8000 input, up to $0.8164 with 4096 output tokens\n"), "{}", table);
+        assert!(table.contains("Context window: 200000 tokens, EXCEEDED\n"), "{}", table);
+    }
+
+    #[test]
+    fn unknown_models_have_no_price_or_window() {
+        let (_tree, report) = report("local-llama");
+        assert_eq!((report.context_window, report.input_cost_usd, report.max_cost_usd), (None, None, None));
+        let table = report.render_table();
+        assert!(table.contains("Cost:           unknown pricing for this model\nContext window: unknown for this model\n"), "{}", table);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["top_files"][0]["name"], "src/lib.rs");
+    }
+}
@@ -0,0 +1,536 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use crate::infra::{detect_infra, InfraSummary};
+
+/// Configuration files of formatters, linters, and toolchains, by exact name
+const TOOL_CONFIGS: &[&str] = &[
+    ".clippy.toml",
+    ".editorconfig",
+    ".flake8",
+    ".golangci.yaml",
+    ".golangci.yml",
+    ".pre-commit-config.yaml",
+    ".rustfmt.toml",
+    "biome.json",
+    "clippy.toml",
+    "deny.toml",
+    "mypy.ini",
+    "ruff.toml",
+    "rust-toolchain",
+    "rust-toolchain.toml",
+    "rustfmt.toml",
+    "tsconfig.json",
+];
+/// Prefixes of configuration files that come in several formats, like `.eslintrc.json`
+const TOOL_CONFIG_PREFIXES: &[&str] = &[".eslintrc", ".prettierrc", "eslint.config.", "prettier.config.", ".stylelintrc"];
+/// Places pull request templates are looked for
+const PR_TEMPLATES: &[&str] = &[
+    ".github/PULL_REQUEST_TEMPLATE.md",
+    ".github/pull_request_template.md",
+    "PULL_REQUEST_TEMPLATE.md",
+    "pull_request_template.md",
+    "docs/PULL_REQUEST_TEMPLATE.md",
+    "docs/pull_request_template.md",
+    ".github/ISSUE_TEMPLATE.md",
+];
+/// Largest file read while looking for facts
+const MAX_FACT_FILE_BYTES: u64 = 256 * 1024;
+
+/// What a development command is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CommandKind {
+    /// Installing dependencies
+    Setup,
+    Build,
+    Test,
+    /// Linting, formatting, and type checking
+    Lint,
+    /// Any other script or make target
+    Other,
+}
+
+/// A command a contributor can run, and where it was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevCommand {
+    pub kind: CommandKind,
+    pub command: String,
+    /// File the command comes from, such as `package.json`
+    pub source: String,
+}
+
+/// The commands a CI workflow runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CiWorkflow {
+    /// Path of the workflow file relative to the repository root
+    pub file: String,
+    /// The workflow's `name`, when it has one
+    pub name: Option<String>,
+    /// Shell commands of its steps, in file order
+    pub commands: Vec<String>,
+}
+
+/// Facts about how a repository is built, tested, linted, and checked in CI, read from
+/// its manifests, Makefile, tool configuration, and workflow files
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolingFacts {
+    pub commands: Vec<DevCommand>,
+    /// Formatter, linter, and toolchain configuration files, relative to the root
+    pub tool_configs: Vec<String>,
+    pub ci: Vec<CiWorkflow>,
+    /// Pull request and issue templates, relative to the root
+    pub templates: Vec<String>,
+    /// CI pipelines, container images, and deployment manifests
+    pub infra: InfraSummary,
+}
+
+impl ToolingFacts {
+    /// Whether nothing was found
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+            && self.tool_configs.is_empty()
+            && self.ci.is_empty()
+            && self.templates.is_empty()
+            && self.infra.is_empty()
+    }
+
+    /// Record a command unless the same command was found before
+    fn push(&mut self, kind: CommandKind, command: &str, source: &str) {
+        if !self.commands.iter().any(|existing| existing.command == command) {
+            self.commands.push(DevCommand {
+                kind,
+                command: command.to_string(),
+                source: source.to_string(),
+            });
+        }
+    }
+
+    /// The commands of one kind, in the order they were found
+    pub fn commands_of(&self, kind: CommandKind) -> impl Iterator<Item = &DevCommand> {
+        self.commands.iter().filter(move |command| command.kind == kind)
+    }
+
+    /// The facts as a block of labeled lists for a prompt; empty lists are left out
+    pub fn to_prompt(&self) -> String {
+        let mut out = String::new();
+        for (kind, label) in [
+            (CommandKind::Setup, "Setup commands"),
+            (CommandKind::Build, "Build commands"),
+            (CommandKind::Test, "Test commands"),
+            (CommandKind::Lint, "Lint and format commands"),
+            (CommandKind::Other, "Other scripts"),
+        ] {
+            let mut commands = self.commands_of(kind).peekable();
+            if commands.peek().is_none() {
+                continue;
+            }
+            let _ = writeln!(out, "{}:", label);
+            for command in commands {
+                let _ = writeln!(out, "- `{}` (from {})", command.command, command.source);
+            }
+        }
+        if !self.tool_configs.is_empty() {
+            let _ = writeln!(out, "Tool configuration files: {}", self.tool_configs.join(", "));
+        }
+        for workflow in &self.ci {
+            match &workflow.name {
+                Some(name) => {
+                    let _ = writeln!(out, "CI workflow {} ({}):", workflow.file, name);
+                }
+                None => {
+                    let _ = writeln!(out, "CI workflow {}:", workflow.file);
+                }
+            }
+            for command in &workflow.commands {
+                let _ = writeln!(out, "- `{}`", command);
+            }
+        }
+        if !self.templates.is_empty() {
+            let _ = writeln!(out, "Pull request and issue templates: {}", self.templates.join(", "));
+        }
+        if !self.infra.is_empty() {
+            let _ = write!(out, "Build and deployment configuration:\n{}", self.infra.to_prompt());
+        }
+        out
+    }
+}
+
+/// Read the build, test, lint, and CI facts of the repository in `dir`. Only the root
+/// and the usual CI and template locations are looked at, apart from the container and
+/// deployment files [`detect_infra`] searches for; unreadable files are skipped.
+pub fn detect_tooling(dir: &Path) -> ToolingFacts {
+    let mut facts = ToolingFacts::default();
+    cargo_commands(dir, &mut facts);
+    node_commands(dir, &mut facts);
+    python_commands(dir, &mut facts);
+    if dir.join("go.mod").is_file() {
+        facts.push(CommandKind::Build, "go build ./...", "go.mod");
+        facts.push(CommandKind::Test, "go test ./...", "go.mod");
+        facts.push(CommandKind::Lint, "go vet ./...", "go.mod");
+    }
+    if let Some(makefile) = ["Makefile", "makefile", "GNUmakefile"].iter().find(|name| dir.join(name).is_file()) {
+        if let Some(source) = read_small(&dir.join(makefile)) {
+            for target in make_targets(&source) {
+                facts.push(command_kind(&target), &format!("make {}", target), makefile);
+            }
+        }
+    }
+    if let Some(justfile) = read_small(&dir.join("justfile")) {
+        for recipe in just_recipes(&justfile) {
+            facts.push(command_kind(&recipe), &format!("just {}", recipe), "justfile");
+        }
+    }
+
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| TOOL_CONFIGS.contains(&name.as_str()) || TOOL_CONFIG_PREFIXES.iter().any(|p| name.starts_with(p)))
+        .collect();
+    names.sort();
+    facts.tool_configs.extend(names);
+
+    facts.ci = ci_workflows(dir);
+    facts.templates = PR_TEMPLATES
+        .iter()
+        .filter(|path| dir.join(path).is_file())
+        .map(|path| path.to_string())
+        .collect();
+    let mut issue_templates: Vec<String> = fs::read_dir(dir.join(".github/ISSUE_TEMPLATE"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| format!(".github/ISSUE_TEMPLATE/{}", entry.file_name().to_string_lossy()))
+        .collect();
+    issue_templates.sort();
+    facts.templates.extend(issue_templates);
+    facts.infra = detect_infra(dir);
+    facts
+}
+
+/// `path`'s content when it is a file small enough to read
+fn read_small(path: &Path) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_FACT_FILE_BYTES {
+        return None;
+    }
+    fs::read_to_string(path).ok()
+}
+
+/// What a script, make target, or recipe called `name` is most likely for
+fn command_kind(name: &str) -> CommandKind {
+    let base = name.split([':', '-', '_']).next().unwrap_or(name);
+    match base {
+        "install" | "setup" | "bootstrap" | "deps" => CommandKind::Setup,
+        "build" | "all" | "compile" | "dist" | "release" => CommandKind::Build,
+        "test" | "tests" | "check" | "coverage" | "e2e" => CommandKind::Test,
+        "lint" | "fmt" | "format" | "typecheck" | "clippy" | "prettier" | "eslint" | "vet" => CommandKind::Lint,
+        _ => CommandKind::Other,
+    }
+}
+
+fn cargo_commands(dir: &Path, facts: &mut ToolingFacts) {
+    let Some(manifest) = read_small(&dir.join("Cargo.toml")).and_then(|m| m.parse::<toml::Table>().ok()) else {
+        return;
+    };
+    let workspace = if manifest.contains_key("workspace") { " --workspace" } else { "" };
+    facts.push(CommandKind::Build, &format!("cargo build{}", workspace), "Cargo.toml");
+    facts.push(CommandKind::Test, &format!("cargo test{}", workspace), "Cargo.toml");
+    facts.push(CommandKind::Lint, "cargo fmt --check", "Cargo.toml");
+    facts.push(CommandKind::Lint, &format!("cargo clippy{} --all-targets", workspace), "Cargo.toml");
+    let lints = manifest.contains_key("lints")
+        || manifest.get("workspace").and_then(|w| w.get("lints")).is_some();
+    if lints {
+        facts.tool_configs.push("Cargo.toml [lints]".to_string());
+    }
+}
+
+fn node_commands(dir: &Path, facts: &mut ToolingFacts) {
+    let Some(package) = read_small(&dir.join("package.json"))
+        .and_then(|p| serde_json::from_str::<serde_json::Value>(&p).ok())
+    else {
+        return;
+    };
+    let manager = [("pnpm-lock.yaml", "pnpm"), ("yarn.lock", "yarn"), ("bun.lockb", "bun"), ("bun.lock", "bun")]
+        .iter()
+        .find(|(lockfile, _)| dir.join(lockfile).is_file())
+        .map_or("npm", |(_, manager)| manager);
+    let install = match (manager, dir.join("package-lock.json").is_file()) {
+        ("npm", true) => "npm ci".to_string(),
+        (manager, _) => format!("{} install", manager),
+    };
+    facts.push(CommandKind::Setup, &install, "package.json");
+
+    let Some(scripts) = package.get("scripts").and_then(|s| s.as_object()) else {
+        return;
+    };
+    for name in scripts.keys() {
+        // npm runs `test` and `start` without `run`; the other managers run any script that way
+        let command = match (manager, name.as_str()) {
+            ("npm", "test" | "start") => format!("npm {}", name),
+            ("npm", _) => format!("npm run {}", name),
+            (manager, _) => format!("{} {}", manager, name),
+        };
+        facts.push(command_kind(name), &command, "package.json scripts");
+    }
+}
+
+fn python_commands(dir: &Path, facts: &mut ToolingFacts) {
+    let pyproject = read_small(&dir.join("pyproject.toml")).and_then(|p| p.parse::<toml::Table>().ok());
+    let tool = pyproject.as_ref().and_then(|p| p.get("tool"));
+    let has_tool = |name: &str| tool.and_then(|t| t.get(name)).is_some();
+    if pyproject.is_some() {
+        facts.push(CommandKind::Setup, "pip install -e .", "pyproject.toml");
+    } else if dir.join("requirements.txt").is_file() {
+        facts.push(CommandKind::Setup, "pip install -r requirements.txt", "requirements.txt");
+    }
+    if has_tool("pytest") || dir.join("pytest.ini").is_file() {
+        facts.push(CommandKind::Test, "pytest", if has_tool("pytest") { "pyproject.toml" } else { "pytest.ini" });
+    }
+    if dir.join("tox.ini").is_file() {
+        facts.push(CommandKind::Test, "tox", "tox.ini");
+    }
+    if has_tool("ruff") || dir.join("ruff.toml").is_file() {
+        facts.push(CommandKind::Lint, "ruff check .", if has_tool("ruff") { "pyproject.toml" } else { "ruff.toml" });
+    }
+    if has_tool("black") {
+        facts.push(CommandKind::Lint, "black --check .", "pyproject.toml");
+    }
+    if has_tool("mypy") || dir.join("mypy.ini").is_file() {
+        facts.push(CommandKind::Lint, "mypy .", if has_tool("mypy") { "pyproject.toml" } else { "mypy.ini" });
+    }
+}
+
+/// Targets a Makefile defines explicitly, in file order; special targets like
+/// `.PHONY`, pattern rules, and variable assignments are skipped
+fn make_targets(makefile: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+    for line in makefile.lines() {
+        if line.starts_with(['\t', ' ', '#', '.']) {
+            continue;
+        }
+        let Some((names, rest)) = line.split_once(':') else {
+            continue;
+        };
+        // `VAR := value` and `VAR ::= value` are assignments, not rules
+        if rest.starts_with('=') || rest.starts_with(":=") || names.contains(['=', '%', '$']) {
+            continue;
+        }
+        for name in names.split_whitespace() {
+            if !targets.iter().any(|target| target == name) {
+                targets.push(name.to_string());
+            }
+        }
+    }
+    targets
+}
+
+/// Recipes a justfile defines, in file order
+fn just_recipes(justfile: &str) -> Vec<String> {
+    justfile
+        .lines()
+        .filter(|line| !line.starts_with([' ', '\t', '#', '[', '@']) && !line.contains(":="))
+        .filter_map(|line| line.split_once(':'))
+        .filter_map(|(head, _)| head.split_whitespace().next())
+        .filter(|name| name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_'))
+        .map(String::from)
+        .collect()
+}
+
+/// GitHub Actions and GitLab CI workflows in `dir`, with the shell commands they run
+fn ci_workflows(dir: &Path) -> Vec<CiWorkflow> {
+    let mut files: Vec<_> = fs::read_dir(dir.join(".github/workflows"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "yml" || ext == "yaml"))
+        .collect();
+    files.sort();
+    files.push(dir.join(".gitlab-ci.yml"));
+
+    files
+        .iter()
+        .filter_map(|path| {
+            let source = read_small(path)?;
+            let gitlab = path.ends_with(".gitlab-ci.yml");
+            Some(CiWorkflow {
+                file: path.strip_prefix(dir).unwrap_or(path).to_string_lossy().replace('\\', "/"),
+                name: workflow_name(&source),
+                commands: if gitlab { script_commands(&source) } else { run_commands(&source) },
+            })
+        })
+        .collect()
+}
+
+/// The top-level `name:` of a workflow
+fn workflow_name(source: &str) -> Option<String> {
+    source
+        .lines()
+        .find_map(|line| line.strip_prefix("name:"))
+        .map(|name| name.trim().trim_matches(['"', '\'']).to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Shell commands of `run:` keys, one per line of a `run: |` block
+fn run_commands(source: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut lines = source.lines().peekable();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start().trim_start_matches("- ");
+        let Some(value) = trimmed.strip_prefix("run:") else {
+            continue;
+        };
+        let value = value.trim();
+        if !value.starts_with(['|', '>']) {
+            push_command(&mut commands, value);
+            continue;
+        }
+        // A block scalar continues while lines are indented past the key
+        let indent = line.len() - line.trim_start().len();
+        while let Some(next) = lines.peek() {
+            if !next.trim().is_empty() && next.len() - next.trim_start().len() <= indent {
+                break;
+            }
+            push_command(&mut commands, next.trim());
+            lines.next();
+        }
+    }
+    commands
+}
+
+/// Items of GitLab `script:` lists
+fn script_commands(source: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut script_indent = None;
+    for line in source.lines() {
+        let indent = line.len() - line.trim_start().len();
+        let trimmed = line.trim();
+        if let Some(value) = trimmed
+            .strip_prefix("script:")
+            .or_else(|| trimmed.strip_prefix("before_script:"))
+        {
+            script_indent = Some(indent);
+            let value = value.trim();
+            if !value.is_empty() {
+                push_command(&mut commands, value.trim_matches(['[', ']']));
+            }
+            continue;
+        }
+        match (script_indent, trimmed.strip_prefix("- ")) {
+            (Some(script), Some(item)) if indent >= script => push_command(&mut commands, item),
+            (Some(script), _) if !trimmed.is_empty() && indent <= script => script_indent = None,
+            _ => {}
+        }
+    }
+    commands
+}
+
+fn push_command(commands: &mut Vec<String>, command: &str) {
+    let command = command.trim().trim_matches(['"', '\'']);
+    if !command.is_empty() && !command.starts_with('#') {
+        commands.push(command.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    fn commands(facts: &ToolingFacts) -> Vec<(CommandKind, &str)> {
+        facts.commands.iter().map(|command| (command.kind, command.command.as_str())).collect()
+    }
+
+    #[test]
+    fn a_rust_workspace_reports_its_real_commands() {
+        let tree = FixtureTree::new()
+            .file("Cargo.toml", "[workspace]\nmembers = [\"crates/*\"]\n\n[workspace.lints.rust]\nunsafe_code = \"forbid\"\n")
+            .file("rustfmt.toml", "max_width = 120\n")
+            .file("Makefile", "CARGO := cargo\n.PHONY: docs\ndocs:\n\t$(CARGO) doc\n%.o: %.c\n")
+            .file(".github/PULL_REQUEST_TEMPLATE.md", "## Summary\n")
+            .file(".github/ISSUE_TEMPLATE/bug.md", "")
+            .file(
+                ".github/workflows/ci.yml",
+                "name: CI\non: push\njobs:\n  test:\n    steps:\n      - uses: actions/checkout@v4\n      - run: |\n          cargo fmt --check\n          cargo test --workspace\n      - run: 'cargo doc'\n",
+            );
+        let facts = detect_tooling(tree.path());
+        assert_eq!(
+            commands(&facts),
+            [
+                (CommandKind::Build, "cargo build --workspace"),
+                (CommandKind::Test, "cargo test --workspace"),
+                (CommandKind::Lint, "cargo fmt --check"),
+                (CommandKind::Lint, "cargo clippy --workspace --all-targets"),
+                (CommandKind::Other, "make docs"),
+            ]
+        );
+        assert_eq!(facts.tool_configs, ["Cargo.toml [lints]", "rustfmt.toml"]);
+        assert_eq!(
+            facts.ci,
+            [CiWorkflow {
+                file: ".github/workflows/ci.yml".into(),
+                name: Some("CI".into()),
+                commands: vec!["cargo fmt --check".into(), "cargo test --workspace".into(), "cargo doc".into()],
+            }]
+        );
+        assert_eq!(facts.templates, [".github/PULL_REQUEST_TEMPLATE.md", ".github/ISSUE_TEMPLATE/bug.md"]);
+        let prompt = facts.to_prompt();
+        assert!(prompt.starts_with("Build commands:\n- `cargo build --workspace` (from Cargo.toml)\nTest commands:\n"), "{}", prompt);
+        assert!(prompt.contains("CI workflow .github/workflows/ci.yml (CI):\n- `cargo fmt --check`\n"), "{}", prompt);
+    }
+
+    #[test]
+    fn a_repository_without_tooling_has_no_facts() {
+        let tree = FixtureTree::new().file("notes.txt", "").file("docs/guide.md", "# Guide\n");
+        let facts = detect_tooling(tree.path());
+        assert!(facts.is_empty(), "{:?}", facts);
+        assert_eq!(facts.to_prompt(), "");
+    }
+
+    #[test]
+    fn scripts_are_classified_and_run_with_the_lockfile_manager() {
+        // `test:unit` is a test script by the name before its colon
+        let tree = FixtureTree::new()
+            .file(
+                "package.json",
+                r#"{"scripts": {"build": "tsc", "test:unit": "vitest", "lint": "eslint .", "start": "node ."}}"#,
+            )
+            .file("pnpm-lock.yaml", "")
+            .file(".eslintrc.cjs", "")
+            .file(".gitlab-ci.yml", "test:\n  script:\n    - pnpm install\n    - pnpm test:unit\n  artifacts:\n    paths: [dist]\n");
+        let facts = detect_tooling(tree.path());
+        assert_eq!(
+            commands(&facts),
+            [
+                (CommandKind::Setup, "pnpm install"),
+                (CommandKind::Build, "pnpm build"),
+                (CommandKind::Lint, "pnpm lint"),
+                (CommandKind::Other, "pnpm start"),
+                (CommandKind::Test, "pnpm test:unit"),
+            ]
+        );
+        assert_eq!(facts.tool_configs, [".eslintrc.cjs"]);
+        assert_eq!(facts.ci[0].commands, ["pnpm install", "pnpm test:unit"]);
+
+        // Without a lockfile npm is assumed, which runs `start` and `test` without `run`
+        let tree = FixtureTree::new().file("package.json", r#"{"scripts": {"start": "node .", "dev": "vite"}}"#);
+        let facts = detect_tooling(tree.path());
+        assert_eq!(
+            commands(&facts),
+            [(CommandKind::Setup, "npm install"), (CommandKind::Other, "npm run dev"), (CommandKind::Other, "npm start")]
+        );
+    }
+
+    #[test]
+    fn make_and_just_targets_skip_what_is_not_a_recipe() {
+        assert_eq!(
+            make_targets("VERSION ::= 1\nall build: deps\n\tcc\n.PHONY: all\n# test:\nbuild:\n%.o: %.c\n"),
+            ["all", "build"]
+        );
+        assert_eq!(just_recipes("set shell := [\"bash\"]\n[private]\nsetup:\ntest *ARGS:\n  cargo test\n@quiet:\n"), ["setup", "test"]);
+    }
+}
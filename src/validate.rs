@@ -0,0 +1,320 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// How long each link may take to answer in [`check_links`]
+#[cfg(feature = "claude")]
+pub const LINK_CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// A problem in generated markdown
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct OutputIssue {
+    pub kind: IssueKind,
+    /// One-based line the problem is on
+    #[cfg_attr(feature = "server", schema(example = 12))]
+    pub line: usize,
+    /// What is wrong, for people
+    #[cfg_attr(feature = "server", schema(example = "link to docs/guide.md: no such file"))]
+    pub message: String,
+}
+
+impl fmt::Display for OutputIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// What kind of problem an [`OutputIssue`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// A level-1 heading after the first
+    DuplicateTitle,
+    /// A code fence that is never closed, so the rest of the document is code
+    UnclosedFence,
+    /// A heading with nothing under it
+    EmptySection,
+    /// A relative link to a file that does not exist
+    BrokenLink,
+    /// A relative image path that does not exist
+    BrokenImage,
+    /// An http(s) link that did not answer with success
+    DeadLink,
+}
+
+/// Structural problems in `markdown`, and relative links and images that do not exist
+/// under `root`, in document order
+pub fn validate_markdown(markdown: &str, root: &Path) -> Vec<OutputIssue> {
+    let line_of = |offset: usize| markdown[..offset].matches('\n').count() + 1;
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let mut issues = Vec::new();
+    let mut seen_title = false;
+    // The last heading, its line, and its text, while nothing has followed it
+    let mut pending: Option<(HeadingLevel, usize, String)> = None;
+    let mut heading: Option<(HeadingLevel, usize, String)> = None;
+
+    for (event, range) in Parser::new_ext(markdown, Options::ENABLE_TABLES).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                if let Some((pending_level, line, text)) = pending.take() {
+                    if level <= pending_level {
+                        issues.push(empty_section(line, &text));
+                    }
+                }
+                if level == HeadingLevel::H1 {
+                    if seen_title {
+                        issues.push(OutputIssue {
+                            kind: IssueKind::DuplicateTitle,
+                            line: line_of(range.start),
+                            message: "more than one level-1 heading".to_string(),
+                        });
+                    }
+                    seen_title = true;
+                }
+                heading = Some((level, line_of(range.start), String::new()));
+            }
+            Event::Text(text) | Event::Code(text) if heading.is_some() => {
+                if let Some((_, _, heading_text)) = &mut heading {
+                    heading_text.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => pending = heading.take(),
+            Event::End(_) => {}
+            event => {
+                if heading.is_none() {
+                    pending = None;
+                }
+                match event {
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(_)))
+                        if !fence_is_closed(&markdown[range.clone()]) =>
+                    {
+                        issues.push(OutputIssue {
+                            kind: IssueKind::UnclosedFence,
+                            line: line_of(range.start),
+                            message: "code fence is never closed".to_string(),
+                        });
+                    }
+                    Event::Start(Tag::Link { dest_url, .. }) if is_missing(&root, &dest_url) => {
+                        issues.push(OutputIssue {
+                            kind: IssueKind::BrokenLink,
+                            line: line_of(range.start),
+                            message: format!("link to {}: no such file", dest_url),
+                        });
+                    }
+                    Event::Start(Tag::Image { dest_url, .. }) if is_missing(&root, &dest_url) => {
+                        issues.push(OutputIssue {
+                            kind: IssueKind::BrokenImage,
+                            line: line_of(range.start),
+                            message: format!("image {}: no such file", dest_url),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    if let Some((_, line, text)) = pending {
+        issues.push(empty_section(line, &text));
+    }
+    issues
+}
+
+/// Check that every http(s) link and image in `markdown` answers with success, each
+/// URL once and all at the same time. Servers that refuse HEAD are asked again with GET.
+#[cfg(feature = "claude")]
+pub async fn check_links(markdown: &str) -> Vec<OutputIssue> {
+    let client = match reqwest::Client::builder()
+        .user_agent(concat!("techdocs/", env!("CARGO_PKG_VERSION")))
+        .timeout(LINK_CHECK_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::warn!("Cannot check links: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut checks = tokio::task::JoinSet::new();
+    let mut seen = std::collections::HashSet::new();
+    for (line, url) in web_links(markdown) {
+        if !seen.insert(url.clone()) {
+            continue;
+        }
+        let client = client.clone();
+        checks.spawn(async move {
+            let mut response = client.head(&url).send().await;
+            if response.as_ref().is_ok_and(|response| {
+                response.status().is_client_error() || response.status() == reqwest::StatusCode::NOT_IMPLEMENTED
+            }) {
+                response = client.get(&url).send().await;
+            }
+            let problem = match response {
+                Ok(response) if response.status().is_success() => return None,
+                Ok(response) => response.status().to_string(),
+                Err(e) if e.is_timeout() => "timed out".to_string(),
+                Err(e) => e.to_string(),
+            };
+            Some(OutputIssue { kind: IssueKind::DeadLink, line, message: format!("link to {}: {}", url, problem) })
+        });
+    }
+
+    let mut issues = Vec::new();
+    while let Some(result) = checks.join_next().await {
+        if let Ok(Some(issue)) = result {
+            issues.push(issue);
+        }
+    }
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+/// The http(s) links and images in `markdown`, with their lines
+#[cfg(feature = "claude")]
+fn web_links(markdown: &str) -> Vec<(usize, String)> {
+    Parser::new_ext(markdown, Options::ENABLE_TABLES)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. })
+                if dest_url.starts_with("http://") || dest_url.starts_with("https://") =>
+            {
+                Some((markdown[..range.start].matches('\n').count() + 1, dest_url.to_string()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn empty_section(line: usize, heading: &str) -> OutputIssue {
+    OutputIssue {
+        kind: IssueKind::EmptySection,
+        line,
+        message: format!("section `{}` is empty", heading.trim()),
+    }
+}
+
+/// Whether a fenced code block's source ends with a closing fence. An unclosed fence
+/// runs to the end of the document, so its last line is content instead.
+fn fence_is_closed(block: &str) -> bool {
+    let mut lines = block.trim_end().lines();
+    let opening = lines.next().unwrap_or_default().trim_start();
+    let marker = opening.chars().next().unwrap_or('`');
+    let width = opening.chars().take_while(|&c| c == marker).count();
+    lines.next_back().is_some_and(|last| {
+        let last = last.trim();
+        last.chars().take_while(|&c| c == marker).count() >= width && last.chars().all(|c| c == marker)
+    })
+}
+
+/// Whether `dest` is a relative link to a file that does not exist under `root`
+fn is_missing(root: &Path, dest: &str) -> bool {
+    local_target(root, dest).is_some_and(|target| !target.exists())
+}
+
+/// The file a relative link points at, or `None` for anchors and links with a scheme.
+/// Links starting with `/` are taken from `root`, as GitHub does.
+fn local_target(root: &Path, dest: &str) -> Option<PathBuf> {
+    if dest.starts_with('#') || dest.starts_with("//") || Url::parse(dest).is_ok() {
+        return None;
+    }
+    let path = dest.split(['#', '?']).next().unwrap_or_default().trim_start_matches('/');
+    if path.is_empty() {
+        return None;
+    }
+    // Joining as a URL resolves `..` and decodes escapes such as `%20`
+    Url::from_directory_path(root)
+        .ok()?
+        .join(path)
+        .ok()?
+        .to_file_path()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    fn kinds(issues: &[OutputIssue]) -> Vec<(IssueKind, usize)> {
+        issues.iter().map(|issue| (issue.kind, issue.line)).collect()
+    }
+
+    #[test]
+    fn a_sound_document_has_no_issues() {
+        let tree = FixtureTree::new().file("docs/guide.md", "").file("assets/logo one.png", "");
+        let markdown = "# Demo\n\n![Logo](assets/logo%20one.png)\n\n## Usage\n\nSee [the guide](docs/guide.md#setup), \
+                        [the top](#demo), [the root](/docs/guide.md), and [the site](https://example.com).\n\n\
+                        ```sh\ncargo run\n```\n\n## Notes\n\n### Details\n\nText\n";
+        let issues = validate_markdown(markdown, tree.path());
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn structural_problems_are_reported_by_line() {
+        let markdown = "# Demo\n\n## Install\n\n## Usage\n\nText\n\n# Demo again\n\nMore\n\n```rust\nfn main() {}\n";
+        let issues = validate_markdown(markdown, Path::new("."));
+        assert_eq!(
+            kinds(&issues),
+            [(IssueKind::EmptySection, 3), (IssueKind::DuplicateTitle, 9), (IssueKind::UnclosedFence, 13)]
+        );
+        assert_eq!(issues[0].to_string(), "line 3: section `Install` is empty");
+    }
+
+    #[test]
+    fn a_trailing_heading_is_an_empty_section() {
+        let issues = validate_markdown("# Demo\n\nIntro\n\n## `cli` flags\n", Path::new("."));
+        assert_eq!(kinds(&issues), [(IssueKind::EmptySection, 5)]);
+        assert_eq!(issues[0].message, "section `cli flags` is empty");
+    }
+
+    #[test]
+    fn missing_files_are_broken_links_and_images() {
+        let tree = FixtureTree::new().file("src/lib.rs", "");
+        let markdown = "# Demo\n\n[Lib](src/lib.rs) [Guide](docs/guide.md)\n\n![Shot](../outside.png)\n";
+        let issues = validate_markdown(markdown, tree.path());
+        assert_eq!(kinds(&issues), [(IssueKind::BrokenLink, 3), (IssueKind::BrokenImage, 5)]);
+        assert_eq!(issues[0].message, "link to docs/guide.md: no such file");
+        assert_eq!(issues[1].message, "image ../outside.png: no such file");
+    }
+
+    #[cfg(feature = "claude")]
+    #[tokio::test]
+    async fn dead_web_links_are_reported_once() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::net::TcpListener;
+
+        // `/ok` answers, `/gone` does not, and `/no-head` only answers GET
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut reader = BufReader::new(stream);
+                    let mut head = String::new();
+                    while reader.read_line(&mut head).await.unwrap() > 0 && !head.ends_with("\r\n\r\n") {}
+                    let request = head.lines().next().unwrap_or_default().to_string();
+                    let status = match request.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+                        [_, "/ok"] | ["GET", "/no-head"] => "200 OK",
+                        ["HEAD", "/no-head"] => "405 Method Not Allowed",
+                        _ => "404 Not Found",
+                    };
+                    let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                    reader.into_inner().write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+
+        let markdown = format!(
+            "# Demo\n\n[ok](http://{addr}/ok)\n\n[gone](http://{addr}/gone)\n\n![shot](http://{addr}/no-head)\n\n\
+             [gone again](http://{addr}/gone) [local](src/lib.rs)\n"
+        );
+        let issues = check_links(&markdown).await;
+        assert_eq!(kinds(&issues), [(IssueKind::DeadLink, 5)]);
+        assert_eq!(issues[0].message, format!("link to http://{}/gone: 404 Not Found", addr));
+    }
+}
@@ -0,0 +1,238 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::Override;
+use notify_debouncer_full::notify::{EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, Debouncer, RecommendedCache};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use crate::{exclude_overrides, Result, TechDocsError};
+
+/// Directories whose contents never end up in a prompt
+const IGNORED_DIRS: &[&str] = &[".git", "target", "node_modules"];
+
+/// Watches a directory for changes that can affect its prompt. Changes in ignored
+/// directories and files excluded by patterns or the root `.gitignore` are filtered out.
+pub struct ChangeWatcher {
+    root: PathBuf,
+    overrides: Override,
+    gitignore: Gitignore,
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+    events: UnboundedReceiver<DebounceEventResult>,
+}
+
+impl ChangeWatcher {
+    /// Start watching `dir`, grouping changes that happen within `debounce` of each other
+    pub fn new(dir: &Path, exclude_patterns: &[String], debounce: Duration) -> Result<Self> {
+        let root = dir.canonicalize()?;
+        let overrides = exclude_overrides(&root, exclude_patterns)?;
+        let mut builder = GitignoreBuilder::new(&root);
+        if let Some(e) = builder.add(root.join(".gitignore")) {
+            // A missing .gitignore is normal; anything else is worth knowing about
+            if !matches!(&e, ignore::Error::Io(io) if io.kind() == std::io::ErrorKind::NotFound) {
+                tracing::warn!("ignoring {}: {}", root.join(".gitignore").display(), e);
+            }
+        }
+        let gitignore = builder.build()?;
+
+        let (sender, events) = unbounded_channel();
+        let mut debouncer = new_debouncer(debounce, None, move |result| {
+            let _ = sender.send(result);
+        })
+        .map_err(watch_error)?;
+        debouncer.watch(&root, RecursiveMode::Recursive).map_err(watch_error)?;
+
+        Ok(Self {
+            root,
+            overrides,
+            gitignore,
+            _debouncer: debouncer,
+            events,
+        })
+    }
+
+    /// Wait for the next batch of relevant changes and return the changed paths
+    pub async fn next_change(&mut self) -> Result<Vec<PathBuf>> {
+        loop {
+            let batch = self
+                .events
+                .recv()
+                .await
+                .ok_or_else(|| TechDocsError::Other("file watcher stopped".into()))?
+                .map_err(|errors| match errors.into_iter().next() {
+                    Some(e) => watch_error(e),
+                    None => TechDocsError::Other("file watcher failed".into()),
+                })?;
+
+            // Opening and reading files, including our own walk, is not a change
+            let mut paths: Vec<PathBuf> = batch
+                .into_iter()
+                .filter(|event| !matches!(event.kind, EventKind::Access(_)))
+                .flat_map(|event| event.event.paths)
+                .filter(|path| self.is_relevant(path))
+                .collect();
+            if !paths.is_empty() {
+                paths.sort();
+                paths.dedup();
+                return Ok(paths);
+            }
+        }
+    }
+
+    fn is_relevant(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if relative.as_os_str().is_empty() {
+            return false;
+        }
+        if relative
+            .components()
+            .any(|c| IGNORED_DIRS.iter().any(|dir| c.as_os_str() == *dir))
+        {
+            return false;
+        }
+        if is_write_temp_file(path) {
+            return false;
+        }
+
+        let is_dir = path.is_dir();
+        let excluded = path
+            .ancestors()
+            .take_while(|ancestor| *ancestor != self.root)
+            .any(|ancestor| {
+                let ancestor_is_dir = if ancestor == path { is_dir } else { true };
+                self.overrides.matched(ancestor, ancestor_is_dir).is_ignore()
+            });
+        !excluded && !self.gitignore.matched_path_or_any_parents(path, is_dir).is_ignore()
+    }
+}
+
+/// Exclude patterns for the `files` inside `dir` that a watch rewrites after each change,
+/// so they neither appear in the prompt nor trigger another rebuild
+pub fn own_file_excludes(dir: &Path, files: &[&Path]) -> Vec<String> {
+    let Ok(root) = dir.canonicalize() else {
+        return Vec::new();
+    };
+    files
+        .iter()
+        .filter_map(|file| {
+            let file = absolute(file);
+            let relative = file.strip_prefix(&root).ok()?;
+            Some(format!("/{}", relative.to_string_lossy()))
+        })
+        .collect()
+}
+
+/// Temporary files created by [`crate::write_atomic`] while writing the caller's own files
+fn is_write_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|name| name.starts_with('.') && name.contains(".techdocs-") && name.ends_with(".tmp"))
+}
+
+/// `path` with its directory resolved, so it compares equal to paths under a canonical root
+fn absolute(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    match (parent.canonicalize(), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn watch_error(e: notify_debouncer_full::notify::Error) -> TechDocsError {
+    TechDocsError::Other(format!("file watcher: {}", e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+
+    fn watcher(tree: &FixtureTree, exclude: &[String]) -> ChangeWatcher {
+        ChangeWatcher::new(tree.path(), exclude, Duration::from_millis(100)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn ignored_excluded_and_temp_paths_are_not_relevant() {
+        let tree = FixtureTree::new()
+            .file("src/lib.rs", "")
+            .file("docs/guide.md", "")
+            .file("generated/out.rs", "")
+            .file("target/debug/build.log", "")
+            .file("node_modules/pkg/index.js", "")
+            .file(".git/HEAD", "")
+            .file("notes.log", "")
+            .file(".gitignore", "*.log\n");
+        let watcher = watcher(&tree, &["generated/".to_string()]);
+        let root = tree.path().canonicalize().unwrap();
+
+        assert!(watcher.is_relevant(&root.join("src/lib.rs")));
+        assert!(watcher.is_relevant(&root.join("docs/guide.md")));
+        assert!(watcher.is_relevant(&root.join("docs")));
+
+        assert!(!watcher.is_relevant(&root));
+        assert!(!watcher.is_relevant(&root.join("target/debug/build.log")));
+        assert!(!watcher.is_relevant(&root.join("node_modules/pkg/index.js")));
+        assert!(!watcher.is_relevant(&root.join(".git/HEAD")));
+        assert!(!watcher.is_relevant(&root.join("generated/out.rs")));
+        assert!(!watcher.is_relevant(&root.join("notes.log")));
+        assert!(!watcher.is_relevant(&root.join(".README.md.techdocs-42.tmp")));
+        assert!(!watcher.is_relevant(Path::new("/somewhere/else.rs")));
+    }
+
+    #[test]
+    fn write_temp_files_are_recognized_by_name() {
+        assert!(is_write_temp_file(Path::new("/p/.README.md.techdocs-123.tmp")));
+        assert!(!is_write_temp_file(Path::new("/p/README.md")));
+        assert!(!is_write_temp_file(Path::new("/p/README.techdocs-123.tmp")));
+        assert!(!is_write_temp_file(Path::new("/p/.README.md.techdocs-123")));
+    }
+
+    #[test]
+    fn own_files_inside_the_root_become_anchored_excludes() {
+        let tree = FixtureTree::new().file("README.md", "").file("docs/api.md", "");
+        let outside = FixtureTree::new().file("README.md", "");
+        let readme = tree.join("README.md");
+        let api = tree.join("docs/api.md");
+        let elsewhere = outside.join("README.md");
+
+        let excludes = own_file_excludes(tree.path(), &[&readme, &api, &elsewhere]);
+        assert_eq!(excludes, vec!["/README.md", "/docs/api.md"]);
+
+        assert!(own_file_excludes(&tree.join("missing"), &[&readme]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn changes_are_reported_once_per_path() {
+        let tree = FixtureTree::new()
+            .file("src/lib.rs", "")
+            .file("src/main.rs", "")
+            .file("target/out", "");
+        let mut watcher = watcher(&tree, &[]);
+        let root = tree.path().canonicalize().unwrap();
+
+        std::fs::write(root.join("target/out"), "ignored").unwrap();
+        std::fs::write(root.join("src/lib.rs"), "pub fn a() {}").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("src/lib.rs"), "pub fn b() {}").unwrap();
+
+        // A loaded machine may split the writes over more than one batch
+        let expected = [root.join("src/lib.rs"), root.join("src/main.rs")];
+        let mut seen = Vec::new();
+        while !expected.iter().all(|path| seen.contains(path)) {
+            let paths = tokio::time::timeout(Duration::from_secs(10), watcher.next_change())
+                .await
+                .expect("no change reported")
+                .unwrap();
+            let mut deduped = paths.clone();
+            deduped.dedup();
+            assert_eq!(paths, deduped, "a path is repeated within a batch");
+            assert!(paths.iter().all(|path| expected.contains(path)), "{:?}", paths);
+            seen.extend(paths);
+        }
+    }
+}
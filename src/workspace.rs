@@ -0,0 +1,283 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::progress::ProgressFn;
+use crate::{clone_repository, FileContext, Result, TechDocsError};
+
+/// Prefix of the clone directories a workspace creates; nothing else in the root is
+/// touched by [`Workspace::sweep`]
+const CLONE_PREFIX: &str = "clone-";
+
+/// A directory repositories are cloned into, bounded by a total size quota and a cap on
+/// any single clone. Clones are deleted when their [`WorkspaceDir`] is dropped.
+pub struct Workspace {
+    root: PathBuf,
+    quota_bytes: u64,
+    max_clone_bytes: u64,
+    /// Bytes held by live clones: what was received while cloning, the size on disk after
+    used: AtomicU64,
+}
+
+impl Workspace {
+    /// Create a workspace, creating `root` when needed
+    pub fn new(root: PathBuf, quota_bytes: u64, max_clone_bytes: u64) -> Result<Self> {
+        fs::create_dir_all(&root).map_err(|e| {
+            TechDocsError::Config(format!("cannot create workspace directory {}: {}", root.display(), e))
+        })?;
+        Ok(Self {
+            root,
+            quota_bytes,
+            max_clone_bytes,
+            used: AtomicU64::new(0),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn quota_bytes(&self) -> u64 {
+        self.quota_bytes
+    }
+
+    /// Bytes taken by the clones this workspace holds now
+    pub fn used_bytes(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Delete clones last modified more than `older_than` ago, which a crashed or killed
+    /// server left behind, returning how many were deleted
+    pub fn sweep(&self, older_than: Duration) -> usize {
+        let Ok(entries) = fs::read_dir(&self.root) else {
+            return 0;
+        };
+        let mut removed = 0;
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let is_clone = entry.file_name().to_str().is_some_and(|name| name.starts_with(CLONE_PREFIX));
+            let is_stale = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age > older_than));
+            if !is_clone || !is_stale {
+                continue;
+            }
+            match fs::remove_dir_all(entry.path()) {
+                Ok(()) => removed += 1,
+                Err(e) => tracing::warn!("cannot remove stale workspace {}: {}", entry.path().display(), e),
+            }
+        }
+        removed
+    }
+
+    /// Clone `url` into a new directory of the workspace. The clone is refused when the
+    /// quota is already used up, and aborted as soon as it grows past the per-clone cap
+    /// or fills the quota.
+    pub async fn clone_repository(self: &Arc<Self>, url: &str, progress: ProgressFn<'_>) -> Result<WorkspaceDir> {
+        let dir = self.create_dir()?;
+        tracing::info!("Cloning {} into {}", url, dir.path().display());
+        let too_large = Arc::new(AtomicBool::new(false));
+        let full = Arc::new(AtomicBool::new(false));
+        let keep_going = {
+            let workspace = Arc::clone(self);
+            let charged = Arc::clone(&dir.charged);
+            let (too_large, full) = (Arc::clone(&too_large), Arc::clone(&full));
+            move |stats: &git2::Progress<'_>| {
+                let received = stats.received_bytes() as u64;
+                workspace.charge(&charged, received);
+                if received > workspace.max_clone_bytes {
+                    too_large.store(true, Ordering::Relaxed);
+                    return false;
+                }
+                if workspace.used_bytes() > workspace.quota_bytes {
+                    full.store(true, Ordering::Relaxed);
+                    return false;
+                }
+                true
+            }
+        };
+
+        let cloned = clone_repository(url, dir, progress, keep_going).await;
+        if too_large.load(Ordering::Relaxed) {
+            return Err(self.clone_too_large());
+        }
+        if full.load(Ordering::Relaxed) {
+            return Err(self.quota_full());
+        }
+        let dir = cloned?;
+
+        // The checkout takes more room than the packs that were received
+        dir.measure()?;
+        Ok(dir)
+    }
+
+    /// Create an empty directory in the workspace for content other than a clone, such
+    /// as an extracted archive. It is charged by [`WorkspaceDir::measure`].
+    pub fn create_dir(self: &Arc<Self>) -> Result<WorkspaceDir> {
+        if self.used_bytes() >= self.quota_bytes {
+            return Err(self.quota_full());
+        }
+        let path = self.root.join(format!("{}{}", CLONE_PREFIX, uuid::Uuid::new_v4()));
+        fs::create_dir(&path).file_context("create", &path)?;
+        Ok(WorkspaceDir {
+            path,
+            workspace: Arc::clone(self),
+            charged: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Bytes a single clone or upload may take
+    pub fn max_clone_bytes(&self) -> u64 {
+        self.max_clone_bytes
+    }
+
+    /// Set what a clone is charged to `bytes`, adjusting the workspace total
+    fn charge(&self, charged: &AtomicU64, bytes: u64) {
+        let previous = charged.swap(bytes, Ordering::Relaxed);
+        if bytes >= previous {
+            self.used.fetch_add(bytes - previous, Ordering::Relaxed);
+        } else {
+            self.used.fetch_sub(previous - bytes, Ordering::Relaxed);
+        }
+    }
+
+    fn quota_full(&self) -> TechDocsError {
+        TechDocsError::Quota(format!(
+            "the {} MB of clone space on this server is in use; try again later",
+            self.quota_bytes / (1024 * 1024)
+        ))
+    }
+
+    fn clone_too_large(&self) -> TechDocsError {
+        TechDocsError::Quota(format!(
+            "the repository is larger than this server's {} MB limit for a clone",
+            self.max_clone_bytes / (1024 * 1024)
+        ))
+    }
+}
+
+/// A clone in a [`Workspace`], deleted and released from the quota when dropped
+pub struct WorkspaceDir {
+    path: PathBuf,
+    workspace: Arc<Workspace>,
+    /// Bytes this clone counts against the quota
+    charged: Arc<AtomicU64>,
+}
+
+impl WorkspaceDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Charge the directory's size on disk to the workspace, failing when it is over the
+    /// per-clone cap or the quota
+    pub fn measure(&self) -> Result<u64> {
+        let size = dir_size(&self.path);
+        self.workspace.charge(&self.charged, size);
+        if size > self.workspace.max_clone_bytes {
+            return Err(self.workspace.clone_too_large());
+        }
+        if self.workspace.used_bytes() > self.workspace.quota_bytes {
+            return Err(self.workspace.quota_full());
+        }
+        Ok(size)
+    }
+}
+
+impl AsRef<Path> for WorkspaceDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for WorkspaceDir {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.path) {
+            tracing::warn!("cannot remove workspace {}: {}", self.path.display(), e);
+        }
+        self.workspace.charge(&self.charged, 0);
+    }
+}
+
+/// Total size of the files under `path`, not following symlinks
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            Ok(file_type) if file_type.is_file() => entry.metadata().map_or(0, |metadata| metadata.len()),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureTree;
+    use std::time::SystemTime;
+
+    fn workspace(tree: &FixtureTree, quota_bytes: u64, max_clone_bytes: u64) -> Arc<Workspace> {
+        Arc::new(Workspace::new(tree.join("workspace"), quota_bytes, max_clone_bytes).unwrap())
+    }
+
+    /// Make `path` look last modified `age` ago
+    fn age(path: &Path, age: Duration) {
+        let dir = fs::File::open(path).unwrap();
+        dir.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn sweeping_removes_only_stale_clones() {
+        let tree = FixtureTree::new()
+            .dir("workspace/clone-old")
+            .file("workspace/clone-old/README.md", "# Old")
+            .dir("workspace/clone-new")
+            .dir("workspace/keep-me");
+        let workspace = workspace(&tree, 1024, 1024);
+        age(&tree.join("workspace/clone-old"), Duration::from_secs(7200));
+        age(&tree.join("workspace/keep-me"), Duration::from_secs(7200));
+
+        assert_eq!(workspace.sweep(Duration::from_secs(3600)), 1);
+        assert!(!tree.join("workspace/clone-old").exists());
+        assert!(tree.join("workspace/clone-new").exists());
+        assert!(tree.join("workspace/keep-me").exists());
+    }
+
+    #[test]
+    fn directories_are_charged_until_dropped() {
+        let tree = FixtureTree::new();
+        let workspace = workspace(&tree, 1000, 600);
+        let first = workspace.create_dir().unwrap();
+        fs::write(first.path().join("a.txt"), "a".repeat(400)).unwrap();
+        assert_eq!(first.measure().unwrap(), 400);
+        assert_eq!(workspace.used_bytes(), 400);
+
+        let second = workspace.create_dir().unwrap();
+        fs::write(second.path().join("b.txt"), "b".repeat(700)).unwrap();
+        let error = second.measure().unwrap_err();
+        assert!(matches!(&error, TechDocsError::Quota(message) if message.contains("larger than")), "{}", error);
+        fs::write(second.path().join("b.txt"), "b".repeat(500)).unwrap();
+        fs::create_dir(second.path().join("nested")).unwrap();
+        fs::write(second.path().join("nested/c.txt"), "c".repeat(100)).unwrap();
+        // 400 + 600 fills the quota but does not exceed it
+        assert_eq!(second.measure().unwrap(), 600);
+        assert_eq!(workspace.used_bytes(), 1000);
+
+        let error = workspace.create_dir().err().unwrap();
+        assert!(matches!(&error, TechDocsError::Quota(message) if message.contains("is in use")), "{}", error);
+
+        let path = second.path().to_path_buf();
+        drop(second);
+        assert!(!path.exists());
+        assert_eq!(workspace.used_bytes(), 400);
+        drop(first);
+        assert_eq!(workspace.used_bytes(), 0);
+        assert_eq!(fs::read_dir(workspace.root()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,593 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use assert_cmd::cargo::cargo_bin_cmd;
+use assert_cmd::Command;
+use tempfile::TempDir;
+
+/// `techdocs-cli` run in `cwd` with none of the caller's configuration: no `TECHDOCS_*`
+/// or Anthropic variables, and a home directory without a user config file
+fn techdocs(cwd: &Path) -> Command {
+    let mut command = cargo_bin_cmd!("techdocs-cli");
+    command
+        .env_clear()
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .env("HOME", cwd)
+        .env("NO_COLOR", "1")
+        .current_dir(cwd);
+    command
+}
+
+/// A small project that is no particular kind, so the default README prompt is used
+fn project() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("notes.txt"), "Notes about the project\n").unwrap();
+    fs::create_dir(dir.path().join("scripts")).unwrap();
+    fs::write(dir.path().join("scripts/run.sh"), "#!/bin/sh\necho run\n").unwrap();
+    dir
+}
+
+fn stdout(command: &mut Command) -> String {
+    String::from_utf8(command.assert().success().get_output().stdout.clone()).unwrap()
+}
+
+/// A stand-in for the Anthropic Messages API answering every request with the same
+/// content blocks. The bodies of the requests it received are kept in order.
+struct MockAnthropic {
+    url: String,
+    requests: Arc<Mutex<Vec<serde_json::Value>>>,
+}
+
+impl MockAnthropic {
+    /// Answer with `text`
+    fn start(text: &str) -> Self {
+        Self::answering(serde_json::json!([{"type": "text", "text": text}]))
+    }
+
+    /// Answer with a call to the tool `name` given `input`
+    fn calling(name: &str, input: serde_json::Value) -> Self {
+        Self::answering(serde_json::json!([{"type": "tool_use", "id": "toolu_test", "name": name, "input": input}]))
+    }
+
+    fn answering(content: serde_json::Value) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&requests);
+        let response = serde_json::json!({
+            "id": "msg_test",
+            "model": "claude-test",
+            "content": content,
+            "stop_reason": "end_turn",
+            "usage": {"input_tokens": 120, "output_tokens": 40},
+        })
+        .to_string();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap_or(0);
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                if reader.read_exact(&mut body).is_err() {
+                    continue;
+                }
+                received.lock().unwrap().push(serde_json::from_slice(&body).unwrap_or_default());
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                );
+            }
+        });
+        Self { url, requests }
+    }
+
+    fn requests(&self) -> Vec<serde_json::Value> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[test]
+fn readme_dry_run_uses_the_embedded_prompt_from_any_directory() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    assert!(!cwd.path().join("prompts").exists());
+
+    let out = stdout(techdocs(cwd.path()).arg("readme").arg("--dry-run").arg(project.path()));
+    assert!(out.starts_with(techdocs::DEFAULT_README_PROMPT.trim_end()), "{}", out);
+    assert!(out.contains(techdocs::DRY_RUN_DELIMITER));
+    assert!(out.contains("notes.txt"));
+}
+
+#[test]
+fn prompt_file_replaces_the_embedded_prompt() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let prompt = cwd.path().join("custom.txt");
+    fs::write(&prompt, "Write a haiku about this project.\n").unwrap();
+
+    let out = stdout(techdocs(cwd.path()).arg("readme").arg("--dry-run").arg("--prompt-file").arg(&prompt).arg(project.path()));
+    assert!(out.starts_with("Write a haiku about this project."), "{}", out);
+
+    let out = stdout(techdocs(cwd.path()).env("TECHDOCS_PROMPT_FILE", &prompt).args(["readme", "--dry-run"]).arg(project.path()));
+    assert!(out.starts_with("Write a haiku about this project."), "{}", out);
+}
+
+#[test]
+fn reviewed_plan_is_replayed_until_a_file_changes() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let plan = cwd.path().join("plan.json");
+
+    let planned = stdout(techdocs(cwd.path()).args(["readme", "--dry-run", "--plan-out"]).arg(&plan).arg(project.path()));
+    let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(&plan).unwrap()).unwrap();
+    let paths: Vec<&str> = written["files"].as_array().unwrap().iter().map(|file| file["path"].as_str().unwrap()).collect();
+    assert!(paths.contains(&"notes.txt") && paths.contains(&"scripts/run.sh"), "{:?}", paths);
+
+    let replayed = stdout(techdocs(cwd.path()).args(["readme", "--dry-run", "--plan-in"]).arg(&plan).arg(project.path()));
+    assert_eq!(replayed, planned);
+
+    fs::write(project.path().join("notes.txt"), "Edited after review\n").unwrap();
+    let assert = techdocs(cwd.path()).args(["readme", "--dry-run", "--plan-in"]).arg(&plan).arg(project.path()).assert().code(3);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    assert!(stderr.contains("notes.txt changed"), "{}", stderr);
+}
+
+#[test]
+fn missing_prompt_file_is_reported() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let assert = techdocs(cwd.path()).args(["readme", "--dry-run", "--prompt-file", "missing.txt"]).arg(project.path()).assert();
+    let stderr = String::from_utf8_lossy(&assert.failure().get_output().stderr).into_owned();
+    assert!(stderr.contains("missing.txt"), "{}", stderr);
+}
+
+#[test]
+fn readme_is_generated_from_any_directory() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let mock = MockAnthropic::start("# Project\n\nA generated README.\n");
+
+    let out = stdout(
+        techdocs(cwd.path())
+            .env("ANTHROPIC_API_KEY", "test-key")
+            .env("ANTHROPIC_BASE_URL", &mock.url)
+            .arg("readme")
+            .arg(project.path()),
+    );
+    assert!(out.contains("A generated README."), "{}", out);
+
+    let requests = mock.requests();
+    assert_eq!(requests.len(), 1);
+    let system = requests[0]["system"].to_string();
+    let first_line = techdocs::DEFAULT_README_PROMPT.lines().next().unwrap();
+    assert!(system.contains(first_line), "{}", system);
+}
+
+#[test]
+fn config_show_layers_flags_over_environment_over_project_file() {
+    let project = project();
+    fs::write(project.path().join(".techdocs.toml"), "exclude = [\"*.lock\"]\nmax_depth = 3\nmax_file_size_kb = 50\n").unwrap();
+    let cwd = TempDir::new().unwrap();
+
+    let show = |command: &mut Command| stdout(command.args(["config", "show"]).arg(project.path()));
+    let out = show(&mut techdocs(cwd.path()));
+    assert!(out.contains("exclude = [\"*.lock\"]"), "{}", out);
+    assert!(out.contains("max_depth = 3"), "{}", out);
+
+    let out = show(techdocs(cwd.path()).env("TECHDOCS_MAX_DEPTH", "5"));
+    assert!(out.contains("max_depth = 5"), "{}", out);
+
+    let out = show(techdocs(cwd.path()).env("TECHDOCS_MAX_DEPTH", "5").args(["--max-depth", "7", "--exclude", "dist/"]));
+    assert!(out.contains("max_depth = 7"), "{}", out);
+    assert!(out.contains("exclude = [\"dist/\"]"), "{}", out);
+    assert!(out.contains("max_file_size_kb = 50"), "{}", out);
+    assert!(out.lines().next().unwrap().ends_with("< environment < command line"), "{}", out);
+}
+
+#[test]
+fn docs_accepts_the_largest_total_size_limit() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let mock = MockAnthropic::start("# Module\n\nWhat this module does.\n");
+
+    techdocs(cwd.path())
+        .env("ANTHROPIC_API_KEY", "test-key")
+        .env("ANTHROPIC_BASE_URL", &mock.url)
+        .args(["docs", "--max-total-size-mb", &u64::MAX.to_string(), "--output-dir"])
+        .arg(cwd.path().join("pages"))
+        .arg(project.path())
+        .assert()
+        .success();
+    assert!(!mock.requests().is_empty());
+    assert!(fs::read_dir(cwd.path().join("pages")).unwrap().next().is_some());
+}
+
+/// Files `list` prints, relative to the project and sorted
+fn listed(project: &TempDir, command: &mut Command) -> Vec<String> {
+    let out = stdout(command.arg("list").arg(project.path()));
+    let mut files: Vec<String> = out
+        .lines()
+        .map(|line| Path::new(line).strip_prefix(project.path()).unwrap().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn list_prints_the_selected_files() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    assert_eq!(listed(&project, &mut techdocs(cwd.path())), ["notes.txt", "scripts/run.sh"]);
+}
+
+#[test]
+fn list_excludes_patterns_with_or_without_a_bang() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    assert_eq!(listed(&project, techdocs(cwd.path()).args(["--exclude", "scripts/"])), ["notes.txt"]);
+    assert_eq!(listed(&project, techdocs(cwd.path()).args(["--exclude", "!*.sh"])), ["notes.txt"]);
+    assert_eq!(listed(&project, techdocs(cwd.path()).args(["--exclude", "*.txt,!*.sh"])), Vec::<String>::new());
+}
+
+#[test]
+fn exclude_from_comes_before_exclude_and_replaces_the_config_file() {
+    let project = project();
+    fs::write(project.path().join(".techdocs.toml"), "exclude = [\"*.lock\"]\n").unwrap();
+    let cwd = TempDir::new().unwrap();
+    let patterns = cwd.path().join("excludes");
+    fs::write(&patterns, "# build output\n\nscripts/\n  *.tmp  \n").unwrap();
+
+    let out = stdout(
+        techdocs(cwd.path()).arg("--exclude-from").arg(&patterns).args(["--exclude", "notes.txt", "config", "show"]).arg(project.path()),
+    );
+    assert!(out.contains("exclude = [\n    \"scripts/\",\n    \"*.tmp\",\n    \"notes.txt\",\n]"), "{}", out);
+    assert!(!out.contains("*.lock"), "{}", out);
+    assert_eq!(listed(&project, techdocs(cwd.path()).arg("--exclude-from").arg(&patterns)), ["notes.txt"]);
+
+    // The config file takes the same format as a string
+    fs::write(project.path().join(".techdocs.toml"), "exclude = \"\"\"\n# scripts\nscripts/\n\"\"\"\n").unwrap();
+    assert_eq!(listed(&project, &mut techdocs(cwd.path())), ["notes.txt"]);
+}
+
+#[test]
+fn invalid_exclude_patterns_are_reported_with_their_line() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let patterns = cwd.path().join("excludes");
+    fs::write(&patterns, "# fine\nscripts/\nsrc/[abc\n").unwrap();
+    let assert = techdocs(cwd.path()).arg("--exclude-from").arg(&patterns).arg("list").arg(project.path()).assert().code(2);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    assert!(stderr.contains(&format!("{}:3: invalid exclude pattern `src/[abc`", patterns.display())), "{}", stderr);
+
+    fs::write(project.path().join(".techdocs.toml"), "exclude = \"scripts/\\nsrc/[abc\\n\"\n").unwrap();
+    let assert = techdocs(cwd.path()).arg("list").arg(project.path()).assert().code(2);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    assert!(stderr.contains("exclude:2: invalid exclude pattern `src/[abc`"), "{}", stderr);
+}
+
+#[test]
+fn prompt_includes_files_and_reports_skipped_ones() {
+    let project = project();
+    fs::write(project.path().join("big.txt"), "a".repeat(3000)).unwrap();
+    let cwd = TempDir::new().unwrap();
+
+    let assert = techdocs(cwd.path()).args(["prompt", "--max-file-size-kb", "1"]).arg(project.path()).assert().success();
+    let output = assert.get_output();
+    let out = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(out.contains("Notes about the project"), "{}", out);
+    assert!(out.contains("echo run"), "{}", out);
+    assert!(!out.contains("aaaa"), "{}", out);
+    assert!(stderr.contains("big.txt (too large, 3000 bytes)"), "{}", stderr);
+}
+
+/// Every gzip member of `path`, decompressed and joined
+fn gunzip(path: &Path) -> String {
+    let mut text = String::new();
+    flate2::read::MultiGzDecoder::new(fs::File::open(path).unwrap()).read_to_string(&mut text).unwrap();
+    text
+}
+
+/// Names in `dir`, sorted, so a leftover temporary file shows up
+fn dir_entries(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn appended_prompts_follow_each_other_until_overwritten() {
+    let (first, second) = (project(), project());
+    fs::write(second.path().join("notes.txt"), "Second project notes\n").unwrap();
+    let cwd = TempDir::new().unwrap();
+    let output = cwd.path().join("prompts.txt");
+    for project in [&first, &second] {
+        techdocs(cwd.path()).args(["prompt", "--append", "--output"]).arg(&output).arg(project.path()).assert().success();
+    }
+    let text = fs::read_to_string(&output).unwrap();
+    let headers: Vec<_> = text.match_indices("===== techdocs prompt: ").map(|(index, _)| index).collect();
+    assert_eq!(headers.len(), 2, "{}", text);
+    assert!(text[headers[0]..headers[1]].contains(&first.path().display().to_string()));
+    assert!(text[headers[0]..headers[1]].contains("Notes about the project"));
+    assert!(text[headers[1]..].contains(&second.path().display().to_string()));
+    assert!(text[headers[1]..].contains("Second project notes"));
+
+    // Without --append the file is replaced whole, leaving no temporary file behind
+    techdocs(cwd.path()).args(["prompt", "--output"]).arg(&output).arg(first.path()).assert().success();
+    let text = fs::read_to_string(&output).unwrap();
+    assert!(!text.contains("===== techdocs prompt: ") && !text.contains("Second project notes"), "{}", text);
+    assert!(text.contains("Notes about the project"), "{}", text);
+    assert_eq!(dir_entries(cwd.path()), ["prompts.txt"]);
+}
+
+#[test]
+fn gzip_output_decompresses_to_the_prompt() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let plain = stdout(techdocs(cwd.path()).arg("prompt").arg(project.path()));
+
+    // A .gz name turns compression on; --gzip does so for any name
+    let detected = cwd.path().join("prompt.txt.gz");
+    techdocs(cwd.path()).args(["prompt", "--output"]).arg(&detected).arg(project.path()).assert().success();
+    assert_eq!(gunzip(&detected), plain);
+    let flagged = cwd.path().join("prompt.bin");
+    techdocs(cwd.path()).args(["prompt", "--gzip", "--output"]).arg(&flagged).arg(project.path()).assert().success();
+    assert_eq!(gunzip(&flagged), plain);
+
+    // Appended parts are gzip members of their own, so the file decompresses whole
+    let appended = cwd.path().join("parts.gz");
+    for _ in 0..2 {
+        techdocs(cwd.path()).args(["prompt", "--append", "--output"]).arg(&appended).arg(project.path()).assert().success();
+    }
+    let text = gunzip(&appended);
+    assert_eq!(text.matches("===== techdocs prompt: ").count(), 2, "{}", text);
+    assert_eq!(text.matches("Notes about the project").count(), 2, "{}", text);
+
+    techdocs(cwd.path()).args(["prompt", "--output"]).arg(&appended).arg(project.path()).assert().success();
+    assert_eq!(gunzip(&appended), plain);
+    assert_eq!(dir_entries(cwd.path()), ["parts.gz", "prompt.bin", "prompt.txt.gz"]);
+}
+
+#[test]
+fn readme_dry_run_honors_excludes() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let out = stdout(techdocs(cwd.path()).args(["readme", "--dry-run", "--exclude", "scripts/"]).arg(project.path()));
+    assert!(out.contains("notes.txt"), "{}", out);
+    assert!(!out.contains("run.sh"), "{}", out);
+}
+
+#[test]
+fn a_tiny_total_size_budget_skips_files_and_reports_them() {
+    let project = project();
+    let lines = "a".repeat(99) + "\n";
+    for name in ["first.txt", "second.txt"] {
+        fs::write(project.path().join(name), lines.repeat(7000)).unwrap();
+    }
+    let cwd = TempDir::new().unwrap();
+    let assert = techdocs(cwd.path())
+        .args(["readme", "--dry-run", "--max-total-size-mb", "1", "--max-file-size-kb", "2048"])
+        .arg(project.path())
+        .assert()
+        .success();
+    let output = assert.get_output();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let skipped: Vec<&str> = stderr.lines().filter(|line| line.starts_with("Skipping")).collect();
+    assert_eq!(skipped.len(), 1, "{}", stderr);
+    assert!(skipped[0].contains("total size limit reached, 700000 bytes"), "{}", stderr);
+    assert!(stderr.contains("Dry run: 3 files included, 1 skipped"), "{}", stderr);
+    // Which of the two fills the budget first is up to the walk
+    let (left_out, included) = match skipped[0].contains("first.txt") {
+        true => ("first.txt", "second.txt"),
+        false => ("second.txt", "first.txt"),
+    };
+    assert!(stdout.contains(included) && !stdout.contains(left_out), "{} of {}", left_out, included);
+    assert!(stdout.contains("notes.txt") && stdout.contains("run.sh"));
+}
+
+#[test]
+fn machine_readable_stdout_has_no_colors_or_logs() {
+    let project = project();
+    fs::write(project.path().join("README.md"), "# Demo\n").unwrap();
+    let cwd = TempDir::new().unwrap();
+    let mock = MockAnthropic::calling(
+        "record_summary",
+        serde_json::json!({"description": "Runs a script.", "topics": ["shell"]}),
+    );
+    let summary = stdout(
+        with_mock(cwd.path(), &mock).env_remove("NO_COLOR").args(["-v", "summary", "--format", "json"]).arg(project.path()),
+    );
+    let parsed: serde_json::Value = serde_json::from_str(&summary).unwrap();
+    assert_eq!(parsed, serde_json::json!({"description": "Runs a script.", "topics": ["shell"]}));
+
+    let assert = techdocs(cwd.path()).env_remove("NO_COLOR").args(["-v", "readme", "--dry-run"]).arg(project.path()).assert().success();
+    let output = assert.get_output();
+    let dry_run = String::from_utf8(output.stdout.clone()).unwrap();
+    assert!(dry_run.contains("notes.txt"), "{}", dry_run);
+    // The logs went to stderr, uncolored because it is a pipe
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(" INFO ") && !stderr.contains('\x1b'), "{}", stderr);
+    for stdout in [&summary, &dry_run] {
+        assert!(!stdout.contains('\x1b'), "{}", stdout);
+        assert!(!stdout.contains(" INFO ") && !stdout.contains(" DEBUG "), "{}", stdout);
+    }
+}
+
+#[test]
+fn missing_directory_is_refused() {
+    let cwd = TempDir::new().unwrap();
+    for command in ["list", "prompt"] {
+        let assert = techdocs(cwd.path()).args([command, "no-such-dir"]).assert().code(3);
+        let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+        assert!(stderr.contains("no-such-dir"), "{}", stderr);
+    }
+}
+
+/// `techdocs-cli` talking to `mock` with a placeholder API key
+fn with_mock(cwd: &Path, mock: &MockAnthropic) -> Command {
+    let mut command = techdocs(cwd);
+    command.env("ANTHROPIC_API_KEY", "test-key").env("ANTHROPIC_BASE_URL", &mock.url);
+    command
+}
+
+#[test]
+fn exit_codes_are_listed_in_the_long_help() {
+    let cwd = TempDir::new().unwrap();
+    let out = stdout(techdocs(cwd.path()).arg("--help"));
+    assert!(out.contains("Exit codes:"), "{}", out);
+    assert!(out.contains("7  readme --diff: the README is out of date"), "{}", out);
+}
+
+#[test]
+fn invalid_configuration_exits_with_2() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    techdocs(cwd.path()).args(["list", "--max-depth", "0"]).arg(project.path()).assert().code(2);
+    fs::write(project.path().join(".techdocs.toml"), "bogus = 1\n").unwrap();
+    techdocs(cwd.path()).arg("list").arg(project.path()).assert().code(2);
+}
+
+#[test]
+fn unresolvable_input_exits_with_3() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    techdocs(cwd.path()).args(["readme", "--dry-run", "no-such-dir"]).assert().code(3);
+    techdocs(cwd.path()).args(["changelog", "--no-llm"]).arg(project.path()).assert().code(3);
+}
+
+#[test]
+fn exceeded_budgets_exit_with_4() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    techdocs(cwd.path()).args(["list", "--max-walk-entries", "1"]).arg(project.path()).assert().code(4);
+    let mock = MockAnthropic::start("# Project\n");
+    with_mock(cwd.path(), &mock).args(["readme", "--max-cost-usd", "0.0000001"]).arg(project.path()).assert().code(4);
+    assert!(mock.requests().is_empty());
+}
+
+#[test]
+fn confirm_refuses_to_ask_without_a_terminal() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let mock = MockAnthropic::start("# Project\n");
+    let assert = with_mock(cwd.path(), &mock).args(["readme", "--confirm"]).arg(project.path()).write_stdin("y\n").assert().code(1);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    assert!(stderr.contains("Proceed? Cannot ask: stdin is not a terminal (pass --yes to proceed)"), "{}", stderr);
+    assert!(mock.requests().is_empty());
+
+    let out = stdout(with_mock(cwd.path(), &mock).args(["readme", "--confirm", "--yes"]).arg(project.path()));
+    assert!(out.contains("# Project"), "{}", out);
+    assert_eq!(mock.requests().len(), 1);
+}
+
+#[test]
+fn cost_ceilings_are_checked_before_sending() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let mock = MockAnthropic::start("# Project\n");
+    let assert = with_mock(cwd.path(), &mock).args(["readme", "--max-cost-usd", "0.0000001"]).arg(project.path()).assert().code(4);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    assert!(stderr.contains("exceeds --max-cost-usd $0.0000"), "{}", stderr);
+
+    let assert = with_mock(cwd.path(), &mock)
+        .args(["readme", "--model", "unpriced-model", "--max-cost-usd", "1"])
+        .arg(project.path())
+        .assert()
+        .code(4);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    assert!(stderr.contains("no pricing known for model unpriced-model, so --max-cost-usd cannot be enforced"), "{}", stderr);
+    assert!(mock.requests().is_empty());
+}
+
+#[test]
+fn provider_failures_exit_with_5() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    techdocs(cwd.path()).arg("readme").arg(project.path()).assert().code(5);
+    // Nothing listens on the discard port
+    techdocs(cwd.path())
+        .env("ANTHROPIC_API_KEY", "test-key")
+        .env("ANTHROPIC_BASE_URL", "http://127.0.0.1:9")
+        .arg("readme")
+        .arg(project.path())
+        .assert()
+        .code(5);
+}
+
+#[test]
+fn write_with_a_repository_url_fails_before_cloning() {
+    let cwd = TempDir::new().unwrap();
+    // Cloning would fail with exit code 3, unable to create its temporary directory
+    let url = "https://github.com/username/repo";
+    let tmp = cwd.path().join("missing");
+    let assert = techdocs(cwd.path()).env("TMPDIR", &tmp).args(["readme", "--write", url]).assert().code(6);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    assert!(stderr.contains("--write cannot be used with a repository URL"), "{}", stderr);
+
+    let assert = techdocs(cwd.path()).env("TMPDIR", &tmp).args(["readme", "--incremental", url]).assert().code(6);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).into_owned();
+    assert!(stderr.contains("--incremental keeps its state beside the README"), "{}", stderr);
+}
+
+#[test]
+fn unwritable_output_exits_with_6() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let mock = MockAnthropic::start("# Project\n");
+    with_mock(cwd.path(), &mock)
+        .args(["readme", "--output"])
+        .arg(cwd.path().join("missing/README.md"))
+        .arg(project.path())
+        .assert()
+        .code(6);
+}
+
+#[test]
+fn outdated_readme_exits_with_7() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let mock = MockAnthropic::start("# Project\n\nA generated README.\n");
+    fs::write(project.path().join("README.md"), "# Project\n\nAn older README.\n").unwrap();
+    let assert = with_mock(cwd.path(), &mock).args(["readme", "--diff"]).arg(project.path()).assert().code(7);
+    let output = assert.get_output();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("+A generated README."));
+    // The run ends normally, so the spend is still reported
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Spent $") && stderr.contains("on 1 Claude requests"), "{}", stderr);
+    assert_eq!(fs::read_to_string(project.path().join("README.md")).unwrap(), "# Project\n\nAn older README.\n");
+
+    fs::write(project.path().join("README.md"), "# Project\n\nA generated README.\n").unwrap();
+    with_mock(cwd.path(), &mock).args(["readme", "--diff"]).arg(project.path()).assert().success();
+}
+
+#[test]
+fn spending_past_the_cost_limit_exits_with_8() {
+    let project = project();
+    let cwd = TempDir::new().unwrap();
+    let mock = MockAnthropic::start("# Module\n");
+    with_mock(cwd.path(), &mock)
+        .args(["docs", "--max-cost-usd", "0.0000001", "--output-dir"])
+        .arg(cwd.path().join("pages"))
+        .arg(project.path())
+        .assert()
+        .code(8);
+}
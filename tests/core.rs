@@ -0,0 +1,81 @@
+//! The library as an embedding tool sees it with `--no-default-features --features core`:
+//! only the prelude's synchronous items, no runtime, and no network. The other feature
+//! sets compile and run these too, so the core entry points behave the same everywhere.
+
+use std::fs;
+use std::path::Path;
+
+use tempfile::TempDir;
+use techdocs::prelude::*;
+
+/// A project with an ignored file, a build directory, and a file over 1 KB. The
+/// `.gitignore` is honored only inside a git repository, hence the empty `.git`.
+fn project() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join(".git")).unwrap();
+    fs::write(dir.path().join(".gitignore"), "*.log\n").unwrap();
+    fs::create_dir_all(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+    fs::write(dir.path().join("notes.txt"), "Notes about the project\n").unwrap();
+    fs::write(dir.path().join("debug.log"), "ignored\n").unwrap();
+    fs::create_dir_all(dir.path().join("target")).unwrap();
+    fs::write(dir.path().join("target/out.txt"), "built\n").unwrap();
+    fs::write(dir.path().join("large.txt"), "x".repeat(2048)).unwrap();
+    dir
+}
+
+fn relative_paths(dir: &TempDir, files: &[CollectedFile]) -> Vec<String> {
+    let mut paths: Vec<String> = files
+        .iter()
+        .map(|file| file.path.strip_prefix(dir.path()).unwrap_or(&file.path).to_string_lossy().replace('\\', "/"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn collect_files_applies_ignore_rules_and_the_size_limit() {
+    let project = project();
+    let files = collect_files(project.path(), &[], 1).unwrap();
+    assert_eq!(relative_paths(&project, &files), ["notes.txt", "src/main.rs"]);
+}
+
+#[test]
+fn write_files_prompt_lists_the_collected_files() {
+    let project = project();
+    let mut prompt = Vec::new();
+    write_files_prompt(project.path(), &PromptOptions::new().exclude(["notes.txt"]), &mut prompt).unwrap();
+    let prompt = String::from_utf8(prompt).unwrap();
+    assert!(prompt.contains("src/main.rs"));
+    assert!(prompt.contains("fn main() {}"));
+    assert!(!prompt.contains("notes.txt"));
+    assert!(!prompt.contains("debug.log"));
+}
+
+#[test]
+fn plan_reports_skipped_files_without_reading_them_into_the_prompt() {
+    let project = project();
+    let plan = PromptPlan::collect(project.path(), &PromptOptions::new().max_file_kb(1)).unwrap();
+    assert_eq!(plan.included().count(), 2);
+    assert!(plan.files.iter().any(|file| file.skipped == Some(SkipReason::TooLarge)));
+}
+
+#[test]
+fn missing_directory_is_an_error_not_a_panic() {
+    let project = project();
+    let missing = project.path().join("missing");
+    assert!(collect_files(&missing, &[], 100).is_err());
+    assert!(inspect_directory(&missing, DirCheck::Readable).is_err());
+}
+
+#[test]
+fn relative_path_in_a_subdirectory_is_inside_the_work_tree() {
+    // The only test here that changes directory; the others use absolute paths
+    let project = project();
+    std::env::set_current_dir(project.path().join("src")).unwrap();
+    let current = inspect_directory(Path::new("."), DirCheck::Capabilities);
+    let sibling = inspect_directory(Path::new("../target"), DirCheck::Capabilities);
+    std::env::set_current_dir(env!("CARGO_MANIFEST_DIR")).unwrap();
+    assert_eq!(current.unwrap(), DirInfo { writable: Some(true), git_work_tree: Some(true) });
+    assert_eq!(sibling.unwrap().git_work_tree, Some(true));
+}